@@ -0,0 +1,196 @@
+use stc::parser::{LexicalError, Location, ParseError};
+use std::io::{self, Write};
+
+/// Source text shown in diagnostics, like a `.st` file or a POU of project
+pub struct SourceFile {
+    pub name: String,
+    pub text: String,
+}
+
+impl SourceFile {
+    pub fn new<S: Into<String>>(name: S, text: String) -> Self {
+        Self {
+            name: name.into(),
+            text,
+        }
+    }
+
+    /// Location of the end of source, Location line starts from 0
+    pub fn end_location(&self) -> Location {
+        let mark = self.text.matches('\n').count();
+        let offset = self
+            .text
+            .rsplit('\n')
+            .next()
+            .map_or(0, |x| x.chars().count());
+
+        Location { mark, offset }
+    }
+}
+
+/// Compile error or warning, file and location are optional for errors not found in
+/// source, like the errors of backend
+pub struct Diagnostic {
+    message: String,
+    file: Option<usize>,
+    location: Option<Location>,
+    length: usize,
+    is_warning: bool,
+}
+
+impl Diagnostic {
+    pub fn new<S: Into<String>>(message: S) -> Self {
+        Self {
+            message: message.into(),
+            file: None,
+            location: None,
+            length: 1,
+            is_warning: false,
+        }
+    }
+
+    /// Warnings are reported without stopping compilation
+    pub fn with_warning(mut self) -> Self {
+        self.is_warning = true;
+        self
+    }
+
+    pub fn with_file(mut self, file: usize) -> Self {
+        self.file = Some(file);
+        self
+    }
+
+    pub fn with_location(mut self, location: Location, length: usize) -> Self {
+        self.location = Some(location);
+        self.length = length.max(1);
+        self
+    }
+
+    /// Diagnostic of parse error in `files[file]`
+    pub fn from_parse_error(e: &ParseError, files: &[SourceFile], file: usize) -> Self {
+        let source = &files[file];
+        let diagnostic = Self::new("").with_file(file);
+
+        match e {
+            ParseError::LexerError(LexicalError::UnexpectedCharacter(mark, offset, ch)) => {
+                Diagnostic {
+                    message: format!("unexpected character '{}'", ch),
+                    ..diagnostic
+                }
+                .with_location(
+                    Location {
+                        mark: *mark,
+                        offset: *offset,
+                    },
+                    1,
+                )
+            }
+            ParseError::LexerError(LexicalError::InvalidDirective(mark, offset)) => {
+                // the directive in braces, or the rest of line if it's not closed there
+                let line = source.text.lines().nth(*mark).unwrap_or_default();
+                let directive = line.chars().skip(*offset);
+                let length = match directive.clone().position(|c| c == '}') {
+                    Some(end) => end + 1,
+                    None => directive.count(),
+                };
+
+                Diagnostic {
+                    message: "invalid conditional compilation directive".to_owned(),
+                    ..diagnostic
+                }
+                .with_location(
+                    Location {
+                        mark: *mark,
+                        offset: *offset,
+                    },
+                    length,
+                )
+            }
+            ParseError::LexerError(LexicalError::UnexpectedEnd) | ParseError::UnexpectedEnd => {
+                Diagnostic {
+                    message: "unexpected end of file".to_owned(),
+                    ..diagnostic
+                }
+                .with_location(source.end_location(), 1)
+            }
+            ParseError::InvalidToken(loc) => {
+                let token = token_at(source, *loc);
+                Diagnostic {
+                    message: format!("invalid token `{}`", token),
+                    ..diagnostic
+                }
+                .with_location(*loc, token.chars().count())
+            }
+            ParseError::InvalidTokenAt(message) => Diagnostic {
+                message: message.clone(),
+                ..diagnostic
+            },
+            ParseError::UnexpectedToken(loc, expected) => {
+                let token = token_at(source, *loc);
+                let mut message = format!("unexpected token `{}`", token);
+                if !expected.is_empty() {
+                    let expected: Vec<_> = expected.iter().map(|x| x.trim_matches('"')).collect();
+                    message.push_str(&format!(", expected one of: {}", expected.join(", ")));
+                }
+
+                Diagnostic {
+                    message,
+                    ..diagnostic
+                }
+                .with_location(*loc, token.chars().count())
+            }
+        }
+    }
+
+    /// Write diagnostic in the format of rustc, with the source line of location
+    pub fn write(&self, w: &mut dyn Write, files: &[SourceFile]) -> io::Result<()> {
+        let severity = if self.is_warning { "warning" } else { "error" };
+        writeln!(w, "{}: {}", severity, self.message)?;
+
+        let Some(source) = self.file.map(|x| &files[x]) else {
+            return Ok(());
+        };
+        let Some(loc) = self.location else {
+            return writeln!(w, " --> {}", source.name);
+        };
+
+        let line_number = (loc.mark + 1).to_string();
+        let gutter = " ".repeat(line_number.len());
+        let line = source.text.lines().nth(loc.mark).unwrap_or("");
+
+        // keep tabs of the line, so that the marker is aligned with the token
+        let indent: String = line
+            .chars()
+            .take(loc.offset)
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+
+        writeln!(
+            w,
+            "{}--> {}:{}:{}",
+            gutter,
+            source.name,
+            loc.mark + 1,
+            loc.offset + 1
+        )?;
+        writeln!(w, "{} |", gutter)?;
+        writeln!(w, "{} | {}", line_number, line)?;
+        writeln!(w, "{} | {}{}", gutter, indent, "^".repeat(self.length))
+    }
+}
+
+/// Text of token at location, which is the identifier or a single character
+fn token_at(source: &SourceFile, loc: Location) -> String {
+    let Some(line) = source.text.lines().nth(loc.mark) else {
+        return String::new();
+    };
+    let mut chars = line.chars().skip(loc.offset).peekable();
+
+    match chars.peek() {
+        Some(c) if c.is_alphanumeric() || *c == '_' => chars
+            .take_while(|c| c.is_alphanumeric() || matches!(c, '_' | '#'))
+            .collect(),
+        Some(c) => c.to_string(),
+        None => String::new(),
+    }
+}
@@ -0,0 +1,479 @@
+mod diagnostic;
+mod source;
+
+use clap::{Parser, ValueEnum};
+use diagnostic::{Diagnostic, SourceFile};
+use source::{PouSource, Sources};
+use stc::analysis::{check_declarations, collect_pragma_messages, TypeAnalyzer};
+use stc::backend::*;
+use stc::prelude::*;
+use stc::serde::BuildCache;
+use stc::utils::Crc32Hasher;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, ValueEnum)]
+enum Backend {
+    Lua,
+    C,
+    Wasm,
+    Llvm,
+}
+
+impl Backend {
+    /// Extension of output file
+    fn extension(&self) -> &'static str {
+        match self {
+            Backend::Lua => "luac",
+            Backend::C => "c",
+            Backend::Wasm => "wasm",
+            Backend::Llvm => "bc",
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Emit {
+    /// Tokens of sources
+    Tokens,
+    /// Syntax tree of declarations and bodies
+    Ast,
+    /// Text form of generated code: Lua listing, WebAssembly text or C source
+    Ir,
+    /// Full listing of generated code, with constants and locals of Lua functions
+    Asm,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, ValueEnum)]
+enum OptLevel {
+    #[value(name = "0")]
+    None,
+    #[value(name = "1")]
+    Basic,
+    #[value(name = "2")]
+    Full,
+}
+
+/// IEC 61131-3 Structured Text compiler
+#[derive(Parser)]
+#[command(name = "stc")]
+struct CompilerArgs {
+    /// Project XML files or `.st` files
+    #[arg(required = true)]
+    inputs: Vec<PathBuf>,
+
+    #[arg(long, value_enum, default_value = "lua")]
+    backend: Backend,
+
+    /// Output file, dumps of `--emit` are written to stdout without output file
+    #[arg(short)]
+    output: Option<PathBuf>,
+
+    /// Write dump instead of compiled code
+    #[arg(long, value_enum)]
+    emit: Option<Emit>,
+
+    #[arg(short = 'O', value_enum, default_value = "0")]
+    opt_level: OptLevel,
+
+    /// Check values assigned to subrange variables at runtime
+    #[arg(long)]
+    range_check: bool,
+
+    /// Cache file of incremental builds, POUs not affected by changes are not analysed and
+    /// generated again
+    #[arg(long)]
+    cache: Option<PathBuf>,
+
+    /// Program run by the Lua module
+    #[arg(long, value_name = "NAME", default_value = "main")]
+    entry: String,
+
+    /// Define name for conditional compilation, like `{IF defined(SIMULATION)}`
+    #[arg(short = 'D', long = "define", value_name = "NAME[=VALUE]")]
+    defines: Vec<String>,
+}
+
+/// Errors are collected by stages, compilation stops after the stage with errors
+struct Compiler {
+    args: CompilerArgs,
+    sources: Sources,
+    mgr: UnitsManager,
+    app: ModuleContext,
+    cache: Option<BuildCache>,
+    /// Warnings are reported whether compilation succeeds or not
+    warnings: Vec<Diagnostic>,
+}
+
+impl Compiler {
+    fn new(args: CompilerArgs) -> Self {
+        let mgr = UnitsManager::new();
+        let app = ModuleContext::new(ModuleKind::Application);
+        mgr.write().add_context(app.clone());
+        mgr.write().set_active_application(Some(app.read().id()));
+
+        let mut sources = Sources::default();
+        for define in &args.defines {
+            match define.split_once('=') {
+                Some((name, value)) => sources.defines.define(name, Some(value.to_owned())),
+                None => sources.defines.define(define.as_str(), None),
+            }
+        }
+
+        Self {
+            args,
+            sources,
+            mgr,
+            app,
+            cache: None,
+            warnings: vec![],
+        }
+    }
+
+    fn files(&self) -> &[SourceFile] {
+        &self.sources.files
+    }
+
+    fn warnings(&self) -> &[Diagnostic] {
+        &self.warnings
+    }
+
+    fn run(&mut self) -> Result<(), Vec<Diagnostic>> {
+        for input in &self.args.inputs {
+            self.sources.load(input).map_err(|e| vec![e])?;
+        }
+        if self.args.emit == Some(Emit::Tokens) {
+            return self.write_dump(&self.dump_tokens());
+        }
+
+        let diagnostics = self.sources.parse(&self.app);
+        if !diagnostics.is_empty() {
+            return Err(diagnostics);
+        }
+        if self.args.emit == Some(Emit::Ast) {
+            return self.write_dump(&self.dump_ast());
+        }
+
+        self.load_cache();
+        let result = self.analyze();
+        self.save_cache()?;
+        result?;
+
+        if self.args.emit == Some(Emit::Asm) && self.args.backend == Backend::C {
+            return Err(vec![Diagnostic::new(
+                "`--emit asm` is not supported by C backend, use `--emit ir`",
+            )]);
+        }
+
+        match self.args.backend {
+            Backend::Lua => self.generate::<LuaBackend>(),
+            Backend::C => self.generate::<CBackend>(),
+            Backend::Wasm => self.generate::<WasmBackend>(),
+            #[cfg(feature = "llvm")]
+            Backend::Llvm => self.generate::<LLVMBackend>(),
+            #[cfg(not(feature = "llvm"))]
+            Backend::Llvm => Err(vec![Diagnostic::new(
+                "LLVM backend is not enabled, build stc with feature `llvm`",
+            )]),
+        }
+    }
+
+    /// Key of cache, caches of other compiler versions or options are discarded
+    fn cache_key(&self) -> u64 {
+        let mut hasher = Crc32Hasher::new();
+        env!("CARGO_PKG_VERSION").hash(&mut hasher);
+        self.args.backend.hash(&mut hasher);
+        self.args.opt_level.hash(&mut hasher);
+        self.args.range_check.hash(&mut hasher);
+
+        hasher.finish()
+    }
+
+    /// Read cache and find the units reused, before type analysis changes the hashes
+    fn load_cache(&mut self) {
+        let Some(path) = &self.args.cache else {
+            return;
+        };
+
+        let mut cache = BuildCache::load(path, self.cache_key());
+        let reused = cache.update(&self.app);
+        let total = cache.units().filter(|x| x.body_hash.is_some()).count();
+        let mut note = format!("note: {} of {} POUs reused from cache", reused.len(), total);
+        if !reused.is_empty() {
+            let names: Vec<_> = reused.iter().map(|x| x.to_string()).collect();
+            note.push_str(&format!(": {}", names.join(", ")));
+        }
+        eprintln!("{}", note);
+
+        self.cache = Some(cache);
+    }
+
+    fn save_cache(&self) -> Result<(), Vec<Diagnostic>> {
+        let (Some(path), Some(cache)) = (&self.args.cache, &self.cache) else {
+            return Ok(());
+        };
+
+        cache.save(path).map_err(|e| {
+            vec![Diagnostic::new(format!(
+                "couldn't write {}: {}",
+                path.display(),
+                e
+            ))]
+        })
+    }
+
+    /// Type analysis of bodies and checks of declarations. Bodies with errors or code in
+    /// cache are not analysed, the errors in cache are reported.
+    fn analyze(&mut self) -> Result<(), Vec<Diagnostic>> {
+        let app_id = self.app.read().id();
+        let mut diagnostics = vec![];
+
+        for (decl_id, file) in self.body_files() {
+            let Some(f) = self.app.read().get_function(decl_id).cloned() else {
+                continue;
+            };
+            let name = self.app.read().get_declaration_by_id(decl_id).cloned();
+            let name = name.unwrap().read().unwrap().decl().qualified_name();
+
+            let cached = self.cache.as_ref().and_then(|x| x.unit(&name));
+            if let Some(unit) = cached {
+                match &unit.diagnostics {
+                    Some(errors) if !errors.is_empty() || unit.code.is_some() => {
+                        for e in errors {
+                            diagnostics.push(Diagnostic::new(e.clone()).with_file(file));
+                        }
+                        continue;
+                    }
+                    _ => {}
+                }
+            }
+
+            let mut type_analyzer = TypeAnalyzer::new();
+            let scope = Scope::new(Some(self.mgr.clone()), Some(app_id), Some(decl_id));
+            type_analyzer.analyze_statement(f.write().parse_tree_mut(), scope);
+            let errors: Vec<_> = type_analyzer
+                .errors()
+                .iter()
+                .map(|x| x.to_string())
+                .collect();
+            for e in &errors {
+                diagnostics.push(Diagnostic::new(e.clone()).with_file(file));
+            }
+            if let Some(cache) = self.cache.as_mut() {
+                cache.set_diagnostics(&name, errors);
+            }
+        }
+
+        for e in check_declarations(&self.mgr, &self.app) {
+            diagnostics.push(Diagnostic::new(e.to_string()));
+        }
+
+        // messages of '{warning}' and '{error}' pragmas, pragmas of statements are located
+        for message in collect_pragma_messages(&self.app) {
+            let mut diagnostic = Diagnostic::new(message.to_string());
+            if let Some(pou) = self.pou_source(message.decl_id) {
+                diagnostic = match (&pou.body, message.location) {
+                    (Some((file, _)), Some(location)) => {
+                        diagnostic.with_file(*file).with_location(location, 1)
+                    }
+                    _ => diagnostic.with_file(pou.decl.0),
+                };
+            }
+
+            match message.is_error {
+                true => diagnostics.push(diagnostic),
+                false => self.warnings.push(diagnostic.with_warning()),
+            }
+        }
+
+        match diagnostics.is_empty() {
+            true => Ok(()),
+            false => Err(diagnostics),
+        }
+    }
+
+    /// Source of declaration, None for declarations not loaded from sources
+    fn pou_source(&self, decl_id: usize) -> Option<&PouSource> {
+        let decl = self.app.read().get_declaration_by_id(decl_id).cloned()?;
+        let uuid = decl.read().unwrap().object_id();
+
+        self.sources.pous.iter().find(|x| x.uuid == uuid)
+    }
+
+    /// Declaration id and source file of bodies, ordered by declaration id
+    fn body_files(&self) -> Vec<(usize, usize)> {
+        let app = self.app.read();
+        let mut decls: Vec<_> = app
+            .declarations()
+            .map(|x| {
+                let proto = x.read().unwrap();
+                (proto.id(), proto.object_id())
+            })
+            .collect();
+        decls.sort();
+
+        decls
+            .into_iter()
+            .filter_map(|(id, uuid)| {
+                let pou = self.sources.pous.iter().find(|x| x.uuid == uuid)?;
+                Some((id, pou.body.as_ref()?.0))
+            })
+            .collect()
+    }
+
+    fn generate<B: CodeGenBackend + 'static>(&mut self) -> Result<(), Vec<Diagnostic>> {
+        let app_id = self.app.read().id();
+        let mut code_gen: CodeGenDriver<B> = CodeGenDriver::new(self.mgr.clone(), app_id)
+            .map_err(|e| vec![Diagnostic::new(e.to_string())])?;
+        code_gen.set_optimize_level(match self.args.opt_level {
+            OptLevel::None => OptimizeLevel::None,
+            OptLevel::Basic => OptimizeLevel::Basic,
+            OptLevel::Full => OptimizeLevel::Full,
+        });
+        code_gen.set_range_check(self.args.range_check);
+        let result = match self.cache.as_mut() {
+            Some(cache) => code_gen.build_application_with_cache(cache),
+            None => code_gen.build_application(),
+        };
+        result.map_err(|e| vec![Diagnostic::new(e.to_string())])?;
+        self.save_cache()?;
+
+        let mut buf = vec![];
+        let backend = code_gen.backend();
+        set_entry(backend, &self.args.entry);
+        let result = match self.args.emit {
+            Some(emit) => dump_code(backend, emit, &mut buf),
+            None => backend.get_module_bytes(&mut buf),
+        };
+        result.map_err(|e| vec![Diagnostic::new(e.to_string())])?;
+
+        if self.args.emit.is_some() {
+            return self.write_dump(&buf);
+        }
+
+        let output =
+            self.args.output.clone().unwrap_or_else(|| {
+                self.args.inputs[0].with_extension(self.args.backend.extension())
+            });
+        write_file(&output, &buf)
+    }
+
+    fn dump_tokens(&self) -> Vec<u8> {
+        let mut s = String::new();
+        for file in self.files() {
+            s.push_str(&format!("// {}\n", file.name));
+            for tok in self.sources.lexer(&file.text) {
+                match tok {
+                    Ok(tok) => s.push_str(&format!(
+                        "{}:{} {:?}\n",
+                        tok.location.mark + 1,
+                        tok.location.offset + 1,
+                        tok.kind
+                    )),
+                    Err(e) => {
+                        s.push_str(&format!("{:?}\n", e));
+                        break;
+                    }
+                }
+            }
+        }
+
+        s.into_bytes()
+    }
+
+    fn dump_ast(&self) -> Vec<u8> {
+        let app = self.app.read();
+        let mut decls: Vec<_> = app.declarations().cloned().collect();
+        decls.sort_by_key(|x| x.read().unwrap().id());
+
+        let mut s = String::new();
+        for proto in decls {
+            let proto = proto.read().unwrap();
+            s.push_str(&format!("{:#?}\n", proto.decl()));
+            if let Some(f) = app.get_function(proto.id()) {
+                s.push_str(&format!("{:#?}\n", f.read().parse_tree()));
+            }
+        }
+
+        s.into_bytes()
+    }
+
+    fn write_dump(&self, buf: &[u8]) -> Result<(), Vec<Diagnostic>> {
+        match &self.args.output {
+            Some(output) => write_file(output, buf),
+            None => io::stdout()
+                .write_all(buf)
+                .map_err(|e| vec![Diagnostic::new(e.to_string())]),
+        }
+    }
+}
+
+/// Program run by the module, only Lua modules have an entry program
+fn set_entry<B: CodeGenBackend + 'static>(backend: &mut B, entry: &str) {
+    let any: &mut dyn std::any::Any = backend;
+    if let Some(lua) = any.downcast_mut::<LuaBackend>() {
+        lua.set_entry(StString::from(entry));
+    }
+}
+
+/// Text form of generated code, C source is written as is
+fn dump_code<B: CodeGenBackend + 'static>(
+    backend: &mut B,
+    emit: Emit,
+    w: &mut dyn Write,
+) -> io::Result<()> {
+    let any: &mut dyn std::any::Any = backend;
+
+    if let Some(lua) = any.downcast_mut::<LuaBackend>() {
+        let mut buf = vec![];
+        lua.get_module_bytes(&mut buf)?;
+        let code = lua_undump(&mut buf.as_slice())?;
+        return w.write_all(code.listing(emit == Emit::Asm).as_bytes());
+    }
+
+    if let Some(wasm) = any.downcast_mut::<WasmBackend>() {
+        return wasm.get_module_text(w);
+    }
+
+    backend.get_module_bytes(w)
+}
+
+fn write_file(path: &Path, buf: &[u8]) -> Result<(), Vec<Diagnostic>> {
+    fs::write(path, buf).map_err(|e| {
+        vec![Diagnostic::new(format!(
+            "couldn't write {}: {}",
+            path.display(),
+            e
+        ))]
+    })
+}
+
+fn main() -> ExitCode {
+    env_logger::init();
+
+    let mut compiler = Compiler::new(CompilerArgs::parse());
+    let result = compiler.run();
+
+    let mut stderr = io::stderr().lock();
+    for warning in compiler.warnings() {
+        let _ = warning.write(&mut stderr, compiler.files());
+        let _ = writeln!(stderr);
+    }
+    let Err(diagnostics) = result else {
+        return ExitCode::SUCCESS;
+    };
+
+    for diagnostic in &diagnostics {
+        let _ = diagnostic.write(&mut stderr, compiler.files());
+        let _ = writeln!(stderr);
+    }
+    let _ = match diagnostics.len() {
+        1 => writeln!(stderr, "error: aborting due to previous error"),
+        n => writeln!(stderr, "error: aborting due to {} previous errors", n),
+    };
+
+    ExitCode::FAILURE
+}
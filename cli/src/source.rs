@@ -0,0 +1,152 @@
+use crate::diagnostic::{Diagnostic, SourceFile};
+use stc::parser::{CompilerDefines, ParserBuilder, StLexer, StLexerBuilder, StLexerOptions};
+use stc::prelude::*;
+use stc::serde::{split_source, Project};
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+/// Source of one POU or type declaration, the body is None for declarations without body.
+/// Sources are indexes of loaded files.
+pub struct PouSource {
+    pub uuid: Uuid,
+    pub decl: (usize, String),
+    pub body: Option<(usize, String)>,
+    /// Namespace of declaration, empty for global namespace
+    pub namespace: StString,
+    /// Namespaces of 'USING' directives out of declaration
+    pub using: Vec<StString>,
+}
+
+/// Sources of all inputs, declarations and bodies are parsed into application
+#[derive(Default)]
+pub struct Sources {
+    pub files: Vec<SourceFile>,
+    pub pous: Vec<PouSource>,
+    /// Names defined for conditional compilation of all sources
+    pub defines: CompilerDefines,
+}
+
+impl Sources {
+    /// Load `.st` file or project XML file
+    pub fn load(&mut self, path: &Path) -> Result<(), Diagnostic> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| Diagnostic::new(format!("couldn't read {}: {}", path.display(), e)))?;
+        let name = path.display().to_string();
+
+        match path.extension().and_then(|x| x.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("xml") => self.load_project(&name, &text),
+            _ => self.load_st(name, text),
+        }
+    }
+
+    fn load_project(&mut self, name: &str, text: &str) -> Result<(), Diagnostic> {
+        let project: Project = quick_xml::de::from_str(text)
+            .map_err(|e| Diagnostic::new(format!("invalid project {}: {}", name, e)))?;
+        let namespace = StString::from(project.namespace.unwrap_or_default());
+
+        for (index, pou) in project.pou_list.pou.into_iter().enumerate() {
+            let decl = self.add_file(
+                format!("{}[{}].interface", name, index),
+                pou.interface.content.clone(),
+            );
+            let body = pou.body.map(|body| {
+                let file = self.add_file(format!("{}[{}].body", name, index), body.content.clone());
+                (file, body.content)
+            });
+            let uuid = pou
+                .uuid_text
+                .and_then(|s| Uuid::from_str(&s).ok())
+                .unwrap_or(Uuid::nil());
+
+            self.pous.push(PouSource {
+                uuid,
+                decl: (decl, pou.interface.content),
+                body,
+                namespace: namespace.clone(),
+                using: vec![],
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Declarations of `.st` file are followed by their bodies, like
+    /// `PROGRAM main: VAR x: INT; END_VAR END_PROGRAM x := x + 1;`
+    fn load_st(&mut self, name: String, text: String) -> Result<(), Diagnostic> {
+        let file = self.add_file(name, text);
+        let segments = split_source(&self.files[file].text, &self.defines)
+            .map_err(|e| Diagnostic::from_parse_error(&e, &self.files, file))?;
+
+        for segment in segments {
+            self.pous.push(PouSource {
+                uuid: Uuid::new_v4(),
+                decl: (file, segment.decl),
+                body: segment.body.map(|body| (file, body)),
+                namespace: segment.namespace,
+                using: segment.using,
+            });
+        }
+
+        Ok(())
+    }
+
+    fn add_file(&mut self, name: String, text: String) -> usize {
+        self.files.push(SourceFile::new(name, text));
+        self.files.len() - 1
+    }
+
+    /// Lexer of source text with the defines of sources
+    pub fn lexer<'a>(&self, text: &'a str) -> StLexer<'a> {
+        let options = StLexerOptions::default().defines(self.defines.clone());
+        StLexerBuilder::from_options(options).build_str(text)
+    }
+
+    /// Parse all declarations and bodies into application
+    pub fn parse(&self, app: &ModuleContext) -> Vec<Diagnostic> {
+        let parser = ParserBuilder::default().build();
+        let mut diagnostics = vec![];
+
+        for pou in &self.pous {
+            let (file, text) = &pou.decl;
+            let mut lexer = self.lexer(text);
+            let mut decl = match parser.parse_decl(&mut lexer) {
+                Ok(decl) => decl,
+                Err(e) => {
+                    diagnostics.push(Diagnostic::from_parse_error(&e, &self.files, *file));
+                    continue;
+                }
+            };
+            if !pou.namespace.is_empty() {
+                decl.set_namespace(pou.namespace.clone());
+            }
+            for namespace in &pou.using {
+                decl.add_using(namespace.clone());
+            }
+            let decl_id = app.write().add_declaration(decl, pou.uuid);
+            let is_pou = app
+                .read()
+                .get_declaration_by_id(decl_id)
+                .is_some_and(|x| !x.read().unwrap().is_type_declaration());
+
+            let body = match &pou.body {
+                Some((file, text)) => {
+                    let mut lexer = self.lexer(text);
+                    match parser.parse_stmt(&mut lexer) {
+                        Ok(body) => body,
+                        Err(e) => {
+                            diagnostics.push(Diagnostic::from_parse_error(&e, &self.files, *file));
+                            continue;
+                        }
+                    }
+                }
+                // POU without body does nothing
+                None if is_pou => Statement::statement_list(Box::default()),
+                None => continue,
+            };
+            app.write().add_function(decl_id, body);
+        }
+
+        diagnostics
+    }
+}
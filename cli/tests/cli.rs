@@ -0,0 +1,231 @@
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Output};
+
+const COUNTER: &str = r#"TYPE Percent: INT(0..100); END_TYPE
+
+FUNCTION_BLOCK Counter
+VAR_INPUT step: INT; END_VAR
+VAR_OUTPUT count: INT; END_VAR
+END_FUNCTION_BLOCK
+count := count + step;
+
+PROGRAM main:
+VAR c: Counter; p: Percent; END_VAR
+END_PROGRAM
+c(step := 2);
+p := c.count;
+"#;
+
+fn stc(dir: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_stc"))
+        .current_dir(dir)
+        .args(args)
+        .output()
+        .expect("stc exec failed")
+}
+
+#[test]
+fn test_compile_st_files() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("counter.st"), COUNTER).unwrap();
+
+    // output is named by the first input without `-o`
+    let output = stc(dir.path(), &["counter.st"]);
+    assert!(output.status.success(), "{:?}", output);
+    assert!(dir.path().join("counter.luac").exists());
+
+    let output = stc(dir.path(), &["counter.st", "--backend", "c", "-o", "app.c"]);
+    assert!(output.status.success(), "{:?}", output);
+    let source = fs::read_to_string(dir.path().join("app.c")).unwrap();
+    assert!(source.contains("void main_body(void)"));
+
+    let output = stc(
+        dir.path(),
+        &["counter.st", "--backend", "wasm", "--emit", "ir"],
+    );
+    assert!(output.status.success(), "{:?}", output);
+    assert!(String::from_utf8_lossy(&output.stdout).starts_with("(module"));
+}
+
+#[test]
+fn test_lua_entry() {
+    let dir = tempfile::tempdir().unwrap();
+    let code = "PROGRAM Prog1:\nVAR x: INT; END_VAR\nEND_PROGRAM\nx := 1;\n";
+    fs::write(dir.path().join("prog.st"), code).unwrap();
+
+    // nothing is written without the entry program
+    for args in [&["prog.st"][..], &["prog.st", "--emit", "ir"]] {
+        let output = stc(dir.path(), args);
+        assert!(!output.status.success());
+        assert!(output.stdout.is_empty());
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(
+            stderr.starts_with("error: entry program 'main' not found\n"),
+            "{}",
+            stderr
+        );
+    }
+    assert!(!dir.path().join("prog.luac").exists());
+
+    let output = stc(dir.path(), &["prog.st", "--entry", "Prog1"]);
+    assert!(output.status.success(), "{:?}", output);
+    assert!(dir.path().join("prog.luac").exists());
+
+    // functions called by the entry program are in the module
+    let code = "FUNCTION Twice: INT\nVAR_INPUT x: INT; END_VAR\nEND_FUNCTION\nTwice := x * 2;\n\
+        PROGRAM main:\nVAR y: INT; END_VAR\nEND_PROGRAM\ny := Twice(21);\nprint(y);\n";
+    fs::write(dir.path().join("main.st"), code).unwrap();
+
+    let output = stc(dir.path(), &["main.st", "--emit", "ir"]);
+    assert!(output.status.success(), "{:?}", output);
+    let listing = String::from_utf8_lossy(&output.stdout);
+    assert!(listing.contains("; _ENV \"Twice\""), "{}", listing);
+
+    let output = stc(dir.path(), &["main.st"]);
+    assert!(output.status.success(), "{:?}", output);
+    let Ok(output) = Command::new("lua")
+        .arg(dir.path().join("main.luac"))
+        .output()
+    else {
+        return;
+    };
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "42\n");
+}
+
+#[test]
+fn test_diagnostics() {
+    let dir = tempfile::tempdir().unwrap();
+    let code = "PROGRAM main:\nVAR x: INT; END_VAR\nEND_PROGRAM\nx := 1;\nx := x + ;\n";
+    fs::write(dir.path().join("main.st"), code).unwrap();
+
+    let output = stc(dir.path(), &["main.st"]);
+    assert!(!output.status.success());
+    assert!(!dir.path().join("main.luac").exists());
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let expected = " --> main.st:5:10\n  |\n5 | x := x + ;\n  |          ^\n";
+    assert!(
+        stderr.starts_with("error: unexpected token `;`"),
+        "{}",
+        stderr
+    );
+    assert!(stderr.contains(expected), "{}", stderr);
+    assert!(stderr.ends_with("error: aborting due to previous error\n"));
+
+    // checks of declarations and type analysis
+    let code = "TYPE Mode: (Manual := 1, Auto := 1); END_TYPE\n\
+        PROGRAM main:\nVAR p: INT(0..100); END_VAR\nEND_PROGRAM\np := 150;\n";
+    fs::write(dir.path().join("main.st"), code).unwrap();
+
+    let output = stc(dir.path(), &["main.st"]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("error: p := 150: value 150 out of range 0..100\n --> main.st\n"));
+    assert!(stderr.contains("error: Mode.Auto: duplicate enum value 1\n"));
+    assert!(stderr.ends_with("error: aborting due to 2 previous errors\n"));
+}
+
+#[test]
+fn test_pragma_messages() {
+    let dir = tempfile::tempdir().unwrap();
+    let code = "{warning 'not tested'}\nPROGRAM main:\nVAR x: INT; END_VAR\nEND_PROGRAM\n\
+        x := 1;\n{warning 'remove it'}\nx := 2;\n";
+    fs::write(dir.path().join("main.st"), code).unwrap();
+
+    // warnings don't stop compilation
+    let output = stc(dir.path(), &["main.st"]);
+    assert!(output.status.success(), "{:?}", output);
+    assert!(dir.path().join("main.luac").exists());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.starts_with("warning: main: not tested\n --> main.st\n"),
+        "{}",
+        stderr
+    );
+    let expected = "warning: main: remove it\n --> main.st:7:1\n  |\n7 | x := 2;\n  | ^\n";
+    assert!(stderr.contains(expected), "{}", stderr);
+
+    let code = code.replace("{warning 'remove it'}", "{error 'remove it'}");
+    fs::write(dir.path().join("main.st"), code).unwrap();
+
+    let output = stc(dir.path(), &["main.st"]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("warning: main: not tested\n"), "{}", stderr);
+    assert!(stderr.contains("error: main: remove it\n --> main.st:7:1\n"));
+    assert!(stderr.ends_with("error: aborting due to previous error\n"));
+}
+
+#[test]
+fn test_conditional_compilation() {
+    let dir = tempfile::tempdir().unwrap();
+    let code = "PROGRAM main:\nVAR x: INT; END_VAR\nEND_PROGRAM\n\
+        {IF defined(SIMULATION)}\nx := 1;\n{ELSE}\nx := ;\n{END_IF}\n";
+    fs::write(dir.path().join("main.st"), code).unwrap();
+
+    let output = stc(dir.path(), &["main.st", "-D", "SIMULATION"]);
+    assert!(output.status.success(), "{:?}", output);
+
+    let output = stc(dir.path(), &["main.st"]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains(" --> main.st:7:6\n"), "{}", stderr);
+
+    let code = code.replace("{ELSE}", "{ELSE}\n{ELSE}");
+    fs::write(dir.path().join("main.st"), code).unwrap();
+
+    let output = stc(dir.path(), &["main.st", "-D", "SIMULATION"]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let expected = "error: invalid conditional compilation directive\n --> main.st:7:1\n  |\n\
+        7 | {ELSE}\n  | ^^^^^^\n";
+    assert!(stderr.starts_with(expected), "{}", stderr);
+}
+
+#[test]
+fn test_incremental_cache() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("counter.st"), COUNTER).unwrap();
+
+    let output = stc(dir.path(), &["counter.st", "--cache", "stc.cache"]);
+    assert!(output.status.success(), "{:?}", output);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(stderr, "note: 0 of 2 POUs reused from cache\n");
+    let full = fs::read(dir.path().join("counter.luac")).unwrap();
+
+    let output = stc(dir.path(), &["counter.st", "--cache", "stc.cache"]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(
+        stderr,
+        "note: 2 of 2 POUs reused from cache: Counter, main\n"
+    );
+    assert_eq!(fs::read(dir.path().join("counter.luac")).unwrap(), full);
+
+    // only the changed body is analysed and generated again
+    let code = COUNTER.replace("p := c.count;", "p := c.count + 1;");
+    fs::write(dir.path().join("counter.st"), code).unwrap();
+    let output = stc(dir.path(), &["counter.st", "--cache", "stc.cache"]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(stderr, "note: 1 of 2 POUs reused from cache: Counter\n");
+    let incremental = fs::read(dir.path().join("counter.luac")).unwrap();
+
+    let output = stc(dir.path(), &["counter.st"]);
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(
+        fs::read(dir.path().join("counter.luac")).unwrap(),
+        incremental
+    );
+
+    // errors of bodies are reported from cache
+    let code = COUNTER.replace("p := c.count;", "p := 150;");
+    fs::write(dir.path().join("counter.st"), code).unwrap();
+    for reused in ["1 of 2 POUs", "2 of 2 POUs"] {
+        let output = stc(dir.path(), &["counter.st", "--cache", "stc.cache"]);
+        assert!(!output.status.success());
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains(reused), "{}", stderr);
+        assert!(stderr.contains("error: p := 150: value 150 out of range 0..100\n"));
+    }
+}
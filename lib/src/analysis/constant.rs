@@ -0,0 +1,462 @@
+use crate::ast::*;
+use crate::backend::utils::{
+    enum_values, expression_variable_name, integer_range, integer_width, is_float_type,
+    literal_integer, sorted_declarations, wider_class,
+};
+use crate::context::{ModuleContext, Scope};
+use crate::parser::{BitValue, LiteralValue, Operator, StString};
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+use std::sync::Arc;
+
+/// Limit of nested constant references, like 'A' initialized with 'B + 1'
+const MAX_DEPTH: usize = 32;
+
+/// Value of constant expression
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConstValue {
+    Bool(bool),
+    /// Integer and its type class, None for untyped integer literals. Durations are
+    /// integers of class TIME.
+    Integer(i128, Option<TypeClass>),
+    Real(f64, TypeClass),
+    String(String),
+}
+
+impl ConstValue {
+    /// Integer value, None for other values or values out of range of LINT
+    pub fn as_i64(&self) -> Option<i64> {
+        match *self {
+            ConstValue::Integer(v, _) => i64::try_from(v).ok(),
+            _ => None,
+        }
+    }
+
+    pub fn is_zero(&self) -> bool {
+        match *self {
+            ConstValue::Integer(v, _) => v == 0,
+            ConstValue::Real(v, _) => v == 0.0,
+            _ => false,
+        }
+    }
+
+    /// Literal of value, integers keep their type class
+    pub fn to_literal(&self) -> LiteralValue {
+        match self {
+            ConstValue::Bool(v) => LiteralValue::Bool(*v),
+            ConstValue::Integer(v, class) => {
+                let v = *v;
+                match class {
+                    Some(TypeClass::Bit) if v == 0 => LiteralValue::Bit(BitValue::Zero),
+                    Some(TypeClass::Bit) => LiteralValue::Bit(BitValue::One),
+                    Some(TypeClass::Byte) => LiteralValue::Byte(v as u8),
+                    Some(TypeClass::SInt) => LiteralValue::SInt(v as i8),
+                    Some(TypeClass::Int) => LiteralValue::Int(v as i16),
+                    Some(TypeClass::UInt) => LiteralValue::UInt(v as u16),
+                    Some(TypeClass::DInt) => LiteralValue::DInt(v as i32),
+                    Some(TypeClass::UDInt) => LiteralValue::UDInt(v as u32),
+                    Some(TypeClass::ULInt) => LiteralValue::ULInt(v as u64),
+                    Some(TypeClass::Time) => LiteralValue::Time(v as i64),
+                    _ if v > i64::MAX as i128 => LiteralValue::ULInt(v as u64),
+                    _ => LiteralValue::LInt(v as i64),
+                }
+            }
+            ConstValue::Real(v, TypeClass::Real) => LiteralValue::Real(format!("{:?}", v)),
+            ConstValue::Real(v, _) => LiteralValue::LReal(format!("{:?}", v)),
+            ConstValue::String(s) => LiteralValue::String(s.clone()),
+        }
+    }
+}
+
+impl Display for ConstValue {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.to_literal())
+    }
+}
+
+/// Reason why expression can't be evaluated at compile time
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConstError {
+    /// Expression refers to variables, calls or other values only known at runtime
+    NotConstant,
+    DivisionByZero,
+    /// Result is out of range of the type
+    Overflow(TypeClass),
+}
+
+impl Error for ConstError {}
+
+impl Display for ConstError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ConstError::NotConstant => write!(f, "not a constant expression"),
+            ConstError::DivisionByZero => write!(f, "division by zero"),
+            ConstError::Overflow(class) => write!(f, "constant overflow of type {}", class),
+        }
+    }
+}
+
+/// Names visible to constant expressions
+pub trait ConstantScope {
+    /// Find variable by name, only 'CONSTANT' variables have values
+    fn find_variable(&self, name: &StString) -> Option<Arc<Variable>>;
+
+    /// Value of enum member, `enum_name` is None for unqualified member name, which can't be
+    /// member of 'qualified_only' enums
+    fn enum_member(&self, enum_name: Option<&StString>, member: &StString) -> Option<i64>;
+}
+
+impl ConstantScope for ModuleContext {
+    fn find_variable(&self, name: &StString) -> Option<Arc<Variable>> {
+        self.read().find_toplevel_global_variable(name)
+    }
+
+    fn enum_member(&self, enum_name: Option<&StString>, member: &StString) -> Option<i64> {
+        sorted_declarations(self).into_iter().find_map(|decl| {
+            let decl = decl.read().unwrap();
+            let DeclKind::Enum(e) = &decl.decl().kind else {
+                return None;
+            };
+            if enum_name.map_or(e.is_qualified_only(), |x| x != e.name()) {
+                return None;
+            }
+
+            enum_values(e)
+                .into_iter()
+                .find(|(name, _)| name == member)
+                .map(|(_, v)| v)
+        })
+    }
+}
+
+impl ConstantScope for Scope {
+    fn find_variable(&self, name: &StString) -> Option<Arc<Variable>> {
+        Scope::find_variable(self, name)
+    }
+
+    fn enum_member(&self, enum_name: Option<&StString>, member: &StString) -> Option<i64> {
+        self.local_context()?.enum_member(enum_name, member)
+    }
+}
+
+/// Evaluate expression at compile time. Literals, 'CONSTANT' variables with initial values
+/// and enum members are constants, integer results out of range of their type are errors.
+pub fn eval_constant(
+    expr: &Expression,
+    scope: &dyn ConstantScope,
+) -> Result<ConstValue, ConstError> {
+    eval(expr, scope, 0)
+}
+
+fn eval(
+    expr: &Expression,
+    scope: &dyn ConstantScope,
+    depth: usize,
+) -> Result<ConstValue, ConstError> {
+    if depth > MAX_DEPTH {
+        return Err(ConstError::NotConstant);
+    }
+
+    match &expr.kind {
+        ExprKind::Literal(lit) => literal_value(lit.literal()),
+        ExprKind::Variable(var) => match scope.find_variable(var.name()) {
+            Some(v) => constant_variable(&v, scope, depth),
+            None => scope
+                .enum_member(None, var.name())
+                .map(|v| ConstValue::Integer(v as i128, None))
+                .ok_or(ConstError::NotConstant),
+        },
+        ExprKind::Compo(compo) => {
+            let enum_name =
+                expression_variable_name(compo.left()).ok_or(ConstError::NotConstant)?;
+            let member = expression_variable_name(compo.right()).ok_or(ConstError::NotConstant)?;
+            if scope.find_variable(enum_name).is_some() {
+                return Err(ConstError::NotConstant);
+            }
+
+            scope
+                .enum_member(Some(enum_name), member)
+                .map(|v| ConstValue::Integer(v as i128, None))
+                .ok_or(ConstError::NotConstant)
+        }
+        ExprKind::Operator(op_expr) => {
+            let operands = op_expr
+                .operands()
+                .iter()
+                .map(|x| eval(x, scope, depth + 1))
+                .collect::<Result<Vec<_>, _>>()?;
+
+            match operands.as_slice() {
+                [x] => unary_operation(op_expr.op(), x),
+                [x, y] => binary_operation(op_expr.op(), x, y),
+                _ => Err(ConstError::NotConstant),
+            }
+        }
+        _ => Err(ConstError::NotConstant),
+    }
+}
+
+fn literal_value(literal: &LiteralValue) -> Result<ConstValue, ConstError> {
+    Ok(match literal {
+        LiteralValue::Bool(v) => ConstValue::Bool(*v),
+        LiteralValue::ULInt(v) => ConstValue::Integer(*v as i128, None),
+        LiteralValue::Time(v) => ConstValue::Integer(*v as i128, Some(TypeClass::Time)),
+        LiteralValue::Real(s) | LiteralValue::LReal(s) => {
+            let v = s.parse().map_err(|_| ConstError::NotConstant)?;
+            ConstValue::Real(v, literal.ty().type_class())
+        }
+        LiteralValue::String(s) => ConstValue::String(s.clone()),
+        _ => ConstValue::Integer(
+            literal_integer(literal).ok_or(ConstError::NotConstant)? as i128,
+            None,
+        ),
+    })
+}
+
+/// Initial value of 'CONSTANT' variable converted to its declared type
+fn constant_variable(
+    variable: &Variable,
+    scope: &dyn ConstantScope,
+    depth: usize,
+) -> Result<ConstValue, ConstError> {
+    if !variable.flags().contains(VariableFlags::CONST) {
+        return Err(ConstError::NotConstant);
+    }
+
+    let initial = variable.initial().as_ref().ok_or(ConstError::NotConstant)?;
+    let value = eval(initial, scope, depth + 1)?;
+    let Some(class) = variable.ty().map(|x| x.type_class()) else {
+        return Ok(value);
+    };
+
+    match value {
+        ConstValue::Integer(v, _) if integer_width(class).is_some() => integer(v, Some(class)),
+        ConstValue::Integer(v, _) if is_float_type(class) => real(v as f64, class),
+        ConstValue::Real(v, _) if is_float_type(class) => real(v, class),
+        value => Ok(value),
+    }
+}
+
+/// Integer checked against range of its type, untyped integers are in range of LINT or
+/// ULINT
+fn integer(v: i128, class: Option<TypeClass>) -> Result<ConstValue, ConstError> {
+    let (min, max) = match class.and_then(integer_range) {
+        Some(range) => range,
+        None if class.is_some() => return Err(ConstError::NotConstant),
+        None => (i64::MIN as i128, u64::MAX as i128),
+    };
+
+    if v < min || v > max {
+        return Err(ConstError::Overflow(class.unwrap_or(TypeClass::LInt)));
+    }
+
+    Ok(ConstValue::Integer(v, class))
+}
+
+fn real(v: f64, class: TypeClass) -> Result<ConstValue, ConstError> {
+    let max = match class {
+        TypeClass::Real => f32::MAX as f64,
+        _ => f64::MAX,
+    };
+
+    if v.is_nan() || v.abs() > max {
+        return Err(ConstError::Overflow(class));
+    }
+
+    Ok(ConstValue::Real(v, class))
+}
+
+fn unary_operation(op: &Operator, x: &ConstValue) -> Result<ConstValue, ConstError> {
+    match (op, x) {
+        (Operator::Not, ConstValue::Bool(x)) => Ok(ConstValue::Bool(!x)),
+        (Operator::Not, ConstValue::Integer(x, class)) => match class.and_then(integer_width) {
+            Some((bits, false)) => integer(!x & ((1 << bits) - 1), *class),
+            _ => integer(!x, *class),
+        },
+        (Operator::Minus, ConstValue::Integer(x, class)) => integer(-x, *class),
+        (Operator::Minus, ConstValue::Real(x, class)) => real(-x, *class),
+        _ => Err(ConstError::NotConstant),
+    }
+}
+
+fn binary_operation(
+    op: &Operator,
+    x: &ConstValue,
+    y: &ConstValue,
+) -> Result<ConstValue, ConstError> {
+    match (x, y) {
+        (ConstValue::Bool(x), ConstValue::Bool(y)) => match op {
+            Operator::BitAnd => Ok(ConstValue::Bool(x & y)),
+            Operator::BitOr => Ok(ConstValue::Bool(x | y)),
+            Operator::Xor => Ok(ConstValue::Bool(x ^ y)),
+            _ => compare(op, x.cmp(y)),
+        },
+        (ConstValue::String(x), ConstValue::String(y)) => compare(op, x.cmp(y)),
+        (ConstValue::Integer(x, a), ConstValue::Integer(y, b)) => {
+            integer_operation(op, *x, *y, wider_class(*a, *b))
+        }
+        (ConstValue::Real(..), ConstValue::Real(..) | ConstValue::Integer(..))
+        | (ConstValue::Integer(..), ConstValue::Real(..)) => {
+            let (x, a) = real_operand(x);
+            let (y, b) = real_operand(y);
+            let class = wider_class(a, b)
+                .filter(|x| is_float_type(*x))
+                .unwrap_or(TypeClass::LReal);
+
+            match op {
+                Operator::Plus => real(x + y, class),
+                Operator::Minus => real(x - y, class),
+                Operator::Multiply => real(x * y, class),
+                Operator::Division | Operator::Mod if y == 0.0 => Err(ConstError::DivisionByZero),
+                Operator::Division => real(x / y, class),
+                Operator::Mod => real(x % y, class),
+                Operator::Power => real(x.powf(y), class),
+                _ => compare(op, x.partial_cmp(&y).ok_or(ConstError::NotConstant)?),
+            }
+        }
+        _ => Err(ConstError::NotConstant),
+    }
+}
+
+fn real_operand(x: &ConstValue) -> (f64, Option<TypeClass>) {
+    match *x {
+        ConstValue::Real(v, class) => (v, Some(class)),
+        ConstValue::Integer(v, class) => (v as f64, class),
+        _ => unreachable!(),
+    }
+}
+
+fn integer_operation(
+    op: &Operator,
+    x: i128,
+    y: i128,
+    class: Option<TypeClass>,
+) -> Result<ConstValue, ConstError> {
+    let v = match op {
+        Operator::Plus => x + y,
+        Operator::Minus => x - y,
+        Operator::Multiply => x
+            .checked_mul(y)
+            .ok_or(ConstError::Overflow(TypeClass::LInt))?,
+        Operator::Division | Operator::Mod if y == 0 => return Err(ConstError::DivisionByZero),
+        // truncated towards zero, remainder has the sign of dividend
+        Operator::Division => x / y,
+        Operator::Mod => x % y,
+        Operator::Power => {
+            // result of integer power is LINT or ULINT like the runtime
+            let class = class.map(|x| match integer_width(x) {
+                Some((_, false)) => TypeClass::ULInt,
+                _ => TypeClass::LInt,
+            });
+            let exp = u32::try_from(y).map_err(|_| ConstError::NotConstant)?;
+            let v = x
+                .checked_pow(exp)
+                .ok_or(ConstError::Overflow(class.unwrap_or(TypeClass::LInt)))?;
+
+            return integer(v, class);
+        }
+        Operator::BitAnd => x & y,
+        Operator::BitOr => x | y,
+        Operator::Xor => x ^ y,
+        _ => return compare(op, x.cmp(&y)),
+    };
+
+    integer(v, class)
+}
+
+fn compare(op: &Operator, ord: std::cmp::Ordering) -> Result<ConstValue, ConstError> {
+    let v = match op {
+        Operator::Less => ord.is_lt(),
+        Operator::LessEqual => ord.is_le(),
+        Operator::Equal => ord.is_eq(),
+        Operator::NotEqual => ord.is_ne(),
+        Operator::Greater => ord.is_gt(),
+        Operator::GreaterEqual => ord.is_ge(),
+        _ => return Err(ConstError::NotConstant),
+    };
+
+    Ok(ConstValue::Bool(v))
+}
+
+/// Rewrite constant sub-expressions of statement to literals, it's optional for backends
+/// which generate better code for literals. Assignment targets and accessed objects are
+/// kept, only their indexes are folded.
+struct ConstantFolder<'a> {
+    scope: &'a dyn ConstantScope,
+    errors: Vec<(String, ConstError)>,
+}
+
+impl ConstantFolder<'_> {
+    fn fold_place(&mut self, expr: &mut Expression) {
+        match &mut expr.kind {
+            ExprKind::Variable(_) => {}
+            ExprKind::Compo(compo) => self.fold_place(compo.left_mut()),
+            ExprKind::ArrayAccess(access) => self.fold_array_access(access),
+            _ => self.visit_expression_mut(expr),
+        }
+    }
+
+    fn fold_array_access(&mut self, access: &mut ArrayAccessExpression) {
+        self.fold_place(access.array_mut());
+        for index in access.indexes_mut() {
+            self.visit_expression_mut(index);
+        }
+    }
+}
+
+impl AstVisitorMut for ConstantFolder<'_> {
+    fn visit_expression_mut(&mut self, expr: &mut Expression) {
+        if matches!(
+            expr.kind,
+            ExprKind::Operator(_) | ExprKind::Variable(_) | ExprKind::Compo(_)
+        ) {
+            match eval_constant(expr, self.scope) {
+                Ok(value) => {
+                    expr.kind =
+                        ExprKind::Literal(Box::new(LiteralExpression::new(value.to_literal())));
+                    return;
+                }
+                Err(ConstError::NotConstant) => {}
+                Err(e) => {
+                    self.errors.push((expr.to_string(), e));
+                    return;
+                }
+            }
+        }
+
+        match &mut expr.kind {
+            ExprKind::Operator(op_expr) => {
+                for operand in op_expr.operands_mut() {
+                    self.visit_expression_mut(operand);
+                }
+            }
+            ExprKind::Assign(assign) => {
+                self.visit_expression_mut(assign.right_mut());
+                self.fold_place(assign.left_mut());
+            }
+            ExprKind::Call(call) => {
+                for arg in call.arguments_mut() {
+                    self.visit_expression_mut(arg);
+                }
+            }
+            ExprKind::Compo(compo) => self.fold_place(compo.left_mut()),
+            ExprKind::ArrayAccess(access) => self.fold_array_access(access),
+            _ => {}
+        }
+    }
+}
+
+/// Fold constant expressions of statement into literals, returns expressions which can't
+/// be evaluated because of errors like division by zero
+pub fn fold_constants(
+    stmt: &mut Statement,
+    scope: &dyn ConstantScope,
+) -> Vec<(String, ConstError)> {
+    let mut folder = ConstantFolder {
+        scope,
+        errors: vec![],
+    };
+    folder.visit_statement_mut(stmt);
+
+    folder.errors
+}
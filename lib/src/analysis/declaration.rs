@@ -0,0 +1,176 @@
+use crate::analysis::eval_constant;
+use crate::ast::*;
+use crate::backend::utils::{
+    const_integer, enum_values, integer_range, pack_mode, sorted_declarations, subrange_bounds,
+    TypeContext,
+};
+use crate::context::{ModuleContext, ResolveError, Scope, UnitsManager};
+use crate::parser::{StString, PACK_MODE_ATTRIBUTE};
+use crate::utils::HasAttribute;
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+
+/// Error found in type and variable declarations
+#[derive(Debug, Clone, PartialEq)]
+pub enum DeclarationError {
+    /// Two members of enum have the same value, like '(Idle := 0, Run := 0)'
+    DuplicateEnumValue(StString, StString, i64),
+    /// Enum value is not an integer literal or out of range of the enum base type
+    InvalidEnumValue(StString, StString),
+    /// Bounds of subrange type are not constant, reversed or out of range of the base type
+    InvalidSubrange(StString),
+    /// Initial value of variable is out of its subrange
+    OutOfRange(StString, i64, (i64, i64)),
+    /// Type of variable is ambiguous between libraries or qualified by unknown namespace
+    UnresolvedType(StString, ResolveError),
+    /// Value of 'pack_mode' attribute is not 1, 2, 4 or 8
+    InvalidPackMode(StString, Option<String>),
+}
+
+impl Error for DeclarationError {}
+
+impl Display for DeclarationError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            DeclarationError::DuplicateEnumValue(name, member, value) => {
+                write!(f, "{}.{}: duplicate enum value {}", name, member, value)
+            }
+            DeclarationError::InvalidEnumValue(name, member) => {
+                write!(f, "{}.{}: invalid enum value", name, member)
+            }
+            DeclarationError::InvalidSubrange(name) => {
+                write!(f, "{}: invalid subrange", name)
+            }
+            DeclarationError::OutOfRange(name, value, (lower, upper)) => write!(
+                f,
+                "{}: value {} out of range {}..{}",
+                name, value, lower, upper
+            ),
+            DeclarationError::UnresolvedType(name, e) => write!(f, "{}: {}", name, e),
+            DeclarationError::InvalidPackMode(name, value) => write!(
+                f,
+                "{}: invalid pack_mode '{}', expected 1, 2, 4 or 8",
+                name,
+                value.as_deref().unwrap_or_default()
+            ),
+        }
+    }
+}
+
+/// Check enum values, subrange bounds, initial values of subrange variables, library types
+/// of variables and 'pack_mode' attributes in all declarations of application
+pub fn check_declarations(mgr: &UnitsManager, app: &ModuleContext) -> Vec<DeclarationError> {
+    let ctx = TypeContext::new(mgr.clone(), app.clone());
+    let app_id = app.read().id();
+    let mut errors = vec![];
+
+    for proto in sorted_declarations(app) {
+        // types are resolved in the namespaces of declaration
+        let decl_id = proto.read().unwrap().id();
+        let scope = Scope::new(Some(mgr.clone()), Some(app_id), Some(decl_id));
+
+        let proto = proto.read().unwrap();
+        let name = proto.decl().qualified_name();
+        match &proto.decl().kind {
+            DeclKind::Enum(e) => check_enum(&name, e, &mut errors),
+            DeclKind::Alias(alias) => {
+                if let Some(sub) = alias.alias().as_subrange() {
+                    check_subrange(&name, sub, &ctx, &mut errors);
+                }
+            }
+            _ => {}
+        }
+
+        let value = proto
+            .decl()
+            .get_attribute_value(&PACK_MODE_ATTRIBUTE.into());
+        if let Some(value) = value.filter(|_| pack_mode(proto.decl()).is_none()) {
+            errors.push(DeclarationError::InvalidPackMode(
+                name.clone(),
+                value.clone(),
+            ));
+        }
+
+        for variable in proto.variables() {
+            let Some(ty) = variable.ty() else {
+                continue;
+            };
+
+            if let Some(name) = ty.user_type_name() {
+                match scope.resolve_declaration(name) {
+                    Ok(_) | Err(ResolveError::NotFound(_)) => {}
+                    Err(e) => {
+                        errors.push(DeclarationError::UnresolvedType(variable.name().clone(), e))
+                    }
+                }
+            }
+
+            // subranges declared by alias are checked by the alias declaration
+            if let Some(sub) = ty.as_subrange() {
+                check_subrange(variable.name(), sub, &ctx, &mut errors);
+            }
+
+            let Some((lower, upper)) = ctx.subrange(ty) else {
+                continue;
+            };
+            let value = variable
+                .initial()
+                .as_ref()
+                .and_then(|x| eval_constant(x, &ctx).ok())
+                .and_then(|x| x.as_i64());
+            if let Some(value) = value.filter(|x| *x < lower || *x > upper) {
+                errors.push(DeclarationError::OutOfRange(
+                    variable.name().clone(),
+                    value,
+                    (lower, upper),
+                ));
+            }
+        }
+    }
+
+    errors
+}
+
+fn check_enum(name: &StString, decl: &EnumDeclare, errors: &mut Vec<DeclarationError>) {
+    let class = decl
+        .ty()
+        .as_ref()
+        .map_or(TypeClass::DInt, |x| x.type_class());
+    let (min, max) = integer_range(class).unwrap_or((i64::MIN as i128, i64::MAX as i128));
+
+    let values = enum_values(decl);
+    for (index, (field, (member, value))) in decl.fields().iter().zip(&values).enumerate() {
+        let explicit = field.initial().as_ref().map(|x| const_integer(x));
+        if explicit.is_some_and(|x| x.is_none()) || (*value as i128) < min || (*value as i128) > max
+        {
+            errors.push(DeclarationError::InvalidEnumValue(
+                name.clone(),
+                member.clone(),
+            ));
+        } else if values[..index].iter().any(|(_, x)| x == value) {
+            errors.push(DeclarationError::DuplicateEnumValue(
+                name.clone(),
+                member.clone(),
+                *value,
+            ));
+        }
+    }
+}
+
+fn check_subrange(
+    name: &StString,
+    sub: &SubrangeType,
+    ctx: &TypeContext,
+    errors: &mut Vec<DeclarationError>,
+) {
+    let range = integer_range(sub.base_type().type_class());
+    let valid = subrange_bounds(sub, ctx)
+        .zip(range)
+        .is_some_and(|((lower, upper), (min, max))| {
+            lower <= upper && lower as i128 >= min && upper as i128 <= max
+        });
+
+    if !valid {
+        errors.push(DeclarationError::InvalidSubrange(name.clone()));
+    }
+}
@@ -0,0 +1,170 @@
+//! Names used by declarations, which are the edges between POUs, data types and global
+//! variables of incremental builds. Names are collected before type analysis, so they may
+//! be names of libraries or builtin functions.
+use crate::ast::*;
+use crate::parser::StString;
+
+use std::collections::BTreeSet;
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Dependencies {
+    /// Names used by types and initial values of variables, return type and base types
+    pub interface: BTreeSet<StString>,
+    /// Names used by body, except the variables of declaration
+    pub body: BTreeSet<StString>,
+}
+
+/// Names used by declaration and its body
+pub fn declaration_dependencies(decl: &Declaration, body: Option<&Statement>) -> Dependencies {
+    let mut collector = NameCollector {
+        locals: decl.variables().iter().map(|x| x.name().clone()).collect(),
+        names: BTreeSet::new(),
+    };
+
+    match &decl.kind {
+        DeclKind::Fun(fun) | DeclKind::FB(fun) | DeclKind::Prg(fun) => {
+            if let Some(ty) = fun.return_type() {
+                collector.add_type(ty);
+            }
+        }
+        DeclKind::Alias(alias) => collector.add_type(alias.alias()),
+        DeclKind::Enum(decl) => {
+            if let Some(ty) = decl.ty() {
+                collector.add_type(ty);
+            }
+        }
+        DeclKind::Configuration(decl) => {
+            for resource in decl.resources() {
+                for program in resource.programs() {
+                    collector.names.insert(program.program().clone());
+                }
+            }
+        }
+        DeclKind::Struct(_) | DeclKind::GlobalVar(_) => {}
+    }
+
+    // values of enum refer to each other, they are not dependencies
+    let is_enum = matches!(decl.kind, DeclKind::Enum(_));
+    for variable in decl.variables() {
+        if let Some(ty) = variable.ty() {
+            collector.add_type(ty);
+        }
+        match variable.initial() {
+            Some(initial) if !is_enum => collector.visit_expression(initial),
+            _ => {}
+        }
+    }
+
+    let interface = std::mem::take(&mut collector.names);
+    if let Some(body) = body {
+        collector.visit_statement(body);
+    }
+
+    Dependencies {
+        interface,
+        body: collector.names,
+    }
+}
+
+/// Names used by global variable, like the dependencies of declaration interface
+pub fn variable_dependencies(variable: &Variable) -> BTreeSet<StString> {
+    let mut collector = NameCollector {
+        locals: vec![],
+        names: BTreeSet::new(),
+    };
+
+    if let Some(ty) = variable.ty() {
+        collector.add_type(ty);
+    }
+    if let Some(initial) = variable.initial() {
+        collector.visit_expression(initial);
+    }
+
+    collector.names
+}
+
+struct NameCollector {
+    locals: Vec<StString>,
+    names: BTreeSet<StString>,
+}
+
+impl NameCollector {
+    fn add_name(&mut self, name: &StString) {
+        if !self.locals.contains(name) {
+            self.names.insert(name.clone());
+        }
+    }
+
+    fn add_type(&mut self, ty: &Type) {
+        if let Some(name) = ty.user_type_name() {
+            self.names.insert(name.clone());
+        }
+
+        if let Some(array) = ty.as_array() {
+            for dim in array.dimensions() {
+                self.visit_expression(dim.lower());
+                self.visit_expression(dim.upper());
+            }
+            self.add_type(array.base_type());
+        }
+        if let Some(sub) = ty.as_subrange() {
+            self.visit_expression(sub.range().lower());
+            self.visit_expression(sub.range().upper());
+        }
+    }
+}
+
+impl AstVisitor<'_> for NameCollector {
+    fn visit_variable_expression(&mut self, _: &ExprInfo, variable: &VariableExpression) {
+        self.add_name(variable.name())
+    }
+
+    fn visit_call_expression(&mut self, call: &CallExpression) {
+        self.visit_expression(call.callee());
+        for arg in call.arguments() {
+            // names of parameters are not dependencies
+            match &arg.kind {
+                ExprKind::Assign(assign) => self.visit_expression(assign.right()),
+                _ => self.visit_expression(arg),
+            }
+        }
+    }
+
+    fn visit_range_expression(&mut self, range: &RangeExpression) {
+        self.visit_expression(range.lower());
+        self.visit_expression(range.upper());
+    }
+
+    fn visit_compo_access_expression(&mut self, compo: &CompoAccessExpression) {
+        // right side is member, or the name in namespace of left side, like 'Lib.FB' or
+        // 'Motion.Axis.MoveAbs'
+        let mut names = vec![];
+        let mut expr = compo.right();
+        let mut left = compo.left();
+        loop {
+            match &expr.kind {
+                ExprKind::Variable(name) => names.push(name.name()),
+                _ => return self.visit_expression(compo.left()),
+            }
+            match &left.kind {
+                ExprKind::Compo(compo) => {
+                    expr = compo.right();
+                    left = compo.left();
+                }
+                _ => break,
+            }
+        }
+
+        match &left.kind {
+            ExprKind::Variable(ns) if !self.locals.contains(ns.name()) => {
+                let mut name = ns.name().to_string();
+                self.add_name(ns.name());
+                for member in names.iter().rev() {
+                    name = format!("{}.{}", name, member);
+                    self.names.insert(name.as_str().into());
+                }
+            }
+            _ => self.visit_expression(compo.left()),
+        }
+    }
+}
@@ -0,0 +1,216 @@
+use crate::ast::*;
+use crate::backend::utils::{expression_variable_name, wider_class};
+use crate::builtin::{EXTENSIBLE_ATTRIBUTE, GENERIC_ATTRIBUTE};
+use crate::context::Prototype;
+use crate::parser::StString;
+use crate::utils::HasAttribute;
+use smallvec::SmallVec;
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+use std::sync::Arc;
+
+/// Concrete signature of a call to function with generic parameters
+#[derive(Debug, Clone, Default)]
+pub struct FunctionInstance {
+    /// Concrete type of each parameter, None if it can't be decided
+    pub parameters: Vec<Option<Type>>,
+    pub return_type: Option<Type>,
+    /// Concrete type class of each type family used by arguments
+    pub families: Vec<(TypeFamily, TypeClass)>,
+}
+
+/// Reason why a function can't be instantiated for call
+#[derive(Debug, Clone, PartialEq)]
+pub enum GenericError {
+    /// Arguments of the same type family have no common type, like TIME and REAL
+    Ambiguous(TypeFamily, TypeClass, TypeClass),
+    /// Type family of return value isn't decided by any argument
+    Unbound(TypeFamily),
+    /// Parameters of type families are declared without the 'generic' attribute
+    NotGeneric,
+}
+
+impl Error for GenericError {}
+
+impl Display for GenericError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            GenericError::Ambiguous(family, a, b) => {
+                write!(f, "ambiguous {} arguments of type {} and {}", family, a, b)
+            }
+            GenericError::Unbound(family) => {
+                write!(f, "return type {} is not decided by arguments", family)
+            }
+            GenericError::NotGeneric => write!(
+                f,
+                "parameters of generic types require the '{}' attribute",
+                GENERIC_ATTRIBUTE
+            ),
+        }
+    }
+}
+
+/// Parameter index of each argument of call, named arguments are bound by name and
+/// positional arguments to inputs and in-outs in declaration order. The last input of
+/// extensible function takes all the remaining positional arguments.
+pub fn argument_parameters(
+    params: &[Arc<Variable>],
+    call: &CallExpression,
+    extensible: bool,
+) -> Vec<Option<usize>> {
+    let positional: Vec<_> = params
+        .iter()
+        .enumerate()
+        .filter(|(_, x)| {
+            x.flags()
+                .intersects(VariableFlags::INPUT | VariableFlags::INOUT)
+        })
+        .map(|(idx, _)| idx)
+        .collect();
+    let mut positional_index = 0;
+
+    call.arguments()
+        .iter()
+        .map(|arg| match &arg.kind {
+            ExprKind::Assign(assign) => {
+                let name = expression_variable_name(assign.left());
+                params.iter().position(|x| Some(x.name()) == name)
+            }
+            _ => {
+                let idx = match positional.get(positional_index) {
+                    Some(idx) => Some(*idx),
+                    None if extensible => positional.last().copied(),
+                    None => None,
+                };
+                positional_index += 1;
+                idx
+            }
+        })
+        .collect()
+}
+
+/// Return true if the last input of function can be repeated
+pub fn is_extensible(proto: &Prototype) -> bool {
+    proto
+        .read()
+        .unwrap()
+        .decl()
+        .get_attribute_value(&StString::new(EXTENSIBLE_ATTRIBUTE))
+        .is_some()
+}
+
+/// Return true if function can declare parameters of generic type families
+pub fn is_generic(proto: &Prototype) -> bool {
+    proto
+        .read()
+        .unwrap()
+        .decl()
+        .get_attribute_value(&StString::new(GENERIC_ATTRIBUTE))
+        .is_some()
+}
+
+/// Type of family when all arguments are untyped integer constants
+fn family_default(family: TypeFamily) -> Option<TypeClass> {
+    [
+        TypeClass::LInt,
+        TypeClass::ULInt,
+        TypeClass::LReal,
+        TypeClass::String,
+    ]
+    .into_iter()
+    .find(|x| family.contains(*x))
+}
+
+/// Common type of two arguments of the same family, numbers and bit strings are widened
+fn common_class(a: TypeClass, b: TypeClass) -> Option<TypeClass> {
+    let numeric =
+        |x: TypeClass| x.belongs_to(TypeFamily::AnyNum) || x.belongs_to(TypeFamily::AnyBit);
+
+    match (a, b) {
+        _ if a == b => Some(a),
+        _ if numeric(a) && numeric(b) => wider_class(Some(a), Some(b)),
+        _ => None,
+    }
+}
+
+/// Instantiate function for a call, `arguments` are the type classes of call arguments
+/// where None is an unknown type or untyped integer constant. Generic parameters of the
+/// same family share one type, which is the wider type of their arguments.
+pub fn instantiate(
+    proto: &Prototype,
+    call: &CallExpression,
+    arguments: &[Option<TypeClass>],
+) -> Result<FunctionInstance, GenericError> {
+    let extensible = is_extensible(proto);
+    let generic = is_generic(proto);
+    let proto = proto.read().unwrap();
+    let params: Vec<_> = proto
+        .variables()
+        .iter()
+        .filter(|x| !x.scope().is_empty())
+        .cloned()
+        .collect();
+    let return_type = match &proto.decl().kind {
+        DeclKind::Fun(f) => f.return_type().clone(),
+        _ => None,
+    };
+
+    let family_of = |ty: Option<&Type>| match ty.map(|x| x.type_class()) {
+        Some(TypeClass::Generic(family)) => Some(family),
+        _ => None,
+    };
+    let declares_family = params.iter().any(|x| family_of(x.ty()).is_some())
+        || family_of(return_type.as_ref()).is_some();
+    if declares_family && !generic {
+        return Err(GenericError::NotGeneric);
+    }
+
+    // bind families to the common type of arguments
+    let mut families: SmallVec<[(TypeFamily, Option<TypeClass>); 4]> = SmallVec::new();
+    let bound = argument_parameters(&params, call, extensible);
+    for (param, class) in bound.iter().zip(arguments) {
+        let Some(family) = param.and_then(|x| family_of(params[x].ty())) else {
+            continue;
+        };
+        let class = class.filter(|x| family.contains(*x));
+
+        match families.iter_mut().find(|(x, _)| *x == family) {
+            Some((_, bound)) => {
+                *bound = match (*bound, class) {
+                    (Some(a), Some(b)) => {
+                        Some(common_class(a, b).ok_or(GenericError::Ambiguous(family, a, b))?)
+                    }
+                    (a, b) => a.or(b),
+                }
+            }
+            None => families.push((family, class)),
+        }
+    }
+    let families: Vec<_> = families
+        .into_iter()
+        .filter_map(|(family, class)| Some((family, class.or_else(|| family_default(family))?)))
+        .collect();
+
+    let concrete = |ty: Option<&Type>| -> Option<Type> {
+        let ty = ty?;
+        let Some(family) = family_of(Some(ty)) else {
+            return Some(ty.clone());
+        };
+
+        families
+            .iter()
+            .find(|(x, _)| *x == family)
+            .map(|(_, class)| Type::from_class(*class))
+    };
+
+    let return_type = match family_of(return_type.as_ref()) {
+        Some(family) => Some(concrete(return_type.as_ref()).ok_or(GenericError::Unbound(family))?),
+        None => return_type,
+    };
+
+    Ok(FunctionInstance {
+        parameters: params.iter().map(|x| concrete(x.ty())).collect(),
+        return_type,
+        families,
+    })
+}
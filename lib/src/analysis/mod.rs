@@ -1,2 +1,19 @@
 mod type_analyze;
-pub use type_analyze::TypeAnalyzer;
+pub use type_analyze::{TypeAnalyzeError, TypeAnalyzer};
+
+mod generic;
+pub use generic::{
+    argument_parameters, instantiate, is_extensible, is_generic, FunctionInstance, GenericError,
+};
+
+mod constant;
+pub use constant::{eval_constant, fold_constants, ConstError, ConstValue, ConstantScope};
+
+mod declaration;
+pub use declaration::{check_declarations, DeclarationError};
+
+mod pragma;
+pub use pragma::{collect_pragma_messages, PragmaMessage};
+
+mod dependency;
+pub use dependency::{declaration_dependencies, variable_dependencies, Dependencies};
@@ -0,0 +1,95 @@
+use crate::ast::*;
+use crate::backend::utils::sorted_declarations;
+use crate::context::ModuleContext;
+use crate::parser::{pragma_messages, Location, StString, ERROR_ATTRIBUTE, WARNING_ATTRIBUTE};
+use crate::utils::HasAttribute;
+use std::fmt::{self, Display, Formatter};
+
+/// Message of `{warning '...'}` or `{error '...'}` pragma
+#[derive(Debug, Clone)]
+pub struct PragmaMessage {
+    pub is_error: bool,
+    /// Id of declaration the pragma is in
+    pub decl_id: usize,
+    /// Qualified name of declaration the pragma is in
+    pub name: StString,
+    pub message: String,
+    /// Start of statement for pragmas of statements
+    pub location: Option<Location>,
+}
+
+impl Display for PragmaMessage {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.name, self.message)
+    }
+}
+
+/// Messages of pragmas before declarations, variables and statements of application,
+/// ordered by declaration id
+pub fn collect_pragma_messages(app: &ModuleContext) -> Vec<PragmaMessage> {
+    let mut messages = vec![];
+
+    for proto in sorted_declarations(app) {
+        let proto = proto.read().unwrap();
+        let mut collector = MessageCollector {
+            decl_id: proto.id(),
+            name: proto.decl().qualified_name(),
+            messages: &mut messages,
+        };
+
+        collector.add(proto.decl(), None);
+        for variable in proto.variables() {
+            collector.add(variable.as_ref(), None);
+        }
+        if let Some(f) = app.read().get_function(proto.id()) {
+            collector.add_statement(f.read().parse_tree());
+        }
+    }
+
+    messages
+}
+
+struct MessageCollector<'a> {
+    decl_id: usize,
+    name: StString,
+    messages: &'a mut Vec<PragmaMessage>,
+}
+
+impl MessageCollector<'_> {
+    fn add<T: HasAttribute>(&mut self, target: &T, location: Option<Location>) {
+        for (kind, is_error) in [(WARNING_ATTRIBUTE, false), (ERROR_ATTRIBUTE, true)] {
+            for message in pragma_messages(target, kind) {
+                self.messages.push(PragmaMessage {
+                    is_error,
+                    decl_id: self.decl_id,
+                    name: self.name.clone(),
+                    message: message.to_owned(),
+                    location,
+                });
+            }
+        }
+    }
+
+    fn add_statement(&mut self, stmt: &Statement) {
+        self.add(stmt, stmt.info.start_pos);
+
+        match &stmt.kind {
+            StmtKind::Expr(_) => {}
+            StmtKind::If(ifst) => {
+                let else_if = ifst.else_if_list().iter().map(|x| x.then_controlled());
+                let controlled = [ifst.then_controlled()]
+                    .into_iter()
+                    .chain(else_if)
+                    .chain([ifst.else_controlled()]);
+                for stmt in controlled.flatten() {
+                    self.add_statement(stmt);
+                }
+            }
+            StmtKind::Stmts(stmts) => {
+                for stmt in stmts.iter() {
+                    self.add_statement(stmt);
+                }
+            }
+        }
+    }
+}
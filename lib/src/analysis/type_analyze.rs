@@ -1,6 +1,14 @@
+use crate::analysis::{eval_constant, instantiate, ConstError, ConstantScope, GenericError};
 use crate::ast::*;
-use crate::context::Scope;
+use crate::backend::utils::{
+    const_integer, expression_variable_name, pou_kind, sorted_declarations, subrange_bounds,
+    PouKind,
+};
+use crate::context::{ResolveError, Scope};
+use crate::parser::{Operator, StString};
 use smallvec::smallvec;
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
 
 /// Type analysis attribute
 #[derive(Clone, Default)]
@@ -11,10 +19,48 @@ struct TypeAnalyzerAttribute {
     derived_type: Option<Type>,
 }
 
+/// Error found by type analysis
+#[derive(Debug, Clone, PartialEq)]
+pub enum TypeAnalyzeError {
+    /// Function can't be instantiated for arguments of call, like 'MAX(t, r)' of TIME and REAL
+    InvalidCall(String, GenericError),
+    /// Constant expression can't be evaluated, like division by constant zero
+    InvalidConstant(String, ConstError),
+    /// Constant assigned to subrange variable is out of its range
+    OutOfRange(String, i64, (i64, i64)),
+    /// Name is ambiguous between libraries or qualified by unknown namespace, like 'Lib.FB'
+    Unresolved(String, ResolveError),
+    /// Member of 'qualified_only' enum is used without the enum name
+    QualifiedOnly(String, StString),
+}
+
+impl Error for TypeAnalyzeError {}
+
+impl Display for TypeAnalyzeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            TypeAnalyzeError::InvalidCall(call, e) => write!(f, "{}: {}", call, e),
+            TypeAnalyzeError::InvalidConstant(expr, e) => write!(f, "{}: {}", expr, e),
+            TypeAnalyzeError::OutOfRange(expr, value, (lower, upper)) => write!(
+                f,
+                "{}: value {} out of range {}..{}",
+                expr, value, lower, upper
+            ),
+            TypeAnalyzeError::Unresolved(expr, e) => write!(f, "{}: {}", expr, e),
+            TypeAnalyzeError::QualifiedOnly(member, name) => write!(
+                f,
+                "{}: member of enum '{}' must be qualified, like '{}.{}'",
+                member, name, name, member
+            ),
+        }
+    }
+}
+
 #[derive(Default)]
 pub struct TypeAnalyzer {
     local_scope: Scope,
     attribute_stack: Vec<TypeAnalyzerAttribute>,
+    errors: Vec<TypeAnalyzeError>,
 }
 
 impl TypeAnalyzer {
@@ -32,6 +78,12 @@ impl TypeAnalyzer {
         debug_assert_eq!(self.attribute_stack.len(), 0)
     }
 
+    /// Errors found by all analyzed statements
+    #[inline]
+    pub fn errors(&self) -> &[TypeAnalyzeError] {
+        &self.errors
+    }
+
     fn current_scope(&self) -> &Scope {
         self.attribute_stack
             .last()
@@ -64,6 +116,63 @@ impl TypeAnalyzer {
             .pop()
             .expect("TypeAnalyzer attribute stack is empty!")
     }
+
+    /// Names not found are left to backends, only ambiguous names and unknown namespaces
+    /// are reported
+    fn unresolved(&mut self, name: &StString, e: ResolveError) {
+        if !matches!(e, ResolveError::NotFound(_)) {
+            self.errors
+                .push(TypeAnalyzeError::Unresolved(name.to_string(), e));
+        }
+    }
+
+    /// Members of 'qualified_only' enums can't be used without the enum name
+    fn check_qualified_only(&mut self, member: &StString) {
+        let Some(ctx) = self.current_scope().local_context().cloned() else {
+            return;
+        };
+        if ctx.enum_member(None, member).is_some() {
+            return;
+        }
+
+        let enum_name = sorted_declarations(&ctx).into_iter().find_map(|decl| {
+            let decl = decl.read().unwrap();
+            match &decl.decl().kind {
+                DeclKind::Enum(e)
+                    if e.is_qualified_only() && e.fields().iter().any(|x| x.name() == member) =>
+                {
+                    Some(e.name().clone())
+                }
+                _ => None,
+            }
+        });
+        if let Some(name) = enum_name {
+            self.errors
+                .push(TypeAnalyzeError::QualifiedOnly(member.to_string(), name));
+        }
+    }
+
+    /// Bounds of subrange type, aliases are resolved in current scope
+    fn subrange(&self, ty: &Type) -> Option<(i64, i64)> {
+        let scope = self.current_scope();
+        let mut ty = ty.clone();
+
+        // limit the depth in case of recursive aliases
+        for _ in 0..16 {
+            if let Some(sub) = ty.as_subrange() {
+                return subrange_bounds(sub, scope);
+            }
+
+            let decl = scope.find_declaration(ty.user_type_name()?).0?;
+            let alias = match &decl.read().unwrap().decl().kind {
+                DeclKind::Alias(alias) => alias.alias().clone(),
+                _ => return None,
+            };
+            ty = alias;
+        }
+
+        None
+    }
 }
 
 impl AstVisitorMut for TypeAnalyzer {
@@ -82,7 +191,18 @@ impl AstVisitorMut for TypeAnalyzer {
             self.current_scope().find_variable(variable.name())
         };
         let (derived_declaration, decl_scope) =
-            self.current_scope().find_declaration(variable.name());
+            match self.current_scope().resolve_declaration(variable.name()) {
+                Ok((decl, scope)) => (Some(decl), Some(scope)),
+                Err(e) => {
+                    if derived_variable.is_none() {
+                        if !self.top().search_local_only {
+                            self.check_qualified_only(variable.name());
+                        }
+                        self.unresolved(variable.name(), e);
+                    }
+                    (None, None)
+                }
+            };
 
         let attr = self.top_mut();
         let ty = match (derived_variable, derived_declaration) {
@@ -121,6 +241,16 @@ impl AstVisitorMut for TypeAnalyzer {
         };
         expr.set_ty(op_type);
 
+        if matches!(expr.op(), Operator::Division | Operator::Mod) && expr.operands().len() == 2 {
+            let divisor = eval_constant(&expr.operands()[1], self.current_scope());
+            if divisor.is_ok_and(|x| x.is_zero()) {
+                self.errors.push(TypeAnalyzeError::InvalidConstant(
+                    expr.to_string(),
+                    ConstError::DivisionByZero,
+                ));
+            }
+        }
+
         // let ref mut result_type = self.top_mut().derived_type;
         // for attr in operands_attr {
         //     if let Some(true) = attr
@@ -149,10 +279,106 @@ impl AstVisitorMut for TypeAnalyzer {
         self.visit_expression_mut(assign.left_mut());
         let attr = self.pop();
 
+        let bounds = attr.derived_type.as_ref().and_then(|x| self.subrange(x));
+        if let Some((lower, upper)) = bounds {
+            let value = eval_constant(assign.right(), self.current_scope())
+                .ok()
+                .and_then(|x| x.as_i64());
+            if let Some(value) = value.filter(|x| *x < lower || *x > upper) {
+                self.errors.push(TypeAnalyzeError::OutOfRange(
+                    assign.to_string(),
+                    value,
+                    (lower, upper),
+                ));
+            }
+        }
+
         assign.set_ty(attr.derived_type.clone())
     }
 
+    fn visit_call_expression_mut(&mut self, call: &mut CallExpression) {
+        // argument classes for instantiation, untyped integer constants fit any integer
+        let mut arguments = vec![];
+        for arg in call.arguments_mut() {
+            let value = match &mut arg.kind {
+                ExprKind::Assign(assign) => assign.right_mut(),
+                _ => arg,
+            };
+
+            self.push_default();
+            self.visit_expression_mut(value);
+            let attr = self.pop();
+
+            let class = attr
+                .derived_type
+                .or_else(|| value.ty().cloned())
+                .map(|x| x.type_class())
+                .filter(|x| const_integer(value).is_none() || *x == TypeClass::Time);
+            arguments.push(class);
+        }
+
+        let decl = callee_name(call.callee(), self.current_scope())
+            .and_then(|name| {
+                let scope = self.current_scope();
+                match scope.resolve_declaration(&name) {
+                    Ok((decl, _)) => Some(decl),
+                    Err(ResolveError::NotFound(_)) => scope.find_builtin_declaration(&name),
+                    Err(e) => {
+                        self.unresolved(&name, e);
+                        None
+                    }
+                }
+            })
+            .filter(|x| pou_kind(x.read().unwrap().decl()) == Some(PouKind::Function));
+        let ty = match decl.map(|decl| instantiate(&decl, call, &arguments)) {
+            Some(Ok(instance)) => instance.return_type,
+            Some(Err(e)) => {
+                self.errors
+                    .push(TypeAnalyzeError::InvalidCall(call.to_string(), e));
+                None
+            }
+            None => None,
+        };
+
+        call.set_ty(ty.clone());
+        self.top_mut().derived_type = ty;
+    }
+
     fn visit_compo_access_expression_mut(&mut self, compo: &mut CompoAccessExpression) {
+        // qualified name like 'Motion.Axis.MoveAbs' is a declaration or global variable
+        let name = match self.top().search_local_only {
+            true => None,
+            false => qualified_name(compo, self.current_scope()),
+        };
+        if let Some(name) = name {
+            let scope = self.current_scope();
+            let (ty, decl_scope) = match scope.resolve_declaration(&name) {
+                Ok((decl, decl_scope)) => {
+                    (decl.read().unwrap().create_user_type(), Some(decl_scope))
+                }
+                Err(ResolveError::NotFound(_)) => {
+                    let variable = scope.find_global_variable(&name);
+                    (variable.and_then(|x| x.ty().cloned()), None)
+                }
+                Err(e) => {
+                    self.unresolved(&name, e);
+                    (None, None)
+                }
+            };
+
+            if let ExprKind::Variable(variable) = &mut compo.right_mut().kind {
+                variable.set_ty(ty.clone());
+            }
+            compo.set_ty(ty.clone());
+
+            let attr = self.top_mut();
+            if decl_scope.is_some() {
+                attr.scope = decl_scope;
+            }
+            attr.derived_type = ty;
+            return;
+        }
+
         self.push(TypeAnalyzerAttribute::default());
         self.visit_expression_mut(compo.left_mut());
         let attr = self.pop();
@@ -166,6 +392,67 @@ impl AstVisitorMut for TypeAnalyzer {
         compo.set_ty(attr.derived_type.clone());
         self.top_mut().derived_type = attr.derived_type
     }
+
+    fn visit_array_access_expression_mut(
+        &mut self,
+        _: &mut ExprInfo,
+        access: &mut ArrayAccessExpression,
+    ) {
+        self.push_default();
+        self.visit_expression_mut(access.array_mut());
+        let attr = self.pop();
+
+        for index in access.indexes_mut() {
+            self.push_default();
+            self.visit_expression_mut(index);
+            self.pop();
+        }
+
+        // element type is the base type of array
+        let ty = attr
+            .derived_type
+            .as_ref()
+            .and_then(|x| x.as_array())
+            .map(|x| x.base_type().clone());
+
+        access.set_ty(ty.clone());
+        self.top_mut().derived_type = ty
+    }
+}
+
+/// Name of called function, which can be qualified by namespace, like 'Lib.FUN'
+fn callee_name(callee: &Expression, scope: &Scope) -> Option<StString> {
+    match &callee.kind {
+        ExprKind::Compo(compo) => qualified_name(compo, scope),
+        _ => expression_variable_name(callee).cloned(),
+    }
+}
+
+/// Name of compo access of namespace, like 'Motion.Axis.MoveAbs'. None if it's the member
+/// access of variable or the left side isn't a namespace.
+fn qualified_name(compo: &CompoAccessExpression, scope: &Scope) -> Option<StString> {
+    let mut names = vec![expression_variable_name(compo.right())?];
+    let mut left = compo.left();
+    while let ExprKind::Compo(compo) = &left.kind {
+        names.push(expression_variable_name(compo.right())?);
+        left = compo.left();
+    }
+
+    let first = expression_variable_name(left)?;
+    if scope.find_variable(first).is_some() {
+        return None;
+    }
+
+    let mut namespace = first.to_string();
+    for name in names.iter().skip(1).rev() {
+        namespace = format!("{}.{}", namespace, name);
+    }
+    let namespace = StString::from(namespace);
+    if !scope.is_namespace(&namespace) {
+        return None;
+    }
+
+    Some(StString::new(format!("{}.{}", namespace, names[0])))
 }
 
 fn analyze_op_expr_type(op1: &Option<Type>, op2: &Option<Type>) -> Option<Type> {
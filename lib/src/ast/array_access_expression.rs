@@ -0,0 +1,51 @@
+use crate::ast::*;
+use crate::{impl_ast_display, impl_into_expression};
+
+/// Array element access, like `a[1]` or `a[i, j]`
+#[derive(Debug)]
+pub struct ArrayAccessExpression {
+    array: Expression,
+    indexes: SmallVec3<Expression>,
+    ty: Option<Type>,
+}
+
+impl_ast_display!(ArrayAccessExpression, visit_array_access_expression);
+impl_into_expression!(ArrayAccessExpression, |x| Expression::array_access(
+    Box::new(x),
+    None,
+    None
+));
+
+impl ArrayAccessExpression {
+    pub fn new(array: Expression, indexes: SmallVec3<Expression>) -> Self {
+        Self {
+            array,
+            indexes,
+            ty: None,
+        }
+    }
+
+    pub fn array(&self) -> &Expression {
+        &self.array
+    }
+
+    pub fn array_mut(&mut self) -> &mut Expression {
+        &mut self.array
+    }
+
+    pub fn indexes(&self) -> &[Expression] {
+        self.indexes.as_slice()
+    }
+
+    pub fn indexes_mut(&mut self) -> &mut [Expression] {
+        self.indexes.as_mut_slice()
+    }
+
+    pub fn ty(&self) -> Option<Type> {
+        self.ty.clone()
+    }
+
+    pub fn set_ty(&mut self, ty: Option<Type>) {
+        self.ty = ty
+    }
+}
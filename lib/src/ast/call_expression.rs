@@ -6,6 +6,8 @@ use smallvec::smallvec;
 pub struct CallExpression {
     callee: Expression,
     arguments: SmallVec8<Expression>,
+    /// Return type of the called function instance
+    ty: Option<Type>,
 }
 
 impl_ast_display!(CallExpression, visit_call_expression);
@@ -16,11 +18,16 @@ impl CallExpression {
         Self {
             callee,
             arguments: smallvec![],
+            ty: None,
         }
     }
 
     pub fn with_arguments(callee: Expression, arguments: SmallVec8<Expression>) -> Self {
-        Self { callee, arguments }
+        Self {
+            callee,
+            arguments,
+            ty: None,
+        }
     }
 
     pub fn callee(&self) -> &Expression {
@@ -38,4 +45,12 @@ impl CallExpression {
     pub fn arguments_mut(&mut self) -> &mut [Expression] {
         self.arguments.as_mut_slice()
     }
+
+    pub fn ty(&self) -> Option<&Type> {
+        self.ty.as_ref()
+    }
+
+    pub fn set_ty(&mut self, ty: Option<Type>) {
+        self.ty = ty;
+    }
 }
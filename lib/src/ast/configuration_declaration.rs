@@ -0,0 +1,146 @@
+use crate::ast::{Expression, SmallVec8, Variable};
+use crate::impl_has_attribute;
+use crate::parser::StString;
+use crate::utils::AttrMap8;
+use std::sync::Arc;
+
+/// 'TASK Fast(INTERVAL := T#10ms, PRIORITY := 1);'
+#[derive(Debug)]
+pub struct TaskDeclare {
+    name: StString,
+    parameters: SmallVec8<(StString, Box<Expression>)>,
+}
+
+impl TaskDeclare {
+    pub fn new(name: StString, parameters: SmallVec8<(StString, Box<Expression>)>) -> Self {
+        Self { name, parameters }
+    }
+
+    pub fn name(&self) -> &StString {
+        &self.name
+    }
+
+    pub fn parameters(&self) -> &[(StString, Box<Expression>)] {
+        self.parameters.as_slice()
+    }
+
+    /// Value of task parameter, like 'INTERVAL' or 'PRIORITY'
+    pub fn parameter(&self, name: &str) -> Option<&Expression> {
+        self.parameters
+            .iter()
+            .find(|(x, _)| x == name)
+            .map(|(_, v)| v.as_ref())
+    }
+}
+
+/// 'PROGRAM Main1 WITH Fast : Main;', program instance without task is not scheduled
+#[derive(Debug)]
+pub struct ProgramInstanceDeclare {
+    name: StString,
+    task: Option<StString>,
+    program: StString,
+}
+
+impl ProgramInstanceDeclare {
+    pub fn new(name: StString, task: Option<StString>, program: StString) -> Self {
+        Self {
+            name,
+            task,
+            program,
+        }
+    }
+
+    pub fn name(&self) -> &StString {
+        &self.name
+    }
+
+    pub fn task(&self) -> Option<&StString> {
+        self.task.as_ref()
+    }
+
+    /// Name of the PROGRAM declaration
+    pub fn program(&self) -> &StString {
+        &self.program
+    }
+}
+
+/// 'RESOURCE Res ON PLC ... END_RESOURCE'
+#[derive(Debug)]
+pub struct ResourceDeclare {
+    name: StString,
+    target: StString,
+    tasks: Vec<TaskDeclare>,
+    programs: Vec<ProgramInstanceDeclare>,
+}
+
+impl ResourceDeclare {
+    pub fn new(
+        name: StString,
+        target: StString,
+        tasks: Vec<TaskDeclare>,
+        programs: Vec<ProgramInstanceDeclare>,
+    ) -> Self {
+        Self {
+            name,
+            target,
+            tasks,
+            programs,
+        }
+    }
+
+    pub fn name(&self) -> &StString {
+        &self.name
+    }
+
+    /// Processing unit after 'ON'
+    pub fn target(&self) -> &StString {
+        &self.target
+    }
+
+    pub fn tasks(&self) -> &[TaskDeclare] {
+        &self.tasks
+    }
+
+    pub fn programs(&self) -> &[ProgramInstanceDeclare] {
+        &self.programs
+    }
+}
+
+/// 'CONFIGURATION Cfg ... END_CONFIGURATION', global variables of configuration are
+/// visible to the whole application
+#[derive(Debug)]
+pub struct ConfigurationDeclare {
+    name: StString,
+    variables: SmallVec8<Arc<Variable>>,
+    resources: Vec<ResourceDeclare>,
+    attributes: AttrMap8,
+}
+
+impl_has_attribute!(ConfigurationDeclare, attributes);
+
+impl ConfigurationDeclare {
+    pub fn new(
+        name: StString,
+        variables: SmallVec8<Arc<Variable>>,
+        resources: Vec<ResourceDeclare>,
+    ) -> Self {
+        Self {
+            name,
+            variables,
+            resources,
+            attributes: AttrMap8::new(),
+        }
+    }
+
+    pub fn name(&self) -> &StString {
+        &self.name
+    }
+
+    pub fn variables(&self) -> &[Arc<Variable>] {
+        self.variables.as_slice()
+    }
+
+    pub fn resources(&self) -> &[ResourceDeclare] {
+        &self.resources
+    }
+}
@@ -14,11 +14,16 @@ pub enum DeclKind {
     Struct(Box<StructDeclare>),
     Enum(Box<EnumDeclare>),
     GlobalVar(Box<GlobalVariableDeclare>),
+    Configuration(Box<ConfigurationDeclare>),
 }
 
 #[derive(Debug)]
 pub struct Declaration {
     pub(crate) kind: DeclKind,
+    /// Qualified name of the namespace declaration belongs to, empty for global namespace
+    namespace: StString,
+    /// Namespaces whose names can be used without qualifier
+    using: Vec<StString>,
 }
 
 macro_rules! decl_call {
@@ -30,6 +35,7 @@ macro_rules! decl_call {
             DeclKind::Struct(ref mut s) => s.$op($($args),*),
             DeclKind::Enum(ref mut e) => e.$op($($args),*),
             DeclKind::GlobalVar(ref mut g) => g.$op($($args),*),
+            DeclKind::Configuration(ref mut c) => c.$op($($args),*),
             DeclKind::Alias(ref mut a) => a.$op($($args),*),
         }
     };
@@ -41,6 +47,7 @@ macro_rules! decl_call {
             DeclKind::Struct(ref s) => s.$op($($args),*),
             DeclKind::Enum(ref e) => e.$op($($args),*),
             DeclKind::GlobalVar(ref g) => g.$op($($args),*),
+            DeclKind::Configuration(ref c) => c.$op($($args),*),
             DeclKind::Alias(ref a) => a.$op($($args),*),
         }
     };
@@ -52,6 +59,7 @@ macro_rules! decl_call {
             DeclKind::Struct(ref s) => s.$op(),
             DeclKind::Enum(ref e) => e.$op(),
             DeclKind::GlobalVar(ref g) => g.$op(),
+            DeclKind::Configuration(ref c) => c.$op(),
             DeclKind::Alias(ref a) => a.$op(),
         }
     };
@@ -72,17 +80,61 @@ impl HasAttribute for Declaration {
 }
 
 impl Declaration {
+    #[inline]
+    fn new(kind: DeclKind) -> Self {
+        Self {
+            kind,
+            namespace: StString::empty(),
+            using: vec![],
+        }
+    }
+
     #[inline]
     pub fn identifier(&self) -> &StString {
         decl_call!(self, name)
     }
 
+    #[inline]
+    pub fn namespace(&self) -> &StString {
+        &self.namespace
+    }
+
+    /// Put declaration into namespace, the current namespace is nested in it
+    pub fn set_namespace(&mut self, namespace: StString) {
+        if !self.namespace.is_empty() {
+            self.namespace = StString::new(format!("{}.{}", namespace, self.namespace));
+        } else {
+            self.namespace = namespace;
+        }
+    }
+
+    #[inline]
+    pub fn using(&self) -> &[StString] {
+        &self.using
+    }
+
+    pub fn add_using(&mut self, namespace: StString) {
+        if !self.using.contains(&namespace) {
+            self.using.push(namespace);
+        }
+    }
+
+    /// Name with the namespace, like 'Motion.Axis.MoveAbs'
+    pub fn qualified_name(&self) -> StString {
+        if self.namespace.is_empty() {
+            self.identifier().clone()
+        } else {
+            StString::new(format!("{}.{}", self.namespace, self.identifier()))
+        }
+    }
+
     #[inline]
     pub fn kind(&self) -> TokenKind {
         match self.kind {
             DeclKind::Fun(..) => TokenKind::Function,
             DeclKind::Struct(..) => TokenKind::Struct,
             DeclKind::GlobalVar(..) => TokenKind::VarGlobal,
+            DeclKind::Configuration(..) => TokenKind::Configuration,
             _ => unimplemented!(),
         }
     }
@@ -94,22 +146,19 @@ impl Declaration {
             DeclKind::Struct(ref s) => s.variables(),
             DeclKind::Enum(ref e) => e.fields(),
             DeclKind::GlobalVar(ref g) => g.variables(),
+            DeclKind::Configuration(ref c) => c.variables(),
             _ => EMPTY_VARIABLES,
         }
     }
 
     #[inline]
     pub fn fun(fun: Box<FunctionDeclare>) -> Self {
-        Self {
-            kind: DeclKind::Fun(fun),
-        }
+        Self::new(DeclKind::Fun(fun))
     }
 
     #[inline]
     pub fn alias(alias: Box<AliasDeclare>) -> Self {
-        Self {
-            kind: DeclKind::Alias(alias),
-        }
+        Self::new(DeclKind::Alias(alias))
     }
 
     #[inline]
@@ -119,9 +168,7 @@ impl Declaration {
 
     #[inline]
     pub fn struct_(struct_: Box<StructDeclare>) -> Self {
-        Self {
-            kind: DeclKind::Struct(struct_),
-        }
+        Self::new(DeclKind::Struct(struct_))
     }
 
     #[inline]
@@ -131,9 +178,7 @@ impl Declaration {
 
     #[inline]
     pub fn enum_(enum_: Box<EnumDeclare>) -> Self {
-        Self {
-            kind: DeclKind::Enum(enum_),
-        }
+        Self::new(DeclKind::Enum(enum_))
     }
 
     #[inline]
@@ -143,8 +188,11 @@ impl Declaration {
 
     #[inline]
     pub fn global_var(global_var: Box<GlobalVariableDeclare>) -> Self {
-        Self {
-            kind: DeclKind::GlobalVar(global_var),
-        }
+        Self::new(DeclKind::GlobalVar(global_var))
+    }
+
+    #[inline]
+    pub fn configuration(configuration: Box<ConfigurationDeclare>) -> Self {
+        Self::new(DeclKind::Configuration(configuration))
     }
 }
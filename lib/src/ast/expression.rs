@@ -1,6 +1,7 @@
 use crate::ast::{
-    AssignExpression, AstVisitor, AstVisitorMut, CallExpression, CompoAccessExpression,
-    LiteralExpression, OperatorExpression, RangeExpression, VariableExpression,
+    ArrayAccessExpression, AssignExpression, AstVisitor, AstVisitorMut, CallExpression,
+    CompoAccessExpression, LiteralExpression, OperatorExpression, RangeExpression,
+    VariableExpression,
 };
 use crate::impl_ast_display;
 use crate::prelude::*;
@@ -14,6 +15,7 @@ pub enum ExprKind {
     Operator(Box<OperatorExpression>),
     Variable(Box<VariableExpression>),
     Compo(Box<CompoAccessExpression>),
+    ArrayAccess(Box<ArrayAccessExpression>),
     Call(Box<CallExpression>),
     Range(Box<RangeExpression>),
 }
@@ -48,6 +50,7 @@ impl Expression {
             ExprKind::Variable(var_expr) => var_expr.ty(),
             ExprKind::Assign(assign_expr) => assign_expr.ty(),
             ExprKind::Operator(op_expr) => op_expr.ty(),
+            ExprKind::Call(call) => call.ty(),
             _ => None,
         }
     }
@@ -134,6 +137,18 @@ impl Expression {
         Self::compo(Box::new(CompoAccessExpression::new(left, right)))
     }
 
+    #[inline]
+    pub fn array_access(
+        access: Box<ArrayAccessExpression>,
+        start: Option<Location>,
+        end: Option<Location>,
+    ) -> Self {
+        Self {
+            kind: ExprKind::ArrayAccess(access),
+            info: ExprInfo { start, end },
+        }
+    }
+
     #[inline]
     pub fn range(range: Box<RangeExpression>) -> Self {
         Self {
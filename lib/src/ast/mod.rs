@@ -43,6 +43,9 @@ pub use assign_expression::{AssignExpression, AssignType};
 mod compo_access_expression;
 pub use compo_access_expression::CompoAccessExpression;
 
+mod array_access_expression;
+pub use array_access_expression::ArrayAccessExpression;
+
 mod statement;
 pub use statement::{Statement, StmtInfo, StmtKind};
 
@@ -58,6 +61,11 @@ pub use call_expression::CallExpression;
 mod global_variable_declaration;
 pub use global_variable_declaration::GlobalVariableDeclare;
 
+mod configuration_declaration;
+pub use configuration_declaration::{
+    ConfigurationDeclare, ProgramInstanceDeclare, ResourceDeclare, TaskDeclare,
+};
+
 mod range_expression;
 pub use range_expression::{Dimensions, RangeExpression};
 
@@ -172,6 +180,15 @@ impl Type {
         }
     }
 
+    /// Type named by identifier, which is a generic type family like 'ANY_NUM' or a user
+    /// type to be resolved
+    pub fn from_identifier(name: StString) -> Self {
+        match TypeFamily::from_name(name.string()) {
+            Some(family) => Self::from_class(TypeClass::Generic(family)),
+            None => UnknownType::from_name(name).into(),
+        }
+    }
+
     pub fn type_class(&self) -> TypeClass {
         match self.inner.as_ref() {
             TypeEnum::Basic(basic) => *basic,
@@ -182,6 +199,35 @@ impl Type {
     pub fn complex(&self) -> bool {
         matches!(*self.inner, TypeEnum::Complex(..))
     }
+
+    /// Name of user defined type, like struct or function block
+    pub fn user_type_name(&self) -> Option<&StString> {
+        match self.inner.as_ref() {
+            TypeEnum::Basic(_) => None,
+            TypeEnum::Complex(complex) => {
+                let any = complex.as_any();
+                any.downcast_ref::<UnknownType>()
+                    .map(|x| x.name())
+                    .or_else(|| any.downcast_ref::<StructType>().map(|x| x.name()))
+            }
+        }
+    }
+
+    /// Returns array type object if this is an array type
+    pub fn as_array(&self) -> Option<&ArrayType> {
+        match self.inner.as_ref() {
+            TypeEnum::Basic(_) => None,
+            TypeEnum::Complex(complex) => complex.as_any().downcast_ref::<ArrayType>(),
+        }
+    }
+
+    /// Returns subrange type object if this is a subrange type
+    pub fn as_subrange(&self) -> Option<&SubrangeType> {
+        match self.inner.as_ref() {
+            TypeEnum::Basic(_) => None,
+            TypeEnum::Complex(complex) => complex.as_any().downcast_ref::<SubrangeType>(),
+        }
+    }
 }
 
 impl<T> From<T> for Type
@@ -302,6 +348,10 @@ impl Display for VariableFlags {
             write!(f, " PERSISTENT")?;
         }
 
+        if self.contains(Self::CONST) {
+            write!(f, " CONSTANT")?;
+        }
+
         Ok(())
     }
 }
@@ -320,6 +370,7 @@ pub enum DeclareClass {
     Program,
     FunctionBlock,
     Method,
+    Property,
     Action,
 }
 
@@ -357,6 +408,8 @@ pub enum TypeClass {
     Real,
     /// 'LREAL' 64 bits float
     LReal,
+    /// 'TIME', 64 bits signed duration in nanoseconds
+    Time,
     /// 'STRING' string type
     String,
     /// UnknownType
@@ -365,6 +418,16 @@ pub enum TypeClass {
     Array,
     /// StructType
     Struct,
+    /// Generic type family of function parameters, like 'ANY_NUM'
+    Generic(TypeFamily),
+}
+
+impl TypeClass {
+    /// Return true if type class is a member of generic type family
+    #[inline]
+    pub fn belongs_to(&self, family: TypeFamily) -> bool {
+        family.contains(*self)
+    }
 }
 
 impl Hash for TypeClass {
@@ -377,8 +440,20 @@ impl Hash for TypeClass {
             TypeClass::Int => 5,
             TypeClass::UnknownType => 6,
             TypeClass::Array => 7,
-            // Some type shouldn't hash directly like ArrayType or UserType
-            _ => unreachable!("TypeClass shouldn't hash: {:?}", self),
+            TypeClass::SInt => 8,
+            TypeClass::DInt => 9,
+            TypeClass::UDInt => 10,
+            TypeClass::LInt => 11,
+            TypeClass::ULInt => 12,
+            TypeClass::Real => 13,
+            TypeClass::LReal => 14,
+            TypeClass::Time => 15,
+            TypeClass::String => 16,
+            TypeClass::Struct => 17,
+            TypeClass::Generic(family) => {
+                18.hash(state);
+                return family.hash(state);
+            }
         };
 
         tag.hash(state);
@@ -400,7 +475,9 @@ impl Display for TypeClass {
             TypeClass::ULInt => write!(f, "ULINT",),
             TypeClass::Real => write!(f, "REAL"),
             TypeClass::LReal => write!(f, "LREAL"),
+            TypeClass::Time => write!(f, "TIME"),
             TypeClass::String => write!(f, "STRING"),
+            TypeClass::Generic(family) => write!(f, "{}", family),
             TypeClass::UnknownType | TypeClass::Array | TypeClass::Struct => {
                 unreachable!("UserType or ArrayType can't display without Type object")
             }
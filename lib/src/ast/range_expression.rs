@@ -11,6 +11,14 @@ impl RangeExpression {
     pub fn new(lower: Expression, upper: Expression) -> Self {
         Self { lower, upper }
     }
+
+    pub fn lower(&self) -> &Expression {
+        &self.lower
+    }
+
+    pub fn upper(&self) -> &Expression {
+        &self.upper
+    }
 }
 
 pub type Dimensions = SmallVec3<RangeExpression>;
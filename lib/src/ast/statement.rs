@@ -1,7 +1,8 @@
 use crate::ast::{AstVisitor, ExprStatement, IfStatement};
-use crate::impl_ast_display;
 use crate::parser::Location;
 use crate::prelude::*;
+use crate::utils::AttrMap8;
+use crate::{impl_ast_display, impl_has_attribute};
 
 #[derive(Debug)]
 pub enum StmtKind {
@@ -14,6 +15,7 @@ pub enum StmtKind {
 pub struct Statement {
     pub kind: StmtKind,
     pub info: StmtInfo,
+    attributes: AttrMap8,
 }
 
 #[derive(Debug, Default)]
@@ -23,6 +25,7 @@ pub struct StmtInfo {
 }
 
 impl_ast_display!(Statement, visit_statement);
+impl_has_attribute!(Statement, attributes);
 
 impl Statement {
     pub fn update_pos(&mut self, start: Option<Location>, end: Option<Location>) {
@@ -46,6 +49,7 @@ impl Statement {
         Self {
             kind: StmtKind::Stmts(stmts),
             info: StmtInfo::default(),
+            attributes: AttrMap8::new(),
         }
     }
 
@@ -61,6 +65,7 @@ impl Statement {
                 start_pos: start,
                 end_pos: end,
             },
+            attributes: AttrMap8::new(),
         }
     }
 
@@ -81,6 +86,7 @@ impl Statement {
                 start_pos: start,
                 end_pos: end,
             },
+            attributes: AttrMap8::new(),
         }
     }
 }
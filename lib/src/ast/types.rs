@@ -1,3 +1,5 @@
+use crate::parser::QUALIFIED_ONLY_ATTRIBUTE;
+use crate::utils::HasAttribute;
 use crate::{ast::*, impl_has_attribute, utils::AttrMap8};
 use std::sync::Arc;
 
@@ -26,8 +28,135 @@ builtin_type_impl!(struct LIntType, TypeClass::LInt);
 builtin_type_impl!(struct ULIntType, TypeClass::ULInt);
 builtin_type_impl!(struct RealType, TypeClass::Real);
 builtin_type_impl!(struct LRealType, TypeClass::LReal);
+builtin_type_impl!(struct TimeType, TypeClass::Time);
 builtin_type_impl!(struct StringType, TypeClass::String);
 
+/// Generic type families of IEC 61131-3, which are used by parameters of standard functions
+/// and user functions with the 'generic' attribute. WORD, DWORD and LWORD are not supported,
+/// the unsigned integers of the same widths are members of 'ANY_BIT' instead.
+///
+/// ```text
+/// ANY
+/// └── ANY_ELEMENTARY
+///     ├── ANY_MAGNITUDE
+///     │   ├── ANY_NUM
+///     │   │   ├── ANY_REAL
+///     │   │   └── ANY_INT
+///     │   └── ANY_DURATION
+///     ├── ANY_BIT
+///     ├── ANY_STRING
+///     └── ANY_DATE
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TypeFamily {
+    Any,
+    AnyElementary,
+    AnyMagnitude,
+    AnyNum,
+    AnyReal,
+    AnyInt,
+    AnyDuration,
+    AnyBit,
+    AnyString,
+    /// Date and time of day types, which have no members yet
+    AnyDate,
+}
+
+impl TypeFamily {
+    pub fn from_name(name: &str) -> Option<Self> {
+        Some(match name.to_ascii_uppercase().as_str() {
+            "ANY" => TypeFamily::Any,
+            "ANY_ELEMENTARY" => TypeFamily::AnyElementary,
+            "ANY_MAGNITUDE" => TypeFamily::AnyMagnitude,
+            "ANY_NUM" => TypeFamily::AnyNum,
+            "ANY_REAL" => TypeFamily::AnyReal,
+            "ANY_INT" => TypeFamily::AnyInt,
+            "ANY_DURATION" => TypeFamily::AnyDuration,
+            "ANY_BIT" => TypeFamily::AnyBit,
+            "ANY_STRING" => TypeFamily::AnyString,
+            "ANY_DATE" => TypeFamily::AnyDate,
+            _ => return None,
+        })
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            TypeFamily::Any => "ANY",
+            TypeFamily::AnyElementary => "ANY_ELEMENTARY",
+            TypeFamily::AnyMagnitude => "ANY_MAGNITUDE",
+            TypeFamily::AnyNum => "ANY_NUM",
+            TypeFamily::AnyReal => "ANY_REAL",
+            TypeFamily::AnyInt => "ANY_INT",
+            TypeFamily::AnyDuration => "ANY_DURATION",
+            TypeFamily::AnyBit => "ANY_BIT",
+            TypeFamily::AnyString => "ANY_STRING",
+            TypeFamily::AnyDate => "ANY_DATE",
+        }
+    }
+
+    /// The enclosing family, None for 'ANY'
+    pub fn parent(&self) -> Option<TypeFamily> {
+        Some(match self {
+            TypeFamily::Any => return None,
+            TypeFamily::AnyElementary => TypeFamily::Any,
+            TypeFamily::AnyMagnitude
+            | TypeFamily::AnyBit
+            | TypeFamily::AnyString
+            | TypeFamily::AnyDate => TypeFamily::AnyElementary,
+            TypeFamily::AnyNum | TypeFamily::AnyDuration => TypeFamily::AnyMagnitude,
+            TypeFamily::AnyReal | TypeFamily::AnyInt => TypeFamily::AnyNum,
+        })
+    }
+
+    /// Return true if family is `other` or nested in it
+    pub fn is_within(&self, other: TypeFamily) -> bool {
+        let mut family = Some(*self);
+        while let Some(x) = family {
+            if x == other {
+                return true;
+            }
+            family = x.parent();
+        }
+
+        false
+    }
+
+    /// The innermost families of type class, unsigned integers are in both 'ANY_INT' and
+    /// 'ANY_BIT'
+    fn of_class(class: TypeClass) -> &'static [TypeFamily] {
+        use TypeClass::*;
+
+        match class {
+            Bit | Bool => &[TypeFamily::AnyBit],
+            SInt | Int | DInt | LInt => &[TypeFamily::AnyInt],
+            Byte | UInt | UDInt | ULInt => &[TypeFamily::AnyInt, TypeFamily::AnyBit],
+            Real | LReal => &[TypeFamily::AnyReal],
+            Time => &[TypeFamily::AnyDuration],
+            String => &[TypeFamily::AnyString],
+            UnknownType | Array | Struct => &[TypeFamily::Any],
+            Generic(_) => &[],
+        }
+    }
+
+    /// Return true if type class is a member of family, generic classes are members of
+    /// their enclosing families
+    pub fn contains(&self, class: TypeClass) -> bool {
+        if let TypeClass::Generic(family) = class {
+            return family.is_within(*self);
+        }
+
+        TypeFamily::of_class(class)
+            .iter()
+            .any(|x| x.is_within(*self))
+    }
+}
+
+impl Display for TypeFamily {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct UnknownType {
     name: StString,
@@ -53,6 +182,14 @@ impl StructType {
     pub fn new(name: StString, proto: usize) -> Self {
         Self { name, proto }
     }
+
+    pub fn name(&self) -> &StString {
+        &self.name
+    }
+
+    pub fn proto(&self) -> usize {
+        self.proto
+    }
 }
 
 impl TypeTrait for StructType {
@@ -112,6 +249,12 @@ impl EnumDeclare {
     pub fn fields(&self) -> &[Arc<Variable>] {
         &self.fields
     }
+
+    /// Members can't be used without the enum name, declared by `{attribute 'qualified_only'}`
+    pub fn is_qualified_only(&self) -> bool {
+        self.get_attribute_value(&QUALIFIED_ONLY_ATTRIBUTE.into())
+            .is_some()
+    }
 }
 
 // impl Type for EnumDeclare {
@@ -141,6 +284,10 @@ impl AliasDeclare {
     pub fn name(&self) -> &StString {
         &self.name
     }
+
+    pub fn alias(&self) -> &Type {
+        &self.alias
+    }
 }
 
 // impl Type for AliasDeclare {
@@ -241,3 +388,35 @@ impl TypeTrait for ArrayType {
         self
     }
 }
+
+/// Integer type restricted to a range of values, like 'INT(0..100)'. Values are stored as
+/// the base type, the bounds are constant expressions.
+#[derive(Debug)]
+pub struct SubrangeType {
+    base_type: Type,
+    range: RangeExpression,
+}
+
+impl SubrangeType {
+    pub fn new(base_type: Type, range: RangeExpression) -> Self {
+        Self { base_type, range }
+    }
+
+    pub fn base_type(&self) -> &Type {
+        &self.base_type
+    }
+
+    pub fn range(&self) -> &RangeExpression {
+        &self.range
+    }
+}
+
+impl TypeTrait for SubrangeType {
+    fn class(&self) -> TypeClass {
+        self.base_type.type_class()
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
@@ -38,6 +38,15 @@ impl Variable {
         }
     }
 
+    pub fn with_type_initial(name: StString, ty: Type, initial: Box<Expression>) -> Self {
+        Self {
+            name,
+            ty: Some(ty),
+            initial: Some(initial),
+            ..Default::default()
+        }
+    }
+
     /// comma split variable declare list, like: a, b, c: INT;
     pub fn multiple_variable_with_type(
         names: SmallVec8<StString>,
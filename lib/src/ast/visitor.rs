@@ -36,6 +36,11 @@ pub trait DeclVisitorMut: Sized {
         walk_global_variable_declaration_mut(self, decl)
     }
 
+    #[inline]
+    fn visit_configuration_declaration_mut(&mut self, decl: &mut ConfigurationDeclare) {
+        walk_configuration_declaration_mut(self, decl)
+    }
+
     #[inline]
     fn visit_variable_declaration_mut(&mut self, variable: &mut Variable) {
         walk_variable_declaration_mut(self, variable)
@@ -106,6 +111,15 @@ pub trait AstVisitorMut: Sized {
     fn visit_compo_access_expression_mut(&mut self, compo: &mut CompoAccessExpression) {
         walk_compo_access_expression_mut(self, compo)
     }
+
+    #[inline]
+    fn visit_array_access_expression_mut(
+        &mut self,
+        info: &mut ExprInfo,
+        access: &mut ArrayAccessExpression,
+    ) {
+        walk_array_access_expression_mut(self, access)
+    }
 }
 
 #[inline]
@@ -126,6 +140,9 @@ fn walk_expression_mut<V: AstVisitorMut>(vis: &mut V, expr: &mut Expression) {
         ExprKind::Assign(ref mut assign) => vis.visit_assign_expression_mut(assign),
         ExprKind::Operator(ref mut operator) => vis.visit_operator_expression_mut(operator),
         ExprKind::Compo(ref mut compo) => vis.visit_compo_access_expression_mut(compo),
+        ExprKind::ArrayAccess(ref mut access) => {
+            vis.visit_array_access_expression_mut(&mut expr.info, access)
+        }
         ExprKind::Variable(ref mut variable) => {
             vis.visit_variable_expression_mut(&mut expr.info, variable)
         }
@@ -189,6 +206,7 @@ fn walk_declaration_mut<V: DeclVisitorMut>(vis: &mut V, decl: &mut Declaration)
         DeclKind::FB(ref mut fun) => vis.visit_function_declaration_mut(fun),
         DeclKind::Prg(ref mut fun) => vis.visit_function_declaration_mut(fun),
         DeclKind::GlobalVar(ref mut gv) => vis.visit_global_variable_declaration_mut(gv),
+        DeclKind::Configuration(ref mut c) => vis.visit_configuration_declaration_mut(c),
     }
 }
 
@@ -202,6 +220,9 @@ fn walk_global_variable_declaration_mut<V: DeclVisitorMut>(
 ) {
 }
 
+#[inline]
+fn walk_configuration_declaration_mut<V: DeclVisitorMut>(_: &mut V, _: &mut ConfigurationDeclare) {}
+
 #[inline]
 fn walk_enum_declaration_mut<V: DeclVisitorMut>(_: &mut V, _: &mut EnumDeclare) {}
 
@@ -236,6 +257,17 @@ fn walk_compo_access_expression_mut<V: AstVisitorMut>(
     vis.visit_expression_mut(compo.right_mut());
 }
 
+#[inline]
+fn walk_array_access_expression_mut<V: AstVisitorMut>(
+    vis: &mut V,
+    access: &mut ArrayAccessExpression,
+) {
+    vis.visit_expression_mut(access.array_mut());
+    for index in access.indexes_mut() {
+        vis.visit_expression_mut(index);
+    }
+}
+
 // Immutable visitor
 
 pub trait DeclVisitor<'ast>: Sized {
@@ -269,6 +301,11 @@ pub trait DeclVisitor<'ast>: Sized {
         walk_global_variable_declaration(self, decl)
     }
 
+    #[inline]
+    fn visit_configuration_declaration(&mut self, decl: &'ast ConfigurationDeclare) {
+        walk_configuration_declaration(self, decl)
+    }
+
     #[inline]
     fn visit_variable_declaration(&mut self, variable: &'ast Variable) {
         walk_variable_declaration(self, variable)
@@ -339,6 +376,11 @@ pub trait AstVisitor<'ast>: Sized {
     fn visit_compo_access_expression(&mut self, compo: &'ast CompoAccessExpression) {
         walk_compo_access_expression(self, compo)
     }
+
+    #[inline]
+    fn visit_array_access_expression(&mut self, access: &'ast ArrayAccessExpression) {
+        walk_array_access_expression(self, access)
+    }
 }
 
 #[inline]
@@ -364,6 +406,7 @@ fn walk_expression<'a, V: AstVisitor<'a>>(vis: &mut V, expr: &'a Expression) {
         ExprKind::Assign(ref assign) => vis.visit_assign_expression(assign),
         ExprKind::Operator(ref operator) => vis.visit_operator_expression(operator),
         ExprKind::Compo(ref compo) => vis.visit_compo_access_expression(compo),
+        ExprKind::ArrayAccess(ref access) => vis.visit_array_access_expression(access),
         ExprKind::Variable(ref variable) => vis.visit_variable_expression(&expr.info, variable),
         ExprKind::Literal(ref literal) => vis.visit_literal(literal),
         ExprKind::Call(ref call) => vis.visit_call_expression(call),
@@ -424,6 +467,7 @@ fn walk_declaration<'a, V: DeclVisitor<'a>>(vis: &mut V, decl: &'a Declaration)
         DeclKind::FB(ref fun) => vis.visit_function_declaration(fun),
         DeclKind::Prg(ref fun) => vis.visit_function_declaration(fun),
         DeclKind::GlobalVar(ref gv) => vis.visit_global_variable_declaration(gv),
+        DeclKind::Configuration(ref c) => vis.visit_configuration_declaration(c),
     }
 }
 
@@ -446,6 +490,10 @@ fn walk_global_variable_declaration<'a, V: DeclVisitor<'a>>(
 ) {
 }
 
+#[inline]
+fn walk_configuration_declaration<'a, V: DeclVisitor<'a>>(_: &mut V, _: &'a ConfigurationDeclare) {
+}
+
 #[inline]
 fn walk_variable_declaration<'a, V: DeclVisitor<'a>>(_: &mut V, _: &'a Variable) {}
 
@@ -470,3 +518,14 @@ fn walk_compo_access_expression<'a, V: AstVisitor<'a>>(
     vis.visit_expression(compo.left());
     vis.visit_expression(compo.right());
 }
+
+#[inline]
+fn walk_array_access_expression<'a, V: AstVisitor<'a>>(
+    vis: &mut V,
+    access: &'a ArrayAccessExpression,
+) {
+    vis.visit_expression(access.array());
+    for index in access.indexes() {
+        vis.visit_expression(index);
+    }
+}
@@ -0,0 +1,83 @@
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use std::any::Any;
+use std::fmt::{Display, Formatter};
+use std::io;
+use std::io::{Read, Write};
+
+use crate::backend::CompiledCode;
+use crate::parser::StString;
+
+/// C source code of a POU, definitions of its functions
+pub struct CCompiledCode {
+    source: String,
+    /// Prototypes of functions called but not declared in application
+    externals: Vec<(StString, String)>,
+}
+
+impl CCompiledCode {
+    pub fn new(source: String, externals: Vec<(StString, String)>) -> Self {
+        Self { source, externals }
+    }
+
+    #[inline]
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    #[inline]
+    pub fn externals(&self) -> &[(StString, String)] {
+        &self.externals
+    }
+
+    /// Source and externals saved by incremental builds
+    pub(super) fn save(&self, w: &mut dyn Write) -> io::Result<()> {
+        write_string(w, &self.source)?;
+        w.write_u32::<LittleEndian>(self.externals.len() as u32)?;
+        for (name, prototype) in &self.externals {
+            write_string(w, name.as_ref())?;
+            write_string(w, prototype)?;
+        }
+
+        Ok(())
+    }
+
+    pub(super) fn load(r: &mut dyn Read) -> io::Result<Self> {
+        let source = read_string(r)?;
+        let count = r.read_u32::<LittleEndian>()?;
+        let mut externals = vec![];
+        for _ in 0..count {
+            externals.push((read_string(r)?.into(), read_string(r)?));
+        }
+
+        Ok(Self { source, externals })
+    }
+}
+
+fn write_string(w: &mut dyn Write, s: &str) -> io::Result<()> {
+    w.write_u32::<LittleEndian>(s.len() as u32)?;
+    w.write_all(s.as_bytes())
+}
+
+fn read_string(r: &mut dyn Read) -> io::Result<String> {
+    let len = r.read_u32::<LittleEndian>()? as usize;
+    let mut buf = vec![0; len];
+    r.read_exact(&mut buf)?;
+
+    String::from_utf8(buf).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+impl Display for CCompiledCode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+impl CompiledCode for CCompiledCode {
+    fn get_bytes(&self, w: &mut dyn Write) -> io::Result<()> {
+        w.write_all(self.source.as_bytes())
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
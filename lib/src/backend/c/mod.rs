@@ -0,0 +1,1382 @@
+/// C source code of POUs
+mod code;
+pub use code::CCompiledCode;
+
+mod module;
+use module::c_write_module;
+
+#[cfg(test)]
+mod test;
+
+use crate::analysis::eval_constant;
+use crate::backend::utils::*;
+use crate::backend::*;
+use crate::parser::{LiteralValue, Location, Operator, NO_CHECK_ATTRIBUTE};
+use crate::prelude::*;
+use crate::utils::HasAttribute;
+
+use log::*;
+use std::collections::HashSet;
+use std::mem;
+use std::sync::Arc;
+
+/// C99 keywords and names used by generated code, identifiers of ST can't use them directly
+const C_RESERVED_NAMES: &[&str] = &[
+    "auto", "bool", "break", "case", "char", "const", "continue", "default", "do", "double",
+    "else", "enum", "extern", "false", "float", "for", "goto", "if", "inline", "int", "long",
+    "register", "restrict", "return", "self", "short", "signed", "sizeof", "static", "struct",
+    "switch", "true", "typedef", "union", "unsigned", "void", "volatile", "while",
+];
+
+/// Identifier in C, names colliding with C keywords or generated code get a `_` suffix
+fn c_name<S: AsRef<str>>(name: S) -> String {
+    let name = name.as_ref();
+    if C_RESERVED_NAMES.contains(&name) || name.starts_with("stc_") {
+        format!("{}_", name)
+    } else {
+        name.to_owned()
+    }
+}
+
+/// Name of user function, `main` is left for the runtime
+fn c_function_name<S: AsRef<str>>(name: S) -> String {
+    match c_name(name) {
+        x if x == "main" => "main_".to_owned(),
+        x => x,
+    }
+}
+
+/// C type of elementary type class
+fn c_class_type(class: TypeClass) -> Option<&'static str> {
+    Some(match class {
+        TypeClass::Bit | TypeClass::Byte => "uint8_t",
+        TypeClass::Bool => "bool",
+        TypeClass::SInt => "int8_t",
+        TypeClass::Int => "int16_t",
+        TypeClass::UInt => "uint16_t",
+        TypeClass::DInt => "int32_t",
+        TypeClass::UDInt => "uint32_t",
+        TypeClass::LInt | TypeClass::Time => "int64_t",
+        TypeClass::ULInt => "uint64_t",
+        TypeClass::Real => "float",
+        TypeClass::LReal => "double",
+        _ => return None,
+    })
+}
+
+/// C type integer operation is done in, untyped integer literals are LINT
+#[inline]
+fn c_integer_type(class: Option<TypeClass>) -> &'static str {
+    class.and_then(c_class_type).unwrap_or("int64_t")
+}
+
+/// Suffix of runtime helpers for integer type, `i32` or `u64` etc.
+fn c_helper_suffix(class: Option<TypeClass>) -> &'static str {
+    match class.and_then(integer_width) {
+        Some((bits, false)) if bits <= 32 => "u32",
+        Some((_, false)) => "u64",
+        Some((bits, true)) if bits <= 32 => "i32",
+        _ => "i64",
+    }
+}
+
+#[inline]
+fn c_declaration(base: &str, declarator: &str) -> String {
+    if declarator.is_empty() {
+        base.to_owned()
+    } else {
+        format!("{} {}", base, declarator)
+    }
+}
+
+/// Integer constant of type class, `v` is the two's complement bit pattern for ULINT
+fn c_integer(v: i64, class: Option<TypeClass>) -> String {
+    match class {
+        Some(TypeClass::Bool) => return (v != 0).to_string(),
+        Some(class) if is_float_type(class) => return c_float(&v.to_string(), class),
+        _ => {}
+    }
+
+    match class.and_then(integer_width) {
+        Some((64, false)) if !(0..=i32::MAX as i64).contains(&v) => {
+            format!("UINT64_C({})", v as u64)
+        }
+        _ if v == i64::MIN => "INT64_MIN".to_owned(),
+        _ if (i32::MIN as i64 + 1..=i32::MAX as i64).contains(&v) => v.to_string(),
+        _ => format!("INT64_C({})", v),
+    }
+}
+
+/// Floating point constant, REAL constants have a `f` suffix
+fn c_float(s: &str, class: TypeClass) -> String {
+    let mut s = s.replace('_', "");
+    if !s.contains(['.', 'e', 'E']) {
+        s.push_str(".0");
+    }
+    if matches!(class, TypeClass::Real) {
+        s.push('f');
+    }
+
+    s
+}
+
+/// C string literal, non-printable characters are escaped in octal
+fn c_string_literal(s: &str) -> String {
+    let mut r = String::with_capacity(s.len() + 2);
+    r.push('"');
+    for b in s.bytes() {
+        match b {
+            b'"' => r.push_str("\\\""),
+            b'\\' => r.push_str("\\\\"),
+            // avoid trigraphs
+            b'?' => r.push_str("\\?"),
+            0x20..=0x7e => r.push(b as char),
+            _ => r.push_str(&format!("\\{:03o}", b)),
+        }
+    }
+    r.push('"');
+
+    r
+}
+
+/// Literal of type class, untyped integer literals are written in the target type directly
+fn c_literal(literal: &LiteralValue, class: Option<TypeClass>) -> String {
+    match literal {
+        LiteralValue::Bool(b) => b.to_string(),
+        LiteralValue::String(s) => c_string_literal(s),
+        LiteralValue::Real(s) | LiteralValue::LReal(s) => {
+            let class = class
+                .filter(|x| is_float_type(*x))
+                .unwrap_or(literal.ty().type_class());
+            c_float(s, class)
+        }
+        LiteralValue::ULInt(v) if *v > i64::MAX as u64 => format!("UINT64_C({})", v),
+        _ => c_integer(literal_integer(literal).unwrap_or(0), class),
+    }
+}
+
+/// Remove parentheses around the whole expression
+fn unparen(s: &str) -> &str {
+    if !s.starts_with('(') || !s.ends_with(')') {
+        return s;
+    }
+
+    let mut depth = 0;
+    let mut quoted = false;
+    let mut escaped = false;
+    for (idx, c) in s.char_indices() {
+        if quoted {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => quoted = false,
+                _ => {}
+            }
+            continue;
+        }
+
+        match c {
+            '"' => quoted = true,
+            '(' => depth += 1,
+            ')' => {
+                depth -= 1;
+                if depth == 0 && idx != s.len() - 1 {
+                    return s;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    &s[1..s.len() - 1]
+}
+
+/// Generate portable C99 source code, every POU is compiled into C functions:
+///
+/// * PROGRAM `p` is `void p_body(void)`, variables are in `stc_globals.p`
+/// * FUNCTION_BLOCK `fb` is a struct with `void fb_init(fb *self)` and `void fb_body(fb *self)`
+/// * FUNCTION `f` is a C function, outputs and in-outs are passed by pointer
+///
+/// Global variables and variables of programs are members of struct `stc_globals`, which is
+/// initialized by `stc_init()`. Runtime errors are reported to `stc_runtime_error()` and
+/// timers read the current time from `stc_now()`, both should be provided by the runtime.
+pub struct CBackend {
+    ctx: TypeContext,
+
+    // tmp values for generating function
+    lines: Vec<String>,
+    indent: usize,
+    // parameters and local variables read by current function, others are marked as unused
+    read_variables: HashSet<StString>,
+    // function block instance pointer is used
+    self_used: bool,
+    // prototypes of called functions which are not declared in application
+    externals: Vec<(StString, String)>,
+    label_count: usize,
+    loop_depth: usize,
+    // values assigned to subrange variables are checked by `stc_range()`
+    range_check: bool,
+    // checks are disabled by 'no_check' attribute of current POU or statement
+    no_check: bool,
+    // first expression of current declaration can't be generated
+    error: Option<CodeGenError>,
+}
+
+impl CBackend {
+    /// Reset states for generating code of declaration
+    fn enter_declaration(&mut self, proto: &Prototype) {
+        self.ctx.enter_declaration(proto);
+        self.lines.clear();
+        self.indent = 1;
+        self.read_variables.clear();
+        self.self_used = false;
+        self.externals.clear();
+        self.label_count = 0;
+        self.loop_depth = 0;
+        self.no_check = has_no_check(proto.read().unwrap().decl());
+        self.error = None;
+    }
+
+    /// Keep the first error of current declaration, code generation goes on
+    fn report_invalid_expression<E: Display>(&mut self, expr: &E, reason: &str) {
+        if self.error.is_none() {
+            self.error = Some(CodeGenError::InvalidExpression(
+                expr.to_string(),
+                reason.to_owned(),
+            ));
+        }
+    }
+
+    fn push_line<S: AsRef<str>>(&mut self, line: S) {
+        let line = format!("{}{}", "    ".repeat(self.indent), line.as_ref());
+        self.lines.push(line);
+    }
+
+    fn take_lines(&mut self) -> String {
+        let mut s = String::new();
+        for line in self.lines.drain(..) {
+            s.push_str(&line);
+            s.push('\n');
+        }
+
+        s
+    }
+
+    /// Declare `declarator` as type, like `int16_t a[3]`, empty declarator for type name
+    fn c_declare(&self, ty: &Type, declarator: &str) -> String {
+        if let Some(arr) = ty.as_array() {
+            let mut declarator = declarator.to_owned();
+            let dims = array_dimensions(arr, &self.ctx).expect("array bounds must be constant");
+            for (_, len) in dims {
+                declarator.push_str(&format!("[{}]", len));
+            }
+
+            return self.c_declare(arr.base_type(), &declarator);
+        }
+
+        if let Some(name) = ty.user_type_name() {
+            let name = match self.ctx.find_declaration(name) {
+                Some(decl) => c_name(decl.read().unwrap().name().string()),
+                None => c_name(name.string()),
+            };
+
+            return c_declaration(&name, declarator);
+        }
+
+        match ty.type_class() {
+            TypeClass::String => format!("char {}[STC_STRING_SIZE]", declarator),
+            class => c_declaration(c_class_type(class).unwrap_or("int64_t"), declarator),
+        }
+    }
+
+    /// Enum constant of member, `enum_name` is None for unqualified member name
+    fn enum_member(&self, enum_name: Option<&StString>, member: &StString) -> Option<String> {
+        sorted_declarations(self.ctx.app())
+            .into_iter()
+            .find_map(|decl| {
+                let decl = decl.read().unwrap();
+                let DeclKind::Enum(e) = &decl.decl().kind else {
+                    return None;
+                };
+                if enum_name.map_or(e.is_qualified_only(), |x| x != e.name()) {
+                    return None;
+                }
+
+                e.fields()
+                    .iter()
+                    .find(|x| x.name() == member)
+                    .map(|x| format!("{}_{}", c_name(e.name().string()), x.origin_name()))
+            })
+    }
+
+    /// First member of enum type, which is the default value
+    fn enum_default(&self, ty: &Type) -> Option<String> {
+        let decl = self.ctx.type_user_decl(&self.ctx.resolve_type(ty))?;
+        let decl = decl.read().unwrap();
+        let DeclKind::Enum(e) = &decl.decl().kind else {
+            return None;
+        };
+
+        Some(match e.fields().first() {
+            Some(field) => format!("{}_{}", c_name(e.name().string()), field.origin_name()),
+            None => format!("(({})0)", c_name(e.name().string())),
+        })
+    }
+
+    /// Qualified enum member, like `Color.Red`
+    fn compo_enum_member(&self, compo: &CompoAccessExpression) -> Option<String> {
+        let enum_name = expression_variable_name(compo.left())?;
+        let member = expression_variable_name(compo.right())?;
+        if self.ctx.scope.find_variable(enum_name).is_some() {
+            return None;
+        }
+
+        self.enum_member(Some(enum_name), member)
+    }
+
+    /// C lvalue of variable, None if name is not a variable
+    fn variable_ref(&mut self, name: &StString, read: bool) -> Option<String> {
+        if self.ctx.is_return_variable(name) {
+            return Some(c_name(self.ctx.pou_name.string()));
+        }
+
+        if let Some(variable) = self.ctx.scope.find_local_variable(name) {
+            let var = c_name(variable.origin_name());
+
+            return Some(match self.ctx.pou_kind {
+                Some(PouKind::FunctionBlock) => {
+                    self.self_used = true;
+                    format!("self->{}", var)
+                }
+                Some(PouKind::Program) => {
+                    format!("stc_globals.{}.{}", c_name(self.ctx.pou_name.string()), var)
+                }
+                _ => {
+                    if read {
+                        self.read_variables.insert(variable.name().clone());
+                    }
+
+                    if is_reference_parameter(&variable) {
+                        format!("(*{})", var)
+                    } else {
+                        var
+                    }
+                }
+            });
+        }
+
+        let variable = self.ctx.scope.find_global_variable(name)?;
+        Some(format!("stc_globals.{}", c_name(variable.origin_name())))
+    }
+
+    /// Generate expression in its own type
+    fn gen_expression(&mut self, expr: &Expression) -> String {
+        match &expr.kind {
+            ExprKind::Literal(lit) => c_literal(lit.literal(), None),
+            ExprKind::Variable(var) => self
+                .variable_ref(var.name(), true)
+                .or_else(|| self.enum_member(None, var.name()))
+                // TODO: variable not found error
+                .unwrap_or_else(|| c_name(var.name().string())),
+            ExprKind::Compo(compo) => self.gen_compo(compo, true),
+            ExprKind::ArrayAccess(access) => self.gen_array_element(expr.info.start, access, true),
+            ExprKind::Operator(_) if const_integer(expr).is_some() => {
+                c_integer(const_integer(expr).unwrap(), None)
+            }
+            ExprKind::Operator(op_expr) => self.gen_operator_expression(op_expr),
+            ExprKind::Call(call) => self.gen_call(call),
+            ExprKind::Assign(assign) => {
+                let lhs_class = self.ctx.expression_class(assign.left());
+                let value = self.gen_value(assign.right(), lhs_class);
+                let lhs = self.gen_lvalue(assign.left());
+                format!("({} = {})", lhs, unparen(&value))
+            }
+            ExprKind::Range(_) => unreachable!("range can't be evaluated: {}", expr),
+        }
+    }
+
+    /// Generate expression converted to type class
+    fn gen_value(&mut self, expr: &Expression, class: Option<TypeClass>) -> String {
+        if let ExprKind::Literal(lit) = &expr.kind {
+            return c_literal(lit.literal(), class);
+        }
+        if let Some(v) = const_integer(expr) {
+            return c_integer(v, class);
+        }
+
+        let value = self.gen_expression(expr);
+        match class.and_then(c_class_type) {
+            Some(ty) if self.ctx.expression_class(expr) != class => format!("({}){}", ty, value),
+            _ => value,
+        }
+    }
+
+    /// Generate expression as assignment target
+    fn gen_lvalue(&mut self, expr: &Expression) -> String {
+        match &expr.kind {
+            ExprKind::Variable(var) => self
+                .variable_ref(var.name(), false)
+                .unwrap_or_else(|| c_name(var.name().string())),
+            ExprKind::Compo(compo) => self.gen_compo(compo, false),
+            ExprKind::ArrayAccess(access) => self.gen_array_element(expr.info.start, access, false),
+            _ => unreachable!("invalid assignment target: {}", expr),
+        }
+    }
+
+    fn gen_compo(&mut self, compo: &CompoAccessExpression, read: bool) -> String {
+        if let Some(member) = self.compo_enum_member(compo) {
+            return member;
+        }
+
+        let field = match self.ctx.compo_variable(compo) {
+            Some(variable) => variable.origin_name().clone(),
+            None => match expression_variable_name(compo.right()) {
+                Some(name) => name.string().clone(),
+                None => {
+                    self.report_invalid_expression(compo, "invalid member access");
+                    return "0".to_owned();
+                }
+            },
+        };
+        let obj = if read {
+            self.gen_expression(compo.left())
+        } else {
+            self.gen_lvalue(compo.left())
+        };
+
+        format!("{}.{}", obj, c_name(field))
+    }
+
+    /// Array element, indexes are checked against array bounds by `stc_index()` unless checks
+    /// are disabled
+    fn gen_array_element(
+        &mut self,
+        location: Option<Location>,
+        access: &ArrayAccessExpression,
+        read: bool,
+    ) -> String {
+        let dims = self
+            .ctx
+            .expression_type(access.array())
+            .and_then(|ty| {
+                self.ctx
+                    .resolve_type(&ty)
+                    .as_array()
+                    .and_then(|x| array_dimensions(x, &self.ctx))
+            })
+            .expect("array bounds must be constant");
+        let message = match location {
+            Some(loc) => format!(
+                "array index out of range at line {}, column {}: {}",
+                loc.mark + 1,
+                loc.offset + 1,
+                access
+            ),
+            None => format!("array index out of range: {}", access),
+        };
+        let message = c_string_literal(&message);
+
+        let mut element = if read {
+            self.gen_expression(access.array())
+        } else {
+            self.gen_lvalue(access.array())
+        };
+        for (index, (lower, len)) in access.indexes().iter().zip(dims) {
+            let index = self.gen_expression(index);
+            if self.no_check {
+                match lower {
+                    0 => element.push_str(&format!("[{}]", unparen(&index))),
+                    _ => element.push_str(&format!("[{} - {}]", index, c_integer(lower, None))),
+                }
+                continue;
+            }
+
+            element.push_str(&format!(
+                "[stc_index({}, {}, {}, {})]",
+                unparen(&index),
+                c_integer(lower, None),
+                len,
+                message
+            ));
+        }
+
+        element
+    }
+
+    fn gen_operator_expression(&mut self, op_expr: &OperatorExpression) -> String {
+        let op = *op_expr.op();
+        let operands = op_expr.operands();
+        let class = self.ctx.operands_class(operands);
+        let is_float = class.map(is_float_type).unwrap_or(false);
+        let ty = c_integer_type(class);
+
+        if operands.len() == 1 {
+            let value = self.gen_value(&operands[0], class);
+            return match op {
+                Operator::Minus if is_float => format!("(-{})", value),
+                Operator::Minus => {
+                    let unsigned_ty = c_integer_type(Some(unsigned_class(class)));
+                    format!("(({})-({}){})", ty, unsigned_ty, value)
+                }
+                Operator::Not if matches!(class, Some(TypeClass::Bool)) => format!("(!{})", value),
+                Operator::Not if matches!(class, Some(TypeClass::Bit)) => {
+                    format!("(({})!{})", ty, value)
+                }
+                Operator::Not => format!("(({})~{})", ty, value),
+                _ => unreachable!("{:?}", op),
+            };
+        }
+
+        let lhs = self.gen_value(&operands[0], class);
+        let rhs = self.gen_value(&operands[1], class);
+        match op {
+            Operator::Less
+            | Operator::LessEqual
+            | Operator::Greater
+            | Operator::GreaterEqual
+            | Operator::Equal
+            | Operator::NotEqual => {
+                let c_op = match op {
+                    Operator::Less => "<",
+                    Operator::LessEqual => "<=",
+                    Operator::Greater => ">",
+                    Operator::GreaterEqual => ">=",
+                    Operator::Equal => "==",
+                    _ => "!=",
+                };
+
+                if matches!(class, Some(TypeClass::String)) {
+                    format!("(strcmp({}, {}) {} 0)", lhs, rhs, c_op)
+                } else {
+                    format!("({} {} {})", lhs, c_op, rhs)
+                }
+            }
+            // boolean logic
+            Operator::BitAnd if matches!(class, Some(TypeClass::Bool)) => {
+                format!("({} && {})", lhs, rhs)
+            }
+            Operator::BitOr if matches!(class, Some(TypeClass::Bool)) => {
+                format!("({} || {})", lhs, rhs)
+            }
+            Operator::Xor if matches!(class, Some(TypeClass::Bool)) => {
+                format!("({} != {})", lhs, rhs)
+            }
+            // bitwise operators, result always in range
+            Operator::BitAnd | Operator::BitOr | Operator::Xor => {
+                let c_op = match op {
+                    Operator::BitAnd => "&",
+                    Operator::BitOr => "|",
+                    _ => "^",
+                };
+                format!("(({})({} {} {}))", ty, lhs, c_op, rhs)
+            }
+            // float arithmetic
+            Operator::Mod if is_float => {
+                let f = if matches!(class, Some(TypeClass::Real)) {
+                    "fmodf"
+                } else {
+                    "fmod"
+                };
+                format!("{}({}, {})", f, lhs, rhs)
+            }
+            Operator::Power if is_float => {
+                let f = if matches!(class, Some(TypeClass::Real)) {
+                    "powf"
+                } else {
+                    "pow"
+                };
+                format!("{}({}, {})", f, lhs, rhs)
+            }
+            _ if is_float => format!("({} {} {})", lhs, arith_operator(op), rhs),
+            // integer arithmetic, division by zero is a runtime error
+            Operator::Division | Operator::Mod | Operator::Power => {
+                let f = match op {
+                    Operator::Division => "div",
+                    Operator::Mod => "mod",
+                    _ => "pow",
+                };
+                let suffix = match (op, class.and_then(integer_width)) {
+                    // power is calculated in 64 bits and wrapped
+                    (Operator::Power, Some((_, false))) => "u64",
+                    (Operator::Power, _) => "i64",
+                    _ => c_helper_suffix(class),
+                };
+                format!("(({})stc_{}_{}({}, {}))", ty, f, suffix, lhs, rhs)
+            }
+            // integer arithmetic wraps around in unsigned type
+            _ => {
+                let unsigned_ty = c_integer_type(Some(unsigned_class(class)));
+                format!(
+                    "(({})(({}){} {} ({}){}))",
+                    ty,
+                    unsigned_ty,
+                    lhs,
+                    arith_operator(op),
+                    unsigned_ty,
+                    rhs
+                )
+            }
+        }
+    }
+
+    /// Call of function or program in expression, function blocks are called by `gen_fb_call`
+    fn gen_call(&mut self, call: &CallExpression) -> String {
+        let Some(name) = expression_variable_name(call.callee()).cloned() else {
+            self.report_invalid_expression(call, "invalid callee");
+            return "0".to_owned();
+        };
+        match self.ctx.standard_call(call) {
+            Some(Ok(standard)) => {
+                return self.gen_standard_call(call, &standard).unwrap_or_else(|| {
+                    self.report_invalid_expression(call, "standard function not supported");
+                    "0".to_owned()
+                })
+            }
+            Some(Err(e)) => {
+                self.report_invalid_expression(call, &e.to_string());
+                return "0".to_owned();
+            }
+            None => {}
+        }
+
+        let decl = self.ctx.find_declaration(&name);
+        let kind = decl
+            .as_ref()
+            .and_then(|x| pou_kind(x.read().unwrap().decl()));
+
+        match (decl, kind) {
+            (Some(decl), Some(PouKind::Function)) => self.gen_function_call(call, &decl),
+            (Some(decl), Some(PouKind::Program)) => {
+                format!("{}_body()", c_name(decl.read().unwrap().name().string()))
+            }
+            _ => self.gen_external_call(call, &name),
+        }
+    }
+
+    /// Standard function as C expression, None if it isn't supported. Functions returning
+    /// STRING and conversions of STRING are not supported.
+    fn gen_standard_call(
+        &mut self,
+        call: &CallExpression,
+        standard: &StandardCall,
+    ) -> Option<String> {
+        let class = standard.return_class;
+        let ty = c_class_type(class)?;
+
+        let mut args = vec![];
+        for (arg, arg_class) in &standard.arguments {
+            let value = match arg {
+                Some(idx) => {
+                    let value =
+                        self.gen_value(argument_value(&call.arguments()[*idx]), Some(*arg_class));
+                    unparen(&value).to_owned()
+                }
+                None if *arg_class == TypeClass::String => "\"\"".to_owned(),
+                None => c_integer(0, Some(*arg_class)),
+            };
+            args.push(value);
+        }
+
+        if let Some((from, to)) = standard.conversion() {
+            return match (from, to) {
+                (TypeClass::String, _) | (_, TypeClass::String) => None,
+                // floats are rounded to integers
+                _ if is_float_type(from) && !is_float_type(to) && to != TypeClass::Bool => {
+                    Some(format!("(({})round({}))", ty, args[0]))
+                }
+                _ => Some(format!("(({}){})", ty, args[0])),
+            };
+        }
+
+        let float_suffix = if class == TypeClass::Real { "f" } else { "" };
+        let helper_suffix = match class {
+            _ if is_float_type(class) => "f64",
+            TypeClass::Bool => "u64",
+            _ if matches!(integer_width(class), Some((_, false))) => "u64",
+            _ => "i64",
+        };
+        let bits = match class {
+            TypeClass::Bool => 1,
+            _ => integer_width(class).map_or(64, |(bits, _)| bits),
+        };
+
+        let name = standard.name.as_str();
+        Some(match name {
+            "ABS" if is_float_type(class) => format!("fabs{}({})", float_suffix, args[0]),
+            "ABS" if helper_suffix == "u64" => args[0].clone(),
+            "ABS" => format!("(({})stc_abs_i64({}))", ty, args[0]),
+            "SQRT" | "LN" | "LOG" | "EXP" | "SIN" | "COS" | "TAN" | "ASIN" | "ACOS" | "ATAN" => {
+                let f = match name {
+                    "LN" => "log",
+                    "LOG" => "log10",
+                    _ => &standard.name,
+                };
+                format!("{}{}({})", f.to_ascii_lowercase(), float_suffix, args[0])
+            }
+            "EXPT" => format!("pow{}({}, {})", float_suffix, args[0], args[1]),
+            "TRUNC" => format!("(({}){})", ty, args[0]),
+            "SHL" | "SHR" | "ROL" | "ROR" => format!(
+                "(({})stc_{}((uint64_t){}, {}, {}))",
+                ty,
+                name.to_ascii_lowercase(),
+                args[0],
+                args[1],
+                bits
+            ),
+            "SEL" => format!("({} ? {} : {})", args[0], args[2], args[1]),
+            "MAX" | "MIN" => {
+                let f = format!("stc_{}_{}", name.to_ascii_lowercase(), helper_suffix);
+                let value = args
+                    .iter()
+                    .skip(1)
+                    .fold(args[0].clone(), |acc, x| format!("{}({}, {})", f, acc, x));
+                format!("(({}){})", ty, value)
+            }
+            "LIMIT" => format!(
+                "(({})stc_limit_{}({}, {}, {}))",
+                ty, helper_suffix, args[0], args[1], args[2]
+            ),
+            // all inputs are evaluated, K selects one of them
+            "MUX" => format!(
+                "(({}[]){{{}}})[stc_index({}, 0, {}, \"MUX K out of range\")]",
+                ty,
+                args[1..].join(", "),
+                args[0],
+                args.len() - 1
+            ),
+            "LEN" => format!("(({})strlen({}))", ty, args[0]),
+            "FIND" => format!("stc_find({}, {})", args[0], args[1]),
+            "STC_NOW" => "stc_now()".to_owned(),
+            _ => return None,
+        })
+    }
+
+    fn gen_function_call(&mut self, call: &CallExpression, decl: &Prototype) -> String {
+        let (name, params) = {
+            let decl = decl.read().unwrap();
+            let params: Vec<_> = decl
+                .variables()
+                .iter()
+                .filter(|x| !x.scope().is_empty())
+                .cloned()
+                .collect();
+            (c_function_name(decl.name().string()), params)
+        };
+        let bound = bind_arguments(&params, call);
+
+        let mut args = vec![];
+        for (param, arg) in params.iter().zip(bound) {
+            let ty = param.ty().cloned().unwrap_or_else(LIntType::new_type);
+            let arg = match arg {
+                Some(arg) if is_reference_parameter(param) => {
+                    format!("&{}", self.gen_expression(arg))
+                }
+                Some(arg) => {
+                    unparen(&self.gen_value(arg, Some(self.ctx.type_class(&ty)))).to_owned()
+                }
+                // not connected output, write into a temporary object
+                None if is_reference_parameter(param) => {
+                    format!("&({}){{0}}", self.c_declare(&ty, ""))
+                }
+                None => self.gen_default_value(&ty, param.initial().as_deref()),
+            };
+            args.push(arg);
+        }
+
+        format!("{}({})", name, args.join(", "))
+    }
+
+    /// Call of function not declared in application, prototype is derived from arguments
+    fn gen_external_call(&mut self, call: &CallExpression, name: &StString) -> String {
+        let mut args = vec![];
+        let mut params = vec![];
+        for arg in call.arguments() {
+            let value = match &arg.kind {
+                ExprKind::Assign(assign) => assign.right(),
+                _ => arg,
+            };
+
+            params.push(self.external_parameter_type(value));
+            args.push(unparen(&self.gen_expression(value)).to_owned());
+        }
+
+        let name = c_name(name.string());
+        if !self.externals.iter().any(|(x, _)| x.string() == &name) {
+            let params = if params.is_empty() {
+                "void".to_owned()
+            } else {
+                params.join(", ")
+            };
+            let prototype = format!("void {}({});", name, params);
+            self.externals.push((StString::new(&name), prototype));
+        }
+
+        format!("{}({})", name, args.join(", "))
+    }
+
+    fn external_parameter_type(&self, value: &Expression) -> String {
+        match self
+            .ctx
+            .expression_type(value)
+            .map(|x| self.ctx.resolve_type(&x))
+        {
+            Some(ty) if ty.as_array().is_some() => "void *".to_owned(),
+            Some(ty) if matches!(ty.type_class(), TypeClass::String) => "const char *".to_owned(),
+            Some(ty) => self.c_declare(&ty, ""),
+            None => match self.ctx.expression_class(value) {
+                Some(TypeClass::String) => "const char *".to_owned(),
+                class => c_integer_type(class).to_owned(),
+            },
+        }
+    }
+
+    /// Default value of type as expression, for arguments of not connected inputs
+    fn gen_default_value(&mut self, ty: &Type, initial: Option<&Expression>) -> String {
+        let class = self.ctx.type_class(ty);
+        if let Some(initial) = initial {
+            // initial values may refer to constants, they are folded like bodies
+            if let Ok(value) = eval_constant(initial, &self.ctx) {
+                return c_literal(&value.to_literal(), Some(class));
+            }
+
+            return unparen(&self.gen_value(initial, Some(class))).to_owned();
+        }
+        if let Some(value) = self.enum_default(ty) {
+            return value;
+        }
+        if let Some((lower, _)) = self.ctx.subrange(ty) {
+            return c_integer(lower, Some(class));
+        }
+
+        match class {
+            TypeClass::String => "\"\"".to_owned(),
+            _ if c_class_type(class).is_some() => c_integer(0, Some(class)),
+            _ => format!("({}){{0}}", self.c_declare(ty, "")),
+        }
+    }
+
+    /// Store value into lvalue, arrays are copied by `memcpy` and strings are truncated
+    fn gen_store(&mut self, lhs: &str, ty: Option<&Type>, value: &str) {
+        let ty = ty.map(|x| self.ctx.resolve_type(x));
+        match ty {
+            Some(ty) if ty.as_array().is_some() => {
+                let size = self.c_declare(&ty, "");
+                self.push_line(format!("memcpy({}, {}, sizeof({}));", lhs, value, size));
+            }
+            Some(ty) if matches!(ty.type_class(), TypeClass::String) => {
+                self.push_line(format!("stc_string_assign({}, {});", lhs, unparen(value)));
+            }
+            _ => self.push_line(format!("{} = {};", lhs, unparen(value))),
+        }
+    }
+
+    /// Initialize object with initial value or default value of type
+    fn gen_init(&mut self, target: &str, ty: &Type, initial: Option<&Expression>) {
+        let resolved = self.ctx.resolve_type(ty);
+
+        if let Some(arr) = resolved.as_array() {
+            let dims = array_dimensions(arr, &self.ctx).expect("array bounds must be constant");
+            let base = arr.base_type().clone();
+            if self.is_zero_default(&base) {
+                self.push_line(format!("memset({}, 0, sizeof({}));", target, target));
+                return;
+            }
+
+            // initialize elements one by one
+            let mut element = target.to_owned();
+            for (_, len) in dims.iter() {
+                let i = format!("i{}", self.loop_depth);
+                self.push_line(format!(
+                    "for (int64_t {} = 0; {} < {}; {}++) {{",
+                    i, i, len, i
+                ));
+                element.push_str(&format!("[{}]", i));
+                self.indent += 1;
+                self.loop_depth += 1;
+            }
+            self.gen_init(&element, &base, None);
+            for _ in dims.iter() {
+                self.loop_depth -= 1;
+                self.indent -= 1;
+                self.push_line("}");
+            }
+            return;
+        }
+
+        if let Some(decl) = self.ctx.type_user_decl(&resolved) {
+            let (name, has_init) = {
+                let decl = decl.read().unwrap();
+                let has_init =
+                    decl.is_function_block() || matches!(decl.decl().kind, DeclKind::Struct(_));
+                (c_name(decl.name().string()), has_init)
+            };
+
+            if has_init {
+                self.push_line(format!("{}_init(&{});", name, target));
+                return;
+            }
+        }
+
+        let value = self.gen_default_value(ty, initial);
+        self.gen_store(target, Some(ty), &value);
+    }
+
+    /// Zero bytes are the default value of type, objects can be initialized by `memset`
+    fn is_zero_default(&self, ty: &Type) -> bool {
+        let ty = self.ctx.resolve_type(ty);
+        if let Some((lower, _)) = self.ctx.subrange(&ty) {
+            return lower == 0;
+        }
+        if let Some(decl) = self.ctx.type_user_decl(&ty) {
+            let decl = decl.read().unwrap();
+            return match &decl.decl().kind {
+                DeclKind::Enum(e) => enum_values(e).first().map(|(_, v)| *v == 0).unwrap_or(true),
+                _ => false,
+            };
+        }
+
+        match ty.as_array() {
+            Some(arr) => self.is_zero_default(arr.base_type()),
+            None => {
+                c_class_type(ty.type_class()).is_some()
+                    || matches!(ty.type_class(), TypeClass::String)
+            }
+        }
+    }
+
+    fn gen_statement(&mut self, stmt: &Statement) {
+        let no_check = self.no_check;
+        self.no_check |= has_no_check(stmt);
+
+        match &stmt.kind {
+            StmtKind::Expr(expr) => self.gen_expression_statement(expr.expr()),
+            StmtKind::If(ifst) => self.gen_if_statement(ifst),
+            StmtKind::Stmts(stmts) => {
+                for stmt in stmts.iter() {
+                    self.gen_statement(stmt);
+                }
+            }
+        }
+
+        self.no_check = no_check;
+    }
+
+    fn gen_expression_statement(&mut self, expr: &Expression) {
+        trace!("CGen: expression statement: {}", expr);
+
+        match &expr.kind {
+            ExprKind::Assign(assign) => self.gen_assign(assign),
+            ExprKind::Call(call) => {
+                // function block instance call
+                if let Some(ty) = self.ctx.expression_type(call.callee()) {
+                    let decl = self.ctx.type_user_decl(&self.ctx.resolve_type(&ty));
+                    if let Some(fb) = decl.filter(|x| x.read().unwrap().is_function_block()) {
+                        return self.gen_fb_call(call, &fb);
+                    }
+                }
+
+                let call = self.gen_call(call);
+                self.push_line(format!("{};", call));
+            }
+            _ => {
+                let value = self.gen_expression(expr);
+                self.push_line(format!("(void){};", value));
+            }
+        }
+    }
+
+    fn gen_assign(&mut self, assign: &AssignExpression) {
+        let lhs_type = self.ctx.expression_type(assign.left());
+        let lhs_class = self.ctx.expression_class(assign.left());
+
+        match assign.assign_type() {
+            AssignType::Set | AssignType::Reset => {
+                let cond = self.gen_expression(assign.right());
+                let lhs = self.gen_lvalue(assign.left());
+                let value = matches!(assign.assign_type(), AssignType::Set);
+
+                self.push_line(format!("if ({}) {{", unparen(&cond)));
+                self.push_line(format!("    {} = {};", lhs, value));
+                self.push_line("}");
+            }
+            _ => {
+                let mut value = self.gen_value(assign.right(), lhs_class);
+                let bounds = lhs_type.as_ref().and_then(|x| self.ctx.subrange(x));
+                if let Some((lower, upper)) = bounds.filter(|_| self.range_check && !self.no_check)
+                {
+                    let message = format!("value out of range {}..{}: {}", lower, upper, assign);
+                    value = format!(
+                        "stc_range({}, {}, {}, {})",
+                        unparen(&value),
+                        c_integer(lower, None),
+                        c_integer(upper, None),
+                        c_string_literal(&message)
+                    );
+                }
+
+                let lhs = self.gen_lvalue(assign.left());
+                self.gen_store(&lhs, lhs_type.as_ref(), &value);
+            }
+        }
+    }
+
+    fn gen_if_statement(&mut self, ifst: &IfStatement) {
+        trace!("CGen: if statement: {}", ifst.condition());
+
+        let cond = self.gen_expression(ifst.condition());
+        self.push_line(format!("if ({}) {{", unparen(&cond)));
+        self.gen_block(ifst.then_controlled());
+
+        for else_if in ifst.else_if_list() {
+            let cond = self.gen_expression(else_if.condition());
+            self.push_line(format!("}} else if ({}) {{", unparen(&cond)));
+            self.gen_block(else_if.then_controlled());
+        }
+
+        if let Some(else_ctrl) = ifst.else_controlled() {
+            self.push_line("} else {");
+            self.gen_block(Some(else_ctrl));
+        }
+
+        self.push_line("}");
+    }
+
+    fn gen_block(&mut self, stmt: Option<&Statement>) {
+        self.indent += 1;
+        if let Some(stmt) = stmt {
+            self.gen_statement(stmt);
+        }
+        self.indent -= 1;
+    }
+
+    /// Call function block instance: inputs are stored into instance, then call body of
+    /// function block with instance, outputs are read from instance after call.
+    fn gen_fb_call(&mut self, call: &CallExpression, fb: &Prototype) {
+        let (fb_name, members) = {
+            let fb = fb.read().unwrap();
+            (c_name(fb.name().string()), fb.variables().to_vec())
+        };
+        let inst = self.gen_expression(call.callee());
+
+        // inputs
+        let mut inputs = members
+            .iter()
+            .filter(|x| x.flags().contains(VariableFlags::INPUT));
+        for arg in call.arguments() {
+            let (member, value) = match &arg.kind {
+                ExprKind::Assign(assign) => match assign.assign_type() {
+                    AssignType::AssignRight => continue,
+                    _ => {
+                        let name = expression_variable_name(assign.left());
+                        let member = members.iter().find(|x| Some(x.name()) == name);
+                        (member, assign.right())
+                    }
+                },
+                _ => (inputs.next(), arg),
+            };
+            // TODO: unknown parameter error
+            let Some(member) = member else { continue };
+
+            let member_class = member.ty().map(|x| self.ctx.type_class(x));
+            let value = self.gen_value(value, member_class);
+            let lhs = format!("{}.{}", inst, c_name(member.origin_name()));
+            self.gen_store(&lhs, member.ty(), &value);
+        }
+
+        self.push_line(format!("{}_body(&{});", fb_name, inst));
+
+        // outputs
+        for arg in call.arguments() {
+            let ExprKind::Assign(assign) = &arg.kind else {
+                continue;
+            };
+            if !matches!(assign.assign_type(), AssignType::AssignRight) {
+                continue;
+            }
+            let name = expression_variable_name(assign.left());
+            let Some(member) = members.iter().find(|x| Some(x.name()) == name) else {
+                continue;
+            };
+
+            let target_type = self.ctx.expression_type(assign.right());
+            let target = self.gen_lvalue(assign.right());
+            let value = format!("{}.{}", inst, c_name(member.origin_name()));
+            self.gen_store(&target, target_type.as_ref(), &value);
+        }
+    }
+
+    /// C prototype of function without semicolon
+    fn function_signature(&self, proto: &Prototype) -> String {
+        let p = proto.read().unwrap();
+        let name = c_name(p.name().string());
+
+        match pou_kind(p.decl()) {
+            Some(PouKind::FunctionBlock) => format!("void {}_body({} *self)", name, name),
+            Some(PouKind::Function) => {
+                let params: Vec<_> = p
+                    .variables()
+                    .iter()
+                    .filter(|x| !x.scope().is_empty())
+                    .map(|x| self.c_parameter(x))
+                    .collect();
+                let params = if params.is_empty() {
+                    "void".to_owned()
+                } else {
+                    params.join(", ")
+                };
+                let ret = match function_return_type(proto) {
+                    Some(ty) => self.c_declare(&ty, ""),
+                    None => "void".to_owned(),
+                };
+
+                format!("{} {}({})", ret, c_function_name(p.name().string()), params)
+            }
+            _ => format!("void {}_body(void)", name),
+        }
+    }
+
+    /// Function parameter, arrays and strings are passed as pointers like C does
+    fn c_parameter(&self, variable: &Variable) -> String {
+        let ty = variable.ty().cloned().unwrap_or_else(LIntType::new_type);
+        let name = c_name(variable.origin_name());
+
+        if is_reference_parameter(variable) {
+            self.c_declare(&ty, &format!("(*{})", name))
+        } else {
+            self.c_declare(&ty, &name)
+        }
+    }
+
+    /// Body of init function of struct or function block
+    fn gen_init_function(&mut self, name: &str, members: &[Arc<Variable>]) -> String {
+        let mut s = format!("void {}_init({} *self)\n{{\n", name, name);
+        if members.iter().all(|x| x.ty().is_none()) {
+            self.push_line("(void)self;");
+        }
+        for member in members {
+            let Some(ty) = member.ty() else { continue };
+
+            let target = format!("self->{}", c_name(member.origin_name()));
+            self.gen_init(&target, ty, member.initial().as_deref());
+        }
+        s.push_str(&self.take_lines());
+        s.push_str("}\n");
+
+        s
+    }
+
+    fn gen_function_block(&mut self, f: &Function, proto: &Prototype) -> String {
+        let (name, members) = {
+            let p = proto.read().unwrap();
+            (c_name(p.name().string()), p.variables().to_vec())
+        };
+
+        let mut s = self.gen_init_function(&name, &members);
+        s.push('\n');
+        self.self_used = false;
+
+        self.gen_statement(f.read().parse_tree());
+        let body = self.take_lines();
+
+        s.push_str(&self.function_signature(proto));
+        s.push_str("\n{\n");
+        if !self.self_used {
+            s.push_str("    (void)self;\n");
+        }
+        s.push_str(&body);
+        s.push_str("}\n");
+
+        s
+    }
+
+    fn gen_program(&mut self, f: &Function, proto: &Prototype) -> String {
+        self.gen_statement(f.read().parse_tree());
+        let body = self.take_lines();
+
+        format!("{}\n{{\n{}}}\n", self.function_signature(proto), body)
+    }
+
+    fn gen_function_pou(&mut self, f: &Function, proto: &Prototype) -> String {
+        let variables = proto.read().unwrap().variables().to_vec();
+
+        self.gen_statement(f.read().parse_tree());
+        let body = self.take_lines();
+
+        // return value and local variables
+        if let Some(ty) = self.ctx.return_type.clone() {
+            let name = c_name(self.ctx.pou_name.string());
+            self.gen_local_variable(&name, &ty, None);
+        }
+        for variable in variables.iter().filter(|x| x.scope().is_empty()) {
+            let Some(ty) = variable.ty() else { continue };
+            self.gen_local_variable(
+                &c_name(variable.origin_name()),
+                ty,
+                variable.initial().as_deref(),
+            );
+        }
+        for variable in variables.iter() {
+            if !self.read_variables.contains(variable.name()) {
+                self.push_line(format!("(void){};", c_name(variable.origin_name())));
+            }
+        }
+        let prologue = self.take_lines();
+
+        let mut s = format!(
+            "{}\n{{\n{}{}",
+            self.function_signature(proto),
+            prologue,
+            body
+        );
+        if self.ctx.return_type.is_some() {
+            s.push_str(&format!(
+                "    return {};\n",
+                c_name(self.ctx.pou_name.string())
+            ));
+        }
+        s.push_str("}\n");
+
+        s
+    }
+
+    fn gen_local_variable(&mut self, name: &str, ty: &Type, initial: Option<&Expression>) {
+        let resolved = self.ctx.resolve_type(ty);
+        let is_scalar =
+            c_class_type(resolved.type_class()).is_some() || self.enum_default(ty).is_some();
+
+        if is_scalar {
+            let value = self.gen_default_value(ty, initial);
+            self.push_line(format!("{} = {};", self.c_declare(ty, name), value));
+        } else {
+            self.push_line(format!("{};", self.c_declare(ty, name)));
+            self.gen_init(name, ty, initial);
+        }
+    }
+}
+
+#[inline]
+fn has_no_check<T: HasAttribute>(target: &T) -> bool {
+    target
+        .get_attribute_value(&NO_CHECK_ATTRIBUTE.into())
+        .is_some()
+}
+
+#[inline]
+fn arith_operator(op: Operator) -> &'static str {
+    match op {
+        Operator::Plus => "+",
+        Operator::Minus => "-",
+        Operator::Multiply => "*",
+        Operator::Division => "/",
+        _ => unreachable!("{:?}", op),
+    }
+}
+
+/// Unsigned type of the same width or wider, integer arithmetic wraps around in it
+fn unsigned_class(class: Option<TypeClass>) -> TypeClass {
+    match class.and_then(integer_width) {
+        Some((bits, _)) if bits <= 32 => TypeClass::UDInt,
+        _ => TypeClass::ULInt,
+    }
+}
+
+impl CodeGenBackend for CBackend {
+    type Label = String;
+
+    fn new(mgr: UnitsManager, app: ModuleContext) -> Self {
+        Self {
+            ctx: TypeContext::new(mgr, app),
+            lines: vec![],
+            indent: 1,
+            read_variables: HashSet::new(),
+            self_used: false,
+            externals: vec![],
+            label_count: 0,
+            loop_depth: 0,
+            range_check: false,
+            no_check: false,
+            error: None,
+        }
+    }
+
+    fn gen_function(&mut self, func: usize) -> Result<Box<dyn CompiledCode>, CodeGenError> {
+        let app = self.ctx.app().read();
+        let f = app
+            .get_function(func)
+            .ok_or(CodeGenError::FunctionNotDefined(func))?
+            .clone();
+        let p = app
+            .get_declaration_by_id(func)
+            .ok_or(CodeGenError::FunctionNotDefined(func))?
+            .clone();
+        drop(app);
+
+        self.enter_declaration(&p);
+        let source = match self.ctx.pou_kind {
+            Some(PouKind::Function) => self.gen_function_pou(&f, &p),
+            Some(PouKind::FunctionBlock) => self.gen_function_block(&f, &p),
+            Some(PouKind::Program) => self.gen_program(&f, &p),
+            None => return Err(CodeGenError::FunctionNotDefined(func)),
+        };
+        if let Some(e) = self.error.take() {
+            return Err(e);
+        }
+
+        Ok(Box::new(CCompiledCode::new(
+            source,
+            mem::take(&mut self.externals),
+        )))
+    }
+
+    /// create a new label, names are unique in function
+    fn create_label<S: AsRef<str>>(&mut self, label: S) -> Self::Label {
+        let name: String = label
+            .as_ref()
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect();
+
+        self.label_count += 1;
+        format!("stc_{}_{}", name, self.label_count)
+    }
+
+    fn insert_label(&mut self, label: Self::Label) {
+        self.push_line(format!("{}:;", label));
+    }
+
+    fn gen_variable_load(&mut self, variable: &mut Variable) {
+        if let Some(var) = self.variable_ref(variable.name(), true) {
+            self.push_line(format!("(void){};", var));
+        }
+    }
+
+    fn gen_operator(&mut self, operator: &mut OperatorExpression) {
+        let value = self.gen_operator_expression(operator);
+        self.push_line(format!("(void){};", value));
+    }
+
+    fn get_module_bytes(&mut self, w: &mut dyn Write) -> io::Result<()> {
+        c_write_module(self, w)
+    }
+
+    fn fold_constants(&self) -> bool {
+        true
+    }
+
+    fn set_range_check(&mut self, enabled: bool) {
+        self.range_check = enabled;
+    }
+
+    fn save_function(&self, code: &dyn CompiledCode) -> Option<Vec<u8>> {
+        let code = code.as_any().downcast_ref::<CCompiledCode>()?;
+        let mut buf = vec![];
+        code.save(&mut buf).ok()?;
+
+        Some(buf)
+    }
+
+    fn load_function(&self, mut bytes: &[u8]) -> Option<Box<dyn CompiledCode>> {
+        let code = CCompiledCode::load(&mut bytes).ok()?;
+        Some(Box::new(code))
+    }
+}
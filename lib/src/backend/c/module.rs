@@ -0,0 +1,432 @@
+use std::collections::HashSet;
+use std::io;
+use std::io::Write;
+use std::sync::Arc;
+
+use super::{c_integer, c_name, pou_kind, sorted_declarations, CBackend, CCompiledCode, PouKind};
+use crate::backend::utils::{enum_values, pack_mode};
+use crate::prelude::*;
+
+const PRELUDE: &str = r#"#include <stdbool.h>
+#include <stdint.h>
+#include <string.h>
+#include <math.h>
+
+/* STRING holds at most 80 characters */
+#define STC_STRING_SIZE 81
+
+/* Runtime errors are reported to the runtime, like array index out of range */
+void stc_runtime_error(const char *message);
+
+/* Current time of timers in nanoseconds, provided by the runtime */
+int64_t stc_now(void);
+
+static inline int64_t stc_index(int64_t index, int64_t lower, int64_t len, const char *message)
+{
+    if (index < lower || index - lower >= len) {
+        stc_runtime_error(message);
+        return 0;
+    }
+    return index - lower;
+}
+
+static inline int64_t stc_range(int64_t value, int64_t lower, int64_t upper, const char *message)
+{
+    if (value < lower || value > upper)
+        stc_runtime_error(message);
+    return value;
+}
+
+static inline void stc_string_assign(char *dst, const char *src)
+{
+    size_t len = strlen(src);
+    if (len > STC_STRING_SIZE - 1)
+        len = STC_STRING_SIZE - 1;
+    memmove(dst, src, len);
+    dst[len] = 0;
+}
+
+static inline uint64_t stc_pow_u64(uint64_t base, uint64_t exp)
+{
+    uint64_t r = 1;
+    for (; exp; exp >>= 1) {
+        if (exp & 1)
+            r *= base;
+        base *= base;
+    }
+    return r;
+}
+
+static inline int64_t stc_pow_i64(int64_t base, int64_t exp)
+{
+    if (exp < 0)
+        return base == 1 ? 1 : base == -1 ? (exp % 2 ? -1 : 1) : 0;
+    return (int64_t)stc_pow_u64((uint64_t)base, (uint64_t)exp);
+}
+
+static inline int64_t stc_abs_i64(int64_t v)
+{
+    return v < 0 ? (int64_t)(0u - (uint64_t)v) : v;
+}
+
+/* Bit shifts of the low `bits` bits of v, negative n is 0 */
+static inline uint64_t stc_shl(uint64_t v, int64_t n, int bits)
+{
+    uint64_t mask = UINT64_MAX >> (64 - bits);
+    if (n < 0)
+        n = 0;
+    return n >= bits ? 0 : (v << n) & mask;
+}
+
+static inline uint64_t stc_shr(uint64_t v, int64_t n, int bits)
+{
+    uint64_t mask = UINT64_MAX >> (64 - bits);
+    if (n < 0)
+        n = 0;
+    return n >= bits ? 0 : (v & mask) >> n;
+}
+
+static inline uint64_t stc_rol(uint64_t v, int64_t n, int bits)
+{
+    uint64_t mask = UINT64_MAX >> (64 - bits);
+    if (n < 0)
+        n = 0;
+    n %= bits;
+    v &= mask;
+    return n ? ((v << n) | (v >> (bits - n))) & mask : v;
+}
+
+static inline uint64_t stc_ror(uint64_t v, int64_t n, int bits)
+{
+    if (n < 0)
+        n = 0;
+    return stc_rol(v, (bits - n % bits) % bits, bits);
+}
+
+/* 1-based position of the first occurrence of pattern in s, 0 if not found */
+static inline int16_t stc_find(const char *s, const char *pattern)
+{
+    const char *p = strstr(s, pattern);
+    return p ? (int16_t)(p - s + 1) : 0;
+}
+"#;
+
+/// Integer division and modulo, division by zero is a runtime error and
+/// overflow of `MIN / -1` wraps around
+fn c_division_helpers() -> String {
+    let mut s = String::new();
+
+    for (suffix, ty, unsigned_ty) in [
+        ("i32", "int32_t", "uint32_t"),
+        ("i64", "int64_t", "uint64_t"),
+        ("u32", "uint32_t", ""),
+        ("u64", "uint64_t", ""),
+    ] {
+        for (name, op) in [("div", "/"), ("mod", "%")] {
+            let result = match (unsigned_ty, name) {
+                ("", _) => format!("a {} b", op),
+                (_, "div") => format!("b == -1 ? ({})(0u - ({})a) : a / b", ty, unsigned_ty),
+                _ => "b == -1 ? 0 : a % b".to_owned(),
+            };
+
+            s.push_str(&format!(
+                "\nstatic inline {ty} stc_{name}_{suffix}({ty} a, {ty} b)\n\
+                {{\n    \
+                    if (b == 0) {{\n        \
+                        stc_runtime_error(\"integer division by zero\");\n        \
+                        return 0;\n    \
+                    }}\n    \
+                    return {result};\n\
+                }}\n"
+            ));
+        }
+    }
+
+    s
+}
+
+/// MAX, MIN and LIMIT of standard functions, integers are compared in 64 bits
+fn c_selection_helpers() -> String {
+    let mut s = String::new();
+
+    for (suffix, ty) in [("i64", "int64_t"), ("u64", "uint64_t"), ("f64", "double")] {
+        s.push_str(&format!(
+            "\nstatic inline {ty} stc_max_{suffix}({ty} a, {ty} b)\n\
+            {{\n    \
+                return a < b ? b : a;\n\
+            }}\n\
+            \nstatic inline {ty} stc_min_{suffix}({ty} a, {ty} b)\n\
+            {{\n    \
+                return b < a ? b : a;\n\
+            }}\n\
+            \nstatic inline {ty} stc_limit_{suffix}({ty} mn, {ty} in, {ty} mx)\n\
+            {{\n    \
+                return in < mn ? mn : in > mx ? mx : in;\n\
+            }}\n"
+        ));
+    }
+
+    s
+}
+
+/// Write the whole application as a C source file, declarations are in id order
+/// except that types are sorted by their dependencies.
+pub fn c_write_module(backend: &mut CBackend, w: &mut dyn Write) -> io::Result<()> {
+    let decls = sorted_declarations(backend.ctx.app());
+
+    // source of POUs
+    let mut codes = vec![];
+    for decl in decls.iter() {
+        let id = decl.read().unwrap().id();
+        let Some(f) = backend.ctx.app().read().get_function(id).cloned() else {
+            continue;
+        };
+
+        let f = f.read();
+        let code = f
+            .compiled_code()
+            .as_ref()
+            .and_then(|x| x.as_any().downcast_ref::<CCompiledCode>());
+        if let Some(code) = code {
+            codes.push((code.source().to_owned(), code.externals().to_vec()));
+        }
+    }
+
+    let mut s = String::from("/* Generated by stc, do not edit */\n\n");
+    s.push_str(PRELUDE);
+    s.push_str(&c_division_helpers());
+    s.push_str(&c_selection_helpers());
+
+    for decl in type_order(backend, &decls) {
+        s.push('\n');
+        s.push_str(&type_definition(backend, &decl));
+    }
+
+    s.push('\n');
+    s.push_str(&globals_definition(backend, &decls));
+
+    // prototypes
+    s.push('\n');
+    for decl in decls.iter() {
+        let p = decl.read().unwrap();
+        let name = c_name(p.name().string());
+        let has_init = p.is_function_block() || matches!(p.decl().kind, DeclKind::Struct(_));
+        let is_pou = pou_kind(p.decl()).is_some();
+        drop(p);
+
+        if has_init {
+            s.push_str(&format!("void {}_init({} *self);\n", name, name));
+        }
+        if is_pou {
+            s.push_str(&format!("{};\n", backend.function_signature(decl)));
+        }
+    }
+    s.push_str("void stc_init(void);\n");
+
+    let mut externals = HashSet::new();
+    for (name, prototype) in codes.iter().flat_map(|(_, x)| x.iter()) {
+        if externals.insert(name.clone()) {
+            s.push_str(prototype);
+            s.push('\n');
+        }
+    }
+
+    // init functions of structs
+    for decl in decls.iter() {
+        let (name, members) = {
+            let p = decl.read().unwrap();
+            let DeclKind::Struct(st) = &p.decl().kind else {
+                continue;
+            };
+            (c_name(p.name().string()), st.variables().to_vec())
+        };
+
+        backend.enter_declaration(decl);
+        s.push('\n');
+        s.push_str(&backend.gen_init_function(&name, &members));
+    }
+
+    for (source, _) in codes.iter() {
+        s.push('\n');
+        s.push_str(source);
+    }
+
+    s.push('\n');
+    s.push_str(&init_function(backend, &decls));
+
+    w.write_all(s.as_bytes())
+}
+
+/// Types of declaration, struct and function block members must be defined first
+fn type_order(backend: &CBackend, decls: &[Prototype]) -> Vec<Prototype> {
+    fn visit(
+        backend: &CBackend,
+        decl: &Prototype,
+        visited: &mut HashSet<usize>,
+        order: &mut Vec<Prototype>,
+    ) {
+        let p = decl.read().unwrap();
+        if !visited.insert(p.id()) {
+            return;
+        }
+
+        let types: Vec<_> = match &p.decl().kind {
+            DeclKind::Alias(alias) => vec![alias.alias().clone()],
+            DeclKind::Struct(_) | DeclKind::Fun(_) | DeclKind::FB(_) => p
+                .variables()
+                .iter()
+                .filter_map(|x| x.ty().cloned())
+                .collect(),
+            _ => vec![],
+        };
+        drop(p);
+
+        for mut ty in types {
+            while let Some(base) = ty.as_array().map(|x| x.base_type().clone()) {
+                ty = base;
+            }
+            if let Some(dependency) = backend.ctx.type_user_decl(&ty) {
+                visit(backend, &dependency, visited, order);
+            }
+        }
+
+        order.push(decl.clone());
+    }
+
+    let mut visited = HashSet::new();
+    let mut order = vec![];
+    for decl in decls {
+        let is_type = {
+            let p = decl.read().unwrap();
+            p.is_function_block()
+                || matches!(
+                    p.decl().kind,
+                    DeclKind::Alias(_) | DeclKind::Enum(_) | DeclKind::Struct(_)
+                )
+        };
+
+        if is_type {
+            visit(backend, decl, &mut visited, &mut order);
+        }
+    }
+
+    order
+}
+
+fn type_definition(backend: &CBackend, decl: &Prototype) -> String {
+    let p = decl.read().unwrap();
+    let name = c_name(p.name().string());
+
+    match &p.decl().kind {
+        DeclKind::Enum(e) => {
+            let base = e.ty().clone().unwrap_or_else(DIntType::new_type);
+            let class = base.type_class();
+            let mut s = format!("typedef {};\n", backend.c_declare(&base, &name));
+            for (field, (_, value)) in e.fields().iter().zip(enum_values(e)) {
+                s.push_str(&format!(
+                    "#define {}_{} (({}){})\n",
+                    name,
+                    field.origin_name(),
+                    name,
+                    c_integer(value, Some(class))
+                ));
+            }
+
+            s
+        }
+        DeclKind::Alias(alias) => format!("typedef {};\n", backend.c_declare(alias.alias(), &name)),
+        _ => {
+            let members = struct_members(backend, p.variables(), 1);
+            let s = format!("typedef struct {} {{\n{}}} {};\n", name, members, name);
+
+            match pack_mode(p.decl()) {
+                Some(n) => format!("#pragma pack(push, {})\n{}#pragma pack(pop)\n", n, s),
+                None => s,
+            }
+        }
+    }
+}
+
+/// Member declarations of struct, empty struct is not allowed in C
+fn struct_members(backend: &CBackend, variables: &[Arc<Variable>], indent: usize) -> String {
+    let indent = "    ".repeat(indent);
+    let mut s = String::new();
+    for variable in variables {
+        let Some(ty) = variable.ty() else { continue };
+        let declarator = c_name(variable.origin_name());
+        s.push_str(&format!(
+            "{}{};\n",
+            indent,
+            backend.c_declare(ty, &declarator)
+        ));
+    }
+
+    if s.is_empty() {
+        s = format!("{}char stc_empty;\n", indent);
+    }
+
+    s
+}
+
+/// Global variables and variables of each program are members of `stc_globals`
+fn globals_definition(backend: &CBackend, decls: &[Prototype]) -> String {
+    let mut members = String::new();
+    for decl in decls {
+        let p = decl.read().unwrap();
+        match (&p.decl().kind, pou_kind(p.decl())) {
+            (DeclKind::GlobalVar(_) | DeclKind::Configuration(_), _) => {
+                for variable in p.decl().variables() {
+                    let Some(ty) = variable.ty() else { continue };
+                    let declarator = c_name(variable.origin_name());
+                    members.push_str(&format!("    {};\n", backend.c_declare(ty, &declarator)));
+                }
+            }
+            (_, Some(PouKind::Program)) if p.variables().iter().any(|x| x.ty().is_some()) => {
+                members.push_str("    struct {\n");
+                members.push_str(&struct_members(backend, p.variables(), 2));
+                members.push_str(&format!("    }} {};\n", c_name(p.name().string())));
+            }
+            _ => {}
+        }
+    }
+
+    if members.is_empty() {
+        members.push_str("    char stc_empty;\n");
+    }
+
+    format!(
+        "typedef struct stc_globals_t {{\n{}}} stc_globals_t;\n\nstc_globals_t stc_globals;\n",
+        members
+    )
+}
+
+/// `stc_init()` initializes global variables and variables of programs, it should be
+/// called once before programs run.
+fn init_function(backend: &mut CBackend, decls: &[Prototype]) -> String {
+    let mut body = String::new();
+    for decl in decls {
+        let (prefix, variables) = {
+            let p = decl.read().unwrap();
+            match (&p.decl().kind, pou_kind(p.decl())) {
+                (DeclKind::GlobalVar(_) | DeclKind::Configuration(_), _) => {
+                    ("stc_globals".to_owned(), p.decl().variables().to_vec())
+                }
+                (_, Some(PouKind::Program)) => (
+                    format!("stc_globals.{}", c_name(p.name().string())),
+                    p.variables().to_vec(),
+                ),
+                _ => continue,
+            }
+        };
+
+        backend.enter_declaration(decl);
+        for variable in variables {
+            let Some(ty) = variable.ty() else { continue };
+            let target = format!("{}.{}", prefix, c_name(variable.origin_name()));
+            backend.gen_init(&target, ty, variable.initial().as_deref());
+        }
+        body.push_str(&backend.take_lines());
+    }
+
+    format!("void stc_init(void)\n{{\n{}}}\n", body)
+}
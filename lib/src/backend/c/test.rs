@@ -0,0 +1,415 @@
+use std::fs;
+use std::process::Command;
+
+use crate::backend::{CBackend, CodeGenBackend, CodeGenDriver, CodeGenError};
+use crate::test::fixtures::{self, *};
+
+/// Generate application with multiple POUs, each POU is (declaration, body)
+fn generate_application(pous: &[(&str, &str)]) -> String {
+    generate(pous, false)
+}
+
+fn generate(pous: &[(&str, &str)], range_check: bool) -> String {
+    let mut code_gen = build(pous, range_check).expect("build app failed");
+
+    let mut buf = vec![];
+    code_gen
+        .backend()
+        .get_module_bytes(&mut buf)
+        .expect("get module bytes failed");
+
+    String::from_utf8(buf).unwrap()
+}
+
+/// Build application, each POU is (declaration, body)
+fn build(
+    pous: &[(&str, &str)],
+    range_check: bool,
+) -> Result<CodeGenDriver<CBackend>, CodeGenError> {
+    fixtures::build_application(pous, |x| x.set_range_check(range_check))
+}
+
+/// Compile the application with a small harness, run `main` for `cycles` times
+/// and execute `report` at last. `support` is C code defined before `main`, like
+/// external functions. Cycles are 10ms apart in the time of timers.
+fn exec_application(
+    pous: &[(&str, &str)],
+    support: &str,
+    cycles: usize,
+    report: &str,
+) -> (bool, String, String) {
+    exec_source(generate_application(pous), support, cycles, report)
+}
+
+fn exec_source(
+    mut source: String,
+    support: &str,
+    cycles: usize,
+    report: &str,
+) -> (bool, String, String) {
+    source.push_str(&format!(
+        r#"
+#include <stdio.h>
+#include <stdlib.h>
+
+void stc_runtime_error(const char *message)
+{{
+    fprintf(stderr, "%s\n", message);
+    exit(1);
+}}
+
+static int64_t stc_time;
+
+int64_t stc_now(void)
+{{
+    return stc_time;
+}}
+{support}
+int main(void)
+{{
+    stc_init();
+    for (int i = 0; i < {cycles}; i++) {{
+        stc_time = i * 10000000LL;
+        main_body();
+    }}
+    {report}
+    return 0;
+}}
+"#
+    ));
+
+    let dir = tempfile::tempdir().expect("create temp dir failed");
+    let src = dir.path().join("app.c");
+    let exe = dir.path().join("app");
+    fs::write(&src, &source).unwrap();
+
+    let output = Command::new("cc")
+        .args(["-std=c99", "-Wall", "-Wextra", "-Werror", "-o"])
+        .arg(&exe)
+        .arg(&src)
+        .arg("-lm")
+        .output()
+        .expect("cc exec failed");
+    assert!(
+        output.status.success(),
+        "{}\n{}",
+        String::from_utf8_lossy(&output.stderr),
+        source
+    );
+
+    let output = Command::new(&exe).output().expect("app exec failed");
+    (
+        output.status.success(),
+        String::from_utf8_lossy(&output.stdout).to_string(),
+        String::from_utf8_lossy(&output.stderr).to_string(),
+    )
+}
+
+fn exec_report(pous: &[(&str, &str)], cycles: usize, report: &str) -> String {
+    let (ok, stdout, stderr) = exec_application(pous, "", cycles, report);
+    assert!(ok, "{}", stderr);
+
+    stdout
+}
+
+#[test]
+fn test_integer_wrap_around() {
+    let r = exec_report(
+        &[INTEGER_WRAP_AROUND],
+        1,
+        r#"printf("%d %d %d %d %lld %llu %lld %llu",
+        stc_globals.main.a, stc_globals.main.b, stc_globals.main.c, stc_globals.main.d,
+        (long long)stc_globals.main.e, (unsigned long long)stc_globals.main.f,
+        (long long)stc_globals.main.g, (unsigned long long)stc_globals.main.h);"#,
+    );
+    assert_eq!(
+        r,
+        "-128 255 -2 1 -2147483648 4294967295 -9223372036854775808 18446744073709551615"
+    );
+}
+
+#[test]
+fn test_division() {
+    let r = exec_report(
+        &[DIVISION],
+        1,
+        r#"printf("%d %d %d %d %d %lu %g %g",
+        stc_globals.main.a, stc_globals.main.b, stc_globals.main.c, stc_globals.main.d,
+        stc_globals.main.x, (unsigned long)stc_globals.main.u, stc_globals.main.r, stc_globals.main.l);"#,
+    );
+    assert_eq!(r, "3 -3 -1 1024 -128 1333333333 3.5 1.5");
+}
+
+#[test]
+fn test_constant_expression() {
+    let source = generate_application(&CONSTANT_EXPRESSION);
+    assert!(source.contains("axes[4]"), "{}", source);
+
+    let r = exec_report(
+        &CONSTANT_EXPRESSION,
+        1,
+        r#"printf("%d", (int)stc_globals.main.a);"#,
+    );
+    assert_eq!(r, "7");
+}
+
+#[test]
+fn test_if_statement() {
+    let r = exec_report(
+        &[IF_STATEMENT],
+        4,
+        r#"printf("%d %d %d %d", (int)stc_globals.main.a, (int)stc_globals.main.b,
+        stc_globals.main.x, stc_globals.main.y);"#,
+    );
+    assert_eq!(r, "4 51 1 0");
+}
+
+#[test]
+fn test_function_block() {
+    let r = exec_report(
+        &FUNCTION_BLOCKS,
+        2,
+        r#"printf("%d %d %d", stc_globals.main.a, stc_globals.main.b, stc_globals.main.d);"#,
+    );
+    assert_eq!(r, "15 20 11");
+}
+
+#[test]
+fn test_struct_array_enum() {
+    let r = exec_report(
+        &STRUCT_ARRAY_ENUM,
+        2,
+        r#"printf("%d %d %d %d", (int)stc_globals.main.m[1][1], (int)stc_globals.main.a,
+        (int)stc_globals.main.b, (int)stc_globals.main.d);"#,
+    );
+    assert_eq!(r, "14 29 29 10");
+}
+
+#[test]
+fn test_function_call() {
+    let (ok, stdout, stderr) = exec_application(
+        &FUNCTION_CALL,
+        "void report(int16_t value) { printf(\"report %d\\n\", value); }\n",
+        1,
+        r#"printf("%d %d %d %d", stc_globals.main.x, stc_globals.main.y, stc_globals.main.z,
+        stc_globals.main.c);"#,
+    );
+    assert!(ok, "{}", stderr);
+    assert_eq!(stdout, "report 3\nreport 15\nreport -32768\n3 15 -32768 1");
+}
+
+#[test]
+fn test_string() {
+    let r = exec_report(
+        &[STRING_COMPARE],
+        1,
+        r#"printf("%s %d %d", stc_globals.main.s, stc_globals.main.x, stc_globals.main.y);"#,
+    );
+    assert_eq!(r, "a\"b? 1 0");
+}
+
+#[test]
+fn test_runtime_errors() {
+    let (ok, _, stderr) = exec_application(&[INDEX_OUT_OF_RANGE], "", 2, "");
+    assert!(!ok);
+    assert_eq!(
+        stderr.trim(),
+        "array index out of range at line 3, column 3: a[i]"
+    );
+
+    let (ok, _, stderr) = exec_application(&[DIVISION_BY_ZERO], "", 1, "");
+    assert!(!ok);
+    assert_eq!(stderr.trim(), "integer division by zero");
+}
+
+#[test]
+fn test_subrange_types() {
+    let percent = ("TYPE Percent: INT(0..100); END_TYPE", "");
+    let main = (
+        "PROGRAM main: VAR p: Percent; level: SINT(-5..5); a, b: INT; END_VAR END_PROGRAM",
+        "a := p; b := level; p := p + 150; level := level + 1;",
+    );
+
+    // values are not checked by default, subranges start from their lower bound
+    let r = exec_report(
+        &[percent, main],
+        1,
+        r#"printf("%d %d %d %d", stc_globals.main.a, stc_globals.main.b,
+        stc_globals.main.p, stc_globals.main.level);"#,
+    );
+    assert_eq!(r, "0 -5 150 -4");
+
+    let source = generate(&[percent, main], true);
+    let (ok, _, stderr) = exec_source(source, "", 1, "");
+    assert!(!ok);
+    assert_eq!(stderr.trim(), "value out of range 0..100: p := p + 150");
+}
+
+#[test]
+fn test_pragmas() {
+    let packed = (
+        "{attribute 'pack_mode' := '1'} \
+        TYPE Packed: STRUCT a: SINT; b: DINT; END_STRUCT END_TYPE",
+        "",
+    );
+    let percent = ("TYPE Percent: INT(0..100); END_TYPE", "");
+    let main = (
+        "PROGRAM main: VAR p, q: Percent; s: Packed; END_VAR END_PROGRAM",
+        "{attribute 'no_check'}\np := 150;\nq := p - 100;",
+    );
+
+    // checks of statement with 'no_check' are not generated
+    let source = generate(&[packed, percent, main], true);
+    assert!(source.contains("#pragma pack(push, 1)\ntypedef struct Packed {"));
+    assert!(!source.contains("0..100: p := 150"));
+    assert!(source.contains("0..100: q := p - 100"));
+
+    let (ok, stdout, stderr) = exec_source(
+        source,
+        "",
+        1,
+        r#"printf("%d %d %d", (int)sizeof(Packed), stc_globals.main.p, stc_globals.main.q);"#,
+    );
+    assert!(ok, "{}", stderr);
+    assert_eq!(stdout, "5 150 50");
+
+    // checks of the whole POU are disabled
+    let main = (
+        "{attribute 'no_check'} \
+        PROGRAM main: VAR a: ARRAY[1..3] OF INT; p: Percent; END_VAR END_PROGRAM",
+        "a[2] := 5; p := a[2];",
+    );
+    let r = exec_report(&[percent, main], 1, r#"printf("%d", stc_globals.main.p);"#);
+    assert_eq!(r, "5");
+    let source = generate(&[percent, main], true);
+    assert!(!source.contains("array index out of range at"));
+    assert!(!source.contains("value out of range 0..100"));
+}
+
+#[test]
+fn test_deterministic_output() {
+    let pous = [COUNTER_MAIN, COUNTER_FB];
+
+    let source = generate_application(&pous);
+    assert_eq!(source, generate_application(&pous));
+    assert!(source
+        .contains("typedef struct Counter {\n    int16_t step;\n    int16_t count;\n} Counter;"));
+    assert!(source.contains("void Counter_body(Counter *self)"));
+    assert!(source.contains("stc_globals_t stc_globals;"));
+}
+
+#[test]
+fn test_invalid_member_access() {
+    let pous = NESTED_STRUCTS;
+
+    let cases = [
+        ("x := a.(b.c);", "invalid member access: a.b.c"),
+        ("a.(b.c) := 1;", "invalid member access: a.b.c"),
+        ("x := x.1;", "invalid member access: x.1"),
+    ];
+    for (body, expected) in cases {
+        let mut pous = pous.to_vec();
+        pous[2].1 = body;
+        let err = build(&pous, false)
+            .err()
+            .expect("invalid member access built");
+        assert_eq!(err.to_string(), expected);
+    }
+
+    // nested members are accessed from left to right
+    let mut pous = pous.to_vec();
+    pous[2].1 = "a.b.c := 3; x := a.b.c;";
+    assert!(generate_application(&pous).contains("a.b.c = 3"));
+}
+
+#[test]
+fn test_standard_numerical() {
+    let r = exec_report(
+        &[STANDARD_NUMERICAL],
+        1,
+        r#"printf("%d %d %g %g %d", stc_globals.main.a, stc_globals.main.b,
+        stc_globals.main.r, stc_globals.main.l, stc_globals.main.d);"#,
+    );
+    assert_eq!(r, "3 -32768 4 1026 -2");
+}
+
+#[test]
+fn test_standard_bit_shift() {
+    let r = exec_report(
+        &[STANDARD_BIT_SHIFT],
+        1,
+        r#"printf("%d %d %d %d %d", stc_globals.main.a, stc_globals.main.b,
+        stc_globals.main.c, stc_globals.main.d, stc_globals.main.e);"#,
+    );
+    assert_eq!(r, "2 64 3 192 0");
+}
+
+#[test]
+fn test_standard_selection() {
+    let r = exec_report(
+        &[STANDARD_SELECTION],
+        1,
+        r#"printf("%d %d %d %d", stc_globals.main.a, stc_globals.main.b,
+        stc_globals.main.c, stc_globals.main.d);"#,
+    );
+    assert_eq!(r, "2 6 100 20");
+
+    let (ok, _, stderr) = exec_application(&[MUX_OUT_OF_RANGE], "", 1, "");
+    assert!(!ok);
+    assert_eq!(stderr.trim(), "MUX K out of range");
+}
+
+#[test]
+fn test_standard_string() {
+    let main = (
+        r#"PROGRAM main: VAR s: STRING := "hello"; a, b: INT; END_VAR END_PROGRAM"#,
+        r#"a := LEN(s); b := FIND(s, "lo");"#,
+    );
+
+    let r = exec_report(
+        &[main],
+        1,
+        r#"printf("%d %d", stc_globals.main.a, stc_globals.main.b);"#,
+    );
+    assert_eq!(r, "5 4");
+
+    let left = (
+        r#"PROGRAM main: VAR s, t: STRING; END_VAR END_PROGRAM"#,
+        "t := LEFT(s, 2);",
+    );
+    let err = build(&[left], false).err().expect("LEFT built");
+    assert_eq!(
+        err.to_string(),
+        "standard function not supported: LEFT(s, 2)"
+    );
+}
+
+#[test]
+fn test_standard_conversion() {
+    let r = exec_report(
+        &[STANDARD_CONVERSION],
+        1,
+        r#"printf("%d %d %d %g", stc_globals.main.a, stc_globals.main.b,
+        stc_globals.main.c, stc_globals.main.r);"#,
+    );
+    assert_eq!(r, "-3 -56 1 3");
+}
+
+#[test]
+fn test_standard_function_blocks() {
+    let report = r#"printf("%d %lld %d %d", stc_globals.main.q, (long long)stc_globals.main.et,
+        stc_globals.main.cv, stc_globals.main.cq);"#;
+
+    let main = STANDARD_FUNCTION_BLOCKS;
+    assert_eq!(exec_report(&[main], 3, report), "0 20000000 1 0");
+    assert_eq!(exec_report(&[main], 4, report), "1 25000000 2 1");
+
+    // declarations of application shadow the standard function blocks
+    let ton = (
+        "FUNCTION_BLOCK TON VAR_OUTPUT Q: BOOL; END_VAR END_FUNCTION_BLOCK",
+        "Q := TRUE;",
+    );
+    let main = ("PROGRAM main: VAR t: TON; END_VAR END_PROGRAM", "t();");
+    let source = generate_application(&[ton, main]);
+    assert_eq!(source.matches("void TON_body(TON *self)\n").count(), 1);
+}
@@ -1,15 +1,15 @@
-use crate::parser::StString;
-
-use indexmap::{IndexMap, IndexSet};
-use std::fmt::{Display, Formatter, Write};
+use std::fmt::{Debug, Display, Formatter, Write};
 use std::hash::{Hash, Hasher};
 
 use super::register::{Reg, RK};
 use super::{ConstIdx, LuaType, LuaVarKind};
 
+/// Strings longer than this are long strings, tagged with variant 1
+const LUAI_MAXSHORTLEN: usize = 40;
+
 macro_rules! excess_k {
     ($v: expr, $k: literal) => {
-        ($v as u32).wrapping_add(((2u32.pow($k) - 1) / 2)) & (2u32.pow($k) - 1)
+        ($v as u32).wrapping_add((2u32.pow($k) - 1) / 2) & (2u32.pow($k) - 1)
     };
 }
 
@@ -27,6 +27,7 @@ macro_rules! excess_sj {
 
 #[repr(u8)]
 #[allow(non_camel_case_types)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum LuaOpCode {
     // A B, R[A] := R[B]
     OP_MOVE = 0,
@@ -143,12 +144,107 @@ enum LuaOpCode {
     OP_EXTRAARG = 82,
 }
 
+/// All opcodes indexed by their numbers
+const LUA_OPCODES: [LuaOpCode; 83] = {
+    use LuaOpCode::*;
+    [
+        OP_MOVE, OP_LOADI, OP_LOADF, OP_LOADK, OP_LOADKX, OP_LOADFALSE, OP_LFALSESKIP, OP_LOADTRUE,
+        OP_LOADNIL, OP_GETUPVAL, OP_SETUPVAL, OP_GETTABUP, OP_GETTABLE, OP_GETI, OP_GETFIELD,
+        OP_SETTABUP, OP_SETTABLE, OP_SETI, OP_SETFIELD, OP_NEWTABLE, OP_SELF, OP_ADDI, OP_ADDK,
+        OP_SUBK, OP_MULK, OP_MODK, OP_POWK, OP_DIVK, OP_IDIVK, OP_BANDK, OP_BORK, OP_BXORK,
+        OP_SHRI, OP_SHLI, OP_ADD, OP_SUB, OP_MUL, OP_MOD, OP_POW, OP_DIV, OP_IDIV, OP_BAND, OP_BOR,
+        OP_BXOR, OP_SHL, OP_SHR, OP_MMBIN, OP_MMBINI, OP_MMBINK, OP_UNM, OP_BNOT, OP_NOT, OP_LEN,
+        OP_CONCAT, OP_CLOSE, OP_TBC, OP_JMP, OP_EQ, OP_LT, OP_LE, OP_EQK, OP_EQI, OP_LTI, OP_LEI,
+        OP_GTI, OP_GEI, OP_TEST, OP_TESTSET, OP_CALL, OP_TAILCALL, OP_RETURN, OP_RETURN0,
+        OP_RETURN1, OP_FORLOOP, OP_FORPREP, OP_TFORPREP, OP_TFORCALL, OP_TFORLOOP, OP_SETLIST,
+        OP_CLOSURE, OP_VARARG, OP_VARARGPREP, OP_EXTRAARG,
+    ]
+};
+
 pub struct LuaExecState {}
 
-#[derive(PartialEq, Debug, Clone)]
+/// Metamethod events, used as the C argument of MMBIN family
+#[repr(u8)]
+#[allow(non_camel_case_types)]
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LuaTMS {
+    TM_INDEX = 0,
+    TM_NEWINDEX = 1,
+    TM_GC = 2,
+    TM_MODE = 3,
+    TM_LEN = 4,
+    TM_EQ = 5,
+    TM_ADD = 6,
+    TM_SUB = 7,
+    TM_MUL = 8,
+    TM_MOD = 9,
+    TM_POW = 10,
+    TM_DIV = 11,
+    TM_IDIV = 12,
+    TM_BAND = 13,
+    TM_BOR = 14,
+    TM_BXOR = 15,
+    TM_SHL = 16,
+    TM_SHR = 17,
+    TM_UNM = 18,
+    TM_BNOT = 19,
+    TM_LT = 20,
+    TM_LE = 21,
+    TM_CONCAT = 22,
+    TM_CALL = 23,
+    TM_CLOSE = 24,
+}
+
+impl LuaTMS {
+    pub fn name(&self) -> &'static str {
+        match self {
+            LuaTMS::TM_INDEX => "__index",
+            LuaTMS::TM_NEWINDEX => "__newindex",
+            LuaTMS::TM_GC => "__gc",
+            LuaTMS::TM_MODE => "__mode",
+            LuaTMS::TM_LEN => "__len",
+            LuaTMS::TM_EQ => "__eq",
+            LuaTMS::TM_ADD => "__add",
+            LuaTMS::TM_SUB => "__sub",
+            LuaTMS::TM_MUL => "__mul",
+            LuaTMS::TM_MOD => "__mod",
+            LuaTMS::TM_POW => "__pow",
+            LuaTMS::TM_DIV => "__div",
+            LuaTMS::TM_IDIV => "__idiv",
+            LuaTMS::TM_BAND => "__band",
+            LuaTMS::TM_BOR => "__bor",
+            LuaTMS::TM_BXOR => "__bxor",
+            LuaTMS::TM_SHL => "__shl",
+            LuaTMS::TM_SHR => "__shr",
+            LuaTMS::TM_UNM => "__unm",
+            LuaTMS::TM_BNOT => "__bnot",
+            LuaTMS::TM_LT => "__lt",
+            LuaTMS::TM_LE => "__le",
+            LuaTMS::TM_CONCAT => "__concat",
+            LuaTMS::TM_CALL => "__call",
+            LuaTMS::TM_CLOSE => "__close",
+        }
+    }
+
+    pub fn from_u8(n: u8) -> Option<Self> {
+        use LuaTMS::*;
+        const ALL: [LuaTMS; 25] = [
+            TM_INDEX, TM_NEWINDEX, TM_GC, TM_MODE, TM_LEN, TM_EQ, TM_ADD, TM_SUB, TM_MUL, TM_MOD, TM_POW,
+            TM_DIV, TM_IDIV, TM_BAND, TM_BOR, TM_BXOR, TM_SHL, TM_SHR, TM_UNM, TM_BNOT, TM_LT, TM_LE,
+            TM_CONCAT, TM_CALL, TM_CLOSE,
+        ];
+
+        ALL.get(n as usize).copied()
+    }
+}
+
+/// Strings of Lua are byte strings, they are not required to be valid UTF-8
+#[derive(Clone)]
 pub enum LuaConstants {
     Nil,
-    String(String),
+    Boolean(bool),
+    String(Vec<u8>),
     Integer(i64),
     Float(f64),
     Function(fn(&mut LuaExecState) -> i32),
@@ -158,12 +254,24 @@ impl LuaConstants {
     pub fn lua_type(&self) -> LuaType {
         match *self {
             Self::Nil => LuaType::NIL,
+            Self::Boolean(..) => LuaType::BOOLEAN,
             Self::Function(..) => LuaType::FUNCTION,
             Self::String(..) => LuaType::STRING,
             Self::Float(..) | Self::Integer(..) => LuaType::NUMBER,
         }
     }
 
+    /// Type tag with variant bits, as written in binary chunks
+    pub fn type_tag(&self) -> u8 {
+        let variant = match *self {
+            Self::Boolean(true) | Self::Float(..) => 1,
+            Self::String(ref s) if s.len() > LUAI_MAXSHORTLEN => 1,
+            _ => 0,
+        };
+
+        self.lua_type().bits() | variant << 4
+    }
+
     pub fn as_lua_i8(&self) -> Option<i8> {
         match *self {
             Self::Integer(i) if i >= i8::MIN as i64 && i <= i8::MAX as i64 => Some(i as i8),
@@ -172,8 +280,10 @@ impl LuaConstants {
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone)]
 pub struct LuaUpValue {
+    // debug name, None if stripped
+    pub name: Option<String>,
     pub stack: u8,
     pub index: u8,
     pub kind: LuaVarKind,
@@ -182,6 +292,7 @@ pub struct LuaUpValue {
 impl Default for LuaUpValue {
     fn default() -> Self {
         Self {
+            name: None,
             stack: 0,
             index: 0,
             kind: LuaVarKind::VDKREG,
@@ -189,6 +300,29 @@ impl Default for LuaUpValue {
     }
 }
 
+/// Debug information of local variable, active in instructions [start_pc, end_pc)
+#[derive(Debug, Clone)]
+pub struct LuaLocalVariable {
+    pub name: Option<String>,
+    pub start_pc: u32,
+    pub end_pc: u32,
+}
+
+impl PartialEq for LuaConstants {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::Nil, Self::Nil) => true,
+            (Self::Boolean(a), Self::Boolean(b)) => a == b,
+            (Self::String(a), Self::String(b)) => a == b,
+            (Self::Integer(a), Self::Integer(b)) => a == b,
+            // compare bits, so NaN and signed zeros are kept as different constants
+            (Self::Float(a), Self::Float(b)) => a.to_bits() == b.to_bits(),
+            (Self::Function(a), Self::Function(b)) => std::ptr::fn_addr_eq(*a, *b),
+            _ => false,
+        }
+    }
+}
+
 impl Eq for LuaConstants {}
 
 impl Hash for LuaConstants {
@@ -197,95 +331,326 @@ impl Hash for LuaConstants {
             LuaConstants::Float(f) => state.write(&f.to_be_bytes()),
             LuaConstants::Function(f) => f.hash(state),
             LuaConstants::Nil => 0.hash(state),
+            LuaConstants::Boolean(b) => b.hash(state),
             LuaConstants::String(s) => s.hash(state),
             LuaConstants::Integer(i) => i.hash(state),
         }
     }
 }
 
+impl Debug for LuaConstants {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Nil => write!(f, "Nil"),
+            Self::Boolean(b) => write!(f, "Boolean({:?})", b),
+            Self::String(s) => write!(f, "String({:?})", String::from_utf8_lossy(s)),
+            Self::Integer(i) => write!(f, "Integer({:?})", i),
+            Self::Float(v) => write!(f, "Float({:?})", v),
+            Self::Function(..) => write!(f, "Function"),
+        }
+    }
+}
+
 impl Display for LuaConstants {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match *self {
-            LuaConstants::String(ref s) => write!(f, "{s}"),
+            LuaConstants::String(ref s) => write!(f, "{}", String::from_utf8_lossy(s)),
             LuaConstants::Float(ref v) => write!(f, "{:?}", v),
             LuaConstants::Integer(ref i) => write!(f, "{}", i),
+            LuaConstants::Boolean(ref b) => write!(f, "{}", b),
+            LuaConstants::Nil => write!(f, "nil"),
             _ => panic!("Display for constants {:?} not implement", self),
         }
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 #[allow(clippy::upper_case_acronyms)]
 pub enum LuaByteCode {
     /// A B: R[A] := R[B]
     Move(Reg, Reg),
     /// A sBx: R[A] := sBx
     LoadI(Reg, i32),
+    /// A sBx: R[A] := (lua_Number)sBx
+    LoadF(Reg, i32),
     /// A B: R[A] := K[Bx]
     LoadK(Reg, ConstIdx),
+    /// A: R[A] := K[extra arg]
+    LoadKX(Reg),
+    /// A: R[A] := false
+    LoadFalse(Reg),
+    /// A: R[A] := false; pc++
+    LFalseSkip(Reg),
+    /// A: R[A] := true
+    LoadTrue(Reg),
+    /// A B: R[A], R[A+1], ..., R[A+B] := nil
+    LoadNil(Reg, u8),
+    /// A B: R[A] := UpValue[B]
+    GetUpVal(Reg, u8),
+    /// A B: UpValue[B] := R[A]
+    SetUpVal(Reg, u8),
 
     /// A B C: R[A] := UpValue[B][K[C]:string]
     GetTabUp(Reg, u8, ConstIdx),
     /// A B C: UpValue[A][K[B]:string] := RK(C)
     SetTabUp(Reg, u8, RK),
+    /// A B C: R[A] := R[B][K[C]:string]
+    GetField(Reg, Reg, ConstIdx),
+    /// A B C: R[A][K[B]:string] := RK(C)
+    SetField(Reg, ConstIdx, RK),
+    /// A B C: R[A] := R[B][R[C]]
+    GetTable(Reg, Reg, Reg),
+    /// A B C: R[A][R[B]] := RK(C)
+    SetTable(Reg, Reg, RK),
+    /// A B C: R[A] := R[B][C]
+    GetI(Reg, Reg, u8),
+    /// A B C: R[A][B] := RK(C)
+    SetI(Reg, u8, RK),
+    /// A B C k: R[A] := {}, B is encoded hash size, C is array size, k means array size
+    /// continues in the following EXTRAARG
+    NewTable(Reg, u8, u8, bool),
+    /// A B C: R[A+1] := R[B]; R[A] := R[B][RK(C):string]
+    Self_(Reg, Reg, RK),
+
+    /// A B sC: R[A] := R[B] + sC
+    AddI(Reg, Reg, i8),
 
     /// A B C: R[A] := R[B] + K[C]:number
     AddK(Reg, Reg, ConstIdx),
+    /// A B C: R[A] := R[B] - K[C]:number
+    SubK(Reg, Reg, ConstIdx),
+    /// A B C: R[A] := R[B] * K[C]:number
+    MulK(Reg, Reg, ConstIdx),
+    /// A B C: R[A] := R[B] % K[C]:number
+    ModK(Reg, Reg, ConstIdx),
+    /// A B C: R[A] := R[B] ^ K[C]:number
+    PowK(Reg, Reg, ConstIdx),
+    /// A B C: R[A] := R[B] / K[C]:number
+    DivK(Reg, Reg, ConstIdx),
+    /// A B C: R[A] := R[B] // K[C]:number
+    IDivK(Reg, Reg, ConstIdx),
+    /// A B C: R[A] := R[B] & K[C]:integer
+    BAndK(Reg, Reg, ConstIdx),
+    /// A B C: R[A] := R[B] | K[C]:integer
+    BOrK(Reg, Reg, ConstIdx),
+    /// A B C: R[A] := R[B] ~ K[C]:integer
+    BXorK(Reg, Reg, ConstIdx),
+
+    /// A B sC: R[A] := R[B] >> sC
+    ShrI(Reg, Reg, i8),
+    /// A B sC: R[A] := sC << R[B]
+    ShlI(Reg, Reg, i8),
 
     /// A B C: R[A] := R[B] + R[C]
     Add(Reg, Reg, Reg),
+    /// A B C: R[A] := R[B] - R[C]
+    Sub(Reg, Reg, Reg),
+    /// A B C: R[A] := R[B] * R[C]
+    Mul(Reg, Reg, Reg),
+    /// A B C: R[A] := R[B] % R[C]
+    Mod(Reg, Reg, Reg),
+    /// A B C: R[A] := R[B] ^ R[C]
+    Pow(Reg, Reg, Reg),
+    /// A B C: R[A] := R[B] / R[C]
+    Div(Reg, Reg, Reg),
+    /// A B C: R[A] := R[B] // R[C]
+    IDiv(Reg, Reg, Reg),
+    /// A B C: R[A] := R[B] & R[C]
+    BAnd(Reg, Reg, Reg),
+    /// A B C: R[A] := R[B] | R[C]
+    BOr(Reg, Reg, Reg),
+    /// A B C: R[A] := R[B] ~ R[C]
+    BXor(Reg, Reg, Reg),
+    /// A B C: R[A] := R[B] << R[C]
+    Shl(Reg, Reg, Reg),
+    /// A B C: R[A] := R[B] >> R[C]
+    Shr(Reg, Reg, Reg),
 
     /// A B C call C metamethod over R[A] and R[B]
-    MMBin(Reg, Reg, ConstIdx),
+    MMBin(Reg, Reg, LuaTMS),
     /// A sB C k call C metamethod over R[A] and sB
-    MMBinI(Reg, i32, ConstIdx),
+    MMBinI(Reg, i8, LuaTMS, bool),
     /// A B C k call C metamethod over R[A] and K[B]
-    MMBinK(Reg, ConstIdx, ConstIdx),
+    MMBinK(Reg, ConstIdx, LuaTMS, bool),
+
+    /// A B: R[A] := -R[B]
+    Unm(Reg, Reg),
+    /// A B: R[A] := ~R[B]
+    BNot(Reg, Reg),
+    /// A B: R[A] := not R[B]
+    Not(Reg, Reg),
+    /// A B: R[A] := #R[B] (length operator)
+    Len(Reg, Reg),
+
+    /// A B: R[A] := R[A].. ... ..R[A + B - 1]
+    Concat(Reg, u8),
+
+    /// A: close all upvalues >= R[A]
+    Close(Reg),
+    /// A: mark variable A "to be closed"
+    Tbc(Reg),
 
     /// sJ: pc += sJ
     Jmp(i32),
     /// A B k: if ((R[A] == R[B]) ~= k) then pc++
-    Eq(Reg, Reg, u8),
-
-    /// A sB k if ((R[A] == sB) ~= k) then pc++
-    EQI(Reg, i8, bool),
-    /// A sB k: if ((R[A] > sB) ~= k) then pc++
-    Gti(Reg, i32, ConstIdx),
-    /// A sB k: if ((R[A] >= sB) ~= k) then pc++
-    Gei(Reg, u8, u8),
-
-    /// A B C: return R[A], ... ,R[A+B-2]
-    Return(u8, u8, u8),
+    Eq(Reg, Reg, bool),
+    /// A B k: if ((R[A] < R[B]) ~= k) then pc++
+    Lt(Reg, Reg, bool),
+    /// A B k: if ((R[A] <= R[B]) ~= k) then pc++
+    Le(Reg, Reg, bool),
+
+    /// A B k: if ((R[A] == K[B]) ~= k) then pc++
+    EqK(Reg, ConstIdx, bool),
+    /// The last flag is C, true if the immediate comes from a float constant
+    /// A sB k C: if ((R[A] == sB) ~= k) then pc++
+    EQI(Reg, i8, bool, bool),
+    /// A sB k C: if ((R[A] < sB) ~= k) then pc++
+    Lti(Reg, i8, bool, bool),
+    /// A sB k C: if ((R[A] <= sB) ~= k) then pc++
+    Lei(Reg, i8, bool, bool),
+    /// A sB k C: if ((R[A] > sB) ~= k) then pc++
+    Gti(Reg, i8, bool, bool),
+    /// A sB k C: if ((R[A] >= sB) ~= k) then pc++
+    Gei(Reg, i8, bool, bool),
+
+    /// A k: if (not R[A] == k) then pc++
+    Test(Reg, bool),
+    /// A B k: if (not R[B] == k) then pc++ else R[A] := R[B]
+    TestSet(Reg, Reg, bool),
+
+    /// A B C k: return R[A], ... ,R[A+B-2], k means upvalues need to be closed
+    Return(u8, u8, u8, bool),
+    /// A: return, B is always 1 as generated by Lua
+    Return0(u8),
+    /// A: return R[A], B is always 2 as generated by Lua
+    Return1(Reg),
 
     /// Call k, v: k is callee symbol position, v is argument count, return value not included
     /// A B C: R[A], ... ,R[A+C-2] := R[A](R[A+1], ... ,R[A+B-1])
     Call(Reg, u8, u8),
+    /// A B C k: return R[A](R[A+1], ... ,R[A+B-1])
+    TailCall(Reg, u8, u8, bool),
+
+    /// A Bx: prepare numeric for loop, skip the loop body of length Bx if not run
+    ForPrep(Reg, u32),
+    /// A Bx: update counters, if loop continues then pc -= Bx
+    ForLoop(Reg, u32),
+
+    /// A Bx: create upvalue for R[A + 3]; pc+=Bx
+    TForPrep(Reg, u32),
+    /// A C: R[A+4], ... ,R[A+3+C] := R[A](R[A+1], R[A+2])
+    TForCall(Reg, u8),
+    /// A Bx: if R[A+2] ~= nil then { R[A]=R[A+2]; pc -= Bx }
+    TForLoop(Reg, u32),
+
+    /// A B C k: R[A][C+i] := R[A+i], 1 <= i <= B, k means C continues in the following EXTRAARG
+    SetList(Reg, u8, u8, bool),
+
+    /// A Bx: R[A] := closure(KPROTO[Bx])
+    Closure(Reg, u32),
+
+    /// A C: R[A], R[A+1], ..., R[A+C-2] = vararg
+    VarArg(Reg, u8),
 
     /// A (adjust vararg parameters)
     VarArgPrep(u8),
+
+    /// Ax: extra (larger) argument for previous opcode
+    ExtraArg(u32),
+}
+
+/// Encode iABC payload
+#[inline]
+fn abck(a: u8, b: u32, c: u32, k: bool) -> u32 {
+    c << 17 | b << 9 | (k as u32) << 8 | a as u32
 }
 
 impl LuaByteCode {
-    fn mnemonic(&self) -> &'static str {
+    pub fn mnemonic(&self) -> &'static str {
         match self {
             LuaByteCode::Call(..) => "CALL",
             LuaByteCode::GetTabUp(..) => "GETTABUP",
             LuaByteCode::SetTabUp(..) => "SETTABUP",
+            LuaByteCode::GetField(..) => "GETFIELD",
+            LuaByteCode::SetField(..) => "SETFIELD",
+            LuaByteCode::GetTable(..) => "GETTABLE",
+            LuaByteCode::SetTable(..) => "SETTABLE",
+            LuaByteCode::ForPrep(..) => "FORPREP",
+            LuaByteCode::ForLoop(..) => "FORLOOP",
+            LuaByteCode::NewTable(..) => "NEWTABLE",
+            LuaByteCode::Closure(..) => "CLOSURE",
+            LuaByteCode::ExtraArg(..) => "EXTRAARG",
             LuaByteCode::LoadK(..) => "LOADK",
+            LuaByteCode::LoadFalse(..) => "LOADFALSE",
+            LuaByteCode::LFalseSkip(..) => "LFALSESKIP",
+            LuaByteCode::LoadTrue(..) => "LOADTRUE",
+            LuaByteCode::LoadNil(..) => "LOADNIL",
             LuaByteCode::Move(..) => "MOVE",
             LuaByteCode::LoadI(..) => "LOADI",
+            LuaByteCode::AddI(..) => "ADDI",
             LuaByteCode::Add(..) => "ADD",
+            LuaByteCode::Sub(..) => "SUB",
+            LuaByteCode::Mul(..) => "MUL",
+            LuaByteCode::Mod(..) => "MOD",
+            LuaByteCode::Pow(..) => "POW",
+            LuaByteCode::Div(..) => "DIV",
+            LuaByteCode::IDiv(..) => "IDIV",
+            LuaByteCode::BAnd(..) => "BAND",
+            LuaByteCode::BOr(..) => "BOR",
+            LuaByteCode::BXor(..) => "BXOR",
+            LuaByteCode::Shl(..) => "SHL",
+            LuaByteCode::Shr(..) => "SHR",
             LuaByteCode::AddK(..) => "ADDK",
+            LuaByteCode::SubK(..) => "SUBK",
+            LuaByteCode::MulK(..) => "MULK",
+            LuaByteCode::ModK(..) => "MODK",
+            LuaByteCode::PowK(..) => "POWK",
+            LuaByteCode::DivK(..) => "DIVK",
+            LuaByteCode::IDivK(..) => "IDIVK",
+            LuaByteCode::BAndK(..) => "BANDK",
+            LuaByteCode::BOrK(..) => "BORK",
+            LuaByteCode::BXorK(..) => "BXORK",
             LuaByteCode::MMBin(..) => "MMBIN",
             LuaByteCode::MMBinI(..) => "MMBINI",
             LuaByteCode::MMBinK(..) => "MMBINK",
+            LuaByteCode::Unm(..) => "UNM",
+            LuaByteCode::BNot(..) => "BNOT",
+            LuaByteCode::Not(..) => "NOT",
             LuaByteCode::EQI(..) => "EQI",
+            LuaByteCode::EqK(..) => "EQK",
+            LuaByteCode::Lti(..) => "LTI",
+            LuaByteCode::Lei(..) => "LEI",
             LuaByteCode::Gei(..) => "GEI",
             LuaByteCode::Gti(..) => "GTI",
             LuaByteCode::Jmp(..) => "JMP",
             LuaByteCode::Eq(..) => "EQ",
+            LuaByteCode::Lt(..) => "LT",
+            LuaByteCode::Le(..) => "LE",
+            LuaByteCode::Test(..) => "TEST",
             LuaByteCode::Return(..) => "RETURN",
             LuaByteCode::VarArgPrep(..) => "VARARGPREP",
+            LuaByteCode::LoadF(..) => "LOADF",
+            LuaByteCode::LoadKX(..) => "LOADKX",
+            LuaByteCode::GetUpVal(..) => "GETUPVAL",
+            LuaByteCode::SetUpVal(..) => "SETUPVAL",
+            LuaByteCode::GetI(..) => "GETI",
+            LuaByteCode::SetI(..) => "SETI",
+            LuaByteCode::Self_(..) => "SELF",
+            LuaByteCode::ShrI(..) => "SHRI",
+            LuaByteCode::ShlI(..) => "SHLI",
+            LuaByteCode::Len(..) => "LEN",
+            LuaByteCode::Concat(..) => "CONCAT",
+            LuaByteCode::Close(..) => "CLOSE",
+            LuaByteCode::Tbc(..) => "TBC",
+            LuaByteCode::TestSet(..) => "TESTSET",
+            LuaByteCode::Return0(..) => "RETURN0",
+            LuaByteCode::Return1(..) => "RETURN1",
+            LuaByteCode::TailCall(..) => "TAILCALL",
+            LuaByteCode::TForPrep(..) => "TFORPREP",
+            LuaByteCode::TForCall(..) => "TFORCALL",
+            LuaByteCode::TForLoop(..) => "TFORLOOP",
+            LuaByteCode::SetList(..) => "SETLIST",
+            LuaByteCode::VarArg(..) => "VARARG",
         }
     }
 
@@ -294,79 +659,212 @@ impl LuaByteCode {
             LuaByteCode::Call(..) => LuaOpCode::OP_CALL,
             LuaByteCode::GetTabUp(..) => LuaOpCode::OP_GETTABUP,
             LuaByteCode::SetTabUp(..) => LuaOpCode::OP_SETTABUP,
+            LuaByteCode::GetField(..) => LuaOpCode::OP_GETFIELD,
+            LuaByteCode::SetField(..) => LuaOpCode::OP_SETFIELD,
+            LuaByteCode::GetTable(..) => LuaOpCode::OP_GETTABLE,
+            LuaByteCode::SetTable(..) => LuaOpCode::OP_SETTABLE,
+            LuaByteCode::ForPrep(..) => LuaOpCode::OP_FORPREP,
+            LuaByteCode::ForLoop(..) => LuaOpCode::OP_FORLOOP,
+            LuaByteCode::NewTable(..) => LuaOpCode::OP_NEWTABLE,
+            LuaByteCode::Closure(..) => LuaOpCode::OP_CLOSURE,
+            LuaByteCode::ExtraArg(..) => LuaOpCode::OP_EXTRAARG,
             LuaByteCode::LoadK(..) => LuaOpCode::OP_LOADK,
+            LuaByteCode::LoadFalse(..) => LuaOpCode::OP_LOADFALSE,
+            LuaByteCode::LFalseSkip(..) => LuaOpCode::OP_LFALSESKIP,
+            LuaByteCode::LoadTrue(..) => LuaOpCode::OP_LOADTRUE,
+            LuaByteCode::LoadNil(..) => LuaOpCode::OP_LOADNIL,
             LuaByteCode::Move(..) => LuaOpCode::OP_MOVE,
             LuaByteCode::LoadI(..) => LuaOpCode::OP_LOADI,
+            LuaByteCode::AddI(..) => LuaOpCode::OP_ADDI,
             LuaByteCode::AddK(..) => LuaOpCode::OP_ADDK,
+            LuaByteCode::SubK(..) => LuaOpCode::OP_SUBK,
+            LuaByteCode::MulK(..) => LuaOpCode::OP_MULK,
+            LuaByteCode::ModK(..) => LuaOpCode::OP_MODK,
+            LuaByteCode::PowK(..) => LuaOpCode::OP_POWK,
+            LuaByteCode::DivK(..) => LuaOpCode::OP_DIVK,
+            LuaByteCode::IDivK(..) => LuaOpCode::OP_IDIVK,
+            LuaByteCode::BAndK(..) => LuaOpCode::OP_BANDK,
+            LuaByteCode::BOrK(..) => LuaOpCode::OP_BORK,
+            LuaByteCode::BXorK(..) => LuaOpCode::OP_BXORK,
             LuaByteCode::MMBin(..) => LuaOpCode::OP_MMBIN,
             LuaByteCode::MMBinI(..) => LuaOpCode::OP_MMBINI,
             LuaByteCode::MMBinK(..) => LuaOpCode::OP_MMBINK,
             LuaByteCode::Add(..) => LuaOpCode::OP_ADD,
+            LuaByteCode::Sub(..) => LuaOpCode::OP_SUB,
+            LuaByteCode::Mul(..) => LuaOpCode::OP_MUL,
+            LuaByteCode::Mod(..) => LuaOpCode::OP_MOD,
+            LuaByteCode::Pow(..) => LuaOpCode::OP_POW,
+            LuaByteCode::Div(..) => LuaOpCode::OP_DIV,
+            LuaByteCode::IDiv(..) => LuaOpCode::OP_IDIV,
+            LuaByteCode::BAnd(..) => LuaOpCode::OP_BAND,
+            LuaByteCode::BOr(..) => LuaOpCode::OP_BOR,
+            LuaByteCode::BXor(..) => LuaOpCode::OP_BXOR,
+            LuaByteCode::Shl(..) => LuaOpCode::OP_SHL,
+            LuaByteCode::Shr(..) => LuaOpCode::OP_SHR,
+            LuaByteCode::Unm(..) => LuaOpCode::OP_UNM,
+            LuaByteCode::BNot(..) => LuaOpCode::OP_BNOT,
+            LuaByteCode::Not(..) => LuaOpCode::OP_NOT,
             LuaByteCode::EQI(..) => LuaOpCode::OP_EQI,
+            LuaByteCode::EqK(..) => LuaOpCode::OP_EQK,
+            LuaByteCode::Lti(..) => LuaOpCode::OP_LTI,
+            LuaByteCode::Lei(..) => LuaOpCode::OP_LEI,
             LuaByteCode::Gei(..) => LuaOpCode::OP_GEI,
             LuaByteCode::Gti(..) => LuaOpCode::OP_GTI,
             LuaByteCode::Jmp(..) => LuaOpCode::OP_JMP,
             LuaByteCode::Eq(..) => LuaOpCode::OP_EQ,
+            LuaByteCode::Lt(..) => LuaOpCode::OP_LT,
+            LuaByteCode::Le(..) => LuaOpCode::OP_LE,
+            LuaByteCode::Test(..) => LuaOpCode::OP_TEST,
             LuaByteCode::Return(..) => LuaOpCode::OP_RETURN,
             LuaByteCode::VarArgPrep(..) => LuaOpCode::OP_VARARGPREP,
+            LuaByteCode::LoadF(..) => LuaOpCode::OP_LOADF,
+            LuaByteCode::LoadKX(..) => LuaOpCode::OP_LOADKX,
+            LuaByteCode::GetUpVal(..) => LuaOpCode::OP_GETUPVAL,
+            LuaByteCode::SetUpVal(..) => LuaOpCode::OP_SETUPVAL,
+            LuaByteCode::GetI(..) => LuaOpCode::OP_GETI,
+            LuaByteCode::SetI(..) => LuaOpCode::OP_SETI,
+            LuaByteCode::Self_(..) => LuaOpCode::OP_SELF,
+            LuaByteCode::ShrI(..) => LuaOpCode::OP_SHRI,
+            LuaByteCode::ShlI(..) => LuaOpCode::OP_SHLI,
+            LuaByteCode::Len(..) => LuaOpCode::OP_LEN,
+            LuaByteCode::Concat(..) => LuaOpCode::OP_CONCAT,
+            LuaByteCode::Close(..) => LuaOpCode::OP_CLOSE,
+            LuaByteCode::Tbc(..) => LuaOpCode::OP_TBC,
+            LuaByteCode::TestSet(..) => LuaOpCode::OP_TESTSET,
+            LuaByteCode::Return0(..) => LuaOpCode::OP_RETURN0,
+            LuaByteCode::Return1(..) => LuaOpCode::OP_RETURN1,
+            LuaByteCode::TailCall(..) => LuaOpCode::OP_TAILCALL,
+            LuaByteCode::TForPrep(..) => LuaOpCode::OP_TFORPREP,
+            LuaByteCode::TForCall(..) => LuaOpCode::OP_TFORCALL,
+            LuaByteCode::TForLoop(..) => LuaOpCode::OP_TFORLOOP,
+            LuaByteCode::SetList(..) => LuaOpCode::OP_SETLIST,
+            LuaByteCode::VarArg(..) => LuaOpCode::OP_VARARG,
         }
     }
 
     pub fn encode(&self) -> u32 {
         let payload = match *self {
             // ABC
-            LuaByteCode::Add(a, b, c) => {
-                (c.num() as u32) << 17 | (b.num() as u32) << 9 | a.num() as u32
-            }
+            LuaByteCode::Add(a, b, c)
+            | LuaByteCode::Sub(a, b, c)
+            | LuaByteCode::Mul(a, b, c)
+            | LuaByteCode::Mod(a, b, c)
+            | LuaByteCode::Pow(a, b, c)
+            | LuaByteCode::Div(a, b, c)
+            | LuaByteCode::IDiv(a, b, c)
+            | LuaByteCode::BAnd(a, b, c)
+            | LuaByteCode::BOr(a, b, c)
+            | LuaByteCode::BXor(a, b, c)
+            | LuaByteCode::Shl(a, b, c)
+            | LuaByteCode::Shr(a, b, c)
+            | LuaByteCode::GetTable(a, b, c) => abck(a.num(), b.num() as u32, c.num() as u32, false),
+            // A B C literal
+            LuaByteCode::GetI(a, b, c) => abck(a.num(), b.num() as u32, c as u32, false),
             // A B k
-            LuaByteCode::Eq(a, b, k) => (k as u32) << 17 | (b.num() as u32) << 9 | a.num() as u32,
-            // A sB8 K(flag)
-            LuaByteCode::EQI(a, sb8, k) => {
-                (excess_k!(sb8, 8)) << 9 | a.num() as u32 | (k as u32) << 8
-            }
-            // A sB k
-            LuaByteCode::Gti(a, sb, k) => {
-                (k as u32) << 17 | excess_sbx!(sb) << 9 | a.num() as u32 | 1u32 << 8
+            LuaByteCode::Eq(a, b, k) | LuaByteCode::Lt(a, b, k) | LuaByteCode::Le(a, b, k) => {
+                abck(a.num(), b.num() as u32, 0, k)
             }
-            LuaByteCode::Gei(a, sb, c) | LuaByteCode::Call(a, sb, c) => {
-                (c as u32) << 17 | (sb as u32) << 9 | a.num() as u32
+            // A KB k
+            LuaByteCode::EqK(a, kb, k) => abck(a.num(), kb as u32, 0, k),
+            // A sB8 K(flag) C(float)
+            LuaByteCode::EQI(a, sb8, k, isf)
+            | LuaByteCode::Lti(a, sb8, k, isf)
+            | LuaByteCode::Lei(a, sb8, k, isf)
+            | LuaByteCode::Gti(a, sb8, k, isf)
+            | LuaByteCode::Gei(a, sb8, k, isf) => abck(a.num(), excess_k!(sb8, 8), isf as u32, k),
+            // A k
+            LuaByteCode::Test(a, k) => abck(a.num(), 0, 0, k),
+            // A B k
+            LuaByteCode::TestSet(a, b, k) => abck(a.num(), b.num() as u32, 0, k),
+            // A B C all literal
+            LuaByteCode::Call(a, b, c) => abck(a.num(), b as u32, c as u32, false),
+            // A B C all literal with k
+            LuaByteCode::TailCall(a, b, c, k) | LuaByteCode::SetList(a, b, c, k) => {
+                abck(a.num(), b as u32, c as u32, k)
             }
             // A B K
-            LuaByteCode::GetTabUp(a, upv, k) => {
-                (k as u32) << 17 | (upv as u32) << 9 | a.num() as u32
-            }
-            // RA, KB, KC with k
-            LuaByteCode::MMBinK(ra, kb, kc) => {
-                (kc as u32) << 17 | (kb as u32) << 9 | ra.num() as u32 | 1u32 << 8
-            }
-            // RA, sB, KC with k
-            LuaByteCode::MMBinI(ra, sb, k) => {
-                (k as u32) << 17 | excess_sbx!(sb) << 9 | ra.num() as u32 | 1u32 << 8
+            LuaByteCode::GetTabUp(a, upv, k) => abck(a.num(), upv as u32, k as u32, false),
+            // RA, KB, TM with k
+            LuaByteCode::MMBinK(ra, kb, tm, k) => abck(ra.num(), kb as u32, tm as u32, k),
+            // RA, sB, TM with k
+            LuaByteCode::MMBinI(ra, sb, tm, k) => abck(ra.num(), excess_k!(sb, 8), tm as u32, k),
+            // RA, RB, TM
+            LuaByteCode::MMBin(ra, rb, tm) => abck(ra.num(), rb.num() as u32, tm as u32, false),
+            // RA, RB, sC
+            LuaByteCode::AddI(ra, rb, sc) | LuaByteCode::ShrI(ra, rb, sc) | LuaByteCode::ShlI(ra, rb, sc) => {
+                abck(ra.num(), rb.num() as u32, excess_k!(sc, 8), false)
             }
             // RA, RB, KC
-            LuaByteCode::AddK(ra, rb, k) | LuaByteCode::MMBin(ra, rb, k) => {
-                (k as u32) << 17 | (rb.num() as u32) << 9 | ra.num() as u32
-            }
+            LuaByteCode::AddK(ra, rb, k)
+            | LuaByteCode::SubK(ra, rb, k)
+            | LuaByteCode::MulK(ra, rb, k)
+            | LuaByteCode::ModK(ra, rb, k)
+            | LuaByteCode::PowK(ra, rb, k)
+            | LuaByteCode::DivK(ra, rb, k)
+            | LuaByteCode::IDivK(ra, rb, k)
+            | LuaByteCode::BAndK(ra, rb, k)
+            | LuaByteCode::BOrK(ra, rb, k)
+            | LuaByteCode::BXorK(ra, rb, k) => abck(ra.num(), rb.num() as u32, k as u32, false),
             // A B RK
-            LuaByteCode::SetTabUp(a, upv, rk) => {
-                let c = match rk {
-                    RK::R(Reg::R(r)) => (r as u32) << 17,
-                    RK::K(k) => (k as u32) << 17 | 1u32 << 8,
-                    _ => unreachable!(),
-                };
-
-                c | (upv as u32) << 9 | a.num() as u32
-            }
-            // A B C all literal
-            LuaByteCode::Return(a, b, c) => (c as u32) << 17 | (b as u32) << 9 | a as u32,
+            LuaByteCode::SetTabUp(a, upv, rk) => match rk {
+                RK::R(r) => abck(a.num(), upv as u32, r.num() as u32, false),
+                RK::K(k) => abck(a.num(), upv as u32, k as u32, true),
+            },
+            // A B KC
+            LuaByteCode::GetField(a, b, k) => abck(a.num(), b.num() as u32, k as u32, false),
+            // A KB RK
+            LuaByteCode::SetField(a, kb, rk) => match rk {
+                RK::R(r) => abck(a.num(), kb as u32, r.num() as u32, false),
+                RK::K(k) => abck(a.num(), kb as u32, k as u32, true),
+            },
+            // A B RK
+            LuaByteCode::SetTable(a, b, rk) | LuaByteCode::Self_(a, b, rk) => match rk {
+                RK::R(r) => abck(a.num(), b.num() as u32, r.num() as u32, false),
+                RK::K(k) => abck(a.num(), b.num() as u32, k as u32, true),
+            },
+            // A B(literal) RK
+            LuaByteCode::SetI(a, b, rk) => match rk {
+                RK::R(r) => abck(a.num(), b as u32, r.num() as u32, false),
+                RK::K(k) => abck(a.num(), b as u32, k as u32, true),
+            },
+            // A B C k
+            LuaByteCode::NewTable(a, b, c, k) => abck(a.num(), b as u32, c as u32, k),
+            // A B C all literal with k
+            LuaByteCode::Return(a, b, c, k) => abck(a, b as u32, c as u32, k),
+            // A, B is number of results plus one
+            LuaByteCode::Return0(a) => abck(a, 1, 0, false),
+            LuaByteCode::Return1(a) => abck(a.num(), 2, 0, false),
             // ABx
             LuaByteCode::LoadK(a, bx) => (bx as u32) << 8 | a.num() as u32,
+            LuaByteCode::Closure(a, bx)
+            | LuaByteCode::ForPrep(a, bx)
+            | LuaByteCode::ForLoop(a, bx)
+            | LuaByteCode::TForPrep(a, bx)
+            | LuaByteCode::TForLoop(a, bx) => bx << 8 | a.num() as u32,
             // AsBx
-            LuaByteCode::LoadI(a, sbx) => excess_sbx!(sbx) << 8 | a.num() as u32,
+            LuaByteCode::LoadI(a, sbx) | LuaByteCode::LoadF(a, sbx) => excess_sbx!(sbx) << 8 | a.num() as u32,
             // A B
-            LuaByteCode::Move(a, b) => (b.num() as u32) << 9 | a.num() as u32,
+            LuaByteCode::Move(a, b)
+            | LuaByteCode::Unm(a, b)
+            | LuaByteCode::BNot(a, b)
+            | LuaByteCode::Not(a, b)
+            | LuaByteCode::Len(a, b) => abck(a.num(), b.num() as u32, 0, false),
+            LuaByteCode::LoadNil(a, b)
+            | LuaByteCode::GetUpVal(a, b)
+            | LuaByteCode::SetUpVal(a, b)
+            | LuaByteCode::Concat(a, b) => abck(a.num(), b as u32, 0, false),
+            // A C
+            LuaByteCode::TForCall(a, c) | LuaByteCode::VarArg(a, c) => abck(a.num(), 0, c as u32, false),
             // A only
-            LuaByteCode::VarArgPrep(a) => (a as u32) << 8,
+            LuaByteCode::LoadFalse(a)
+            | LuaByteCode::LFalseSkip(a)
+            | LuaByteCode::LoadTrue(a)
+            | LuaByteCode::LoadKX(a)
+            | LuaByteCode::Close(a)
+            | LuaByteCode::Tbc(a) => a.num() as u32,
+            LuaByteCode::VarArgPrep(a) => a as u32,
+            // Ax
+            LuaByteCode::ExtraArg(ax) => ax,
             // sJ
             LuaByteCode::Jmp(sj) => excess_sj!(sj),
         };
@@ -374,13 +872,130 @@ impl LuaByteCode {
         let op = self.opcode() as u32;
         payload << 7 | op
     }
+
+    /// Decode an instruction word, None if the opcode or the metamethod event is unknown
+    pub fn decode(word: u32) -> Option<LuaByteCode> {
+        let op = *LUA_OPCODES.get((word & 0x7f) as usize)?;
+        let a = (word >> 7 & 0xff) as u8;
+        let k = word >> 15 & 1 != 0;
+        let b = (word >> 16 & 0xff) as u8;
+        let c = (word >> 24) as u8;
+        let bx = word >> 15;
+        let sbx = bx as i32 - excess_sbx!(0) as i32;
+        // sB and sC share the same excess, wrapped into i8 and unwrapped by encode
+        let sb = b.wrapping_sub(excess_k!(0, 8) as u8) as i8;
+        let sc = c.wrapping_sub(excess_k!(0, 8) as u8) as i8;
+        let ra = Reg::R(a);
+        let rb = Reg::R(b);
+        let rk = if k { RK::K(c) } else { RK::R(Reg::R(c)) };
+
+        let code = match op {
+            LuaOpCode::OP_MOVE => LuaByteCode::Move(ra, rb),
+            LuaOpCode::OP_LOADI => LuaByteCode::LoadI(ra, sbx),
+            LuaOpCode::OP_LOADF => LuaByteCode::LoadF(ra, sbx),
+            LuaOpCode::OP_LOADK => LuaByteCode::LoadK(ra, bx as ConstIdx),
+            LuaOpCode::OP_LOADKX => LuaByteCode::LoadKX(ra),
+            LuaOpCode::OP_LOADFALSE => LuaByteCode::LoadFalse(ra),
+            LuaOpCode::OP_LFALSESKIP => LuaByteCode::LFalseSkip(ra),
+            LuaOpCode::OP_LOADTRUE => LuaByteCode::LoadTrue(ra),
+            LuaOpCode::OP_LOADNIL => LuaByteCode::LoadNil(ra, b),
+            LuaOpCode::OP_GETUPVAL => LuaByteCode::GetUpVal(ra, b),
+            LuaOpCode::OP_SETUPVAL => LuaByteCode::SetUpVal(ra, b),
+            LuaOpCode::OP_GETTABUP => LuaByteCode::GetTabUp(ra, b, c),
+            LuaOpCode::OP_GETTABLE => LuaByteCode::GetTable(ra, rb, Reg::R(c)),
+            LuaOpCode::OP_GETI => LuaByteCode::GetI(ra, rb, c),
+            LuaOpCode::OP_GETFIELD => LuaByteCode::GetField(ra, rb, c),
+            LuaOpCode::OP_SETTABUP => LuaByteCode::SetTabUp(ra, b, rk),
+            LuaOpCode::OP_SETTABLE => LuaByteCode::SetTable(ra, rb, rk),
+            LuaOpCode::OP_SETI => LuaByteCode::SetI(ra, b, rk),
+            LuaOpCode::OP_SETFIELD => LuaByteCode::SetField(ra, b, rk),
+            LuaOpCode::OP_NEWTABLE => LuaByteCode::NewTable(ra, b, c, k),
+            LuaOpCode::OP_SELF => LuaByteCode::Self_(ra, rb, rk),
+            LuaOpCode::OP_ADDI => LuaByteCode::AddI(ra, rb, sc),
+            LuaOpCode::OP_ADDK => LuaByteCode::AddK(ra, rb, c),
+            LuaOpCode::OP_SUBK => LuaByteCode::SubK(ra, rb, c),
+            LuaOpCode::OP_MULK => LuaByteCode::MulK(ra, rb, c),
+            LuaOpCode::OP_MODK => LuaByteCode::ModK(ra, rb, c),
+            LuaOpCode::OP_POWK => LuaByteCode::PowK(ra, rb, c),
+            LuaOpCode::OP_DIVK => LuaByteCode::DivK(ra, rb, c),
+            LuaOpCode::OP_IDIVK => LuaByteCode::IDivK(ra, rb, c),
+            LuaOpCode::OP_BANDK => LuaByteCode::BAndK(ra, rb, c),
+            LuaOpCode::OP_BORK => LuaByteCode::BOrK(ra, rb, c),
+            LuaOpCode::OP_BXORK => LuaByteCode::BXorK(ra, rb, c),
+            LuaOpCode::OP_SHRI => LuaByteCode::ShrI(ra, rb, sc),
+            LuaOpCode::OP_SHLI => LuaByteCode::ShlI(ra, rb, sc),
+            LuaOpCode::OP_ADD => LuaByteCode::Add(ra, rb, Reg::R(c)),
+            LuaOpCode::OP_SUB => LuaByteCode::Sub(ra, rb, Reg::R(c)),
+            LuaOpCode::OP_MUL => LuaByteCode::Mul(ra, rb, Reg::R(c)),
+            LuaOpCode::OP_MOD => LuaByteCode::Mod(ra, rb, Reg::R(c)),
+            LuaOpCode::OP_POW => LuaByteCode::Pow(ra, rb, Reg::R(c)),
+            LuaOpCode::OP_DIV => LuaByteCode::Div(ra, rb, Reg::R(c)),
+            LuaOpCode::OP_IDIV => LuaByteCode::IDiv(ra, rb, Reg::R(c)),
+            LuaOpCode::OP_BAND => LuaByteCode::BAnd(ra, rb, Reg::R(c)),
+            LuaOpCode::OP_BOR => LuaByteCode::BOr(ra, rb, Reg::R(c)),
+            LuaOpCode::OP_BXOR => LuaByteCode::BXor(ra, rb, Reg::R(c)),
+            LuaOpCode::OP_SHL => LuaByteCode::Shl(ra, rb, Reg::R(c)),
+            LuaOpCode::OP_SHR => LuaByteCode::Shr(ra, rb, Reg::R(c)),
+            LuaOpCode::OP_MMBIN => LuaByteCode::MMBin(ra, rb, LuaTMS::from_u8(c)?),
+            LuaOpCode::OP_MMBINI => LuaByteCode::MMBinI(ra, sb, LuaTMS::from_u8(c)?, k),
+            LuaOpCode::OP_MMBINK => LuaByteCode::MMBinK(ra, b, LuaTMS::from_u8(c)?, k),
+            LuaOpCode::OP_UNM => LuaByteCode::Unm(ra, rb),
+            LuaOpCode::OP_BNOT => LuaByteCode::BNot(ra, rb),
+            LuaOpCode::OP_NOT => LuaByteCode::Not(ra, rb),
+            LuaOpCode::OP_LEN => LuaByteCode::Len(ra, rb),
+            LuaOpCode::OP_CONCAT => LuaByteCode::Concat(ra, b),
+            LuaOpCode::OP_CLOSE => LuaByteCode::Close(ra),
+            LuaOpCode::OP_TBC => LuaByteCode::Tbc(ra),
+            LuaOpCode::OP_JMP => LuaByteCode::Jmp((word >> 7) as i32 - excess_sj!(0) as i32),
+            LuaOpCode::OP_EQ => LuaByteCode::Eq(ra, rb, k),
+            LuaOpCode::OP_LT => LuaByteCode::Lt(ra, rb, k),
+            LuaOpCode::OP_LE => LuaByteCode::Le(ra, rb, k),
+            LuaOpCode::OP_EQK => LuaByteCode::EqK(ra, b, k),
+            LuaOpCode::OP_EQI => LuaByteCode::EQI(ra, sb, k, c != 0),
+            LuaOpCode::OP_LTI => LuaByteCode::Lti(ra, sb, k, c != 0),
+            LuaOpCode::OP_LEI => LuaByteCode::Lei(ra, sb, k, c != 0),
+            LuaOpCode::OP_GTI => LuaByteCode::Gti(ra, sb, k, c != 0),
+            LuaOpCode::OP_GEI => LuaByteCode::Gei(ra, sb, k, c != 0),
+            LuaOpCode::OP_TEST => LuaByteCode::Test(ra, k),
+            LuaOpCode::OP_TESTSET => LuaByteCode::TestSet(ra, rb, k),
+            LuaOpCode::OP_CALL => LuaByteCode::Call(ra, b, c),
+            LuaOpCode::OP_TAILCALL => LuaByteCode::TailCall(ra, b, c, k),
+            LuaOpCode::OP_RETURN => LuaByteCode::Return(a, b, c, k),
+            LuaOpCode::OP_RETURN0 => LuaByteCode::Return0(a),
+            LuaOpCode::OP_RETURN1 => LuaByteCode::Return1(ra),
+            LuaOpCode::OP_FORLOOP => LuaByteCode::ForLoop(ra, bx),
+            LuaOpCode::OP_FORPREP => LuaByteCode::ForPrep(ra, bx),
+            LuaOpCode::OP_TFORPREP => LuaByteCode::TForPrep(ra, bx),
+            LuaOpCode::OP_TFORCALL => LuaByteCode::TForCall(ra, c),
+            LuaOpCode::OP_TFORLOOP => LuaByteCode::TForLoop(ra, bx),
+            LuaOpCode::OP_SETLIST => LuaByteCode::SetList(ra, b, c, k),
+            LuaOpCode::OP_CLOSURE => LuaByteCode::Closure(ra, bx),
+            LuaOpCode::OP_VARARG => LuaByteCode::VarArg(ra, c),
+            LuaOpCode::OP_VARARGPREP => LuaByteCode::VarArgPrep(a),
+            LuaOpCode::OP_EXTRAARG => LuaByteCode::ExtraArg(word >> 7),
+        };
+
+        Some(code)
+    }
 }
 
 #[derive(Debug)]
 pub struct LuaCompiledCode {
     pub byte_codes: Vec<LuaByteCode>,
-    pub constants: IndexSet<LuaConstants>,
-    pub upvalues: IndexMap<StString, LuaUpValue>,
+    // constants in the order of chunk, equal constants may be duplicated
+    pub constants: Vec<LuaConstants>,
+    pub upvalues: Vec<LuaUpValue>,
+    pub protos: Vec<LuaCompiledCode>,
+    pub num_params: u8,
+    pub is_vararg: bool,
+    pub max_stack_size: u8,
+    // chunk name, None for nested functions which share source with enclosing function
+    pub source: Option<String>,
+    pub line_defined: u32,
+    pub last_line_defined: u32,
+    // source line of each instruction
+    pub line_info: Vec<u32>,
+    pub local_variables: Vec<LuaLocalVariable>,
 }
 
 impl LuaCompiledCode {
@@ -389,7 +1004,7 @@ impl LuaCompiledCode {
     }
 
     pub fn constants(&self) -> impl Iterator<Item = &LuaConstants> {
-        self.constants.as_slice().into_iter()
+        self.constants.iter()
     }
 
     pub fn byte_codes(&self) -> &[LuaByteCode] {
@@ -402,24 +1017,70 @@ impl LuaCompiledCode {
 
         match code {
             // ABC
-            LuaByteCode::Add(a, b, c) => {
+            LuaByteCode::Add(a, b, c)
+            | LuaByteCode::Sub(a, b, c)
+            | LuaByteCode::Mul(a, b, c)
+            | LuaByteCode::Mod(a, b, c)
+            | LuaByteCode::Pow(a, b, c)
+            | LuaByteCode::Div(a, b, c)
+            | LuaByteCode::IDiv(a, b, c)
+            | LuaByteCode::BAnd(a, b, c)
+            | LuaByteCode::BOr(a, b, c)
+            | LuaByteCode::BXor(a, b, c)
+            | LuaByteCode::Shl(a, b, c)
+            | LuaByteCode::Shr(a, b, c)
+            | LuaByteCode::GetTable(a, b, c) => {
                 write!(s, "R{} R{} R{}", a.num(), b.num(), c.num()).unwrap()
             }
+            LuaByteCode::GetI(a, b, c) => write!(s, "R{} R{} {c}", a.num(), b.num()).unwrap(),
             // A sB8 K(flag)
-            LuaByteCode::EQI(a, sb8, k) => write!(s, "R{} {sb8} {}", a.num(), *k as usize).unwrap(),
-            // RA, KB, KC with k
-            LuaByteCode::MMBinK(ra, kb, kc) => write!(s, "R{} {kb} {kc}", ra.num()).unwrap(),
+            LuaByteCode::EQI(a, sb8, k, _)
+            | LuaByteCode::Lti(a, sb8, k, _)
+            | LuaByteCode::Lei(a, sb8, k, _)
+            | LuaByteCode::Gti(a, sb8, k, _)
+            | LuaByteCode::Gei(a, sb8, k, _) => {
+                write!(s, "R{} {sb8} {}", a.num(), *k as usize).unwrap()
+            }
+            // RA, KB, TM with k
+            LuaByteCode::MMBinK(ra, kb, tm, k) => {
+                write!(s, "R{} {kb} {} {}", ra.num(), *tm as u8, *k as usize).unwrap()
+            }
+            // RA sB TM with k
+            LuaByteCode::MMBinI(ra, sb, tm, k) => {
+                write!(s, "R{} {sb} {} {}", ra.num(), *tm as u8, *k as usize).unwrap()
+            }
+            // RA, RB, TM
+            LuaByteCode::MMBin(ra, rb, tm) => {
+                write!(s, "R{} R{} {}", ra.num(), rb.num(), *tm as u8).unwrap()
+            }
             // A B k
-            LuaByteCode::Eq(a, b, k) => write!(s, "R{} R{} {k}", a.num(), b.num()).unwrap(),
-            // A sB k
-            LuaByteCode::Gti(a, b, c) => write!(s, "R{} {b} {c}", a.num()).unwrap(),
-            LuaByteCode::Gei(a, b, c) | LuaByteCode::Call(a, b, c) => {
-                write!(s, "R{} {b} {c}", a.num()).unwrap()
+            LuaByteCode::Eq(a, b, k) | LuaByteCode::Lt(a, b, k) | LuaByteCode::Le(a, b, k) => {
+                write!(s, "R{} R{} {}", a.num(), b.num(), *k as usize).unwrap()
+            }
+            // A KB k
+            LuaByteCode::EqK(a, kb, k) => write!(s, "R{} {kb} {}", a.num(), *k as usize).unwrap(),
+            // A k
+            LuaByteCode::Test(a, k) => write!(s, "R{} {}", a.num(), *k as usize).unwrap(),
+            LuaByteCode::TestSet(a, b, k) => write!(s, "R{} R{} {}", a.num(), b.num(), *k as usize).unwrap(),
+            LuaByteCode::Call(a, b, c) => write!(s, "R{} {b} {c}", a.num()).unwrap(),
+            LuaByteCode::TailCall(a, b, c, k) | LuaByteCode::SetList(a, b, c, k) => {
+                write!(s, "R{} {b} {c} {}", a.num(), *k as usize).unwrap()
+            }
+            // RegA, RegB, sC
+            LuaByteCode::AddI(ra, rb, sc) | LuaByteCode::ShrI(ra, rb, sc) | LuaByteCode::ShlI(ra, rb, sc) => {
+                write!(s, "R{} R{} {sc}", ra.num(), rb.num()).unwrap()
             }
-            // RA sB KC with k
-            LuaByteCode::MMBinI(ra, sb, kc) => write!(s, "R{} {sb} {kc}", ra.num()).unwrap(),
             // RegA, RegB, K
-            LuaByteCode::AddK(ra, rb, k) | LuaByteCode::MMBin(ra, rb, k) => {
+            LuaByteCode::AddK(ra, rb, k)
+            | LuaByteCode::SubK(ra, rb, k)
+            | LuaByteCode::MulK(ra, rb, k)
+            | LuaByteCode::ModK(ra, rb, k)
+            | LuaByteCode::PowK(ra, rb, k)
+            | LuaByteCode::DivK(ra, rb, k)
+            | LuaByteCode::IDivK(ra, rb, k)
+            | LuaByteCode::BAndK(ra, rb, k)
+            | LuaByteCode::BOrK(ra, rb, k)
+            | LuaByteCode::BXorK(ra, rb, k) => {
                 write!(s, "R{} R{} {k}", ra.num(), rb.num()).unwrap()
             }
             // Reg, Upv, K
@@ -434,33 +1095,103 @@ impl LuaCompiledCode {
                 }
                 .unwrap();
             }
+            // Reg, Reg, K
+            LuaByteCode::GetField(a, b, k) => write!(s, "R{} R{} {k}", a.num(), b.num()).unwrap(),
+            // Reg, K, RK
+            LuaByteCode::SetField(a, kb, rk) => {
+                write!(s, "R{} {kb} ", a.num()).unwrap();
+
+                match rk {
+                    RK::R(r) => write!(s, "{}", r.num()),
+                    RK::K(k) => write!(s, "{}k", k),
+                }
+                .unwrap();
+            }
+            // Reg, Reg, RK
+            LuaByteCode::SetTable(a, b, rk) | LuaByteCode::Self_(a, b, rk) => {
+                write!(s, "R{} R{} ", a.num(), b.num()).unwrap();
+
+                match rk {
+                    RK::R(r) => write!(s, "{}", r.num()),
+                    RK::K(k) => write!(s, "{}k", k),
+                }
+                .unwrap();
+            }
+            // Reg, B, RK
+            LuaByteCode::SetI(a, b, rk) => {
+                write!(s, "R{} {b} ", a.num()).unwrap();
+
+                match rk {
+                    RK::R(r) => write!(s, "{}", r.num()),
+                    RK::K(k) => write!(s, "{}k", k),
+                }
+                .unwrap();
+            }
+            LuaByteCode::NewTable(a, b, c, k) => write!(s, "R{} {b} {c} {}", a.num(), *k as usize).unwrap(),
+            LuaByteCode::Closure(a, bx)
+            | LuaByteCode::ForPrep(a, bx)
+            | LuaByteCode::ForLoop(a, bx)
+            | LuaByteCode::TForPrep(a, bx)
+            | LuaByteCode::TForLoop(a, bx) => write!(s, "R{} {bx}", a.num()).unwrap(),
+            LuaByteCode::ExtraArg(ax) => write!(s, "{ax}").unwrap(),
             // ABC with k
-            LuaByteCode::Return(a, b, c) => write!(s, "{a} {b} {c}").unwrap(),
+            LuaByteCode::Return(a, b, c, k) => write!(s, "{a} {b} {c} {}", *k as usize).unwrap(),
+            LuaByteCode::Return0(a) => write!(s, "{a}").unwrap(),
+            LuaByteCode::Return1(a) => write!(s, "R{}", a.num()).unwrap(),
             // ABx
             LuaByteCode::LoadK(a, bx) => write!(s, "R{} {bx}", a.num()).unwrap(),
             // AsBx
-            LuaByteCode::LoadI(a, sbx) => write!(s, "R{} {sbx}", a.num()).unwrap(),
+            LuaByteCode::LoadI(a, sbx) | LuaByteCode::LoadF(a, sbx) => write!(s, "R{} {sbx}", a.num()).unwrap(),
             // A B
-            LuaByteCode::Move(a, b) => write!(s, "R{} R{}", a.num(), b.num()).unwrap(),
+            LuaByteCode::Move(a, b)
+            | LuaByteCode::Unm(a, b)
+            | LuaByteCode::BNot(a, b)
+            | LuaByteCode::Not(a, b)
+            | LuaByteCode::Len(a, b) => write!(s, "R{} R{}", a.num(), b.num()).unwrap(),
+            LuaByteCode::LoadNil(a, b)
+            | LuaByteCode::GetUpVal(a, b)
+            | LuaByteCode::SetUpVal(a, b)
+            | LuaByteCode::Concat(a, b) => write!(s, "R{} {b}", a.num()).unwrap(),
+            LuaByteCode::TForCall(a, c) | LuaByteCode::VarArg(a, c) => write!(s, "R{} {c}", a.num()).unwrap(),
             // A only
+            LuaByteCode::LoadFalse(a)
+            | LuaByteCode::LFalseSkip(a)
+            | LuaByteCode::LoadTrue(a)
+            | LuaByteCode::LoadKX(a)
+            | LuaByteCode::Close(a)
+            | LuaByteCode::Tbc(a) => write!(s, "R{}", a.num()).unwrap(),
             LuaByteCode::VarArgPrep(a) => write!(s, "{a}").unwrap(),
             // sJ
             LuaByteCode::Jmp(sj) => write!(s, "{sj}").unwrap(),
         }
 
         match code {
-            LuaByteCode::MMBin(_, _, k)
-            | LuaByteCode::MMBinI(_, _, k)
-            | LuaByteCode::MMBinK(_, _, k) => {
-                write!(s, " ; {}", self.constants[*k as usize]).unwrap();
+            LuaByteCode::MMBin(_, _, tm)
+            | LuaByteCode::MMBinI(_, _, tm, _)
+            | LuaByteCode::MMBinK(_, _, tm, _) => {
+                write!(s, " ; {}", tm.name()).unwrap();
             }
-            LuaByteCode::LoadK(a, bx) => {
-                write!(s, " ; K[{}] = {}", bx, self.constants[*bx as usize]).unwrap();
+            LuaByteCode::LoadK(_, k)
+            | LuaByteCode::EqK(_, k, _)
+            | LuaByteCode::AddK(_, _, k)
+            | LuaByteCode::SubK(_, _, k)
+            | LuaByteCode::MulK(_, _, k)
+            | LuaByteCode::ModK(_, _, k)
+            | LuaByteCode::PowK(_, _, k)
+            | LuaByteCode::DivK(_, _, k)
+            | LuaByteCode::IDivK(_, _, k)
+            | LuaByteCode::BAndK(_, _, k)
+            | LuaByteCode::BOrK(_, _, k)
+            | LuaByteCode::BXorK(_, _, k) => {
+                write!(s, " ; K[{}] = {}", k, self.constants[*k as usize]).unwrap();
             }
-            LuaByteCode::GetTabUp(a, b, k) => {
+            LuaByteCode::GetTabUp(_, _, k) => {
                 write!(s, " ; _ENV \"{}\"", self.constants[*k as usize]).unwrap()
             }
-            LuaByteCode::Call(a, b, c) => {
+            LuaByteCode::GetField(_, _, k) | LuaByteCode::SetField(_, k, _) => {
+                write!(s, " ; \"{}\"", self.constants[*k as usize]).unwrap()
+            }
+            LuaByteCode::Call(_, b, c) => {
                 if *b == 0 {
                     write!(s, " ; all in ").unwrap();
                 } else {
@@ -507,6 +1238,13 @@ impl Display for LuaCompiledCode {
             writeln!(f, "{:<4} {:<20}", idx + 1, self.disassembly_code(bc))?
         }
 
+        // nested functions
+        for (idx, proto) in self.protos.iter().enumerate() {
+            writeln!(f)?;
+            writeln!(f, "Proto {}:", idx)?;
+            write!(f, "{}", proto)?;
+        }
+
         Ok(())
     }
 }
@@ -515,6 +1253,7 @@ impl Display for LuaCompiledCode {
 mod test {
     use super::LuaByteCode;
     use super::Reg;
+    use super::LUA_OPCODES;
 
     #[test]
     fn test_encoding() {
@@ -523,10 +1262,36 @@ mod test {
         let code = LuaByteCode::LoadI(Reg::from_raw(3), -65535);
         assert_eq!(code.encode(), 0x00000181);
 
-        let code = LuaByteCode::Return(0, 1, 1);
+        let code = LuaByteCode::Return(0, 1, 1, false);
         assert_eq!(code.encode(), 0x01010046);
 
         let code = LuaByteCode::Jmp(6);
         assert_eq!(code.encode(), 0x800002B8);
     }
+
+    #[test]
+    fn test_decoding() {
+        for word in [0x80008001, 0x00000181, 0x01010046, 0x800002B8] {
+            assert_eq!(LuaByteCode::decode(word).unwrap().encode(), word);
+        }
+
+        let code = LuaByteCode::decode(0x80008001).unwrap();
+        assert!(matches!(code, LuaByteCode::LoadI(Reg::R(0), 2)));
+        let code = LuaByteCode::decode(0x800002B8).unwrap();
+        assert!(matches!(code, LuaByteCode::Jmp(6)));
+
+        // sB of 128 is wrapped in i8 and encoded back
+        let word = LuaByteCode::Lti(Reg::from_raw(1), -128, true, true).encode();
+        assert!(matches!(LuaByteCode::decode(word), Some(LuaByteCode::Lti(_, -128, true, true))));
+
+        // unknown opcode
+        assert!(LuaByteCode::decode(83).is_none());
+    }
+
+    #[test]
+    fn test_opcodes_table() {
+        for (idx, op) in LUA_OPCODES.iter().enumerate() {
+            assert_eq!(*op as usize, idx);
+        }
+    }
 }
@@ -5,84 +5,159 @@ use byteorder::{LittleEndian, WriteBytesExt};
 
 use crate::backend::CompiledCode;
 use crate::backend::lua::bytecode::LuaCompiledCode;
+use crate::parser::StString;
 
-use super::{LuaConstants, utils::*};
-use super::{Function, LuaBackend, Prototype};
+use super::bytecode::{LuaByteCode, LuaUpValue};
+use super::register::{Reg, RK};
+use super::utils::max_stack_size;
+use super::{module_pous, LuaBackend, LuaConstants};
+use crate::backend::utils::PouKind;
 
 /// Lua signature
-const LUA_SIGNATURE: &str = "\x1bLua";
+pub(super) const LUA_SIGNATURE: &str = "\x1bLua";
+/// Lua version, 5.4
+pub(super) const LUAC_VERSION: u8 = 5 * 16 + 4;
+/// format, now is zero
+pub(super) const LUAC_FORMAT: u8 = 0;
 
 /// data to catch conversion errors
-const LUAC_DATA: &[u8] = &[0x19, 0x93, 0x0d, 0x0a, 0x1a, 0x0a];
-const LUAC_INT: u64 = 0x5678;
+pub(super) const LUAC_DATA: &[u8] = &[0x19, 0x93, 0x0d, 0x0a, 0x1a, 0x0a];
+pub(super) const LUAC_INT: u64 = 0x5678;
 // 0x4077280000000000
-const LUAC_NUMBER: f64 = 370.5;
+pub(super) const LUAC_NUMBER: f64 = 370.5;
 
-pub fn lua_dump_module(backend: &LuaBackend, w: &mut dyn Write) -> io::Result<()> {
-    // Lua header
-    lua_dump_bytes(w, LUA_SIGNATURE.as_bytes())?;
-    // Lua version, 5.4
-    lua_dump_byte(w, 5 * 16 + 4)?;
-    // format, now is zero
-    lua_dump_byte(w, 0)?;
-    // data
-    lua_dump_bytes(w, LUAC_DATA)?;
-    // size of Lua instruction
-    lua_dump_byte(w, 4)?;
-    // size of Lua integer
-    lua_dump_byte(w, 8)?;
-    // size of Lua Number
-    lua_dump_byte(w, 8)?;
-    // LUAC_INT
-    lua_dump_bytes(w, &LUAC_INT.to_le_bytes())?;
-    // LUAC_NUMBER
-    lua_dump_bytes(w, &LUAC_NUMBER.to_le_bytes())?;
+/// line difference must fit in a signed byte
+const LIMLINEDIFF: i64 = 0x80;
+/// max instructions without absolute line info
+const MAXIWTHABS: usize = 128;
+/// mark of absolute line info in lineinfo
+pub(super) const ABSLINEINFO: i8 = -0x80;
 
-    // size of UpValues in 1 byte, TODO: hard-coded 1
-    lua_dump_byte(w, 1)?;
+pub fn lua_dump_module(backend: &LuaBackend, w: &mut dyn Write) -> io::Result<()> {
+    lua_dump_program(backend, backend.entry(), w)
+}
 
-    // Start to dump functions
-    // get main function
+/// Dump module running PROGRAM `name`. All POUs are nested functions of the main chunk,
+/// the main chunk registers function block classes, functions and programs into _ENV by
+/// their names and calls the program.
+pub fn lua_dump_program(backend: &LuaBackend, name: &StString, w: &mut dyn Write) -> io::Result<()> {
     let app = backend.current_application();
-    let app_clone = app.clone();
     let app_read = app.read();
-    let main_proto = app_read.find_declaration_by_name(&"main".into());
-    if let Some(p) = main_proto {
-        let main_id = p.read().unwrap().id();
-        let main_func = app_clone.read().get_function(main_id).cloned();
+    let pous: Vec<_> = module_pous(&app)
+        .into_iter()
+        .filter_map(|(id, name, kind)| Some((app_read.get_function(id)?.clone(), name, kind)))
+        .collect();
+    if !pous.iter().any(|(_, x, kind)| x == name && *kind == PouKind::Program) {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("entry program '{}' not found", name),
+        ));
+    }
+
+    let guards: Vec<_> = pous.iter().map(|(f, _, _)| f.read()).collect();
+    let mut protos = Vec::with_capacity(pous.len());
+    for (guard, (_, pou_name, _)) in guards.iter().zip(&pous) {
+        let code = guard
+            .compiled_code()
+            .as_ref()
+            .and_then(|x| x.as_any().downcast_ref::<LuaCompiledCode>())
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("POU '{}' is not compiled", pou_name),
+                )
+            })?;
+        protos.push(code);
+    }
+
+    let kinds: Vec<_> = pous.iter().map(|(_, name, kind)| (name, *kind)).collect();
+    let main = lua_main_chunk(name, &kinds)?;
+
+    lua_dump_header(w)?;
+    // size of UpValues in 1 byte
+    lua_dump_byte(w, main.upvalues.len() as u8)?;
+    lua_dump_function(w, &main, &protos)
+}
 
-        if let Some(f) = main_func {
-            lua_dump_function(backend, p, &f, w)?;
+/// Main chunk of module, nested function `i` is the POU `pous[i]`
+fn lua_main_chunk(entry: &StString, pous: &[(&StString, PouKind)]) -> io::Result<LuaCompiledCode> {
+    let mut constants: Vec<LuaConstants> = vec![];
+    let mut string_constant = |name: &StString| {
+        let constant = LuaConstants::String(name.string().as_bytes().to_vec());
+        let idx = match constants.iter().position(|x| *x == constant) {
+            Some(idx) => idx,
+            None => {
+                constants.push(constant);
+                constants.len() - 1
+            }
+        };
+
+        u8::try_from(idx)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "too many POUs in module"))
+    };
+
+    let r = Reg::R(0);
+    let mut byte_codes = vec![LuaByteCode::VarArgPrep(0)];
+    for (idx, (name, kind)) in pous.iter().enumerate() {
+        byte_codes.push(LuaByteCode::Closure(r, idx as u32));
+        // function block class is returned by its function
+        if *kind == PouKind::FunctionBlock {
+            byte_codes.push(LuaByteCode::Call(r, 1, 2));
         }
+        byte_codes.push(LuaByteCode::SetTabUp(Reg::R(0), string_constant(name)?, RK::R(r)));
     }
+    byte_codes.push(LuaByteCode::GetTabUp(r, 0, string_constant(entry)?));
+    byte_codes.push(LuaByteCode::Call(r, 1, 1));
+    byte_codes.push(LuaByteCode::Return(0, 1, 1, false));
+
+    Ok(LuaCompiledCode {
+        line_info: vec![0; byte_codes.len()],
+        byte_codes,
+        constants,
+        upvalues: vec![LuaUpValue {
+            name: Some("_ENV".to_owned()),
+            stack: 1,
+            ..Default::default()
+        }],
+        protos: vec![],
+        num_params: 0,
+        is_vararg: true,
+        max_stack_size: max_stack_size(1, 0),
+        source: Some(format!("={}", entry)),
+        line_defined: 0,
+        last_line_defined: 0,
+        local_variables: vec![],
+    })
+}
 
-    Ok(())
+/// Dump a standalone function as a binary chunk, e.g. a function read by `lua_undump`
+pub fn lua_dump_chunk(w: &mut dyn Write, lua_code: &LuaCompiledCode) -> io::Result<()> {
+    lua_dump_header(w)?;
+    // size of UpValues in 1 byte
+    lua_dump_byte(w, lua_code.upvalues.len() as u8)?;
+    lua_dump_function(w, lua_code, &[])
 }
 
 fn lua_dump_function(
-    b: &LuaBackend,
-    p: &Prototype,
-    f: &Function,
     w: &mut dyn Write,
+    lua_code: &LuaCompiledCode,
+    extra_protos: &[&LuaCompiledCode],
 ) -> io::Result<()> {
-    // TODO: source file name
-    lua_dump_string(w, None)?;
-    // TODO: linedefined
-    lua_dump_size(w, 0)?;
-    // TODO: lastlinedefined
-    lua_dump_size(w, 0)?;
+    // source name
+    lua_dump_string(w, lua_code.source.as_ref())?;
+    // linedefined
+    lua_dump_size(w, lua_code.line_defined as u64)?;
+    // lastlinedefined
+    lua_dump_size(w, lua_code.last_line_defined as u64)?;
     // numparams
-    lua_dump_byte(w, num_params(p))?;
+    lua_dump_byte(w, lua_code.num_params)?;
     // is_vararg
-    let r = if is_vararg(p) { 1 } else { 0 };
+    let r = if lua_code.is_vararg { 1 } else { 0 };
     lua_dump_byte(w, r)?;
     // maxstacksize of proto
-    lua_dump_byte(w, max_stack_size(p))?;
+    lua_dump_byte(w, lua_code.max_stack_size)?;
 
     // Dump size of code
-    let f = f.read();
-    let code = f.compiled_code().as_ref().unwrap();
-    let lua_code = code.as_any().downcast_ref::<LuaCompiledCode>().unwrap();
     lua_dump_size(w, lua_code.byte_codes().len() as u64)?;
 
     // Dump Code
@@ -95,12 +170,13 @@ fn lua_dump_function(
 
     // Dump Constants
     for constant in lua_code.constants() {
-        lua_dump_byte(w, constant.lua_type().bits())?;
+        lua_dump_byte(w, constant.type_tag())?;
 
         match *constant {
+            LuaConstants::Nil | LuaConstants::Boolean(..) => {}
             LuaConstants::Integer(i) => lua_dump_integer(w, i)?,
             LuaConstants::Float(f) => lua_dump_float(w, f)?,
-            LuaConstants::String(ref s) => lua_dump_string(w, Some(s))?,
+            LuaConstants::String(ref s) => lua_dump_byte_string(w, Some(s))?,
             _ => todo!(),
         }
     }
@@ -109,31 +185,88 @@ fn lua_dump_function(
     lua_dump_size(w, lua_code.upvalues.len() as u64)?;
 
     // Dump UpValues
-    for (_, upv) in &lua_code.upvalues {
+    for upv in &lua_code.upvalues {
         w.write_all(&[upv.stack, upv.index, upv.kind.bits()])?;
     }
 
     // Dump size of Protos
-    lua_dump_size(w, 0)?;
+    let protos_len = lua_code.protos.len() + extra_protos.len();
+    lua_dump_size(w, protos_len as u64)?;
 
     // Dump Protos
+    for proto in &lua_code.protos {
+        lua_dump_function(w, proto, &[])?;
+    }
+    for proto in extra_protos {
+        lua_dump_function(w, proto, &[])?;
+    }
 
-    // Dump size of Debug line info
-    lua_dump_size(w, 0)?;
+    // Dump Debug line info
+    let (line_info, abs_line_info) = lua_line_info(lua_code.line_defined, &lua_code.line_info);
+    lua_dump_size(w, line_info.len() as u64)?;
+    for diff in line_info {
+        lua_dump_byte(w, diff as u8)?;
+    }
 
-    // Dump size of Debug abs line info
-    lua_dump_size(w, 0)?;
+    // Dump Debug abs line info
+    lua_dump_size(w, abs_line_info.len() as u64)?;
+    for (pc, line) in abs_line_info {
+        lua_dump_size(w, pc as u64)?;
+        lua_dump_size(w, line as u64)?;
+    }
 
-    // Dump size of Debug loc vars
-    lua_dump_size(w, 0)?;
+    // Dump Debug loc vars
+    lua_dump_size(w, lua_code.local_variables.len() as u64)?;
+    for local in &lua_code.local_variables {
+        lua_dump_string(w, local.name.as_ref())?;
+        lua_dump_size(w, local.start_pc as u64)?;
+        lua_dump_size(w, local.end_pc as u64)?;
+    }
 
-    // Dump size of Debug upvalues
-    lua_dump_size(w, 0)?;
+    // Dump Debug upvalue names, nothing if all names are stripped
+    let has_names = lua_code.upvalues.iter().any(|x| x.name.is_some());
+    if has_names {
+        lua_dump_size(w, lua_code.upvalues.len() as u64)?;
+        for upv in &lua_code.upvalues {
+            lua_dump_string(w, upv.name.as_ref())?;
+        }
+    } else {
+        lua_dump_size(w, 0)?;
+    }
 
-    // Dump Debug
     Ok(())
 }
 
+/// Encode line of instructions into relative line info and absolute line info like
+/// `savelineinfo` of Lua. Line difference from previous instruction is saved in lineinfo,
+/// absolute line is saved if difference is too large or every `MAXIWTHABS` instructions.
+fn lua_line_info(line_defined: u32, lines: &[u32]) -> (Vec<i8>, Vec<(u32, u32)>) {
+    let mut line_info = Vec::with_capacity(lines.len());
+    let mut abs_line_info = vec![];
+    let mut previous_line = line_defined as i64;
+    let mut instructions_without_abs = 0;
+
+    for (pc, line) in lines.iter().enumerate() {
+        let diff = *line as i64 - previous_line;
+        let need_abs = diff.abs() >= LIMLINEDIFF || {
+            instructions_without_abs += 1;
+            instructions_without_abs > MAXIWTHABS
+        };
+
+        if need_abs {
+            abs_line_info.push((pc as u32, *line));
+            line_info.push(ABSLINEINFO);
+            instructions_without_abs = 1;
+        } else {
+            line_info.push(diff as i8);
+        }
+
+        previous_line = *line as i64;
+    }
+
+    (line_info, abs_line_info)
+}
+
 #[inline]
 fn lua_dump_integer(w: &mut dyn Write, n: i64) -> io::Result<()> {
     w.write_i64::<LittleEndian>(n)
@@ -166,9 +299,22 @@ fn lua_dump_size(w: &mut dyn Write, mut data: u64) -> io::Result<()> {
     lua_dump_bytes(w, &buf[reverse_index + 1..])
 }
 
-#[inline]
-fn lua_dump_header() -> io::Result<()> {
-    Ok(())
+pub(super) fn lua_dump_header(w: &mut dyn Write) -> io::Result<()> {
+    lua_dump_bytes(w, LUA_SIGNATURE.as_bytes())?;
+    lua_dump_byte(w, LUAC_VERSION)?;
+    lua_dump_byte(w, LUAC_FORMAT)?;
+    // data
+    lua_dump_bytes(w, LUAC_DATA)?;
+    // size of Lua instruction
+    lua_dump_byte(w, 4)?;
+    // size of Lua integer
+    lua_dump_byte(w, 8)?;
+    // size of Lua Number
+    lua_dump_byte(w, 8)?;
+    // LUAC_INT
+    lua_dump_bytes(w, &LUAC_INT.to_le_bytes())?;
+    // LUAC_NUMBER
+    lua_dump_bytes(w, &LUAC_NUMBER.to_le_bytes())
 }
 
 #[inline]
@@ -183,9 +329,12 @@ fn lua_dump_bytes(w: &mut dyn Write, bytes: &[u8]) -> io::Result<()> {
 
 #[inline]
 fn lua_dump_string(w: &mut dyn Write, opt_str: Option<&String>) -> io::Result<()> {
-    match opt_str {
-        Some(s) => {
-            let bytes = s.as_bytes();
+    lua_dump_byte_string(w, opt_str.map(|x| x.as_bytes()))
+}
+
+fn lua_dump_byte_string(w: &mut dyn Write, opt_bytes: Option<&[u8]>) -> io::Result<()> {
+    match opt_bytes {
+        Some(bytes) => {
             lua_dump_size(w, (bytes.len() + 1) as u64)?;
             w.write_all(bytes)
         },
@@ -213,7 +362,7 @@ impl CompiledCode for LuaCompiledCode {
 
 #[cfg(test)]
 mod test {
-    use crate::backend::lua::dump::lua_dump_size;
+    use crate::backend::lua::dump::{lua_dump_size, lua_line_info, ABSLINEINFO};
 
     #[test]
     fn test_lua_dump_size() {
@@ -239,4 +388,23 @@ mod test {
         assert_eq!(0x05, buf[0]);
         assert_eq!(0x9c, buf[1]);
     }
+
+    #[test]
+    fn test_lua_line_info() {
+        let (info, abs) = lua_line_info(0, &[1, 1, 2, 1]);
+        assert_eq!(info, vec![1, 0, 1, -1]);
+        assert!(abs.is_empty());
+
+        // large difference
+        let (info, abs) = lua_line_info(0, &[1, 200, 201]);
+        assert_eq!(info, vec![1, ABSLINEINFO, 1]);
+        assert_eq!(abs, vec![(1, 200)]);
+
+        // absolute line every 128 instructions
+        let (info, abs) = lua_line_info(0, &[1; 300]);
+        assert_eq!(info.len(), 300);
+        assert_eq!(abs, vec![(128, 1), (256, 1)]);
+        assert_eq!(info[128], ABSLINEINFO);
+        assert_eq!(info[129], 0);
+    }
 }
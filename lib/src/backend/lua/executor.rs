@@ -0,0 +1,189 @@
+use mlua::{ChunkMode, Function, HookTriggers, Lua, Table, VmState};
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use super::dump::lua_dump_program;
+use super::LuaBackend;
+use crate::backend::utils::{pou_kind, sorted_declarations, PouKind};
+use crate::backend::{CodeGenDriver, CodeGenError};
+use crate::context::ProgramInstance;
+use crate::prelude::*;
+use crate::scheduler::CycleExecutor;
+
+/// Simulated execution time of one Lua instruction in nanoseconds
+pub const DEFAULT_INSTRUCTION_TIME: u64 = 100;
+
+/// Environment of program instance, global variables are written into the shared table,
+/// others are variables of the instance
+const INSTANCE_ENV: &str = r#"
+local shared, names = ...
+return setmetatable({}, {
+    __index = shared,
+    __newindex = function(t, k, v)
+        if names[k] then shared[k] = v else rawset(t, k, v) end
+    end,
+})
+"#;
+
+/// Run program instances of scheduler by the Lua VM, each instance runs the chunk of its
+/// program in a separate environment, global variables are shared by all instances
+pub struct LuaExecutor {
+    lua: Lua,
+    /// Global variables, the standard library of Lua is visible through it
+    shared: Table,
+    /// Names of global variables
+    names: Table,
+    /// Binary chunk of each program by program name
+    programs: HashMap<StString, Vec<u8>>,
+    /// Loaded chunk and environment of each program instance by instance name
+    instances: HashMap<StString, (Function, Table)>,
+    executed_instructions: Rc<Cell<u64>>,
+    /// Simulated execution time of one instruction in nanoseconds
+    instruction_time: u64,
+    /// Simulated time in nanoseconds, set by scheduler
+    time: u64,
+}
+
+impl LuaExecutor {
+    pub fn new(mgr: UnitsManager, app: ModuleContext) -> Result<Self, CodeGenError> {
+        let app_id = app.read().id();
+        let mut driver: CodeGenDriver<LuaBackend> = CodeGenDriver::new(mgr, app_id)?;
+        driver.build_application()?;
+
+        let mut programs = HashMap::new();
+        let mut globals = vec![];
+        for decl in sorted_declarations(&app) {
+            let decl = decl.read().unwrap();
+            match decl.decl().kind {
+                DeclKind::GlobalVar(_) | DeclKind::Configuration(_) => {
+                    globals.extend(decl.variables().iter().map(|x| x.name().clone()))
+                }
+                _ if pou_kind(decl.decl()) == Some(PouKind::Program) => {
+                    let mut chunk = vec![];
+                    lua_dump_program(driver.backend(), decl.name(), &mut chunk)
+                        .expect("write to vec failed");
+                    programs.insert(decl.name().clone(), chunk);
+                }
+                _ => {}
+            }
+        }
+
+        let lua = Lua::new();
+        let executed_instructions = Rc::new(Cell::new(0));
+        let counter = executed_instructions.clone();
+        lua.set_hook(HookTriggers::new().every_nth_instruction(1), move |_, _| {
+            counter.set(counter.get() + 1);
+            Ok(VmState::Continue)
+        });
+
+        let (shared, names) =
+            Self::create_tables(&lua, &globals).expect("create tables of Lua executor failed");
+
+        Ok(Self {
+            lua,
+            shared,
+            names,
+            programs,
+            instances: HashMap::new(),
+            executed_instructions,
+            instruction_time: DEFAULT_INSTRUCTION_TIME,
+            time: 0,
+        })
+    }
+
+    fn create_tables(lua: &Lua, globals: &[StString]) -> mlua::Result<(Table, Table)> {
+        let shared = lua.create_table()?;
+        let meta = lua.create_table()?;
+        meta.set("__index", lua.globals())?;
+        shared.set_metatable(Some(meta));
+
+        let names = lua.create_table()?;
+        for name in globals {
+            names.set(name.string().as_str(), true)?;
+        }
+
+        Ok((shared, names))
+    }
+
+    #[inline]
+    pub fn lua(&self) -> &Lua {
+        &self.lua
+    }
+
+    /// Current time in nanoseconds
+    #[inline]
+    pub fn time(&self) -> u64 {
+        self.time
+    }
+
+    #[inline]
+    pub fn executed_instructions(&self) -> u64 {
+        self.executed_instructions.get()
+    }
+
+    #[inline]
+    pub fn instruction_time(&self) -> u64 {
+        self.instruction_time
+    }
+
+    /// Set simulated execution time of one instruction in nanoseconds
+    #[inline]
+    pub fn set_instruction_time(&mut self, time: u64) {
+        self.instruction_time = time;
+    }
+
+    /// Value of global variable
+    pub fn global(&self, name: &str) -> mlua::Result<mlua::Value> {
+        self.shared.raw_get(name)
+    }
+
+    /// Value of variable of program instance, like `Main1.count`
+    pub fn variable(&self, path: &str) -> mlua::Result<mlua::Value> {
+        let Some((instance, name)) = path.split_once('.') else {
+            return self.global(path);
+        };
+
+        match self.instances.get(&StString::new(instance)) {
+            Some((_, env)) => env.raw_get(name),
+            None => Ok(mlua::Value::Nil),
+        }
+    }
+
+    /// Run one cycle of program instance, the chunk of instance is loaded on first run
+    pub fn run_instance(&mut self, instance: &StString, program: &StString) -> mlua::Result<()> {
+        if !self.instances.contains_key(instance) {
+            let chunk = self.programs.get(program).ok_or_else(|| {
+                mlua::Error::RuntimeError(format!("program '{}' not found", program))
+            })?;
+
+            let env: Table = self
+                .lua
+                .load(INSTANCE_ENV)
+                .call((self.shared.clone(), self.names.clone()))?;
+            let f = self
+                .lua
+                .load(chunk.as_slice())
+                .set_name(instance.string())
+                .set_mode(ChunkMode::Binary)
+                .set_environment(env.clone())
+                .into_function()?;
+            self.instances.insert(instance.clone(), (f, env));
+        }
+
+        self.instances[instance].0.call(())
+    }
+}
+
+/// Execution time of cycle is estimated by the count of executed instructions
+impl CycleExecutor for LuaExecutor {
+    type Error = mlua::Error;
+
+    fn run_cycle(&mut self, instance: &ProgramInstance, now: u64) -> mlua::Result<u64> {
+        self.time = now;
+
+        let executed = self.executed_instructions();
+        self.run_instance(&instance.name, &instance.program)?;
+        Ok((self.executed_instructions() - executed) * self.instruction_time)
+    }
+}
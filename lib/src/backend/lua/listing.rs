@@ -0,0 +1,428 @@
+use std::fmt::Write;
+
+use super::bytecode::{LuaByteCode, LuaCompiledCode, LuaConstants};
+use super::register::RK;
+
+/// MAXARG_C + 1, multiplier of EXTRAARG for NEWTABLE and SETLIST
+const EXTRAARG_C_SCALE: u32 = 256;
+
+impl LuaCompiledCode {
+    /// Listing of function and all nested functions in the format of `luac -l`, `full` also
+    /// lists constants, locals and upvalues like `luac -l -l`
+    pub fn listing(&self, full: bool) -> String {
+        let mut s = String::with_capacity(1024);
+        self.list_function(&mut s, full, None);
+
+        s
+    }
+
+    fn list_function(&self, s: &mut String, full: bool, parent_source: Option<&str>) {
+        let source = self.source.as_deref().or(parent_source);
+
+        self.list_header(s, source);
+        for pc in 0..self.byte_codes.len() {
+            self.list_code(s, pc);
+        }
+        if full {
+            self.list_debug(s);
+        }
+
+        for proto in &self.protos {
+            proto.list_function(s, full, source);
+        }
+    }
+
+    fn list_header(&self, s: &mut String, source: Option<&str>) {
+        let source = match source.unwrap_or("=?") {
+            x if x.starts_with('@') || x.starts_with('=') => &x[1..],
+            x if x.starts_with('\x1b') => "(bstring)",
+            _ => "(string)",
+        };
+        let kind = if self.line_defined == 0 { "main" } else { "function" };
+        let n = self.byte_codes.len();
+
+        writeln!(s).unwrap();
+        writeln!(
+            s,
+            "{kind} <{source}:{},{}> ({n} instruction{} at {:p})",
+            self.line_defined,
+            self.last_line_defined,
+            plural(n),
+            self
+        )
+        .unwrap();
+
+        let (params, slots) = (self.num_params as usize, self.max_stack_size as usize);
+        let (upvalues, locals) = (self.upvalues.len(), self.local_variables.len());
+        let (constants, functions) = (self.constants.len(), self.protos.len());
+        writeln!(
+            s,
+            "{params}{} param{}, {slots} slot{}, {upvalues} upvalue{}, {locals} local{}, {constants} constant{}, {functions} function{}",
+            if self.is_vararg { "+" } else { "" },
+            plural(params),
+            plural(slots),
+            plural(upvalues),
+            plural(locals),
+            plural(constants),
+            plural(functions),
+        )
+        .unwrap();
+    }
+
+    fn list_code(&self, s: &mut String, pc: usize) {
+        let code = &self.byte_codes[pc];
+        let pc = pc as i64;
+
+        write!(s, "\t{}\t", pc + 1).unwrap();
+        match self.line_info.get(pc as usize) {
+            Some(line) if *line > 0 => write!(s, "[{line}]\t").unwrap(),
+            _ => write!(s, "[-]\t").unwrap(),
+        }
+        write!(s, "{:<9}\t", code.mnemonic()).unwrap();
+
+        // Ax of the following EXTRAARG
+        let extra_arg = match self.byte_codes.get(pc as usize + 1) {
+            Some(LuaByteCode::ExtraArg(ax)) => *ax,
+            _ => 0,
+        };
+
+        match code {
+            LuaByteCode::Move(a, b)
+            | LuaByteCode::Unm(a, b)
+            | LuaByteCode::BNot(a, b)
+            | LuaByteCode::Not(a, b)
+            | LuaByteCode::Len(a, b) => write!(s, "{} {}", a.num(), b.num()).unwrap(),
+            LuaByteCode::LoadI(a, sbx) | LuaByteCode::LoadF(a, sbx) => write!(s, "{} {sbx}", a.num()).unwrap(),
+            LuaByteCode::LoadK(a, bx) => {
+                write!(s, "{} {bx}\t; {}", a.num(), self.constant_string(*bx as usize)).unwrap()
+            }
+            LuaByteCode::LoadKX(a) => {
+                write!(s, "{}\t; {}", a.num(), self.constant_string(extra_arg as usize)).unwrap()
+            }
+            LuaByteCode::LoadFalse(a)
+            | LuaByteCode::LFalseSkip(a)
+            | LuaByteCode::LoadTrue(a)
+            | LuaByteCode::Close(a)
+            | LuaByteCode::Tbc(a)
+            | LuaByteCode::Return1(a) => write!(s, "{}", a.num()).unwrap(),
+            LuaByteCode::LoadNil(a, b) => write!(s, "{} {b}\t; {} out", a.num(), *b as u32 + 1).unwrap(),
+            LuaByteCode::GetUpVal(a, b) | LuaByteCode::SetUpVal(a, b) => {
+                write!(s, "{} {b}\t; {}", a.num(), self.upvalue_name(*b)).unwrap()
+            }
+            LuaByteCode::GetTabUp(a, b, c) => write!(
+                s,
+                "{} {b} {c}\t; {} {}",
+                a.num(),
+                self.upvalue_name(*b),
+                self.constant_string(*c as usize)
+            )
+            .unwrap(),
+            LuaByteCode::GetTable(a, b, c) => write!(s, "{} {} {}", a.num(), b.num(), c.num()).unwrap(),
+            LuaByteCode::GetI(a, b, c) => write!(s, "{} {} {c}", a.num(), b.num()).unwrap(),
+            LuaByteCode::GetField(a, b, c) => {
+                write!(s, "{} {} {c}\t; {}", a.num(), b.num(), self.constant_string(*c as usize)).unwrap()
+            }
+            LuaByteCode::SetTabUp(a, b, rk) => {
+                write!(s, "{} {b} {}\t; ", a.num(), rk_string(rk)).unwrap();
+                write!(s, "{} {}", self.upvalue_name(a.num()), self.constant_string(*b as usize)).unwrap();
+                self.write_rk_constant(s, rk, " ");
+            }
+            LuaByteCode::SetTable(a, b, rk) | LuaByteCode::Self_(a, b, rk) => {
+                write!(s, "{} {} {}", a.num(), b.num(), rk_string(rk)).unwrap();
+                self.write_rk_constant(s, rk, "\t; ");
+            }
+            LuaByteCode::SetI(a, b, rk) => {
+                write!(s, "{} {b} {}", a.num(), rk_string(rk)).unwrap();
+                self.write_rk_constant(s, rk, "\t; ");
+            }
+            LuaByteCode::SetField(a, b, rk) => {
+                write!(s, "{} {b} {}\t; {}", a.num(), rk_string(rk), self.constant_string(*b as usize)).unwrap();
+                self.write_rk_constant(s, rk, " ");
+            }
+            LuaByteCode::NewTable(a, b, c, _) => write!(
+                s,
+                "{} {b} {c}\t; {}",
+                a.num(),
+                *c as u32 + extra_arg * EXTRAARG_C_SCALE
+            )
+            .unwrap(),
+            LuaByteCode::AddI(a, b, sc) | LuaByteCode::ShrI(a, b, sc) | LuaByteCode::ShlI(a, b, sc) => {
+                write!(s, "{} {} {}", a.num(), b.num(), signed_arg(*sc)).unwrap()
+            }
+            LuaByteCode::AddK(a, b, c)
+            | LuaByteCode::SubK(a, b, c)
+            | LuaByteCode::MulK(a, b, c)
+            | LuaByteCode::ModK(a, b, c)
+            | LuaByteCode::PowK(a, b, c)
+            | LuaByteCode::DivK(a, b, c)
+            | LuaByteCode::IDivK(a, b, c)
+            | LuaByteCode::BAndK(a, b, c)
+            | LuaByteCode::BOrK(a, b, c)
+            | LuaByteCode::BXorK(a, b, c) => {
+                write!(s, "{} {} {c}\t; {}", a.num(), b.num(), self.constant_string(*c as usize)).unwrap()
+            }
+            LuaByteCode::Add(a, b, c)
+            | LuaByteCode::Sub(a, b, c)
+            | LuaByteCode::Mul(a, b, c)
+            | LuaByteCode::Mod(a, b, c)
+            | LuaByteCode::Pow(a, b, c)
+            | LuaByteCode::Div(a, b, c)
+            | LuaByteCode::IDiv(a, b, c)
+            | LuaByteCode::BAnd(a, b, c)
+            | LuaByteCode::BOr(a, b, c)
+            | LuaByteCode::BXor(a, b, c)
+            | LuaByteCode::Shl(a, b, c)
+            | LuaByteCode::Shr(a, b, c) => write!(s, "{} {} {}", a.num(), b.num(), c.num()).unwrap(),
+            LuaByteCode::MMBin(a, b, tm) => {
+                write!(s, "{} {} {}\t; {}", a.num(), b.num(), *tm as u8, tm.name()).unwrap()
+            }
+            LuaByteCode::MMBinI(a, sb, tm, k) => {
+                let sb = signed_arg(*sb);
+                write!(s, "{} {sb} {} {}\t; {}", a.num(), *tm as u8, *k as u8, tm.name()).unwrap();
+                if *k {
+                    write!(s, " flip").unwrap();
+                }
+            }
+            LuaByteCode::MMBinK(a, b, tm, k) => {
+                write!(s, "{} {b} {} {}\t; {} ", a.num(), *tm as u8, *k as u8, tm.name()).unwrap();
+                write!(s, "{}", self.constant_string(*b as usize)).unwrap();
+                if *k {
+                    write!(s, " flip").unwrap();
+                }
+            }
+            LuaByteCode::Concat(a, b) => write!(s, "{} {b}", a.num()).unwrap(),
+            LuaByteCode::Jmp(sj) => write!(s, "{sj}\t; to {}", *sj as i64 + pc + 2).unwrap(),
+            LuaByteCode::Eq(a, b, k) | LuaByteCode::Lt(a, b, k) | LuaByteCode::Le(a, b, k) => {
+                write!(s, "{} {} {}", a.num(), b.num(), *k as u8).unwrap()
+            }
+            LuaByteCode::EqK(a, b, k) => {
+                write!(s, "{} {b} {}\t; {}", a.num(), *k as u8, self.constant_string(*b as usize)).unwrap()
+            }
+            LuaByteCode::EQI(a, sb, k, _)
+            | LuaByteCode::Lti(a, sb, k, _)
+            | LuaByteCode::Lei(a, sb, k, _)
+            | LuaByteCode::Gti(a, sb, k, _)
+            | LuaByteCode::Gei(a, sb, k, _) => write!(s, "{} {} {}", a.num(), signed_arg(*sb), *k as u8).unwrap(),
+            LuaByteCode::Test(a, k) => write!(s, "{} {}", a.num(), *k as u8).unwrap(),
+            LuaByteCode::TestSet(a, b, k) => write!(s, "{} {} {}", a.num(), b.num(), *k as u8).unwrap(),
+            LuaByteCode::Call(a, b, c) => {
+                write!(s, "{} {b} {c}\t; {} in {} out", a.num(), count_string(*b), count_string(*c)).unwrap()
+            }
+            LuaByteCode::TailCall(a, b, c, k) => {
+                write!(s, "{} {b} {c}{}\t; {} in", a.num(), k_suffix(*k), *b as i32 - 1).unwrap()
+            }
+            LuaByteCode::Return(a, b, c, k) => {
+                write!(s, "{a} {b} {c}{}\t; {} out", k_suffix(*k), count_string(*b)).unwrap()
+            }
+            LuaByteCode::Return0(_) => {}
+            LuaByteCode::ForLoop(a, bx) | LuaByteCode::TForLoop(a, bx) => {
+                write!(s, "{} {bx}\t; to {}", a.num(), pc - *bx as i64 + 2).unwrap()
+            }
+            LuaByteCode::ForPrep(a, bx) => write!(s, "{} {bx}\t; exit to {}", a.num(), pc + *bx as i64 + 3).unwrap(),
+            LuaByteCode::TForPrep(a, bx) => write!(s, "{} {bx}\t; to {}", a.num(), pc + *bx as i64 + 2).unwrap(),
+            LuaByteCode::TForCall(a, c) => write!(s, "{} {c}", a.num()).unwrap(),
+            LuaByteCode::SetList(a, b, c, k) => {
+                write!(s, "{} {b} {c}", a.num()).unwrap();
+                if *k {
+                    write!(s, "\t; {}", *c as u32 + extra_arg * EXTRAARG_C_SCALE).unwrap();
+                }
+            }
+            LuaByteCode::Closure(a, bx) => {
+                write!(s, "{} {bx}", a.num()).unwrap();
+                if let Some(proto) = self.protos.get(*bx as usize) {
+                    write!(s, "\t; {:p}", proto).unwrap();
+                }
+            }
+            LuaByteCode::VarArg(a, c) => write!(s, "{} {c}\t; {} out", a.num(), count_string(*c)).unwrap(),
+            LuaByteCode::VarArgPrep(a) => write!(s, "{a}").unwrap(),
+            LuaByteCode::ExtraArg(ax) => write!(s, "{ax}").unwrap(),
+        }
+
+        writeln!(s).unwrap();
+    }
+
+    fn list_debug(&self, s: &mut String) {
+        writeln!(s, "constants ({}) for {:p}:", self.constants.len(), self).unwrap();
+        for (idx, constant) in self.constants.iter().enumerate() {
+            let tag = match constant {
+                LuaConstants::Nil => "N",
+                LuaConstants::Boolean(..) => "B",
+                LuaConstants::Float(..) => "F",
+                LuaConstants::Integer(..) => "I",
+                LuaConstants::String(..) => "S",
+                LuaConstants::Function(..) => "?",
+            };
+            writeln!(s, "\t{idx}\t{tag}\t{}", self.constant_string(idx)).unwrap();
+        }
+
+        writeln!(s, "locals ({}) for {:p}:", self.local_variables.len(), self).unwrap();
+        for (idx, local) in self.local_variables.iter().enumerate() {
+            let name = local.name.as_deref().unwrap_or("?");
+            writeln!(s, "\t{idx}\t{name}\t{}\t{}", local.start_pc + 1, local.end_pc + 1).unwrap();
+        }
+
+        writeln!(s, "upvalues ({}) for {:p}:", self.upvalues.len(), self).unwrap();
+        for (idx, upv) in self.upvalues.iter().enumerate() {
+            let name = upv.name.as_deref().unwrap_or("-");
+            writeln!(s, "\t{idx}\t{name}\t{}\t{}", upv.stack, upv.index).unwrap();
+        }
+    }
+
+    fn upvalue_name(&self, idx: u8) -> &str {
+        self.upvalues
+            .get(idx as usize)
+            .and_then(|x| x.name.as_deref())
+            .unwrap_or("-")
+    }
+
+    fn constant_string(&self, idx: usize) -> String {
+        match self.constants.get(idx) {
+            Some(LuaConstants::Nil) => "nil".to_owned(),
+            Some(LuaConstants::Boolean(b)) => b.to_string(),
+            Some(LuaConstants::Integer(i)) => i.to_string(),
+            Some(LuaConstants::Float(f)) => float_string(*f),
+            Some(LuaConstants::String(s)) => quoted_string(s),
+            Some(LuaConstants::Function(..)) | None => "?".to_owned(),
+        }
+    }
+
+    fn write_rk_constant(&self, s: &mut String, rk: &RK, prefix: &str) {
+        if let RK::K(k) = rk {
+            write!(s, "{prefix}{}", self.constant_string(*k as usize)).unwrap();
+        }
+    }
+}
+
+#[inline]
+fn plural(n: usize) -> &'static str {
+    if n == 1 {
+        ""
+    } else {
+        "s"
+    }
+}
+
+#[inline]
+fn k_suffix(k: bool) -> &'static str {
+    if k {
+        "k"
+    } else {
+        ""
+    }
+}
+
+/// Register or constant argument, constant is suffixed by 'k'
+fn rk_string(rk: &RK) -> String {
+    match rk {
+        RK::R(r) => r.num().to_string(),
+        RK::K(k) => format!("{k}k"),
+    }
+}
+
+/// Count of arguments or results encoded with plus one, zero means all values on stack
+fn count_string(n: u8) -> String {
+    match n {
+        0 => "all".to_owned(),
+        n => (n - 1).to_string(),
+    }
+}
+
+/// sB and sC are kept in i8, 128 is wrapped to -128
+#[inline]
+fn signed_arg(v: i8) -> i32 {
+    (v as u8).wrapping_add(127) as i32 - 127
+}
+
+/// Format float like "%.14g", with ".0" appended if it looks like an integer
+fn float_string(f: f64) -> String {
+    const PRECISION: i32 = 14;
+
+    let s = if f.is_nan() {
+        if f.is_sign_negative() { "-nan" } else { "nan" }.to_owned()
+    } else if f.is_infinite() {
+        if f < 0.0 { "-inf" } else { "inf" }.to_owned()
+    } else {
+        // exponent after rounding to the precision
+        let exp_form = format!("{:.*e}", PRECISION as usize - 1, f);
+        let (mantissa, exp) = exp_form.split_once('e').unwrap();
+        let exp: i32 = exp.parse().unwrap();
+
+        if !(-4..PRECISION).contains(&exp) {
+            let sign = if exp < 0 { '-' } else { '+' };
+            format!("{}e{sign}{:02}", trim_fraction(mantissa), exp.abs())
+        } else {
+            trim_fraction(&format!("{:.*}", (PRECISION - 1 - exp) as usize, f)).to_owned()
+        }
+    };
+
+    if s.bytes().all(|x| x == b'-' || x.is_ascii_digit()) {
+        s + ".0"
+    } else {
+        s
+    }
+}
+
+#[inline]
+fn trim_fraction(s: &str) -> &str {
+    if s.contains('.') {
+        s.trim_end_matches('0').trim_end_matches('.')
+    } else {
+        s
+    }
+}
+
+/// Quote string with escapes as luac does
+fn quoted_string(s: &[u8]) -> String {
+    let mut r = String::with_capacity(s.len() + 2);
+    r.push('"');
+
+    for &b in s {
+        match b {
+            b'"' => r.push_str("\\\""),
+            b'\\' => r.push_str("\\\\"),
+            0x07 => r.push_str("\\a"),
+            0x08 => r.push_str("\\b"),
+            0x0c => r.push_str("\\f"),
+            b'\n' => r.push_str("\\n"),
+            b'\r' => r.push_str("\\r"),
+            b'\t' => r.push_str("\\t"),
+            0x0b => r.push_str("\\v"),
+            b' '..=b'~' => r.push(b as char),
+            _ => write!(r, "\\{:03}", b).unwrap(),
+        }
+    }
+
+    r.push('"');
+    r
+}
+
+#[cfg(test)]
+mod test {
+    use super::{float_string, quoted_string, signed_arg};
+
+    #[test]
+    fn test_float_string() {
+        assert_eq!(float_string(1.0), "1.0");
+        assert_eq!(float_string(-2.5), "-2.5");
+        assert_eq!(float_string(0.1), "0.1");
+        assert_eq!(float_string(1e100), "1e+100");
+        assert_eq!(float_string(1.5e-7), "1.5e-07");
+        assert_eq!(float_string(1e15), "1e+15");
+        assert_eq!(float_string(f64::INFINITY), "inf");
+    }
+
+    #[test]
+    fn test_quoted_string() {
+        assert_eq!(quoted_string(b"a\"b\\\n"), "\"a\\\"b\\\\\\n\"");
+        assert_eq!(quoted_string("\x01é".as_bytes()), "\"\\001\\195\\169\"");
+        assert_eq!(quoted_string(b"\xff"), "\"\\255\"");
+    }
+
+    #[test]
+    fn test_signed_arg() {
+        assert_eq!(signed_arg(-1), -1);
+        assert_eq!(signed_arg(127), 127);
+        assert_eq!(signed_arg(-127), -127);
+        assert_eq!(signed_arg(-128), 128);
+    }
+}
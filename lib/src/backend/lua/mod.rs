@@ -2,9 +2,23 @@
 mod bytecode;
 use bytecode::*;
 
+pub use bytecode::LuaCompiledCode;
+
 mod dump;
+pub use dump::lua_dump_chunk;
 use dump::lua_dump_module;
 
+mod undump;
+pub use undump::lua_undump;
+
+mod executor;
+pub use executor::LuaExecutor;
+
+mod listing;
+
+mod optimize;
+use optimize::optimize;
+
 mod register;
 use register::*;
 
@@ -18,15 +32,20 @@ use label::{InstLabel, LabelPtr};
 #[cfg(test)]
 mod test;
 
+use crate::analysis::GenericError;
+use crate::backend::utils::{
+    argument_value, function_return_type, pou_kind, sorted_declarations, PouKind, StandardCall,
+};
 use crate::backend::*;
-use crate::parser::{BitValue, LiteralValue, Operator};
+use crate::parser::{BitValue, LiteralValue, Location, Operator};
 use crate::prelude::*;
 
-use indexmap::{IndexMap, IndexSet};
+use indexmap::IndexSet;
 use log::*;
 use smallvec::{smallvec, SmallVec};
 use std::mem;
 use std::rc::Rc;
+use std::sync::Arc;
 
 type ConstIdx = u8;
 
@@ -49,7 +68,7 @@ bitflags! {
 
 bitflags! {
     #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
-    pub(crate) struct LuaVarKind: u8 {
+    pub struct LuaVarKind: u8 {
         // regular
         const VDKREG        = 0;
         // constant
@@ -84,7 +103,6 @@ struct LuaBackendStates {
     scope: Option<Scope>,
     error: bool,
     access_mode: LuaAccessMode,
-    expr_exit_label: Option<LabelPtr>,
 }
 
 impl LuaBackendStates {
@@ -108,7 +126,6 @@ impl Default for LuaBackendStates {
             error: false,
             access_mode: LuaAccessMode::None,
             const_idx: None,
-            expr_exit_label: None,
         }
     }
 }
@@ -123,12 +140,67 @@ pub struct LuaBackend {
     reg_mgr: RegisterManager,
 
     // tmp values for generating function
-    upvalue_table: IndexMap<StString, LuaUpValue>,
+    upvalue_table: Vec<LuaUpValue>,
     constants: IndexSet<LuaConstants>,
     labels: SmallVec<[LabelPtr; 32]>,
+    protos: Vec<LuaCompiledCode>,
+    // function block instance register, members are accessed as fields of it
+    self_register: Option<Reg>,
+    // source line of current statement, and source line of each instruction
+    current_line: u32,
+    line_info: Vec<u32>,
+    optimize_level: OptimizeLevel,
+    // first expression of current function can't be generated
+    error: Option<CodeGenError>,
+    // program run by the main chunk of module
+    entry: StString,
+}
+
+/// Declaration id, name and kind of all POUs in application, ordered by id.
+/// POUs are nested functions of the main chunk of module in this order.
+fn module_pous(app: &ModuleContext) -> Vec<(usize, StString, PouKind)> {
+    sorted_declarations(app)
+        .iter()
+        .filter_map(|x| {
+            let p = x.read().unwrap();
+            let kind = pou_kind(p.decl())?;
+            Some((p.id(), p.name().clone(), kind))
+        })
+        .collect()
+}
+
+/// Name of variable expression
+fn expression_variable_name(expr: &Expression) -> Option<&StString> {
+    match &expr.kind {
+        ExprKind::Variable(var) => Some(var.name()),
+        _ => None,
+    }
+}
+
+/// Value expression of call argument, named argument `x := value` returns `value`
+fn argument_value_mut(arg: &mut Expression) -> &mut Expression {
+    if !matches!(arg.kind, ExprKind::Assign(..)) {
+        return arg;
+    }
+
+    match &mut arg.kind {
+        ExprKind::Assign(assign) => assign.right_mut(),
+        _ => unreachable!(),
+    }
 }
 
 impl LuaBackend {
+    /// Program run by the main chunk of module, 'main' by default
+    #[inline]
+    pub fn entry(&self) -> &StString {
+        &self.entry
+    }
+
+    #[inline]
+    pub fn set_entry(&mut self, name: StString) {
+        self.entry = name;
+    }
+
     #[inline]
     fn code_gettabup(&mut self, dst: Reg, k: ConstIdx) {
         self.push_code(LuaByteCode::GetTabUp(dst, 0, k));
@@ -148,7 +220,7 @@ impl LuaBackend {
     fn code_load(&mut self, dst: Reg, rk: RK) {
         match rk {
             RK::R(r) => self.code_move(r, dst),
-            RK::K(v) => self.code_load_literal(dst, &LiteralValue::Byte(v)),
+            RK::K(k) => self.code_load_constant(dst, k),
         }
     }
 
@@ -165,7 +237,14 @@ impl LuaBackend {
 
     #[inline]
     fn code_load_constant(&mut self, r: Reg, k: ConstIdx) {
-        self.push_code(LuaByteCode::LoadK(r, k))
+        match self.constants[k as usize] {
+            LuaConstants::Boolean(true) => self.push_code(LuaByteCode::LoadTrue(r)),
+            LuaConstants::Boolean(false) => self.push_code(LuaByteCode::LoadFalse(r)),
+            LuaConstants::Integer(i) if fit_sbx(i).is_some() => {
+                self.push_code(LuaByteCode::LoadI(r, i as i32))
+            }
+            _ => self.push_code(LuaByteCode::LoadK(r, k)),
+        }
     }
 
     #[inline]
@@ -189,49 +268,275 @@ impl LuaBackend {
     #[inline]
     fn push_code(&mut self, code: LuaByteCode) {
         trace!("Code-Lua: {:?}", code);
-        self.byte_codes.push(code)
+        self.byte_codes.push(code);
+        self.line_info.push(self.current_line);
     }
 
-    #[inline]
-    fn code_add(&mut self, dst: Reg, op0: RK, op1: RK) {
-        let addk = self.add_string_constant("__add");
+    /// R[dst] := R[op0] <op> R[op1], followed by MMBIN
+    fn code_arith(&mut self, op: Operator, dst: Reg, op0: Reg, op1: Reg, int: bool) {
+        let (code, tm) = match op {
+            Operator::Plus => (LuaByteCode::Add(dst, op0, op1), LuaTMS::TM_ADD),
+            Operator::Minus => (LuaByteCode::Sub(dst, op0, op1), LuaTMS::TM_SUB),
+            Operator::Multiply => (LuaByteCode::Mul(dst, op0, op1), LuaTMS::TM_MUL),
+            Operator::Power => (LuaByteCode::Pow(dst, op0, op1), LuaTMS::TM_POW),
+            Operator::Division if int => (LuaByteCode::IDiv(dst, op0, op1), LuaTMS::TM_IDIV),
+            Operator::Division => (LuaByteCode::Div(dst, op0, op1), LuaTMS::TM_DIV),
+            Operator::Mod => (LuaByteCode::Mod(dst, op0, op1), LuaTMS::TM_MOD),
+            Operator::BitAnd => (LuaByteCode::BAnd(dst, op0, op1), LuaTMS::TM_BAND),
+            Operator::BitOr => (LuaByteCode::BOr(dst, op0, op1), LuaTMS::TM_BOR),
+            Operator::Xor => (LuaByteCode::BXor(dst, op0, op1), LuaTMS::TM_BXOR),
+            _ => unreachable!("{:?}", op),
+        };
 
-        match (op0, op1) {
-            (RK::K(k), RK::R(r)) | (RK::R(r), RK::K(k)) => {
-                self.push_code(LuaByteCode::AddK(dst, r, k));
-                self.push_code(LuaByteCode::MMBinK(dst, k, addk));
-            }
-            (RK::K(k1), RK::K(k2)) => {
-                self.push_code(LuaByteCode::LoadK(dst, k1));
-                self.push_code(LuaByteCode::AddK(dst, dst, k2));
-                self.push_code(LuaByteCode::MMBinK(dst, k2, addk));
-            }
-            (RK::R(r1), RK::R(r2)) => {
-                self.push_code(LuaByteCode::Add(dst, r1, r2));
-                self.push_code(LuaByteCode::MMBin(r1, r2, addk));
-            }
+        self.push_code(code);
+        self.push_code(LuaByteCode::MMBin(op0, op1, tm));
+    }
+
+    /// R[dst] := R[src] <op> K[k], followed by MMBINK
+    fn code_arith_k(&mut self, op: Operator, dst: Reg, src: Reg, k: ConstIdx) {
+        let (code, tm) = match op {
+            Operator::Plus => (LuaByteCode::AddK(dst, src, k), LuaTMS::TM_ADD),
+            Operator::Minus => (LuaByteCode::SubK(dst, src, k), LuaTMS::TM_SUB),
+            Operator::Multiply => (LuaByteCode::MulK(dst, src, k), LuaTMS::TM_MUL),
+            Operator::BitAnd => (LuaByteCode::BAndK(dst, src, k), LuaTMS::TM_BAND),
+            Operator::BitOr => (LuaByteCode::BOrK(dst, src, k), LuaTMS::TM_BOR),
+            Operator::Xor => (LuaByteCode::BXorK(dst, src, k), LuaTMS::TM_BXOR),
+            _ => unreachable!("{:?}", op),
+        };
+
+        self.push_code(code);
+        self.push_code(LuaByteCode::MMBinK(src, k, tm, false));
+    }
+
+    /// IEC integer division truncates toward zero, but Lua '//' is floor division.
+    /// q := a // b; if a % b ~= 0 and q < 0 then q := q + 1
+    fn code_int_div(&mut self, dst: Reg, op0: Reg, op1: Reg) {
+        let rem = self.reg_mgr.alloc_hard();
+
+        self.code_arith(Operator::Division, dst, op0, op1, true);
+        self.code_arith(Operator::Mod, rem, op0, op1, true);
+        self.push_code(LuaByteCode::EQI(rem, 0, true, false));
+        self.code_jmp_fixed(4);
+        self.push_code(LuaByteCode::Gei(dst, 0, true, false));
+        self.code_jmp_fixed(2);
+        self.push_code(LuaByteCode::AddI(dst, dst, 1));
+        self.push_code(LuaByteCode::MMBinI(dst, 1, LuaTMS::TM_ADD, false));
+
+        self.reg_mgr.free(&rem);
+    }
+
+    /// Lua has no unsigned division, ULINT is divided like `math.ult` based division:
+    /// if b < 0 then q := ult(a, b) and 0 or 1
+    /// else q := ((a >> 1) // b) * 2; if not ult(a - q * b, b) then q := q + 1
+    fn code_ulint_div(&mut self, dst: Reg, op0: Reg, op1: Reg) {
+        let small_divisor = self.create_label("ulint-div-small-divisor");
+        let done = self.create_label("ulint-div-done");
+
+        // divisor not less than 2^63, quotient is 0 or 1
+        self.push_code(LuaByteCode::Gei(op1, 0, true, false));
+        self.code_jmp(small_divisor.clone());
+        let zero = self.reg_mgr.alloc_hard();
+        self.push_code(LuaByteCode::LoadI(dst, 1));
+        self.push_code(LuaByteCode::LoadI(zero, 0));
+        self.code_move_if_less(op0, op1, zero, dst, TypeClass::ULInt);
+        self.reg_mgr.free(&zero);
+        self.code_jmp(done.clone());
+
+        self.insert_label(small_divisor);
+        let rem = self.reg_mgr.alloc_hard();
+        self.push_code(LuaByteCode::ShrI(dst, op0, 1));
+        self.push_code(LuaByteCode::MMBinI(op0, 1, LuaTMS::TM_SHR, false));
+        self.code_arith(Operator::Division, dst, dst, op1, true);
+        self.code_arith(Operator::Plus, dst, dst, dst, true);
+        self.code_arith(Operator::Multiply, rem, dst, op1, true);
+        self.code_arith(Operator::Minus, rem, op0, rem, true);
+        let (flipped_rem, divisor) = (self.code_flip_sign(rem), self.code_flip_sign(op1));
+        self.push_code(LuaByteCode::Lt(flipped_rem, divisor, true));
+        self.code_jmp(done.clone());
+        self.push_code(LuaByteCode::AddI(dst, dst, 1));
+        self.push_code(LuaByteCode::MMBinI(dst, 1, LuaTMS::TM_ADD, false));
+        self.reg_mgr.free(&flipped_rem);
+        self.reg_mgr.free(&divisor);
+        self.reg_mgr.free(&rem);
+
+        self.insert_label(done);
+    }
+
+    /// IEC MOD takes the sign of dividend, but Lua '%' takes the sign of divisor.
+    /// r := a % b; if r ~= 0 and (r ~ a) < 0 then r := r - b
+    fn code_int_mod(&mut self, dst: Reg, op0: Reg, op1: Reg) {
+        let sign = self.reg_mgr.alloc_hard();
+
+        self.code_arith(Operator::Mod, dst, op0, op1, true);
+        self.push_code(LuaByteCode::EQI(dst, 0, true, false));
+        self.code_jmp_fixed(6);
+        self.code_arith(Operator::Xor, sign, dst, op0, true);
+        self.push_code(LuaByteCode::Gei(sign, 0, true, false));
+        self.code_jmp_fixed(2);
+        self.code_arith(Operator::Minus, dst, dst, op1, true);
+
+        self.reg_mgr.free(&sign);
+    }
+
+    /// Wrap integer value in R[src] into the range of `class`, result in R[dst].
+    /// REAL values are rounded to single precision.
+    fn code_wrap(&mut self, dst: Reg, src: Reg, class: TypeClass) {
+        if class == TypeClass::Real {
+            return self.code_narrow_real(dst, src);
+        }
+        let Some((bits, signed)) = integer_width(class) else {
+            return self.code_move(src, dst);
+        };
+
+        if bits == 64 {
+            // convert to integer representation only
+            let zero = self.add_integer_constant(0);
+            return self.code_arith_k(Operator::BitOr, dst, src, zero);
+        }
+
+        let mask = self.add_integer_constant((1i64 << bits) - 1);
+        self.code_arith_k(Operator::BitAnd, dst, src, mask);
+
+        if signed {
+            let sign = self.add_integer_constant(1i64 << (bits - 1));
+            self.code_arith_k(Operator::Xor, dst, dst, sign);
+            self.code_arith_k(Operator::Minus, dst, dst, sign);
         }
     }
 
-    #[inline]
-    fn code_eq(&mut self, dst: Reg, op0: RK, op1: RK) {
-        self.code_load(dst, op0);
+    /// Lua numbers are doubles, REAL is narrowed by `string.unpack("f", string.pack("f", x))`,
+    /// values out of the range of single precision become infinity
+    fn code_narrow_real(&mut self, dst: Reg, src: Reg) {
+        let regs = self.reg_mgr.alloc_hard_batch(2);
+        let k_string = self.add_string_constant("string");
+        let k_format = self.add_string_constant("f");
+
+        for (i, function) in ["pack", "unpack"].into_iter().enumerate() {
+            let k_function = self.add_string_constant(function);
+            // argument is the value or the packed string in R[regs[0]]
+            self.code_move(if i == 0 { src } else { regs[0] }, regs[2]);
+            self.code_gettabup(regs[0], k_string);
+            self.push_code(LuaByteCode::GetField(regs[0], regs[0], k_function));
+            self.code_load_constant(regs[1], k_format);
+            self.push_code(LuaByteCode::Call(regs[0], 3, 2));
+        }
+        self.code_move(regs[0], dst);
 
-        match op1 {
-            RK::R(r) => self.push_code(LuaByteCode::Eq(dst, r, 0)),
+        for r in regs {
+            self.reg_mgr.free(&r);
+        }
+    }
+
+    /// Flip sign bit of unsigned 64 bits integer, so signed comparison can be used
+    fn code_flip_sign(&mut self, r: Reg) -> Reg {
+        let flipped = self.reg_mgr.alloc_hard();
+        let sign = self.add_integer_constant(i64::MIN);
+        self.code_arith_k(Operator::Xor, flipped, r, sign);
+
+        flipped
+    }
+
+    /// Materialize comparison result into boolean register
+    fn code_compare(&mut self, op: Operator, dst: Reg, op0: Reg, op1: Reg) {
+        let code = match op {
+            Operator::Less => LuaByteCode::Lt(op0, op1, true),
+            Operator::LessEqual => LuaByteCode::Le(op0, op1, true),
+            Operator::Greater => LuaByteCode::Lt(op1, op0, true),
+            Operator::GreaterEqual => LuaByteCode::Le(op1, op0, true),
+            Operator::Equal => LuaByteCode::Eq(op0, op1, true),
+            Operator::NotEqual => LuaByteCode::Eq(op0, op1, false),
+            _ => unreachable!("{:?}", op),
+        };
+
+        self.push_code(code);
+        self.code_jmp_fixed(1);
+        self.push_code(LuaByteCode::LFalseSkip(dst));
+        self.push_code(LuaByteCode::LoadTrue(dst));
+    }
+
+    /// Boolean AND/OR, R[dst] := R[op0] and/or R[op1]
+    fn code_logic(&mut self, op: Operator, dst: Reg, op0: Reg, op1: Reg) {
+        self.code_move(op0, dst);
+        self.push_code(LuaByteCode::Test(dst, matches!(op, Operator::BitOr)));
+        self.code_jmp_fixed(1);
+        self.code_move(op1, dst);
+    }
+
+    /// Evaluate expression into register, constants will be loaded into a new register
+    fn code_expression_register(&mut self, expr: &mut Expression) -> Reg {
+        self.push_access_attribute(LuaAccessMode::LoadNewRegister);
+        self.visit_expression_mut(expr);
+        let attr = self.pop_attribute();
+
+        match attr.rk() {
+            RK::R(r) => r,
             RK::K(k) => {
-                // Test constants can load into i8
-                if let Some(ki8) = self.constants[k as usize].as_lua_i8() {
-                    self.push_code(LuaByteCode::EQI(dst, ki8, false))
-                } else {
-                    // use extra register to compare
-                    let r = self.reg_mgr.alloc_hard();
+                let r = self.reg_mgr.alloc_hard();
+                self.code_load_constant(r, k);
+                r
+            }
+        }
+    }
 
-                    self.code_load_constant(r, k);
-                    self.push_code(LuaByteCode::Eq(dst, r, 0));
-                    self.reg_mgr.free(&r);
-                }
+    /// Evaluate condition and jump to `label` if condition is false
+    fn code_condition_jump(&mut self, cond: &mut Expression, label: LabelPtr) {
+        let class = self.expression_class(cond);
+        let r = self.code_expression_register(cond);
+
+        match class.and_then(integer_width) {
+            // BIT or integer condition, jump if zero
+            Some(_) => self.push_code(LuaByteCode::EQI(r, 0, true, false)),
+            _ => self.push_code(LuaByteCode::Test(r, false)),
+        }
+        self.code_jmp(label);
+
+        self.reg_mgr.free(&r);
+    }
+
+    /// Get type class of expression, untyped integer literals will be None
+    fn expression_class(&mut self, expr: &Expression) -> Option<TypeClass> {
+        if let Some(ty) = expr.ty() {
+            return Some(ty.type_class());
+        }
+
+        match &expr.kind {
+            ExprKind::Literal(lit) => match lit.literal() {
+                LiteralValue::Bool(_)
+                | LiteralValue::Real(_)
+                | LiteralValue::LReal(_)
+                | LiteralValue::String(_) => Some(lit.literal().ty().type_class()),
+                _ => None,
+            },
+            ExprKind::Variable(var) => self
+                .current_scope()
+                .find_variable(var.name())
+                .and_then(|v| v.ty().map(|ty| ty.type_class())),
+            ExprKind::Assign(assign) => self.expression_class(assign.left()),
+            ExprKind::Compo(_) | ExprKind::ArrayAccess(_) => {
+                self.expression_type(expr).map(|ty| ty.type_class())
             }
+            ExprKind::Call(call) => match self.standard_call(call) {
+                Some(Ok(standard)) => Some(standard.return_class),
+                _ => None,
+            },
+            ExprKind::Operator(op_expr) => match op_expr.op() {
+                Operator::Less
+                | Operator::LessEqual
+                | Operator::Greater
+                | Operator::GreaterEqual
+                | Operator::Equal
+                | Operator::NotEqual => Some(TypeClass::Bool),
+                _ => {
+                    let operands = op_expr.operands();
+                    let mut class = self.expression_class(&operands[0]);
+                    for operand in operands.iter().skip(1) {
+                        class = wider_class(class, self.expression_class(operand));
+                    }
+
+                    class
+                }
+            },
+            _ => None,
         }
     }
 
@@ -239,15 +544,22 @@ impl LuaBackend {
     fn add_constant(&mut self, v: &LiteralValue) -> ConstIdx {
         match v {
             LiteralValue::String(s) => self.add_string_constant(s),
+            LiteralValue::Bool(b) => self.add_boolean_constant(*b),
             LiteralValue::Bit(BitValue::Zero) => self.add_integer_constant(0),
             LiteralValue::Bit(BitValue::One) => self.add_integer_constant(1),
-            LiteralValue::DInt(i) => self.add_integer_constant(*i as i64),
+            LiteralValue::Byte(i) => self.add_integer_constant(*i as i64),
+            LiteralValue::SInt(i) => self.add_integer_constant(*i as i64),
+            LiteralValue::Int(i) => self.add_integer_constant(*i as i64),
             LiteralValue::UInt(i) => self.add_integer_constant(*i as i64),
+            LiteralValue::DInt(i) => self.add_integer_constant(*i as i64),
+            LiteralValue::UDInt(i) => self.add_integer_constant(*i as i64),
+            LiteralValue::LInt(i) => self.add_integer_constant(*i),
+            LiteralValue::ULInt(i) => self.add_integer_constant(*i as i64),
+            LiteralValue::Time(i) => self.add_integer_constant(*i),
             LiteralValue::Real(s) | LiteralValue::LReal(s) => {
                 let f: f64 = s.parse().unwrap();
                 self.add_float_constant(f)
             }
-            _ => panic!("Add literal {:?} failed", v),
         }
     }
 
@@ -284,16 +596,6 @@ impl LuaBackend {
         self.states.push(attr);
     }
 
-    fn push_exit_label(&mut self, exit_label: LabelPtr) {
-        let attr = LuaBackendStates {
-            scope: self.top_attribute().scope.clone(),
-            expr_exit_label: Some(exit_label),
-            ..Default::default()
-        };
-
-        self.states.push(attr);
-    }
-
     #[inline]
     fn pop_attribute(&mut self) -> LuaBackendStates {
         self.states.pop().unwrap()
@@ -310,9 +612,1178 @@ impl LuaBackend {
         self.top_attribute().clone().scope.unwrap()
     }
 
+    /// Generate a function into a new Lua prototype, states of the current function are
+    /// saved and restored. All POUs are nested functions of the main chunk of module, so
+    /// _ENV is the upvalue of enclosing function.
+    fn gen_proto<F>(&mut self, num_params: u8, is_vararg: bool, f: F) -> LuaCompiledCode
+    where
+        F: FnOnce(&mut Self),
+    {
+        let outer_byte_codes = mem::take(&mut self.byte_codes);
+        let outer_constants = mem::take(&mut self.constants);
+        let outer_upvalues = mem::take(&mut self.upvalue_table);
+        let outer_labels = mem::take(&mut self.labels);
+        let outer_protos = mem::take(&mut self.protos);
+        let outer_reg_mgr = mem::replace(&mut self.reg_mgr, RegisterManager::new());
+        let outer_self_register = self.self_register.take();
+        let outer_line_info = mem::take(&mut self.line_info);
+        let outer_current_line = mem::replace(&mut self.current_line, 0);
+
+        // create upvalue table
+        self.upvalue_table.push(LuaUpValue {
+            name: Some("_ENV".to_owned()),
+            stack: 0,
+            index: 0,
+            kind: LuaVarKind::VDKREG,
+        });
+
+        f(self);
+
+        // jmp relocate
+        let labels = mem::take(&mut self.labels);
+        for label in labels {
+            let label_offset = label.borrow().inst_index.unwrap();
+            for inst_offset in &label.borrow().fixup_instructions {
+                let fix_offset = label_offset as i32 - (*inst_offset as i32) - 1;
+                self.code_fixup_jmp(*inst_offset, fix_offset);
+            }
+        }
+
+        // check register is balance
+        let max_stack_size = max_stack_size(self.reg_mgr.max_stack_size(), num_params);
+        assert!(self.reg_mgr.check_and_reset());
+
+        let line_info = mem::replace(&mut self.line_info, outer_line_info);
+        let lines = line_info.iter().filter(|x| **x > 0);
+        let line_defined = lines.clone().min().copied().unwrap_or(0);
+        let last_line_defined = lines.max().copied().unwrap_or(0);
+
+        let mut code = LuaCompiledCode {
+            byte_codes: mem::replace(&mut self.byte_codes, outer_byte_codes),
+            constants: mem::replace(&mut self.constants, outer_constants).into_iter().collect(),
+            upvalues: mem::replace(&mut self.upvalue_table, outer_upvalues),
+            protos: mem::replace(&mut self.protos, outer_protos),
+            num_params,
+            is_vararg,
+            max_stack_size,
+            source: None,
+            line_defined,
+            last_line_defined,
+            line_info,
+            local_variables: vec![],
+        };
+        self.labels = outer_labels;
+        self.reg_mgr = outer_reg_mgr;
+        self.self_register = outer_self_register;
+        self.current_line = outer_current_line;
+
+        optimize(&mut code, self.optimize_level);
+        code
+    }
+
+    /// Function block is a class table `{ new = constructor, body = body }`,
+    /// the generated function returns this table.
+    fn gen_function_block_class(&mut self, f: &Function) -> LuaCompiledCode {
+        self.gen_proto(0, false, |this| {
+            // constructor, return a new instance table
+            let ctor = this.gen_proto(0, false, |this| this.code_fb_constructor());
+            this.protos.push(ctor);
+
+            // body, instance table is the only parameter
+            let body = this.gen_proto(1, false, |this| {
+                let self_reg = this.reg_mgr.alloc_hard();
+                this.self_register = Some(self_reg);
+
+                let mut fun = f.write();
+                this.visit_statement_mut(fun.parse_tree_mut());
+                this.push_code(LuaByteCode::Return(0, 1, 0, false));
+
+                this.reg_mgr.free(&self_reg);
+            });
+            this.protos.push(body);
+
+            let class = this.reg_mgr.alloc_hard();
+            let r = this.reg_mgr.alloc_hard();
+            this.push_code(LuaByteCode::NewTable(class, table_hash_size(2), 0, false));
+            this.push_code(LuaByteCode::ExtraArg(0));
+            for (idx, name) in ["new", "body"].iter().enumerate() {
+                let k = this.add_string_constant(name);
+                this.push_code(LuaByteCode::Closure(r, idx as u32));
+                this.push_code(LuaByteCode::SetField(class, k, RK::R(r)));
+            }
+            this.push_code(LuaByteCode::Return(class.num(), 2, 0, false));
+
+            this.reg_mgr.free(&r);
+            this.reg_mgr.free(&class);
+        })
+    }
+
+    /// Inputs of function are parameters, its variables and return value are fields of a
+    /// frame table created by each call, the return value is returned.
+    fn gen_function_frame(&mut self, f: &Function, p: &Prototype) -> LuaCompiledCode {
+        let (name, variables) = {
+            let p = p.read().unwrap();
+            (p.name().clone(), p.variables().to_vec())
+        };
+        let return_type = function_return_type(p);
+        let (inputs, locals): (Vec<_>, Vec<_>) = variables
+            .into_iter()
+            .partition(|x| x.flags().contains(VariableFlags::INPUT));
+
+        self.gen_proto(inputs.len() as u8, false, |this| {
+            let params: Vec<_> = inputs.iter().map(|_| this.reg_mgr.alloc_hard()).collect();
+            let frame = this.code_table_default(&locals);
+            for (variable, r) in inputs.iter().zip(&params) {
+                let k = this.add_string_constant(variable.name());
+                this.push_code(LuaByteCode::SetField(frame, k, RK::R(*r)));
+                this.reg_mgr.free(r);
+            }
+
+            let k_result = this.add_string_constant(&name);
+            if let Some(r) = return_type.and_then(|ty| this.code_default_value(&ty, None)) {
+                this.push_code(LuaByteCode::SetField(frame, k_result, RK::R(r)));
+                this.reg_mgr.free(&r);
+            }
+
+            this.self_register = Some(frame);
+            let mut fun = f.write();
+            this.visit_statement_mut(fun.parse_tree_mut());
+
+            let r = this.reg_mgr.alloc_hard();
+            this.push_code(LuaByteCode::GetField(r, frame, k_result));
+            this.push_code(LuaByteCode::Return(r.num(), 2, 0, false));
+            this.reg_mgr.free(&r);
+            this.reg_mgr.free(&frame);
+        })
+    }
+
+    /// Function block constructor, all members are initialized with initial value or default value
+    fn code_fb_constructor(&mut self) {
+        let variables: Vec<_> = self
+            .local_proto
+            .as_ref()
+            .map(|x| x.read().unwrap().variables().to_vec())
+            .unwrap_or_default();
+
+        let obj = self.code_table_default(&variables);
+        self.push_code(LuaByteCode::Return(obj.num(), 2, 0, false));
+        self.reg_mgr.free(&obj);
+    }
+
+    /// Create a new table in register, fields are initialized with initial value or default value
+    fn code_table_default(&mut self, variables: &[Arc<Variable>]) -> Reg {
+        let obj = self.reg_mgr.alloc_hard();
+        self.push_code(LuaByteCode::NewTable(obj, table_hash_size(variables.len()), 0, false));
+        self.push_code(LuaByteCode::ExtraArg(0));
+
+        for variable in variables {
+            if let Some(r) = self.code_variable_default(variable) {
+                let k = self.add_string_constant(variable.name());
+                self.push_code(LuaByteCode::SetField(obj, k, RK::R(r)));
+                self.reg_mgr.free(&r);
+            }
+        }
+
+        obj
+    }
+
+    /// Array is a flattened table with index starts from 1, all elements are initialized
+    /// with default value of base type in a numeric for loop.
+    fn code_array_default(&mut self, arr: &ArrayType) -> Option<Reg> {
+        let len: i64 = array_dimensions(arr, &self.app)?.iter().map(|(_, len)| len).product();
+
+        let table = self.reg_mgr.alloc_hard();
+        self.push_code(LuaByteCode::NewTable(table, 0, len.min(u8::MAX as i64) as u8, false));
+        self.push_code(LuaByteCode::ExtraArg(0));
+        if len == 0 {
+            return Some(table);
+        }
+
+        // for i = 1, len do table[i] = default end
+        let regs = self.reg_mgr.alloc_hard_batch(3);
+        let k_len = self.add_integer_constant(len);
+        self.push_code(LuaByteCode::LoadI(regs[0], 1));
+        self.code_load_constant(regs[1], k_len);
+        self.push_code(LuaByteCode::LoadI(regs[2], 1));
+
+        let prep = self.byte_codes.len();
+        self.push_code(LuaByteCode::ForPrep(regs[0], 0));
+        if let Some(r) = self.code_default_value(arr.base_type(), None) {
+            self.push_code(LuaByteCode::SetTable(table, regs[3], RK::R(r)));
+            self.reg_mgr.free(&r);
+        }
+        let body_len = (self.byte_codes.len() - prep - 1) as u32;
+        self.byte_codes[prep] = LuaByteCode::ForPrep(regs[0], body_len);
+        self.push_code(LuaByteCode::ForLoop(regs[0], body_len + 1));
+
+        for r in regs {
+            self.reg_mgr.free(&r);
+        }
+
+        Some(table)
+    }
+
+    /// Initialize global variables and variables of program, variables are initialized
+    /// only once and keep their states between cycles. Function block classes are
+    /// registered into _ENV by the main chunk of module. Instances in functions and
+    /// function blocks are constructed with their frames, see `code_table_default`.
+    fn code_entry_prologue(&mut self) {
+        // global variables are shared by programs, the first program run initializes them
+        let mut variables: Vec<_> = sorted_declarations(&self.app)
+            .iter()
+            .filter_map(|x| {
+                let p = x.read().unwrap();
+                matches!(p.decl().kind, DeclKind::GlobalVar(_) | DeclKind::Configuration(_))
+                    .then(|| p.variables().to_vec())
+            })
+            .flatten()
+            .collect();
+        if let Some(proto) = self.local_proto.as_ref() {
+            variables.extend_from_slice(proto.read().unwrap().variables());
+        }
+
+        for variable in variables {
+            if variable.ty().is_none() {
+                continue;
+            }
+
+            // if variable not exists, initialize it
+            let initialized = self.create_label("variable-initialized");
+            let r = self.reg_mgr.alloc_hard();
+            let nil = self.reg_mgr.alloc_hard();
+            let k = self.add_string_constant(variable.name());
+            self.code_gettabup(r, k);
+            self.push_code(LuaByteCode::LoadNil(nil, 0));
+            self.push_code(LuaByteCode::Eq(r, nil, false));
+            self.code_jmp(initialized.clone());
+            self.reg_mgr.free(&nil);
+            self.reg_mgr.free(&r);
+
+            if let Some(r) = self.code_variable_default(&variable) {
+                self.code_settabup(k, RK::R(r));
+                self.reg_mgr.free(&r);
+            }
+            self.insert_label(initialized);
+        }
+    }
+
+    /// Load initial value or default value of variable into a new register
+    #[inline]
+    fn code_variable_default(&mut self, variable: &Variable) -> Option<Reg> {
+        self.code_default_value(variable.ty()?, variable.initial().as_deref())
+    }
+
+    /// Load initial value or default value of type into a new register, function block
+    /// instance is created by its constructor, structs and arrays are new tables.
+    fn code_default_value(&mut self, ty: &Type, initial: Option<&Expression>) -> Option<Reg> {
+        if let Some(arr) = ty.as_array() {
+            return self.code_array_default(arr);
+        }
+
+        if let Some(decl) = self.type_user_decl(ty) {
+            return self.code_user_type_default(decl, initial);
+        }
+
+        let class = ty.type_class();
+        let initial = initial.and_then(|x| self.add_initial_constant(x));
+        let k = match initial {
+            Some(k) => match self.constants[k as usize] {
+                LuaConstants::Integer(i) if integer_width(class).is_some() => {
+                    self.add_integer_constant(wrap_integer(i, class))
+                }
+                LuaConstants::Integer(i) if is_float_type(class) => {
+                    self.add_float_constant(i as f64)
+                }
+                _ => k,
+            },
+            None => match class {
+                TypeClass::Bool => self.add_boolean_constant(false),
+                TypeClass::Real | TypeClass::LReal => self.add_float_constant(0.0),
+                TypeClass::String => self.add_string_constant(""),
+                _ if integer_width(class).is_some() => {
+                    // subrange starts from its lower bound
+                    let lower = ty
+                        .as_subrange()
+                        .and_then(|x| subrange_bounds(x, &self.app))
+                        .map_or(0, |(lower, _)| lower);
+                    self.add_integer_constant(lower)
+                }
+                _ => return None,
+            },
+        };
+
+        let r = self.reg_mgr.alloc_hard();
+        self.code_load_constant(r, k);
+        Some(r)
+    }
+
+    /// Default value of user type: function block instance, struct table or enum value
+    fn code_user_type_default(&mut self, decl: Prototype, initial: Option<&Expression>) -> Option<Reg> {
+        let decl = decl.read().unwrap();
+        let name = decl.name().clone();
+        let is_function_block = decl.is_function_block();
+        let first_enum_value = match &decl.decl().kind {
+            DeclKind::Enum(e) => Some(enum_values(e).first().map(|(_, v)| *v).unwrap_or(0)),
+            _ => None,
+        };
+        let fields = match &decl.decl().kind {
+            DeclKind::Struct(s) => Some(s.variables().to_vec()),
+            _ => None,
+        };
+        drop(decl);
+
+        if is_function_block {
+            let r = self.reg_mgr.alloc_hard_batch(0)[0];
+            let k_class = self.add_string_constant(&name);
+            let k_new = self.add_string_constant("new");
+            self.code_gettabup(r, k_class);
+            self.push_code(LuaByteCode::GetField(r, r, k_new));
+            self.push_code(LuaByteCode::Call(r, 1, 2));
+
+            return Some(r);
+        }
+
+        if let Some(fields) = fields {
+            return Some(self.code_table_default(&fields));
+        }
+
+        let value = first_enum_value?;
+        let k = initial
+            .and_then(|x| self.add_initial_constant(x))
+            .unwrap_or_else(|| self.add_integer_constant(value));
+        let r = self.reg_mgr.alloc_hard();
+        self.code_load_constant(r, k);
+        Some(r)
+    }
+
+    /// Constant of variable initial value, only literals and enum values are supported
+    fn add_initial_constant(&mut self, expr: &Expression) -> Option<ConstIdx> {
+        match &expr.kind {
+            ExprKind::Literal(lit) => Some(self.add_constant(lit.literal())),
+            ExprKind::Variable(var) => {
+                let value = self.enum_member_value(None, var.name())?;
+                Some(self.add_integer_constant(value))
+            }
+            ExprKind::Compo(compo) => {
+                let value = self.compo_enum_value(compo)?;
+                Some(self.add_integer_constant(value))
+            }
+            ExprKind::Operator(op)
+                if matches!(op.op(), Operator::Minus) && op.operands().len() == 1 =>
+            {
+                let k = self.add_initial_constant(&op.operands()[0])?;
+                match self.constants[k as usize] {
+                    LuaConstants::Integer(i) => Some(self.add_integer_constant(i.wrapping_neg())),
+                    LuaConstants::Float(f) => Some(self.add_float_constant(-f)),
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+
+    /// Find declaration of user type, like function block, struct or enum
+    fn type_user_decl(&self, ty: &Type) -> Option<Prototype> {
+        let name = ty.user_type_name()?;
+        self.app.read().find_declaration_by_name(name).cloned()
+    }
+
+    /// Find declaration of user type of expression, like function block instance
+    fn expression_user_decl(&mut self, expr: &Expression) -> Option<Prototype> {
+        let ty = self.expression_type(expr)?;
+        self.type_user_decl(&ty)
+    }
+
+    /// Get declared type of variable, member or array element expression
+    fn expression_type(&mut self, expr: &Expression) -> Option<Type> {
+        match &expr.kind {
+            ExprKind::Variable(var) => self
+                .current_scope()
+                .find_variable(var.name())?
+                .ty()
+                .cloned(),
+            ExprKind::Compo(compo) => self.compo_variable(compo)?.ty().cloned(),
+            ExprKind::ArrayAccess(access) => access.ty().or_else(|| {
+                let ty = self.expression_type(access.array())?;
+                ty.as_array().map(|x| x.base_type().clone())
+            }),
+            _ => None,
+        }
+    }
+
+    /// Value of enum member, `enum_name` is None for unqualified member name
+    fn enum_member_value(&self, enum_name: Option<&StString>, member: &StString) -> Option<i64> {
+        self.app.read().declarations().find_map(|decl| {
+            let decl = decl.read().unwrap();
+            let DeclKind::Enum(e) = &decl.decl().kind else {
+                return None;
+            };
+            if enum_name.map_or(e.is_qualified_only(), |x| x != e.name()) {
+                return None;
+            }
+
+            enum_values(e)
+                .into_iter()
+                .find(|(name, _)| name == member)
+                .map(|(_, v)| v)
+        })
+    }
+
+    /// Value of qualified enum member, like `Color.Red`
+    fn compo_enum_value(&mut self, compo: &CompoAccessExpression) -> Option<i64> {
+        let enum_name = expression_variable_name(compo.left())?;
+        let member = expression_variable_name(compo.right())?;
+        if self.current_scope().find_variable(enum_name).is_some() {
+            return None;
+        }
+
+        self.enum_member_value(Some(enum_name), member)
+    }
+
+    /// Find the member variable of compo access expression
+    fn compo_variable(&mut self, compo: &CompoAccessExpression) -> Option<Arc<Variable>> {
+        let field = expression_variable_name(compo.right())?;
+        let decl = self.expression_user_decl(compo.left())?;
+        let decl = decl.read().unwrap();
+
+        decl.variables().iter().find(|x| x.name() == field).cloned()
+    }
+
+    /// Function block members are fields of instance table and variables of function are
+    /// fields of frame table, returns the register of table
+    fn variable_owner(&mut self, name: &StString) -> Option<Reg> {
+        let self_reg = self.self_register?;
+        if self.is_return_variable(name) {
+            return Some(self_reg);
+        }
+
+        self.current_scope()
+            .find_local_variable(name)
+            .map(|_| self_reg)
+    }
+
+    /// Return value of function is the variable named by function
+    fn is_return_variable(&self, name: &StString) -> bool {
+        self.local_proto.as_ref().is_some_and(|p| {
+            function_return_type(p).is_some() && p.read().unwrap().name() == name
+        })
+    }
+
+    fn code_load_variable(&mut self, dst: Reg, name: &StString) {
+        let k = self.add_string_constant(name);
+        match self.variable_owner(name) {
+            Some(obj) => self.push_code(LuaByteCode::GetField(dst, obj, k)),
+            None => self.code_gettabup(dst, k),
+        }
+    }
+
+    fn code_store_variable(&mut self, name: &StString, value: RK) {
+        let k = self.add_string_constant(name);
+        match self.variable_owner(name) {
+            Some(obj) => self.push_code(LuaByteCode::SetField(obj, k, value)),
+            None => self.code_settabup(k, value),
+        }
+    }
+
+    /// Keep the first error of current function, code generation goes on
+    fn report_invalid_expression<E: Display>(&mut self, expr: &E, reason: &str) {
+        if self.error.is_none() {
+            self.error = Some(CodeGenError::InvalidExpression(
+                expr.to_string(),
+                reason.to_owned(),
+            ));
+        }
+    }
+
+    /// Store value into variable or member of function block instance
+    fn code_store(&mut self, target: &mut Expression, value: RK) {
+        match &mut target.kind {
+            ExprKind::Variable(var) => {
+                let name = var.name().clone();
+                self.code_store_variable(&name, value)
+            }
+            ExprKind::Compo(compo) => {
+                let Some(field) = expression_variable_name(compo.right()).cloned() else {
+                    self.report_invalid_expression(compo, "invalid member access");
+                    return;
+                };
+                let obj = self.code_expression_register(compo.left_mut());
+                let k = self.add_string_constant(&field);
+                self.push_code(LuaByteCode::SetField(obj, k, value));
+                self.reg_mgr.free(&obj);
+            }
+            ExprKind::ArrayAccess(access) => {
+                let Some((table, index)) = self.code_array_element(target.info.start, access)
+                else {
+                    return;
+                };
+                self.push_code(LuaByteCode::SetTable(table, index, value));
+                self.reg_mgr.free(&index);
+                self.reg_mgr.free(&table);
+            }
+            _ => self.report_invalid_expression(target, "invalid assignment target"),
+        }
+    }
+
+    /// Evaluate array table and element index of the flattened table into registers.
+    /// Indexes are checked against array bounds, Lua error is raised if out of range.
+    /// None if bounds of array are not constant, the error is reported.
+    fn code_array_element(
+        &mut self,
+        location: Option<Location>,
+        access: &mut ArrayAccessExpression,
+    ) -> Option<(Reg, Reg)> {
+        let dims = self
+            .expression_type(access.array())
+            .and_then(|ty| ty.as_array().and_then(|x| array_dimensions(x, &self.app)));
+        let Some(dims) = dims else {
+            self.report_invalid_expression(access, "array bounds must be constant");
+            return None;
+        };
+        let message = match location {
+            Some(loc) => format!(
+                "array index out of range at line {}, column {}: {}",
+                loc.mark + 1,
+                loc.offset + 1,
+                access
+            ),
+            None => format!("array index out of range: {}", access),
+        };
+
+        let table = self.code_expression_register(access.array_mut());
+        let offset = self.reg_mgr.alloc_hard();
+        let out_of_range = self.create_label("index-out-of-range");
+        for (idx, (index, (lower, len))) in access.indexes_mut().iter_mut().zip(dims).enumerate() {
+            let r = self.code_expression_register(index);
+            let off = if idx == 0 {
+                offset
+            } else {
+                self.reg_mgr.alloc_hard()
+            };
+            let k_lower = self.add_integer_constant(lower);
+            self.code_arith_k(Operator::Minus, off, r, k_lower);
+            self.reg_mgr.free(&r);
+
+            // jump to error if off < 0 or len <= off
+            let len_reg = self.reg_mgr.alloc_hard();
+            let k_len = self.add_integer_constant(len);
+            self.push_code(LuaByteCode::Lti(off, 0, true, false));
+            self.code_jmp(out_of_range.clone());
+            self.code_load_constant(len_reg, k_len);
+            self.push_code(LuaByteCode::Le(len_reg, off, true));
+            self.code_jmp(out_of_range.clone());
+            self.reg_mgr.free(&len_reg);
+
+            // row-major order, offset := offset * len + off
+            if idx > 0 {
+                self.code_arith_k(Operator::Multiply, offset, offset, k_len);
+                self.code_arith(Operator::Plus, offset, offset, off, true);
+                self.reg_mgr.free(&off);
+            }
+        }
+
+        // index of Lua table starts from 1
+        self.push_code(LuaByteCode::AddI(offset, offset, 1));
+        self.push_code(LuaByteCode::MMBinI(offset, 1, LuaTMS::TM_ADD, false));
+
+        let in_range = self.create_label("index-in-range");
+        self.code_jmp(in_range.clone());
+        self.insert_label(out_of_range);
+        self.code_error(&message);
+        self.insert_label(in_range);
+
+        Some((table, offset))
+    }
+
+    /// Raise Lua error, position information is not added to message
+    fn code_error(&mut self, message: &str) {
+        let regs = self.reg_mgr.alloc_hard_batch(2);
+        let k_error = self.add_string_constant("error");
+        let k_message = self.add_string_constant(message);
+        self.code_gettabup(regs[0], k_error);
+        self.code_load_constant(regs[1], k_message);
+        self.push_code(LuaByteCode::LoadI(regs[2], 0));
+        self.push_code(LuaByteCode::Call(regs[0], 3, 1));
+
+        for r in regs {
+            self.reg_mgr.free(&r);
+        }
+    }
+
+    /// Evaluate value which will be stored into variable of type `lhs_class`,
+    /// value need to be wrapped into the range of lhs type
+    fn code_value_for(&mut self, expr: &mut Expression, lhs_class: Option<TypeClass>) -> RK {
+        let rhs_class = self.expression_class(expr);
+
+        self.push_access_attribute(LuaAccessMode::LoadNewRegister);
+        self.visit_expression_mut(expr);
+        let rhs = self.pop_attribute();
+
+        let wrap_class = lhs_class
+            .filter(|x| rhs_class != Some(*x))
+            .filter(|x| matches!(integer_width(*x), Some((bits, _)) if bits < 64));
+        // REAL arithmetic is computed in double precision, rounded when stored
+        let wrap_class = wrap_class.or(lhs_class.filter(|x| *x == TypeClass::Real));
+
+        match rhs.rk() {
+            RK::K(constant_index) => {
+                assert_eq!(rhs.registers.len(), 0);
+
+                match (wrap_class, &self.constants[constant_index as usize]) {
+                    (Some(TypeClass::Real), LuaConstants::Integer(i)) => {
+                        RK::K(self.add_float_constant(*i as f32 as f64))
+                    }
+                    (Some(TypeClass::Real), LuaConstants::Float(f)) => {
+                        RK::K(self.add_float_constant(*f as f32 as f64))
+                    }
+                    (Some(class), LuaConstants::Integer(i)) => {
+                        RK::K(self.add_integer_constant(wrap_integer(*i, class)))
+                    }
+                    _ => RK::K(constant_index),
+                }
+            }
+            RK::R(value) => {
+                assert_eq!(1, rhs.registers.len());
+
+                match wrap_class {
+                    Some(class) => {
+                        let wrapped = self.reg_mgr.alloc_hard();
+                        self.code_wrap(wrapped, value, class);
+                        self.reg_mgr.free(&value);
+                        RK::R(wrapped)
+                    }
+                    None => RK::R(value),
+                }
+            }
+        }
+    }
+
+    /// Call function block instance: inputs are stored into instance, then call body of
+    /// function block class with instance, outputs are read from instance after call.
+    fn code_fb_call(&mut self, call: &mut CallExpression, fb: Prototype) {
+        let (fb_name, inputs) = {
+            let fb = fb.read().unwrap();
+            let inputs: Vec<_> = fb
+                .variables()
+                .iter()
+                .filter(|x| x.flags().contains(VariableFlags::INPUT))
+                .cloned()
+                .collect();
+            (fb.name().clone(), inputs)
+        };
+        let members: Vec<_> = fb.read().unwrap().variables().to_vec();
+        let member_class = |name: &StString| {
+            members
+                .iter()
+                .find(|x| x.name() == name)
+                .and_then(|x| x.ty().map(|ty| ty.type_class()))
+        };
+
+        let inst = self.code_expression_register(call.callee_mut());
+
+        // inputs
+        let mut positional = 0;
+        for arg in call.arguments_mut().iter_mut() {
+            let name = match &arg.kind {
+                ExprKind::Assign(assign) => match assign.assign_type() {
+                    AssignType::AssignRight => continue,
+                    _ => expression_variable_name(assign.left()).cloned(),
+                },
+                _ => {
+                    positional += 1;
+                    inputs.get(positional - 1).map(|x| x.name().clone())
+                }
+            };
+            // TODO: unknown parameter error
+            let Some(name) = name else { continue };
+
+            let value = self.code_value_for(argument_value_mut(arg), member_class(&name));
+            let k = self.add_string_constant(&name);
+            self.push_code(LuaByteCode::SetField(inst, k, value));
+            if let RK::R(r) = value {
+                self.reg_mgr.free(&r);
+            }
+        }
+
+        // call body
+        let regs = self.reg_mgr.alloc_hard_batch(1);
+        let k_class = self.add_string_constant(&fb_name);
+        let k_body = self.add_string_constant("body");
+        self.code_gettabup(regs[0], k_class);
+        self.push_code(LuaByteCode::GetField(regs[0], regs[0], k_body));
+        self.code_move(inst, regs[1]);
+        self.push_code(LuaByteCode::Call(regs[0], 2, 1));
+        for r in regs {
+            self.reg_mgr.free(&r);
+        }
+
+        // outputs
+        for arg in call.arguments_mut().iter_mut() {
+            let ExprKind::Assign(assign) = &mut arg.kind else {
+                continue;
+            };
+            if !matches!(assign.assign_type(), AssignType::AssignRight) {
+                continue;
+            }
+            let Some(name) = expression_variable_name(assign.left()).cloned() else {
+                continue;
+            };
+
+            let r = self.reg_mgr.alloc_hard();
+            let k = self.add_string_constant(&name);
+            self.push_code(LuaByteCode::GetField(r, inst, k));
+            self.code_store(assign.right_mut(), RK::R(r));
+            self.reg_mgr.free(&r);
+        }
+
+        self.reg_mgr.free(&inst);
+    }
+
+    /// Call of standard function, None if callee is declared in application or isn't a
+    /// standard function
+    fn standard_call(
+        &mut self,
+        call: &CallExpression,
+    ) -> Option<Result<StandardCall, GenericError>> {
+        let name = expression_variable_name(call.callee())?;
+        if self.app.read().find_declaration_by_name(name).is_some() {
+            return None;
+        }
+
+        let builtin = self.mgr.read().builtin_context();
+        let decl = builtin.read().find_declaration_by_name(name).cloned()?;
+        if pou_kind(decl.read().unwrap().decl()) != Some(PouKind::Function) {
+            return None;
+        }
+
+        let arguments: Vec<_> = call
+            .arguments()
+            .iter()
+            .map(|arg| self.expression_class(argument_value(arg)))
+            .collect();
+        Some(StandardCall::new(&decl, call, &arguments))
+    }
+
+    /// Evaluate argument of standard function into a new register, input not connected is
+    /// the default value of `class`
+    fn code_standard_argument(
+        &mut self,
+        call: &mut CallExpression,
+        arg: Option<usize>,
+        class: TypeClass,
+    ) -> Reg {
+        let Some(idx) = arg else {
+            let r = self.reg_mgr.alloc_hard();
+            match class {
+                TypeClass::Bool => self.push_code(LuaByteCode::LoadFalse(r)),
+                TypeClass::String => {
+                    let k = self.add_string_constant("");
+                    self.code_load_constant(r, k);
+                }
+                _ if is_float_type(class) => self.push_code(LuaByteCode::LoadF(r, 0)),
+                _ => self.push_code(LuaByteCode::LoadI(r, 0)),
+            }
+            return r;
+        };
+
+        let value = argument_value_mut(&mut call.arguments_mut()[idx]);
+        match self.code_value_for(value, Some(class)) {
+            RK::R(r) => r,
+            RK::K(k) => {
+                let r = self.reg_mgr.alloc_hard();
+                self.code_load_constant(r, k);
+                r
+            }
+        }
+    }
+
+    /// R[dst] := lib.function(args...)
+    fn code_library_call(&mut self, dst: Reg, lib: &str, function: &str, args: &[Reg]) {
+        let regs = self.reg_mgr.alloc_hard_batch(args.len());
+        let k_lib = self.add_string_constant(lib);
+        let k_function = self.add_string_constant(function);
+        self.code_gettabup(regs[0], k_lib);
+        self.push_code(LuaByteCode::GetField(regs[0], regs[0], k_function));
+        for (arg, r) in args.iter().zip(&regs[1..]) {
+            self.code_move(*arg, *r);
+        }
+        self.push_code(LuaByteCode::Call(regs[0], args.len() as u8 + 1, 2));
+        self.code_move(regs[0], dst);
+
+        for r in regs {
+            self.reg_mgr.free(&r);
+        }
+    }
+
+    /// R[dst] := R[op0] .. R[op1] .. ...
+    fn code_concat(&mut self, dst: Reg, args: &[Reg]) {
+        let regs = self.reg_mgr.alloc_hard_batch(args.len() - 1);
+        for (arg, r) in args.iter().zip(&regs) {
+            self.code_move(*arg, *r);
+        }
+        self.push_code(LuaByteCode::Concat(regs[0], args.len() as u8));
+        self.code_move(regs[0], dst);
+
+        for r in regs {
+            self.reg_mgr.free(&r);
+        }
+    }
+
+    /// Copy of integer in R[src] which is at least `min`, argument registers may be
+    /// registers of variables and can't be modified
+    fn code_clamped(&mut self, src: Reg, min: i8) -> Reg {
+        let r = self.reg_mgr.alloc_hard();
+        self.code_move(src, r);
+        self.push_code(LuaByteCode::Gei(r, min, true, false));
+        self.code_jmp_fixed(1);
+        self.push_code(LuaByteCode::LoadI(r, min as i32));
+
+        r
+    }
+
+    /// R[dst] := R[src] + imm
+    fn code_add_immediate(&mut self, dst: Reg, src: Reg, imm: i8) {
+        self.push_code(LuaByteCode::AddI(dst, src, imm));
+        self.push_code(LuaByteCode::MMBinI(src, imm, LuaTMS::TM_ADD, false));
+    }
+
+    /// Round float in R[src] to integer value, `floor(x + offset)` for positive values
+    /// and `ceil(x - offset)` for negative values
+    fn code_round(&mut self, dst: Reg, src: Reg, offset: f64) {
+        let tmp = self.reg_mgr.alloc_hard();
+        let k_offset = self.add_float_constant(offset);
+        let negative = self.create_label("round-negative");
+        let exit = self.create_label("round-exit");
+
+        self.push_code(LuaByteCode::Lti(src, 0, true, false));
+        self.code_jmp(negative.clone());
+        self.code_arith_k(Operator::Plus, tmp, src, k_offset);
+        self.code_library_call(dst, "math", "floor", &[tmp]);
+        self.code_jmp(exit.clone());
+        self.insert_label(negative);
+        self.code_arith_k(Operator::Minus, tmp, src, k_offset);
+        self.code_library_call(dst, "math", "ceil", &[tmp]);
+        self.insert_label(exit);
+
+        self.reg_mgr.free(&tmp);
+    }
+
+    /// R[dst] := R[value] if R[op0] < R[op1], unsigned 64 bits integers are compared with
+    /// sign bit flipped
+    fn code_move_if_less(&mut self, op0: Reg, op1: Reg, value: Reg, dst: Reg, class: TypeClass) {
+        if integer_width(class) == Some((64, false)) {
+            let (op0, op1) = (self.code_flip_sign(op0), self.code_flip_sign(op1));
+            self.push_code(LuaByteCode::Lt(op0, op1, false));
+            self.reg_mgr.free(&op0);
+            self.reg_mgr.free(&op1);
+        } else {
+            self.push_code(LuaByteCode::Lt(op0, op1, false));
+        }
+        self.code_jmp_fixed(1);
+        self.code_move(value, dst);
+    }
+
+    /// R[dst] := R[op0] << R[op1] or R[op0] >> R[op1], shifts are logical
+    fn code_shift(&mut self, left: bool, dst: Reg, op0: Reg, op1: Reg) {
+        if left {
+            self.push_code(LuaByteCode::Shl(dst, op0, op1));
+            self.push_code(LuaByteCode::MMBin(op0, op1, LuaTMS::TM_SHL));
+        } else {
+            self.push_code(LuaByteCode::Shr(dst, op0, op1));
+            self.push_code(LuaByteCode::MMBin(op0, op1, LuaTMS::TM_SHR));
+        }
+    }
+
+    /// Type conversion like `INT_TO_REAL`, floats are rounded to integers
+    fn code_conversion(&mut self, dst: Reg, src: Reg, from: TypeClass, to: TypeClass) {
+        match (from, to) {
+            _ if from == to => self.code_move(src, dst),
+            (_, TypeClass::Bool) => {
+                let zero = self.reg_mgr.alloc_hard();
+                self.push_code(LuaByteCode::LoadI(zero, 0));
+                self.code_compare(Operator::NotEqual, dst, src, zero);
+                self.reg_mgr.free(&zero);
+            }
+            (TypeClass::Bool, _) => {
+                self.push_code(LuaByteCode::Test(src, false));
+                self.code_jmp_fixed(2);
+                self.push_code(LuaByteCode::LoadI(dst, 1));
+                self.code_jmp_fixed(1);
+                self.push_code(LuaByteCode::LoadI(dst, 0));
+                if is_float_type(to) {
+                    let k_zero = self.add_float_constant(0.0);
+                    self.code_arith_k(Operator::Plus, dst, dst, k_zero);
+                }
+            }
+            _ if is_float_type(to) => {
+                if is_float_type(from) {
+                    self.code_move(src, dst);
+                } else {
+                    let k_zero = self.add_float_constant(0.0);
+                    self.code_arith_k(Operator::Plus, dst, src, k_zero);
+                }
+                self.code_wrap(dst, dst, to);
+            }
+            _ if is_float_type(from) => {
+                self.code_round(dst, src, 0.5);
+                self.code_wrap(dst, dst, to);
+            }
+            _ => self.code_wrap(dst, src, to),
+        }
+    }
+
+    /// Lower standard function into R[dst], false if it isn't supported. Conversions of
+    /// STRING are not supported, BOOL can't be shifted or ordered.
+    fn code_standard_call(
+        &mut self,
+        call: &mut CallExpression,
+        standard: &StandardCall,
+        dst: Reg,
+    ) -> bool {
+        let class = standard.return_class;
+        let name = standard.name.as_str();
+        let conversion = standard.conversion();
+        let supported = match name {
+            _ if conversion.is_some() => {
+                !matches!(conversion, Some((TypeClass::String, _) | (_, TypeClass::String)))
+            }
+            "SHL" | "SHR" | "ROL" | "ROR" | "MAX" | "MIN" | "LIMIT" => class != TypeClass::Bool,
+            // index of input is immediate operand of EQI
+            "MUX" => standard.arguments.len() <= i8::MAX as usize + 1,
+            "ABS" | "SQRT" | "LN" | "LOG" | "EXP" | "SIN" | "COS" | "TAN" | "ASIN" | "ACOS"
+            | "ATAN" | "EXPT" | "TRUNC" | "SEL" | "LEN" | "LEFT" | "RIGHT" | "MID" | "CONCAT"
+            | "INSERT" | "DELETE" | "REPLACE" | "FIND" | "STC_NOW" => true,
+            _ => false,
+        };
+        if !supported {
+            return false;
+        }
+
+        let mut args: SmallVec8<Reg> = smallvec![];
+        for (arg, arg_class) in &standard.arguments {
+            let r = self.code_standard_argument(call, *arg, *arg_class);
+            args.push(r);
+        }
+        // temporary registers
+        let mut tmps: SmallVec8<Reg> = smallvec![];
+
+        match name {
+            _ if conversion.is_some() => {
+                let (from, to) = conversion.unwrap();
+                self.code_conversion(dst, args[0], from, to);
+            }
+            "ABS" if matches!(integer_width(class), Some((_, false))) => {
+                self.code_move(args[0], dst)
+            }
+            "ABS" => {
+                self.code_library_call(dst, "math", "abs", &args);
+                self.code_wrap(dst, dst, class);
+            }
+            "SQRT" | "LN" | "EXP" | "SIN" | "COS" | "TAN" | "ASIN" | "ACOS" | "ATAN" => {
+                let function = match name {
+                    "LN" => "log".to_owned(),
+                    _ => name.to_ascii_lowercase(),
+                };
+                self.code_library_call(dst, "math", &function, &args);
+                self.code_wrap(dst, dst, class);
+            }
+            "LOG" => {
+                let base = self.reg_mgr.alloc_hard();
+                self.push_code(LuaByteCode::LoadI(base, 10));
+                self.code_library_call(dst, "math", "log", &[args[0], base]);
+                self.code_wrap(dst, dst, class);
+                tmps.push(base);
+            }
+            "EXPT" => {
+                self.code_arith(Operator::Power, dst, args[0], args[1], false);
+                self.code_wrap(dst, dst, class);
+            }
+            "TRUNC" => {
+                self.code_round(dst, args[0], 0.0);
+                self.code_wrap(dst, dst, class);
+            }
+            "SHL" | "SHR" | "ROL" | "ROR" => {
+                let bits = integer_width(class).map_or(64, |(bits, _)| bits);
+                let (value, n) = (args[0], args[1]);
+                // bits shifted in from the left are zero
+                let unsigned = self.reg_mgr.alloc_hard();
+                let k_mask = self.add_integer_constant(if bits == 64 {
+                    -1
+                } else {
+                    (1i64 << bits) - 1
+                });
+                self.code_arith_k(Operator::BitAnd, unsigned, value, k_mask);
+                tmps.push(unsigned);
+
+                match name {
+                    "SHL" | "SHR" => {
+                        let n = self.code_clamped(n, 0);
+                        self.code_shift(name == "SHL", dst, unsigned, n);
+                        tmps.push(n);
+                    }
+                    _ => {
+                        let count = self.reg_mgr.alloc_hard();
+                        let rest = self.reg_mgr.alloc_hard();
+                        let width = self.reg_mgr.alloc_hard();
+                        self.push_code(LuaByteCode::LoadI(width, bits as i32));
+                        self.code_arith(Operator::Mod, count, n, width, true);
+                        if name == "ROR" {
+                            self.code_arith(Operator::Minus, count, width, count, true);
+                            self.code_arith(Operator::Mod, count, count, width, true);
+                        }
+                        self.code_arith(Operator::Minus, rest, width, count, true);
+                        self.code_shift(true, dst, unsigned, count);
+                        self.code_shift(false, rest, unsigned, rest);
+                        self.code_arith(Operator::BitOr, dst, dst, rest, true);
+                        tmps.extend([count, rest, width]);
+                    }
+                }
+                self.code_wrap(dst, dst, class);
+            }
+            "SEL" => {
+                self.code_move(args[1], dst);
+                self.push_code(LuaByteCode::Test(args[0], false));
+                self.code_jmp_fixed(1);
+                self.code_move(args[2], dst);
+            }
+            "MAX" | "MIN" => {
+                self.code_move(args[0], dst);
+                for x in args.iter().skip(1) {
+                    if name == "MAX" {
+                        self.code_move_if_less(dst, *x, *x, dst, class);
+                    } else {
+                        self.code_move_if_less(*x, dst, *x, dst, class);
+                    }
+                }
+            }
+            "LIMIT" => {
+                let (mn, value, mx) = (args[0], args[1], args[2]);
+                self.code_move(value, dst);
+                self.code_move_if_less(dst, mn, mn, dst, class);
+                self.code_move_if_less(mx, dst, mx, dst, class);
+            }
+            // all inputs are evaluated, K selects one of them
+            "MUX" => {
+                let k = args[0];
+                let out_of_range = self.create_label("mux-out-of-range");
+                let exit = self.create_label("mux-exit");
+
+                self.push_code(LuaByteCode::Lti(k, 0, true, false));
+                self.code_jmp(out_of_range.clone());
+                for (i, input) in args.iter().skip(1).enumerate() {
+                    self.push_code(LuaByteCode::EQI(k, i as i8, false, false));
+                    self.code_jmp_fixed(1);
+                    self.code_move(*input, dst);
+                }
+                let count = self.reg_mgr.alloc_hard();
+                self.push_code(LuaByteCode::LoadI(count, args.len() as i32 - 1));
+                self.push_code(LuaByteCode::Lt(k, count, true));
+                self.code_jmp(exit.clone());
+                self.insert_label(out_of_range);
+                self.code_error("MUX K out of range");
+                self.insert_label(exit);
+                tmps.push(count);
+            }
+            "LEN" => self.push_code(LuaByteCode::Len(dst, args[0])),
+            "LEFT" | "MID" => {
+                let len = self.code_clamped(args[1], 0);
+                let start = match name {
+                    "LEFT" => {
+                        let r = self.reg_mgr.alloc_hard();
+                        self.push_code(LuaByteCode::LoadI(r, 1));
+                        r
+                    }
+                    _ => self.code_clamped(args[2], 1),
+                };
+                // end position is inclusive
+                let end = self.reg_mgr.alloc_hard();
+                self.code_arith(Operator::Plus, end, start, len, true);
+                self.code_add_immediate(end, end, -1);
+                self.code_library_call(dst, "string", "sub", &[args[0], start, end]);
+                tmps.extend([len, start, end]);
+            }
+            "RIGHT" => {
+                let len = self.code_clamped(args[1], 0);
+                let start = self.reg_mgr.alloc_hard();
+                self.push_code(LuaByteCode::Len(start, args[0]));
+                self.code_arith(Operator::Minus, start, start, len, true);
+                self.code_add_immediate(start, start, 1);
+                let start_clamped = self.code_clamped(start, 1);
+                self.code_library_call(dst, "string", "sub", &[args[0], start_clamped]);
+                tmps.extend([len, start, start_clamped]);
+            }
+            "CONCAT" => self.code_concat(dst, &args),
+            "INSERT" | "DELETE" | "REPLACE" => {
+                // IN1[..P] .. IN2 .. IN1[P + L..]
+                let (s, with, len, p) = match name {
+                    "INSERT" => (args[0], Some(args[1]), None, args[2]),
+                    "DELETE" => (args[0], None, Some(args[1]), args[2]),
+                    _ => (args[0], Some(args[1]), Some(args[2]), args[3]),
+                };
+                let head_end = match len {
+                    Some(_) => {
+                        let p = self.code_clamped(p, 1);
+                        self.code_add_immediate(p, p, -1);
+                        p
+                    }
+                    None => self.code_clamped(p, 0),
+                };
+                let tail_start = self.reg_mgr.alloc_hard();
+                match len {
+                    Some(len) => {
+                        let len = self.code_clamped(len, 0);
+                        self.code_arith(Operator::Plus, tail_start, head_end, len, true);
+                        tmps.push(len);
+                    }
+                    None => self.code_move(head_end, tail_start),
+                }
+                self.code_add_immediate(tail_start, tail_start, 1);
+
+                let one = self.reg_mgr.alloc_hard();
+                let head = self.reg_mgr.alloc_hard();
+                let tail = self.reg_mgr.alloc_hard();
+                self.push_code(LuaByteCode::LoadI(one, 1));
+                self.code_library_call(head, "string", "sub", &[s, one, head_end]);
+                self.code_library_call(tail, "string", "sub", &[s, tail_start]);
+                match with {
+                    Some(with) => self.code_concat(dst, &[head, with, tail]),
+                    None => self.code_concat(dst, &[head, tail]),
+                }
+                tmps.extend([head_end, tail_start, one, head, tail]);
+            }
+            "FIND" => {
+                let init = self.reg_mgr.alloc_hard();
+                let plain = self.reg_mgr.alloc_hard();
+                self.push_code(LuaByteCode::LoadI(init, 1));
+                self.push_code(LuaByteCode::LoadTrue(plain));
+                self.code_library_call(dst, "string", "find", &[args[0], args[1], init, plain]);
+                // nil if not found
+                self.push_code(LuaByteCode::Test(dst, true));
+                self.code_jmp_fixed(1);
+                self.push_code(LuaByteCode::LoadI(dst, 0));
+                tmps.extend([init, plain]);
+            }
+            // current time of timers is read from global function `stc_now` of runtime
+            "STC_NOW" => {
+                let regs = self.reg_mgr.alloc_hard_batch(0);
+                let k_now = self.add_string_constant("stc_now");
+                self.code_gettabup(regs[0], k_now);
+                self.push_code(LuaByteCode::Call(regs[0], 1, 2));
+                self.code_move(regs[0], dst);
+                tmps.extend(regs);
+            }
+            _ => unreachable!("{}", name),
+        }
+
+        for r in args.into_iter().chain(tmps) {
+            self.reg_mgr.free(&r);
+        }
+        true
+    }
+
     #[inline]
     fn add_string_constant<S: AsRef<str>>(&mut self, s: S) -> ConstIdx {
-        let constant = LuaConstants::String(s.as_ref().to_owned());
+        let constant = LuaConstants::String(s.as_ref().as_bytes().to_vec());
         let (idx, _inserted) = self.constants.insert_full(constant);
         idx as ConstIdx
     }
@@ -324,6 +1795,13 @@ impl LuaBackend {
         idx as ConstIdx
     }
 
+    #[inline]
+    fn add_boolean_constant(&mut self, b: bool) -> ConstIdx {
+        let constant = LuaConstants::Boolean(b);
+        let (idx, _inserted) = self.constants.insert_full(constant);
+        idx as ConstIdx
+    }
+
     #[inline]
     fn add_float_constant(&mut self, f: f64) -> ConstIdx {
         let constant = LuaConstants::Float(f);
@@ -344,9 +1822,16 @@ impl CodeGenBackend for LuaBackend {
             local_function: None,
             local_proto: None,
             constants: IndexSet::new(),
-            upvalue_table: IndexMap::new(),
+            upvalue_table: vec![],
             reg_mgr: RegisterManager::new(),
             labels: smallvec![],
+            protos: vec![],
+            self_register: None,
+            current_line: 0,
+            line_info: vec![],
+            optimize_level: OptimizeLevel::None,
+            error: None,
+            entry: StString::from("main"),
         }
     }
 
@@ -359,65 +1844,54 @@ impl CodeGenBackend for LuaBackend {
         let p = app.get_declaration_by_id(func).cloned();
 
         self.local_function = Some(f.clone());
-        self.local_proto = p;
+        self.local_proto = p.clone();
+        self.error = None;
         drop(app);
 
         let app_id = self.app.read().id();
         let fun_scope = Scope::new(Some(self.mgr.clone()), Some(app_id), Some(func));
 
-        // generate VarArgPrep
-        if let Some(p) = &self.local_proto {
-            if is_vararg(p) {
-                self.push_code(LuaByteCode::VarArgPrep(0));
-            }
-        }
-
-        // create upvalue table
-        // self.upvalues.push(LuaUpValue {
-        //     name: None,
-        //     stack: 1,
-        //     index: 0,
-        //     kind: 0,
-        // });
-        self.upvalue_table.insert(
-            StString::empty(),
-            LuaUpValue {
-                stack: 1,
-                index: 0,
-                kind: LuaVarKind::VDKREG,
-            },
-        );
+        // each program is an entry, the main chunk of module runs the entry program
+        let kind = p.as_ref().and_then(|x| pou_kind(x.read().unwrap().decl()));
+        let (params, vararg) = p
+            .as_ref()
+            .map(|x| (num_params(x), is_vararg(x)))
+            .unwrap_or((0, false));
 
-        let mut fun = f.write();
         self.push_attribute_with_scope(fun_scope);
-        self.visit_statement_mut(fun.parse_tree_mut());
-        self.pop_attribute();
+        let mut code = match (kind, p.as_ref()) {
+            (Some(PouKind::FunctionBlock), _) => self.gen_function_block_class(&f),
+            (Some(PouKind::Function), Some(p)) => self.gen_function_frame(&f, p),
+            _ => self.gen_proto(params, vararg, |this| {
+                // generate VarArgPrep
+                if vararg {
+                    this.push_code(LuaByteCode::VarArgPrep(params));
+                }
 
-        // generate return
-        self.push_code(LuaByteCode::Return(0, 1, 1));
+                if kind == Some(PouKind::Program) {
+                    this.code_entry_prologue();
+                }
 
-        // jmp relocate
-        let labels = mem::take(&mut self.labels);
-        for label in labels {
-            let label_offset = label.borrow().inst_index.unwrap();
-            for inst_offset in &label.borrow().fixup_instructions {
-                let fix_offset = label_offset as i32 - (*inst_offset as i32) - 1;
-                self.code_fixup_jmp(*inst_offset, fix_offset);
-            }
-        }
+                let mut fun = f.write();
+                this.visit_statement_mut(fun.parse_tree_mut());
 
-        let byte_codes = mem::take(&mut self.byte_codes);
-        let constants = mem::replace(&mut self.constants, IndexSet::new());
-        let upvalues = mem::replace(&mut self.upvalue_table, IndexMap::new());
+                // generate return
+                let nparams1 = if vararg { params + 1 } else { 0 };
+                this.push_code(LuaByteCode::Return(0, 1, nparams1, false));
+            }),
+        };
+        self.pop_attribute();
 
-        // reset RegMan and check register is balance
-        assert!(self.reg_mgr.check_and_reset());
+        if let Some(e) = self.error.take() {
+            return Err(e);
+        }
+
+        // POU is named by POU name
+        code.source = p.map(|x| format!("={}", x.read().unwrap().name()));
+        code.line_defined = 0;
+        code.last_line_defined = 0;
 
-        Ok(Box::new(LuaCompiledCode {
-            byte_codes,
-            constants,
-            upvalues,
-        }))
+        Ok(Box::new(code))
     }
 
     /// create a new label
@@ -452,9 +1926,39 @@ impl CodeGenBackend for LuaBackend {
     fn get_module_bytes(&mut self, w: &mut dyn Write) -> io::Result<()> {
         lua_dump_module(self, w)
     }
+
+    fn set_optimize_level(&mut self, level: OptimizeLevel) {
+        self.optimize_level = level;
+    }
+
+    fn save_function(&self, code: &dyn CompiledCode) -> Option<Vec<u8>> {
+        let code = code.as_any().downcast_ref::<LuaCompiledCode>()?;
+        let mut buf = vec![];
+        lua_dump_chunk(&mut buf, code).ok()?;
+
+        Some(buf)
+    }
+
+    fn load_function(&self, mut bytes: &[u8]) -> Option<Box<dyn CompiledCode>> {
+        let code = lua_undump(&mut bytes).ok()?;
+        Some(Box::new(code))
+    }
 }
 
 impl AstVisitorMut for LuaBackend {
+    fn visit_statement_mut(&mut self, stmt: &mut Statement) {
+        // instructions are mapped to the first line of statement, Location line starts from 0
+        if let Some(start) = stmt.info.start_pos {
+            self.current_line = start.mark as u32 + 1;
+        }
+
+        match stmt.kind {
+            StmtKind::Expr(ref mut expr) => self.visit_expr_statement_mut(expr),
+            StmtKind::If(ref mut ifst) => self.visit_if_statement_mut(&mut stmt.info, ifst),
+            StmtKind::Stmts(ref mut v) => self.visit_statement_list_mut(v),
+        }
+    }
+
     fn visit_literal_mut(&mut self, literal: &mut LiteralExpression) {
         trace!("LuaGen: literal expression: {:?}", literal);
 
@@ -505,20 +2009,19 @@ impl AstVisitorMut for LuaBackend {
             }
             LuaAccessMode::WriteRegister => {
                 let dst = self.top_attribute().registers[0];
-                let constant_index = self.add_string_constant(var_expr.name());
-                self.code_gettabup(dst, constant_index);
+                self.code_load_variable(dst, var_expr.name());
             }
             // Write register into stack
             LuaAccessMode::Write => {}
             // Load into register
             LuaAccessMode::LoadNewRegister => {
-                let scope = self.top_attribute().scope.as_ref().unwrap();
-                if let Some(variable) = scope.find_variable(var_expr.name()) {
-                    let const_idx = self.add_string_constant(var_expr.name());
+                if let Some(variable) = var {
                     let reg = self.reg_mgr.alloc_local_variable(variable.name());
 
-                    self.code_gettabup(reg, const_idx);
+                    self.code_load_variable(reg, var_expr.name());
                     self.top_attribute().registers = smallvec![reg];
+                } else if let Some(value) = self.enum_member_value(None, var_expr.name()) {
+                    self.top_attribute().const_idx = Some(self.add_integer_constant(value));
                 } else {
                     // TODO: variable not found error
                 }
@@ -536,6 +2039,38 @@ impl AstVisitorMut for LuaBackend {
     fn visit_call_expression_mut(&mut self, call: &mut CallExpression) {
         trace!("LuaGen: call expression: {}", call);
 
+        // function block instance call
+        if let Some(fb) = self.expression_user_decl(call.callee()) {
+            if fb.read().unwrap().is_function_block() {
+                return self.code_fb_call(call, fb);
+            }
+        }
+
+        if let Some(standard) = self.standard_call(call) {
+            let dst = match self.top_attribute().registers.first() {
+                Some(r) => *r,
+                None => self.reg_mgr.alloc_hard(),
+            };
+            let supported = match standard {
+                Ok(standard) => self.code_standard_call(call, &standard, dst),
+                Err(e) => {
+                    self.report_invalid_expression(call, &e.to_string());
+                    true
+                }
+            };
+            if !supported {
+                self.report_invalid_expression(call, "standard function not supported");
+            }
+
+            // result of call statement is discarded
+            if matches!(self.top_attribute().access_mode, LuaAccessMode::None) {
+                self.reg_mgr.free(&dst);
+            } else {
+                self.top_attribute().registers = smallvec![dst];
+            }
+            return;
+        }
+
         let args_count = call.arguments().len();
         self.push_access_attribute(LuaAccessMode::Call(args_count));
         self.visit_expression_mut(call.callee_mut());
@@ -552,122 +2087,248 @@ impl AstVisitorMut for LuaBackend {
 
         // visit all arguments
         for (idx, arg) in call.arguments_mut().iter_mut().enumerate() {
-            self.push_access_attribute(LuaAccessMode::WriteRegister);
-            self.top_attribute().registers = smallvec![arg_regs[idx + 1]];
+            self.push_access_attribute(LuaAccessMode::LoadNewRegister);
             self.visit_expression_mut(arg);
-            self.pop_attribute();
+            let rk = self.pop_attribute().rk();
+
+            self.code_load(arg_regs[idx + 1], rk);
+            if let RK::R(r) = rk {
+                self.reg_mgr.free(&r);
+            }
         }
 
-        // free registers
-        for r in arg_regs {
-            self.reg_mgr.free(&r);
+        // free registers of arguments, result is in callee_reg
+        for r in &arg_regs[1..] {
+            self.reg_mgr.free(r);
         }
 
         self.push_code(LuaByteCode::Call(
             callee_reg,
             call.arguments().len() as u8 + 1,
-            1,
+            2,
         ));
 
-        self.reg_mgr.free(&callee_reg);
+        // result of call statement is discarded
+        if matches!(self.top_attribute().access_mode, LuaAccessMode::None) {
+            self.reg_mgr.free(&callee_reg);
+        } else {
+            self.top_attribute().registers = smallvec![callee_reg];
+        }
     }
 
     fn visit_if_statement_mut(&mut self, _: &mut StmtInfo, ifst: &mut IfStatement) {
         trace!("LuaGen: if statement: {}", ifst.condition());
 
         let if_exit_label = self.create_label("if-exit");
+        let has_else = ifst.else_controlled().is_some() || !ifst.else_if_list().is_empty();
 
-        self.push_exit_label(if_exit_label.clone());
-        self.visit_expression_mut(ifst.condition_mut());
-        let attr = self.pop_attribute();
-
+        let next_label = self.create_label("if-next");
+        self.code_condition_jump(ifst.condition_mut(), next_label.clone());
         if let Some(then_ctrl) = ifst.then_controlled_mut() {
             self.visit_statement_mut(then_ctrl);
         }
+        if has_else {
+            self.code_jmp(if_exit_label.clone());
+        }
+        self.insert_label(next_label);
+
+        for else_if in ifst.else_if_list_mut() {
+            let next_label = self.create_label("elseif-next");
+            self.code_condition_jump(else_if.condition_mut(), next_label.clone());
+            if let Some(then_ctrl) = else_if.then_controlled_mut() {
+                self.visit_statement_mut(then_ctrl);
+            }
+            self.code_jmp(if_exit_label.clone());
+            self.insert_label(next_label);
+        }
+
+        if let Some(else_ctrl) = ifst.else_controlled_mut() {
+            self.visit_statement_mut(else_ctrl);
+        }
 
         self.insert_label(if_exit_label);
-        self.reg_mgr.free(&attr.registers[0]);
     }
 
     fn visit_operator_expression_mut(&mut self, operator: &mut OperatorExpression) {
         trace!("LuaGen: operator expression: {}", operator);
 
         let op = *operator.op();
+        let operand_class = {
+            let operands = operator.operands();
+            let class = self.expression_class(&operands[0]);
+            operands
+                .iter()
+                .skip(1)
+                .fold(class, |class, x| wider_class(class, self.expression_class(x)))
+        };
+        let is_float = operand_class.map(is_float_type).unwrap_or(false);
+        let is_bool = matches!(operand_class, Some(TypeClass::Bool));
+        let wrap_class = operand_class.filter(|x| integer_width(*x).is_some());
+
+        let dst = match self.top_attribute().registers.first() {
+            Some(r) => *r,
+            _ => self.reg_mgr.alloc_hard(),
+        };
+
         let operands = operator.operands_mut();
+        let mut regs: SmallVec8<Reg> = smallvec![];
+        for operand in operands.iter_mut() {
+            let r = self.code_expression_register(operand);
+            regs.push(r);
+        }
 
-        match op {
-            // binary operators
-            Operator::Less
-            | Operator::Plus
-            | Operator::LessEqual
-            | Operator::Equal
-            | Operator::NotEqual
-            | Operator::Greater
-            | Operator::GreaterEqual => {
-                let dest_reg = match self.top_attribute().registers.first() {
-                    Some(r) => *r,
-                    _ => self.reg_mgr.alloc_hard(),
-                };
-                let cond = self.top_attribute().expr_exit_label.clone();
-
-                self.push_access_attribute(LuaAccessMode::LoadNewRegister);
-                self.visit_expression_mut(&mut operands[0]);
-                let rk0 = self.pop_attribute().rk();
-
-                self.push_access_attribute(LuaAccessMode::LoadNewRegister);
-                self.visit_expression_mut(&mut operands[1]);
-                let rk1 = self.pop_attribute().rk();
-
-                // generate operators
-                match op {
-                    // a + b
-                    Operator::Plus => self.code_add(dest_reg, rk0, rk1),
-                    // a = b
-                    Operator::Equal => self.code_eq(dest_reg, rk0, rk1),
-                    _ => unreachable!(),
+        match (op, regs.len()) {
+            // unary operators
+            (Operator::Minus, 1) => {
+                self.push_code(LuaByteCode::Unm(dst, regs[0]));
+                if let Some(class) = wrap_class {
+                    self.code_wrap(dst, dst, class);
                 }
-
-                // conditional jump
-                if let Some(lbl) = cond {
-                    self.code_jmp(lbl);
+            }
+            (Operator::Not, 1) => {
+                if is_bool {
+                    self.push_code(LuaByteCode::Not(dst, regs[0]));
+                } else {
+                    self.push_code(LuaByteCode::BNot(dst, regs[0]));
+                    if let Some(class) = wrap_class {
+                        self.code_wrap(dst, dst, class);
+                    }
                 }
-
-                if let RK::R(r0) = rk0 {
-                    self.reg_mgr.free(&r0);
+            }
+            // comparison
+            (
+                Operator::Less
+                | Operator::LessEqual
+                | Operator::Greater
+                | Operator::GreaterEqual
+                | Operator::Equal
+                | Operator::NotEqual,
+                2,
+            ) => {
+                if matches!(operand_class, Some(TypeClass::ULInt))
+                    && !matches!(op, Operator::Equal | Operator::NotEqual)
+                {
+                    let op0 = self.code_flip_sign(regs[0]);
+                    let op1 = self.code_flip_sign(regs[1]);
+                    self.code_compare(op, dst, op0, op1);
+                    self.reg_mgr.free(&op0);
+                    self.reg_mgr.free(&op1);
+                } else {
+                    self.code_compare(op, dst, regs[0], regs[1]);
                 }
-                if let RK::R(r1) = rk1 {
-                    self.reg_mgr.free(&r1);
+            }
+            // boolean logic
+            (Operator::BitAnd | Operator::BitOr, 2) if is_bool => {
+                self.code_logic(op, dst, regs[0], regs[1])
+            }
+            (Operator::Xor, 2) if is_bool => {
+                self.code_compare(Operator::NotEqual, dst, regs[0], regs[1])
+            }
+            // bitwise operators, result always in range
+            (Operator::BitAnd | Operator::BitOr | Operator::Xor, 2) => {
+                self.code_arith(op, dst, regs[0], regs[1], true)
+            }
+            // float arithmetic
+            (_, 2) if is_float => self.code_arith(op, dst, regs[0], regs[1], false),
+            // integer arithmetic
+            (Operator::Division, 2) if operand_class == Some(TypeClass::ULInt) => {
+                self.code_ulint_div(dst, regs[0], regs[1])
+            }
+            (Operator::Division, 2) => {
+                self.code_int_div(dst, regs[0], regs[1]);
+                if let Some(class) = wrap_class {
+                    self.code_wrap(dst, dst, class);
+                }
+            }
+            (Operator::Mod, 2) => self.code_int_mod(dst, regs[0], regs[1]),
+            (Operator::Plus | Operator::Minus | Operator::Multiply | Operator::Power, 2) => {
+                self.code_arith(op, dst, regs[0], regs[1], true);
+                match wrap_class {
+                    Some(class) => self.code_wrap(dst, dst, class),
+                    // Lua power always produce float
+                    None if matches!(op, Operator::Power) => {
+                        self.code_wrap(dst, dst, TypeClass::LInt);
+                    }
+                    None => {}
                 }
-                self.top_attribute().registers = smallvec![dest_reg];
             }
+            _ => unreachable!("{:?}", op),
+        }
 
-            _ => unreachable!(),
+        for r in regs {
+            self.reg_mgr.free(&r);
         }
+        self.top_attribute().registers = smallvec![dst];
     }
 
-    fn visit_assign_expression_mut(&mut self, assign: &mut AssignExpression) {
-        trace!("LuaGen: assignment expression: {}", assign);
+    fn visit_compo_access_expression_mut(&mut self, compo: &mut CompoAccessExpression) {
+        trace!("LuaGen: compo access expression: {}", compo);
 
-        self.push_access_attribute(LuaAccessMode::LoadNewRegister);
-        assign.right_mut().accept_mut(self);
-        let rhs = self.pop_attribute();
+        // enum value is integer constant
+        if let Some(value) = self.compo_enum_value(compo) {
+            let k = self.add_integer_constant(value);
+            match self.top_attribute().registers.first().copied() {
+                Some(r) => self.code_load_constant(r, k),
+                None => self.top_attribute().const_idx = Some(k),
+            }
+            return;
+        }
 
-        // Get lhs register
-        self.push_access_attribute(LuaAccessMode::ReadSymbol);
-        assign.left_mut().accept_mut(self);
-        let lhs_constant_index = self.pop_attribute().const_idx.unwrap();
-        // let lhs_reg = self.pop_attribute().registers[0];
+        let Some(field) = expression_variable_name(compo.right()).cloned() else {
+            self.report_invalid_expression(compo, "invalid member access");
+            let dst = match self.top_attribute().registers.first() {
+                Some(r) => *r,
+                _ => self.reg_mgr.alloc_hard(),
+            };
+            self.push_code(LuaByteCode::LoadNil(dst, 0));
+            self.top_attribute().registers = smallvec![dst];
+            return;
+        };
+        let obj = self.code_expression_register(compo.left_mut());
+        let dst = match self.top_attribute().registers.first() {
+            Some(r) => *r,
+            _ => self.reg_mgr.alloc_hard(),
+        };
 
-        if let Some(constant_index) = rhs.const_idx {
-            assert_eq!(rhs.registers.len(), 0);
-            // self.code_load_constant(lhs_reg, constant_index)
-            self.code_settabup(lhs_constant_index, RK::K(constant_index));
-        } else {
-            assert_eq!(1, rhs.registers.len());
-            // assert_ne!(lhs_reg, rhs.registers[0]);
+        let k = self.add_string_constant(&field);
+        self.push_code(LuaByteCode::GetField(dst, obj, k));
+        self.reg_mgr.free(&obj);
+
+        self.top_attribute().registers = smallvec![dst];
+    }
 
-            self.reg_mgr.free(&rhs.registers[0]);
-            self.code_settabup(lhs_constant_index, RK::R(rhs.registers[0]));
+    fn visit_array_access_expression_mut(
+        &mut self,
+        info: &mut ExprInfo,
+        access: &mut ArrayAccessExpression,
+    ) {
+        trace!("LuaGen: array access expression: {}", access);
+
+        let element = self.code_array_element(info.start, access);
+        let dst = match self.top_attribute().registers.first() {
+            Some(r) => *r,
+            _ => self.reg_mgr.alloc_hard(),
+        };
+        self.top_attribute().registers = smallvec![dst];
+
+        let Some((table, index)) = element else {
+            self.push_code(LuaByteCode::LoadNil(dst, 0));
+            return;
+        };
+        self.push_code(LuaByteCode::GetTable(dst, table, index));
+        self.reg_mgr.free(&index);
+        self.reg_mgr.free(&table);
+    }
+
+    fn visit_assign_expression_mut(&mut self, assign: &mut AssignExpression) {
+        trace!("LuaGen: assignment expression: {}", assign);
+
+        let lhs_class = self.expression_class(assign.left());
+        let value = self.code_value_for(assign.right_mut(), lhs_class);
+
+        self.code_store(assign.left_mut(), value);
+        if let RK::R(r) = value {
+            self.reg_mgr.free(&r);
         }
     }
 }
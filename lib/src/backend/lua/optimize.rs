@@ -0,0 +1,1150 @@
+//! Peephole optimizer for generated Lua bytecode.
+//!
+//! Instructions with an implicit successor (conditional tests and their `JMP`, arithmetic
+//! and its `MMBIN`, `NEWTABLE` and its `EXTRAARG`, `LFALSESKIP`) are never separated,
+//! jump offsets are turned into labels before instructions are removed and resolved again
+//! afterwards.
+
+use super::bytecode::{LuaByteCode, LuaCompiledCode, LuaConstants, LuaTMS};
+use super::label::{InstLabel, LabelPtr};
+use super::register::{Reg, RK};
+use super::utils::fit_sbx;
+use super::ConstIdx;
+use crate::backend::OptimizeLevel;
+
+use smallvec::{smallvec, SmallVec};
+
+type Successors = SmallVec<[usize; 2]>;
+
+/// Optimize a compiled function in place, nested functions are optimized when generated
+pub fn optimize(code: &mut LuaCompiledCode, level: OptimizeLevel) {
+    if level == OptimizeLevel::None {
+        return;
+    }
+
+    loop {
+        let mut changed = thread_jumps(code);
+        changed |= remove_unreachable(code);
+
+        if level == OptimizeLevel::Full {
+            changed |= constant_operands(code);
+            changed |= coalesce_moves(code);
+            changed |= remove_dead_stores(code);
+        }
+
+        if !changed {
+            break;
+        }
+    }
+
+    // after constant operands, constants are preferred by arithmetic instructions
+    canonical_loads(code);
+
+    if level == OptimizeLevel::Full {
+        compact_registers(code);
+    }
+}
+
+/// Registers set, one bit for each register
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+struct RegSet([u64; 4]);
+
+impl RegSet {
+    fn insert(&mut self, r: u8) {
+        self.0[r as usize / 64] |= 1 << (r % 64);
+    }
+
+    /// Insert `n` registers start from `r`
+    fn insert_range(&mut self, r: u8, n: usize) {
+        for x in (r as usize..r as usize + n).take_while(|x| *x <= u8::MAX as usize) {
+            self.insert(x as u8);
+        }
+    }
+
+    /// Insert all registers start from `r`, used for the variable count ranges end at stack top
+    fn insert_to_top(&mut self, r: u8) {
+        self.insert_range(r, u8::MAX as usize + 1)
+    }
+
+    fn contains(&self, r: u8) -> bool {
+        self.0[r as usize / 64] & (1 << (r % 64)) != 0
+    }
+
+    fn union(&self, other: &RegSet) -> RegSet {
+        let mut r = *self;
+        for (a, b) in r.0.iter_mut().zip(other.0) {
+            *a |= b;
+        }
+        r
+    }
+
+    fn difference(&self, other: &RegSet) -> RegSet {
+        let mut r = *self;
+        for (a, b) in r.0.iter_mut().zip(other.0) {
+            *a &= !b;
+        }
+        r
+    }
+
+    fn intersects(&self, other: &RegSet) -> bool {
+        self.0.iter().zip(other.0).any(|(a, b)| a & b != 0)
+    }
+
+    fn iter(&self) -> impl Iterator<Item = u8> + '_ {
+        (0..=u8::MAX).filter(|x| self.contains(*x))
+    }
+}
+
+/// Registers read and written by an instruction
+#[derive(Default)]
+struct Effect {
+    uses: RegSet,
+    // registers always written
+    defs: RegSet,
+    // registers may be written, superset of defs
+    clobbers: RegSet,
+    // registers start from this one are overwritten by a call frame
+    call_base: Option<u8>,
+    // instruction operates on registers up to stack top
+    to_top: bool,
+}
+
+impl Effect {
+    fn use_reg(&mut self, r: Reg) {
+        self.uses.insert(r.num());
+    }
+
+    fn use_rk(&mut self, rk: RK) {
+        if let RK::R(r) = rk {
+            self.use_reg(r)
+        }
+    }
+
+    /// Use `n` registers start from `r`, or all registers up to stack top if `n` is None
+    fn use_range(&mut self, r: u8, n: Option<usize>) {
+        match n {
+            Some(n) => self.uses.insert_range(r, n),
+            None => {
+                self.uses.insert_to_top(r);
+                self.to_top = true;
+            }
+        }
+    }
+
+    fn def_reg(&mut self, r: Reg) {
+        self.defs.insert(r.num());
+        self.clobbers.insert(r.num());
+    }
+
+    fn def_range(&mut self, r: u8, n: usize) {
+        self.defs.insert_range(r, n);
+        self.clobbers.insert_range(r, n);
+    }
+
+    fn results(&mut self, r: u8, c: u8) {
+        if c == 0 {
+            self.clobbers.insert_to_top(r);
+            self.to_top = true;
+        } else {
+            self.def_range(r, c as usize - 1);
+        }
+    }
+}
+
+fn effect(code: &LuaByteCode, protos: &[LuaCompiledCode]) -> Effect {
+    use LuaByteCode::*;
+
+    let mut e = Effect::default();
+    match *code {
+        Move(a, b)
+        | GetField(a, b, _)
+        | GetI(a, b, _)
+        | AddI(a, b, _)
+        | AddK(a, b, _)
+        | SubK(a, b, _)
+        | MulK(a, b, _)
+        | ModK(a, b, _)
+        | PowK(a, b, _)
+        | DivK(a, b, _)
+        | IDivK(a, b, _)
+        | BAndK(a, b, _)
+        | BOrK(a, b, _)
+        | BXorK(a, b, _)
+        | ShrI(a, b, _)
+        | ShlI(a, b, _)
+        | Unm(a, b)
+        | BNot(a, b)
+        | Not(a, b)
+        | Len(a, b) => {
+            e.use_reg(b);
+            e.def_reg(a);
+        }
+        LoadI(a, _)
+        | LoadF(a, _)
+        | LoadK(a, _)
+        | LoadKX(a)
+        | LoadFalse(a)
+        | LFalseSkip(a)
+        | LoadTrue(a)
+        | GetUpVal(a, _)
+        | GetTabUp(a, _, _)
+        | NewTable(a, ..) => e.def_reg(a),
+        LoadNil(a, b) => e.def_range(a.num(), b as usize + 1),
+        SetUpVal(a, _)
+        | MMBinI(a, ..)
+        | MMBinK(a, ..)
+        | EqK(a, ..)
+        | EQI(a, ..)
+        | Test(a, _)
+        | Return1(a) => e.use_reg(a),
+        Lti(a, ..) | Lei(a, ..) | Gti(a, ..) | Gei(a, ..) => e.use_reg(a),
+        // A is the index of upvalue
+        SetTabUp(_, _, rk) => e.use_rk(rk),
+        SetField(a, _, rk) | SetI(a, _, rk) => {
+            e.use_reg(a);
+            e.use_rk(rk);
+        }
+        GetTable(a, b, c)
+        | Add(a, b, c)
+        | Sub(a, b, c)
+        | Mul(a, b, c)
+        | Mod(a, b, c)
+        | Pow(a, b, c)
+        | Div(a, b, c)
+        | IDiv(a, b, c)
+        | BAnd(a, b, c)
+        | BOr(a, b, c)
+        | BXor(a, b, c)
+        | Shl(a, b, c)
+        | Shr(a, b, c) => {
+            e.use_reg(b);
+            e.use_reg(c);
+            e.def_reg(a);
+        }
+        SetTable(a, b, rk) => {
+            e.use_reg(a);
+            e.use_reg(b);
+            e.use_rk(rk);
+        }
+        Self_(a, b, rk) => {
+            e.use_reg(b);
+            e.use_rk(rk);
+            e.def_range(a.num(), 2);
+        }
+        // result is written to the destination of previous arithmetic instruction, which
+        // is defined by the arithmetic instruction already
+        MMBin(a, b, _) | Eq(a, b, _) | Lt(a, b, _) | Le(a, b, _) => {
+            e.use_reg(a);
+            e.use_reg(b);
+        }
+        Concat(a, n) => {
+            e.uses.insert_range(a.num(), n as usize);
+            e.def_reg(a);
+        }
+        Close(a) | Tbc(a) => e.uses.insert_to_top(a.num()),
+        TestSet(a, b, _) => {
+            e.use_reg(b);
+            e.clobbers.insert(a.num());
+        }
+        Call(a, b, c) => {
+            e.use_range(a.num(), (b > 0).then_some(b as usize));
+            e.results(a.num(), c);
+            e.call_base = Some(a.num());
+        }
+        TailCall(a, b, ..) => e.use_range(a.num(), (b > 0).then_some(b as usize)),
+        Return(a, b, ..) => e.use_range(a, (b > 0).then(|| b as usize - 1)),
+        ForPrep(a, _) | ForLoop(a, _) => {
+            e.uses.insert_range(a.num(), 3);
+            e.clobbers.insert_range(a.num(), 4);
+        }
+        TForPrep(a, _) => e.uses.insert_range(a.num(), 4),
+        TForCall(a, c) => {
+            e.uses.insert_range(a.num(), 4);
+            e.def_range(a.num() + 4, c as usize);
+            e.call_base = Some(a.num() + 4);
+        }
+        TForLoop(a, _) => {
+            e.uses.insert(a.num() + 4);
+            e.clobbers.insert(a.num() + 2);
+        }
+        SetList(a, b, ..) => e.use_range(a.num(), (b > 0).then_some(b as usize + 1)),
+        // registers captured by nested function are used
+        Closure(a, bx) => {
+            for upv in protos
+                .get(bx as usize)
+                .iter()
+                .flat_map(|x| x.upvalues.iter())
+            {
+                if upv.stack != 0 {
+                    e.uses.insert(upv.index);
+                }
+            }
+            e.def_reg(a);
+        }
+        VarArg(a, c) => e.results(a.num(), c),
+        Jmp(_) | Return0(_) | VarArgPrep(_) | ExtraArg(_) => {}
+    }
+
+    e
+}
+
+/// Instructions skip the next one when succeed
+fn has_companion(code: &LuaByteCode) -> bool {
+    use LuaByteCode::*;
+
+    is_arith(code) || matches!(code, NewTable(..) | LoadKX(..))
+}
+
+/// The next instruction is skipped or jumped conditionally
+fn has_skip_slot(code: &LuaByteCode) -> bool {
+    use LuaByteCode::*;
+
+    has_companion(code)
+        || matches!(
+            code,
+            Eq(..)
+                | Lt(..)
+                | Le(..)
+                | EqK(..)
+                | EQI(..)
+                | Lti(..)
+                | Lei(..)
+                | Gti(..)
+                | Gei(..)
+                | Test(..)
+                | TestSet(..)
+                | LFalseSkip(..)
+        )
+}
+
+/// Arithmetic instructions followed by a metamethod instruction
+fn is_arith(code: &LuaByteCode) -> bool {
+    use LuaByteCode::*;
+
+    matches!(
+        code,
+        AddI(..)
+            | AddK(..)
+            | SubK(..)
+            | MulK(..)
+            | ModK(..)
+            | PowK(..)
+            | DivK(..)
+            | IDivK(..)
+            | BAndK(..)
+            | BOrK(..)
+            | BXorK(..)
+            | ShrI(..)
+            | ShlI(..)
+            | Add(..)
+            | Sub(..)
+            | Mul(..)
+            | Mod(..)
+            | Pow(..)
+            | Div(..)
+            | IDiv(..)
+            | BAnd(..)
+            | BOr(..)
+            | BXor(..)
+            | Shl(..)
+            | Shr(..)
+    )
+}
+
+/// Jump target of instruction at `pc`
+fn jump_target(code: &LuaByteCode, pc: usize) -> Option<usize> {
+    use LuaByteCode::*;
+
+    let pc = pc as i64;
+    let target = match *code {
+        Jmp(sj) => pc + 1 + sj as i64,
+        ForPrep(_, bx) => pc + bx as i64 + 2,
+        ForLoop(_, bx) | TForLoop(_, bx) => pc + 1 - bx as i64,
+        TForPrep(_, bx) => pc + bx as i64 + 1,
+        _ => return None,
+    };
+
+    Some(target as usize)
+}
+
+/// Successors of instruction at `pc` in control flow, instructions skipped by `pc` are
+/// treated as successors too, so they're never separated from `pc`.
+fn successors(codes: &[LuaByteCode], pc: usize) -> Successors {
+    use LuaByteCode::*;
+
+    let mut succ: Successors = match codes[pc] {
+        Return(..) | Return0(_) | Return1(_) | TailCall(..) => smallvec![],
+        Jmp(_) => smallvec![],
+        ForPrep(..) | ForLoop(..) | TForPrep(..) | TForLoop(..) => smallvec![pc + 1],
+        ref x if has_skip_slot(x) => smallvec![pc + 1, pc + 2],
+        _ => smallvec![pc + 1],
+    };
+
+    if let Some(target) = jump_target(&codes[pc], pc) {
+        succ.push(target);
+    }
+
+    succ.retain(|x| *x < codes.len());
+    succ
+}
+
+fn predecessors(codes: &[LuaByteCode]) -> Vec<Successors> {
+    let mut preds = vec![smallvec![]; codes.len()];
+    for pc in 0..codes.len() {
+        for s in successors(codes, pc) {
+            preds[s].push(pc);
+        }
+    }
+
+    preds
+}
+
+/// Registers live after each instruction
+fn live_out(code: &LuaCompiledCode) -> Vec<RegSet> {
+    let codes = &code.byte_codes;
+    let effects: Vec<_> = codes.iter().map(|x| effect(x, &code.protos)).collect();
+    let succs: Vec<_> = (0..codes.len()).map(|pc| successors(codes, pc)).collect();
+
+    let mut live_in = vec![RegSet::default(); codes.len()];
+    let mut live_out = vec![RegSet::default(); codes.len()];
+    let mut changed = true;
+    while changed {
+        changed = false;
+
+        for pc in (0..codes.len()).rev() {
+            let out = succs[pc]
+                .iter()
+                .fold(RegSet::default(), |acc, s| acc.union(&live_in[*s]));
+            let inp = effects[pc].uses.union(&out.difference(&effects[pc].defs));
+
+            if out != live_out[pc] || inp != live_in[pc] {
+                live_out[pc] = out;
+                live_in[pc] = inp;
+                changed = true;
+            }
+        }
+    }
+
+    live_out
+}
+
+/// Labels of all jump targets, fixup instructions are the jump instructions
+fn jump_labels(codes: &[LuaByteCode]) -> Vec<LabelPtr> {
+    let mut labels: Vec<LabelPtr> = vec![];
+
+    for (pc, code) in codes.iter().enumerate() {
+        if let Some(target) = jump_target(code, pc) {
+            let label = match labels
+                .iter()
+                .find(|x| x.borrow().inst_index == Some(target))
+            {
+                Some(label) => label.clone(),
+                None => {
+                    let label = InstLabel::new(format!("L{}", target).into());
+                    label.borrow_mut().inst_index = Some(target);
+                    labels.push(label.clone());
+                    label
+                }
+            };
+
+            label.borrow_mut().fixup_instructions.push(pc);
+        }
+    }
+
+    labels
+}
+
+/// Remove marked instructions, jumps to removed instruction are relocated to the next one
+fn remove_instructions(code: &mut LuaCompiledCode, remove: &[bool]) -> bool {
+    if !remove.iter().any(|x| *x) {
+        return false;
+    }
+
+    let labels = jump_labels(&code.byte_codes);
+
+    // new position of each instruction, and the end of function
+    let mut new_pos = Vec::with_capacity(remove.len() + 1);
+    let mut n = 0;
+    for removed in remove {
+        new_pos.push(n);
+        if !removed {
+            n += 1;
+        }
+    }
+    new_pos.push(n);
+
+    for label in &labels {
+        let mut label = label.borrow_mut();
+        label.inst_index = label.inst_index.map(|x| new_pos[x]);
+        label.fixup_instructions = label
+            .fixup_instructions
+            .iter()
+            .filter(|x| !remove[**x])
+            .map(|x| new_pos[*x])
+            .collect();
+    }
+
+    let mut removed = remove.iter();
+    code.byte_codes.retain(|_| !removed.next().unwrap());
+    if code.line_info.len() == remove.len() {
+        let mut removed = remove.iter();
+        code.line_info.retain(|_| !removed.next().unwrap());
+    }
+
+    // jmp relocate
+    for label in labels {
+        let label = label.borrow();
+        let target = label.inst_index.unwrap() as i64;
+        for pc in &label.fixup_instructions {
+            let offset = target - *pc as i64;
+            code.byte_codes[*pc] = match code.byte_codes[*pc] {
+                LuaByteCode::Jmp(_) => LuaByteCode::Jmp((offset - 1) as i32),
+                LuaByteCode::ForPrep(a, _) => LuaByteCode::ForPrep(a, (offset - 2) as u32),
+                LuaByteCode::ForLoop(a, _) => LuaByteCode::ForLoop(a, (1 - offset) as u32),
+                LuaByteCode::TForPrep(a, _) => LuaByteCode::TForPrep(a, (offset - 1) as u32),
+                LuaByteCode::TForLoop(a, _) => LuaByteCode::TForLoop(a, (1 - offset) as u32),
+                x => unreachable!("{:?} is not a jump instruction", x),
+            };
+        }
+    }
+
+    true
+}
+
+/// Replace `LOADK` with the instructions encode constant in itself
+fn canonical_loads(code: &mut LuaCompiledCode) {
+    for i in 0..code.byte_codes.len() {
+        let LuaByteCode::LoadK(a, k) = code.byte_codes[i] else {
+            continue;
+        };
+
+        let replacement = match code.constants.get(k as usize) {
+            Some(LuaConstants::Integer(v)) => fit_sbx(*v).map(|v| LuaByteCode::LoadI(a, v)),
+            Some(LuaConstants::Float(f)) => float_sbx(*f).map(|v| LuaByteCode::LoadF(a, v)),
+            Some(LuaConstants::Boolean(true)) => Some(LuaByteCode::LoadTrue(a)),
+            Some(LuaConstants::Boolean(false)) => Some(LuaByteCode::LoadFalse(a)),
+            Some(LuaConstants::Nil) => Some(LuaByteCode::LoadNil(a, 0)),
+            _ => None,
+        };
+
+        if let Some(x) = replacement {
+            code.byte_codes[i] = x;
+        }
+    }
+}
+
+/// Float value which has exact integer value fits sBx
+fn float_sbx(f: f64) -> Option<i32> {
+    if f.fract() != 0.0 || (f == 0.0 && f.is_sign_negative()) || f.abs() > i32::MAX as f64 {
+        return None;
+    }
+
+    fit_sbx(f as i64)
+}
+
+/// Jumps to another jump go to the final target directly, the unused jumps are removed
+/// later if unreachable
+fn thread_jumps(code: &mut LuaCompiledCode) -> bool {
+    let codes = &mut code.byte_codes;
+    let mut changed = false;
+
+    for pc in 0..codes.len() {
+        let LuaByteCode::Jmp(_) = codes[pc] else {
+            continue;
+        };
+
+        let mut target = jump_target(&codes[pc], pc).unwrap();
+        let mut steps = 0;
+        while let Some(LuaByteCode::Jmp(_)) = codes.get(target) {
+            let next = jump_target(&codes[target], target).unwrap();
+            // jump loops
+            if next == target || next == pc || steps > codes.len() {
+                break;
+            }
+
+            target = next;
+            steps += 1;
+        }
+
+        let offset = target as i32 - pc as i32 - 1;
+        if codes[pc] != LuaByteCode::Jmp(offset) {
+            codes[pc] = LuaByteCode::Jmp(offset);
+            changed = true;
+        }
+    }
+
+    changed
+}
+
+/// Remove instructions can't be reached and jumps to the next instruction
+fn remove_unreachable(code: &mut LuaCompiledCode) -> bool {
+    let codes = &code.byte_codes;
+    if codes.is_empty() {
+        return false;
+    }
+
+    let mut reachable = vec![false; codes.len()];
+    let mut pending = vec![0];
+    while let Some(pc) = pending.pop() {
+        if !reachable[pc] {
+            reachable[pc] = true;
+            pending.extend(successors(codes, pc));
+        }
+    }
+
+    let remove: Vec<_> = (0..codes.len())
+        .map(|pc| !reachable[pc] || (codes[pc] == LuaByteCode::Jmp(0) && !is_skip_slot(codes, pc)))
+        .collect();
+
+    remove_instructions(code, &remove)
+}
+
+#[inline]
+fn is_skip_slot(codes: &[LuaByteCode], pc: usize) -> bool {
+    pc > 0 && has_skip_slot(&codes[pc - 1])
+}
+
+/// Constant loaded to register
+#[derive(Clone, Copy)]
+enum Known {
+    Integer(i64),
+    Constant(ConstIdx),
+}
+
+/// Use constant operand forms for instructions read registers loaded with constant,
+/// `LOADI 2 2; ADD 0 1 2` becomes `ADDI 0 1 2`, the loads are removed as dead stores.
+fn constant_operands(code: &mut LuaCompiledCode) -> bool {
+    let preds = predecessors(&code.byte_codes);
+    let mut known: Vec<Option<Known>> = vec![None; u8::MAX as usize + 1];
+    let mut changed = false;
+
+    for pc in 0..code.byte_codes.len() {
+        // the skipped companion instruction has the same effect as the instruction before it
+        let flows_in = preds[pc].iter().all(|p| {
+            *p + 1 == pc
+                || (*p + 2 == pc
+                    && has_companion(&code.byte_codes[*p])
+                    && preds[pc - 1].as_slice() == [*p])
+        });
+        if !flows_in || preds[pc].is_empty() {
+            known.fill(None);
+        }
+
+        if let Some((op, companion)) = constant_operand(code, pc, &known) {
+            code.byte_codes[pc] = op;
+            if let Some(x) = companion {
+                code.byte_codes[pc + 1] = x;
+            }
+            changed = true;
+        }
+
+        let e = effect(&code.byte_codes[pc], &code.protos);
+        for r in e.clobbers.iter() {
+            known[r as usize] = None;
+        }
+        if let Some(base) = e.call_base {
+            known[base as usize..].fill(None);
+        }
+
+        match code.byte_codes[pc] {
+            LuaByteCode::LoadI(a, v) => known[a.num() as usize] = Some(Known::Integer(v as i64)),
+            LuaByteCode::LoadK(a, k) => known[a.num() as usize] = Some(Known::Constant(k)),
+            _ => {}
+        }
+    }
+
+    changed
+}
+
+/// Rewrite instruction at `pc` with constant operand, the metamethod instruction after
+/// arithmetic instruction is rewritten together
+fn constant_operand(
+    code: &mut LuaCompiledCode,
+    pc: usize,
+    known: &[Option<Known>],
+) -> Option<(LuaByteCode, Option<LuaByteCode>)> {
+    use LuaByteCode::*;
+
+    let get = |r: Reg| known[r.num() as usize];
+    let imm = |r: Reg| match get(r) {
+        Some(Known::Integer(v)) if (-127..=127).contains(&v) => Some(v as i8),
+        _ => None,
+    };
+
+    match code.byte_codes[pc] {
+        Eq(a, b, k) => match (get(a), get(b)) {
+            (_, Some(_)) if imm(b).is_some() => Some((EQI(a, imm(b)?, k, false), None)),
+            (Some(_), _) if imm(a).is_some() => Some((EQI(b, imm(a)?, k, false), None)),
+            (_, Some(x)) => Some((EqK(a, constant_index(code, x)?, k), None)),
+            (Some(x), _) => Some((EqK(b, constant_index(code, x)?, k), None)),
+            _ => None,
+        },
+        Lt(a, b, k) => match (imm(a), imm(b)) {
+            (_, Some(v)) => Some((Lti(a, v, k, false), None)),
+            (Some(v), _) => Some((Gti(b, v, k, false), None)),
+            _ => None,
+        },
+        Le(a, b, k) => match (imm(a), imm(b)) {
+            (_, Some(v)) => Some((Lei(a, v, k, false), None)),
+            (Some(v), _) => Some((Gei(b, v, k, false), None)),
+            _ => None,
+        },
+        Add(a, b, c)
+        | Sub(a, b, c)
+        | Mul(a, b, c)
+        | Mod(a, b, c)
+        | Pow(a, b, c)
+        | Div(a, b, c)
+        | IDiv(a, b, c)
+        | BAnd(a, b, c)
+        | BOr(a, b, c)
+        | BXor(a, b, c) => {
+            let MMBin(mb, mc, tm) = *code.byte_codes.get(pc + 1)? else {
+                return None;
+            };
+            if mb != b || mc != c {
+                return None;
+            }
+
+            let commutative = matches!(
+                tm,
+                LuaTMS::TM_ADD
+                    | LuaTMS::TM_MUL
+                    | LuaTMS::TM_BAND
+                    | LuaTMS::TM_BOR
+                    | LuaTMS::TM_BXOR
+            );
+
+            // the constant is the second operand, or the first operand of commutative operators
+            let (r, x, flip) = match (get(b), get(c)) {
+                (_, Some(x)) => (b, x, false),
+                (Some(x), _) if commutative => (c, x, true),
+                _ => return None,
+            };
+
+            if let Known::Integer(v) = x {
+                if (-127..=127).contains(&v) {
+                    let v = v as i8;
+                    match tm {
+                        LuaTMS::TM_ADD => {
+                            return Some((AddI(a, r, v), Some(MMBinI(r, v, tm, flip))))
+                        }
+                        LuaTMS::TM_SUB => {
+                            return Some((AddI(a, r, -v), Some(MMBinI(r, v, tm, flip))))
+                        }
+                        _ => {}
+                    }
+                }
+            }
+
+            // bitwise operators require integer constant
+            let value = match x {
+                Known::Integer(v) => LuaConstants::Integer(v),
+                Known::Constant(k) => code.constants.get(k as usize)?.clone(),
+            };
+            let bitwise = matches!(tm, LuaTMS::TM_BAND | LuaTMS::TM_BOR | LuaTMS::TM_BXOR);
+            match value {
+                LuaConstants::Integer(_) => {}
+                LuaConstants::Float(_) if !bitwise => {}
+                _ => return None,
+            }
+
+            let k = constant_index(code, x)?;
+            let op = match tm {
+                LuaTMS::TM_ADD => AddK(a, r, k),
+                LuaTMS::TM_SUB => SubK(a, r, k),
+                LuaTMS::TM_MUL => MulK(a, r, k),
+                LuaTMS::TM_MOD => ModK(a, r, k),
+                LuaTMS::TM_POW => PowK(a, r, k),
+                LuaTMS::TM_DIV => DivK(a, r, k),
+                LuaTMS::TM_IDIV => IDivK(a, r, k),
+                LuaTMS::TM_BAND => BAndK(a, r, k),
+                LuaTMS::TM_BOR => BOrK(a, r, k),
+                LuaTMS::TM_BXOR => BXorK(a, r, k),
+                _ => return None,
+            };
+
+            Some((op, Some(MMBinK(r, k, tm, flip))))
+        }
+        _ => None,
+    }
+}
+
+/// Index of known constant, integer is added to constants table if absent
+fn constant_index(code: &mut LuaCompiledCode, x: Known) -> Option<ConstIdx> {
+    match x {
+        Known::Constant(k) => Some(k),
+        Known::Integer(v) => {
+            let constant = LuaConstants::Integer(v);
+            let idx = match code.constants.iter().position(|x| *x == constant) {
+                Some(idx) => idx,
+                None => {
+                    code.constants.push(constant);
+                    code.constants.len() - 1
+                }
+            };
+            if idx > ConstIdx::MAX as usize {
+                code.constants.pop();
+                return None;
+            }
+
+            Some(idx as ConstIdx)
+        }
+    }
+}
+
+/// Write result directly to the destination of `MOVE`:
+/// `GETTABUP 3 0 1; MOVE 5 3` becomes `GETTABUP 5 0 1` if register 3 is dead after `MOVE`
+fn coalesce_moves(code: &mut LuaCompiledCode) -> bool {
+    use LuaByteCode::*;
+
+    let preds = predecessors(&code.byte_codes);
+    let live = live_out(code);
+    let mut remove = vec![false; code.byte_codes.len()];
+
+    for pc in 1..code.byte_codes.len() {
+        let Move(d, t) = code.byte_codes[pc] else {
+            continue;
+        };
+
+        // producer and its companion
+        let mut p = pc - 1;
+        if p > 0 && has_companion(&code.byte_codes[p - 1]) {
+            p -= 1;
+            if preds[p + 1].as_slice() != [p] {
+                continue;
+            }
+        }
+
+        let group = p..pc;
+        if d == t
+            || live[pc].contains(t.num())
+            || remove[p]
+            || is_skip_slot(&code.byte_codes, p)
+            || preds[pc].iter().any(|x| !group.contains(x))
+        {
+            continue;
+        }
+
+        let retarget = match code.byte_codes[p] {
+            Move(a, b) if a == t => Move(d, b),
+            LoadI(a, v) if a == t => LoadI(d, v),
+            LoadF(a, v) if a == t => LoadF(d, v),
+            LoadK(a, k) if a == t => LoadK(d, k),
+            LoadFalse(a) if a == t => LoadFalse(d),
+            LoadTrue(a) if a == t => LoadTrue(d),
+            LoadNil(a, 0) if a == t => LoadNil(d, 0),
+            GetUpVal(a, b) if a == t => GetUpVal(d, b),
+            GetTabUp(a, b, k) if a == t => GetTabUp(d, b, k),
+            GetField(a, b, k) if a == t => GetField(d, b, k),
+            GetTable(a, b, c) if a == t => GetTable(d, b, c),
+            GetI(a, b, c) if a == t => GetI(d, b, c),
+            NewTable(a, b, c, k) if a == t => NewTable(d, b, c, k),
+            Unm(a, b) if a == t => Unm(d, b),
+            BNot(a, b) if a == t => BNot(d, b),
+            Not(a, b) if a == t => Not(d, b),
+            Len(a, b) if a == t => Len(d, b),
+            ref x if is_arith(x) && effect(x, &code.protos).defs.contains(t.num()) => {
+                map_destination(x, d)
+            }
+            _ => continue,
+        };
+
+        code.byte_codes[p] = retarget;
+        remove[pc] = true;
+    }
+
+    remove_instructions(code, &remove)
+}
+
+/// Arithmetic instruction with another destination register
+fn map_destination(code: &LuaByteCode, d: Reg) -> LuaByteCode {
+    use LuaByteCode::*;
+
+    match *code {
+        AddI(_, b, c) => AddI(d, b, c),
+        AddK(_, b, c) => AddK(d, b, c),
+        SubK(_, b, c) => SubK(d, b, c),
+        MulK(_, b, c) => MulK(d, b, c),
+        ModK(_, b, c) => ModK(d, b, c),
+        PowK(_, b, c) => PowK(d, b, c),
+        DivK(_, b, c) => DivK(d, b, c),
+        IDivK(_, b, c) => IDivK(d, b, c),
+        BAndK(_, b, c) => BAndK(d, b, c),
+        BOrK(_, b, c) => BOrK(d, b, c),
+        BXorK(_, b, c) => BXorK(d, b, c),
+        ShrI(_, b, c) => ShrI(d, b, c),
+        ShlI(_, b, c) => ShlI(d, b, c),
+        Add(_, b, c) => Add(d, b, c),
+        Sub(_, b, c) => Sub(d, b, c),
+        Mul(_, b, c) => Mul(d, b, c),
+        Mod(_, b, c) => Mod(d, b, c),
+        Pow(_, b, c) => Pow(d, b, c),
+        Div(_, b, c) => Div(d, b, c),
+        IDiv(_, b, c) => IDiv(d, b, c),
+        BAnd(_, b, c) => BAnd(d, b, c),
+        BOr(_, b, c) => BOr(d, b, c),
+        BXor(_, b, c) => BXor(d, b, c),
+        Shl(_, b, c) => Shl(d, b, c),
+        Shr(_, b, c) => Shr(d, b, c),
+        x => x,
+    }
+}
+
+/// Remove instructions without side effect whose results are never read
+fn remove_dead_stores(code: &mut LuaCompiledCode) -> bool {
+    use LuaByteCode::*;
+
+    let live = live_out(code);
+    let codes = &code.byte_codes;
+    let remove: Vec<_> = (0..codes.len())
+        .map(|pc| {
+            let pure = match codes[pc] {
+                Move(a, b) if a == b => true,
+                Move(..) | LoadI(..) | LoadF(..) | LoadK(..) | LoadFalse(..) | LoadTrue(..)
+                | LoadNil(..) | GetUpVal(..) => {
+                    !effect(&codes[pc], &code.protos).defs.intersects(&live[pc])
+                }
+                _ => false,
+            };
+
+            pure && !is_skip_slot(codes, pc)
+        })
+        .collect();
+
+    remove_instructions(code, &remove)
+}
+
+/// Renumber registers to remove the unused ones, stack size is reduced by the number of
+/// removed registers
+fn compact_registers(code: &mut LuaCompiledCode) {
+    let mut used = RegSet::default();
+    used.insert_range(0, code.num_params as usize);
+
+    for x in &code.byte_codes {
+        let e = effect(x, &code.protos);
+        // registers of variable count ranges are unknown
+        if e.to_top {
+            return;
+        }
+
+        used = used.union(&e.uses).union(&e.clobbers);
+    }
+
+    // registers captured by nested functions are referenced by index
+    if code
+        .protos
+        .iter()
+        .flat_map(|x| x.upvalues.iter())
+        .any(|x| x.stack != 0)
+    {
+        return;
+    }
+
+    let mut mapping = [0u8; u8::MAX as usize + 1];
+    let mut n = 0u8;
+    for (r, m) in mapping
+        .iter_mut()
+        .enumerate()
+        .take(code.max_stack_size as usize)
+    {
+        *m = n;
+        if used.contains(r as u8) {
+            n += 1;
+        }
+    }
+
+    let holes = code.max_stack_size - n;
+    if holes == 0 || used.iter().any(|x| x >= code.max_stack_size) {
+        return;
+    }
+
+    for x in code.byte_codes.iter_mut() {
+        *x = map_registers(x, |r| mapping[r as usize]);
+    }
+    code.max_stack_size = (code.max_stack_size - holes).max(2);
+}
+
+/// Rename all register operands of instruction
+fn map_registers<F: Fn(u8) -> u8>(code: &LuaByteCode, f: F) -> LuaByteCode {
+    use LuaByteCode::*;
+
+    let r = |x: Reg| Reg::R(f(x.num()));
+    let rk = |x: RK| match x {
+        RK::R(x) => RK::R(r(x)),
+        k => k,
+    };
+
+    match *code {
+        Move(a, b) => Move(r(a), r(b)),
+        LoadI(a, v) => LoadI(r(a), v),
+        LoadF(a, v) => LoadF(r(a), v),
+        LoadK(a, k) => LoadK(r(a), k),
+        LoadKX(a) => LoadKX(r(a)),
+        LoadFalse(a) => LoadFalse(r(a)),
+        LFalseSkip(a) => LFalseSkip(r(a)),
+        LoadTrue(a) => LoadTrue(r(a)),
+        LoadNil(a, b) => LoadNil(r(a), b),
+        GetUpVal(a, b) => GetUpVal(r(a), b),
+        SetUpVal(a, b) => SetUpVal(r(a), b),
+        GetTabUp(a, b, c) => GetTabUp(r(a), b, c),
+        SetTabUp(a, b, c) => SetTabUp(a, b, rk(c)),
+        GetField(a, b, c) => GetField(r(a), r(b), c),
+        SetField(a, b, c) => SetField(r(a), b, rk(c)),
+        GetTable(a, b, c) => GetTable(r(a), r(b), r(c)),
+        SetTable(a, b, c) => SetTable(r(a), r(b), rk(c)),
+        GetI(a, b, c) => GetI(r(a), r(b), c),
+        SetI(a, b, c) => SetI(r(a), b, rk(c)),
+        NewTable(a, b, c, k) => NewTable(r(a), b, c, k),
+        Self_(a, b, c) => Self_(r(a), r(b), rk(c)),
+        AddI(a, b, c) => AddI(r(a), r(b), c),
+        AddK(a, b, c) => AddK(r(a), r(b), c),
+        SubK(a, b, c) => SubK(r(a), r(b), c),
+        MulK(a, b, c) => MulK(r(a), r(b), c),
+        ModK(a, b, c) => ModK(r(a), r(b), c),
+        PowK(a, b, c) => PowK(r(a), r(b), c),
+        DivK(a, b, c) => DivK(r(a), r(b), c),
+        IDivK(a, b, c) => IDivK(r(a), r(b), c),
+        BAndK(a, b, c) => BAndK(r(a), r(b), c),
+        BOrK(a, b, c) => BOrK(r(a), r(b), c),
+        BXorK(a, b, c) => BXorK(r(a), r(b), c),
+        ShrI(a, b, c) => ShrI(r(a), r(b), c),
+        ShlI(a, b, c) => ShlI(r(a), r(b), c),
+        Add(a, b, c) => Add(r(a), r(b), r(c)),
+        Sub(a, b, c) => Sub(r(a), r(b), r(c)),
+        Mul(a, b, c) => Mul(r(a), r(b), r(c)),
+        Mod(a, b, c) => Mod(r(a), r(b), r(c)),
+        Pow(a, b, c) => Pow(r(a), r(b), r(c)),
+        Div(a, b, c) => Div(r(a), r(b), r(c)),
+        IDiv(a, b, c) => IDiv(r(a), r(b), r(c)),
+        BAnd(a, b, c) => BAnd(r(a), r(b), r(c)),
+        BOr(a, b, c) => BOr(r(a), r(b), r(c)),
+        BXor(a, b, c) => BXor(r(a), r(b), r(c)),
+        Shl(a, b, c) => Shl(r(a), r(b), r(c)),
+        Shr(a, b, c) => Shr(r(a), r(b), r(c)),
+        MMBin(a, b, tm) => MMBin(r(a), r(b), tm),
+        MMBinI(a, v, tm, k) => MMBinI(r(a), v, tm, k),
+        MMBinK(a, c, tm, k) => MMBinK(r(a), c, tm, k),
+        Unm(a, b) => Unm(r(a), r(b)),
+        BNot(a, b) => BNot(r(a), r(b)),
+        Not(a, b) => Not(r(a), r(b)),
+        Len(a, b) => Len(r(a), r(b)),
+        Concat(a, n) => Concat(r(a), n),
+        Close(a) => Close(r(a)),
+        Tbc(a) => Tbc(r(a)),
+        Eq(a, b, k) => Eq(r(a), r(b), k),
+        Lt(a, b, k) => Lt(r(a), r(b), k),
+        Le(a, b, k) => Le(r(a), r(b), k),
+        EqK(a, b, k) => EqK(r(a), b, k),
+        EQI(a, v, k, f) => EQI(r(a), v, k, f),
+        Lti(a, v, k, f) => Lti(r(a), v, k, f),
+        Lei(a, v, k, f) => Lei(r(a), v, k, f),
+        Gti(a, v, k, f) => Gti(r(a), v, k, f),
+        Gei(a, v, k, f) => Gei(r(a), v, k, f),
+        Test(a, k) => Test(r(a), k),
+        TestSet(a, b, k) => TestSet(r(a), r(b), k),
+        Return(a, b, c, k) => Return(f(a), b, c, k),
+        Return0(a) => Return0(f(a)),
+        Return1(a) => Return1(r(a)),
+        Call(a, b, c) => Call(r(a), b, c),
+        TailCall(a, b, c, k) => TailCall(r(a), b, c, k),
+        ForPrep(a, bx) => ForPrep(r(a), bx),
+        ForLoop(a, bx) => ForLoop(r(a), bx),
+        TForPrep(a, bx) => TForPrep(r(a), bx),
+        TForCall(a, c) => TForCall(r(a), c),
+        TForLoop(a, bx) => TForLoop(r(a), bx),
+        SetList(a, b, c, k) => SetList(r(a), b, c, k),
+        Closure(a, bx) => Closure(r(a), bx),
+        VarArg(a, c) => VarArg(r(a), c),
+        x @ (Jmp(_) | VarArgPrep(_) | ExtraArg(_)) => x,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn compiled(byte_codes: Vec<LuaByteCode>) -> LuaCompiledCode {
+        LuaCompiledCode {
+            line_info: vec![1; byte_codes.len()],
+            byte_codes,
+            constants: vec![],
+            upvalues: vec![],
+            protos: vec![],
+            num_params: 0,
+            is_vararg: false,
+            max_stack_size: 8,
+            source: None,
+            line_defined: 0,
+            last_line_defined: 0,
+            local_variables: vec![],
+        }
+    }
+
+    #[test]
+    fn test_jump_threading() {
+        use LuaByteCode::*;
+
+        let mut code = compiled(vec![
+            Test(Reg::R(0), false),
+            Jmp(1),
+            LoadI(Reg::R(0), 1),
+            Jmp(0),
+            Jmp(1),
+            LoadI(Reg::R(0), 2),
+            Return1(Reg::R(0)),
+        ]);
+        optimize(&mut code, OptimizeLevel::Basic);
+
+        // `JMP 1` jumps to the final target, jumps to next are removed and unreachable load is removed
+        assert_eq!(
+            code.byte_codes,
+            vec![
+                Test(Reg::R(0), false),
+                Jmp(1),
+                LoadI(Reg::R(0), 1),
+                Return1(Reg::R(0))
+            ]
+        );
+        assert_eq!(code.line_info.len(), code.byte_codes.len());
+    }
+
+    #[test]
+    fn test_skip_slot_preserved() {
+        use LuaByteCode::*;
+
+        // `JMP 0` after test and `LOADTRUE` after `LFALSESKIP` can't be removed
+        let codes = vec![
+            Test(Reg::R(0), false),
+            Jmp(0),
+            LFalseSkip(Reg::R(1)),
+            LoadTrue(Reg::R(1)),
+            Return1(Reg::R(1)),
+        ];
+        let mut code = compiled(codes.clone());
+        optimize(&mut code, OptimizeLevel::Full);
+        assert_eq!(code.byte_codes, codes);
+    }
+
+    #[test]
+    fn test_loop_relocate() {
+        use LuaByteCode::*;
+
+        // dead load in loop body, FORPREP and FORLOOP are relocated
+        let mut code = compiled(vec![
+            LoadI(Reg::R(0), 1),
+            LoadI(Reg::R(1), 3),
+            LoadI(Reg::R(2), 1),
+            ForPrep(Reg::R(0), 2),
+            LoadI(Reg::R(5), 0),
+            SetTabUp(Reg::R(0), 0, RK::R(Reg::R(3))),
+            ForLoop(Reg::R(0), 3),
+            Return0(0),
+        ]);
+        optimize(&mut code, OptimizeLevel::Full);
+
+        assert_eq!(code.byte_codes[3], ForPrep(Reg::R(0), 1));
+        assert_eq!(code.byte_codes[5], ForLoop(Reg::R(0), 2));
+        assert_eq!(code.max_stack_size, 4);
+    }
+}
@@ -42,8 +42,9 @@ impl Reg {
 pub struct RegisterManager {
     // Cursor point to next free register id
     virtual_register_cursor: usize,
-    real_register_cursor: u8,
     used_real_registers: HashSet<u8>,
+    // High-water mark of real registers, the count of registers ever used
+    max_real_registers: usize,
     local_variable_register: SmallMap<StString, Reg>,
     local_variable_register_reverse: SmallMap<Reg, StString>,
 }
@@ -53,8 +54,8 @@ impl RegisterManager {
     pub fn new() -> Self {
         Self {
             virtual_register_cursor: 0,
-            real_register_cursor: 0,
             used_real_registers: HashSet::with_capacity(MAX_REGISTER_ID as usize),
+            max_real_registers: 0,
             local_variable_register: SmallMap::with_capacity(201),
             local_variable_register_reverse: SmallMap::with_capacity(201),
         }
@@ -91,7 +92,7 @@ impl RegisterManager {
         }
 
         self.virtual_register_cursor = 0;
-        self.real_register_cursor = 0;
+        self.max_real_registers = 0;
         self.used_real_registers.clear();
         self.local_variable_register.clean();
         self.local_variable_register_reverse.clean();
@@ -106,37 +107,46 @@ impl RegisterManager {
         Reg::VR(next)
     }
 
+    /// Allocate the lowest free register
     pub fn alloc_hard(&mut self) -> Reg {
-        // ensure has free register to allocate
-        assert!(self.used_real_registers.len() <= MAX_REGISTER_ID as usize);
+        let r = (0..MAX_REGISTER_ID)
+            .find(|x| !self.used_real_registers.contains(x))
+            .expect("no more registers!");
 
-        loop {
-            if self.used_real_registers.insert(self.real_register_cursor) {
-                return Reg::R(self.real_register_cursor);
-            }
-
-            self.real_register_cursor += 1;
-            self.real_register_cursor %= MAX_REGISTER_ID;
-        }
+        self.used_real_registers.insert(r);
+        self.max_real_registers = self.max_real_registers.max(r as usize + 1);
+        Reg::R(r)
     }
 
+    /// Allocate `count + 1` continuous registers on the top of all registers in use,
+    /// the first one is used for callee and the others are arguments
     pub fn alloc_hard_batch(&mut self, count: usize) -> Vec<Reg> {
-        let cursor = self.real_register_cursor as usize;
-        if cursor + count >= MAX_REGISTER_ID as usize {
+        let base = self
+            .used_real_registers
+            .iter()
+            .max()
+            .map(|x| *x as usize + 1)
+            .unwrap_or(0);
+        if base + count >= MAX_REGISTER_ID as usize {
             panic!("no more registers!")
         }
 
-        self.real_register_cursor = (cursor + count) as u8;
-
-        let mut r = Vec::with_capacity(count);
-        for x in cursor..=cursor + count {
+        let mut r = Vec::with_capacity(count + 1);
+        for x in base..=base + count {
             r.push(Reg::R(x as u8));
             self.used_real_registers.insert(x as u8);
         }
+        self.max_real_registers = self.max_real_registers.max(base + count + 1);
 
         r
     }
 
+    /// Count of registers ever used, the `maxstacksize` of function
+    #[inline]
+    pub fn max_stack_size(&self) -> u8 {
+        self.max_real_registers as u8
+    }
+
     #[inline]
     pub fn free(&mut self, reg: &Reg) {
         // prevent free for local variable register
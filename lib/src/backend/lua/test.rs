@@ -2,33 +2,28 @@ use mlua::{ChunkMode, Lua};
 use std::io::Write;
 use std::process::Command;
 
-use crate::backend::{CodeGenBackend, CodeGenDriver, LuaBackend};
-use crate::{parser::*, prelude::*};
+use crate::backend::{
+    lua_dump_chunk, lua_undump, CodeGenBackend, CodeGenDriver, CodeGenError, LuaBackend,
+    OptimizeLevel,
+};
+use super::bytecode::{LuaByteCode, LuaConstants};
+use crate::test::fixtures::{self, *};
 
 fn generate_module<S1: AsRef<str>, S2: AsRef<str>>(decl: S1, body: S2, writer: &mut dyn Write) {
-    let mgr = UnitsManager::new();
-    let ctx = ModuleContext::new(ModuleKind::Application);
-    let mut lexer = StLexerBuilder::new().build_str(decl.as_ref());
-    let decl = ParserBuilder::default()
-        .build()
-        .parse_decl(&mut lexer)
-        .unwrap();
-    let fun_id = ctx.write().add_declaration(decl, Uuid::nil());
-
-    let mut lexer = StLexerBuilder::new().build_str(body.as_ref());
-    let body = ParserBuilder::default()
-        .build()
-        .parse_stmt(&mut lexer)
-        .unwrap();
-    ctx.write().add_function(fun_id, body);
+    generate_application(&[(decl.as_ref(), body.as_ref())], writer)
+}
 
-    mgr.write().add_context(ctx.clone());
-    mgr.write().set_active_application(Some(ctx.read().id()));
+/// Generate application with multiple POUs, each POU is (declaration, body)
+fn generate_application(pous: &[(&str, &str)], writer: &mut dyn Write) {
+    generate_optimized_application(pous, OptimizeLevel::None, writer)
+}
 
-    // Build
-    let ctx_id = ctx.read().id();
-    let mut code_gen: CodeGenDriver<LuaBackend> = CodeGenDriver::new(mgr.clone(), ctx_id).unwrap();
-    code_gen.build_application().expect("build app failed");
+fn generate_optimized_application(
+    pous: &[(&str, &str)],
+    level: OptimizeLevel,
+    writer: &mut dyn Write,
+) {
+    let mut code_gen = build_application(pous, level).expect("build app failed");
 
     code_gen
         .backend()
@@ -36,6 +31,14 @@ fn generate_module<S1: AsRef<str>, S2: AsRef<str>>(decl: S1, body: S2, writer: &
         .expect("get module bytes failed");
 }
 
+/// Build application, each POU is (declaration, body)
+fn build_application(
+    pous: &[(&str, &str)],
+    level: OptimizeLevel,
+) -> Result<CodeGenDriver<LuaBackend>, CodeGenError> {
+    fixtures::build_application(pous, |x| x.set_optimize_level(level))
+}
+
 fn exec_binary<S1: AsRef<str>, S2: AsRef<str>>(decl: S1, body: S2) -> (String, String) {
     // Write to temporary file
     let mut f = tempfile::Builder::new()
@@ -170,3 +173,830 @@ end_if";
     let r = lua.globals().get::<i32>("a");
     assert_eq!(r.unwrap(), 0);
 }
+
+fn exec_module<S1: AsRef<str>, S2: AsRef<str>>(decl: S1, body: S2) -> Lua {
+    // Generate to buffer
+    let mut buf = vec![];
+    generate_module(decl, body, &mut buf);
+
+    let lua = Lua::new();
+    if let Err(e) = lua.load(buf).set_mode(ChunkMode::Binary).exec() {
+        panic!("exec failed: {}", e);
+    }
+
+    lua
+}
+
+/// Execute application `cycles` times in the same Lua state, like PLC scan cycles. Cycles
+/// are 10ms apart in the time of timers.
+fn exec_application(pous: &[(&str, &str)], cycles: usize) -> Lua {
+    let mut buf = vec![];
+    generate_application(pous, &mut buf);
+
+    let lua = Lua::new();
+    lua.load("function stc_now() return stc_time end").exec().unwrap();
+    let main = lua
+        .load(buf)
+        .set_mode(ChunkMode::Binary)
+        .into_function()
+        .unwrap();
+    for i in 0..cycles {
+        lua.globals().set("stc_time", i as i64 * 10_000_000).unwrap();
+        if let Err(e) = main.call::<()>(()) {
+            panic!("exec failed: {}", e);
+        }
+    }
+
+    lua
+}
+
+#[test]
+fn test_bit_overflow() {
+    let decl = "PROGRAM main: VAR a, b, c: BIT; END_VAR END_PROGRAM";
+    let body = "a := 1; a := a + 1; b := 1; b := NOT b; c := 0; c := NOT c;";
+
+    let lua = exec_module(decl, body);
+    assert_eq!(lua.globals().get::<i64>("a").unwrap(), 0);
+    assert_eq!(lua.globals().get::<i64>("b").unwrap(), 0);
+    assert_eq!(lua.globals().get::<i64>("c").unwrap(), 1);
+}
+
+#[test]
+fn test_bool_operators() {
+    let decl = "PROGRAM main: VAR a, b, c, d, e: BOOL; END_VAR END_PROGRAM";
+    let body = "a := TRUE; b := NOT a; c := a AND b; d := a OR b; e := a XOR TRUE;";
+
+    let lua = exec_module(decl, body);
+    assert!(lua.globals().get::<bool>("a").unwrap());
+    assert!(!lua.globals().get::<bool>("b").unwrap());
+    assert!(!lua.globals().get::<bool>("c").unwrap());
+    assert!(lua.globals().get::<bool>("d").unwrap());
+    assert!(!lua.globals().get::<bool>("e").unwrap());
+}
+
+#[test]
+fn test_sint_overflow() {
+    let decl = "PROGRAM main: VAR a, b, c: SINT; END_VAR END_PROGRAM";
+    let body = "a := 127; a := a + 1; b := a - 1; c := 100; c := c * 3;";
+
+    let lua = exec_module(decl, body);
+    assert_eq!(lua.globals().get::<i64>("a").unwrap(), -128);
+    assert_eq!(lua.globals().get::<i64>("b").unwrap(), 127);
+    assert_eq!(lua.globals().get::<i64>("c").unwrap(), 44);
+}
+
+#[test]
+fn test_byte_overflow() {
+    let decl = "PROGRAM main: VAR a, b, c: BYTE; END_VAR END_PROGRAM";
+    let body = "a := 255; a := a + 1; b := a - 1; c := NOT a;";
+
+    let lua = exec_module(decl, body);
+    assert_eq!(lua.globals().get::<i64>("a").unwrap(), 0);
+    assert_eq!(lua.globals().get::<i64>("b").unwrap(), 255);
+    assert_eq!(lua.globals().get::<i64>("c").unwrap(), 255);
+}
+
+#[test]
+fn test_int_overflow() {
+    let decl = "PROGRAM main: VAR a, b, c, d, e: INT; END_VAR END_PROGRAM";
+    let body = "\
+a := 32767; a := a + 1; \
+b := 200; b := b * b; \
+c := -7; c := c / 2; \
+d := -7; d := d MOD 2; \
+e := 7; e := e MOD -2;";
+
+    let lua = exec_module(decl, body);
+    assert_eq!(lua.globals().get::<i64>("a").unwrap(), -32768);
+    assert_eq!(lua.globals().get::<i64>("b").unwrap(), -25536);
+    assert_eq!(lua.globals().get::<i64>("c").unwrap(), -3);
+    assert_eq!(lua.globals().get::<i64>("d").unwrap(), -1);
+    assert_eq!(lua.globals().get::<i64>("e").unwrap(), 1);
+}
+
+#[test]
+fn test_int_division_by_zero() {
+    let decl = "PROGRAM main: VAR a, b: INT; END_VAR END_PROGRAM";
+    let body = "a := 1; b := 0; a := a / b;";
+
+    let mut buf = vec![];
+    generate_module(decl, body, &mut buf);
+
+    let lua = Lua::new();
+    assert!(lua.load(buf).set_mode(ChunkMode::Binary).exec().is_err());
+}
+
+#[test]
+fn test_uint_overflow() {
+    let decl = "PROGRAM main: VAR a, b, c: UINT; END_VAR END_PROGRAM";
+    let body = "a := 65535; a := a + 1; b := a - 1; c := 300; c := c * c;";
+
+    let lua = exec_module(decl, body);
+    assert_eq!(lua.globals().get::<i64>("a").unwrap(), 0);
+    assert_eq!(lua.globals().get::<i64>("b").unwrap(), 65535);
+    assert_eq!(lua.globals().get::<i64>("c").unwrap(), 24464);
+}
+
+#[test]
+fn test_dint_overflow() {
+    let decl = "PROGRAM main: VAR a, b: DINT; END_VAR END_PROGRAM";
+    let body = "a := 2147483647; a := a + 1; b := a - 1;";
+
+    let lua = exec_module(decl, body);
+    assert_eq!(lua.globals().get::<i64>("a").unwrap(), -2147483648);
+    assert_eq!(lua.globals().get::<i64>("b").unwrap(), 2147483647);
+}
+
+#[test]
+fn test_udint_overflow() {
+    let decl = "PROGRAM main: VAR a, b: UDINT; END_VAR END_PROGRAM";
+    let body = "a := 4294967295; a := a + 1; b := a - 1;";
+
+    let lua = exec_module(decl, body);
+    assert_eq!(lua.globals().get::<i64>("a").unwrap(), 0);
+    assert_eq!(lua.globals().get::<i64>("b").unwrap(), 4294967295);
+}
+
+#[test]
+fn test_lint_overflow() {
+    let decl = "PROGRAM main: VAR a, b: LINT; END_VAR END_PROGRAM";
+    let body = "a := 9223372036854775807; a := a + 1; b := a - 1;";
+
+    let lua = exec_module(decl, body);
+    assert_eq!(lua.globals().get::<i64>("a").unwrap(), i64::MIN);
+    assert_eq!(lua.globals().get::<i64>("b").unwrap(), i64::MAX);
+}
+
+#[test]
+fn test_ulint_overflow() {
+    let decl = "PROGRAM main: VAR a, b: ULINT; c, d: BOOL; END_VAR END_PROGRAM";
+    let body = "\
+a := 18446744073709551615; b := a + 1; \
+c := a > 1; d := b < a;";
+
+    let lua = exec_module(decl, body);
+    assert_eq!(lua.globals().get::<i64>("a").unwrap() as u64, u64::MAX);
+    assert_eq!(lua.globals().get::<i64>("b").unwrap(), 0);
+    assert!(lua.globals().get::<bool>("c").unwrap());
+    assert!(lua.globals().get::<bool>("d").unwrap());
+}
+
+#[test]
+fn test_ulint_division() {
+    // operands not less than 2^63 are divided as unsigned
+    let decl = "PROGRAM main: VAR a, b, c, d, e, f, g: ULINT; END_VAR END_PROGRAM";
+    let body = "a := 18446744073709551615; b := 9223372036854775808; \
+        c := a / 3; d := a / b; e := b / a; f := a / 2; g := 10; g := g / 3;";
+
+    let lua = exec_module(decl, body);
+    let value = |name: &str| lua.globals().get::<i64>(name).unwrap() as u64;
+    assert_eq!(value("c"), u64::MAX / 3);
+    assert_eq!(value("d"), 1);
+    assert_eq!(value("e"), 0);
+    assert_eq!(value("f"), u64::MAX / 2);
+    assert_eq!(value("g"), 3);
+}
+
+#[test]
+fn test_real_arithmetic() {
+    let decl = "PROGRAM main: VAR a, b: REAL; END_VAR END_PROGRAM";
+    let body = "a := 7.0; a := a / 2.0; b := 1.5; b := b * 2.0 - 4.0;";
+
+    let lua = exec_module(decl, body);
+    assert_eq!(lua.globals().get::<f64>("a").unwrap(), 3.5);
+    assert_eq!(lua.globals().get::<f64>("b").unwrap(), -1.0);
+
+    // REAL is rounded to single precision
+    let decl = "PROGRAM main: VAR a, b, c, d, e: REAL; l: LREAL; END_VAR END_PROGRAM";
+    let body = "a := 0.1; l := 0.1; b := l; c := 1.0; c := c / 3.0; d := 16777217; \
+        e := 10000000000.0; e := e * e * e * e; l := e;";
+
+    let lua = exec_module(decl, body);
+    let value = |name: &str| lua.globals().get::<f64>(name).unwrap();
+    assert_eq!(value("a"), 0.1f32 as f64);
+    assert_eq!(value("b"), 0.1f32 as f64);
+    assert_eq!(value("c"), (1.0f32 / 3.0) as f64);
+    assert_eq!(value("d"), 16777216.0);
+    assert_eq!(value("e"), f64::INFINITY);
+    assert_eq!(value("l"), f64::INFINITY);
+
+    // intermediate results are not rounded
+    let decl = "PROGRAM main: VAR a: REAL := 16777216.0; END_VAR END_PROGRAM";
+    let lua = exec_module(decl, "a := a + 1.0 - 1.0;");
+    assert_eq!(lua.globals().get::<f64>("a").unwrap(), 16777216.0);
+}
+
+#[test]
+fn test_lreal_overflow() {
+    let decl = "PROGRAM main: VAR a: LREAL; b: BOOL; END_VAR END_PROGRAM";
+    let body = "\
+a := 10000000000.0; \
+a := a * a; a := a * a; a := a * a; a := a * a; a := a * a; \
+b := a > 1.0;";
+
+    let lua = exec_module(decl, body);
+    assert!(lua.globals().get::<f64>("a").unwrap().is_infinite());
+    assert!(lua.globals().get::<bool>("b").unwrap());
+}
+
+#[test]
+fn test_if_elseif_else() {
+    let decl = "PROGRAM main: VAR a, b: INT; END_VAR END_PROGRAM";
+    let body = "\
+a := 2; \
+if a = 1 then \
+    b := 10; \
+elseif a = 2 then \
+    b := 20; \
+else \
+    b := 30; \
+end_if";
+
+    let lua = exec_module(decl, body);
+    assert_eq!(lua.globals().get::<i64>("b").unwrap(), 20);
+}
+
+#[test]
+fn test_function_block_call() {
+    let main = (
+        "PROGRAM main: VAR c: Counter; a, b: INT; END_VAR END_PROGRAM",
+        "c(step := 2); c(step := 3); a := c.count; c(5, count => b);",
+    );
+
+    let lua = exec_application(&[COUNTER_FB, main], 1);
+    assert_eq!(lua.globals().get::<i64>("a").unwrap(), 5);
+    assert_eq!(lua.globals().get::<i64>("b").unwrap(), 10);
+}
+
+#[test]
+fn test_function_block_state() {
+    // instance is allocated once and keep states between cycles
+    let main = (
+        "PROGRAM main: VAR c: Counter; a: INT; END_VAR END_PROGRAM",
+        "c(step := 1); a := c.count;",
+    );
+
+    let lua = exec_application(&[COUNTER_FB, main], 3);
+    assert_eq!(lua.globals().get::<i64>("a").unwrap(), 3);
+
+    // members can be written directly
+    let main = (
+        "PROGRAM main: VAR c: Counter; a: INT; END_VAR END_PROGRAM",
+        "c.count := 100; c(step := 1); a := c.count;",
+    );
+
+    let lua = exec_application(&[COUNTER_FB, main], 1);
+    assert_eq!(lua.globals().get::<i64>("a").unwrap(), 101);
+}
+
+#[test]
+fn test_function_block_nested() {
+    let main = (
+        "PROGRAM main: VAR o: Outer; a, b: INT; END_VAR END_PROGRAM",
+        "o(); a := o.q; o(x := 200); b := o.q;",
+    );
+
+    let lua = exec_application(&[INNER_FB, OUTER_FB, main], 1);
+    assert_eq!(lua.globals().get::<i64>("a").unwrap(), 10);
+    // 200 wrapped into SINT is -56
+    assert_eq!(lua.globals().get::<i64>("b").unwrap(), 12 - 56);
+}
+
+#[test]
+fn test_function_block_in_function() {
+    // instances in functions are constructed on each call
+    let sum = (
+        "FUNCTION sum: INT VAR_INPUT n: INT; END_VAR VAR c: Counter; o: Outer; END_VAR END_FUNCTION",
+        "c(step := n); c(step := n); o(); sum := c.count + o.q;",
+    );
+    let main = (
+        "PROGRAM main: VAR a, b: INT; END_VAR END_PROGRAM",
+        "a := sum(3); b := sum(4);",
+    );
+
+    let lua = exec_application(&[COUNTER_FB, INNER_FB, OUTER_FB, sum, main], 1);
+    assert_eq!(lua.globals().get::<i64>("a").unwrap(), 6 + 10);
+    assert_eq!(lua.globals().get::<i64>("b").unwrap(), 8 + 10);
+}
+
+#[test]
+fn test_struct_default() {
+    let point = (
+        "TYPE Point: STRUCT x: INT; y: INT := 3; END_STRUCT END_TYPE",
+        "",
+    );
+    let line = (
+        "TYPE Line: STRUCT a: Point; b: Point; valid: BOOL := TRUE; END_STRUCT END_TYPE",
+        "",
+    );
+    let main = (
+        "PROGRAM main: VAR l: Line; x: INT; END_VAR END_PROGRAM",
+        "l.b.x := l.a.y + 1; x := l.b.x + l.b.y;",
+    );
+
+    let lua = exec_application(&[point, line, main], 1);
+    let l: mlua::Table = lua.globals().get("l").unwrap();
+    let a: mlua::Table = l.get("a").unwrap();
+    let b: mlua::Table = l.get("b").unwrap();
+    assert_eq!(a.get::<i64>("x").unwrap(), 0);
+    assert_eq!(a.get::<i64>("y").unwrap(), 3);
+    assert_eq!(b.get::<i64>("x").unwrap(), 4);
+    assert!(l.get::<bool>("valid").unwrap());
+    assert_eq!(lua.globals().get::<i64>("x").unwrap(), 7);
+}
+
+#[test]
+fn test_invalid_assignment() {
+    let decl = "PROGRAM main VAR x: INT; n: INT := 3; a: ARRAY[1..n] OF INT; END_VAR END_PROGRAM";
+    let cases = [
+        ("x := x[1];", "array bounds must be constant: x[1]"),
+        ("x[1] := 1;", "array bounds must be constant: x[1]"),
+        ("a[1] := x;", "array bounds must be constant: a[1]"),
+        ("1 := x;", "invalid assignment target: 1"),
+    ];
+    for (body, expected) in cases {
+        let err = build_application(&[(decl, body)], OptimizeLevel::None)
+            .err()
+            .expect("invalid assignment built");
+        assert_eq!(err.to_string(), expected);
+    }
+}
+
+#[test]
+fn test_invalid_member_access() {
+    let pous = NESTED_STRUCTS;
+
+    let cases = [
+        ("x := a.(b.c);", "invalid member access: a.b.c"),
+        ("a.(b.c) := 1;", "invalid member access: a.b.c"),
+        ("x := x.1;", "invalid member access: x.1"),
+    ];
+    for (body, expected) in cases {
+        let mut pous = pous.to_vec();
+        pous[2].1 = body;
+        let err = build_application(&pous, OptimizeLevel::None)
+            .err()
+            .expect("invalid member access built");
+        assert_eq!(err.to_string(), expected);
+    }
+
+    // nested members are accessed from left to right
+    let mut pous = pous.to_vec();
+    pous[2].1 = "a.b.c := 3; x := a.b.c;";
+    let lua = exec_application(&pous, 1);
+    assert_eq!(lua.globals().get::<i64>("x").unwrap(), 3);
+}
+
+#[test]
+fn test_array_lower_bound() {
+    let decl = "PROGRAM main: VAR a: ARRAY[-5..5] OF INT; i, b: INT; END_VAR END_PROGRAM";
+    let body = "a[-5] := 1; a[5] := 2; i := 0; a[i] := a[-5] + a[5]; b := a[-1];";
+
+    let lua = exec_module(decl, body);
+    let a: mlua::Table = lua.globals().get("a").unwrap();
+    assert_eq!(a.raw_len(), 11);
+    assert_eq!(a.get::<i64>(1).unwrap(), 1);
+    assert_eq!(a.get::<i64>(6).unwrap(), 3);
+    assert_eq!(a.get::<i64>(11).unwrap(), 2);
+    assert_eq!(lua.globals().get::<i64>("b").unwrap(), 0);
+}
+
+#[test]
+fn test_array_multi_dimensions() {
+    let point = ("TYPE Point: STRUCT x, y: DINT; END_STRUCT END_TYPE", "");
+    let main = (
+        "PROGRAM main: VAR \
+        m: ARRAY[1..2, 0..2] OF DINT; \
+        p: ARRAY[0..1] OF Point; \
+        a, b: DINT; \
+        END_VAR END_PROGRAM",
+        "m[2, 1] := 7; m[1, 2] := m[2, 1] * 2; a := m[1, 2]; p[1].y := a; b := p[1].y + p[0].y;",
+    );
+
+    let lua = exec_application(&[point, main], 1);
+    let m: mlua::Table = lua.globals().get("m").unwrap();
+    assert_eq!(m.raw_len(), 6);
+    // row-major order
+    assert_eq!(m.get::<i64>(5).unwrap(), 7);
+    assert_eq!(m.get::<i64>(3).unwrap(), 14);
+    assert_eq!(lua.globals().get::<i64>("a").unwrap(), 14);
+    assert_eq!(lua.globals().get::<i64>("b").unwrap(), 14);
+}
+
+#[test]
+fn test_array_out_of_range() {
+    let decl = "PROGRAM main: VAR a: ARRAY[1..3] OF INT; i: INT; END_VAR END_PROGRAM";
+    let body = "a[3] := 1;\ni := 4;\n  a[i] := 2;";
+
+    let (_, e) = exec_binary(decl, body);
+    assert!(
+        e.contains("array index out of range at line 3, column 3: a[i]"),
+        "Error: {}",
+        e
+    );
+
+    let decl = "PROGRAM main: VAR a: ARRAY[-2..2, 0..1] OF INT; b: INT; END_VAR END_PROGRAM";
+    let body = "b := a[-3, 0];";
+
+    let (_, e) = exec_binary(decl, body);
+    assert!(e.contains("array index out of range at line 1"), "Error: {}", e);
+}
+
+#[test]
+fn test_enum_values() {
+    let color = (
+        "TYPE Color: (Red, Green := 10, Blue) DINT; END_TYPE",
+        "",
+    );
+    let main = (
+        "PROGRAM main: VAR c: Color; d: Color := Color.Blue; a, b: DINT; x: BOOL; END_VAR END_PROGRAM",
+        "a := Green; b := Color.Blue + 1; x := c = Red AND d = Blue;",
+    );
+
+    let lua = exec_application(&[color, main], 1);
+    assert_eq!(lua.globals().get::<i64>("c").unwrap(), 0);
+    assert_eq!(lua.globals().get::<i64>("d").unwrap(), 11);
+    assert_eq!(lua.globals().get::<i64>("a").unwrap(), 10);
+    assert_eq!(lua.globals().get::<i64>("b").unwrap(), 12);
+    assert!(lua.globals().get::<bool>("x").unwrap());
+}
+
+#[test]
+fn test_runtime_error_line() {
+    let decl = "PROGRAM main: VAR a: INT; END_VAR END_PROGRAM";
+    let body = "a := 1;\nif a = 1 then\n    undefined_function(a);\nend_if";
+
+    let (_, e) = exec_binary(decl, body);
+    assert!(e.contains("main:3:"), "Error: {}", e);
+}
+
+#[test]
+fn test_max_stack_size() {
+    // each nested expression holds a temporary register
+    let decl = "PROGRAM main: VAR a, b: DINT; END_VAR END_PROGRAM";
+    let body = format!("a := 1; b := {}a{};", "a + (".repeat(100), ")".repeat(100));
+
+    let lua = exec_module(decl, body);
+    assert_eq!(lua.globals().get::<i64>("b").unwrap(), 101);
+}
+
+#[test]
+fn test_standard_numerical() {
+    let lua = exec_application(&[STANDARD_NUMERICAL], 1);
+    assert_eq!(lua.globals().get::<i64>("a").unwrap(), 3);
+    assert_eq!(lua.globals().get::<i64>("b").unwrap(), -32768);
+    assert_eq!(lua.globals().get::<f64>("r").unwrap(), 4.0);
+    assert_eq!(lua.globals().get::<f64>("l").unwrap(), 1026.0);
+    assert_eq!(lua.globals().get::<i64>("d").unwrap(), -2);
+}
+
+#[test]
+fn test_standard_bit_shift() {
+    let lua = exec_application(&[STANDARD_BIT_SHIFT], 1);
+    let values: Vec<i64> = ["a", "b", "c", "d", "e"]
+        .iter()
+        .map(|x| lua.globals().get(*x).unwrap())
+        .collect();
+    assert_eq!(values, [2, 64, 3, 192, 0]);
+}
+
+#[test]
+fn test_standard_selection() {
+    let lua = exec_application(&[STANDARD_SELECTION], 1);
+    let values: Vec<i64> = ["a", "b", "c", "d"]
+        .iter()
+        .map(|x| lua.globals().get(*x).unwrap())
+        .collect();
+    assert_eq!(values, [2, 6, 100, 20]);
+
+    let (_, e) = exec_binary(MUX_OUT_OF_RANGE.0, MUX_OUT_OF_RANGE.1);
+    assert!(e.contains("MUX K out of range"), "Error: {}", e);
+}
+
+#[test]
+fn test_standard_string() {
+    let decl = r#"PROGRAM main: VAR s: STRING := "hello"; a, b, c: INT; t1, t2, t3, t4, t5, t6, t7: STRING; END_VAR END_PROGRAM"#;
+    let body = r#"a := LEN(s); b := FIND(s, "lo"); c := FIND(s, "x"); t1 := LEFT(s, 2);
+        t2 := RIGHT(s, 9); t3 := MID(s, 3, 2); t4 := CONCAT(s, " ", "world");
+        t5 := INSERT(s, "XY", 2); t6 := DELETE(s, 2, 2); t7 := REPLACE(s, "L", 2, 3);"#;
+
+    let lua = exec_module(decl, body);
+    let values: Vec<i64> = ["a", "b", "c"]
+        .iter()
+        .map(|x| lua.globals().get(*x).unwrap())
+        .collect();
+    assert_eq!(values, [5, 4, 0]);
+    let strings: Vec<String> = ["t1", "t2", "t3", "t4", "t5", "t6", "t7"]
+        .iter()
+        .map(|x| lua.globals().get(*x).unwrap())
+        .collect();
+    assert_eq!(
+        strings,
+        ["he", "hello", "ell", "hello world", "heXYllo", "hlo", "heLo"]
+    );
+}
+
+#[test]
+fn test_standard_conversion() {
+    let lua = exec_application(&[STANDARD_CONVERSION], 1);
+    assert_eq!(lua.globals().get::<i64>("a").unwrap(), -3);
+    assert_eq!(lua.globals().get::<i64>("b").unwrap(), -56);
+    assert!(lua.globals().get::<bool>("c").unwrap());
+    assert_eq!(lua.globals().get::<f64>("r").unwrap(), 3.0);
+
+    let decl = "PROGRAM main: VAR a: INT; s: STRING; END_VAR END_PROGRAM";
+    let err = build_application(&[(decl, "s := INT_TO_STRING(a);")], OptimizeLevel::None)
+        .err()
+        .expect("INT_TO_STRING built");
+    assert_eq!(
+        err.to_string(),
+        "standard function not supported: INT_TO_STRING(a)"
+    );
+}
+
+#[test]
+fn test_standard_function_blocks() {
+    for (cycles, expected) in [(3, (false, 20000000, 1, false)), (4, (true, 25000000, 2, true))] {
+        let lua = exec_application(&[STANDARD_FUNCTION_BLOCKS], cycles);
+        let globals = lua.globals();
+        let values = (
+            globals.get::<bool>("q").unwrap(),
+            globals.get::<i64>("et").unwrap(),
+            globals.get::<i64>("cv").unwrap(),
+            globals.get::<bool>("cq").unwrap(),
+        );
+        assert_eq!(values, expected);
+    }
+}
+
+/// Load chunk and dump it again, the result must be the same as input
+fn chunk_round_trip(chunk: &[u8]) -> super::LuaCompiledCode {
+    let code = lua_undump(&mut &chunk[..]).expect("load chunk failed");
+
+    let mut buf = vec![];
+    lua_dump_chunk(&mut buf, &code).unwrap();
+    assert_eq!(buf, chunk);
+
+    code
+}
+
+#[test]
+fn test_chunk_round_trip() {
+    let fb = (
+        "FUNCTION_BLOCK Counter VAR_INPUT step: INT; END_VAR VAR count: INT; END_VAR END_FUNCTION_BLOCK",
+        "count := count + step;",
+    );
+    let main = (
+        "PROGRAM main: VAR c: Counter; a: ARRAY[1..3] OF INT; x: INT; END_VAR END_PROGRAM",
+        "c(step := 2);\nx := c.count;\na[2] := x;\nprint(x);",
+    );
+
+    let mut buf = vec![];
+    generate_application(&[fb, main], &mut buf);
+    let code = chunk_round_trip(&buf);
+    assert_eq!(code.protos.len(), 2);
+    assert_eq!(code.upvalues[0].name.as_deref(), Some("_ENV"));
+
+    // POUs are registered into _ENV by the main chunk, then the entry program is called
+    let listing = code.listing(true);
+    assert!(listing.starts_with("\nmain <main:0,0> ("), "{}", listing);
+    assert!(listing.contains("\tSETTABUP \t0 0 0\t; _ENV \"Counter\""), "{}", listing);
+    assert!(listing.contains("\tGETTABUP \t0 0 1\t; _ENV \"main\""), "{}", listing);
+    assert!(listing.contains("0+ params, "), "{}", listing);
+    assert!(listing.contains("[4]\tGETTABUP \t"), "{}", listing);
+    assert!(listing.contains("; _ENV \"print\""), "{}", listing);
+    assert!(listing.contains("\tFORPREP  \t"), "{}", listing);
+    assert!(listing.contains("upvalues (1) for "), "{}", listing);
+    assert!(listing.contains("\tSETFIELD \t0 0 1\t; \"count\""), "{}", listing);
+}
+
+#[test]
+fn test_chunk_vararg_programs() {
+    let idle = (
+        "PROGRAM idle: VAR n: INT; END_VAR END_PROGRAM",
+        "n := n + 1;",
+    );
+    let twice = (
+        "FUNCTION twice: INT VAR_INPUT x: INT; END_VAR END_FUNCTION",
+        "twice := x * 2;",
+    );
+    let main = ("PROGRAM main: VAR a: INT; END_VAR END_PROGRAM", "a := twice(21);");
+
+    // every program is an entry and vararg, functions have fixed parameters
+    let mut buf = vec![];
+    generate_application(&[idle, twice, main], &mut buf);
+    let code = chunk_round_trip(&buf);
+    let vararg: Vec<_> = code.protos.iter().map(|x| x.is_vararg).collect();
+    assert_eq!(vararg, [true, false, true]);
+}
+
+#[test]
+fn test_chunk_from_lua() {
+    let source = r##"
+        local t = {1, 2, 3, n = "a long string constant which is longer than forty bytes"}
+        local function counter(...)
+            local n = select("#", ...)
+            return function() n = n + 1; return n end
+        end
+        local s = ""
+        for k, v in ipairs(t) do s = s .. k .. v end
+        local x <close> = nil
+        local f = 1.5
+        if f < 2.0 and #t > 2 then f = f * 2.5 end
+        local o = { v = 1 }
+        function o:get() return self.v >> 1 end
+        return counter(t)(), s, o:get(), f
+    "##;
+
+    let lua = Lua::new();
+    let function = lua.load(source).into_function().unwrap();
+    for strip in [false, true] {
+        let chunk = function.dump(strip);
+        let code = chunk_round_trip(&chunk);
+
+        let listing = code.listing(true);
+        for op in ["VARARGPREP", "SETLIST", "TFORCALL", "CONCAT", "TBC", "SELF", "LEN", "CLOSURE", "SETUPVAL", "SHRI"] {
+            assert!(listing.contains(&format!("\t{:<9}\t", op)), "{} not found: {}", op, listing);
+        }
+
+        // dumped chunk is still runnable
+        let mut buf = vec![];
+        lua_dump_chunk(&mut buf, &code).unwrap();
+        let (a, s, b, f): (i64, String, i64, f64) =
+            lua.load(buf).set_mode(ChunkMode::Binary).call(()).unwrap();
+        assert_eq!((a, s.as_str(), b, f), (2, "112233", 0, 3.75));
+    }
+}
+
+#[test]
+fn test_chunk_constants() {
+    let lua = Lua::new();
+    let function = lua.load(r#"local s = "\xff\xfe"; return s, "\xff\xfe""#).into_function().unwrap();
+    let mut code = chunk_round_trip(&function.dump(false));
+    assert_eq!(code.constants, [LuaConstants::String(vec![0xff, 0xfe])]);
+
+    // equal constants are kept in the order of chunk
+    code.constants.push(code.constants[0].clone());
+    let last = code.byte_codes.iter().rposition(|x| matches!(x, LuaByteCode::LoadK(..))).unwrap();
+    if let LuaByteCode::LoadK(r, _) = code.byte_codes[last] {
+        code.byte_codes[last] = LuaByteCode::LoadK(r, 1);
+    }
+
+    let mut buf = vec![];
+    lua_dump_chunk(&mut buf, &code).unwrap();
+    let code = chunk_round_trip(&buf);
+    assert_eq!(code.constants.len(), 2);
+
+    let (a, b): (mlua::String, mlua::String) = lua.load(buf).set_mode(ChunkMode::Binary).call(()).unwrap();
+    assert_eq!(a.as_bytes().to_vec(), b"\xff\xfe");
+    assert_eq!(b.as_bytes().to_vec(), b"\xff\xfe");
+}
+
+#[test]
+fn test_undump_invalid_chunk() {
+    let err = lua_undump(&mut &b"print(1)"[..]).unwrap_err();
+    assert_eq!(err.to_string(), "not a binary chunk");
+
+    // truncated chunk
+    let mut buf = vec![];
+    generate_module("PROGRAM main: VAR a: INT; END_VAR END_PROGRAM", "a := 1;", &mut buf);
+    assert!(lua_undump(&mut &buf[..buf.len() - 4]).is_err());
+}
+
+/// Instructions count of function and all nested functions
+fn instructions_count(code: &super::LuaCompiledCode) -> usize {
+    code.byte_codes.len() + code.protos.iter().map(instructions_count).sum::<usize>()
+}
+
+/// Tables are compared by content
+fn value_string(v: mlua::Value) -> String {
+    match v {
+        mlua::Value::Table(t) => {
+            let mut fields: Vec<_> = t
+                .pairs::<mlua::Value, mlua::Value>()
+                .map(|x| x.unwrap())
+                .map(|(k, v)| format!("{}={}", value_string(k), value_string(v)))
+                .collect();
+            fields.sort();
+            format!("{{{}}}", fields.join(", "))
+        }
+        v => format!("{}: {}", v.type_name(), v.to_string().unwrap()),
+    }
+}
+
+/// Execute application with optimize level, returns the globals or error and the instructions count
+fn exec_optimized(
+    pous: &[(&str, &str)],
+    level: OptimizeLevel,
+    globals: &[&str],
+) -> (Result<Vec<String>, String>, usize) {
+    let mut buf = vec![];
+    generate_optimized_application(pous, level, &mut buf);
+    let count = instructions_count(&lua_undump(&mut &buf[..]).unwrap());
+
+    let lua = Lua::new();
+    let main = lua
+        .load(buf)
+        .set_mode(ChunkMode::Binary)
+        .into_function()
+        .unwrap();
+    let r = (0..2)
+        .try_for_each(|_| main.call::<()>(()))
+        .map(|_| {
+            globals
+                .iter()
+                .map(|x| value_string(lua.globals().get(*x).unwrap()))
+                .collect()
+        })
+        .map_err(|e| e.to_string());
+
+    (r, count)
+}
+
+#[test]
+fn test_optimize_levels() {
+    let point = ("TYPE Point: STRUCT x, y: DINT; END_STRUCT END_TYPE", "");
+    let color = ("TYPE Color: (Red, Green := 10, Blue) DINT; END_TYPE", "");
+
+    let applications = [
+        (
+            vec![(
+                "PROGRAM main: VAR a, b, c, d: INT; e: SINT; u: UINT; END_VAR END_PROGRAM",
+                "a := 17; b := 5; c := a / b; d := a MOD b; e := e + 100; u := u - 3;\n\
+                a := a * 3 - 2; b := 2 * b + 1000; c := c AND 6 OR 1;",
+            )],
+            vec!["a", "b", "c", "d", "e", "u"],
+        ),
+        (
+            vec![(
+                "PROGRAM main: VAR a, b: DINT; x, y, z: BOOL; r: REAL; END_VAR END_PROGRAM",
+                "a := a + 1; x := a < 5; y := 3 <= a; z := a = 2;\n\
+                if a = 1 then\n b := 10;\nelseif a > 2 then\n b := 20;\nelse\n b := b + 1;\nend_if\n\
+                r := r + 1.5; r := r * 2.0;",
+            )],
+            vec!["a", "b", "x", "y", "z", "r"],
+        ),
+        (
+            vec![
+                COUNTER_FB,
+                INNER_FB,
+                OUTER_FB,
+                (
+                    "PROGRAM main: VAR c: Counter; o: Outer; a, b: INT; END_VAR END_PROGRAM",
+                    "c(step := 2); a := c.count; o(x := 100); b := o.q;",
+                ),
+            ],
+            vec!["a", "b", "c", "o"],
+        ),
+        (
+            vec![
+                point,
+                color,
+                (
+                    "PROGRAM main: VAR \
+                    m: ARRAY[1..2, 0..2] OF DINT; \
+                    p: ARRAY[0..1] OF Point; \
+                    c: Color := Color.Blue; \
+                    a, b: DINT; \
+                    END_VAR END_PROGRAM",
+                    "m[2, 1] := m[2, 1] + 7; p[1].y := m[2, 1] * 2; a := p[1].y + p[0].x; b := c + Green;",
+                ),
+            ],
+            vec!["m", "p", "a", "b"],
+        ),
+        // runtime errors are raised at the same line
+        (
+            vec![(
+                "PROGRAM main: VAR a: ARRAY[1..3] OF INT; i: INT; END_VAR END_PROGRAM",
+                "i := i + 2;\na[i] := 1;",
+            )],
+            vec!["a"],
+        ),
+        (
+            vec![(
+                "PROGRAM main: VAR a, b: INT; END_VAR END_PROGRAM",
+                "a := 1;\nb := a / b;",
+            )],
+            vec!["a"],
+        ),
+    ];
+
+    // total instructions count of each level
+    let mut counts = [0; 3];
+    for (pous, globals) in &applications {
+        let (expected, count) = exec_optimized(pous, OptimizeLevel::None, globals);
+        counts[0] += count;
+
+        for (i, level) in [OptimizeLevel::Basic, OptimizeLevel::Full]
+            .into_iter()
+            .enumerate()
+        {
+            let (r, optimized_count) = exec_optimized(pous, level, globals);
+            assert_eq!(r, expected, "{:?} of {:?}", level, pous);
+            assert!(optimized_count <= count);
+            counts[i + 1] += optimized_count;
+        }
+    }
+
+    assert!(counts[2] < counts[0], "{:?}", counts);
+}
@@ -0,0 +1,306 @@
+use std::io;
+use std::io::Read;
+
+use byteorder::{LittleEndian, ReadBytesExt};
+
+use super::bytecode::{LuaByteCode, LuaCompiledCode, LuaConstants, LuaLocalVariable, LuaUpValue};
+use super::dump::{ABSLINEINFO, LUAC_DATA, LUAC_FORMAT, LUAC_INT, LUAC_NUMBER, LUAC_VERSION, LUA_SIGNATURE};
+use super::LuaVarKind;
+
+/// Sizes are read from chunk, at most this number of items is reserved before they are read,
+/// so a corrupt size fails at the end of chunk instead of reserving all of it
+const MAX_RESERVED_ITEMS: usize = 4096;
+
+/// Load a Lua 5.4 binary chunk, nested functions of main function are loaded into `protos`
+pub fn lua_undump(r: &mut dyn Read) -> io::Result<LuaCompiledCode> {
+    lua_load_header(r)?;
+
+    // size of UpValues of main function, it's also saved in function
+    let _ = lua_load_byte(r)?;
+
+    lua_load_function(r)
+}
+
+fn lua_load_header(r: &mut dyn Read) -> io::Result<()> {
+    let signature = lua_load_bytes(r, LUA_SIGNATURE.len())?;
+    if signature != LUA_SIGNATURE.as_bytes() {
+        return Err(invalid_data("not a binary chunk"));
+    }
+
+    if lua_load_byte(r)? != LUAC_VERSION {
+        return Err(invalid_data("version mismatch"));
+    }
+    if lua_load_byte(r)? != LUAC_FORMAT {
+        return Err(invalid_data("format mismatch"));
+    }
+    if lua_load_bytes(r, LUAC_DATA.len())? != LUAC_DATA {
+        return Err(invalid_data("corrupted chunk"));
+    }
+
+    // size of Lua instruction, integer and Number
+    for (size, name) in [(4, "Instruction"), (8, "lua_Integer"), (8, "lua_Number")] {
+        if lua_load_byte(r)? != size {
+            return Err(invalid_data(format!("{} size mismatch", name)));
+        }
+    }
+
+    if r.read_u64::<LittleEndian>()? != LUAC_INT {
+        return Err(invalid_data("integer format mismatch"));
+    }
+    if r.read_f64::<LittleEndian>()? != LUAC_NUMBER {
+        return Err(invalid_data("float format mismatch"));
+    }
+
+    Ok(())
+}
+
+fn lua_load_function(r: &mut dyn Read) -> io::Result<LuaCompiledCode> {
+    let source = lua_load_string(r)?;
+    let line_defined = lua_load_size(r)? as u32;
+    let last_line_defined = lua_load_size(r)? as u32;
+    let num_params = lua_load_byte(r)?;
+    let is_vararg = lua_load_byte(r)? != 0;
+    let max_stack_size = lua_load_byte(r)?;
+
+    // Code
+    let n = lua_load_size(r)?;
+    let mut byte_codes = Vec::with_capacity(reserved_items(n));
+    for _ in 0..n {
+        let word = r.read_u32::<LittleEndian>()?;
+        // instruction must be encoded back to the same word, otherwise some fields are lost
+        let code = LuaByteCode::decode(word)
+            .filter(|x| x.encode() == word)
+            .ok_or_else(|| invalid_data(format!("unsupported instruction {:08X}", word)))?;
+
+        byte_codes.push(code);
+    }
+
+    // Constants
+    let n = lua_load_size(r)?;
+    let mut constants = Vec::with_capacity(reserved_items(n));
+    for _ in 0..n {
+        let tag = lua_load_byte(r)?;
+        let constant = match tag {
+            0x00 => LuaConstants::Nil,
+            0x01 => LuaConstants::Boolean(false),
+            0x11 => LuaConstants::Boolean(true),
+            0x03 => LuaConstants::Integer(r.read_i64::<LittleEndian>()?),
+            0x13 => LuaConstants::Float(r.read_f64::<LittleEndian>()?),
+            // short string and long string
+            0x04 | 0x14 => match lua_load_byte_string(r)? {
+                Some(s) => LuaConstants::String(s),
+                None => return Err(invalid_data("null string constant")),
+            },
+            _ => return Err(invalid_data(format!("unknown constant type {:#04x}", tag))),
+        };
+
+        // constants are referenced by index, equal constants are kept
+        constants.push(constant);
+    }
+
+    // UpValues
+    let n = lua_load_size(r)?;
+    let mut upvalues = Vec::with_capacity(reserved_items(n));
+    for _ in 0..n {
+        upvalues.push(LuaUpValue {
+            name: None,
+            stack: lua_load_byte(r)?,
+            index: lua_load_byte(r)?,
+            kind: LuaVarKind::from_bits_retain(lua_load_byte(r)?),
+        });
+    }
+
+    // Protos
+    let n = lua_load_size(r)?;
+    let mut protos = Vec::with_capacity(reserved_items(n));
+    for _ in 0..n {
+        protos.push(lua_load_function(r)?);
+    }
+
+    // Debug line info
+    let n = lua_load_size(r)?;
+    let line_info: Vec<_> = lua_load_bytes(r, n as usize)?.into_iter().map(|x| x as i8).collect();
+
+    // Debug abs line info
+    let n = lua_load_size(r)?;
+    let mut abs_line_info = Vec::with_capacity(reserved_items(n));
+    for _ in 0..n {
+        abs_line_info.push((lua_load_size(r)? as u32, lua_load_size(r)? as u32));
+    }
+
+    let line_info = lua_decode_line_info(line_defined, &line_info, &abs_line_info)?;
+
+    // Debug loc vars
+    let n = lua_load_size(r)?;
+    let mut local_variables = Vec::with_capacity(reserved_items(n));
+    for _ in 0..n {
+        local_variables.push(LuaLocalVariable {
+            name: lua_load_string(r)?,
+            start_pc: lua_load_size(r)? as u32,
+            end_pc: lua_load_size(r)? as u32,
+        });
+    }
+
+    // Debug upvalue names
+    let n = lua_load_size(r)?;
+    if n as usize > upvalues.len() {
+        return Err(invalid_data("too many upvalue names"));
+    }
+    for upv in upvalues.iter_mut().take(n as usize) {
+        upv.name = lua_load_string(r)?;
+    }
+
+    Ok(LuaCompiledCode {
+        byte_codes,
+        constants,
+        upvalues,
+        protos,
+        num_params,
+        is_vararg,
+        max_stack_size,
+        source,
+        line_defined,
+        last_line_defined,
+        line_info,
+        local_variables,
+    })
+}
+
+/// Restore line of each instruction from relative line info and absolute line info, the
+/// reverse of `lua_line_info`
+fn lua_decode_line_info(line_defined: u32, line_info: &[i8], abs_line_info: &[(u32, u32)]) -> io::Result<Vec<u32>> {
+    let mut lines = Vec::with_capacity(line_info.len());
+    let mut abs_lines = abs_line_info.iter();
+    let mut line = line_defined as i64;
+
+    for (pc, diff) in line_info.iter().enumerate() {
+        if *diff == ABSLINEINFO {
+            match abs_lines.next() {
+                Some((abs_pc, abs_line)) if *abs_pc as usize == pc => line = *abs_line as i64,
+                _ => return Err(invalid_data("bad absolute line info")),
+            }
+        } else {
+            line += *diff as i64;
+        }
+
+        if line < 0 {
+            return Err(invalid_data("bad line info"));
+        }
+        lines.push(line as u32);
+    }
+
+    Ok(lines)
+}
+
+#[inline]
+fn lua_load_byte(r: &mut dyn Read) -> io::Result<u8> {
+    r.read_u8()
+}
+
+/// Bytes are read in steps, the buffer grows only with the bytes actually read
+fn lua_load_bytes(r: &mut dyn Read, len: usize) -> io::Result<Vec<u8>> {
+    let mut buf = Vec::with_capacity(len.min(MAX_RESERVED_ITEMS));
+    r.take(len as u64).read_to_end(&mut buf)?;
+    if buf.len() != len {
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "unexpected end of chunk"));
+    }
+
+    Ok(buf)
+}
+
+fn lua_load_size(r: &mut dyn Read) -> io::Result<u64> {
+    let mut size = 0u64;
+
+    loop {
+        let b = lua_load_byte(r)?;
+        if size > u64::MAX >> 7 {
+            return Err(invalid_data("integer overflow"));
+        }
+
+        size = size << 7 | (b & 0x7f) as u64;
+
+        // last byte is marked
+        if b & 0x80 != 0 {
+            return Ok(size);
+        }
+    }
+}
+
+/// Names in debug info, they are required to be valid UTF-8
+fn lua_load_string(r: &mut dyn Read) -> io::Result<Option<String>> {
+    match lua_load_byte_string(r)? {
+        Some(bytes) => String::from_utf8(bytes)
+            .map(Some)
+            .map_err(|_| invalid_data("string is not valid UTF-8")),
+        None => Ok(None),
+    }
+}
+
+fn lua_load_byte_string(r: &mut dyn Read) -> io::Result<Option<Vec<u8>>> {
+    match lua_load_size(r)? {
+        0 => Ok(None),
+        size => lua_load_bytes(r, size as usize - 1).map(Some),
+    }
+}
+
+#[inline]
+fn reserved_items(n: u64) -> usize {
+    n.min(MAX_RESERVED_ITEMS as u64) as usize
+}
+
+#[inline]
+fn invalid_data<S: Into<String>>(msg: S) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.into())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::backend::lua::dump::lua_dump_header;
+    use crate::backend::lua::undump::{lua_decode_line_info, lua_load_size, lua_undump};
+    use std::io;
+
+    #[test]
+    fn test_lua_load_size() {
+        for (bytes, size) in [(&[0x80][..], 0), (&[0xff], 127), (&[0x01, 0x80], 128), (&[0x05, 0x9c], 668)] {
+            let mut r = bytes;
+            assert_eq!(lua_load_size(&mut r).unwrap(), size);
+        }
+    }
+
+    #[test]
+    fn test_lua_decode_line_info() {
+        let lines = lua_decode_line_info(0, &[1, 0, 1, -1], &[]).unwrap();
+        assert_eq!(lines, vec![1, 1, 2, 1]);
+
+        let lines = lua_decode_line_info(0, &[1, -0x80, 1], &[(1, 200)]).unwrap();
+        assert_eq!(lines, vec![1, 200, 201]);
+
+        // absolute line info doesn't match the instruction
+        assert!(lua_decode_line_info(0, &[1, -0x80, 1], &[(2, 200)]).is_err());
+    }
+
+    #[test]
+    fn test_lua_undump_corrupt_size() {
+        // size of 2^56 - 1 in the last 8 bytes
+        let huge_size = [0x7f, 0x7f, 0x7f, 0x7f, 0x7f, 0x7f, 0x7f, 0xff];
+        let mut header = vec![];
+        lua_dump_header(&mut header).unwrap();
+        // size of UpValues
+        header.push(1);
+
+        // size of source
+        let mut chunk = header.clone();
+        chunk.extend_from_slice(&huge_size);
+        chunk.extend_from_slice(b"main");
+        let e = lua_undump(&mut chunk.as_slice()).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::UnexpectedEof);
+
+        // size of code, after source, lines, params, vararg and max stack size
+        let mut chunk = header;
+        chunk.extend_from_slice(&[0x80, 0x80, 0x80, 0, 1, 2]);
+        chunk.extend_from_slice(&huge_size);
+        chunk.extend_from_slice(&[0x51, 0, 0, 0]);
+        let e = lua_undump(&mut chunk.as_slice()).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::UnexpectedEof);
+    }
+}
@@ -1,13 +1,20 @@
 use crate::parser::{BitValue, LiteralValue};
 
-use super::{Prototype, VariableFlags};
+use super::{Prototype, TypeClass, VariableFlags};
+use crate::backend::utils::{pou_kind, PouKind};
+
+pub use crate::backend::utils::{
+    array_dimensions, enum_values, integer_width, is_float_type, subrange_bounds, wider_class,
+};
 
 /// sBx use 17 Bits
 const SBX_BIT_SIZE: u32 = 17;
-/// Max value of sBx is 2^18 - 1
-const SBX_MAX_VALUE: i32 = 2_i32.pow(SBX_BIT_SIZE + 1) - 1;
-/// Min value of sBx is -2^18
-const SBX_MIN_VALUE: i32 = 0 - 2_i32.pow(SBX_BIT_SIZE);
+/// Excess of sBx is (2^17 - 1) / 2
+const SBX_OFFSET: i32 = (2_i32.pow(SBX_BIT_SIZE) - 1) / 2;
+/// Max value of sBx is 2^17 - 1 - offset
+const SBX_MAX_VALUE: i32 = 2_i32.pow(SBX_BIT_SIZE) - 1 - SBX_OFFSET;
+/// Min value of sBx is -offset
+const SBX_MIN_VALUE: i32 = -SBX_OFFSET;
 /// sBx Mask, lower 17 Bits is 1
 const SBX_MASK: u32 = 0b0001_1111_1111_1111_1111;
 
@@ -21,7 +28,13 @@ pub fn try_fit_sbx(literal: &LiteralValue) -> Option<i32> {
         LiteralValue::Byte(v) => Some(v as i32),
         LiteralValue::SInt(v) => Some(v as i32),
         LiteralValue::Int(v) => Some(v as i32),
-        LiteralValue::UInt(v) => Some(v as i32),
+        LiteralValue::UInt(v) => {
+            if v as i32 <= SBX_MAX_VALUE {
+                Some(v as i32)
+            } else {
+                None
+            }
+        }
         LiteralValue::DInt(v) => {
             if (SBX_MIN_VALUE..=SBX_MAX_VALUE).contains(&v) {
                 Some(v)
@@ -54,6 +67,35 @@ pub fn try_fit_sbx(literal: &LiteralValue) -> Option<i32> {
     }
 }
 
+/// Returns true if integer constant can fit into sBx value
+#[inline]
+pub fn fit_sbx(v: i64) -> Option<i32> {
+    if (SBX_MIN_VALUE as i64..=SBX_MAX_VALUE as i64).contains(&v) {
+        Some(v as i32)
+    } else {
+        None
+    }
+}
+
+/// Wrap a Lua integer into the value range of IEC integer type.
+/// 64 bits values are kept as the two's complement bit pattern, so ULINT is stored as i64.
+pub fn wrap_integer(v: i64, class: TypeClass) -> i64 {
+    match integer_width(class) {
+        Some((64, _)) | None => v,
+        Some((bits, signed)) => {
+            let mask = (1i64 << bits) - 1;
+            let unsigned = v & mask;
+
+            if signed {
+                let sign = 1i64 << (bits - 1);
+                (unsigned ^ sign) - sign
+            } else {
+                unsigned
+            }
+        }
+    }
+}
+
 #[inline]
 pub fn num_params(p: &Prototype) -> u8 {
     let proto = p.read().unwrap();
@@ -66,14 +108,25 @@ pub fn num_params(p: &Prototype) -> u8 {
         .count() as u8
 }
 
+/// Programs are entries and vararg like Lua main chunk, other functions have fixed parameters
 #[inline]
-pub fn is_vararg(_p: &Prototype) -> bool {
-    // TODO:
-    true
+pub fn is_vararg(p: &Prototype) -> bool {
+    pou_kind(p.read().unwrap().decl()) == Some(PouKind::Program)
 }
 
+/// Registers 0/1 are always valid in Lua, `maxstacksize` at least 2
 #[inline]
-pub fn max_stack_size(_p: &Prototype) -> u8 {
-    // TODO:
-    64
+pub fn max_stack_size(registers: u8, num_params: u8) -> u8 {
+    registers.max(num_params).max(2)
 }
+
+/// Encoded hash size for NEWTABLE, ceil(log2(n)) + 1 or 0 for empty hash part
+#[inline]
+pub fn table_hash_size(n: usize) -> u8 {
+    if n == 0 {
+        0
+    } else {
+        (usize::BITS - (n - 1).leading_zeros() + 1) as u8
+    }
+}
+
@@ -8,10 +8,27 @@ pub use llvm::{LLVMBackend, LLVMBackendContext, LLVMModuleBuilder};
 mod lua;
 
 #[cfg(feature = "lua_backend")]
-pub use lua::LuaBackend;
+pub use lua::{lua_dump_chunk, lua_undump, LuaBackend, LuaCompiledCode, LuaExecutor};
 
+#[cfg(feature = "c_backend")]
+mod c;
+
+#[cfg(feature = "c_backend")]
+pub use c::{CBackend, CCompiledCode};
+
+#[cfg(feature = "wasm_backend")]
+mod wasm;
+
+#[cfg(feature = "wasm_backend")]
+pub use wasm::{WasmBackend, WasmCompiledCode};
+
+pub(crate) mod utils;
+
+use crate::analysis::{check_declarations, fold_constants, ConstError, DeclarationError};
 use crate::ast::{OperatorExpression, Variable};
-use crate::context::{ModuleContext, UnitsManager};
+use crate::context::{ModuleContext, Scope, UnitsManager};
+use crate::serde::BuildCache;
+use utils::add_standard_function_blocks;
 
 use bitflags::bitflags;
 use log::info;
@@ -32,6 +49,17 @@ bitflags! {
     }
 }
 
+/// Optimization of generated code
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum OptimizeLevel {
+    #[default]
+    None,
+    /// Cheap transformations, like jump threading and unreachable code removal
+    Basic,
+    /// All optimizations
+    Full,
+}
+
 pub trait CodeGenBackend {
     type Label;
 
@@ -42,6 +70,29 @@ pub trait CodeGenBackend {
     fn gen_variable_load(&mut self, variable: &mut Variable);
     fn gen_operator(&mut self, operator: &mut OperatorExpression);
     fn get_module_bytes(&mut self, w: &mut dyn Write) -> io::Result<()>;
+
+    /// Backends don't support optimization ignore the level
+    fn set_optimize_level(&mut self, _level: OptimizeLevel) {}
+
+    /// Constant expressions are folded into literals before generating functions if true
+    fn fold_constants(&self) -> bool {
+        false
+    }
+
+    /// Check values assigned to subrange variables at runtime, backends without range check
+    /// ignore it
+    fn set_range_check(&mut self, _enabled: bool) {}
+
+    /// Bytes of compiled function saved by incremental builds, None if backend can't load
+    /// the function from bytes
+    fn save_function(&self, _code: &dyn CompiledCode) -> Option<Vec<u8>> {
+        None
+    }
+
+    /// Compiled function saved by `save_function`
+    fn load_function(&self, _bytes: &[u8]) -> Option<Box<dyn CompiledCode>> {
+        None
+    }
 }
 
 pub trait CompiledCode: Display + Send + Sync {
@@ -52,6 +103,10 @@ pub trait CompiledCode: Display + Send + Sync {
 pub enum CodeGenError {
     AppNotFound,
     FunctionNotDefined(usize),
+    InvalidConstant(String, ConstError),
+    InvalidDeclaration(DeclarationError),
+    /// Expression can't be generated by backend, and the reason
+    InvalidExpression(String, String),
 }
 
 impl Error for CodeGenError {}
@@ -63,6 +118,9 @@ impl Display for CodeGenError {
             CodeGenError::FunctionNotDefined(func) => {
                 f.write_str(&format!("Function {} not defined", func))
             }
+            CodeGenError::InvalidConstant(expr, e) => write!(f, "{}: {}", expr, e),
+            CodeGenError::InvalidDeclaration(e) => Display::fmt(e, f),
+            CodeGenError::InvalidExpression(expr, reason) => write!(f, "{}: {}", reason, expr),
         }
     }
 }
@@ -80,6 +138,8 @@ where
     mgr: UnitsManager,
     app: ModuleContext,
     backend: B,
+    optimize_level: OptimizeLevel,
+    range_check: bool,
 }
 
 impl<B> CodeGenDriver<B>
@@ -96,6 +156,8 @@ where
             mgr: mgr.clone(),
             app: app.clone(),
             backend: B::new(mgr, app),
+            optimize_level: OptimizeLevel::None,
+            range_check: false,
         })
     }
 
@@ -103,7 +165,34 @@ where
         &mut self.backend
     }
 
+    pub fn set_optimize_level(&mut self, level: OptimizeLevel) {
+        self.optimize_level = level;
+    }
+
+    /// Generate runtime checks of values assigned to subrange variables
+    pub fn set_range_check(&mut self, enabled: bool) {
+        self.range_check = enabled;
+    }
+
     pub fn build_application(&mut self) -> Result<(), CodeGenError> {
+        self.build(None)
+    }
+
+    /// Build application with codes of reused units in cache, codes of other functions are
+    /// saved into cache if backend supports
+    pub fn build_application_with_cache(
+        &mut self,
+        cache: &mut BuildCache,
+    ) -> Result<(), CodeGenError> {
+        self.build(Some(cache))
+    }
+
+    fn build(&mut self, mut cache: Option<&mut BuildCache>) -> Result<(), CodeGenError> {
+        add_standard_function_blocks(&self.app);
+        if let Some(e) = check_declarations(&self.mgr, &self.app).into_iter().next() {
+            return Err(CodeGenError::InvalidDeclaration(e));
+        }
+
         let mut decl_info: Vec<_> = self
             .app
             .read()
@@ -113,6 +202,8 @@ where
         decl_info.sort_by_key(|(x, _)| *x);
 
         let mut backend = B::new(self.mgr.clone(), self.app.clone());
+        backend.set_optimize_level(self.optimize_level);
+        backend.set_range_check(self.range_check);
         for (decl_id, proto) in decl_info {
             let proto = proto.read().unwrap();
             if !proto.is_type_declaration() {
@@ -125,7 +216,31 @@ where
                     .ok_or(CodeGenError::FunctionNotDefined(decl_id))?
                     .clone();
 
+                let saved = cache
+                    .as_ref()
+                    .and_then(|x| x.unit(&proto.decl().qualified_name())?.code.as_deref())
+                    .and_then(|x| backend.load_function(x));
+                if let Some(code) = saved {
+                    info!("reuse code of function {} {}", decl_id, proto);
+                    f.write().set_compiled_code(code);
+                    continue;
+                }
+
+                if backend.fold_constants() {
+                    let app_id = self.app.read().id();
+                    let scope = Scope::new(Some(self.mgr.clone()), Some(app_id), Some(decl_id));
+                    let errors = fold_constants(f.write().parse_tree_mut(), &scope);
+                    if let Some((expr, e)) = errors.into_iter().next() {
+                        return Err(CodeGenError::InvalidConstant(expr, e));
+                    }
+                }
+
                 let target_code = backend.gen_function(decl_id)?;
+                if let Some(cache) = cache.as_mut() {
+                    if let Some(bytes) = backend.save_function(target_code.as_ref()) {
+                        cache.set_code(&proto.decl().qualified_name(), bytes);
+                    }
+                }
                 f.write().set_compiled_code(target_code);
             }
         }
@@ -0,0 +1,625 @@
+//! Helpers shared by backends and the interpreter working on the AST directly
+
+use crate::analysis::{
+    argument_parameters, eval_constant, instantiate, is_extensible, ConstantScope,
+    FunctionInstance, GenericError,
+};
+use crate::builtin::standard_function_block;
+use crate::parser::{BitValue, LiteralValue, Operator, PACK_MODE_ATTRIBUTE};
+use crate::prelude::*;
+use crate::utils::HasAttribute;
+
+use std::sync::Arc;
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PouKind {
+    Function,
+    FunctionBlock,
+    Program,
+}
+
+pub fn pou_kind(decl: &Declaration) -> Option<PouKind> {
+    match &decl.kind {
+        DeclKind::Fun(f) => match f.class() {
+            DeclareClass::Function => Some(PouKind::Function),
+            DeclareClass::FunctionBlock => Some(PouKind::FunctionBlock),
+            DeclareClass::Program => Some(PouKind::Program),
+            _ => None,
+        },
+        DeclKind::FB(_) => Some(PouKind::FunctionBlock),
+        DeclKind::Prg(_) => Some(PouKind::Program),
+        _ => None,
+    }
+}
+
+/// Alignment of struct members set by `{attribute 'pack_mode' := 'n'}`, n is 1, 2, 4 or 8
+pub fn pack_mode(decl: &Declaration) -> Option<u32> {
+    let value = decl
+        .get_attribute_value(&PACK_MODE_ATTRIBUTE.into())?
+        .as_ref()?;
+
+    value
+        .trim()
+        .parse()
+        .ok()
+        .filter(|x| matches!(x, 1 | 2 | 4 | 8))
+}
+
+/// Bit width and signedness of IEC integer types, None for other types
+pub fn integer_width(class: TypeClass) -> Option<(u32, bool)> {
+    match class {
+        TypeClass::Bit => Some((1, false)),
+        TypeClass::Byte => Some((8, false)),
+        TypeClass::SInt => Some((8, true)),
+        TypeClass::Int => Some((16, true)),
+        TypeClass::UInt => Some((16, false)),
+        TypeClass::DInt => Some((32, true)),
+        TypeClass::UDInt => Some((32, false)),
+        TypeClass::LInt => Some((64, true)),
+        TypeClass::ULInt => Some((64, false)),
+        // TIME is a signed count of nanoseconds
+        TypeClass::Time => Some((64, true)),
+        _ => None,
+    }
+}
+
+/// Minimum and maximum values of IEC integer types, None for other types
+pub fn integer_range(class: TypeClass) -> Option<(i128, i128)> {
+    let (bits, signed) = integer_width(class)?;
+    Some(match signed {
+        true => (-(1 << (bits - 1)), (1 << (bits - 1)) - 1),
+        false => (0, (1 << bits) - 1),
+    })
+}
+
+/// Integer value of literal, None for non-integer literals
+pub fn literal_integer(literal: &LiteralValue) -> Option<i64> {
+    match *literal {
+        LiteralValue::Bit(BitValue::Zero) => Some(0),
+        LiteralValue::Bit(BitValue::One) => Some(1),
+        LiteralValue::Byte(v) => Some(v as i64),
+        LiteralValue::SInt(v) => Some(v as i64),
+        LiteralValue::Int(v) => Some(v as i64),
+        LiteralValue::UInt(v) => Some(v as i64),
+        LiteralValue::DInt(v) => Some(v as i64),
+        LiteralValue::UDInt(v) => Some(v as i64),
+        LiteralValue::LInt(v) => Some(v),
+        LiteralValue::ULInt(v) => Some(v as i64),
+        LiteralValue::Time(v) => Some(v),
+        _ => None,
+    }
+}
+
+/// Integer value of constant expression, only literals and negative literals are supported
+pub fn const_integer(expr: &Expression) -> Option<i64> {
+    match &expr.kind {
+        ExprKind::Literal(lit) => literal_integer(lit.literal()),
+        ExprKind::Operator(op)
+            if matches!(op.op(), Operator::Minus) && op.operands().len() == 1 =>
+        {
+            const_integer(&op.operands()[0]).map(|x| x.wrapping_neg())
+        }
+        _ => None,
+    }
+}
+
+/// Values of enum fields, field without explicit value is the previous value plus one
+pub fn enum_values(decl: &EnumDeclare) -> Vec<(StString, i64)> {
+    let mut next = 0;
+    decl.fields()
+        .iter()
+        .map(|field| {
+            let value = field
+                .initial()
+                .as_ref()
+                .and_then(|x| const_integer(x))
+                .unwrap_or(next);
+            next = value.wrapping_add(1);
+
+            (field.name().clone(), value)
+        })
+        .collect()
+}
+
+/// Lower bound and length of each array dimension, bounds are constant expressions which
+/// may refer to 'CONSTANT' variables and enum members of scope. None if bounds are not
+/// constant.
+pub fn array_dimensions(
+    arr: &ArrayType,
+    scope: &dyn ConstantScope,
+) -> Option<SmallVec3<(i64, i64)>> {
+    arr.dimensions()
+        .iter()
+        .map(|dim| {
+            let lower = eval_constant(dim.lower(), scope).ok()?.as_i64()?;
+            let upper = eval_constant(dim.upper(), scope).ok()?.as_i64()?;
+
+            Some((lower, (upper - lower + 1).max(0)))
+        })
+        .collect()
+}
+
+/// Lower and upper bounds of subrange type, None if bounds are not constant
+pub fn subrange_bounds(sub: &SubrangeType, scope: &dyn ConstantScope) -> Option<(i64, i64)> {
+    let lower = eval_constant(sub.range().lower(), scope).ok()?.as_i64()?;
+    let upper = eval_constant(sub.range().upper(), scope).ok()?.as_i64()?;
+
+    Some((lower, upper))
+}
+
+#[inline]
+pub fn is_float_type(class: TypeClass) -> bool {
+    matches!(class, TypeClass::Real | TypeClass::LReal)
+}
+
+/// Returns the wider type of two operands, None stands for untyped integer literal
+pub fn wider_class(a: Option<TypeClass>, b: Option<TypeClass>) -> Option<TypeClass> {
+    fn rank(class: TypeClass) -> u8 {
+        match class {
+            TypeClass::Bit | TypeClass::Bool => 0,
+            TypeClass::SInt | TypeClass::Byte => 1,
+            TypeClass::Int | TypeClass::UInt => 2,
+            TypeClass::DInt | TypeClass::UDInt => 3,
+            TypeClass::LInt | TypeClass::ULInt | TypeClass::Time => 4,
+            TypeClass::Real => 5,
+            TypeClass::LReal => 6,
+            _ => 7,
+        }
+    }
+
+    match (a, b) {
+        (Some(a), Some(b)) if rank(b) > rank(a) => Some(b),
+        (Some(a), _) => Some(a),
+        (None, b) => b,
+    }
+}
+
+/// Name of variable expression
+pub fn expression_variable_name(expr: &Expression) -> Option<&StString> {
+    match &expr.kind {
+        ExprKind::Variable(var) => Some(var.name()),
+        _ => None,
+    }
+}
+
+/// Outputs and in-outs of functions are passed by reference
+#[inline]
+pub fn is_reference_parameter(variable: &Variable) -> bool {
+    variable
+        .flags()
+        .intersects(VariableFlags::OUTPUT | VariableFlags::INOUT)
+}
+
+/// Return type of function, None for function blocks and programs
+pub fn function_return_type(proto: &Prototype) -> Option<Type> {
+    match &proto.read().unwrap().decl().kind {
+        DeclKind::Fun(f) if matches!(f.class(), DeclareClass::Function) => f.return_type().clone(),
+        _ => None,
+    }
+}
+
+/// All declarations of application, ordered by id
+pub fn sorted_declarations(app: &ModuleContext) -> Vec<Prototype> {
+    let mut decls: Vec<_> = app.read().declarations().cloned().collect();
+    decls.sort_by_key(|x| x.read().unwrap().id());
+
+    decls
+}
+
+/// Add standard function blocks which are types of variables in application and not
+/// declared by it, so their bodies are compiled like function blocks of application
+pub fn add_standard_function_blocks(app: &ModuleContext) {
+    let mut used: Vec<StString> = vec![];
+    for decl in app.read().declarations() {
+        for variable in decl.read().unwrap().variables() {
+            let Some(mut ty) = variable.ty().cloned() else {
+                continue;
+            };
+            while let Some(base) = ty.as_array().map(|x| x.base_type().clone()) {
+                ty = base;
+            }
+            if let Some(name) = ty.user_type_name().filter(|x| !used.contains(x)) {
+                used.push(name.clone());
+            }
+        }
+    }
+
+    for name in used {
+        if app.read().find_declaration_by_name(&name).is_some() {
+            continue;
+        }
+        let Some((decl, body)) = standard_function_block(&name) else {
+            continue;
+        };
+
+        let mut app = app.write();
+        let id = app.add_declaration(decl, Uuid::new_v4());
+        app.add_function(id, body);
+    }
+}
+
+/// Argument expressions bound to parameters, named arguments are bound by name,
+/// positional arguments are bound to inputs and in-outs in declaration order.
+pub fn bind_arguments<'a>(
+    params: &[Arc<Variable>],
+    call: &'a CallExpression,
+) -> Vec<Option<&'a Expression>> {
+    let mut bound = vec![None; params.len()];
+    let mut positional = params
+        .iter()
+        .enumerate()
+        .filter(|(_, x)| {
+            x.flags()
+                .intersects(VariableFlags::INPUT | VariableFlags::INOUT)
+        })
+        .map(|(idx, _)| idx);
+
+    for arg in call.arguments() {
+        let (idx, value) = match &arg.kind {
+            ExprKind::Assign(assign) => {
+                let name = expression_variable_name(assign.left());
+                let idx = params.iter().position(|x| Some(x.name()) == name);
+                (idx, assign.right())
+            }
+            _ => (positional.next(), arg),
+        };
+
+        // TODO: unknown parameter error
+        if let Some(idx) = idx {
+            bound[idx] = Some(value);
+        }
+    }
+
+    bound
+}
+
+/// Call of standard function, arguments are in order of parameters and the repeated
+/// inputs of extensible function are at the end
+#[derive(Debug, Clone)]
+pub struct StandardCall {
+    /// Upper case name of function, like `ABS` or `INT_TO_REAL`
+    pub name: String,
+    /// Index of argument expression in call and concrete type class of its parameter,
+    /// index is None if input isn't connected
+    pub arguments: Vec<(Option<usize>, TypeClass)>,
+    pub return_class: TypeClass,
+}
+
+impl StandardCall {
+    /// Instantiate standard function `decl` for call, `arguments` are the type classes of
+    /// call arguments
+    pub fn new(
+        decl: &Prototype,
+        call: &CallExpression,
+        arguments: &[Option<TypeClass>],
+    ) -> Result<Self, GenericError> {
+        let instance = instantiate(decl, call, arguments)?;
+        let (name, params) = {
+            let decl = decl.read().unwrap();
+            (
+                decl.name().string().to_ascii_uppercase(),
+                decl.variables().to_vec(),
+            )
+        };
+        let bound = argument_parameters(&params, call, is_extensible(decl));
+
+        let class_of = |ty: Option<&Type>| ty.map_or(TypeClass::LInt, |x| x.type_class());
+        let mut args = vec![];
+        for (idx, ty) in instance.parameters.iter().enumerate() {
+            let class = class_of(ty.as_ref());
+            let len = args.len();
+            args.extend(
+                bound
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, x)| **x == Some(idx))
+                    .map(|(arg, _)| (Some(arg), class)),
+            );
+
+            if args.len() == len {
+                args.push((None, class));
+            }
+        }
+
+        Ok(Self {
+            name,
+            arguments: args,
+            return_class: class_of(instance.return_type.as_ref()),
+        })
+    }
+
+    /// Source and target type classes of conversion function like `INT_TO_REAL`
+    pub fn conversion(&self) -> Option<(TypeClass, TypeClass)> {
+        self.name.contains("_TO_").then(|| {
+            let from = self.arguments.first().map_or(TypeClass::LInt, |(_, x)| *x);
+            (from, self.return_class)
+        })
+    }
+}
+
+/// Value expression of call argument, named argument `x := value` returns `value`
+pub fn argument_value(arg: &Expression) -> &Expression {
+    match &arg.kind {
+        ExprKind::Assign(assign) => assign.right(),
+        _ => arg,
+    }
+}
+
+/// Types of declarations and expressions in the POU which code is generated for
+pub struct TypeContext {
+    mgr: UnitsManager,
+    app: ModuleContext,
+    pub scope: Scope,
+    pub pou_kind: Option<PouKind>,
+    // declared name of current POU
+    pub pou_name: StString,
+    pub return_type: Option<Type>,
+    /// Concrete types of type families in generic function instance
+    pub families: Vec<(TypeFamily, TypeClass)>,
+}
+
+impl TypeContext {
+    pub fn new(mgr: UnitsManager, app: ModuleContext) -> Self {
+        Self {
+            mgr,
+            app,
+            scope: Scope::default(),
+            pou_kind: None,
+            pou_name: StString::empty(),
+            return_type: None,
+            families: vec![],
+        }
+    }
+
+    #[inline]
+    pub fn app(&self) -> &ModuleContext {
+        &self.app
+    }
+
+    pub fn enter_declaration(&mut self, proto: &Prototype) {
+        let (id, name, kind) = {
+            let p = proto.read().unwrap();
+            (p.id(), p.name().clone(), pou_kind(p.decl()))
+        };
+
+        let app_id = self.app.read().id();
+        self.scope = Scope::new(Some(self.mgr.clone()), Some(app_id), Some(id));
+        self.pou_kind = kind;
+        self.pou_name = name;
+        self.return_type = function_return_type(proto);
+        self.families.clear();
+    }
+
+    /// Find declaration of application by qualified name, or by the name in the namespaces
+    /// of current declaration
+    pub fn find_declaration(&self, name: &StString) -> Option<Prototype> {
+        let decl = self.app.read().find_declaration_by_name(name).cloned();
+        decl.or_else(|| {
+            let (decl, _) = self.scope.resolve_declaration(name).ok()?;
+            let id = decl.read().unwrap().id();
+            self.app.read().get_declaration_by_id(id).cloned()
+        })
+    }
+
+    /// Find standard function or function block of the builtin module
+    pub fn builtin_declaration(&self, name: &StString) -> Option<Prototype> {
+        let builtin = self.mgr.read().builtin_context();
+        let decl = builtin.read().find_declaration_by_name(name).cloned();
+        decl
+    }
+
+    /// Concrete signature of function call, arguments decide the types of generic
+    /// parameters
+    pub fn call_instance(
+        &self,
+        call: &CallExpression,
+        decl: &Prototype,
+    ) -> Result<FunctionInstance, GenericError> {
+        let arguments: Vec<_> = call
+            .arguments()
+            .iter()
+            .map(|arg| match &arg.kind {
+                ExprKind::Assign(assign) => self.expression_class(assign.right()),
+                _ => self.expression_class(arg),
+            })
+            .collect();
+
+        instantiate(decl, call, &arguments)
+    }
+
+    /// Call of standard function, None if callee is declared in application or isn't a
+    /// standard function
+    pub fn standard_call(
+        &self,
+        call: &CallExpression,
+    ) -> Option<Result<StandardCall, GenericError>> {
+        let name = expression_variable_name(call.callee())?;
+        if self.find_declaration(name).is_some() {
+            return None;
+        }
+
+        let decl = self.builtin_declaration(name)?;
+        if pou_kind(decl.read().unwrap().decl()) != Some(PouKind::Function) {
+            return None;
+        }
+
+        let arguments: Vec<_> = call
+            .arguments()
+            .iter()
+            .map(|arg| self.expression_class(argument_value(arg)))
+            .collect();
+        Some(StandardCall::new(&decl, call, &arguments))
+    }
+
+    /// Find declaration of user type, like function block, struct, enum or alias. Standard
+    /// function blocks are shadowed by declarations of application.
+    pub fn type_user_decl(&self, ty: &Type) -> Option<Prototype> {
+        let name = ty.user_type_name()?;
+        self.find_declaration(name)
+            .or_else(|| self.builtin_declaration(name))
+    }
+
+    /// Follow aliases to the underlying type
+    pub fn resolve_type(&self, ty: &Type) -> Type {
+        let mut ty = ty.clone();
+
+        // limit the depth in case of recursive aliases
+        for _ in 0..16 {
+            let Some(decl) = self.type_user_decl(&ty) else {
+                break;
+            };
+            let alias = match &decl.read().unwrap().decl().kind {
+                DeclKind::Alias(alias) => alias.alias().clone(),
+                _ => break,
+            };
+            ty = alias;
+        }
+
+        ty
+    }
+
+    /// Type class of type, enums are in class of their base type and type families are in
+    /// class of the function instance being generated
+    pub fn type_class(&self, ty: &Type) -> TypeClass {
+        let ty = self.resolve_type(ty);
+        if let TypeClass::Generic(family) = ty.type_class() {
+            if let Some((_, class)) = self.families.iter().find(|(x, _)| *x == family) {
+                return *class;
+            }
+        }
+
+        if let Some(decl) = self.type_user_decl(&ty) {
+            if let DeclKind::Enum(e) = &decl.read().unwrap().decl().kind {
+                return e.ty().as_ref().map_or(TypeClass::DInt, |x| x.type_class());
+            }
+        }
+
+        ty.type_class()
+    }
+
+    /// Value of enum member, `enum_name` is None for unqualified member name
+    #[inline]
+    pub fn enum_member(&self, enum_name: Option<&StString>, member: &StString) -> Option<i64> {
+        self.app.enum_member(enum_name, member)
+    }
+
+    /// First member of enum type, which is the default value
+    pub fn enum_default(&self, ty: &Type) -> Option<i64> {
+        let decl = self.type_user_decl(&self.resolve_type(ty))?;
+        let decl = decl.read().unwrap();
+        let DeclKind::Enum(e) = &decl.decl().kind else {
+            return None;
+        };
+
+        Some(enum_values(e).first().map(|(_, v)| *v).unwrap_or(0))
+    }
+
+    /// Bounds of subrange type, aliases are resolved
+    pub fn subrange(&self, ty: &Type) -> Option<(i64, i64)> {
+        subrange_bounds(self.resolve_type(ty).as_subrange()?, self)
+    }
+
+    /// Default value of enum and subrange types, which is the first member or the lower
+    /// bound
+    pub fn default_integer(&self, ty: &Type) -> Option<i64> {
+        self.subrange(ty)
+            .map(|(lower, _)| lower)
+            .or_else(|| self.enum_default(ty))
+    }
+
+    /// Find the member variable of compo access expression
+    pub fn compo_variable(&self, compo: &CompoAccessExpression) -> Option<Arc<Variable>> {
+        let field = expression_variable_name(compo.right())?;
+        let ty = self.resolve_type(&self.expression_type(compo.left())?);
+        let decl = self.type_user_decl(&ty)?;
+        let decl = decl.read().unwrap();
+
+        decl.variables().iter().find(|x| x.name() == field).cloned()
+    }
+
+    #[inline]
+    pub fn is_return_variable(&self, name: &StString) -> bool {
+        self.return_type.is_some() && name == &self.pou_name
+    }
+
+    /// Get declared type of variable, member, array element or function call expression
+    pub fn expression_type(&self, expr: &Expression) -> Option<Type> {
+        match &expr.kind {
+            ExprKind::Variable(var) if self.is_return_variable(var.name()) => {
+                self.return_type.clone()
+            }
+            ExprKind::Variable(var) => self.scope.find_variable(var.name())?.ty().cloned(),
+            ExprKind::Compo(compo) => self.compo_variable(compo)?.ty().cloned(),
+            ExprKind::ArrayAccess(access) => access.ty().or_else(|| {
+                let ty = self.resolve_type(&self.expression_type(access.array())?);
+                ty.as_array().map(|x| x.base_type().clone())
+            }),
+            ExprKind::Call(call) => {
+                let name = expression_variable_name(call.callee())?;
+                let decl = self
+                    .find_declaration(name)
+                    .or_else(|| self.builtin_declaration(name))?;
+                let kind = pou_kind(decl.read().unwrap().decl());
+                match kind {
+                    Some(PouKind::Function) => self.call_instance(call, &decl).ok()?.return_type,
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+
+    /// Get type class of expression, untyped integer literals will be None
+    pub fn expression_class(&self, expr: &Expression) -> Option<TypeClass> {
+        if let Some(ty) = expr.ty() {
+            return Some(self.type_class(ty));
+        }
+
+        match &expr.kind {
+            ExprKind::Literal(lit) => match lit.literal() {
+                LiteralValue::Bool(_)
+                | LiteralValue::Real(_)
+                | LiteralValue::LReal(_)
+                | LiteralValue::Time(_)
+                | LiteralValue::String(_) => Some(lit.literal().ty().type_class()),
+                _ => None,
+            },
+            ExprKind::Variable(_)
+            | ExprKind::Compo(_)
+            | ExprKind::ArrayAccess(_)
+            | ExprKind::Call(_) => self.expression_type(expr).map(|ty| self.type_class(&ty)),
+            ExprKind::Assign(assign) => self.expression_class(assign.left()),
+            ExprKind::Operator(op_expr) => match op_expr.op() {
+                Operator::Less
+                | Operator::LessEqual
+                | Operator::Greater
+                | Operator::GreaterEqual
+                | Operator::Equal
+                | Operator::NotEqual => Some(TypeClass::Bool),
+                _ => self.operands_class(op_expr.operands()),
+            },
+            _ => None,
+        }
+    }
+
+    /// The wider type class of operands
+    pub fn operands_class(&self, operands: &[Expression]) -> Option<TypeClass> {
+        let class = self.expression_class(&operands[0]);
+        operands.iter().skip(1).fold(class, |class, x| {
+            wider_class(class, self.expression_class(x))
+        })
+    }
+}
+
+impl ConstantScope for TypeContext {
+    fn find_variable(&self, name: &StString) -> Option<Arc<Variable>> {
+        self.scope
+            .find_variable(name)
+            .or_else(|| self.app.read().find_toplevel_global_variable(name))
+    }
+
+    fn enum_member(&self, enum_name: Option<&StString>, member: &StString) -> Option<i64> {
+        TypeContext::enum_member(self, enum_name, member)
+    }
+}
@@ -0,0 +1,150 @@
+use std::any::Any;
+use std::fmt::{Display, Formatter, Write as _};
+use std::io;
+use std::io::Write;
+
+use super::instruction::{write_unsigned, Callee, Instruction, ValType};
+use crate::backend::CompiledCode;
+use crate::parser::StString;
+
+/// Function of wasm module, calls are resolved when the module is written
+#[derive(Debug, Clone)]
+pub struct WasmFunction {
+    pub name: String,
+    pub callee: Callee,
+    pub params: Vec<ValType>,
+    pub results: Vec<ValType>,
+    /// Locals excluding parameters
+    pub locals: Vec<ValType>,
+    /// Instructions without the final `end`
+    pub body: Vec<Instruction>,
+}
+
+impl WasmFunction {
+    /// Encode body of function as an entry of code section
+    pub fn encode(&self, buf: &mut Vec<u8>, resolve: &dyn Fn(&Callee) -> u32) {
+        let mut code = vec![];
+
+        // locals are compressed as runs of the same type
+        let mut runs: Vec<(u32, ValType)> = vec![];
+        for ty in self.locals.iter() {
+            match runs.last_mut() {
+                Some((n, last)) if last == ty => *n += 1,
+                _ => runs.push((1, *ty)),
+            }
+        }
+        write_unsigned(&mut code, runs.len() as u64);
+        for (n, ty) in runs {
+            write_unsigned(&mut code, n as u64);
+            code.push(ty.code());
+        }
+
+        for instruction in self.body.iter() {
+            instruction.encode(&mut code, resolve);
+        }
+        Instruction::End.encode(&mut code, resolve);
+
+        write_unsigned(buf, code.len() as u64);
+        buf.extend_from_slice(&code);
+    }
+
+    /// Function in text format, `type_index` is the index of its type in module
+    pub fn write_text(
+        &self,
+        s: &mut String,
+        type_index: Option<usize>,
+        name: &dyn Fn(&Callee) -> String,
+    ) {
+        write!(s, "  (func ${}", self.name).unwrap();
+        if let Some(idx) = type_index {
+            write!(s, " (type {})", idx).unwrap();
+        }
+        for param in self.params.iter() {
+            write!(s, " (param {})", param).unwrap();
+        }
+        for result in self.results.iter() {
+            write!(s, " (result {})", result).unwrap();
+        }
+        s.push('\n');
+
+        if !self.locals.is_empty() {
+            let locals: Vec<_> = self.locals.iter().map(|x| x.to_string()).collect();
+            writeln!(s, "    (local {})", locals.join(" ")).unwrap();
+        }
+
+        let mut depth = 2;
+        for instruction in self.body.iter() {
+            if matches!(instruction, Instruction::End | Instruction::Else) {
+                depth -= 1;
+            }
+            writeln!(s, "{}{}", "  ".repeat(depth), instruction.text(name)).unwrap();
+            if matches!(
+                instruction,
+                Instruction::Block(_)
+                    | Instruction::Loop(_)
+                    | Instruction::If(_)
+                    | Instruction::Else
+            ) {
+                depth += 1;
+            }
+        }
+        s.push_str("  )\n");
+    }
+}
+
+/// Name of callee without module, used for listing functions of a POU alone
+pub fn callee_name(callee: &Callee) -> String {
+    match callee {
+        Callee::Body(id) => format!("body_{}", id),
+        Callee::Init(id) => format!("init_{}", id),
+        Callee::Runtime(r) => r.name().to_owned(),
+        Callee::External(name) => name.string().to_owned(),
+    }
+}
+
+/// Wasm functions of a POU, function blocks have an init function and a body function
+pub struct WasmCompiledCode {
+    functions: Vec<WasmFunction>,
+    /// Functions called but not declared in application, with types of parameters
+    externals: Vec<(StString, Vec<ValType>)>,
+}
+
+impl WasmCompiledCode {
+    pub fn new(functions: Vec<WasmFunction>, externals: Vec<(StString, Vec<ValType>)>) -> Self {
+        Self {
+            functions,
+            externals,
+        }
+    }
+
+    #[inline]
+    pub fn functions(&self) -> &[WasmFunction] {
+        &self.functions
+    }
+
+    #[inline]
+    pub fn externals(&self) -> &[(StString, Vec<ValType>)] {
+        &self.externals
+    }
+}
+
+impl Display for WasmCompiledCode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let mut s = String::new();
+        for function in self.functions.iter() {
+            function.write_text(&mut s, None, &callee_name);
+        }
+
+        f.write_str(&s)
+    }
+}
+
+impl CompiledCode for WasmCompiledCode {
+    fn get_bytes(&self, w: &mut dyn Write) -> io::Result<()> {
+        w.write_all(self.to_string().as_bytes())
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
@@ -0,0 +1,578 @@
+use crate::prelude::StString;
+
+use std::fmt::{self, Display, Formatter};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ValType {
+    I32,
+    I64,
+    F32,
+    F64,
+}
+
+impl ValType {
+    pub fn code(&self) -> u8 {
+        match self {
+            ValType::I32 => 0x7f,
+            ValType::I64 => 0x7e,
+            ValType::F32 => 0x7d,
+            ValType::F64 => 0x7c,
+        }
+    }
+}
+
+impl Display for ValType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ValType::I32 => write!(f, "i32"),
+            ValType::I64 => write!(f, "i64"),
+            ValType::F32 => write!(f, "f32"),
+            ValType::F64 => write!(f, "f64"),
+        }
+    }
+}
+
+/// Result type of block, loop and if
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlockType {
+    Empty,
+    Value(ValType),
+}
+
+/// Functions provided by the module or imported from the runtime, ordered as in module
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Runtime {
+    /// Imported `stc_runtime_error(message: i32, len: i32)`, message is UTF-8 in memory
+    Error,
+    /// Imported `stc_pow_f64(f64, f64) -> f64`
+    PowF64,
+    /// Imported math functions like `stc_sin_f64(f64) -> f64`
+    LnF64,
+    Log10F64,
+    ExpF64,
+    SinF64,
+    CosF64,
+    TanF64,
+    AsinF64,
+    AcosF64,
+    AtanF64,
+    /// Imported `stc_now() -> i64`, current time of timers in nanoseconds
+    Now,
+    Index,
+    DivI32,
+    DivU32,
+    DivI64,
+    DivU64,
+    ModI32,
+    ModU32,
+    ModI64,
+    ModU64,
+    PowI64,
+    PowU64,
+    /// Bit shifts `stc_shl(value, n, bits) -> i64` of the low `bits` bits
+    Shl,
+    Shr,
+    Rol,
+    Ror,
+    FModF32,
+    FModF64,
+    StringAssign,
+    StringCompare,
+    /// `stc_init()` initializes global variables and variables of programs
+    Init,
+}
+
+impl Runtime {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Runtime::Error => "stc_runtime_error",
+            Runtime::PowF64 => "stc_pow_f64",
+            Runtime::LnF64 => "stc_ln_f64",
+            Runtime::Log10F64 => "stc_log10_f64",
+            Runtime::ExpF64 => "stc_exp_f64",
+            Runtime::SinF64 => "stc_sin_f64",
+            Runtime::CosF64 => "stc_cos_f64",
+            Runtime::TanF64 => "stc_tan_f64",
+            Runtime::AsinF64 => "stc_asin_f64",
+            Runtime::AcosF64 => "stc_acos_f64",
+            Runtime::AtanF64 => "stc_atan_f64",
+            Runtime::Now => "stc_now",
+            Runtime::Index => "stc_index",
+            Runtime::DivI32 => "stc_div_i32",
+            Runtime::DivU32 => "stc_div_u32",
+            Runtime::DivI64 => "stc_div_i64",
+            Runtime::DivU64 => "stc_div_u64",
+            Runtime::ModI32 => "stc_mod_i32",
+            Runtime::ModU32 => "stc_mod_u32",
+            Runtime::ModI64 => "stc_mod_i64",
+            Runtime::ModU64 => "stc_mod_u64",
+            Runtime::PowI64 => "stc_pow_i64",
+            Runtime::PowU64 => "stc_pow_u64",
+            Runtime::Shl => "stc_shl",
+            Runtime::Shr => "stc_shr",
+            Runtime::Rol => "stc_rol",
+            Runtime::Ror => "stc_ror",
+            Runtime::FModF32 => "stc_fmod_f32",
+            Runtime::FModF64 => "stc_fmod_f64",
+            Runtime::StringAssign => "stc_string_assign",
+            Runtime::StringCompare => "stc_string_compare",
+            Runtime::Init => "stc_init",
+        }
+    }
+
+    /// Function is imported from the runtime
+    #[inline]
+    pub fn is_import(&self) -> bool {
+        matches!(
+            self,
+            Runtime::Error
+                | Runtime::PowF64
+                | Runtime::LnF64
+                | Runtime::Log10F64
+                | Runtime::ExpF64
+                | Runtime::SinF64
+                | Runtime::CosF64
+                | Runtime::TanF64
+                | Runtime::AsinF64
+                | Runtime::AcosF64
+                | Runtime::AtanF64
+                | Runtime::Now
+        )
+    }
+
+    /// Parameters and results of imported function
+    pub fn import_type(&self) -> (Vec<ValType>, Vec<ValType>) {
+        match self {
+            Runtime::Error => (vec![ValType::I32, ValType::I32], vec![]),
+            Runtime::PowF64 => (vec![ValType::F64, ValType::F64], vec![ValType::F64]),
+            Runtime::Now => (vec![], vec![ValType::I64]),
+            _ if self.is_import() => (vec![ValType::F64], vec![ValType::F64]),
+            _ => unreachable!("{:?} is not imported", self),
+        }
+    }
+}
+
+/// Function called by `call`, indexes are resolved when the module is written
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Callee {
+    /// Body of POU with declaration id
+    Body(usize),
+    /// Init function of struct or function block with declaration id
+    Init(usize),
+    Runtime(Runtime),
+    /// Function not declared in application, imported from the runtime
+    External(StString),
+}
+
+/// Load and store instructions, alignment is always the natural alignment
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemoryOp {
+    I32Load,
+    I64Load,
+    F32Load,
+    F64Load,
+    I32Load8S,
+    I32Load8U,
+    I32Load16S,
+    I32Load16U,
+    I32Store,
+    I64Store,
+    F32Store,
+    F64Store,
+    I32Store8,
+    I32Store16,
+}
+
+impl MemoryOp {
+    pub fn opcode(&self) -> u8 {
+        match self {
+            MemoryOp::I32Load => 0x28,
+            MemoryOp::I64Load => 0x29,
+            MemoryOp::F32Load => 0x2a,
+            MemoryOp::F64Load => 0x2b,
+            MemoryOp::I32Load8S => 0x2c,
+            MemoryOp::I32Load8U => 0x2d,
+            MemoryOp::I32Load16S => 0x2e,
+            MemoryOp::I32Load16U => 0x2f,
+            MemoryOp::I32Store => 0x36,
+            MemoryOp::I64Store => 0x37,
+            MemoryOp::F32Store => 0x38,
+            MemoryOp::F64Store => 0x39,
+            MemoryOp::I32Store8 => 0x3a,
+            MemoryOp::I32Store16 => 0x3b,
+        }
+    }
+
+    /// log2 of alignment
+    pub fn align(&self) -> u32 {
+        match self {
+            MemoryOp::I32Load8S | MemoryOp::I32Load8U | MemoryOp::I32Store8 => 0,
+            MemoryOp::I32Load16S | MemoryOp::I32Load16U | MemoryOp::I32Store16 => 1,
+            MemoryOp::I32Load | MemoryOp::F32Load | MemoryOp::I32Store | MemoryOp::F32Store => 2,
+            MemoryOp::I64Load | MemoryOp::F64Load | MemoryOp::I64Store | MemoryOp::F64Store => 3,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            MemoryOp::I32Load => "i32.load",
+            MemoryOp::I64Load => "i64.load",
+            MemoryOp::F32Load => "f32.load",
+            MemoryOp::F64Load => "f64.load",
+            MemoryOp::I32Load8S => "i32.load8_s",
+            MemoryOp::I32Load8U => "i32.load8_u",
+            MemoryOp::I32Load16S => "i32.load16_s",
+            MemoryOp::I32Load16U => "i32.load16_u",
+            MemoryOp::I32Store => "i32.store",
+            MemoryOp::I64Store => "i64.store",
+            MemoryOp::F32Store => "f32.store",
+            MemoryOp::F64Store => "f64.store",
+            MemoryOp::I32Store8 => "i32.store8",
+            MemoryOp::I32Store16 => "i32.store16",
+        }
+    }
+}
+
+macro_rules! numeric_ops {
+    ($($op:ident = $code:expr, $name:expr;)*) => {
+        /// Numeric instructions without immediates, opcodes greater than 0xff are
+        /// prefixed by `0xfc`
+        #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+        pub enum NumericOp {
+            $($op,)*
+        }
+
+        impl NumericOp {
+            pub fn opcode(&self) -> u16 {
+                match self {
+                    $(NumericOp::$op => $code,)*
+                }
+            }
+
+            pub fn name(&self) -> &'static str {
+                match self {
+                    $(NumericOp::$op => $name,)*
+                }
+            }
+        }
+    };
+}
+
+numeric_ops! {
+    I32Eqz = 0x45, "i32.eqz";
+    I32Eq = 0x46, "i32.eq";
+    I32Ne = 0x47, "i32.ne";
+    I32LtS = 0x48, "i32.lt_s";
+    I32LtU = 0x49, "i32.lt_u";
+    I32GtS = 0x4a, "i32.gt_s";
+    I32GtU = 0x4b, "i32.gt_u";
+    I32LeS = 0x4c, "i32.le_s";
+    I32LeU = 0x4d, "i32.le_u";
+    I32GeS = 0x4e, "i32.ge_s";
+    I32GeU = 0x4f, "i32.ge_u";
+    I64Eqz = 0x50, "i64.eqz";
+    I64Eq = 0x51, "i64.eq";
+    I64Ne = 0x52, "i64.ne";
+    I64LtS = 0x53, "i64.lt_s";
+    I64LtU = 0x54, "i64.lt_u";
+    I64GtS = 0x55, "i64.gt_s";
+    I64GtU = 0x56, "i64.gt_u";
+    I64LeS = 0x57, "i64.le_s";
+    I64LeU = 0x58, "i64.le_u";
+    I64GeS = 0x59, "i64.ge_s";
+    I64GeU = 0x5a, "i64.ge_u";
+    F32Eq = 0x5b, "f32.eq";
+    F32Ne = 0x5c, "f32.ne";
+    F32Lt = 0x5d, "f32.lt";
+    F32Gt = 0x5e, "f32.gt";
+    F32Le = 0x5f, "f32.le";
+    F32Ge = 0x60, "f32.ge";
+    F64Eq = 0x61, "f64.eq";
+    F64Ne = 0x62, "f64.ne";
+    F64Lt = 0x63, "f64.lt";
+    F64Gt = 0x64, "f64.gt";
+    F64Le = 0x65, "f64.le";
+    F64Ge = 0x66, "f64.ge";
+    I32Add = 0x6a, "i32.add";
+    I32Sub = 0x6b, "i32.sub";
+    I32Mul = 0x6c, "i32.mul";
+    I32DivS = 0x6d, "i32.div_s";
+    I32DivU = 0x6e, "i32.div_u";
+    I32RemS = 0x6f, "i32.rem_s";
+    I32RemU = 0x70, "i32.rem_u";
+    I32And = 0x71, "i32.and";
+    I32Or = 0x72, "i32.or";
+    I32Xor = 0x73, "i32.xor";
+    I64Add = 0x7c, "i64.add";
+    I64Sub = 0x7d, "i64.sub";
+    I64Mul = 0x7e, "i64.mul";
+    I64DivS = 0x7f, "i64.div_s";
+    I64DivU = 0x80, "i64.div_u";
+    I64RemS = 0x81, "i64.rem_s";
+    I64RemU = 0x82, "i64.rem_u";
+    I64And = 0x83, "i64.and";
+    I64Or = 0x84, "i64.or";
+    I64Xor = 0x85, "i64.xor";
+    I64Shl = 0x86, "i64.shl";
+    I64ShrU = 0x88, "i64.shr_u";
+    F32Abs = 0x8b, "f32.abs";
+    F32Neg = 0x8c, "f32.neg";
+    F32Trunc = 0x8f, "f32.trunc";
+    F32Sqrt = 0x91, "f32.sqrt";
+    F32Add = 0x92, "f32.add";
+    F32Sub = 0x93, "f32.sub";
+    F32Mul = 0x94, "f32.mul";
+    F32Div = 0x95, "f32.div";
+    F32Copysign = 0x98, "f32.copysign";
+    F64Abs = 0x99, "f64.abs";
+    F64Neg = 0x9a, "f64.neg";
+    F64Trunc = 0x9d, "f64.trunc";
+    F64Sqrt = 0x9f, "f64.sqrt";
+    F64Add = 0xa0, "f64.add";
+    F64Sub = 0xa1, "f64.sub";
+    F64Mul = 0xa2, "f64.mul";
+    F64Div = 0xa3, "f64.div";
+    F64Copysign = 0xa6, "f64.copysign";
+    I32WrapI64 = 0xa7, "i32.wrap_i64";
+    I64ExtendI32S = 0xac, "i64.extend_i32_s";
+    I64ExtendI32U = 0xad, "i64.extend_i32_u";
+    F32ConvertI32S = 0xb2, "f32.convert_i32_s";
+    F32ConvertI32U = 0xb3, "f32.convert_i32_u";
+    F32ConvertI64S = 0xb4, "f32.convert_i64_s";
+    F32ConvertI64U = 0xb5, "f32.convert_i64_u";
+    F32DemoteF64 = 0xb6, "f32.demote_f64";
+    F64ConvertI32S = 0xb7, "f64.convert_i32_s";
+    F64ConvertI32U = 0xb8, "f64.convert_i32_u";
+    F64ConvertI64S = 0xb9, "f64.convert_i64_s";
+    F64ConvertI64U = 0xba, "f64.convert_i64_u";
+    F64PromoteF32 = 0xbb, "f64.promote_f32";
+    I32Extend8S = 0xc0, "i32.extend8_s";
+    I32Extend16S = 0xc1, "i32.extend16_s";
+    I32TruncSatF32S = 0xfc00, "i32.trunc_sat_f32_s";
+    I32TruncSatF32U = 0xfc01, "i32.trunc_sat_f32_u";
+    I32TruncSatF64S = 0xfc02, "i32.trunc_sat_f64_s";
+    I32TruncSatF64U = 0xfc03, "i32.trunc_sat_f64_u";
+    I64TruncSatF32S = 0xfc04, "i64.trunc_sat_f32_s";
+    I64TruncSatF32U = 0xfc05, "i64.trunc_sat_f32_u";
+    I64TruncSatF64S = 0xfc06, "i64.trunc_sat_f64_s";
+    I64TruncSatF64U = 0xfc07, "i64.trunc_sat_f64_u";
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Instruction {
+    Unreachable,
+    Block(BlockType),
+    Loop(BlockType),
+    If(BlockType),
+    Else,
+    End,
+    Br(u32),
+    BrIf(u32),
+    Return,
+    Call(Callee),
+    Drop,
+    Select,
+    LocalGet(u32),
+    LocalSet(u32),
+    LocalTee(u32),
+    /// Load or store with static offset
+    Load(MemoryOp, u32),
+    Store(MemoryOp, u32),
+    I32Const(i32),
+    I64Const(i64),
+    F32Const(f32),
+    F64Const(f64),
+    /// Address of zero terminated bytes in data segment, replaced by `i32.const` when the
+    /// module is written
+    Data(Vec<u8>),
+    Numeric(NumericOp),
+    /// Copy memory `(dst, src, len)`
+    MemoryCopy,
+    /// Fill memory `(dst, value, len)`
+    MemoryFill,
+}
+
+impl From<NumericOp> for Instruction {
+    fn from(op: NumericOp) -> Self {
+        Instruction::Numeric(op)
+    }
+}
+
+pub fn write_unsigned(buf: &mut Vec<u8>, mut v: u64) {
+    loop {
+        let byte = (v & 0x7f) as u8;
+        v >>= 7;
+        if v == 0 {
+            buf.push(byte);
+            return;
+        }
+        buf.push(byte | 0x80);
+    }
+}
+
+pub fn write_signed(buf: &mut Vec<u8>, mut v: i64) {
+    loop {
+        let byte = (v & 0x7f) as u8;
+        v >>= 7;
+        if (v == 0 && byte & 0x40 == 0) || (v == -1 && byte & 0x40 != 0) {
+            buf.push(byte);
+            return;
+        }
+        buf.push(byte | 0x80);
+    }
+}
+
+fn write_block_type(buf: &mut Vec<u8>, ty: BlockType) {
+    match ty {
+        BlockType::Empty => buf.push(0x40),
+        BlockType::Value(v) => buf.push(v.code()),
+    }
+}
+
+impl Instruction {
+    /// Encode instruction into binary format, `resolve` gives function index of callee
+    pub fn encode(&self, buf: &mut Vec<u8>, resolve: &dyn Fn(&Callee) -> u32) {
+        match self {
+            Instruction::Unreachable => buf.push(0x00),
+            Instruction::Block(ty) => {
+                buf.push(0x02);
+                write_block_type(buf, *ty);
+            }
+            Instruction::Loop(ty) => {
+                buf.push(0x03);
+                write_block_type(buf, *ty);
+            }
+            Instruction::If(ty) => {
+                buf.push(0x04);
+                write_block_type(buf, *ty);
+            }
+            Instruction::Else => buf.push(0x05),
+            Instruction::End => buf.push(0x0b),
+            Instruction::Br(depth) => {
+                buf.push(0x0c);
+                write_unsigned(buf, *depth as u64);
+            }
+            Instruction::BrIf(depth) => {
+                buf.push(0x0d);
+                write_unsigned(buf, *depth as u64);
+            }
+            Instruction::Return => buf.push(0x0f),
+            Instruction::Call(callee) => {
+                buf.push(0x10);
+                write_unsigned(buf, resolve(callee) as u64);
+            }
+            Instruction::Drop => buf.push(0x1a),
+            Instruction::Select => buf.push(0x1b),
+            Instruction::LocalGet(idx) => {
+                buf.push(0x20);
+                write_unsigned(buf, *idx as u64);
+            }
+            Instruction::LocalSet(idx) => {
+                buf.push(0x21);
+                write_unsigned(buf, *idx as u64);
+            }
+            Instruction::LocalTee(idx) => {
+                buf.push(0x22);
+                write_unsigned(buf, *idx as u64);
+            }
+            Instruction::Load(op, offset) | Instruction::Store(op, offset) => {
+                buf.push(op.opcode());
+                write_unsigned(buf, op.align() as u64);
+                write_unsigned(buf, *offset as u64);
+            }
+            Instruction::I32Const(v) => {
+                buf.push(0x41);
+                write_signed(buf, *v as i64);
+            }
+            Instruction::I64Const(v) => {
+                buf.push(0x42);
+                write_signed(buf, *v);
+            }
+            Instruction::F32Const(v) => {
+                buf.push(0x43);
+                buf.extend_from_slice(&v.to_le_bytes());
+            }
+            Instruction::F64Const(v) => {
+                buf.push(0x44);
+                buf.extend_from_slice(&v.to_le_bytes());
+            }
+            Instruction::Data(_) => unreachable!("data address not resolved"),
+            Instruction::Numeric(op) => match op.opcode() {
+                code if code > 0xff => {
+                    buf.push((code >> 8) as u8);
+                    write_unsigned(buf, (code & 0xff) as u64);
+                }
+                code => buf.push(code as u8),
+            },
+            Instruction::MemoryCopy => buf.extend_from_slice(&[0xfc, 0x0a, 0x00, 0x00]),
+            Instruction::MemoryFill => buf.extend_from_slice(&[0xfc, 0x0b, 0x00]),
+        }
+    }
+
+    /// Instruction in text format, `name` gives the name of callee
+    pub fn text(&self, name: &dyn Fn(&Callee) -> String) -> String {
+        fn block(op: &str, ty: &BlockType) -> String {
+            match ty {
+                BlockType::Empty => op.to_owned(),
+                BlockType::Value(v) => format!("{} (result {})", op, v),
+            }
+        }
+
+        match self {
+            Instruction::Unreachable => "unreachable".to_owned(),
+            Instruction::Block(ty) => block("block", ty),
+            Instruction::Loop(ty) => block("loop", ty),
+            Instruction::If(ty) => block("if", ty),
+            Instruction::Else => "else".to_owned(),
+            Instruction::End => "end".to_owned(),
+            Instruction::Br(depth) => format!("br {}", depth),
+            Instruction::BrIf(depth) => format!("br_if {}", depth),
+            Instruction::Return => "return".to_owned(),
+            Instruction::Call(callee) => format!("call ${}", name(callee)),
+            Instruction::Drop => "drop".to_owned(),
+            Instruction::Select => "select".to_owned(),
+            Instruction::LocalGet(idx) => format!("local.get {}", idx),
+            Instruction::LocalSet(idx) => format!("local.set {}", idx),
+            Instruction::LocalTee(idx) => format!("local.tee {}", idx),
+            Instruction::Load(op, 0) | Instruction::Store(op, 0) => op.name().to_owned(),
+            Instruction::Load(op, offset) | Instruction::Store(op, offset) => {
+                format!("{} offset={}", op.name(), offset)
+            }
+            Instruction::I32Const(v) => format!("i32.const {}", v),
+            Instruction::I64Const(v) => format!("i64.const {}", v),
+            Instruction::F32Const(v) => format!("f32.const {}", float_text(format!("{:?}", v))),
+            Instruction::F64Const(v) => format!("f64.const {}", float_text(format!("{:?}", v))),
+            Instruction::Data(bytes) => format!("i32.const {}", data_text(bytes)),
+            Instruction::Numeric(op) => op.name().to_owned(),
+            Instruction::MemoryCopy => "memory.copy".to_owned(),
+            Instruction::MemoryFill => "memory.fill".to_owned(),
+        }
+    }
+}
+
+/// Float constant in text format, Debug format of Rust is the shortest round-trip form
+/// and infinities are written as `inf` already
+fn float_text(s: String) -> String {
+    match s.as_str() {
+        "NaN" => "nan".to_owned(),
+        _ => s,
+    }
+}
+
+/// Bytes as string in text format, non-printable bytes are escaped as hex
+pub fn data_text(bytes: &[u8]) -> String {
+    let mut s = String::from("\"");
+    for b in bytes {
+        match b {
+            b'"' | b'\\' => {
+                s.push('\\');
+                s.push(*b as char);
+            }
+            0x20..=0x7e => s.push(*b as char),
+            _ => s.push_str(&format!("\\{:02x}", b)),
+        }
+    }
+    s.push('"');
+
+    s
+}
@@ -0,0 +1,275 @@
+use crate::backend::utils::*;
+use crate::prelude::*;
+
+use std::collections::HashMap;
+
+/// STRING holds at most 80 bytes and a terminating zero
+pub const STRING_SIZE: u32 = 81;
+
+/// Objects never start at address 0, null pointer stands for absent aggregate arguments
+const MEMORY_BASE: u32 = 16;
+
+#[inline]
+pub fn align_to(offset: u32, align: u32) -> u32 {
+    offset.div_ceil(align) * align
+}
+
+/// Size and alignment of elementary type class
+fn class_size(class: TypeClass) -> (u32, u32) {
+    match class {
+        TypeClass::Bit | TypeClass::Bool | TypeClass::Byte | TypeClass::SInt => (1, 1),
+        TypeClass::Int | TypeClass::UInt => (2, 2),
+        TypeClass::DInt | TypeClass::UDInt | TypeClass::Real => (4, 4),
+        TypeClass::String => (STRING_SIZE, 1),
+        _ => (8, 8),
+    }
+}
+
+#[derive(Debug, Default)]
+struct StructLayout {
+    size: u32,
+    align: u32,
+    members: HashMap<StString, u32>,
+}
+
+/// Static memory layout of application, all objects are placed at fixed addresses:
+///
+/// * global variables and variables of programs
+/// * frames of functions, holding aggregate inputs, local variables and return values
+/// * scratch area for outputs and in-outs of function calls not in memory
+/// * data of string literals and messages, which starts at `data_start()`
+///
+/// Functions have static frames since recursion is not allowed.
+#[derive(Debug, Default)]
+pub struct MemoryLayout {
+    // struct and function block layouts by declaration id
+    structs: HashMap<usize, StructLayout>,
+    globals: HashMap<StString, u32>,
+    // program variables and aggregate variables of functions by declaration id and name
+    statics: HashMap<(usize, StString), u32>,
+    // offsets of global variables and program variables, like `main.a`
+    exports: Vec<(String, u32)>,
+    scratch: u32,
+    data_start: u32,
+    end: u32,
+}
+
+impl MemoryLayout {
+    pub fn new(ctx: &TypeContext) -> Self {
+        let mut layout = Self {
+            end: MEMORY_BASE,
+            ..Default::default()
+        };
+        let decls = sorted_declarations(ctx.app());
+
+        for decl in decls.iter() {
+            let (id, is_struct) = {
+                let p = decl.read().unwrap();
+                let is_struct =
+                    p.is_function_block() || matches!(p.decl().kind, DeclKind::Struct(_));
+                (p.id(), is_struct)
+            };
+
+            if is_struct {
+                layout.struct_layout(ctx, id);
+            }
+        }
+
+        let mut scratch_size = 0;
+        for decl in decls.iter() {
+            let p = decl.read().unwrap();
+            let id = p.id();
+
+            match (&p.decl().kind, pou_kind(p.decl())) {
+                (DeclKind::GlobalVar(_) | DeclKind::Configuration(_), _) => {
+                    for variable in p.decl().variables() {
+                        let Some(ty) = variable.ty() else { continue };
+                        let addr = layout.allocate(ctx, ty);
+                        layout.globals.insert(variable.name().clone(), addr);
+                        layout
+                            .exports
+                            .push((variable.origin_name().to_string(), addr));
+                    }
+                }
+                (_, Some(PouKind::Program)) => {
+                    for variable in p.variables() {
+                        let Some(ty) = variable.ty() else { continue };
+                        let addr = layout.allocate(ctx, ty);
+                        layout.statics.insert((id, variable.name().clone()), addr);
+                        layout.exports.push((
+                            format!("{}.{}", p.name().string(), variable.origin_name()),
+                            addr,
+                        ));
+                    }
+                }
+                (_, Some(PouKind::Function)) => {
+                    let mut refs_size = 0;
+                    for variable in p.variables() {
+                        let Some(ty) = variable.ty() else { continue };
+
+                        if is_reference_parameter(variable) {
+                            let (size, _) = layout.type_size(ctx, ty);
+                            refs_size = align_to(refs_size, 8) + size;
+                        } else if !is_scalar(ctx, ty) {
+                            let addr = layout.allocate(ctx, ty);
+                            layout.statics.insert((id, variable.name().clone()), addr);
+                        }
+                    }
+                    scratch_size = scratch_size.max(refs_size);
+
+                    // return value
+                    if let Some(ty) = function_return_type(decl).filter(|x| !is_scalar(ctx, x)) {
+                        let addr = layout.allocate(ctx, &ty);
+                        layout.statics.insert((id, p.name().clone()), addr);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        layout.scratch = align_to(layout.end, 8);
+        layout.data_start = align_to(layout.scratch + scratch_size, 8);
+        layout.end = layout.data_start;
+
+        layout
+    }
+
+    /// Allocate static object of type
+    fn allocate(&mut self, ctx: &TypeContext, ty: &Type) -> u32 {
+        let (size, align) = self.type_size(ctx, ty);
+        let addr = align_to(self.end, align);
+        self.end = addr + size;
+
+        addr
+    }
+
+    fn struct_layout(&mut self, ctx: &TypeContext, id: usize) -> (u32, u32) {
+        if let Some(st) = self.structs.get(&id) {
+            return (st.size, st.align);
+        }
+
+        let variables = {
+            let app = ctx.app().read();
+            let Some(decl) = app.get_declaration_by_id(id) else {
+                return (0, 1);
+            };
+            let variables = decl.read().unwrap().variables().to_vec();
+            variables
+        };
+
+        // insert an empty layout first in case of recursive types
+        self.structs.insert(id, StructLayout::default());
+
+        let mut st = StructLayout {
+            size: 0,
+            align: 1,
+            members: HashMap::new(),
+        };
+        for variable in variables {
+            let Some(ty) = variable.ty() else { continue };
+            let (size, align) = self.type_size(ctx, ty);
+            let offset = align_to(st.size, align);
+
+            st.members.insert(variable.name().clone(), offset);
+            st.size = offset + size;
+            st.align = st.align.max(align);
+        }
+        st.size = align_to(st.size, st.align);
+
+        let r = (st.size, st.align);
+        self.structs.insert(id, st);
+
+        r
+    }
+
+    fn type_size(&mut self, ctx: &TypeContext, ty: &Type) -> (u32, u32) {
+        let ty = ctx.resolve_type(ty);
+        if let Some(arr) = ty.as_array() {
+            let count: i64 = array_dimensions(arr, ctx)
+                .expect("array bounds must be constant")
+                .iter()
+                .map(|(_, len)| *len)
+                .product();
+            let (size, align) = self.type_size(ctx, arr.base_type());
+            return (size * count as u32, align);
+        }
+
+        if let Some(decl) = ctx.type_user_decl(&ty) {
+            let (id, is_struct) = {
+                let p = decl.read().unwrap();
+                let is_struct =
+                    p.is_function_block() || matches!(p.decl().kind, DeclKind::Struct(_));
+                (p.id(), is_struct)
+            };
+
+            if is_struct {
+                return self.struct_layout(ctx, id);
+            }
+        }
+
+        class_size(ctx.type_class(&ty))
+    }
+
+    /// Size and alignment of type, layouts of all structs have been computed
+    pub fn size_align(&self, ctx: &TypeContext, ty: &Type) -> (u32, u32) {
+        let ty = ctx.resolve_type(ty);
+        if let Some(arr) = ty.as_array() {
+            let count: i64 = array_dimensions(arr, ctx)
+                .expect("array bounds must be constant")
+                .iter()
+                .map(|(_, len)| *len)
+                .product();
+            let (size, align) = self.size_align(ctx, arr.base_type());
+            return (size * count as u32, align);
+        }
+
+        if let Some(decl) = ctx.type_user_decl(&ty) {
+            let id = decl.read().unwrap().id();
+            if let Some(st) = self.structs.get(&id) {
+                return (st.size, st.align);
+            }
+        }
+
+        class_size(ctx.type_class(&ty))
+    }
+
+    #[inline]
+    pub fn member_offset(&self, decl: usize, name: &StString) -> Option<u32> {
+        self.structs.get(&decl)?.members.get(name).copied()
+    }
+
+    #[inline]
+    pub fn global(&self, name: &StString) -> Option<u32> {
+        self.globals.get(name).copied()
+    }
+
+    #[inline]
+    pub fn static_variable(&self, decl: usize, name: &StString) -> Option<u32> {
+        self.statics.get(&(decl, name.clone())).copied()
+    }
+
+    #[inline]
+    pub fn exports(&self) -> &[(String, u32)] {
+        &self.exports
+    }
+
+    #[inline]
+    pub fn scratch(&self) -> u32 {
+        self.scratch
+    }
+
+    #[inline]
+    pub fn data_start(&self) -> u32 {
+        self.data_start
+    }
+}
+
+/// Values of type are held in wasm locals and on the stack directly
+pub fn is_scalar(ctx: &TypeContext, ty: &Type) -> bool {
+    ctx.resolve_type(ty).as_array().is_none() && is_scalar_class(ctx.type_class(ty))
+}
+
+#[inline]
+pub fn is_scalar_class(class: TypeClass) -> bool {
+    integer_width(class).is_some() || is_float_type(class) || matches!(class, TypeClass::Bool)
+}
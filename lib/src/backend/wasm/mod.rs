@@ -0,0 +1,1633 @@
+/// Wasm functions of POUs
+mod code;
+pub use code::WasmCompiledCode;
+use code::WasmFunction;
+
+mod instruction;
+use instruction::*;
+
+mod layout;
+use layout::*;
+
+mod module;
+use module::{wasm_write_module, wasm_write_text};
+
+#[cfg(test)]
+mod test;
+
+use crate::analysis::eval_constant;
+use crate::backend::utils::*;
+use crate::backend::*;
+use crate::parser::{LiteralValue, Location, Operator};
+use crate::prelude::*;
+
+use log::*;
+use std::collections::HashMap;
+use std::mem;
+use std::sync::Arc;
+
+/// Where variable of current POU lives
+#[derive(Debug, Clone, Copy)]
+enum Storage {
+    /// Scalar in wasm local
+    Local(u32),
+    /// Object in linear memory at static address
+    Static(u32),
+    /// Object in linear memory at address in wasm local plus offset, like members of
+    /// function block instance and outputs of function
+    Indirect(u32, u32),
+}
+
+/// Assignable place, address of memory place has been pushed on the stack
+#[derive(Debug, Clone, Copy)]
+enum Place {
+    Local(u32),
+    /// Static offset to the address on the stack
+    Memory(u32),
+}
+
+/// Address of object to be initialized
+#[derive(Debug, Clone, Copy)]
+enum Addr {
+    Static(u32),
+    /// Address in wasm local plus offset
+    Local(u32, u32),
+}
+
+/// Wasm value type of type class, untyped integer literals are LINT and aggregates are
+/// passed by address
+fn val_type(class: Option<TypeClass>) -> ValType {
+    match class {
+        None | Some(TypeClass::LInt) | Some(TypeClass::ULInt) | Some(TypeClass::Time) => {
+            ValType::I64
+        }
+        Some(TypeClass::Real) => ValType::F32,
+        Some(TypeClass::LReal) => ValType::F64,
+        _ => ValType::I32,
+    }
+}
+
+#[inline]
+fn is_signed(class: Option<TypeClass>) -> bool {
+    class
+        .and_then(integer_width)
+        .map(|(_, signed)| signed)
+        .unwrap_or(true)
+}
+
+fn load_op(class: TypeClass) -> MemoryOp {
+    match class {
+        TypeClass::SInt => MemoryOp::I32Load8S,
+        TypeClass::Bit | TypeClass::Bool | TypeClass::Byte => MemoryOp::I32Load8U,
+        TypeClass::Int => MemoryOp::I32Load16S,
+        TypeClass::UInt => MemoryOp::I32Load16U,
+        TypeClass::LInt | TypeClass::ULInt | TypeClass::Time => MemoryOp::I64Load,
+        TypeClass::Real => MemoryOp::F32Load,
+        TypeClass::LReal => MemoryOp::F64Load,
+        _ => MemoryOp::I32Load,
+    }
+}
+
+fn store_op(class: TypeClass) -> MemoryOp {
+    match class {
+        TypeClass::Bit | TypeClass::Bool | TypeClass::Byte | TypeClass::SInt => MemoryOp::I32Store8,
+        TypeClass::Int | TypeClass::UInt => MemoryOp::I32Store16,
+        TypeClass::LInt | TypeClass::ULInt | TypeClass::Time => MemoryOp::I64Store,
+        TypeClass::Real => MemoryOp::F32Store,
+        TypeClass::LReal => MemoryOp::F64Store,
+        _ => MemoryOp::I32Store,
+    }
+}
+
+/// Integer constant wrapped into the width of type class
+fn wrap_integer(v: i64, class: Option<TypeClass>) -> i64 {
+    match class.and_then(integer_width) {
+        Some((bits, true)) if bits < 64 => v << (64 - bits) >> (64 - bits),
+        Some((bits, false)) if bits < 64 => v & ((1 << bits) - 1),
+        _ => v,
+    }
+}
+
+/// Generate WebAssembly module for simulation, every POU is compiled into wasm functions:
+///
+/// * PROGRAM `p` is `p_body()` exported as `p`, its variables are at static addresses
+/// * FUNCTION_BLOCK `fb` has `fb_init(self)` and `fb_body(self)`, `self` is instance address
+/// * FUNCTION `f` is a wasm function, outputs and in-outs are passed by address
+///
+/// All objects are in the exported linear memory, `stc_init()` initializes global variables
+/// and variables of programs. Offsets of them are exported as immutable globals, like
+/// `main.a`. Integer arithmetic wraps around in the declared widths. Runtime errors are
+/// reported to the imported `env.stc_runtime_error(message, len)` and then trap.
+pub struct WasmBackend {
+    ctx: TypeContext,
+    layout: Option<Arc<MemoryLayout>>,
+
+    // tmp values for generating function
+    code: Vec<Instruction>,
+    params: Vec<ValType>,
+    locals: Vec<ValType>,
+    variables: HashMap<StString, Storage>,
+    // functions called but not declared in application
+    externals: Vec<(StString, Vec<ValType>)>,
+    // first expression of current declaration can't be generated
+    error: Option<CodeGenError>,
+}
+
+impl WasmBackend {
+    /// Write the module in WebAssembly text format, which is equivalent to the binary
+    /// module written by `get_module_bytes()`
+    pub fn get_module_text(&mut self, w: &mut dyn Write) -> io::Result<()> {
+        wasm_write_text(self, w)
+    }
+
+    /// Memory layout of application, computed when code is generated at first
+    fn layout(&mut self) -> Arc<MemoryLayout> {
+        self.layout
+            .get_or_insert_with(|| Arc::new(MemoryLayout::new(&self.ctx)))
+            .clone()
+    }
+
+    /// Reset states for generating code of declaration
+    fn enter_declaration(&mut self, proto: &Prototype) {
+        self.ctx.enter_declaration(proto);
+        self.code.clear();
+        self.params.clear();
+        self.locals.clear();
+        self.variables.clear();
+        self.error = None;
+    }
+
+    /// Keep the first error of current declaration, code generation goes on
+    fn report_invalid_expression<E: Display>(&mut self, expr: &E, reason: &str) {
+        if self.error.is_none() {
+            self.error = Some(CodeGenError::InvalidExpression(
+                expr.to_string(),
+                reason.to_owned(),
+            ));
+        }
+    }
+
+    /// Place of invalid expression, an address is pushed to keep the stack balanced
+    fn invalid_place<E: Display>(&mut self, expr: &E, reason: &str) -> Place {
+        self.report_invalid_expression(expr, reason);
+        self.emit(Instruction::I32Const(0));
+        Place::Memory(0)
+    }
+
+    /// Take the generated code as function
+    fn take_function(
+        &mut self,
+        name: String,
+        callee: Callee,
+        results: Vec<ValType>,
+    ) -> WasmFunction {
+        WasmFunction {
+            name,
+            callee,
+            params: mem::take(&mut self.params),
+            results,
+            locals: mem::take(&mut self.locals),
+            body: mem::take(&mut self.code),
+        }
+    }
+
+    #[inline]
+    fn emit<I: Into<Instruction>>(&mut self, instruction: I) {
+        self.code.push(instruction.into());
+    }
+
+    fn new_local(&mut self, ty: ValType) -> u32 {
+        self.locals.push(ty);
+        (self.params.len() + self.locals.len() - 1) as u32
+    }
+
+    #[inline]
+    fn call_runtime(&mut self, runtime: Runtime) {
+        self.emit(Instruction::Call(Callee::Runtime(runtime)));
+    }
+
+    /// Scalar type class of type, None for aggregates like strings, arrays and structs
+    fn scalar_class(&self, ty: &Type) -> Option<TypeClass> {
+        Some(self.ctx.type_class(ty)).filter(|_| is_scalar(&self.ctx, ty))
+    }
+
+    #[inline]
+    fn size_of(&mut self, ty: &Type) -> u32 {
+        self.layout().size_align(&self.ctx, ty).0
+    }
+
+    /// Declaration id of struct or function block type
+    fn struct_id(&self, ty: &Type) -> Option<usize> {
+        let decl = self.ctx.type_user_decl(&self.ctx.resolve_type(ty))?;
+        let decl = decl.read().unwrap();
+        let is_struct = decl.is_function_block() || matches!(decl.decl().kind, DeclKind::Struct(_));
+
+        Some(decl.id()).filter(|_| is_struct)
+    }
+
+    /// Enum member used in expression, like `Red` or `Color.Red`
+    fn enum_constant(&self, expr: &Expression) -> Option<i64> {
+        match &expr.kind {
+            ExprKind::Variable(var) if self.variable_storage(var.name()).is_none() => {
+                self.ctx.enum_member(None, var.name())
+            }
+            ExprKind::Compo(compo) => {
+                let enum_name = expression_variable_name(compo.left())?;
+                let member = expression_variable_name(compo.right())?;
+                if self.ctx.scope.find_variable(enum_name).is_some() {
+                    return None;
+                }
+
+                self.ctx.enum_member(Some(enum_name), member)
+            }
+            _ => None,
+        }
+    }
+
+    fn variable_storage(&self, name: &StString) -> Option<Storage> {
+        if let Some(storage) = self.variables.get(name) {
+            return Some(*storage);
+        }
+
+        self.ctx.scope.find_global_variable(name)?;
+        let layout = self.layout.as_ref()?;
+        layout.global(name).map(Storage::Static)
+    }
+
+    /// Push integer constant in type class
+    fn emit_integer(&mut self, v: i64, class: Option<TypeClass>) {
+        match class {
+            Some(TypeClass::Real) => self.emit(Instruction::F32Const(v as f32)),
+            Some(TypeClass::LReal) => self.emit(Instruction::F64Const(v as f64)),
+            Some(TypeClass::Bool) => self.emit(Instruction::I32Const((v != 0) as i32)),
+            _ => match val_type(class) {
+                ValType::I64 => self.emit(Instruction::I64Const(v)),
+                _ => self.emit(Instruction::I32Const(wrap_integer(v, class) as i32)),
+            },
+        }
+    }
+
+    fn gen_literal(&mut self, literal: &LiteralValue, class: Option<TypeClass>) {
+        match literal {
+            LiteralValue::Bool(b) => {
+                self.emit_integer(*b as i64, Some(TypeClass::Bool));
+                self.gen_convert(Some(TypeClass::Bool), class);
+            }
+            LiteralValue::String(s) => {
+                self.emit(Instruction::Data(s.as_bytes().to_vec()));
+            }
+            LiteralValue::Real(s) | LiteralValue::LReal(s) => {
+                let v: f64 = s.replace('_', "").parse().unwrap_or(0.0);
+                let float_class = class
+                    .filter(|x| is_float_type(*x))
+                    .unwrap_or(literal.ty().type_class());
+
+                match float_class {
+                    TypeClass::Real => self.emit(Instruction::F32Const(v as f32)),
+                    _ => self.emit(Instruction::F64Const(v)),
+                }
+                self.gen_convert(Some(float_class), class);
+            }
+            LiteralValue::ULInt(v) => self.emit_integer(*v as i64, class),
+            _ => self.emit_integer(literal_integer(literal).unwrap_or(0), class),
+        }
+    }
+
+    /// Wrap integer on the stack into the width of type class
+    fn gen_normalize(&mut self, class: Option<TypeClass>) {
+        match class {
+            Some(TypeClass::SInt) => self.emit(NumericOp::I32Extend8S),
+            Some(TypeClass::Int) => self.emit(NumericOp::I32Extend16S),
+            Some(TypeClass::Bit) | Some(TypeClass::Byte) | Some(TypeClass::UInt) => {
+                let mask = match class {
+                    Some(TypeClass::Bit) => 1,
+                    Some(TypeClass::Byte) => 0xff,
+                    _ => 0xffff,
+                };
+                self.emit(Instruction::I32Const(mask));
+                self.emit(NumericOp::I32And);
+            }
+            _ => {}
+        }
+    }
+
+    /// Convert value on the stack from type class to another
+    fn gen_convert(&mut self, from: Option<TypeClass>, to: Option<TypeClass>) {
+        if from == to
+            || from.is_some_and(|x| !is_scalar_class(x))
+            || to.is_some_and(|x| !is_scalar_class(x))
+        {
+            return;
+        }
+
+        let (from_type, to_type) = (val_type(from), val_type(to));
+        if matches!(to, Some(TypeClass::Bool)) {
+            match from_type {
+                ValType::I32 => {
+                    self.emit(Instruction::I32Const(0));
+                    self.emit(NumericOp::I32Ne);
+                }
+                ValType::I64 => {
+                    self.emit(Instruction::I64Const(0));
+                    self.emit(NumericOp::I64Ne);
+                }
+                ValType::F32 => {
+                    self.emit(Instruction::F32Const(0.0));
+                    self.emit(NumericOp::F32Ne);
+                }
+                ValType::F64 => {
+                    self.emit(Instruction::F64Const(0.0));
+                    self.emit(NumericOp::F64Ne);
+                }
+            }
+            return;
+        }
+
+        let signed = is_signed(from) && !matches!(from, Some(TypeClass::Bool));
+        let op = match (from_type, to_type) {
+            (ValType::I32, ValType::I64) if signed => Some(NumericOp::I64ExtendI32S),
+            (ValType::I32, ValType::I64) => Some(NumericOp::I64ExtendI32U),
+            (ValType::I64, ValType::I32) => Some(NumericOp::I32WrapI64),
+            (ValType::I32, ValType::F32) if signed => Some(NumericOp::F32ConvertI32S),
+            (ValType::I32, ValType::F32) => Some(NumericOp::F32ConvertI32U),
+            (ValType::I64, ValType::F32) if signed => Some(NumericOp::F32ConvertI64S),
+            (ValType::I64, ValType::F32) => Some(NumericOp::F32ConvertI64U),
+            (ValType::I32, ValType::F64) if signed => Some(NumericOp::F64ConvertI32S),
+            (ValType::I32, ValType::F64) => Some(NumericOp::F64ConvertI32U),
+            (ValType::I64, ValType::F64) if signed => Some(NumericOp::F64ConvertI64S),
+            (ValType::I64, ValType::F64) => Some(NumericOp::F64ConvertI64U),
+            (ValType::F32, ValType::F64) => Some(NumericOp::F64PromoteF32),
+            (ValType::F64, ValType::F32) => Some(NumericOp::F32DemoteF64),
+            // float to integer saturates instead of trapping
+            (ValType::F32, ValType::I32) if is_signed(to) => Some(NumericOp::I32TruncSatF32S),
+            (ValType::F32, ValType::I32) => Some(NumericOp::I32TruncSatF32U),
+            (ValType::F64, ValType::I32) if is_signed(to) => Some(NumericOp::I32TruncSatF64S),
+            (ValType::F64, ValType::I32) => Some(NumericOp::I32TruncSatF64U),
+            (ValType::F32, ValType::I64) if is_signed(to) => Some(NumericOp::I64TruncSatF32S),
+            (ValType::F32, ValType::I64) => Some(NumericOp::I64TruncSatF32U),
+            (ValType::F64, ValType::I64) if is_signed(to) => Some(NumericOp::I64TruncSatF64S),
+            (ValType::F64, ValType::I64) => Some(NumericOp::I64TruncSatF64U),
+            _ => None,
+        };
+
+        if let Some(op) = op {
+            self.emit(op);
+        }
+        self.gen_normalize(to);
+    }
+
+    /// Push value of expression in its own type class, aggregates are pushed as address
+    fn gen_expression(&mut self, expr: &Expression) {
+        if let Some(v) = self.enum_constant(expr) {
+            return self.emit_integer(v, None);
+        }
+
+        match &expr.kind {
+            ExprKind::Literal(lit) => self.gen_literal(lit.literal(), None),
+            ExprKind::Variable(_) | ExprKind::Compo(_) | ExprKind::ArrayAccess(_) => {
+                let ty = self.ctx.expression_type(expr);
+                let place = self.gen_place(expr);
+                self.gen_load(place, ty.as_ref());
+            }
+            ExprKind::Operator(_) if const_integer(expr).is_some() => {
+                self.emit_integer(const_integer(expr).unwrap(), None)
+            }
+            ExprKind::Operator(op_expr) => self.gen_operator_expression(op_expr),
+            ExprKind::Call(call) => {
+                if !self.gen_call(call) {
+                    // TODO: function without return value used in expression
+                    self.emit(Instruction::I32Const(0));
+                }
+            }
+            ExprKind::Assign(assign) => {
+                self.gen_assign(assign);
+                self.gen_expression(assign.left());
+            }
+            ExprKind::Range(_) => unreachable!("range can't be evaluated: {}", expr),
+        }
+    }
+
+    /// Push value of expression converted to type class
+    fn gen_value(&mut self, expr: &Expression, class: Option<TypeClass>) {
+        if let ExprKind::Literal(lit) = &expr.kind {
+            return self.gen_literal(lit.literal(), class);
+        }
+        if let Some(v) = const_integer(expr).or_else(|| self.enum_constant(expr)) {
+            return self.emit_integer(v, class);
+        }
+        // initial values aren't folded with bodies, they may refer to constants
+        if let Ok(value) = eval_constant(expr, &self.ctx) {
+            return self.gen_literal(&value.to_literal(), class);
+        }
+
+        let from = self.ctx.expression_class(expr);
+        self.gen_expression(expr);
+        self.gen_convert(from, class);
+    }
+
+    /// Push address of place in memory and returns the static offset
+    fn gen_place(&mut self, expr: &Expression) -> Place {
+        match &expr.kind {
+            ExprKind::Variable(var) => {
+                let Some(storage) = self.variable_storage(var.name()) else {
+                    return self.invalid_place(expr, "variable not found");
+                };
+
+                match storage {
+                    Storage::Local(idx) => Place::Local(idx),
+                    Storage::Static(addr) => {
+                        self.emit(Instruction::I32Const(addr as i32));
+                        Place::Memory(0)
+                    }
+                    Storage::Indirect(idx, offset) => {
+                        self.emit(Instruction::LocalGet(idx));
+                        Place::Memory(offset)
+                    }
+                }
+            }
+            ExprKind::Compo(compo) => {
+                let Some(field) = expression_variable_name(compo.right()) else {
+                    return self.invalid_place(expr, "invalid member access");
+                };
+                let member_offset = self
+                    .ctx
+                    .expression_type(compo.left())
+                    .and_then(|ty| self.struct_id(&ty))
+                    .and_then(|id| self.layout().member_offset(id, field));
+                let Some(member_offset) = member_offset else {
+                    return self.invalid_place(expr, "member not found");
+                };
+
+                match self.gen_place(compo.left()) {
+                    Place::Memory(offset) => Place::Memory(offset + member_offset),
+                    Place::Local(_) => unreachable!("member of scalar: {}", expr),
+                }
+            }
+            ExprKind::ArrayAccess(access) => self.gen_array_element(expr.info.start, access),
+            _ => unreachable!("invalid assignment target: {}", expr),
+        }
+    }
+
+    /// Element address is `array + (index - lower) * stride`, indexes are checked against
+    /// array bounds by `stc_index()`
+    fn gen_array_element(
+        &mut self,
+        location: Option<Location>,
+        access: &ArrayAccessExpression,
+    ) -> Place {
+        let arr = self
+            .ctx
+            .expression_type(access.array())
+            .map(|ty| self.ctx.resolve_type(&ty))
+            .expect("array type not found");
+        let arr = arr.as_array().expect("array type required");
+        let dims = array_dimensions(arr, &self.ctx).expect("array bounds must be constant");
+        let element_size = self.size_of(arr.base_type());
+
+        let message = match location {
+            Some(loc) => format!(
+                "array index out of range at line {}, column {}: {}",
+                loc.mark + 1,
+                loc.offset + 1,
+                access
+            ),
+            None => format!("array index out of range: {}", access),
+        };
+
+        let Place::Memory(offset) = self.gen_place(access.array()) else {
+            unreachable!("array in wasm local: {}", access);
+        };
+        for (idx, (index, (lower, len))) in access.indexes().iter().zip(dims.iter()).enumerate() {
+            let stride: i64 = dims.iter().skip(idx + 1).map(|(_, len)| *len).product();
+
+            self.gen_value(index, Some(TypeClass::LInt));
+            self.emit(Instruction::I64Const(*lower));
+            self.emit(Instruction::I64Const(*len));
+            self.emit(Instruction::Data(message.as_bytes().to_vec()));
+            self.emit(Instruction::I32Const(message.len() as i32));
+            self.call_runtime(Runtime::Index);
+            self.emit(Instruction::I32Const(stride as i32 * element_size as i32));
+            self.emit(NumericOp::I32Mul);
+            self.emit(NumericOp::I32Add);
+        }
+
+        Place::Memory(offset)
+    }
+
+    /// Push value in place, aggregates are pushed as address
+    fn gen_load(&mut self, place: Place, ty: Option<&Type>) {
+        match (place, ty.and_then(|x| self.scalar_class(x))) {
+            (Place::Local(idx), _) => self.emit(Instruction::LocalGet(idx)),
+            (Place::Memory(offset), Some(class)) => {
+                self.emit(Instruction::Load(load_op(class), offset))
+            }
+            (Place::Memory(offset), None) => self.gen_offset(offset),
+        }
+    }
+
+    /// Add static offset to the address on the stack
+    fn gen_offset(&mut self, offset: u32) {
+        if offset > 0 {
+            self.emit(Instruction::I32Const(offset as i32));
+            self.emit(NumericOp::I32Add);
+        }
+    }
+
+    /// Push address of object in memory
+    fn gen_address(&mut self, expr: &Expression) {
+        match self.gen_place(expr) {
+            Place::Memory(offset) => self.gen_offset(offset),
+            Place::Local(_) => unreachable!("address of wasm local: {}", expr),
+        }
+    }
+
+    /// Place to be stored into, address of aggregate is pushed without static offset
+    fn gen_target(&mut self, expr: &Expression, ty: Option<&Type>) -> Place {
+        let place = self.gen_place(expr);
+        match place {
+            Place::Memory(offset) if ty.and_then(|x| self.scalar_class(x)).is_none() => {
+                self.gen_offset(offset);
+                Place::Memory(0)
+            }
+            _ => place,
+        }
+    }
+
+    /// Store value on the stack into place, strings are truncated and other aggregates
+    /// are copied
+    fn gen_store(&mut self, place: Place, ty: Option<&Type>) {
+        match (place, ty.and_then(|x| self.scalar_class(x))) {
+            (Place::Local(idx), _) => self.emit(Instruction::LocalSet(idx)),
+            (Place::Memory(offset), Some(class)) => {
+                self.emit(Instruction::Store(store_op(class), offset))
+            }
+            (Place::Memory(_), None) => match ty {
+                Some(ty) => self.gen_copy(ty),
+                // type of invalid target is unknown, the error is reported by `gen_place()`
+                None => {
+                    self.emit(Instruction::Drop);
+                    self.emit(Instruction::Drop);
+                }
+            },
+        }
+    }
+
+    /// Copy aggregate from source address to destination address on the stack
+    fn gen_copy(&mut self, ty: &Type) {
+        if matches!(self.ctx.type_class(ty), TypeClass::String) {
+            self.call_runtime(Runtime::StringAssign);
+        } else {
+            let size = self.size_of(ty);
+            self.emit(Instruction::I32Const(size as i32));
+            self.emit(Instruction::MemoryCopy);
+        }
+    }
+
+    fn gen_operator_expression(&mut self, op_expr: &OperatorExpression) {
+        let op = *op_expr.op();
+        let operands = op_expr.operands();
+        let class = self.ctx.operands_class(operands);
+        let ty = val_type(class);
+        let is_float = class.map(is_float_type).unwrap_or(false);
+        let is_i64 = ty == ValType::I64;
+
+        if operands.len() == 1 {
+            match op {
+                Operator::Minus if is_float => {
+                    self.gen_value(&operands[0], class);
+                    self.emit(match ty {
+                        ValType::F32 => NumericOp::F32Neg,
+                        _ => NumericOp::F64Neg,
+                    });
+                }
+                Operator::Minus => {
+                    self.emit_integer(0, class);
+                    self.gen_value(&operands[0], class);
+                    self.emit(if is_i64 {
+                        NumericOp::I64Sub
+                    } else {
+                        NumericOp::I32Sub
+                    });
+                    self.gen_normalize(class);
+                }
+                Operator::Not if matches!(class, Some(TypeClass::Bool)) => {
+                    self.gen_value(&operands[0], class);
+                    self.emit(NumericOp::I32Eqz);
+                }
+                Operator::Not => {
+                    self.gen_value(&operands[0], class);
+                    self.emit_integer(-1, if is_i64 { None } else { Some(TypeClass::DInt) });
+                    self.emit(if is_i64 {
+                        NumericOp::I64Xor
+                    } else {
+                        NumericOp::I32Xor
+                    });
+                    self.gen_normalize(class);
+                }
+                _ => unreachable!("{:?}", op),
+            }
+            return;
+        }
+
+        // strings are compared by `stc_string_compare()`
+        if matches!(class, Some(TypeClass::String)) {
+            self.gen_expression(&operands[0]);
+            self.gen_expression(&operands[1]);
+            self.call_runtime(Runtime::StringCompare);
+            self.emit(Instruction::I32Const(0));
+            self.emit(compare_operator(op, ValType::I32, true));
+            return;
+        }
+
+        match op {
+            // power is calculated in 64 bits and wrapped
+            Operator::Power if is_float => {
+                self.gen_value(&operands[0], Some(TypeClass::LReal));
+                self.gen_value(&operands[1], Some(TypeClass::LReal));
+                self.call_runtime(Runtime::PowF64);
+                self.gen_convert(Some(TypeClass::LReal), class);
+            }
+            Operator::Power => {
+                let (wide, runtime) = if is_signed(class) {
+                    (TypeClass::LInt, Runtime::PowI64)
+                } else {
+                    (TypeClass::ULInt, Runtime::PowU64)
+                };
+
+                self.gen_value(&operands[0], Some(wide));
+                self.gen_value(&operands[1], Some(wide));
+                self.call_runtime(runtime);
+                self.gen_convert(Some(wide), class);
+            }
+            _ => {
+                self.gen_value(&operands[0], class);
+                self.gen_value(&operands[1], class);
+                self.gen_binary_operator(op, class);
+            }
+        }
+    }
+
+    /// Binary operator on two values of type class on the stack
+    fn gen_binary_operator(&mut self, op: Operator, class: Option<TypeClass>) {
+        let ty = val_type(class);
+        let signed = is_signed(class);
+
+        let numeric_op = match (op, ty) {
+            (
+                Operator::Less
+                | Operator::LessEqual
+                | Operator::Greater
+                | Operator::GreaterEqual
+                | Operator::Equal
+                | Operator::NotEqual,
+                _,
+            ) => return self.emit(compare_operator(op, ty, signed)),
+            // float arithmetic
+            (Operator::Mod, ValType::F32) => return self.call_runtime(Runtime::FModF32),
+            (Operator::Mod, ValType::F64) => return self.call_runtime(Runtime::FModF64),
+            (Operator::Plus, ValType::F32) => NumericOp::F32Add,
+            (Operator::Minus, ValType::F32) => NumericOp::F32Sub,
+            (Operator::Multiply, ValType::F32) => NumericOp::F32Mul,
+            (Operator::Division, ValType::F32) => NumericOp::F32Div,
+            (Operator::Plus, ValType::F64) => NumericOp::F64Add,
+            (Operator::Minus, ValType::F64) => NumericOp::F64Sub,
+            (Operator::Multiply, ValType::F64) => NumericOp::F64Mul,
+            (Operator::Division, ValType::F64) => NumericOp::F64Div,
+            // integer division by zero is a runtime error
+            (Operator::Division | Operator::Mod, _) => {
+                let runtime = match (op, ty, signed) {
+                    (Operator::Division, ValType::I32, true) => Runtime::DivI32,
+                    (Operator::Division, ValType::I32, false) => Runtime::DivU32,
+                    (Operator::Division, _, true) => Runtime::DivI64,
+                    (Operator::Division, _, false) => Runtime::DivU64,
+                    (_, ValType::I32, true) => Runtime::ModI32,
+                    (_, ValType::I32, false) => Runtime::ModU32,
+                    (_, _, true) => Runtime::ModI64,
+                    (_, _, false) => Runtime::ModU64,
+                };
+                self.call_runtime(runtime);
+                return self.gen_normalize(class);
+            }
+            // boolean logic and bitwise operators, result always in range
+            (Operator::BitAnd, ValType::I64) => NumericOp::I64And,
+            (Operator::BitOr, ValType::I64) => NumericOp::I64Or,
+            (Operator::Xor, ValType::I64) => NumericOp::I64Xor,
+            (Operator::BitAnd, _) => NumericOp::I32And,
+            (Operator::BitOr, _) => NumericOp::I32Or,
+            (Operator::Xor, _) => NumericOp::I32Xor,
+            // integer arithmetic wraps around
+            (Operator::Plus, ValType::I64) => NumericOp::I64Add,
+            (Operator::Minus, ValType::I64) => NumericOp::I64Sub,
+            (Operator::Multiply, ValType::I64) => NumericOp::I64Mul,
+            (Operator::Plus, _) => NumericOp::I32Add,
+            (Operator::Minus, _) => NumericOp::I32Sub,
+            (Operator::Multiply, _) => NumericOp::I32Mul,
+            _ => unreachable!("{:?}", op),
+        };
+
+        self.emit(numeric_op);
+        self.gen_normalize(class);
+    }
+
+    /// Call of function or program in expression, returns true if a value is pushed.
+    /// Function blocks are called by `gen_fb_call`.
+    fn gen_call(&mut self, call: &CallExpression) -> bool {
+        let Some(name) = expression_variable_name(call.callee()).cloned() else {
+            self.report_invalid_expression(call, "invalid callee");
+            return false;
+        };
+        match self.ctx.standard_call(call) {
+            Some(Ok(standard)) => {
+                if !self.gen_standard_call(call, &standard) {
+                    self.report_invalid_expression(call, "standard function not supported");
+                    return false;
+                }
+                return true;
+            }
+            Some(Err(e)) => {
+                self.report_invalid_expression(call, &e.to_string());
+                return false;
+            }
+            None => {}
+        }
+
+        let decl = self.ctx.find_declaration(&name);
+        let kind = decl
+            .as_ref()
+            .and_then(|x| pou_kind(x.read().unwrap().decl()));
+
+        match (decl, kind) {
+            (Some(decl), Some(PouKind::Function)) => self.gen_function_call(call, &decl),
+            (Some(decl), Some(PouKind::Program)) => {
+                let id = decl.read().unwrap().id();
+                self.emit(Instruction::Call(Callee::Body(id)));
+                false
+            }
+            _ => {
+                self.gen_external_call(call, &name);
+                false
+            }
+        }
+    }
+
+    /// Standard function on scalar values, pushes the value in return type and returns
+    /// false if function isn't supported. Arguments are evaluated into locals in order.
+    /// Functions on strings are not supported.
+    fn gen_standard_call(&mut self, call: &CallExpression, standard: &StandardCall) -> bool {
+        let class = standard.return_class;
+        let name = standard.name.as_str();
+        let conversion = standard.conversion();
+        let supported = match name {
+            _ if conversion.is_some() => true,
+            "ABS" | "SQRT" | "LN" | "LOG" | "EXP" | "SIN" | "COS" | "TAN" | "ASIN" | "ACOS"
+            | "ATAN" | "EXPT" | "TRUNC" | "SHL" | "SHR" | "ROL" | "ROR" | "SEL" | "MAX" | "MIN"
+            | "LIMIT" | "MUX" | "STC_NOW" => true,
+            _ => false,
+        };
+        if !supported
+            || !standard.arguments.iter().all(|(_, x)| is_scalar_class(*x))
+            || !is_scalar_class(class)
+        {
+            return false;
+        }
+
+        let mut args = vec![];
+        for (arg, arg_class) in &standard.arguments {
+            match arg {
+                Some(idx) => {
+                    self.gen_value(argument_value(&call.arguments()[*idx]), Some(*arg_class))
+                }
+                None => self.emit_integer(0, Some(*arg_class)),
+            }
+            let local = self.new_local(val_type(Some(*arg_class)));
+            self.emit(Instruction::LocalSet(local));
+            args.push((local, Some(*arg_class)));
+        }
+        let ty = val_type(Some(class));
+        let signed = is_signed(Some(class));
+
+        if let Some((from, to)) = conversion {
+            self.emit(Instruction::LocalGet(args[0].0));
+            // floats are rounded half away from zero, `trunc(x + copysign(0.5, x))`
+            if is_float_type(from) && !is_float_type(to) && to != TypeClass::Bool {
+                let (half, copysign, add) = match val_type(Some(from)) {
+                    ValType::F32 => (
+                        Instruction::F32Const(0.5),
+                        NumericOp::F32Copysign,
+                        NumericOp::F32Add,
+                    ),
+                    _ => (
+                        Instruction::F64Const(0.5),
+                        NumericOp::F64Copysign,
+                        NumericOp::F64Add,
+                    ),
+                };
+                self.emit(half);
+                self.emit(Instruction::LocalGet(args[0].0));
+                self.emit(copysign);
+                self.emit(add);
+            }
+            self.gen_convert(Some(from), Some(to));
+            return true;
+        }
+
+        match name {
+            "ABS" if is_float_type(class) => {
+                self.emit(Instruction::LocalGet(args[0].0));
+                self.emit(match ty {
+                    ValType::F32 => NumericOp::F32Abs,
+                    _ => NumericOp::F64Abs,
+                });
+            }
+            "ABS" if !signed => self.emit(Instruction::LocalGet(args[0].0)),
+            // `select(0 - x, x, x < 0)`
+            "ABS" => {
+                let x = args[0].0;
+                self.emit_integer(0, Some(class));
+                self.emit(Instruction::LocalGet(x));
+                self.gen_binary_operator(Operator::Minus, Some(class));
+                self.emit(Instruction::LocalGet(x));
+                self.emit(Instruction::LocalGet(x));
+                self.emit_integer(0, Some(class));
+                self.emit(compare_operator(Operator::Less, ty, true));
+                self.emit(Instruction::Select);
+            }
+            "SQRT" => {
+                self.emit(Instruction::LocalGet(args[0].0));
+                self.emit(match ty {
+                    ValType::F32 => NumericOp::F32Sqrt,
+                    _ => NumericOp::F64Sqrt,
+                });
+            }
+            // transcendental functions are imported in double precision
+            "LN" | "LOG" | "EXP" | "SIN" | "COS" | "TAN" | "ASIN" | "ACOS" | "ATAN" | "EXPT" => {
+                let runtime = match name {
+                    "LN" => Runtime::LnF64,
+                    "LOG" => Runtime::Log10F64,
+                    "EXP" => Runtime::ExpF64,
+                    "SIN" => Runtime::SinF64,
+                    "COS" => Runtime::CosF64,
+                    "TAN" => Runtime::TanF64,
+                    "ASIN" => Runtime::AsinF64,
+                    "ACOS" => Runtime::AcosF64,
+                    "ATAN" => Runtime::AtanF64,
+                    _ => Runtime::PowF64,
+                };
+                for (local, arg_class) in args {
+                    self.emit(Instruction::LocalGet(local));
+                    self.gen_convert(arg_class, Some(TypeClass::LReal));
+                }
+                self.call_runtime(runtime);
+                self.gen_convert(Some(TypeClass::LReal), Some(class));
+            }
+            "TRUNC" => {
+                self.emit(Instruction::LocalGet(args[0].0));
+                self.gen_convert(args[0].1, Some(class));
+            }
+            // shifts of the low bits of value in 64 bits
+            "SHL" | "SHR" | "ROL" | "ROR" => {
+                let bits = match class {
+                    TypeClass::Bool => 1,
+                    _ => integer_width(class).map_or(64, |(bits, _)| bits),
+                };
+                let runtime = match name {
+                    "SHL" => Runtime::Shl,
+                    "SHR" => Runtime::Shr,
+                    "ROL" => Runtime::Rol,
+                    _ => Runtime::Ror,
+                };
+
+                self.emit(Instruction::LocalGet(args[0].0));
+                self.gen_convert(args[0].1, Some(TypeClass::ULInt));
+                self.emit(Instruction::LocalGet(args[1].0));
+                self.gen_convert(args[1].1, Some(TypeClass::LInt));
+                self.emit(Instruction::I64Const(bits as i64));
+                self.call_runtime(runtime);
+                self.gen_convert(Some(TypeClass::ULInt), Some(class));
+            }
+            "SEL" => {
+                self.emit(Instruction::LocalGet(args[2].0));
+                self.emit(Instruction::LocalGet(args[1].0));
+                self.emit(Instruction::LocalGet(args[0].0));
+                self.emit(Instruction::Select);
+            }
+            // `result := select(x, result, x > result)` for each input
+            "MAX" | "MIN" => {
+                let op = match name {
+                    "MAX" => Operator::Greater,
+                    _ => Operator::Less,
+                };
+                let result = args[0].0;
+                for (x, _) in args.iter().skip(1) {
+                    self.emit(Instruction::LocalGet(*x));
+                    self.emit(Instruction::LocalGet(result));
+                    self.emit(Instruction::LocalGet(*x));
+                    self.emit(Instruction::LocalGet(result));
+                    self.emit(compare_operator(op, ty, signed));
+                    self.emit(Instruction::Select);
+                    self.emit(Instruction::LocalSet(result));
+                }
+                self.emit(Instruction::LocalGet(result));
+            }
+            // `select(mn, select(mx, in, in > mx), in < mn)`
+            "LIMIT" => {
+                let (mn, x, mx) = (args[0].0, args[1].0, args[2].0);
+                self.emit(Instruction::LocalGet(mn));
+                self.emit(Instruction::LocalGet(mx));
+                self.emit(Instruction::LocalGet(x));
+                self.emit(Instruction::LocalGet(x));
+                self.emit(Instruction::LocalGet(mx));
+                self.emit(compare_operator(Operator::Greater, ty, signed));
+                self.emit(Instruction::Select);
+                self.emit(Instruction::LocalGet(x));
+                self.emit(Instruction::LocalGet(mn));
+                self.emit(compare_operator(Operator::Less, ty, signed));
+                self.emit(Instruction::Select);
+            }
+            // K out of range is a runtime error, all inputs are evaluated
+            "MUX" => {
+                let message = "MUX K out of range";
+                self.emit(Instruction::LocalGet(args[0].0));
+                self.gen_convert(args[0].1, Some(TypeClass::LInt));
+                self.emit(Instruction::I64Const(0));
+                self.emit(Instruction::I64Const(args.len() as i64 - 1));
+                self.emit(Instruction::Data(message.as_bytes().to_vec()));
+                self.emit(Instruction::I32Const(message.len() as i32));
+                self.call_runtime(Runtime::Index);
+                let k = self.new_local(ValType::I32);
+                self.emit(Instruction::LocalSet(k));
+
+                let result = args[1].0;
+                for (idx, (x, _)) in args.iter().enumerate().skip(2) {
+                    self.emit(Instruction::LocalGet(*x));
+                    self.emit(Instruction::LocalGet(result));
+                    self.emit(Instruction::LocalGet(k));
+                    self.emit(Instruction::I32Const(idx as i32 - 1));
+                    self.emit(NumericOp::I32Eq);
+                    self.emit(Instruction::Select);
+                    self.emit(Instruction::LocalSet(result));
+                }
+                self.emit(Instruction::LocalGet(result));
+            }
+            "STC_NOW" => self.call_runtime(Runtime::Now),
+            _ => unreachable!("{}", name),
+        }
+
+        true
+    }
+
+    /// Outputs and in-outs are passed by address, those not in memory are passed through
+    /// the scratch area and copied back after call.
+    fn gen_function_call(&mut self, call: &CallExpression, decl: &Prototype) -> bool {
+        let (id, params) = {
+            let decl = decl.read().unwrap();
+            let params: Vec<_> = decl
+                .variables()
+                .iter()
+                .filter(|x| !x.scope().is_empty())
+                .cloned()
+                .collect();
+            (decl.id(), params)
+        };
+        let bound = bind_arguments(&params, call);
+
+        let mut scratch = self.layout().scratch();
+        let mut copy_back = vec![];
+        for (param, arg) in params.iter().zip(bound) {
+            let ty = param.ty().cloned().unwrap_or_else(LIntType::new_type);
+
+            if is_reference_parameter(param) {
+                let place = match arg {
+                    Some(arg) => self.gen_place(arg),
+                    None => Place::Local(u32::MAX),
+                };
+                match place {
+                    Place::Memory(offset) => self.gen_offset(offset),
+                    Place::Local(idx) => {
+                        let class = self.scalar_class(&ty);
+                        scratch = align_to(scratch, 8);
+                        if idx != u32::MAX {
+                            self.emit(Instruction::I32Const(scratch as i32));
+                            self.emit(Instruction::LocalGet(idx));
+                            self.emit(Instruction::Store(
+                                store_op(class.unwrap_or(TypeClass::LInt)),
+                                0,
+                            ));
+                            copy_back.push((idx, class, scratch));
+                        }
+                        self.emit(Instruction::I32Const(scratch as i32));
+                        scratch += self.size_of(&ty);
+                    }
+                }
+            } else if let Some(class) = self.scalar_class(&ty) {
+                match arg {
+                    Some(arg) => self.gen_value(arg, Some(class)),
+                    None => self.gen_default_value(&ty, param.initial().as_deref()),
+                }
+            } else {
+                // the callee initializes absent aggregate inputs
+                match arg {
+                    Some(arg) => self.gen_expression(arg),
+                    None => self.emit(Instruction::I32Const(0)),
+                }
+            }
+        }
+
+        self.emit(Instruction::Call(Callee::Body(id)));
+        for (idx, class, addr) in copy_back {
+            self.emit(Instruction::I32Const(addr as i32));
+            self.emit(Instruction::Load(
+                load_op(class.unwrap_or(TypeClass::LInt)),
+                0,
+            ));
+            self.emit(Instruction::LocalSet(idx));
+        }
+
+        function_return_type(decl).is_some()
+    }
+
+    /// Call of function not declared in application, which is imported from the runtime.
+    /// Type of the import is derived from arguments.
+    fn gen_external_call(&mut self, call: &CallExpression, name: &StString) {
+        let mut params = vec![];
+        for arg in call.arguments() {
+            let value = match &arg.kind {
+                ExprKind::Assign(assign) => assign.right(),
+                _ => arg,
+            };
+
+            params.push(val_type(self.ctx.expression_class(value)));
+            self.gen_expression(value);
+        }
+
+        if !self.externals.iter().any(|(x, _)| x == name) {
+            self.externals.push((name.clone(), params));
+        }
+        self.emit(Instruction::Call(Callee::External(name.clone())));
+    }
+
+    /// Push default value of scalar type, for arguments of not connected inputs
+    fn gen_default_value(&mut self, ty: &Type, initial: Option<&Expression>) {
+        let class = self.scalar_class(ty);
+        if let Some(initial) = initial {
+            return self.gen_value(initial, class);
+        }
+
+        let v = self.ctx.default_integer(ty).unwrap_or(0);
+        self.emit_integer(v, class);
+    }
+
+    /// Push address of object to be initialized and returns the static offset
+    fn push_addr(&mut self, addr: Addr) -> u32 {
+        match addr {
+            Addr::Static(addr) => {
+                self.emit(Instruction::I32Const(addr as i32));
+                0
+            }
+            Addr::Local(idx, offset) => {
+                self.emit(Instruction::LocalGet(idx));
+                offset
+            }
+        }
+    }
+
+    fn push_full_addr(&mut self, addr: Addr) {
+        let offset = self.push_addr(addr);
+        self.gen_offset(offset);
+    }
+
+    /// Initialize object with initial value or default value of type
+    fn gen_init(&mut self, addr: Addr, ty: &Type, initial: Option<&Expression>) {
+        let resolved = self.ctx.resolve_type(ty);
+
+        if let Some(arr) = resolved.as_array() {
+            let base = arr.base_type().clone();
+            let size = self.size_of(&resolved);
+            if self.is_zero_default(&base) {
+                self.push_full_addr(addr);
+                self.emit(Instruction::I32Const(0));
+                self.emit(Instruction::I32Const(size as i32));
+                self.emit(Instruction::MemoryFill);
+                return;
+            }
+
+            // initialize elements one by one
+            let element = self.new_local(ValType::I32);
+            let end = self.new_local(ValType::I32);
+            self.push_full_addr(addr);
+            self.emit(Instruction::LocalTee(element));
+            self.emit(Instruction::I32Const(size as i32));
+            self.emit(NumericOp::I32Add);
+            self.emit(Instruction::LocalSet(end));
+
+            self.emit(Instruction::Block(BlockType::Empty));
+            self.emit(Instruction::Loop(BlockType::Empty));
+            self.emit(Instruction::LocalGet(element));
+            self.emit(Instruction::LocalGet(end));
+            self.emit(NumericOp::I32GeU);
+            self.emit(Instruction::BrIf(1));
+            self.gen_init(Addr::Local(element, 0), &base, None);
+            let element_size = self.size_of(&base);
+            self.emit(Instruction::LocalGet(element));
+            self.emit(Instruction::I32Const(element_size as i32));
+            self.emit(NumericOp::I32Add);
+            self.emit(Instruction::LocalSet(element));
+            self.emit(Instruction::Br(0));
+            self.emit(Instruction::End);
+            self.emit(Instruction::End);
+            return;
+        }
+
+        if let Some(id) = self.struct_id(&resolved) {
+            self.push_full_addr(addr);
+            self.emit(Instruction::Call(Callee::Init(id)));
+            return;
+        }
+
+        match self.scalar_class(ty) {
+            Some(class) => {
+                let offset = self.push_addr(addr);
+                self.gen_default_value(ty, initial);
+                self.emit(Instruction::Store(store_op(class), offset));
+            }
+            // strings
+            None => {
+                self.push_full_addr(addr);
+                self.emit(Instruction::I32Const(0));
+                self.emit(Instruction::I32Const(STRING_SIZE as i32));
+                self.emit(Instruction::MemoryFill);
+
+                if let Some(initial) = initial {
+                    self.push_full_addr(addr);
+                    self.gen_expression(initial);
+                    self.gen_copy(ty);
+                }
+            }
+        }
+    }
+
+    /// Zero bytes are the default value of type, objects can be initialized by `memory.fill`
+    fn is_zero_default(&self, ty: &Type) -> bool {
+        let ty = self.ctx.resolve_type(ty);
+        if let Some(v) = self.ctx.default_integer(&ty) {
+            return v == 0;
+        }
+        if self.struct_id(&ty).is_some() {
+            return false;
+        }
+
+        match ty.as_array() {
+            Some(arr) => self.is_zero_default(arr.base_type()),
+            None => true,
+        }
+    }
+
+    fn gen_statement(&mut self, stmt: &Statement) {
+        match &stmt.kind {
+            StmtKind::Expr(expr) => self.gen_expression_statement(expr.expr()),
+            StmtKind::If(ifst) => self.gen_if_statement(ifst),
+            StmtKind::Stmts(stmts) => {
+                for stmt in stmts.iter() {
+                    self.gen_statement(stmt);
+                }
+            }
+        }
+    }
+
+    fn gen_expression_statement(&mut self, expr: &Expression) {
+        trace!("WasmGen: expression statement: {}", expr);
+
+        match &expr.kind {
+            ExprKind::Assign(assign) => self.gen_assign(assign),
+            ExprKind::Call(call) => {
+                // function block instance call
+                if let Some(ty) = self.ctx.expression_type(call.callee()) {
+                    let decl = self.ctx.type_user_decl(&self.ctx.resolve_type(&ty));
+                    if let Some(fb) = decl.filter(|x| x.read().unwrap().is_function_block()) {
+                        return self.gen_fb_call(call, &fb);
+                    }
+                }
+
+                if self.gen_call(call) {
+                    self.emit(Instruction::Drop);
+                }
+            }
+            _ => {
+                self.gen_expression(expr);
+                self.emit(Instruction::Drop);
+            }
+        }
+    }
+
+    fn gen_assign(&mut self, assign: &AssignExpression) {
+        let lhs_type = self.ctx.expression_type(assign.left());
+        let lhs_class = lhs_type.as_ref().and_then(|x| self.scalar_class(x));
+
+        match assign.assign_type() {
+            AssignType::Set | AssignType::Reset => {
+                let value = matches!(assign.assign_type(), AssignType::Set);
+
+                self.gen_value(assign.right(), Some(TypeClass::Bool));
+                self.emit(Instruction::If(BlockType::Empty));
+                let place = self.gen_target(assign.left(), lhs_type.as_ref());
+                self.emit_integer(value as i64, lhs_class);
+                self.gen_store(place, lhs_type.as_ref());
+                self.emit(Instruction::End);
+            }
+            _ => {
+                let place = self.gen_target(assign.left(), lhs_type.as_ref());
+                match lhs_class {
+                    Some(class) => self.gen_value(assign.right(), Some(class)),
+                    None => self.gen_expression(assign.right()),
+                }
+                self.gen_store(place, lhs_type.as_ref());
+            }
+        }
+    }
+
+    fn gen_if_statement(&mut self, ifst: &IfStatement) {
+        trace!("WasmGen: if statement: {}", ifst.condition());
+
+        self.gen_value(ifst.condition(), Some(TypeClass::Bool));
+        self.emit(Instruction::If(BlockType::Empty));
+        if let Some(then) = ifst.then_controlled() {
+            self.gen_statement(then);
+        }
+
+        // else-if is nested in else
+        for else_if in ifst.else_if_list() {
+            self.emit(Instruction::Else);
+            self.gen_value(else_if.condition(), Some(TypeClass::Bool));
+            self.emit(Instruction::If(BlockType::Empty));
+            if let Some(then) = else_if.then_controlled() {
+                self.gen_statement(then);
+            }
+        }
+
+        if let Some(else_ctrl) = ifst.else_controlled() {
+            self.emit(Instruction::Else);
+            self.gen_statement(else_ctrl);
+        }
+
+        for _ in 0..=ifst.else_if_list().len() {
+            self.emit(Instruction::End);
+        }
+    }
+
+    /// Call function block instance: inputs are stored into instance, then call body of
+    /// function block with instance, outputs are read from instance after call.
+    fn gen_fb_call(&mut self, call: &CallExpression, fb: &Prototype) {
+        let (fb_id, members) = {
+            let fb = fb.read().unwrap();
+            (fb.id(), fb.variables().to_vec())
+        };
+        let layout = self.layout();
+
+        let inst = self.new_local(ValType::I32);
+        self.gen_address(call.callee());
+        self.emit(Instruction::LocalSet(inst));
+
+        // inputs
+        let mut inputs = members
+            .iter()
+            .filter(|x| x.flags().contains(VariableFlags::INPUT));
+        for arg in call.arguments() {
+            let (member, value) = match &arg.kind {
+                ExprKind::Assign(assign) => match assign.assign_type() {
+                    AssignType::AssignRight => continue,
+                    _ => {
+                        let name = expression_variable_name(assign.left());
+                        let member = members.iter().find(|x| Some(x.name()) == name);
+                        (member, assign.right())
+                    }
+                },
+                _ => (inputs.next(), arg),
+            };
+            // TODO: unknown parameter error
+            let Some(member) = member else { continue };
+            let Some(offset) = layout.member_offset(fb_id, member.name()) else {
+                continue;
+            };
+
+            let mut place = Place::Memory(offset);
+            self.emit(Instruction::LocalGet(inst));
+            match member.ty().and_then(|x| self.scalar_class(x)) {
+                Some(class) => self.gen_value(value, Some(class)),
+                None => {
+                    self.gen_offset(offset);
+                    place = Place::Memory(0);
+                    self.gen_expression(value);
+                }
+            }
+            self.gen_store(place, member.ty());
+        }
+
+        self.emit(Instruction::LocalGet(inst));
+        self.emit(Instruction::Call(Callee::Body(fb_id)));
+
+        // outputs
+        for arg in call.arguments() {
+            let ExprKind::Assign(assign) = &arg.kind else {
+                continue;
+            };
+            if !matches!(assign.assign_type(), AssignType::AssignRight) {
+                continue;
+            }
+            let name = expression_variable_name(assign.left());
+            let Some(member) = members.iter().find(|x| Some(x.name()) == name) else {
+                continue;
+            };
+            let Some(offset) = layout.member_offset(fb_id, member.name()) else {
+                continue;
+            };
+
+            let target_type = self.ctx.expression_type(assign.right());
+            let target_class = target_type.as_ref().and_then(|x| self.scalar_class(x));
+            let place = self.gen_target(assign.right(), target_type.as_ref());
+            self.emit(Instruction::LocalGet(inst));
+            self.gen_load(Place::Memory(offset), member.ty());
+            self.gen_convert(member.ty().and_then(|x| self.scalar_class(x)), target_class);
+            self.gen_store(place, target_type.as_ref());
+        }
+    }
+
+    /// Init function of struct or function block, which takes the object address
+    fn gen_init_function(&mut self, proto: &Prototype) -> WasmFunction {
+        self.enter_declaration(proto);
+        let (id, name, members) = {
+            let p = proto.read().unwrap();
+            (p.id(), p.name().string().to_owned(), p.variables().to_vec())
+        };
+        let layout = self.layout();
+
+        self.params.push(ValType::I32);
+        for member in members.iter() {
+            let Some(ty) = member.ty() else { continue };
+            let Some(offset) = layout.member_offset(id, member.name()) else {
+                continue;
+            };
+
+            self.gen_init(Addr::Local(0, offset), ty, member.initial().as_deref());
+        }
+
+        self.take_function(format!("{}_init", name), Callee::Init(id), vec![])
+    }
+
+    fn gen_function_block(&mut self, f: &Function, proto: &Prototype) -> Vec<WasmFunction> {
+        let init = self.gen_init_function(proto);
+
+        self.enter_declaration(proto);
+        let (id, name, members) = {
+            let p = proto.read().unwrap();
+            (p.id(), p.name().string().to_owned(), p.variables().to_vec())
+        };
+        let layout = self.layout();
+
+        self.params.push(ValType::I32);
+        for member in members.iter() {
+            if let Some(offset) = layout.member_offset(id, member.name()) {
+                self.variables
+                    .insert(member.name().clone(), Storage::Indirect(0, offset));
+            }
+        }
+
+        self.gen_statement(f.read().parse_tree());
+        let body = self.take_function(format!("{}_body", name), Callee::Body(id), vec![]);
+
+        vec![init, body]
+    }
+
+    fn gen_program(&mut self, f: &Function, proto: &Prototype) -> Vec<WasmFunction> {
+        let (id, name, variables) = {
+            let p = proto.read().unwrap();
+            (p.id(), p.name().string().to_owned(), p.variables().to_vec())
+        };
+        let layout = self.layout();
+
+        for variable in variables.iter() {
+            if let Some(addr) = layout.static_variable(id, variable.name()) {
+                self.variables
+                    .insert(variable.name().clone(), Storage::Static(addr));
+            }
+        }
+
+        self.gen_statement(f.read().parse_tree());
+        vec![self.take_function(format!("{}_body", name), Callee::Body(id), vec![])]
+    }
+
+    /// Scalar inputs are wasm parameters, aggregate inputs are passed by address and
+    /// copied into the frame of function, local variables not in wasm locals are in the
+    /// frame too.
+    fn gen_function_pou(&mut self, f: &Function, proto: &Prototype) -> Vec<WasmFunction> {
+        let (id, name, variables) = {
+            let p = proto.read().unwrap();
+            (p.id(), p.name().clone(), p.variables().to_vec())
+        };
+        let layout = self.layout();
+        let frame = |x: &StString| {
+            layout
+                .static_variable(id, x)
+                .expect("variable not in frame")
+        };
+
+        // parameters
+        let mut aggregate_inputs = vec![];
+        for variable in variables.iter().filter(|x| !x.scope().is_empty()) {
+            let ty = variable.ty().cloned().unwrap_or_else(LIntType::new_type);
+            let idx = self.params.len() as u32;
+
+            let (storage, param_type) = match self.scalar_class(&ty) {
+                _ if is_reference_parameter(variable) => (Storage::Indirect(idx, 0), ValType::I32),
+                Some(class) => (Storage::Local(idx), val_type(Some(class))),
+                None => {
+                    aggregate_inputs.push((idx, variable.clone()));
+                    (Storage::Static(frame(variable.name())), ValType::I32)
+                }
+            };
+            self.params.push(param_type);
+            self.variables.insert(variable.name().clone(), storage);
+        }
+
+        // absent aggregate inputs are initialized with default values
+        for (idx, variable) in aggregate_inputs {
+            let ty = variable.ty().cloned().unwrap_or_else(LIntType::new_type);
+            let addr = frame(variable.name());
+
+            self.emit(Instruction::LocalGet(idx));
+            self.emit(Instruction::If(BlockType::Empty));
+            self.emit(Instruction::I32Const(addr as i32));
+            self.emit(Instruction::LocalGet(idx));
+            self.gen_copy(&ty);
+            self.emit(Instruction::Else);
+            self.gen_init(Addr::Static(addr), &ty, variable.initial().as_deref());
+            self.emit(Instruction::End);
+        }
+
+        // return value and local variables
+        let mut locals: Vec<_> = variables
+            .iter()
+            .filter(|x| x.scope().is_empty())
+            .filter_map(|x| Some((x.name().clone(), x.ty()?.clone(), Some(x.clone()))))
+            .collect();
+        if let Some(ty) = self.ctx.return_type.clone() {
+            locals.insert(0, (name.clone(), ty, None));
+        }
+        for (name, ty, variable) in locals {
+            let initial = variable.as_ref().and_then(|x| x.initial().as_deref());
+            match self.scalar_class(&ty) {
+                Some(class) => {
+                    let idx = self.new_local(val_type(Some(class)));
+                    self.gen_default_value(&ty, initial);
+                    self.emit(Instruction::LocalSet(idx));
+                    self.variables.insert(name, Storage::Local(idx));
+                }
+                None => {
+                    let addr = frame(&name);
+                    self.gen_init(Addr::Static(addr), &ty, initial);
+                    self.variables.insert(name, Storage::Static(addr));
+                }
+            }
+        }
+
+        self.gen_statement(f.read().parse_tree());
+
+        // aggregates are returned by address in frame
+        let mut results = vec![];
+        if let Some(ty) = self.ctx.return_type.clone() {
+            let class = self.scalar_class(&ty);
+            match self.variables.get(&name) {
+                Some(Storage::Local(idx)) => self.emit(Instruction::LocalGet(*idx)),
+                Some(Storage::Static(addr)) => self.emit(Instruction::I32Const(*addr as i32)),
+                _ => unreachable!("return value not found"),
+            }
+            results.push(val_type(class.or(Some(TypeClass::DInt))));
+        }
+
+        vec![self.take_function(name.string().to_owned(), Callee::Body(id), results)]
+    }
+}
+
+fn compare_operator(op: Operator, ty: ValType, signed: bool) -> NumericOp {
+    match (ty, op, signed) {
+        (ValType::I32, Operator::Equal, _) => NumericOp::I32Eq,
+        (ValType::I32, Operator::NotEqual, _) => NumericOp::I32Ne,
+        (ValType::I32, Operator::Less, true) => NumericOp::I32LtS,
+        (ValType::I32, Operator::Less, false) => NumericOp::I32LtU,
+        (ValType::I32, Operator::LessEqual, true) => NumericOp::I32LeS,
+        (ValType::I32, Operator::LessEqual, false) => NumericOp::I32LeU,
+        (ValType::I32, Operator::Greater, true) => NumericOp::I32GtS,
+        (ValType::I32, Operator::Greater, false) => NumericOp::I32GtU,
+        (ValType::I32, Operator::GreaterEqual, true) => NumericOp::I32GeS,
+        (ValType::I32, _, false) => NumericOp::I32GeU,
+        (ValType::I64, Operator::Equal, _) => NumericOp::I64Eq,
+        (ValType::I64, Operator::NotEqual, _) => NumericOp::I64Ne,
+        (ValType::I64, Operator::Less, true) => NumericOp::I64LtS,
+        (ValType::I64, Operator::Less, false) => NumericOp::I64LtU,
+        (ValType::I64, Operator::LessEqual, true) => NumericOp::I64LeS,
+        (ValType::I64, Operator::LessEqual, false) => NumericOp::I64LeU,
+        (ValType::I64, Operator::Greater, true) => NumericOp::I64GtS,
+        (ValType::I64, Operator::Greater, false) => NumericOp::I64GtU,
+        (ValType::I64, Operator::GreaterEqual, true) => NumericOp::I64GeS,
+        (ValType::I64, _, false) => NumericOp::I64GeU,
+        (ValType::F32, Operator::Equal, _) => NumericOp::F32Eq,
+        (ValType::F32, Operator::NotEqual, _) => NumericOp::F32Ne,
+        (ValType::F32, Operator::Less, _) => NumericOp::F32Lt,
+        (ValType::F32, Operator::LessEqual, _) => NumericOp::F32Le,
+        (ValType::F32, Operator::Greater, _) => NumericOp::F32Gt,
+        (ValType::F32, _, _) => NumericOp::F32Ge,
+        (ValType::F64, Operator::Equal, _) => NumericOp::F64Eq,
+        (ValType::F64, Operator::NotEqual, _) => NumericOp::F64Ne,
+        (ValType::F64, Operator::Less, _) => NumericOp::F64Lt,
+        (ValType::F64, Operator::LessEqual, _) => NumericOp::F64Le,
+        (ValType::F64, Operator::Greater, _) => NumericOp::F64Gt,
+        (ValType::F64, _, _) => NumericOp::F64Ge,
+        _ => unreachable!("{:?}", op),
+    }
+}
+
+impl CodeGenBackend for WasmBackend {
+    type Label = u32;
+
+    fn new(mgr: UnitsManager, app: ModuleContext) -> Self {
+        Self {
+            ctx: TypeContext::new(mgr, app),
+            layout: None,
+            code: vec![],
+            params: vec![],
+            locals: vec![],
+            variables: HashMap::new(),
+            externals: vec![],
+            error: None,
+        }
+    }
+
+    fn gen_function(&mut self, func: usize) -> Result<Box<dyn CompiledCode>, CodeGenError> {
+        let app = self.ctx.app().read();
+        let f = app
+            .get_function(func)
+            .ok_or(CodeGenError::FunctionNotDefined(func))?
+            .clone();
+        let p = app
+            .get_declaration_by_id(func)
+            .ok_or(CodeGenError::FunctionNotDefined(func))?
+            .clone();
+        drop(app);
+
+        self.layout();
+        self.enter_declaration(&p);
+        self.externals.clear();
+        let functions = match self.ctx.pou_kind {
+            Some(PouKind::Function) => self.gen_function_pou(&f, &p),
+            Some(PouKind::FunctionBlock) => self.gen_function_block(&f, &p),
+            Some(PouKind::Program) => self.gen_program(&f, &p),
+            None => return Err(CodeGenError::FunctionNotDefined(func)),
+        };
+        if let Some(e) = self.error.take() {
+            return Err(e);
+        }
+
+        Ok(Box::new(WasmCompiledCode::new(
+            functions,
+            mem::take(&mut self.externals),
+        )))
+    }
+
+    /// Wasm has structured control flow only, labels are block depths
+    fn create_label<S: AsRef<str>>(&mut self, _label: S) -> Self::Label {
+        0
+    }
+
+    fn insert_label(&mut self, _label: Self::Label) {}
+
+    fn gen_variable_load(&mut self, variable: &mut Variable) {
+        if let Some(storage) = self.variable_storage(variable.name()) {
+            let ty = variable.ty().cloned();
+            let place = match storage {
+                Storage::Local(idx) => Place::Local(idx),
+                Storage::Static(addr) => {
+                    self.emit(Instruction::I32Const(addr as i32));
+                    Place::Memory(0)
+                }
+                Storage::Indirect(idx, offset) => {
+                    self.emit(Instruction::LocalGet(idx));
+                    Place::Memory(offset)
+                }
+            };
+            self.gen_load(place, ty.as_ref());
+            self.emit(Instruction::Drop);
+        }
+    }
+
+    fn gen_operator(&mut self, operator: &mut OperatorExpression) {
+        self.gen_operator_expression(operator);
+        self.emit(Instruction::Drop);
+    }
+
+    fn get_module_bytes(&mut self, w: &mut dyn Write) -> io::Result<()> {
+        wasm_write_module(self, w)
+    }
+
+    fn fold_constants(&self) -> bool {
+        true
+    }
+}
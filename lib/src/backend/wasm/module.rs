@@ -0,0 +1,854 @@
+use std::collections::{BTreeSet, HashMap};
+use std::fmt::Write as _;
+use std::io;
+use std::io::Write;
+
+use super::code::WasmFunction;
+use super::instruction::*;
+use super::{Addr, WasmBackend, WasmCompiledCode};
+use crate::backend::utils::{pou_kind, sorted_declarations, PouKind};
+use crate::prelude::*;
+
+const WASM_PAGE_SIZE: u32 = 65536;
+
+/// Message of integer division by zero
+const DIVISION_BY_ZERO: &str = "integer division by zero";
+
+/// Module assembled from compiled code of POUs, functions are in the order of index
+struct WasmModule {
+    /// Imported functions from `env`, with name and type
+    imports: Vec<(String, Vec<ValType>, Vec<ValType>)>,
+    functions: Vec<WasmFunction>,
+    types: Vec<(Vec<ValType>, Vec<ValType>)>,
+    indexes: HashMap<Callee, u32>,
+    names: HashMap<Callee, String>,
+    /// Offsets of variables exported as immutable globals
+    globals: Vec<(String, u32)>,
+    /// Programs exported as entry points of cycles
+    entries: Vec<(String, Callee)>,
+    pages: u32,
+    data_start: u32,
+    data: Vec<u8>,
+}
+
+impl WasmModule {
+    fn new(backend: &mut WasmBackend) -> Self {
+        let layout = backend.layout();
+        let decls = sorted_declarations(backend.ctx.app());
+
+        // functions of POUs
+        let mut functions = vec![];
+        let mut externals: Vec<(StString, Vec<ValType>)> = vec![];
+        let mut entries = vec![];
+        for decl in decls.iter() {
+            let (id, name, is_program) = {
+                let p = decl.read().unwrap();
+                let is_program = matches!(pou_kind(p.decl()), Some(PouKind::Program));
+                (p.id(), p.name().string().to_owned(), is_program)
+            };
+            let Some(f) = backend.ctx.app().read().get_function(id).cloned() else {
+                continue;
+            };
+
+            let f = f.read();
+            let Some(code) = f
+                .compiled_code()
+                .as_ref()
+                .and_then(|x| x.as_any().downcast_ref::<WasmCompiledCode>())
+            else {
+                continue;
+            };
+
+            functions.extend(code.functions().iter().cloned());
+            for (name, params) in code.externals() {
+                if !externals.iter().any(|(x, _)| x == name) {
+                    externals.push((name.clone(), params.clone()));
+                }
+            }
+            if is_program {
+                entries.push((name, Callee::Body(id)));
+            }
+        }
+
+        // init functions of structs
+        let mut struct_inits = vec![];
+        for decl in decls.iter() {
+            if matches!(decl.read().unwrap().decl().kind, DeclKind::Struct(_)) {
+                struct_inits.push(backend.gen_init_function(decl));
+            }
+        }
+        let stc_init = stc_init_function(backend, &decls);
+
+        // runtime helpers used by application, including those used by helpers
+        let mut runtime = BTreeSet::new();
+        let mut pending: Vec<_> = functions
+            .iter()
+            .chain(struct_inits.iter())
+            .chain(Some(&stc_init))
+            .flat_map(|x| called_runtime(&x.body))
+            .collect();
+        let mut helpers = vec![];
+        while let Some(r) = pending.pop() {
+            if !runtime.insert(r) || r.is_import() {
+                continue;
+            }
+
+            let helper = runtime_function(r);
+            pending.extend(called_runtime(&helper.body));
+            helpers.push(helper);
+        }
+        helpers.sort_by_key(|x| match x.callee {
+            Callee::Runtime(r) => r,
+            _ => unreachable!(),
+        });
+
+        // imports are always first in function index space, `stc_runtime_error` is always
+        // imported as function 0
+        runtime.insert(Runtime::Error);
+        let mut imports = vec![];
+        let mut indexes = HashMap::new();
+        let mut names = HashMap::new();
+        for r in runtime.iter().filter(|x| x.is_import()) {
+            let (params, results) = r.import_type();
+            indexes.insert(Callee::Runtime(*r), imports.len() as u32);
+            imports.push((r.name().to_owned(), params, results));
+        }
+        for (name, params) in externals {
+            indexes.insert(Callee::External(name.clone()), imports.len() as u32);
+            imports.push((name.string().to_owned(), params, vec![]));
+        }
+        for callee in indexes.keys() {
+            names.insert(callee.clone(), callee_import_name(callee));
+        }
+
+        let functions: Vec<_> = helpers
+            .into_iter()
+            .chain(struct_inits)
+            .chain(functions)
+            .chain(Some(stc_init))
+            .collect();
+        for (idx, function) in functions.iter().enumerate() {
+            indexes.insert(function.callee.clone(), (imports.len() + idx) as u32);
+            names.insert(function.callee.clone(), function.name.clone());
+        }
+
+        // string literals and messages are placed after all objects
+        let data_start = layout.data_start();
+        let mut data = vec![];
+        let mut data_offsets = HashMap::new();
+        let functions = functions
+            .into_iter()
+            .map(|mut f| {
+                for instruction in f.body.iter_mut() {
+                    let Instruction::Data(bytes) = instruction else {
+                        continue;
+                    };
+
+                    let offset = *data_offsets.entry(bytes.clone()).or_insert_with(|| {
+                        let offset = data.len() as u32;
+                        data.extend_from_slice(bytes);
+                        data.push(0);
+                        offset
+                    });
+                    *instruction = Instruction::I32Const((data_start + offset) as i32);
+                }
+                f
+            })
+            .collect();
+
+        let mut module = Self {
+            imports,
+            functions,
+            types: vec![],
+            indexes,
+            names,
+            globals: layout.exports().to_vec(),
+            entries,
+            pages: (data_start + data.len() as u32)
+                .div_ceil(WASM_PAGE_SIZE)
+                .max(1),
+            data_start,
+            data,
+        };
+
+        // function types
+        let signatures: Vec<_> = module
+            .imports
+            .iter()
+            .map(|(_, params, results)| (params.clone(), results.clone()))
+            .chain(
+                module
+                    .functions
+                    .iter()
+                    .map(|f| (f.params.clone(), f.results.clone())),
+            )
+            .collect();
+        for signature in signatures {
+            if !module.types.contains(&signature) {
+                module.types.push(signature);
+            }
+        }
+
+        module
+    }
+
+    fn type_index(&self, params: &[ValType], results: &[ValType]) -> usize {
+        self.types
+            .iter()
+            .position(|(p, r)| p == params && r == results)
+            .expect("function type not found")
+    }
+
+    fn index(&self, callee: &Callee) -> u32 {
+        *self
+            .indexes
+            .get(callee)
+            .unwrap_or_else(|| panic!("function not found: {:?}", callee))
+    }
+
+    fn name(&self, callee: &Callee) -> String {
+        self.names
+            .get(callee)
+            .cloned()
+            .unwrap_or_else(|| panic!("function not found: {:?}", callee))
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let mut buf = b"\0asm\x01\0\0\0".to_vec();
+
+        // type section
+        write_section(&mut buf, 1, self.types.len(), |s| {
+            for (params, results) in self.types.iter() {
+                s.push(0x60);
+                write_val_types(s, params);
+                write_val_types(s, results);
+            }
+        });
+
+        // import section
+        write_section(&mut buf, 2, self.imports.len(), |s| {
+            for (name, params, results) in self.imports.iter() {
+                write_name(s, "env");
+                write_name(s, name);
+                s.push(0x00);
+                write_unsigned(s, self.type_index(params, results) as u64);
+            }
+        });
+
+        // function section
+        write_section(&mut buf, 3, self.functions.len(), |s| {
+            for f in self.functions.iter() {
+                write_unsigned(s, self.type_index(&f.params, &f.results) as u64);
+            }
+        });
+
+        // memory section
+        write_section(&mut buf, 5, 1, |s| {
+            s.push(0x00);
+            write_unsigned(s, self.pages as u64);
+        });
+
+        // global section
+        write_section(&mut buf, 6, self.globals.len(), |s| {
+            for (_, offset) in self.globals.iter() {
+                s.push(ValType::I32.code());
+                s.push(0x00);
+                Instruction::I32Const(*offset as i32).encode(s, &|_| 0);
+                Instruction::End.encode(s, &|_| 0);
+            }
+        });
+
+        // export section
+        let exports = 2 + self.entries.len() + self.globals.len();
+        write_section(&mut buf, 7, exports, |s| {
+            write_name(s, "memory");
+            s.push(0x02);
+            write_unsigned(s, 0);
+
+            write_name(s, "stc_init");
+            s.push(0x00);
+            write_unsigned(s, self.index(&Callee::Runtime(Runtime::Init)) as u64);
+
+            for (name, callee) in self.entries.iter() {
+                write_name(s, name);
+                s.push(0x00);
+                write_unsigned(s, self.index(callee) as u64);
+            }
+
+            for (idx, (name, _)) in self.globals.iter().enumerate() {
+                write_name(s, name);
+                s.push(0x03);
+                write_unsigned(s, idx as u64);
+            }
+        });
+
+        // code section
+        let resolve = |callee: &Callee| self.index(callee);
+        write_section(&mut buf, 10, self.functions.len(), |s| {
+            for f in self.functions.iter() {
+                f.encode(s, &resolve);
+            }
+        });
+
+        // data section
+        if !self.data.is_empty() {
+            write_section(&mut buf, 11, 1, |s| {
+                s.push(0x00);
+                Instruction::I32Const(self.data_start as i32).encode(s, &|_| 0);
+                Instruction::End.encode(s, &|_| 0);
+                write_unsigned(s, self.data.len() as u64);
+                s.extend_from_slice(&self.data);
+            });
+        }
+
+        buf
+    }
+
+    fn text(&self) -> String {
+        let mut s = String::from("(module\n");
+
+        for (idx, (params, results)) in self.types.iter().enumerate() {
+            write!(s, "  (type (;{};) (func", idx).unwrap();
+            for param in params {
+                write!(s, " (param {})", param).unwrap();
+            }
+            for result in results {
+                write!(s, " (result {})", result).unwrap();
+            }
+            s.push_str("))\n");
+        }
+
+        for (name, params, results) in self.imports.iter() {
+            writeln!(
+                s,
+                "  (import \"env\" \"{}\" (func ${} (type {})))",
+                name,
+                name,
+                self.type_index(params, results)
+            )
+            .unwrap();
+        }
+
+        writeln!(s, "  (memory (export \"memory\") {})", self.pages).unwrap();
+        for (name, offset) in self.globals.iter() {
+            writeln!(
+                s,
+                "  (global (export {}) i32 (i32.const {}))",
+                data_text(name.as_bytes()),
+                offset
+            )
+            .unwrap();
+        }
+
+        let name = |callee: &Callee| self.name(callee);
+        for f in self.functions.iter() {
+            f.write_text(&mut s, Some(self.type_index(&f.params, &f.results)), &name);
+        }
+
+        writeln!(s, "  (export \"stc_init\" (func $stc_init))").unwrap();
+        for (export, callee) in self.entries.iter() {
+            writeln!(
+                s,
+                "  (export {} (func ${}))",
+                data_text(export.as_bytes()),
+                self.name(callee)
+            )
+            .unwrap();
+        }
+
+        if !self.data.is_empty() {
+            writeln!(
+                s,
+                "  (data (i32.const {}) {})",
+                self.data_start,
+                data_text(&self.data)
+            )
+            .unwrap();
+        }
+
+        s.push_str(")\n");
+        s
+    }
+}
+
+fn callee_import_name(callee: &Callee) -> String {
+    match callee {
+        Callee::Runtime(r) => r.name().to_owned(),
+        Callee::External(name) => name.string().to_owned(),
+        _ => unreachable!("{:?} is not imported", callee),
+    }
+}
+
+fn write_name(buf: &mut Vec<u8>, name: &str) {
+    write_unsigned(buf, name.len() as u64);
+    buf.extend_from_slice(name.as_bytes());
+}
+
+fn write_val_types(buf: &mut Vec<u8>, types: &[ValType]) {
+    write_unsigned(buf, types.len() as u64);
+    buf.extend(types.iter().map(|x| x.code()));
+}
+
+/// Section with id and vector of `count` items written by `f`
+fn write_section<F: FnOnce(&mut Vec<u8>)>(buf: &mut Vec<u8>, id: u8, count: usize, f: F) {
+    let mut section = vec![];
+    write_unsigned(&mut section, count as u64);
+    f(&mut section);
+
+    buf.push(id);
+    write_unsigned(buf, section.len() as u64);
+    buf.extend_from_slice(&section);
+}
+
+fn called_runtime(body: &[Instruction]) -> impl Iterator<Item = Runtime> + '_ {
+    body.iter().filter_map(|x| match x {
+        Instruction::Call(Callee::Runtime(r)) => Some(*r),
+        _ => None,
+    })
+}
+
+/// `stc_init()` initializes global variables and variables of programs
+fn stc_init_function(backend: &mut WasmBackend, decls: &[Prototype]) -> WasmFunction {
+    let layout = backend.layout();
+    let mut code = vec![];
+    let mut locals = vec![];
+
+    for decl in decls {
+        let (id, variables, is_global) = {
+            let p = decl.read().unwrap();
+            match (&p.decl().kind, pou_kind(p.decl())) {
+                (DeclKind::GlobalVar(_) | DeclKind::Configuration(_), _) => {
+                    (p.id(), p.decl().variables().to_vec(), true)
+                }
+                (_, Some(PouKind::Program)) => (p.id(), p.variables().to_vec(), false),
+                _ => continue,
+            }
+        };
+
+        backend.enter_declaration(decl);
+        backend.locals = locals;
+        for variable in variables {
+            let Some(ty) = variable.ty() else { continue };
+            let addr = match is_global {
+                true => layout.global(variable.name()),
+                false => layout.static_variable(id, variable.name()),
+            };
+            let Some(addr) = addr else { continue };
+
+            backend.gen_init(Addr::Static(addr), ty, variable.initial().as_deref());
+        }
+
+        code.append(&mut backend.code);
+        locals = std::mem::take(&mut backend.locals);
+    }
+
+    WasmFunction {
+        name: "stc_init".to_owned(),
+        callee: Callee::Runtime(Runtime::Init),
+        params: vec![],
+        results: vec![],
+        locals,
+        body: code,
+    }
+}
+
+/// Report division by zero if the divisor in local 1 is zero
+fn check_divisor(code: &mut Vec<Instruction>, eqz: NumericOp) {
+    code.extend([
+        Instruction::LocalGet(1),
+        eqz.into(),
+        Instruction::If(BlockType::Empty),
+        Instruction::Data(DIVISION_BY_ZERO.as_bytes().to_vec()),
+        Instruction::I32Const(DIVISION_BY_ZERO.len() as i32),
+        Instruction::Call(Callee::Runtime(Runtime::Error)),
+        Instruction::Unreachable,
+        Instruction::End,
+    ]);
+}
+
+/// Mask of the low `bits` bits in local 2, `-1 >> (64 - bits)`
+fn shift_mask() -> [Instruction; 5] {
+    [
+        Instruction::I64Const(-1),
+        Instruction::I64Const(64),
+        Instruction::LocalGet(2),
+        NumericOp::I64Sub.into(),
+        NumericOp::I64ShrU.into(),
+    ]
+}
+
+/// Negative shift count in local 1 is 0
+fn clamp_shift_count(code: &mut Vec<Instruction>) {
+    code.extend([
+        Instruction::LocalGet(1),
+        Instruction::I64Const(0),
+        NumericOp::I64LtS.into(),
+        Instruction::If(BlockType::Empty),
+        Instruction::I64Const(0),
+        Instruction::LocalSet(1),
+        Instruction::End,
+    ]);
+}
+
+/// Body of runtime helper provided by module
+fn runtime_function(r: Runtime) -> WasmFunction {
+    use Instruction::*;
+    use ValType::*;
+
+    let (params, results, locals, body) = match r {
+        // `stc_index(index, lower, len, message, message_len) -> offset`
+        Runtime::Index => {
+            let body = vec![
+                LocalGet(0),
+                LocalGet(1),
+                NumericOp::I64Sub.into(),
+                LocalTee(5),
+                LocalGet(2),
+                NumericOp::I64GeU.into(),
+                If(BlockType::Empty),
+                LocalGet(3),
+                LocalGet(4),
+                Call(Callee::Runtime(Runtime::Error)),
+                Unreachable,
+                End,
+                LocalGet(5),
+                NumericOp::I32WrapI64.into(),
+            ];
+            (vec![I64, I64, I64, I32, I32], vec![I32], vec![I64], body)
+        }
+        // overflow of `MIN / -1` wraps around and `MIN MOD -1` is 0
+        Runtime::DivI32 | Runtime::ModI32 | Runtime::DivI64 | Runtime::ModI64 => {
+            let (ty, eqz, eq, sub, op, minus_one, zero) = match r {
+                Runtime::DivI32 | Runtime::ModI32 => (
+                    I32,
+                    NumericOp::I32Eqz,
+                    NumericOp::I32Eq,
+                    NumericOp::I32Sub,
+                    if r == Runtime::DivI32 {
+                        NumericOp::I32DivS
+                    } else {
+                        NumericOp::I32RemS
+                    },
+                    I32Const(-1),
+                    I32Const(0),
+                ),
+                _ => (
+                    I64,
+                    NumericOp::I64Eqz,
+                    NumericOp::I64Eq,
+                    NumericOp::I64Sub,
+                    if r == Runtime::DivI64 {
+                        NumericOp::I64DivS
+                    } else {
+                        NumericOp::I64RemS
+                    },
+                    I64Const(-1),
+                    I64Const(0),
+                ),
+            };
+
+            let mut body = vec![];
+            check_divisor(&mut body, eqz);
+            body.extend([LocalGet(1), minus_one, eq.into(), If(BlockType::Empty)]);
+            match r {
+                Runtime::DivI32 | Runtime::DivI64 => {
+                    body.extend([zero, LocalGet(0), sub.into(), Return])
+                }
+                _ => body.extend([zero, Return]),
+            }
+            body.extend([End, LocalGet(0), LocalGet(1), op.into()]);
+
+            (vec![ty, ty], vec![ty], vec![], body)
+        }
+        Runtime::DivU32 | Runtime::ModU32 | Runtime::DivU64 | Runtime::ModU64 => {
+            let (ty, eqz, op) = match r {
+                Runtime::DivU32 => (I32, NumericOp::I32Eqz, NumericOp::I32DivU),
+                Runtime::ModU32 => (I32, NumericOp::I32Eqz, NumericOp::I32RemU),
+                Runtime::DivU64 => (I64, NumericOp::I64Eqz, NumericOp::I64DivU),
+                _ => (I64, NumericOp::I64Eqz, NumericOp::I64RemU),
+            };
+
+            let mut body = vec![];
+            check_divisor(&mut body, eqz);
+            body.extend([LocalGet(0), LocalGet(1), op.into()]);
+
+            (vec![ty, ty], vec![ty], vec![], body)
+        }
+        // exponentiation by squaring
+        Runtime::PowU64 => {
+            let body = vec![
+                I64Const(1),
+                LocalSet(2),
+                Block(BlockType::Empty),
+                Loop(BlockType::Empty),
+                LocalGet(1),
+                NumericOp::I64Eqz.into(),
+                BrIf(1),
+                LocalGet(1),
+                I64Const(1),
+                NumericOp::I64And.into(),
+                NumericOp::I32WrapI64.into(),
+                If(BlockType::Empty),
+                LocalGet(2),
+                LocalGet(0),
+                NumericOp::I64Mul.into(),
+                LocalSet(2),
+                End,
+                LocalGet(0),
+                LocalGet(0),
+                NumericOp::I64Mul.into(),
+                LocalSet(0),
+                LocalGet(1),
+                I64Const(1),
+                NumericOp::I64ShrU.into(),
+                LocalSet(1),
+                Br(0),
+                End,
+                End,
+                LocalGet(2),
+            ];
+            (vec![I64, I64], vec![I64], vec![I64], body)
+        }
+        // negative exponent gives 0 unless base is 1 or -1
+        Runtime::PowI64 => {
+            let body = vec![
+                LocalGet(1),
+                I64Const(0),
+                NumericOp::I64LtS.into(),
+                If(BlockType::Empty),
+                LocalGet(0),
+                I64Const(1),
+                NumericOp::I64Eq.into(),
+                If(BlockType::Empty),
+                I64Const(1),
+                Return,
+                End,
+                LocalGet(0),
+                I64Const(-1),
+                NumericOp::I64Eq.into(),
+                If(BlockType::Empty),
+                I64Const(-1),
+                I64Const(1),
+                LocalGet(1),
+                I64Const(1),
+                NumericOp::I64And.into(),
+                NumericOp::I32WrapI64.into(),
+                Select,
+                Return,
+                End,
+                I64Const(0),
+                Return,
+                End,
+                LocalGet(0),
+                LocalGet(1),
+                Call(Callee::Runtime(Runtime::PowU64)),
+            ];
+            (vec![I64, I64], vec![I64], vec![], body)
+        }
+        // `n >= bits` shifts all bits out
+        Runtime::Shl | Runtime::Shr => {
+            let mut body = vec![];
+            clamp_shift_count(&mut body);
+            body.extend([
+                LocalGet(1),
+                LocalGet(2),
+                NumericOp::I64GeU.into(),
+                If(BlockType::Empty),
+                I64Const(0),
+                Return,
+                End,
+                LocalGet(0),
+            ]);
+            if r == Runtime::Shl {
+                body.extend([LocalGet(1), NumericOp::I64Shl.into()]);
+                body.extend(shift_mask());
+                body.push(NumericOp::I64And.into());
+            } else {
+                body.extend(shift_mask());
+                body.extend([
+                    NumericOp::I64And.into(),
+                    LocalGet(1),
+                    NumericOp::I64ShrU.into(),
+                ]);
+            }
+            (vec![I64, I64, I64], vec![I64], vec![], body)
+        }
+        // `((v << n) | (v >> (bits - n))) & mask` with `n = n % bits`
+        Runtime::Rol => {
+            let mut body = vec![];
+            clamp_shift_count(&mut body);
+            body.extend([
+                LocalGet(1),
+                LocalGet(2),
+                NumericOp::I64RemU.into(),
+                LocalSet(1),
+                LocalGet(0),
+            ]);
+            body.extend(shift_mask());
+            body.extend([
+                NumericOp::I64And.into(),
+                LocalSet(0),
+                LocalGet(1),
+                NumericOp::I64Eqz.into(),
+                If(BlockType::Empty),
+                LocalGet(0),
+                Return,
+                End,
+                LocalGet(0),
+                LocalGet(1),
+                NumericOp::I64Shl.into(),
+                LocalGet(0),
+                LocalGet(2),
+                LocalGet(1),
+                NumericOp::I64Sub.into(),
+                NumericOp::I64ShrU.into(),
+                NumericOp::I64Or.into(),
+            ]);
+            body.extend(shift_mask());
+            body.push(NumericOp::I64And.into());
+            (vec![I64, I64, I64], vec![I64], vec![], body)
+        }
+        // rotate left by `(bits - n % bits) % bits`
+        Runtime::Ror => {
+            let mut body = vec![];
+            clamp_shift_count(&mut body);
+            body.extend([
+                LocalGet(0),
+                LocalGet(2),
+                LocalGet(1),
+                LocalGet(2),
+                NumericOp::I64RemU.into(),
+                NumericOp::I64Sub.into(),
+                LocalGet(2),
+                NumericOp::I64RemU.into(),
+                LocalGet(2),
+                Call(Callee::Runtime(Runtime::Rol)),
+            ]);
+            (vec![I64, I64, I64], vec![I64], vec![], body)
+        }
+        // `a - b * trunc(a / b)`, the same as `fmod()` of C
+        Runtime::FModF32 | Runtime::FModF64 => {
+            let (ty, ops) = match r {
+                Runtime::FModF32 => (
+                    F32,
+                    [
+                        NumericOp::F32Div,
+                        NumericOp::F32Trunc,
+                        NumericOp::F32Mul,
+                        NumericOp::F32Sub,
+                    ],
+                ),
+                _ => (
+                    F64,
+                    [
+                        NumericOp::F64Div,
+                        NumericOp::F64Trunc,
+                        NumericOp::F64Mul,
+                        NumericOp::F64Sub,
+                    ],
+                ),
+            };
+
+            let body = vec![
+                LocalGet(0),
+                LocalGet(1),
+                LocalGet(0),
+                LocalGet(1),
+                ops[0].into(),
+                ops[1].into(),
+                ops[2].into(),
+                ops[3].into(),
+            ];
+            (vec![ty, ty], vec![ty], vec![], body)
+        }
+        // `stc_string_assign(dst, src)`, at most 80 bytes are copied
+        Runtime::StringAssign => {
+            let body = vec![
+                Block(BlockType::Empty),
+                Loop(BlockType::Empty),
+                LocalGet(2),
+                I32Const(super::STRING_SIZE as i32 - 1),
+                NumericOp::I32GeU.into(),
+                BrIf(1),
+                LocalGet(1),
+                LocalGet(2),
+                NumericOp::I32Add.into(),
+                Load(MemoryOp::I32Load8U, 0),
+                NumericOp::I32Eqz.into(),
+                BrIf(1),
+                LocalGet(2),
+                I32Const(1),
+                NumericOp::I32Add.into(),
+                LocalSet(2),
+                Br(0),
+                End,
+                End,
+                LocalGet(0),
+                LocalGet(1),
+                LocalGet(2),
+                MemoryCopy,
+                LocalGet(0),
+                LocalGet(2),
+                NumericOp::I32Add.into(),
+                I32Const(0),
+                Store(MemoryOp::I32Store8, 0),
+            ];
+            (vec![I32, I32], vec![], vec![I32], body)
+        }
+        // `stc_string_compare(a, b)`, compares bytes like `strcmp()`
+        Runtime::StringCompare => {
+            let body = vec![
+                Loop(BlockType::Empty),
+                LocalGet(0),
+                LocalGet(2),
+                NumericOp::I32Add.into(),
+                Load(MemoryOp::I32Load8U, 0),
+                LocalTee(3),
+                LocalGet(1),
+                LocalGet(2),
+                NumericOp::I32Add.into(),
+                Load(MemoryOp::I32Load8U, 0),
+                LocalTee(4),
+                NumericOp::I32Ne.into(),
+                If(BlockType::Empty),
+                LocalGet(3),
+                LocalGet(4),
+                NumericOp::I32Sub.into(),
+                Return,
+                End,
+                LocalGet(3),
+                NumericOp::I32Eqz.into(),
+                If(BlockType::Empty),
+                I32Const(0),
+                Return,
+                End,
+                LocalGet(2),
+                I32Const(1),
+                NumericOp::I32Add.into(),
+                LocalSet(2),
+                Br(0),
+                End,
+                Unreachable,
+            ];
+            (vec![I32, I32], vec![I32], vec![I32, I32, I32], body)
+        }
+        _ => unreachable!("{:?} is not a helper", r),
+    };
+
+    WasmFunction {
+        name: r.name().to_owned(),
+        callee: Callee::Runtime(r),
+        params,
+        results,
+        locals,
+        body,
+    }
+}
+
+pub fn wasm_write_module(backend: &mut WasmBackend, w: &mut dyn Write) -> io::Result<()> {
+    w.write_all(&WasmModule::new(backend).encode())
+}
+
+pub fn wasm_write_text(backend: &mut WasmBackend, w: &mut dyn Write) -> io::Result<()> {
+    w.write_all(WasmModule::new(backend).text().as_bytes())
+}
@@ -0,0 +1,451 @@
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::Arc;
+use wasmi::{Caller, Engine, Error, Instance, Linker, Module, Store};
+
+use crate::backend::{CodeGenBackend, CodeGenDriver, CodeGenError, WasmBackend};
+use crate::test::fixtures::{self, *};
+
+/// Generate application with multiple POUs, each POU is (declaration, body).
+/// Returns the binary module and the module in text format.
+fn generate_application(pous: &[(&str, &str)]) -> (Vec<u8>, String) {
+    let mut code_gen = build_application(pous).expect("build app failed");
+
+    let mut bytes = vec![];
+    code_gen
+        .backend()
+        .get_module_bytes(&mut bytes)
+        .expect("get module bytes failed");
+
+    let mut text = vec![];
+    code_gen
+        .backend()
+        .get_module_text(&mut text)
+        .expect("get module text failed");
+
+    (bytes, String::from_utf8(text).unwrap())
+}
+
+/// Build application, each POU is (declaration, body)
+fn build_application(pous: &[(&str, &str)]) -> Result<CodeGenDriver<WasmBackend>, CodeGenError> {
+    fixtures::build_application(pous, |_| {})
+}
+
+/// Host implementation of math import
+type MathFunction = fn(f64) -> f64;
+
+/// Instance of application, `log` holds reported values and runtime errors, `time` is
+/// the time of timers in nanoseconds
+struct Simulation {
+    store: Store<Vec<String>>,
+    instance: Instance,
+    time: Arc<AtomicI64>,
+}
+
+impl Simulation {
+    fn new(wasm: &[u8]) -> Self {
+        let engine = Engine::default();
+        let module = Module::new(&engine, wasm).expect("invalid module");
+        let mut store = Store::new(&engine, vec![]);
+
+        let time = Arc::new(AtomicI64::new(0));
+
+        let mut linker = Linker::<Vec<String>>::new(&engine);
+        let now = time.clone();
+        linker
+            .func_wrap("env", "stc_now", move || now.load(Ordering::Relaxed))
+            .unwrap();
+        linker
+            .func_wrap(
+                "env",
+                "stc_runtime_error",
+                |mut caller: Caller<'_, Vec<String>>, message: i32, len: i32| {
+                    let memory = caller
+                        .get_export("memory")
+                        .and_then(|x| x.into_memory())
+                        .unwrap();
+                    let bytes = &memory.data(&caller)[message as usize..(message + len) as usize];
+                    let message = String::from_utf8_lossy(bytes).to_string();
+
+                    caller.data_mut().push(message.clone());
+                    Err::<(), _>(Error::new(message))
+                },
+            )
+            .unwrap()
+            .func_wrap("env", "stc_pow_f64", |a: f64, b: f64| a.powf(b))
+            .unwrap();
+        let math: [(&str, MathFunction); 9] = [
+            ("ln", f64::ln),
+            ("log10", f64::log10),
+            ("exp", f64::exp),
+            ("sin", f64::sin),
+            ("cos", f64::cos),
+            ("tan", f64::tan),
+            ("asin", f64::asin),
+            ("acos", f64::acos),
+            ("atan", f64::atan),
+        ];
+        for (name, f) in math {
+            linker
+                .func_wrap("env", &format!("stc_{}_f64", name), move |x: f64| f(x))
+                .unwrap();
+        }
+        linker
+            .func_wrap(
+                "env",
+                "report",
+                |mut caller: Caller<'_, Vec<String>>, value: i32| {
+                    caller.data_mut().push(format!("report {}", value));
+                },
+            )
+            .unwrap();
+
+        let instance = linker
+            .instantiate(&mut store, &module)
+            .and_then(|x| x.start(&mut store))
+            .expect("instantiate failed");
+
+        Self {
+            store,
+            instance,
+            time,
+        }
+    }
+
+    fn set_time(&self, time: i64) {
+        self.time.store(time, Ordering::Relaxed);
+    }
+
+    /// Call exported function without parameters and results
+    fn call(&mut self, name: &str) -> Result<(), Error> {
+        self.instance
+            .get_typed_func::<(), ()>(&self.store, name)
+            .expect("function not exported")
+            .call(&mut self.store, ())
+    }
+
+    /// Address of variable exported as global, like `main.a`
+    fn address(&self, name: &str) -> usize {
+        self.instance
+            .get_global(&self.store, name)
+            .and_then(|x| x.get(&self.store).i32())
+            .unwrap_or_else(|| panic!("variable {} not exported", name)) as usize
+    }
+
+    fn bytes<const N: usize>(&self, name: &str) -> [u8; N] {
+        let addr = self.address(name);
+        let memory = self.instance.get_memory(&self.store, "memory").unwrap();
+
+        memory.data(&self.store)[addr..addr + N].try_into().unwrap()
+    }
+
+    /// Value of variable as string, `ty` is the Rust type of variable
+    fn value(&self, name: &str, ty: &str) -> String {
+        match ty {
+            "i8" => i8::from_le_bytes(self.bytes(name)).to_string(),
+            "u8" => u8::from_le_bytes(self.bytes(name)).to_string(),
+            "i16" => i16::from_le_bytes(self.bytes(name)).to_string(),
+            "u16" => u16::from_le_bytes(self.bytes(name)).to_string(),
+            "i32" => i32::from_le_bytes(self.bytes(name)).to_string(),
+            "u32" => u32::from_le_bytes(self.bytes(name)).to_string(),
+            "i64" => i64::from_le_bytes(self.bytes(name)).to_string(),
+            "u64" => u64::from_le_bytes(self.bytes(name)).to_string(),
+            "f32" => f32::from_le_bytes(self.bytes(name)).to_string(),
+            "f64" => f64::from_le_bytes(self.bytes(name)).to_string(),
+            "str" => {
+                let bytes: [u8; 81] = self.bytes(name);
+                let len = bytes.iter().position(|x| *x == 0).unwrap();
+                String::from_utf8_lossy(&bytes[..len]).to_string()
+            }
+            _ => unreachable!("{}", ty),
+        }
+    }
+
+    fn log(&self) -> &[String] {
+        self.store.data()
+    }
+}
+
+/// Run `main` for `cycles` times after `stc_init`, cycles are 10ms apart in the time of
+/// timers. Then read variables of `main`, each variable is (name, type). The binary module
+/// and the text module must give the same results. Returns the values and the log.
+fn exec_application(
+    pous: &[(&str, &str)],
+    cycles: usize,
+    variables: &[(&str, &str)],
+) -> (Result<String, String>, Vec<String>) {
+    let (wasm, text) = generate_application(pous);
+    let from_text = wat::parse_str(&text).unwrap_or_else(|e| panic!("{}\n{}", e, text));
+
+    let mut results = vec![];
+    for wasm in [wasm, from_text] {
+        let mut sim = Simulation::new(&wasm);
+        let r = sim
+            .call("stc_init")
+            .and_then(|_| {
+                (0..cycles).try_for_each(|i| {
+                    sim.set_time(i as i64 * 10_000_000);
+                    sim.call("main")
+                })
+            })
+            .map(|_| {
+                let values: Vec<_> = variables
+                    .iter()
+                    .map(|(name, ty)| sim.value(&format!("main.{}", name), ty))
+                    .collect();
+                values.join(" ")
+            })
+            .map_err(|_| sim.log().last().cloned().unwrap_or_default());
+
+        results.push((r, sim.log().to_vec()));
+    }
+
+    assert_eq!(results[0], results[1], "{}", text);
+    results.pop().unwrap()
+}
+
+fn exec_report(pous: &[(&str, &str)], cycles: usize, variables: &[(&str, &str)]) -> String {
+    let (r, _) = exec_application(pous, cycles, variables);
+    r.unwrap()
+}
+
+#[test]
+fn test_integer_wrap_around() {
+    let r = exec_report(
+        &[INTEGER_WRAP_AROUND],
+        1,
+        &[
+            ("a", "i8"),
+            ("b", "u8"),
+            ("c", "i16"),
+            ("d", "u16"),
+            ("e", "i32"),
+            ("f", "u32"),
+            ("g", "i64"),
+            ("h", "u64"),
+        ],
+    );
+    assert_eq!(
+        r,
+        "-128 255 -2 1 -2147483648 4294967295 -9223372036854775808 18446744073709551615"
+    );
+}
+
+#[test]
+fn test_division() {
+    let r = exec_report(
+        &[DIVISION],
+        1,
+        &[
+            ("a", "i16"),
+            ("b", "i16"),
+            ("c", "i16"),
+            ("d", "i16"),
+            ("x", "i8"),
+            ("u", "u32"),
+            ("r", "f32"),
+            ("l", "f64"),
+        ],
+    );
+    assert_eq!(r, "3 -3 -1 1024 -128 1333333333 3.5 1.5");
+
+    // fractional exponents are computed by the imported math function
+    let main = (
+        "PROGRAM main: VAR p: LREAL; END_VAR END_PROGRAM",
+        "p := 2.0 ** 0.5;",
+    );
+    let r = exec_report(&[main], 1, &[("p", "f64")]);
+    assert_eq!(r, 2f64.sqrt().to_string());
+}
+
+#[test]
+fn test_if_statement() {
+    let r = exec_report(
+        &[IF_STATEMENT],
+        4,
+        &[("a", "i32"), ("b", "i32"), ("x", "u8"), ("y", "u8")],
+    );
+    assert_eq!(r, "4 51 1 0");
+}
+
+#[test]
+fn test_function_block() {
+    let r = exec_report(
+        &FUNCTION_BLOCKS,
+        2,
+        &[("a", "i16"), ("b", "i16"), ("d", "i16")],
+    );
+    assert_eq!(r, "15 20 11");
+}
+
+#[test]
+fn test_struct_array_enum() {
+    let r = exec_report(
+        &STRUCT_ARRAY_ENUM,
+        2,
+        &[("a", "i32"), ("b", "i32"), ("d", "i32")],
+    );
+    assert_eq!(r, "29 29 10");
+}
+
+#[test]
+fn test_constant_expression() {
+    let r = exec_report(&CONSTANT_EXPRESSION, 1, &[("a", "i32")]);
+    assert_eq!(r, "7");
+}
+
+#[test]
+fn test_function_call() {
+    let (r, log) = exec_application(
+        &FUNCTION_CALL,
+        1,
+        &[("x", "i16"), ("y", "i16"), ("z", "i16"), ("c", "u8")],
+    );
+    assert_eq!(r.unwrap(), "3 15 -32768 1");
+    assert_eq!(log, ["report 3", "report 15", "report -32768"]);
+}
+
+#[test]
+fn test_string() {
+    let r = exec_report(
+        &[STRING_COMPARE],
+        1,
+        &[("s", "str"), ("x", "u8"), ("y", "u8")],
+    );
+    assert_eq!(r, "a\"b? 1 0");
+}
+
+#[test]
+fn test_runtime_errors() {
+    let (r, _) = exec_application(&[INDEX_OUT_OF_RANGE], 2, &[]);
+    assert_eq!(
+        r.unwrap_err(),
+        "array index out of range at line 3, column 3: a[i]"
+    );
+
+    let (r, _) = exec_application(&[DIVISION_BY_ZERO], 1, &[]);
+    assert_eq!(r.unwrap_err(), "integer division by zero");
+}
+
+#[test]
+fn test_export_table() {
+    let pous = [COUNTER_MAIN, COUNTER_FB];
+
+    let (wasm, text) = generate_application(&pous);
+    assert_eq!((wasm.clone(), text.clone()), generate_application(&pous));
+    assert!(text.contains("(export \"stc_init\" (func $stc_init))"));
+    assert!(text.contains("(export \"main\" (func $main_body))"));
+    assert!(text.contains("(func $Counter_body (type 2) (param i32)"));
+
+    let mut sim = Simulation::new(&wasm);
+    sim.call("stc_init").unwrap();
+    for _ in 0..3 {
+        sim.call("main").unwrap();
+    }
+
+    // instance of function block is laid out like a struct
+    assert_eq!(sim.address("main.a"), sim.address("main.c") + 4);
+    assert_eq!(sim.value("main.c", "i16"), "1");
+    assert_eq!(sim.value("main.a", "i16"), "3");
+}
+
+#[test]
+fn test_invalid_member_access() {
+    let pous = NESTED_STRUCTS;
+
+    let cases = [
+        ("x := a.(b.c);", "invalid member access: a.b.c"),
+        ("a.(b.c) := 1;", "invalid member access: a.b.c"),
+        ("x := a.b.d;", "member not found: a.b.d"),
+        ("x := x.y;", "member not found: x.y"),
+        ("x := y;", "variable not found: y"),
+    ];
+    for (body, expected) in cases {
+        let mut pous = pous.to_vec();
+        pous[2].1 = body;
+        let err = build_application(&pous)
+            .err()
+            .expect("invalid member access built");
+        assert_eq!(err.to_string(), expected);
+    }
+
+    // nested members are accessed from left to right
+    let mut pous = pous.to_vec();
+    pous[2].1 = "a.b.c := 3; x := a.b.c;";
+    let r = exec_report(&pous, 1, &[("x", "i16")]);
+    assert_eq!(r, "3");
+}
+
+#[test]
+fn test_standard_numerical() {
+    let r = exec_report(
+        &[STANDARD_NUMERICAL],
+        1,
+        &[
+            ("a", "i16"),
+            ("b", "i16"),
+            ("r", "f32"),
+            ("l", "f64"),
+            ("d", "i32"),
+        ],
+    );
+    assert_eq!(r, "3 -32768 4 1026 -2");
+}
+
+#[test]
+fn test_standard_bit_shift() {
+    let r = exec_report(
+        &[STANDARD_BIT_SHIFT],
+        1,
+        &[
+            ("a", "u8"),
+            ("b", "u8"),
+            ("c", "u8"),
+            ("d", "u8"),
+            ("e", "u8"),
+        ],
+    );
+    assert_eq!(r, "2 64 3 192 0");
+}
+
+#[test]
+fn test_standard_selection() {
+    let r = exec_report(
+        &[STANDARD_SELECTION],
+        1,
+        &[("a", "i16"), ("b", "i16"), ("c", "i16"), ("d", "i16")],
+    );
+    assert_eq!(r, "2 6 100 20");
+
+    let (r, _) = exec_application(&[MUX_OUT_OF_RANGE], 1, &[]);
+    assert_eq!(r.unwrap_err(), "MUX K out of range");
+}
+
+#[test]
+fn test_standard_string() {
+    let main = (
+        r#"PROGRAM main: VAR s: STRING := "hello"; a: INT; END_VAR END_PROGRAM"#,
+        "a := LEN(s);",
+    );
+
+    let err = build_application(&[main]).err().expect("LEN built");
+    assert_eq!(err.to_string(), "standard function not supported: LEN(s)");
+}
+
+#[test]
+fn test_standard_conversion() {
+    let r = exec_report(
+        &[STANDARD_CONVERSION],
+        1,
+        &[("a", "i16"), ("b", "i8"), ("c", "u8"), ("r", "f32")],
+    );
+    assert_eq!(r, "-3 -56 1 3");
+}
+
+#[test]
+fn test_standard_function_blocks() {
+    let main = STANDARD_FUNCTION_BLOCKS;
+    let variables = [("q", "u8"), ("et", "i64"), ("cv", "i16"), ("cq", "u8")];
+
+    assert_eq!(exec_report(&[main], 3, &variables), "0 20000000 1 0");
+    assert_eq!(exec_report(&[main], 4, &variables), "1 25000000 2 1");
+}
@@ -0,0 +1,194 @@
+use crate::ast::*;
+use crate::parser::{ParserBuilder, StLexerBuilder, StString};
+
+/// Standard function blocks as (declaration, body) in ST. The interpreter runs native
+/// bodies, backends compile these bodies together with the application. Internal members
+/// keep the state between calls: 'M' is the running flag of timers or the previous input
+/// of edge detection, '*_M' are previous inputs and 'START' is the time when timing
+/// started. Timers read the current time by 'STC_NOW()'.
+const FUNCTION_BLOCKS: &[(&str, &str)] = &[
+    // timers, Q is set when IN stays true for PT, a changed PT is used at once
+    (
+        "FUNCTION_BLOCK TON
+        VAR_INPUT IN: BOOL; PT: TIME; END_VAR
+        VAR_OUTPUT Q: BOOL; ET: TIME; END_VAR
+        VAR M: BOOL; START: TIME; END_VAR
+        END_FUNCTION_BLOCK",
+        "IF IN THEN
+            IF NOT M THEN
+                M := TRUE;
+                START := STC_NOW();
+            END_IF
+            ET := STC_NOW() - START;
+            IF ET > PT THEN ET := PT; END_IF
+            IF ET < T#0s THEN ET := T#0s; END_IF
+            Q := ET >= PT;
+        ELSE
+            M := FALSE;
+            Q := FALSE;
+            ET := T#0s;
+        END_IF",
+    ),
+    // Q is reset when IN stays false for PT
+    (
+        "FUNCTION_BLOCK TOF
+        VAR_INPUT IN: BOOL; PT: TIME; END_VAR
+        VAR_OUTPUT Q: BOOL; ET: TIME; END_VAR
+        VAR M: BOOL; START: TIME; END_VAR
+        END_FUNCTION_BLOCK",
+        "IF IN THEN
+            M := FALSE;
+            Q := TRUE;
+            ET := T#0s;
+        ELSE
+            IF Q THEN
+                IF NOT M THEN START := STC_NOW(); END_IF
+                ET := STC_NOW() - START;
+                IF ET > PT THEN ET := PT; END_IF
+                IF ET < T#0s THEN ET := T#0s; END_IF
+                M := ET < PT;
+                Q := M;
+            END_IF
+        END_IF",
+    ),
+    // pulse of PT starts on rising edge of IN and can't be retriggered, ET is kept until
+    // IN is false after pulse
+    (
+        "FUNCTION_BLOCK TP
+        VAR_INPUT IN: BOOL; PT: TIME; END_VAR
+        VAR_OUTPUT Q: BOOL; ET: TIME; END_VAR
+        VAR M: BOOL; START: TIME; IN_M: BOOL; END_VAR
+        END_FUNCTION_BLOCK",
+        "IF M OR (IN AND NOT IN_M) THEN
+            IF NOT M THEN START := STC_NOW(); END_IF
+            ET := STC_NOW() - START;
+            IF ET > PT THEN ET := PT; END_IF
+            IF ET < T#0s THEN ET := T#0s; END_IF
+            M := ET < PT;
+            Q := M;
+        ELSE
+            IF NOT IN THEN ET := T#0s; END_IF
+        END_IF
+        IN_M := IN;",
+    ),
+    // edge detection
+    (
+        "FUNCTION_BLOCK R_TRIG
+        VAR_INPUT CLK: BOOL; END_VAR
+        VAR_OUTPUT Q: BOOL; END_VAR
+        VAR M: BOOL; END_VAR
+        END_FUNCTION_BLOCK",
+        "Q := CLK AND NOT M;
+        M := CLK;",
+    ),
+    (
+        "FUNCTION_BLOCK F_TRIG
+        VAR_INPUT CLK: BOOL; END_VAR
+        VAR_OUTPUT Q: BOOL; END_VAR
+        VAR M: BOOL; END_VAR
+        END_FUNCTION_BLOCK",
+        "Q := NOT CLK AND M;
+        M := CLK;",
+    ),
+    // counters, counting inputs are rising edge triggered and CV saturates at the bounds
+    // of INT
+    (
+        "FUNCTION_BLOCK CTU
+        VAR_INPUT CU: BOOL; R: BOOL; PV: INT; END_VAR
+        VAR_OUTPUT Q: BOOL; CV: INT; END_VAR
+        VAR CU_M: BOOL; END_VAR
+        END_FUNCTION_BLOCK",
+        "IF R THEN
+            CV := 0;
+        ELSE
+            IF CU AND NOT CU_M AND CV < 32767 THEN CV := CV + 1; END_IF
+        END_IF
+        CU_M := CU;
+        Q := CV >= PV;",
+    ),
+    (
+        "FUNCTION_BLOCK CTD
+        VAR_INPUT CD: BOOL; LD: BOOL; PV: INT; END_VAR
+        VAR_OUTPUT Q: BOOL; CV: INT; END_VAR
+        VAR CD_M: BOOL; END_VAR
+        END_FUNCTION_BLOCK",
+        "IF LD THEN
+            CV := PV;
+        ELSE
+            IF CD AND NOT CD_M AND CV > -32768 THEN CV := CV - 1; END_IF
+        END_IF
+        CD_M := CD;
+        Q := CV <= 0;",
+    ),
+    // counting up and down at the same time keeps CV
+    (
+        "FUNCTION_BLOCK CTUD
+        VAR_INPUT CU: BOOL; CD: BOOL; R: BOOL; LD: BOOL; PV: INT; END_VAR
+        VAR_OUTPUT QU: BOOL; QD: BOOL; CV: INT; END_VAR
+        VAR CU_M: BOOL; CD_M: BOOL; END_VAR
+        END_FUNCTION_BLOCK",
+        "IF R THEN
+            CV := 0;
+        ELSEIF LD THEN
+            CV := PV;
+        ELSE
+            IF CU AND NOT CU_M AND NOT (CD AND NOT CD_M) AND CV < 32767 THEN
+                CV := CV + 1;
+            END_IF
+            IF CD AND NOT CD_M AND NOT (CU AND NOT CU_M) AND CV > -32768 THEN
+                CV := CV - 1;
+            END_IF
+        END_IF
+        CU_M := CU;
+        CD_M := CD;
+        QU := CV >= PV;
+        QD := CV <= 0;",
+    ),
+    // bistables, SR is set dominant and RS is reset dominant
+    (
+        "FUNCTION_BLOCK SR
+        VAR_INPUT S1: BOOL; R: BOOL; END_VAR
+        VAR_OUTPUT Q1: BOOL; END_VAR
+        END_FUNCTION_BLOCK",
+        "Q1 := S1 OR (NOT R AND Q1);",
+    ),
+    (
+        "FUNCTION_BLOCK RS
+        VAR_INPUT S: BOOL; R1: BOOL; END_VAR
+        VAR_OUTPUT Q1: BOOL; END_VAR
+        END_FUNCTION_BLOCK",
+        "Q1 := NOT R1 AND (S OR Q1);",
+    ),
+];
+
+fn parse_declaration(source: &str) -> Declaration {
+    let mut lexer = StLexerBuilder::new().build_str(source);
+    ParserBuilder::default()
+        .build()
+        .parse_decl(&mut lexer)
+        .expect("invalid standard function block")
+}
+
+pub(super) fn declarations() -> Vec<Declaration> {
+    FUNCTION_BLOCKS
+        .iter()
+        .map(|(decl, _)| parse_declaration(decl))
+        .collect()
+}
+
+/// Declaration and body of standard function block `name`
+pub fn standard_function_block(name: &StString) -> Option<(Declaration, Statement)> {
+    FUNCTION_BLOCKS.iter().find_map(|(decl, body)| {
+        let decl = parse_declaration(decl);
+        if decl.identifier() != name {
+            return None;
+        }
+
+        let mut lexer = StLexerBuilder::new().build_str(body);
+        let body = ParserBuilder::default()
+            .build()
+            .parse_stmt(&mut lexer)
+            .expect("invalid standard function block");
+        Some((decl, body))
+    })
+}
@@ -0,0 +1,162 @@
+use crate::ast::*;
+use crate::parser::StString;
+use crate::utils::HasAttribute;
+use std::sync::Arc;
+
+/// Attribute of function which last input can be repeated, like 'MAX(a, b, c)'
+pub const EXTENSIBLE_ATTRIBUTE: &str = "extensible";
+
+/// Attribute of function which parameters can be of generic type families, like 'ANY_NUM'
+pub const GENERIC_ATTRIBUTE: &str = "generic";
+
+const ANY: TypeClass = TypeClass::Generic(TypeFamily::Any);
+const ANY_ELEMENTARY: TypeClass = TypeClass::Generic(TypeFamily::AnyElementary);
+const ANY_NUM: TypeClass = TypeClass::Generic(TypeFamily::AnyNum);
+const ANY_REAL: TypeClass = TypeClass::Generic(TypeFamily::AnyReal);
+const ANY_INT: TypeClass = TypeClass::Generic(TypeFamily::AnyInt);
+const ANY_BIT: TypeClass = TypeClass::Generic(TypeFamily::AnyBit);
+const STRING: TypeClass = TypeClass::String;
+
+type Parameters = &'static [(&'static str, TypeClass)];
+
+/// Standard functions as (name, return type, inputs)
+const FUNCTIONS: &[(&str, TypeClass, Parameters)] = &[
+    // numerical
+    ("ABS", ANY_NUM, &[("IN", ANY_NUM)]),
+    ("SQRT", ANY_REAL, &[("IN", ANY_REAL)]),
+    ("LN", ANY_REAL, &[("IN", ANY_REAL)]),
+    ("LOG", ANY_REAL, &[("IN", ANY_REAL)]),
+    ("EXP", ANY_REAL, &[("IN", ANY_REAL)]),
+    ("SIN", ANY_REAL, &[("IN", ANY_REAL)]),
+    ("COS", ANY_REAL, &[("IN", ANY_REAL)]),
+    ("TAN", ANY_REAL, &[("IN", ANY_REAL)]),
+    ("ASIN", ANY_REAL, &[("IN", ANY_REAL)]),
+    ("ACOS", ANY_REAL, &[("IN", ANY_REAL)]),
+    ("ATAN", ANY_REAL, &[("IN", ANY_REAL)]),
+    ("EXPT", ANY_REAL, &[("IN1", ANY_REAL), ("IN2", ANY_NUM)]),
+    ("TRUNC", TypeClass::DInt, &[("IN", ANY_REAL)]),
+    // bit shift
+    ("SHL", ANY_BIT, &[("IN", ANY_BIT), ("N", ANY_INT)]),
+    ("SHR", ANY_BIT, &[("IN", ANY_BIT), ("N", ANY_INT)]),
+    ("ROL", ANY_BIT, &[("IN", ANY_BIT), ("N", ANY_INT)]),
+    ("ROR", ANY_BIT, &[("IN", ANY_BIT), ("N", ANY_INT)]),
+    // selection
+    (
+        "SEL",
+        ANY,
+        &[("G", TypeClass::Bool), ("IN0", ANY), ("IN1", ANY)],
+    ),
+    (
+        "MAX",
+        ANY_ELEMENTARY,
+        &[("IN1", ANY_ELEMENTARY), ("IN2", ANY_ELEMENTARY)],
+    ),
+    (
+        "MIN",
+        ANY_ELEMENTARY,
+        &[("IN1", ANY_ELEMENTARY), ("IN2", ANY_ELEMENTARY)],
+    ),
+    (
+        "LIMIT",
+        ANY_ELEMENTARY,
+        &[
+            ("MN", ANY_ELEMENTARY),
+            ("IN", ANY_ELEMENTARY),
+            ("MX", ANY_ELEMENTARY),
+        ],
+    ),
+    ("MUX", ANY, &[("K", ANY_INT), ("IN0", ANY), ("IN1", ANY)]),
+    // string, positions are 1-based
+    ("LEN", TypeClass::Int, &[("IN", STRING)]),
+    ("LEFT", STRING, &[("IN", STRING), ("L", ANY_INT)]),
+    ("RIGHT", STRING, &[("IN", STRING), ("L", ANY_INT)]),
+    (
+        "MID",
+        STRING,
+        &[("IN", STRING), ("L", ANY_INT), ("P", ANY_INT)],
+    ),
+    ("CONCAT", STRING, &[("IN1", STRING), ("IN2", STRING)]),
+    (
+        "INSERT",
+        STRING,
+        &[("IN1", STRING), ("IN2", STRING), ("P", ANY_INT)],
+    ),
+    (
+        "DELETE",
+        STRING,
+        &[("IN", STRING), ("L", ANY_INT), ("P", ANY_INT)],
+    ),
+    (
+        "REPLACE",
+        STRING,
+        &[
+            ("IN1", STRING),
+            ("IN2", STRING),
+            ("L", ANY_INT),
+            ("P", ANY_INT),
+        ],
+    ),
+    ("FIND", TypeClass::Int, &[("IN1", STRING), ("IN2", STRING)]),
+    // current time of timers in nanoseconds, provided by the time source of target
+    ("STC_NOW", TypeClass::Time, &[]),
+];
+
+const EXTENSIBLE_FUNCTIONS: &[&str] = &["MAX", "MIN", "MUX", "CONCAT"];
+
+/// Types of type conversion functions, like 'INT_TO_REAL'
+const CONVERSION_TYPES: &[TypeClass] = &[
+    TypeClass::Bool,
+    TypeClass::SInt,
+    TypeClass::Byte,
+    TypeClass::Int,
+    TypeClass::UInt,
+    TypeClass::DInt,
+    TypeClass::UDInt,
+    TypeClass::LInt,
+    TypeClass::ULInt,
+    TypeClass::Real,
+    TypeClass::LReal,
+    TypeClass::Time,
+    TypeClass::String,
+];
+
+fn function(name: &str, ret: TypeClass, params: &[(&str, TypeClass)]) -> Declaration {
+    let params = params
+        .iter()
+        .map(|(name, class)| {
+            let mut v = Variable::with_type(StString::new(*name), Type::from_class(*class));
+            v.set_flags(VariableFlags::INPUT);
+            Arc::new(v)
+        })
+        .collect();
+    let fun = FunctionDeclare::new(
+        StString::new(name),
+        DeclareClass::Function,
+        Some(Type::from_class(ret)),
+        params,
+    );
+
+    Declaration::fun(Box::new(fun))
+}
+
+pub(super) fn declarations() -> Vec<Declaration> {
+    let mut decls = vec![];
+
+    for (name, ret, params) in FUNCTIONS {
+        let mut decl = function(name, *ret, params);
+        decl.set_attribute(StString::new(GENERIC_ATTRIBUTE), None);
+        if EXTENSIBLE_FUNCTIONS.contains(name) {
+            decl.set_attribute(StString::new(EXTENSIBLE_ATTRIBUTE), None);
+        }
+        decls.push(decl);
+    }
+
+    for from in CONVERSION_TYPES {
+        for to in CONVERSION_TYPES.iter().filter(|x| *x != from) {
+            let name = format!("{}_TO_{}", from, to);
+            decls.push(function(&name, *to, &[("IN", *from)]));
+        }
+    }
+
+    decls
+}
@@ -0,0 +1,33 @@
+//! Compiler builtin module, which is added to every `UnitsManager`. It provides the
+//! standard functions and function blocks of IEC 61131-3, declarations of application
+//! shadow them.
+
+mod function_blocks;
+mod functions;
+pub use function_blocks::standard_function_block;
+pub use functions::{EXTENSIBLE_ATTRIBUTE, GENERIC_ATTRIBUTE};
+
+use crate::context::{ModuleContext, ModuleKind};
+use uuid::Uuid;
+
+/// Object ids of builtin declarations are fixed, the high half of id is this value and the
+/// low half is the index of declaration
+const BUILTIN_OBJECT_ID: u64 = 0x7374_635f_6275_696c;
+
+/// Create the builtin module with declarations of all standard functions and function
+/// blocks, the module has id `BUILTIN_CONTEXT_ID`
+pub fn builtin_context() -> ModuleContext {
+    let ctx = ModuleContext::new(ModuleKind::CompilerBuiltin);
+
+    {
+        let mut ctx = ctx.write();
+        let decls = functions::declarations()
+            .into_iter()
+            .chain(function_blocks::declarations());
+        for (idx, decl) in decls.enumerate() {
+            ctx.add_declaration(decl, Uuid::from_u64_pair(BUILTIN_OBJECT_ID, idx as u64));
+        }
+    }
+
+    ctx
+}
@@ -1,14 +1,15 @@
 mod module_context;
-pub use module_context::{Function, ModuleContext, Prototype};
+pub use module_context::{Function, ModuleContext, Prototype, BUILTIN_CONTEXT_ID};
 
 mod units_manager;
 pub use units_manager::UnitsManager;
 
 mod scope;
 mod task;
+pub use task::{ConfigurationError, ProgramInstance, TaskConfiguration, TaskInfo};
 mod library;
 
-pub use scope::Scope;
+pub use scope::{ResolveError, Scope};
 
 pub enum ModuleKind {
     Application,
@@ -1,13 +1,10 @@
 use crate::ast::*;
 use crate::backend::CompiledCode;
-use crate::context::task::TaskInfo;
 use crate::context::ModuleKind;
 use crate::parser::StString;
 use indexmap::IndexMap;
 use log::warn;
 use once_cell::sync::Lazy;
-use smallvec::smallvec;
-use std::borrow::Cow;
 use std::collections::{HashMap, HashSet};
 use std::fmt::{Display, Formatter};
 use std::hash::{Hash, Hasher};
@@ -16,6 +13,9 @@ use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard};
 use uuid::Uuid;
 
+/// Id of the compiler builtin module, which is the same in every `UnitsManager`
+pub const BUILTIN_CONTEXT_ID: usize = usize::MAX;
+
 static CONTEXT_ID: Lazy<AtomicUsize> = Lazy::new(|| AtomicUsize::new(0));
 static DECLARATION_ID: Lazy<AtomicUsize> = Lazy::new(|| AtomicUsize::new(0));
 
@@ -134,6 +134,10 @@ impl PrototypeImpl {
         &self.decl
     }
 
+    pub fn decl_mut(&mut self) -> &mut Declaration {
+        &mut self.decl
+    }
+
     pub fn id(&self) -> usize {
         self.id
     }
@@ -162,63 +166,55 @@ impl PrototypeImpl {
         self.variables().iter().find(|x| x.name() == self.name())
     }
 
+    /// return true if Prototype is a function block
+    #[inline]
+    pub fn is_function_block(&self) -> bool {
+        match self.decl.kind {
+            DeclKind::FB(_) => true,
+            DeclKind::Fun(ref f) => matches!(f.class(), DeclareClass::FunctionBlock),
+            _ => false,
+        }
+    }
+
     /// return false if Prototype is Function, like FB or Fun or Prg
     #[inline]
     pub fn is_type_declaration(&self) -> bool {
         matches!(
             self.decl.kind,
-            DeclKind::GlobalVar(_) | DeclKind::Alias(_) | DeclKind::Enum(_)
+            DeclKind::GlobalVar(_)
+                | DeclKind::Alias(_)
+                | DeclKind::Enum(_)
+                | DeclKind::Struct(_)
+                | DeclKind::Configuration(_)
         )
     }
 }
 
-fn proto_name_string(name: &StString) -> Cow<String> {
+fn proto_name_string(name: &StString) -> &str {
     let s = name.string();
 
     if s.is_empty() {
-        return Cow::Owned("(No Name)".to_owned());
+        return "(No Name)";
     }
 
-    Cow::Borrowed(name.string())
+    s
 }
 
 impl Display for PrototypeImpl {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        match &self.decl.kind {
-            DeclKind::FB(fun) => f.write_fmt(format_args!(
-                "{} ({})",
-                proto_name_string(fun.name()),
-                "FUNCTION_BLOCK"
-            )),
-            DeclKind::Prg(fun) => f.write_fmt(format_args!(
-                "{} ({})",
-                proto_name_string(fun.name()),
-                "PROGRAM"
-            )),
-            DeclKind::Fun(fun) => f.write_fmt(format_args!(
-                "{} ({})",
-                proto_name_string(fun.name()),
-                "FUN"
-            )),
-            DeclKind::Alias(alias) => f.write_fmt(format_args!(
-                "{} ({})",
-                proto_name_string(alias.name()),
-                "TYPE"
-            )),
-            DeclKind::Struct(s) => f.write_fmt(format_args!(
-                "{} ({})",
-                proto_name_string(s.name()),
-                "STRUCT"
-            )),
-            DeclKind::Enum(e) => {
-                f.write_fmt(format_args!("{} ({})", proto_name_string(e.name()), "ENUM"))
-            }
-            DeclKind::GlobalVar(g) => f.write_fmt(format_args!(
-                "{} ({})",
-                proto_name_string(g.name()),
-                "VAR_GLOBAL"
-            )),
-        }
+        let kind = match &self.decl.kind {
+            DeclKind::FB(_) => "FUNCTION_BLOCK",
+            DeclKind::Prg(_) => "PROGRAM",
+            DeclKind::Fun(_) => "FUN",
+            DeclKind::Alias(_) => "TYPE",
+            DeclKind::Struct(_) => "STRUCT",
+            DeclKind::Enum(_) => "ENUM",
+            DeclKind::GlobalVar(_) => "VAR_GLOBAL",
+            DeclKind::Configuration(_) => "CONFIGURATION",
+        };
+
+        let name = self.decl.qualified_name();
+        f.write_fmt(format_args!("{} ({})", proto_name_string(&name), kind))
     }
 }
 
@@ -298,14 +294,17 @@ impl ModuleContext {
     pub fn new(kind: ModuleKind) -> Self {
         Self {
             inner: Arc::new(RwLock::new(ModuleContextImpl {
-                id: get_next_context_id(),
+                id: match kind {
+                    ModuleKind::CompilerBuiltin => BUILTIN_CONTEXT_ID,
+                    _ => get_next_context_id(),
+                },
                 kind,
                 declaration_id_map: IndexMap::new(),
                 declaration_uuid_map: HashMap::new(),
                 declaration_name_map: HashMap::new(),
+                namespaces: HashSet::new(),
                 function_id_map: IndexMap::new(),
                 toplevel_global_variable_declarations: HashSet::new(),
-                task_info: smallvec![],
             })),
         }
     }
@@ -326,10 +325,12 @@ pub struct ModuleContextImpl {
     kind: ModuleKind,
     declaration_id_map: IndexMap<usize, Prototype>,
     declaration_uuid_map: HashMap<Uuid, Prototype>,
+    /// Declarations by their qualified names
     declaration_name_map: HashMap<StString, Prototype>,
+    /// Namespaces of declarations and the namespaces enclosing them
+    namespaces: HashSet<StString>,
     function_id_map: IndexMap<usize, Function>,
     toplevel_global_variable_declarations: HashSet<Prototype>,
-    task_info: SmallVec8<TaskInfo>,
 }
 
 impl ModuleContextImpl {
@@ -355,13 +356,22 @@ impl ModuleContextImpl {
             return proto.read().unwrap().id();
         }
 
-        let name = decl.identifier().clone();
+        let name = decl.qualified_name();
+        let mut namespace = decl.namespace().to_string();
+        while !namespace.is_empty() {
+            self.namespaces.insert(namespace.as_str().into());
+            namespace.truncate(namespace.rfind('.').unwrap_or(0));
+        }
+
         let mut toplevel_global_variable_declaration = false;
 
-        if let DeclKind::GlobalVar(ref g) = decl.kind {
-            if g.name().is_empty() {
+        match decl.kind {
+            DeclKind::GlobalVar(ref g) if g.name().is_empty() => {
                 toplevel_global_variable_declaration = true;
             }
+            // global variables of configuration are visible to the whole application
+            DeclKind::Configuration(_) => toplevel_global_variable_declaration = true,
+            _ => {}
         }
 
         let decl = Prototype::with_object_id(decl, id);
@@ -432,24 +442,40 @@ impl ModuleContextImpl {
         self.declaration_uuid_map.get(uuid)
     }
 
+    /// Find declaration by qualified name, like 'Motion.Axis.MoveAbs'
     #[inline]
     pub fn find_declaration_by_name(&self, ident: &StString) -> Option<&Prototype> {
         self.declaration_name_map.get(ident)
     }
 
+    /// Whether namespace is declared by the declarations of module, like 'Motion' and
+    /// 'Motion.Axis' of 'Motion.Axis.MoveAbs'
     #[inline]
+    pub fn has_namespace(&self, namespace: &StString) -> bool {
+        self.namespaces.contains(namespace)
+    }
+
+    /// Find global variable by qualified name, like 'Motion.MaxSpeed'
     pub fn find_toplevel_global_variable(&self, ident: &StString) -> Option<Arc<Variable>> {
-        self.find_toplevel_global_variable_map(|x| x.name() == ident)
+        let (namespace, name) = ident
+            .string()
+            .rsplit_once('.')
+            .unwrap_or(("", ident.string()));
+        let name = StString::from(name);
+
+        self.find_toplevel_global_variable_map(|decl, x| {
+            decl.namespace() == namespace && *x.name() == name
+        })
     }
 
     pub fn find_toplevel_global_variable_map<F>(&self, f: F) -> Option<Arc<Variable>>
     where
-        F: Fn(&Arc<Variable>) -> bool,
+        F: Fn(&Declaration, &Arc<Variable>) -> bool,
     {
         for decl in self.toplevel_global_variable_declarations.iter() {
             let decl = decl.read().unwrap();
             for v in decl.decl.variables().iter() {
-                if f(v) {
+                if f(&decl.decl, v) {
                     return Some(v.clone());
                 }
             }
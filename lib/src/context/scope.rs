@@ -1,8 +1,42 @@
 use crate::ast::Variable;
 use crate::context::{ModuleContext, Prototype, UnitsManager};
 use crate::parser::StString;
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
 use std::sync::Arc;
 
+/// Error of name resolution in scope
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ResolveError {
+    /// Name isn't declared by the context or the libraries it uses
+    NotFound(StString),
+    /// Qualified name like 'Lib.FB', but 'Lib' is neither a namespace of the context nor the
+    /// namespace of a library it uses
+    UnknownNamespace(StString),
+    /// Name isn't declared by the context, but by more than one library or 'USING' namespace
+    Ambiguous(StString, Vec<StString>),
+}
+
+impl Error for ResolveError {}
+
+impl Display for ResolveError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ResolveError::NotFound(name) => write!(f, "{} not found", name),
+            ResolveError::UnknownNamespace(namespace) => {
+                write!(f, "unknown namespace {}", namespace)
+            }
+            ResolveError::Ambiguous(name, namespaces) => {
+                let candidates: Vec<_> = namespaces
+                    .iter()
+                    .map(|ns| format!("{}.{}", ns, name))
+                    .collect();
+                write!(f, "{} is ambiguous: {}", name, candidates.join(", "))
+            }
+        }
+    }
+}
+
 #[derive(Clone, Default)]
 pub struct Scope {
     units_manager: Option<UnitsManager>,
@@ -32,25 +66,82 @@ impl Scope {
         }
     }
 
+    #[inline]
+    pub fn local_context(&self) -> Option<&ModuleContext> {
+        self.local_context.as_ref()
+    }
+
+    #[inline]
     pub fn find_declaration(&self, ident: &StString) -> (Option<Prototype>, Option<Scope>) {
-        let decl = self
-            .local_context
-            .as_ref()
-            .and_then(|ctx| ctx.read().find_declaration_by_name(ident).cloned());
-
-        match decl {
-            None => (None, None),
-            Some(decl) => {
-                let ctx_id = self.local_context.as_ref().map(|x| x.read().id());
-                let fun_id = decl.read().unwrap().id();
-                (
-                    Some(decl),
-                    Some(Self::new(self.units_manager.clone(), ctx_id, Some(fun_id))),
-                )
-            }
+        match self.resolve_declaration(ident) {
+            Ok((decl, scope)) => (Some(decl), Some(scope)),
+            Err(_) => (None, None),
         }
     }
 
+    /// Find declaration by name, the scope returned is the scope of declaration.
+    ///
+    /// Names are searched in the namespaces enclosing local declaration from the innermost
+    /// one, then in the namespaces of its 'USING' directives, and then in libraries.
+    /// Declarations of local context shadow the declarations of libraries, and a name
+    /// declared by more than one library or 'USING' namespace is ambiguous. Names qualified
+    /// by namespace of library, like 'Lib.FB', are only searched in the library.
+    pub fn resolve_declaration(
+        &self,
+        ident: &StString,
+    ) -> Result<(Prototype, Scope), ResolveError> {
+        let (decl, ctx) = self.resolve(ident, |ctx, name| {
+            ctx.read().find_declaration_by_name(name).cloned()
+        })?;
+
+        let ctx_id = ctx.read().id();
+        let fun_id = decl.read().unwrap().id();
+        Ok((
+            decl,
+            Self::new(self.units_manager.clone(), Some(ctx_id), Some(fun_id)),
+        ))
+    }
+
+    /// Global scope of library used by local context by namespace
+    pub fn find_namespace(&self, namespace: &StString) -> Option<Scope> {
+        let (_, lib) = self
+            .dependencies()
+            .into_iter()
+            .find(|(ns, _)| ns == namespace)?;
+        let lib_id = lib.read().id();
+
+        Some(Self::new(self.units_manager.clone(), Some(lib_id), None))
+    }
+
+    /// Whether name is a namespace in scope, like 'Motion.Axis' of 'Motion.Axis.MoveAbs',
+    /// or the namespace of a library
+    pub fn is_namespace(&self, namespace: &StString) -> bool {
+        let dependencies = self.dependencies();
+        let is_library_namespace = |name: &StString| match name.string().split_once('.') {
+            Some((ns, name)) => dependencies
+                .iter()
+                .any(|(x, lib)| x == ns && lib.read().has_namespace(&name.into())),
+            None => dependencies.iter().any(|(x, _)| x == name),
+        };
+
+        let (mut names, using) = self.qualified_names(namespace);
+        names.extend(using.into_iter().map(|(_, name)| name));
+        names.iter().any(|name| {
+            self.local_context
+                .as_ref()
+                .is_some_and(|ctx| ctx.read().has_namespace(name))
+                || is_library_namespace(name)
+        })
+    }
+
+    /// Find standard function in the builtin module, which is shadowed by declarations and
+    /// variables of local context
+    pub fn find_builtin_declaration(&self, ident: &StString) -> Option<Prototype> {
+        let builtin = self.units_manager.as_ref()?.read().builtin_context();
+        let decl = builtin.read().find_declaration_by_name(ident).cloned();
+        decl
+    }
+
     pub fn find_variable(&self, ident: &StString) -> Option<Arc<Variable>> {
         self.find_local_variable(ident)
             .or_else(|| self.find_global_variable(ident))
@@ -64,8 +155,144 @@ impl Scope {
     }
 
     pub fn find_global_variable(&self, ident: &StString) -> Option<Arc<Variable>> {
-        self.local_context
-            .as_ref()
-            .and_then(|ctx| ctx.read().find_toplevel_global_variable(ident))
+        self.resolve(ident, |ctx, name| {
+            ctx.read().find_toplevel_global_variable(name)
+        })
+        .ok()
+        .map(|(variable, _)| variable)
+    }
+
+    /// Libraries used by local context, with the namespaces they are used by
+    fn dependencies(&self) -> Vec<(StString, ModuleContext)> {
+        let (Some(mgr), Some(ctx)) = (&self.units_manager, &self.local_context) else {
+            return vec![];
+        };
+
+        let ctx_id = ctx.read().id();
+        let mgr = mgr.read();
+        mgr.dependencies(ctx_id)
+            .iter()
+            .filter_map(|(ns, id)| Some((ns.clone(), mgr.get_context(*id)?)))
+            .collect()
+    }
+
+    /// Names ident may refer to: the names in the namespaces enclosing local declaration,
+    /// from the innermost one to the global namespace, and the names in the namespaces of
+    /// its 'USING' directives
+    fn qualified_names(&self, ident: &StString) -> (Vec<StString>, Vec<(StString, StString)>) {
+        let Some(decl) = &self.local_declaration else {
+            return (vec![ident.clone()], vec![]);
+        };
+        let decl = decl.read().unwrap();
+        let qualify = |ns: &str| StString::new(format!("{}.{}", ns, ident));
+
+        let mut names = vec![];
+        let mut namespace = decl.decl().namespace().string().as_str();
+        while !namespace.is_empty() {
+            names.push(qualify(namespace));
+            namespace = namespace.rsplit_once('.').map_or("", |(parent, _)| parent);
+        }
+        names.push(ident.clone());
+
+        let using = decl
+            .decl()
+            .using()
+            .iter()
+            .map(|ns| (ns.clone(), qualify(ns.string())))
+            .collect();
+        (names, using)
+    }
+
+    /// Find item by qualified name in local context, or in the library used by the first
+    /// part of name
+    fn find_qualified<T, F>(
+        &self,
+        dependencies: &[(StString, ModuleContext)],
+        name: &StString,
+        f: &F,
+    ) -> Option<(T, ModuleContext)>
+    where
+        F: Fn(&ModuleContext, &StString) -> Option<T>,
+    {
+        if let Some(ctx) = &self.local_context {
+            if let Some(x) = f(ctx, name) {
+                return Some((x, ctx.clone()));
+            }
+        }
+
+        let (namespace, name) = name.string().split_once('.')?;
+        let (_, lib) = dependencies.iter().find(|(ns, _)| ns == namespace)?;
+        f(lib, &name.into()).map(|x| (x, lib.clone()))
+    }
+
+    /// Find item in the namespaces of local context first, then in the libraries used by
+    /// local context
+    fn resolve<T, F>(&self, ident: &StString, f: F) -> Result<(T, ModuleContext), ResolveError>
+    where
+        F: Fn(&ModuleContext, &StString) -> Option<T>,
+    {
+        let dependencies = self.dependencies();
+        let (names, using) = self.qualified_names(ident);
+
+        // names of enclosing namespaces shadow the outer ones
+        if let Some(ctx) = &self.local_context {
+            for name in &names {
+                if let Some(x) = f(ctx, name) {
+                    return Ok((x, ctx.clone()));
+                }
+            }
+        }
+
+        let mut found: Vec<_> = using
+            .into_iter()
+            .filter_map(|(ns, name)| {
+                self.find_qualified(&dependencies, &name, &f)
+                    .map(|(x, ctx)| (ns, x, ctx))
+            })
+            .collect();
+        match found.len() {
+            0 => {}
+            1 => {
+                let (_, x, ctx) = found.remove(0);
+                return Ok((x, ctx));
+            }
+            _ => {
+                return Err(ResolveError::Ambiguous(
+                    ident.clone(),
+                    found.into_iter().map(|(ns, ..)| ns).collect(),
+                ))
+            }
+        }
+
+        if let Some((namespace, name)) = ident.string().rsplit_once('.') {
+            let namespace = StString::new(namespace);
+            if !self.is_namespace(&namespace) {
+                return Err(ResolveError::UnknownNamespace(namespace));
+            }
+
+            // names of local namespaces are searched above
+            let (ns, name) = ident.string().split_once('.').unwrap();
+            return dependencies
+                .iter()
+                .find(|(x, _)| x == ns)
+                .and_then(|(_, lib)| f(lib, &name.into()).map(|x| (x, lib.clone())))
+                .ok_or_else(|| ResolveError::NotFound(ident.clone()));
+        }
+
+        let mut found: Vec<_> = dependencies
+            .into_iter()
+            .filter_map(|(ns, lib)| f(&lib, ident).map(|x| (ns, x, lib)))
+            .collect();
+        match found.len() {
+            0 => Err(ResolveError::NotFound(ident.clone())),
+            1 => {
+                let (_, x, lib) = found.remove(0);
+                Ok((x, lib))
+            }
+            _ => Err(ResolveError::Ambiguous(
+                ident.clone(),
+                found.into_iter().map(|(ns, ..)| ns).collect(),
+            )),
+        }
     }
 }
@@ -1,5 +1,224 @@
-use uuid::Uuid;
+use crate::ast::*;
+use crate::backend::utils::{const_integer, pou_kind, PouKind};
+use crate::context::ModuleContext;
+use crate::parser::StString;
 
-pub(crate) struct TaskInfo {
-    pub entry_object_id: Uuid,
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConfigurationError {
+    /// No CONFIGURATION in application, or not found by name
+    NotFound(Option<StString>),
+    /// Task parameter is not a constant of expected type, like 'INTERVAL := x', or the
+    /// interval is zero
+    InvalidTaskParameter { task: StString, parameter: StString },
+    /// Program instance bound to a task which is not declared in the same resource
+    UnknownTask { instance: StString, task: StString },
+    /// Program instance of a PROGRAM which is not declared
+    UnknownProgram {
+        instance: StString,
+        program: StString,
+    },
+    /// Task or program instance declared twice
+    DuplicateName(StString),
+}
+
+impl Error for ConfigurationError {}
+
+impl Display for ConfigurationError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigurationError::NotFound(None) => f.write_str("configuration not found"),
+            ConfigurationError::NotFound(Some(name)) => {
+                write!(f, "configuration '{}' not found", name)
+            }
+            ConfigurationError::InvalidTaskParameter { task, parameter } => {
+                write!(f, "invalid parameter '{}' of task '{}'", parameter, task)
+            }
+            ConfigurationError::UnknownTask { instance, task } => {
+                write!(f, "task '{}' of program '{}' not found", task, instance)
+            }
+            ConfigurationError::UnknownProgram { instance, program } => write!(
+                f,
+                "program '{}' of instance '{}' not found",
+                program, instance
+            ),
+            ConfigurationError::DuplicateName(name) => write!(f, "'{}' declared twice", name),
+        }
+    }
+}
+
+/// Task of resource. Tasks without interval are not cyclic and never scheduled, smaller
+/// priority number is the higher priority.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TaskInfo {
+    pub name: StString,
+    pub resource: StString,
+    /// Cycle interval in nanoseconds
+    pub interval: Option<u64>,
+    pub priority: u32,
+}
+
+/// Instance of PROGRAM, each instance has its own variables
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProgramInstance {
+    pub name: StString,
+    pub program: StString,
+    /// Index of the task in `TaskConfiguration::tasks()`
+    pub task: Option<usize>,
+}
+
+/// Tasks and program instances of all resources of a configuration
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TaskConfiguration {
+    name: StString,
+    tasks: Vec<TaskInfo>,
+    instances: Vec<ProgramInstance>,
+}
+
+impl TaskConfiguration {
+    /// Find configuration in application, the first one if `name` is None
+    pub fn from_application(
+        app: &ModuleContext,
+        name: Option<&StString>,
+    ) -> Result<Self, ConfigurationError> {
+        let app = app.read();
+        let mut decls: Vec<_> = app.declarations().collect();
+        decls.sort_by_key(|x| x.read().unwrap().id());
+
+        for decl in decls {
+            let decl = decl.read().unwrap();
+            let DeclKind::Configuration(ref cfg) = decl.decl().kind else {
+                continue;
+            };
+            if name.is_some_and(|x| x != cfg.name()) {
+                continue;
+            }
+
+            let configuration = Self::from_declaration(cfg)?;
+            for instance in configuration.instances.iter() {
+                let is_program = app
+                    .find_declaration_by_name(&instance.program)
+                    .is_some_and(|x| pou_kind(x.read().unwrap().decl()) == Some(PouKind::Program));
+                if !is_program {
+                    return Err(ConfigurationError::UnknownProgram {
+                        instance: instance.name.clone(),
+                        program: instance.program.clone(),
+                    });
+                }
+            }
+
+            return Ok(configuration);
+        }
+
+        Err(ConfigurationError::NotFound(name.cloned()))
+    }
+
+    /// Evaluate task parameters and bind program instances to tasks, programs are not
+    /// checked
+    pub fn from_declaration(cfg: &ConfigurationDeclare) -> Result<Self, ConfigurationError> {
+        let mut configuration = Self {
+            name: cfg.name().clone(),
+            ..Default::default()
+        };
+
+        for resource in cfg.resources() {
+            let first_task = configuration.tasks.len();
+            for task in resource.tasks() {
+                if configuration.find_task(task.name()).is_some() {
+                    return Err(ConfigurationError::DuplicateName(task.name().clone()));
+                }
+
+                let parameter = |name: &str| -> Result<Option<i64>, ConfigurationError> {
+                    let Some(expr) = task.parameter(name) else {
+                        return Ok(None);
+                    };
+
+                    const_integer(expr)
+                        .filter(|x| *x >= 0)
+                        .map(Some)
+                        .ok_or_else(|| ConfigurationError::InvalidTaskParameter {
+                            task: task.name().clone(),
+                            parameter: StString::new(name),
+                        })
+                };
+
+                // a cyclic task needs a positive interval, T#0s would never be released
+                let interval = match parameter("INTERVAL")? {
+                    Some(0) => {
+                        return Err(ConfigurationError::InvalidTaskParameter {
+                            task: task.name().clone(),
+                            parameter: StString::new("INTERVAL"),
+                        })
+                    }
+                    x => x.map(|x| x as u64),
+                };
+
+                configuration.tasks.push(TaskInfo {
+                    name: task.name().clone(),
+                    resource: resource.name().clone(),
+                    interval,
+                    priority: parameter("PRIORITY")?.unwrap_or(0) as u32,
+                });
+            }
+
+            for program in resource.programs() {
+                if configuration.find_instance(program.name()).is_some() {
+                    return Err(ConfigurationError::DuplicateName(program.name().clone()));
+                }
+
+                // tasks are local to resource
+                let task = match program.task() {
+                    Some(task) => Some(
+                        configuration.tasks[first_task..]
+                            .iter()
+                            .position(|x| &x.name == task)
+                            .map(|x| x + first_task)
+                            .ok_or_else(|| ConfigurationError::UnknownTask {
+                                instance: program.name().clone(),
+                                task: task.clone(),
+                            })?,
+                    ),
+                    None => None,
+                };
+
+                configuration.instances.push(ProgramInstance {
+                    name: program.name().clone(),
+                    program: program.program().clone(),
+                    task,
+                });
+            }
+        }
+
+        Ok(configuration)
+    }
+
+    #[inline]
+    pub fn name(&self) -> &StString {
+        &self.name
+    }
+
+    #[inline]
+    pub fn tasks(&self) -> &[TaskInfo] {
+        &self.tasks
+    }
+
+    #[inline]
+    pub fn instances(&self) -> &[ProgramInstance] {
+        &self.instances
+    }
+
+    pub fn find_task(&self, name: &StString) -> Option<usize> {
+        self.tasks.iter().position(|x| &x.name == name)
+    }
+
+    pub fn find_instance(&self, name: &StString) -> Option<&ProgramInstance> {
+        self.instances.iter().find(|x| &x.name == name)
+    }
+
+    /// Program instances of task in declaration order
+    pub fn task_instances(&self, task: usize) -> impl Iterator<Item = &ProgramInstance> {
+        self.instances.iter().filter(move |x| x.task == Some(task))
+    }
 }
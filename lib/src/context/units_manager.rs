@@ -1,6 +1,9 @@
-use crate::context::ModuleContext;
+use crate::builtin::builtin_context;
+use crate::context::{ModuleContext, BUILTIN_CONTEXT_ID};
+use crate::parser::StString;
 use crate::prelude::Scope;
 use indexmap::IndexMap;
+use std::collections::HashMap;
 use std::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard};
 
 /// Program Organization Units Manager
@@ -45,13 +48,19 @@ impl Default for UnitsManager {
 pub struct UnitsManagerImpl {
     active_application: Option<usize>,
     contexts: IndexMap<usize, ModuleContext>,
+    /// Libraries used by contexts, with the namespaces libraries are referenced by
+    dependencies: HashMap<usize, Vec<(StString, usize)>>,
 }
 
 impl UnitsManagerImpl {
     fn new() -> Self {
+        let mut contexts = IndexMap::new();
+        contexts.insert(BUILTIN_CONTEXT_ID, builtin_context());
+
         Self {
             active_application: None,
-            contexts: IndexMap::new(),
+            contexts,
+            dependencies: HashMap::new(),
         }
     }
 
@@ -64,6 +73,25 @@ impl UnitsManagerImpl {
         self.contexts.insert(id, ctx);
     }
 
+    /// Library `lib` is visible in context `ctx` by its declarations, or qualified by namespace
+    /// like `Lib.FB`
+    pub fn add_dependency(&mut self, ctx: usize, namespace: StString, lib: usize) {
+        let dependencies = self.dependencies.entry(ctx).or_default();
+        if dependencies
+            .iter()
+            .any(|(ns, id)| *ns == namespace && *id == lib)
+        {
+            return;
+        }
+
+        dependencies.push((namespace, lib));
+    }
+
+    /// Libraries used by context and their namespaces, in the order they are added
+    pub fn dependencies(&self, ctx: usize) -> &[(StString, usize)] {
+        self.dependencies.get(&ctx).map_or(&[], |x| x.as_slice())
+    }
+
     pub fn set_active_application(&mut self, app: Option<usize>) {
         self.active_application = app
     }
@@ -76,7 +104,16 @@ impl UnitsManagerImpl {
         self.active_application.and_then(|x| self.get_context(x))
     }
 
+    /// The compiler builtin module with standard functions
+    pub fn builtin_context(&self) -> ModuleContext {
+        self.contexts[&BUILTIN_CONTEXT_ID].clone()
+    }
+
+    /// Applications and libraries, the compiler builtin module is not included
     pub fn contexts(&self) -> impl Iterator<Item = &ModuleContext> {
-        self.contexts.values()
+        self.contexts
+            .iter()
+            .filter(|(id, _)| **id != BUILTIN_CONTEXT_ID)
+            .map(|(_, ctx)| ctx)
     }
 }
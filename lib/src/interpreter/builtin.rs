@@ -0,0 +1,396 @@
+use crate::backend::utils::{integer_width, is_float_type};
+use crate::parser::{LiteralValue, Operator, StLexerBuilder, TokenKind};
+use crate::prelude::*;
+
+use super::{RuntimeError, StructValue, Value};
+
+/// Call standard function of the builtin module, arguments are converted to the concrete
+/// parameter types in order of parameters, extensible inputs are at the end. `class` is the
+/// concrete return type.
+pub(super) fn call_builtin(
+    name: &str,
+    args: &[Value],
+    class: TypeClass,
+) -> Result<Value, RuntimeError> {
+    let invalid = || RuntimeError::InvalidOperation(format!("{}({:?})", name, args));
+    let name = name.to_ascii_uppercase();
+
+    if let Some((from, to)) = name.split_once("_TO_") {
+        return convert(from, to, args.first().ok_or_else(invalid)?).ok_or_else(invalid);
+    }
+
+    let float = |i: usize| args.get(i).and_then(|x| x.as_f64()).ok_or_else(invalid);
+    let int = |i: usize| args.get(i).and_then(|x| x.as_i64()).ok_or_else(invalid);
+    let string = |i: usize| match args.get(i) {
+        Some(Value::String(s)) => Ok(s.as_str()),
+        _ => Err(invalid()),
+    };
+    let real = |v: f64| Value::from_f64(v, class).ok_or_else(invalid);
+
+    match name.as_str() {
+        "ABS" => match args.first() {
+            Some(v @ (Value::Real(_) | Value::LReal(_))) => real(v.as_f64().unwrap().abs()),
+            Some(v) => Value::from_i64(v.as_i64().ok_or_else(invalid)?.wrapping_abs(), class)
+                .ok_or_else(invalid),
+            None => Err(invalid()),
+        },
+        "SQRT" => real(float(0)?.sqrt()),
+        "LN" => real(float(0)?.ln()),
+        "LOG" => real(float(0)?.log10()),
+        "EXP" => real(float(0)?.exp()),
+        "SIN" => real(float(0)?.sin()),
+        "COS" => real(float(0)?.cos()),
+        "TAN" => real(float(0)?.tan()),
+        "ASIN" => real(float(0)?.asin()),
+        "ACOS" => real(float(0)?.acos()),
+        "ATAN" => real(float(0)?.atan()),
+        "EXPT" => real(float(0)?.powf(float(1)?)),
+        "TRUNC" => Value::from_f64(float(0)?.trunc(), class).ok_or_else(invalid),
+        "SHL" | "SHR" | "ROL" | "ROR" => {
+            let bits = match class {
+                TypeClass::Bit | TypeClass::Bool => 1,
+                _ => integer_width(class).ok_or_else(invalid)?.0 as u64,
+            };
+            let mask = u64::MAX >> (64 - bits);
+            let v = int(0)? as u64 & mask;
+            let n = int(1)?.max(0) as u64;
+
+            let r = match name.as_str() {
+                "SHL" if n >= bits => 0,
+                "SHL" => v << n,
+                "SHR" if n >= bits => 0,
+                "SHR" => v >> n,
+                _ => {
+                    let n = n % bits;
+                    let n = if name == "ROL" { n } else { (bits - n) % bits };
+                    match n {
+                        0 => v,
+                        _ => (v << n) | (v >> (bits - n)),
+                    }
+                }
+            };
+            Value::from_i64((r & mask) as i64, class).ok_or_else(invalid)
+        }
+        "SEL" => {
+            let g = args.first().and_then(|x| x.as_bool()).ok_or_else(invalid)?;
+            args.get(if g { 2 } else { 1 }).cloned().ok_or_else(invalid)
+        }
+        "MAX" | "MIN" => {
+            let op = match name.as_str() {
+                "MAX" => Operator::Greater,
+                _ => Operator::Less,
+            };
+            let mut result = args.first().cloned().ok_or_else(invalid)?;
+            for v in args.iter().skip(1) {
+                if Value::binary(op, v, &result)?.as_bool() == Some(true) {
+                    result = v.clone();
+                }
+            }
+            Ok(result)
+        }
+        "LIMIT" => {
+            let [mn, v, mx] = args else {
+                return Err(invalid());
+            };
+            if Value::binary(Operator::Less, v, mn)?.as_bool() == Some(true) {
+                Ok(mn.clone())
+            } else if Value::binary(Operator::Greater, v, mx)?.as_bool() == Some(true) {
+                Ok(mx.clone())
+            } else {
+                Ok(v.clone())
+            }
+        }
+        "MUX" => {
+            let k = int(0)?;
+            usize::try_from(k)
+                .ok()
+                .and_then(|k| args.get(k + 1))
+                .cloned()
+                .ok_or_else(|| RuntimeError::IndexOutOfRange(format!("MUX K = {}", k)))
+        }
+        "LEN" => Ok(Value::Int(string(0)?.chars().count() as i16)),
+        "LEFT" => Ok(substring(string(0)?, 1, int(1)?)),
+        "RIGHT" => {
+            let s = string(0)?;
+            let len = int(1)?.max(0);
+            let count = s.chars().count() as i64;
+            Ok(substring(s, count - len.min(count) + 1, len))
+        }
+        "MID" => Ok(substring(string(0)?, int(2)?, int(1)?)),
+        "CONCAT" => {
+            let mut s = String::new();
+            for i in 0..args.len() {
+                s.push_str(string(i)?);
+            }
+            Ok(Value::String(s))
+        }
+        "INSERT" => {
+            let (s, p) = (string(0)?, int(2)?);
+            let mut chars: Vec<_> = s.chars().collect();
+            let p = p.clamp(0, chars.len() as i64) as usize;
+            chars.splice(p..p, string(1)?.chars());
+            Ok(Value::String(chars.into_iter().collect()))
+        }
+        "DELETE" => Ok(replace(string(0)?, "", int(1)?, int(2)?)),
+        "REPLACE" => Ok(replace(string(0)?, string(1)?, int(2)?, int(3)?)),
+        "FIND" => {
+            let (s, pattern) = (string(0)?, string(1)?);
+            let p = s
+                .find(pattern)
+                .map_or(0, |x| s[..x].chars().count() as i16 + 1);
+            Ok(Value::Int(p))
+        }
+        _ => Err(RuntimeError::FunctionNotFound(StString::new(name))),
+    }
+}
+
+/// Members of standard function block instance
+struct Instance<'a>(&'a mut StructValue);
+
+impl Instance<'_> {
+    fn get(&self, name: &str) -> Result<&Value, RuntimeError> {
+        let name = StString::new(name);
+        self.0
+            .get(&name)
+            .ok_or(RuntimeError::VariableNotFound(name))
+    }
+
+    fn bool(&self, name: &str) -> Result<bool, RuntimeError> {
+        Ok(self.get(name)?.as_bool().unwrap_or(false))
+    }
+
+    fn int(&self, name: &str) -> Result<i64, RuntimeError> {
+        Ok(self.get(name)?.as_i64().unwrap_or(0))
+    }
+
+    /// Value is converted to the type of member
+    fn set(&mut self, name: &str, value: Value) -> Result<(), RuntimeError> {
+        let name = StString::new(name);
+        match self.0.get_mut(&name) {
+            Some(member) => {
+                member.assign(value);
+                Ok(())
+            }
+            None => Err(RuntimeError::VariableNotFound(name)),
+        }
+    }
+
+    /// Return true on rising edge of input, `memory` keeps the previous value
+    fn rising_edge(&mut self, input: &str, memory: &str) -> Result<bool, RuntimeError> {
+        let (v, m) = (self.bool(input)?, self.bool(memory)?);
+        self.set(memory, Value::Bool(v))?;
+        Ok(v && !m)
+    }
+}
+
+/// Run body of standard function block on instance, `now` is the current time of timers
+/// in nanoseconds
+pub(super) fn exec_function_block(
+    name: &str,
+    instance: &mut StructValue,
+    now: u64,
+) -> Result<(), RuntimeError> {
+    let mut fb = Instance(instance);
+    let now = now as i64;
+    let name = name.to_ascii_uppercase();
+
+    match name.as_str() {
+        "TON" | "TOF" | "TP" => {
+            let (input, pt) = (fb.bool("IN")?, fb.int("PT")?.max(0));
+            let (timing, start) = (fb.bool("M")?, fb.int("START")?);
+            let elapsed = |start: i64| now.saturating_sub(start).clamp(0, pt);
+
+            match name.as_str() {
+                // Q is set when IN stays true for PT, a changed PT is used at once
+                "TON" if !input => {
+                    fb.set("M", Value::Bool(false))?;
+                    fb.set("Q", Value::Bool(false))?;
+                    fb.set("ET", Value::Time(0))?;
+                }
+                "TON" => {
+                    let start = if timing { start } else { now };
+                    let et = elapsed(start);
+                    fb.set("M", Value::Bool(true))?;
+                    fb.set("START", Value::Time(start))?;
+                    fb.set("Q", Value::Bool(et >= pt))?;
+                    fb.set("ET", Value::Time(et))?;
+                }
+                // Q is reset when IN stays false for PT
+                "TOF" if input => {
+                    fb.set("M", Value::Bool(false))?;
+                    fb.set("Q", Value::Bool(true))?;
+                    fb.set("ET", Value::Time(0))?;
+                }
+                "TOF" => {
+                    if fb.bool("Q")? {
+                        let start = if timing { start } else { now };
+                        let et = elapsed(start);
+                        fb.set("M", Value::Bool(et < pt))?;
+                        fb.set("START", Value::Time(start))?;
+                        fb.set("Q", Value::Bool(et < pt))?;
+                        fb.set("ET", Value::Time(et))?;
+                    }
+                }
+                // pulse of PT starts on rising edge of IN and can't be retriggered
+                _ => {
+                    let rising = fb.rising_edge("IN", "IN_M")?;
+                    let start = match (timing, rising) {
+                        (true, _) => Some(start),
+                        (false, true) => Some(now),
+                        (false, false) => None,
+                    };
+
+                    match start {
+                        Some(start) => {
+                            let et = elapsed(start);
+                            fb.set("M", Value::Bool(et < pt))?;
+                            fb.set("START", Value::Time(start))?;
+                            fb.set("Q", Value::Bool(et < pt))?;
+                            fb.set("ET", Value::Time(et))?;
+                        }
+                        // ET is kept until IN is false after pulse
+                        None if !input => fb.set("ET", Value::Time(0))?,
+                        None => {}
+                    }
+                }
+            }
+        }
+        "R_TRIG" => {
+            let q = fb.rising_edge("CLK", "M")?;
+            fb.set("Q", Value::Bool(q))?;
+        }
+        "F_TRIG" => {
+            let (clk, m) = (fb.bool("CLK")?, fb.bool("M")?);
+            fb.set("Q", Value::Bool(!clk && m))?;
+            fb.set("M", Value::Bool(clk))?;
+        }
+        // counters saturate at the bounds of INT
+        "CTU" => {
+            let up = fb.rising_edge("CU", "CU_M")?;
+            let cv = match (fb.bool("R")?, fb.int("CV")?) {
+                (true, _) => 0,
+                (false, cv) if up && cv < i16::MAX as i64 => cv + 1,
+                (false, cv) => cv,
+            };
+            fb.set("CV", Value::Int(cv as i16))?;
+            fb.set("Q", Value::Bool(cv >= fb.int("PV")?))?;
+        }
+        "CTD" => {
+            let down = fb.rising_edge("CD", "CD_M")?;
+            let cv = match (fb.bool("LD")?, fb.int("CV")?) {
+                (true, _) => fb.int("PV")?,
+                (false, cv) if down && cv > i16::MIN as i64 => cv - 1,
+                (false, cv) => cv,
+            };
+            fb.set("CV", Value::Int(cv as i16))?;
+            fb.set("Q", Value::Bool(cv <= 0))?;
+        }
+        "CTUD" => {
+            let up = fb.rising_edge("CU", "CU_M")?;
+            let down = fb.rising_edge("CD", "CD_M")?;
+            let cv = match (fb.bool("R")?, fb.bool("LD")?, fb.int("CV")?) {
+                (true, _, _) => 0,
+                (false, true, _) => fb.int("PV")?,
+                // counting up and down at the same time keeps CV
+                (false, false, cv) if up && down => cv,
+                (false, false, cv) if up && cv < i16::MAX as i64 => cv + 1,
+                (false, false, cv) if down && cv > i16::MIN as i64 => cv - 1,
+                (false, false, cv) => cv,
+            };
+            fb.set("CV", Value::Int(cv as i16))?;
+            fb.set("QU", Value::Bool(cv >= fb.int("PV")?))?;
+            fb.set("QD", Value::Bool(cv <= 0))?;
+        }
+        // set dominant
+        "SR" => {
+            let q = fb.bool("S1")? || (!fb.bool("R")? && fb.bool("Q1")?);
+            fb.set("Q1", Value::Bool(q))?;
+        }
+        // reset dominant
+        "RS" => {
+            let q = !fb.bool("R1")? && (fb.bool("S")? || fb.bool("Q1")?);
+            fb.set("Q1", Value::Bool(q))?;
+        }
+        _ => return Err(RuntimeError::FunctionNotFound(StString::new(name))),
+    }
+
+    Ok(())
+}
+
+/// Characters from 1-based position `p` with length `len`
+fn substring(s: &str, p: i64, len: i64) -> Value {
+    let skip = (p.max(1) - 1) as usize;
+    let take = len.max(0) as usize;
+
+    Value::String(s.chars().skip(skip).take(take).collect())
+}
+
+/// Replace `len` characters from 1-based position `p` with `with`
+fn replace(s: &str, with: &str, len: i64, p: i64) -> Value {
+    let mut chars: Vec<_> = s.chars().collect();
+    let start = ((p.max(1) - 1) as usize).min(chars.len());
+    let end = (start + len.max(0) as usize).min(chars.len());
+    chars.splice(start..end, with.chars());
+
+    Value::String(chars.into_iter().collect())
+}
+
+/// Type conversion function like 'REAL_TO_INT', floats are rounded to integers
+fn convert(from: &str, to: &str, v: &Value) -> Option<Value> {
+    let to = type_class(to)?;
+    let v = match type_class(from)? {
+        TypeClass::String => return parse_string(v, to),
+        from => v.convert(from),
+    };
+
+    match (&v, to) {
+        (_, TypeClass::String) => Some(Value::String(v.to_string())),
+        (Value::Real(_) | Value::LReal(_), _) if !is_float_type(to) => {
+            Value::from_f64(v.as_f64()?.round(), to)
+        }
+        _ => Some(v.convert(to)),
+    }
+}
+
+/// Parse string as literal of type, invalid strings are zero
+fn parse_string(v: &Value, to: TypeClass) -> Option<Value> {
+    let Value::String(s) = v else {
+        return None;
+    };
+    let s = s.trim();
+
+    let parsed = match to {
+        TypeClass::String => Some(v.clone()),
+        TypeClass::Bool => Some(Value::Bool(s.eq_ignore_ascii_case("TRUE") || s == "1")),
+        TypeClass::Real | TypeClass::LReal => s.parse().ok().and_then(|x| Value::from_f64(x, to)),
+        TypeClass::Time => match StLexerBuilder::new().build_str(s).next() {
+            Some(Ok(tok)) => match tok.kind {
+                TokenKind::Literal(LiteralValue::Time(ns)) => Some(Value::Time(ns)),
+                _ => None,
+            },
+            _ => None,
+        },
+        _ => s.parse().ok().and_then(|x| Value::from_i64(x, to)),
+    };
+
+    parsed.or_else(|| Value::zero(to))
+}
+
+fn type_class(name: &str) -> Option<TypeClass> {
+    Some(match name {
+        "BOOL" => TypeClass::Bool,
+        "SINT" => TypeClass::SInt,
+        "BYTE" => TypeClass::Byte,
+        "INT" => TypeClass::Int,
+        "UINT" => TypeClass::UInt,
+        "DINT" => TypeClass::DInt,
+        "UDINT" => TypeClass::UDInt,
+        "LINT" => TypeClass::LInt,
+        "ULINT" => TypeClass::ULInt,
+        "REAL" => TypeClass::Real,
+        "LREAL" => TypeClass::LReal,
+        "TIME" => TypeClass::Time,
+        "STRING" => TypeClass::String,
+        _ => return None,
+    })
+}
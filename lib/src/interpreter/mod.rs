@@ -0,0 +1,1112 @@
+//! Reference interpreter, which executes statements and expressions of application
+//! directly. Semantics follow the code generators: integer arithmetic wraps around in the
+//! declared widths, operands are calculated in the wider type of them and integer division
+//! by zero is a runtime error.
+
+mod builtin;
+mod value;
+pub use value::{StructValue, Value, STRING_LENGTH};
+
+// the hand-written parser does not support all statements in tests
+#[cfg(test)]
+mod test;
+
+use crate::analysis::is_generic;
+use crate::backend::utils::*;
+use crate::prelude::*;
+use crate::scheduler::CycleExecutor;
+
+use log::*;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+use std::mem;
+use std::sync::Arc;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RuntimeError {
+    DivisionByZero,
+    /// Message with location of array access
+    IndexOutOfRange(String),
+    /// Message with value assigned to subrange variable
+    OutOfRange(String),
+    VariableNotFound(StString),
+    FunctionNotFound(StString),
+    InvalidOperation(String),
+}
+
+impl Error for RuntimeError {}
+
+impl Display for RuntimeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            RuntimeError::DivisionByZero => f.write_str("integer division by zero"),
+            RuntimeError::IndexOutOfRange(message) => f.write_str(message),
+            RuntimeError::OutOfRange(message) => f.write_str(message),
+            RuntimeError::VariableNotFound(name) => write!(f, "variable not found: {}", name),
+            RuntimeError::FunctionNotFound(name) => write!(f, "function not found: {}", name),
+            RuntimeError::InvalidOperation(op) => write!(f, "invalid operation: {}", op),
+        }
+    }
+}
+
+/// Function provided by the host, called for functions not declared in application
+pub type NativeFunction = Box<dyn FnMut(&[Value]) -> Option<Value>>;
+
+/// Current time in nanoseconds for timers of standard function blocks
+pub type TimeSource = Box<dyn FnMut() -> u64>;
+
+/// Variables of POU being executed
+struct Frame {
+    proto: Prototype,
+    variables: StructValue,
+    /// Concrete types of type families in generic function
+    families: Vec<(TypeFamily, TypeClass)>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Root {
+    /// Variables of the current frame
+    Frame,
+    Global,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Selector {
+    Member(StString),
+    Index(usize),
+}
+
+/// Assignable place, like `a`, `s.b[2].c` or a global variable
+#[derive(Debug, Clone)]
+struct Place {
+    root: Root,
+    name: StString,
+    path: Vec<Selector>,
+}
+
+impl Place {
+    fn member(&self, name: &StString) -> Place {
+        let mut place = self.clone();
+        place.path.push(Selector::Member(name.clone()));
+        place
+    }
+}
+
+fn select<'a>(mut value: &'a Value, path: &[Selector]) -> Option<&'a Value> {
+    for selector in path {
+        value = match (value, selector) {
+            (Value::Struct(st), Selector::Member(name)) => st.get(name)?,
+            (Value::Array(elements), Selector::Index(idx)) => elements.get(*idx)?,
+            _ => return None,
+        };
+    }
+
+    Some(value)
+}
+
+fn select_mut<'a>(mut value: &'a mut Value, path: &[Selector]) -> Option<&'a mut Value> {
+    for selector in path {
+        value = match (value, selector) {
+            (Value::Struct(st), Selector::Member(name)) => st.get_mut(name)?,
+            (Value::Array(elements), Selector::Index(idx)) => elements.get_mut(*idx)?,
+            _ => return None,
+        };
+    }
+
+    Some(value)
+}
+
+/// Default simulated execution time of one statement, 1 microsecond
+pub const DEFAULT_STATEMENT_TIME: u64 = 1_000;
+
+/// Tree-walking interpreter of application. Programs are executed by `run_program()`, one
+/// call for one cycle. Global variables, variables of programs and instances of function
+/// blocks keep their values between cycles. Program instances of configuration are run by
+/// `run_instance()`, each instance has its own variables. Timers of standard function
+/// blocks run on the simulated time, unless a time source is set.
+///
+/// When `stop_on_error` is set, which is the default, the first runtime error stops
+/// execution. Otherwise errors are collected and execution continues: the result of
+/// division by zero is zero, out of range array elements read as default and writes to
+/// them are ignored.
+///
+/// When `range_check` is set, values assigned to subrange variables are checked. Out of
+/// range values are errors and not stored.
+pub struct Interpreter {
+    ctx: TypeContext,
+    stop_on_error: bool,
+    range_check: bool,
+    errors: Vec<RuntimeError>,
+    initialized: bool,
+    globals: StructValue,
+    /// Variables of program instances by instance name
+    programs: HashMap<StString, StructValue>,
+    frames: Vec<Frame>,
+    natives: HashMap<StString, NativeFunction>,
+    /// Simulated time in nanoseconds, set by scheduler
+    time: u64,
+    /// Replaces simulated time for timers if set
+    time_source: Option<TimeSource>,
+    executed_statements: u64,
+    /// Simulated execution time of one statement in nanoseconds
+    statement_time: u64,
+}
+
+impl Interpreter {
+    pub fn new(mgr: UnitsManager, app: ModuleContext) -> Self {
+        Self {
+            ctx: TypeContext::new(mgr, app),
+            stop_on_error: true,
+            range_check: false,
+            errors: vec![],
+            initialized: false,
+            globals: StructValue::new(),
+            programs: HashMap::new(),
+            frames: vec![],
+            natives: HashMap::new(),
+            time: 0,
+            time_source: None,
+            executed_statements: 0,
+            statement_time: DEFAULT_STATEMENT_TIME,
+        }
+    }
+
+    #[inline]
+    pub fn stop_on_error(&self) -> bool {
+        self.stop_on_error
+    }
+
+    pub fn set_stop_on_error(&mut self, stop: bool) {
+        self.stop_on_error = stop;
+    }
+
+    #[inline]
+    pub fn range_check(&self) -> bool {
+        self.range_check
+    }
+
+    pub fn set_range_check(&mut self, enabled: bool) {
+        self.range_check = enabled;
+    }
+
+    /// Runtime errors collected when `stop_on_error` is not set
+    #[inline]
+    pub fn errors(&self) -> &[RuntimeError] {
+        &self.errors
+    }
+
+    pub fn clear_errors(&mut self) {
+        self.errors.clear();
+    }
+
+    /// Current time in nanoseconds
+    #[inline]
+    pub fn time(&self) -> u64 {
+        self.time
+    }
+
+    pub fn set_time(&mut self, time: u64) {
+        self.time = time;
+    }
+
+    /// Drive timers like TON by time source instead of the simulated time
+    pub fn set_time_source<F>(&mut self, f: F)
+    where
+        F: FnMut() -> u64 + 'static,
+    {
+        self.time_source = Some(Box::new(f));
+    }
+
+    /// Time seen by timers, from time source or the simulated time
+    fn now(&mut self) -> u64 {
+        match self.time_source.as_mut() {
+            Some(f) => f(),
+            None => self.time,
+        }
+    }
+
+    /// Count of statements executed since created, used to estimate execution time
+    #[inline]
+    pub fn executed_statements(&self) -> u64 {
+        self.executed_statements
+    }
+
+    #[inline]
+    pub fn statement_time(&self) -> u64 {
+        self.statement_time
+    }
+
+    /// Set simulated execution time of one statement in nanoseconds
+    pub fn set_statement_time(&mut self, time: u64) {
+        self.statement_time = time;
+    }
+
+    /// Provide function called by application but not declared in it, like I/O of the
+    /// runtime. Arguments are passed in order, outputs are not supported.
+    pub fn register_function<F>(&mut self, name: &str, f: F)
+    where
+        F: FnMut(&[Value]) -> Option<Value> + 'static,
+    {
+        self.natives.insert(StString::new(name), Box::new(f));
+    }
+
+    /// Initialize global variables and variables of programs with initial values, this is
+    /// done on the first run if not called.
+    pub fn init(&mut self) -> Result<(), RuntimeError> {
+        self.globals = StructValue::new();
+        self.programs.clear();
+        self.frames.clear();
+
+        for decl in sorted_declarations(self.ctx.app()) {
+            let (name, variables, is_global) = {
+                let p = decl.read().unwrap();
+                let name = p.name().clone();
+                match (&p.decl().kind, pou_kind(p.decl())) {
+                    (DeclKind::GlobalVar(_) | DeclKind::Configuration(_), _) => {
+                        (name, p.variables().to_vec(), true)
+                    }
+                    (_, Some(PouKind::Program)) => (name, p.variables().to_vec(), false),
+                    _ => continue,
+                }
+            };
+
+            self.ctx.enter_declaration(&decl);
+            if is_global {
+                // initial values may refer to the previous globals, like constants
+                for variable in variables {
+                    let value = self.default_value(variable.ty(), variable.initial().as_deref())?;
+                    self.globals.push(variable.name().clone(), value);
+                }
+            } else {
+                let values = self.default_variables(&variables)?;
+                self.programs.insert(name, values);
+            }
+        }
+
+        self.initialized = true;
+        Ok(())
+    }
+
+    /// Execute one cycle of program
+    pub fn run_program(&mut self, name: &str) -> Result<(), RuntimeError> {
+        if !self.initialized {
+            self.init()?;
+        }
+
+        self.run_instance(name, name)
+    }
+
+    /// Execute one cycle of program instance, variables of instance are initialized on the
+    /// first run
+    pub fn run_instance(&mut self, instance: &str, program: &str) -> Result<(), RuntimeError> {
+        if !self.initialized {
+            self.init()?;
+        }
+
+        let program = StString::new(program);
+        let decl = self
+            .ctx
+            .find_declaration(&program)
+            .filter(|x| pou_kind(x.read().unwrap().decl()) == Some(PouKind::Program))
+            .ok_or(RuntimeError::FunctionNotFound(program))?;
+
+        let instance = StString::new(instance);
+        if !self.programs.contains_key(&instance) {
+            let variables = decl.read().unwrap().variables().to_vec();
+            self.ctx.enter_declaration(&decl);
+            let values = self.default_variables(&variables)?;
+            self.programs.insert(instance.clone(), values);
+        }
+
+        self.frames.clear();
+        self.exec_program(&decl, &instance)
+    }
+
+    /// Call function with values of inputs in declaration order, returns the return value
+    pub fn call_function(
+        &mut self,
+        name: &str,
+        args: &[Value],
+    ) -> Result<Option<Value>, RuntimeError> {
+        if !self.initialized {
+            self.init()?;
+        }
+
+        let name = StString::new(name);
+        let decl = self
+            .ctx
+            .find_declaration(&name)
+            .filter(|x| pou_kind(x.read().unwrap().decl()) == Some(PouKind::Function))
+            .ok_or(RuntimeError::FunctionNotFound(name))?;
+        let params: Vec<_> = decl
+            .read()
+            .unwrap()
+            .variables()
+            .iter()
+            .filter(|x| !x.scope().is_empty())
+            .cloned()
+            .collect();
+
+        let mut inputs = vec![None; params.len()];
+        let mut args = args.iter();
+        for (input, param) in inputs.iter_mut().zip(params.iter()) {
+            if param.flags().contains(VariableFlags::INPUT) {
+                *input = args.next().cloned();
+            }
+        }
+
+        self.frames.clear();
+        let (ret, _) = self.exec_function(&decl, inputs, vec![])?;
+        Ok(ret)
+    }
+
+    #[inline]
+    pub fn global(&self, name: &str) -> Option<&Value> {
+        self.globals.get(&StString::new(name))
+    }
+
+    /// Value of variable by path, like `main.c.count` for variables of program instances or
+    /// `g.x` for global variables
+    pub fn variable(&self, path: &str) -> Option<&Value> {
+        let mut names = path.split('.').map(StString::new);
+        let first = names.next()?;
+        let rest: Vec<_> = names.map(Selector::Member).collect();
+
+        if let Some(value) = self.globals.get(&first) {
+            return select(value, &rest);
+        }
+
+        let program = self.programs.get(&first)?;
+        let (member, rest) = rest.split_first()?;
+        let Selector::Member(member) = member else {
+            return None;
+        };
+
+        select(program.get(member)?, rest)
+    }
+
+    /// Report runtime error, returns Err if execution should be stopped
+    fn error(&mut self, e: RuntimeError) -> Result<(), RuntimeError> {
+        warn!("Interpreter: runtime error: {}", e);
+
+        if self.stop_on_error {
+            return Err(e);
+        }
+
+        self.errors.push(e);
+        Ok(())
+    }
+
+    fn frame(&self) -> Option<&Frame> {
+        self.frames.last()
+    }
+
+    /// Enter declaration of the current frame after returning from a call
+    fn restore_context(&mut self) {
+        if let Some(frame) = self.frames.last() {
+            let (proto, families) = (frame.proto.clone(), frame.families.clone());
+            self.ctx.enter_declaration(&proto);
+            self.ctx.families = families;
+        }
+    }
+
+    /// Run body of POU in a new frame with variables, returns the variables of frame
+    fn exec_frame(
+        &mut self,
+        proto: &Prototype,
+        variables: StructValue,
+        families: Vec<(TypeFamily, TypeClass)>,
+    ) -> (StructValue, Result<(), RuntimeError>) {
+        let id = proto.read().unwrap().id();
+        let f = self.ctx.app().read().get_function(id).cloned();
+
+        self.ctx.enter_declaration(proto);
+        self.ctx.families.clone_from(&families);
+        self.frames.push(Frame {
+            proto: proto.clone(),
+            variables,
+            families,
+        });
+
+        let r = match f {
+            Some(f) => self.exec_statement(f.read().parse_tree()),
+            None => Err(RuntimeError::FunctionNotFound(
+                proto.read().unwrap().name().clone(),
+            )),
+        };
+
+        let frame = self.frames.pop().unwrap();
+        self.restore_context();
+
+        (frame.variables, r)
+    }
+
+    fn exec_program(&mut self, proto: &Prototype, instance: &StString) -> Result<(), RuntimeError> {
+        let variables = self.programs.remove(instance).unwrap_or_default();
+
+        let (variables, r) = self.exec_frame(proto, variables, vec![]);
+        self.programs.insert(instance.clone(), variables);
+
+        r
+    }
+
+    /// Execute function with values of parameters, absent inputs are initialized with
+    /// default values. `families` are the concrete types of generic function instance.
+    /// Returns the return value and values of parameters after execution.
+    fn exec_function(
+        &mut self,
+        proto: &Prototype,
+        params: Vec<Option<Value>>,
+        families: Vec<(TypeFamily, TypeClass)>,
+    ) -> Result<(Option<Value>, StructValue), RuntimeError> {
+        let (name, variables) = {
+            let p = proto.read().unwrap();
+            (p.name().clone(), p.variables().to_vec())
+        };
+        self.ctx.enter_declaration(proto);
+        self.ctx.families.clone_from(&families);
+
+        let mut frame = StructValue::new();
+        let mut params = params.into_iter();
+        for variable in variables.iter() {
+            let mut value = self.default_value(variable.ty(), variable.initial().as_deref())?;
+            if !variable.scope().is_empty() {
+                if let Some(param) = params.next().flatten() {
+                    value.assign(param);
+                }
+            }
+
+            frame.push(variable.name().clone(), value);
+        }
+        if let Some(ty) = self.ctx.return_type.clone() {
+            frame.push(name.clone(), self.default_value(Some(&ty), None)?);
+        }
+
+        self.restore_context();
+        let (frame, r) = self.exec_frame(proto, frame, families);
+        r?;
+
+        let ret = frame.get(&name).cloned();
+        let params = variables
+            .iter()
+            .filter(|x| !x.scope().is_empty())
+            .filter_map(|x| Some((x.name().clone(), frame.get(x.name())?.clone())))
+            .fold(StructValue::new(), |mut st, (name, value)| {
+                st.push(name, value);
+                st
+            });
+
+        Ok((ret, params))
+    }
+
+    fn default_variables(
+        &mut self,
+        variables: &[Arc<Variable>],
+    ) -> Result<StructValue, RuntimeError> {
+        let mut values = StructValue::new();
+        for variable in variables {
+            let value = self.default_value(variable.ty(), variable.initial().as_deref())?;
+            values.push(variable.name().clone(), value);
+        }
+
+        Ok(values)
+    }
+
+    /// Initial value of object of type
+    fn default_value(
+        &mut self,
+        ty: Option<&Type>,
+        initial: Option<&Expression>,
+    ) -> Result<Value, RuntimeError> {
+        let Some(ty) = ty else {
+            return Ok(Value::LInt(0));
+        };
+        let resolved = self.ctx.resolve_type(ty);
+
+        if let Some(arr) = resolved.as_array() {
+            let count: i64 = array_dimensions(arr, &self.ctx)
+                .ok_or_else(|| RuntimeError::InvalidOperation(format!("array bounds: {}", ty)))?
+                .iter()
+                .map(|(_, len)| *len)
+                .product();
+            let element = self.default_value(Some(arr.base_type()), None)?;
+
+            return Ok(Value::Array(vec![element; count as usize]));
+        }
+
+        if let Some(decl) = self.ctx.type_user_decl(&resolved) {
+            let members = {
+                let p = decl.read().unwrap();
+                let is_struct =
+                    p.is_function_block() || matches!(p.decl().kind, DeclKind::Struct(_));
+                is_struct.then(|| p.variables().to_vec())
+            };
+
+            if let Some(members) = members {
+                return Ok(Value::Struct(self.default_variables(&members)?));
+            }
+        }
+
+        let class = self.ctx.type_class(ty);
+        let default = self.ctx.default_integer(ty).unwrap_or(0);
+        let mut value = Value::from_i64(default, class)
+            .or_else(|| Value::zero(class))
+            .ok_or_else(|| RuntimeError::InvalidOperation(format!("unknown type: {}", ty)))?;
+
+        if let Some(initial) = initial {
+            value.assign(self.eval(initial)?);
+        }
+
+        Ok(value)
+    }
+
+    fn exec_statement(&mut self, stmt: &Statement) -> Result<(), RuntimeError> {
+        match &stmt.kind {
+            StmtKind::Expr(expr) => {
+                self.executed_statements += 1;
+                self.exec_expression_statement(expr.expr())
+            }
+            StmtKind::If(ifst) => {
+                self.executed_statements += 1;
+                self.exec_if_statement(ifst)
+            }
+            StmtKind::Stmts(stmts) => {
+                for stmt in stmts.iter() {
+                    self.exec_statement(stmt)?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    fn exec_expression_statement(&mut self, expr: &Expression) -> Result<(), RuntimeError> {
+        trace!("Interpreter: expression statement: {}", expr);
+
+        match &expr.kind {
+            ExprKind::Assign(assign) => self.exec_assign(assign),
+            ExprKind::Call(call) => self.exec_call(call).map(|_| ()),
+            _ => self.eval(expr).map(|_| ()),
+        }
+    }
+
+    fn exec_if_statement(&mut self, ifst: &IfStatement) -> Result<(), RuntimeError> {
+        if self.eval_condition(ifst.condition())? {
+            return match ifst.then_controlled() {
+                Some(then) => self.exec_statement(then),
+                None => Ok(()),
+            };
+        }
+
+        for else_if in ifst.else_if_list() {
+            if self.eval_condition(else_if.condition())? {
+                return match else_if.then_controlled() {
+                    Some(then) => self.exec_statement(then),
+                    None => Ok(()),
+                };
+            }
+        }
+
+        match ifst.else_controlled() {
+            Some(else_ctrl) => self.exec_statement(else_ctrl),
+            None => Ok(()),
+        }
+    }
+
+    fn eval_condition(&mut self, expr: &Expression) -> Result<bool, RuntimeError> {
+        let value = self.eval(expr)?;
+        value
+            .as_bool()
+            .ok_or_else(|| RuntimeError::InvalidOperation(format!("condition {}", value)))
+    }
+
+    fn exec_assign(&mut self, assign: &AssignExpression) -> Result<(), RuntimeError> {
+        let place = self.eval_place(assign.left())?;
+        let value = match assign.assign_type() {
+            AssignType::Set | AssignType::Reset => {
+                if !self.eval_condition(assign.right())? {
+                    return Ok(());
+                }
+                Value::Bool(matches!(assign.assign_type(), AssignType::Set))
+            }
+            _ => self.eval(assign.right())?,
+        };
+
+        if self.range_check {
+            let bounds = self
+                .ctx
+                .expression_type(assign.left())
+                .and_then(|ty| self.ctx.subrange(&ty));
+            if let Some((lower, upper)) = bounds {
+                let v = value.as_i64().unwrap_or(lower);
+                if v < lower || v > upper {
+                    let message = format!("value out of range {}..{}: {}", lower, upper, assign);
+                    self.error(RuntimeError::OutOfRange(message))?;
+                    return Ok(());
+                }
+            }
+        }
+
+        if let Some(place) = place {
+            self.store(&place, value)?;
+        }
+        Ok(())
+    }
+
+    fn load(&self, place: &Place) -> Option<&Value> {
+        let root = match place.root {
+            Root::Frame => self.frame()?.variables.get(&place.name)?,
+            Root::Global => self.globals.get(&place.name)?,
+        };
+
+        select(root, &place.path)
+    }
+
+    fn load_mut(&mut self, place: &Place) -> Option<&mut Value> {
+        let root = match place.root {
+            Root::Frame => self.frames.last_mut()?.variables.get_mut(&place.name)?,
+            Root::Global => self.globals.get_mut(&place.name)?,
+        };
+
+        select_mut(root, &place.path)
+    }
+
+    fn store(&mut self, place: &Place, value: Value) -> Result<(), RuntimeError> {
+        match self.load_mut(place) {
+            Some(target) => {
+                target.assign(value);
+                Ok(())
+            }
+            None => Err(RuntimeError::VariableNotFound(place.name.clone())),
+        }
+    }
+
+    /// Place of expression, None if array index is out of range and execution continues
+    fn eval_place(&mut self, expr: &Expression) -> Result<Option<Place>, RuntimeError> {
+        match &expr.kind {
+            ExprKind::Variable(var) => {
+                let name = var.name().clone();
+                let root = if self
+                    .frame()
+                    .is_some_and(|x| x.variables.get(&name).is_some())
+                {
+                    Root::Frame
+                } else if self.globals.get(&name).is_some() {
+                    Root::Global
+                } else {
+                    return Err(RuntimeError::VariableNotFound(name));
+                };
+
+                Ok(Some(Place {
+                    root,
+                    name,
+                    path: vec![],
+                }))
+            }
+            ExprKind::Compo(compo) => {
+                let field = expression_variable_name(compo.right())
+                    .ok_or_else(|| RuntimeError::InvalidOperation(expr.to_string()))?;
+                let place = self.eval_place(compo.left())?;
+
+                Ok(place.map(|x| x.member(field)))
+            }
+            ExprKind::ArrayAccess(access) => {
+                let Some(mut place) = self.eval_place(access.array())? else {
+                    return Ok(None);
+                };
+                let dims = self
+                    .ctx
+                    .expression_type(access.array())
+                    .map(|x| self.ctx.resolve_type(&x))
+                    .and_then(|x| x.as_array().and_then(|x| array_dimensions(x, &self.ctx)))
+                    .ok_or_else(|| RuntimeError::InvalidOperation(expr.to_string()))?;
+
+                let mut offset = 0;
+                let mut in_range = true;
+                for (index, (lower, len)) in access.indexes().iter().zip(dims.iter()) {
+                    let value = self.eval(index)?;
+                    let index = value.as_i64().ok_or_else(|| {
+                        RuntimeError::InvalidOperation(format!("index {}", value))
+                    })?;
+
+                    in_range &= index >= *lower && index - lower < *len;
+                    offset = offset * len + (index - lower);
+                }
+
+                if !in_range {
+                    let message = match expr.info.start {
+                        Some(loc) => format!(
+                            "array index out of range at line {}, column {}: {}",
+                            loc.mark + 1,
+                            loc.offset + 1,
+                            access
+                        ),
+                        None => format!("array index out of range: {}", access),
+                    };
+                    self.error(RuntimeError::IndexOutOfRange(message))?;
+                    return Ok(None);
+                }
+
+                place.path.push(Selector::Index(offset as usize));
+                Ok(Some(place))
+            }
+            _ => Err(RuntimeError::InvalidOperation(format!(
+                "{} is not assignable",
+                expr
+            ))),
+        }
+    }
+
+    /// Enum member used in expression, like `Red` or `Color.Red`
+    fn enum_constant(&self, expr: &Expression) -> Option<i64> {
+        match &expr.kind {
+            ExprKind::Variable(var) => {
+                let is_variable = self
+                    .frame()
+                    .is_some_and(|x| x.variables.get(var.name()).is_some())
+                    || self.globals.get(var.name()).is_some();
+                if is_variable {
+                    return None;
+                }
+
+                self.ctx.enum_member(None, var.name())
+            }
+            ExprKind::Compo(compo) => {
+                let enum_name = expression_variable_name(compo.left())?;
+                let member = expression_variable_name(compo.right())?;
+                if self.ctx.scope.find_variable(enum_name).is_some() {
+                    return None;
+                }
+
+                self.ctx.enum_member(Some(enum_name), member)
+            }
+            _ => None,
+        }
+    }
+
+    fn eval_literal(&self, literal: &LiteralValue) -> Value {
+        match literal {
+            LiteralValue::Bool(b) => Value::Bool(*b),
+            LiteralValue::String(s) => Value::String(s.clone()),
+            LiteralValue::Real(s) => Value::Real(s.replace('_', "").parse().unwrap_or(0.0)),
+            LiteralValue::LReal(s) => Value::LReal(s.replace('_', "").parse().unwrap_or(0.0)),
+            LiteralValue::Time(v) => Value::Time(*v),
+            // integer literals are untyped
+            _ => Value::LInt(literal_integer(literal).unwrap_or(0)),
+        }
+    }
+
+    /// Value of expression in type class of it, untyped integers are LINT
+    fn eval(&mut self, expr: &Expression) -> Result<Value, RuntimeError> {
+        if let Some(v) = self.enum_constant(expr) {
+            return Ok(Value::LInt(v));
+        }
+
+        match &expr.kind {
+            ExprKind::Literal(lit) => Ok(self.eval_literal(lit.literal())),
+            ExprKind::Variable(_) | ExprKind::Compo(_) | ExprKind::ArrayAccess(_) => {
+                match self.eval_place(expr)? {
+                    Some(place) => self
+                        .load(&place)
+                        .cloned()
+                        .ok_or_else(|| RuntimeError::VariableNotFound(place.name.clone())),
+                    // out of range element reads as default value
+                    None => {
+                        let ty = self.ctx.expression_type(expr);
+                        self.default_value(ty.as_ref(), None)
+                    }
+                }
+            }
+            ExprKind::Operator(op_expr) => self.eval_operator(op_expr),
+            ExprKind::Call(call) => Ok(self.exec_call(call)?.unwrap_or(Value::LInt(0))),
+            ExprKind::Assign(assign) => {
+                self.exec_assign(assign)?;
+                self.eval(assign.left())
+            }
+            ExprKind::Range(_) => Err(RuntimeError::InvalidOperation(expr.to_string())),
+        }
+    }
+
+    /// Value of expression converted to type class
+    fn eval_as(&mut self, expr: &Expression, class: TypeClass) -> Result<Value, RuntimeError> {
+        Ok(self.eval(expr)?.convert(class))
+    }
+
+    fn eval_operator(&mut self, op_expr: &OperatorExpression) -> Result<Value, RuntimeError> {
+        let op = *op_expr.op();
+        let operands = op_expr.operands();
+        let class = self.ctx.operands_class(operands).unwrap_or(TypeClass::LInt);
+
+        if operands.len() == 1 {
+            let v = self.eval_as(&operands[0], class)?;
+            return Value::unary(op, &v);
+        }
+
+        // integer power is calculated in 64 bits and float power in LREAL
+        let wide = match (op, class) {
+            (Operator::Power, _) if is_float_type(class) => TypeClass::LReal,
+            (Operator::Power, _) => match integer_width(class) {
+                Some((_, false)) => TypeClass::ULInt,
+                _ => TypeClass::LInt,
+            },
+            _ => class,
+        };
+
+        let a = self.eval_as(&operands[0], wide)?;
+        let b = self.eval_as(&operands[1], wide)?;
+        match Value::binary(op, &a, &b) {
+            // results of comparisons are BOOL
+            Ok(v @ Value::Bool(_)) => Ok(v),
+            Ok(v) => Ok(v.convert(class)),
+            Err(e) => {
+                self.error(e)?;
+                Ok(Value::zero(class).unwrap_or(Value::LInt(0)))
+            }
+        }
+    }
+
+    /// Call function, function block instance, program or native function, returns the
+    /// return value of function
+    fn exec_call(&mut self, call: &CallExpression) -> Result<Option<Value>, RuntimeError> {
+        // function block instance
+        if let Some(ty) = self.ctx.expression_type(call.callee()) {
+            let decl = self.ctx.type_user_decl(&self.ctx.resolve_type(&ty));
+            if let Some(fb) = decl.filter(|x| x.read().unwrap().is_function_block()) {
+                self.exec_fb_call(call, &fb)?;
+                return Ok(None);
+            }
+        }
+
+        let name = expression_variable_name(call.callee())
+            .cloned()
+            .ok_or_else(|| RuntimeError::InvalidOperation(call.to_string()))?;
+        let decl = self.ctx.find_declaration(&name);
+        let kind = decl
+            .as_ref()
+            .and_then(|x| pou_kind(x.read().unwrap().decl()));
+
+        match (decl, kind) {
+            (Some(decl), Some(PouKind::Function)) => self.exec_function_call(call, &decl),
+            (Some(decl), Some(PouKind::Program)) => {
+                self.exec_program(&decl, &name)?;
+                Ok(None)
+            }
+            // registered functions replace standard functions
+            (None, _) if !self.natives.contains_key(&name) => {
+                match self.ctx.builtin_declaration(&name) {
+                    Some(decl) => self.exec_builtin_call(call, &decl).map(Some),
+                    None => Err(RuntimeError::FunctionNotFound(name)),
+                }
+            }
+            _ => self.exec_native_call(call, &name),
+        }
+    }
+
+    /// Arguments of standard function are converted to the types of the instance picked
+    /// by arguments, missing inputs are zero
+    fn exec_builtin_call(
+        &mut self,
+        call: &CallExpression,
+        decl: &Prototype,
+    ) -> Result<Value, RuntimeError> {
+        let arguments: Vec<_> = call
+            .arguments()
+            .iter()
+            .map(|arg| self.ctx.expression_class(argument_value(arg)))
+            .collect();
+        let standard = match StandardCall::new(decl, call, &arguments) {
+            Ok(standard) => standard,
+            Err(e) => {
+                self.error(RuntimeError::InvalidOperation(format!("{}: {}", call, e)))?;
+                return Ok(Value::LInt(0));
+            }
+        };
+
+        let mut args = vec![];
+        for (arg, class) in &standard.arguments {
+            match arg {
+                Some(idx) => {
+                    args.push(self.eval_as(argument_value(&call.arguments()[*idx]), *class)?)
+                }
+                None => args.push(Value::zero(*class).unwrap_or(Value::LInt(0))),
+            }
+        }
+
+        let class = standard.return_class;
+        if standard.name == "STC_NOW" {
+            return Ok(Value::Time(self.now() as i64));
+        }
+        match builtin::call_builtin(&standard.name, &args, class) {
+            Ok(v) => Ok(v),
+            Err(e) => {
+                self.error(e)?;
+                Ok(Value::zero(class).unwrap_or(Value::LInt(0)))
+            }
+        }
+    }
+
+    /// Inputs and in-outs are passed by value, outputs and in-outs are copied back after
+    /// the call. Generic functions are instantiated by types of arguments.
+    fn exec_function_call(
+        &mut self,
+        call: &CallExpression,
+        decl: &Prototype,
+    ) -> Result<Option<Value>, RuntimeError> {
+        let families = if is_generic(decl) {
+            let instance = self.ctx.call_instance(call, decl);
+            instance
+                .map_err(|e| RuntimeError::InvalidOperation(format!("{}: {}", call, e)))?
+                .families
+        } else {
+            vec![]
+        };
+        let params: Vec<_> = decl
+            .read()
+            .unwrap()
+            .variables()
+            .iter()
+            .filter(|x| !x.scope().is_empty())
+            .cloned()
+            .collect();
+        let bound = bind_arguments(&params, call);
+
+        let mut values = vec![];
+        let mut places = vec![];
+        for (param, arg) in params.iter().zip(bound.iter()) {
+            let Some(arg) = arg else {
+                values.push(None);
+                places.push(None);
+                continue;
+            };
+
+            if is_reference_parameter(param) {
+                let place = self.eval_place(arg)?;
+                let value = place.as_ref().and_then(|x| self.load(x)).cloned();
+                values.push(value.filter(|_| param.flags().contains(VariableFlags::INOUT)));
+                places.push(place);
+            } else {
+                values.push(Some(self.eval(arg)?));
+                places.push(None);
+            }
+        }
+
+        let (ret, outputs) = self.exec_function(decl, values, families)?;
+        for (param, place) in params.iter().zip(places) {
+            if let (Some(place), Some(value)) = (place, outputs.get(param.name())) {
+                self.store(&place, value.clone())?;
+            }
+        }
+
+        Ok(ret)
+    }
+
+    /// Inputs are assigned to instance, then the body runs on the instance and outputs are
+    /// read from instance after call
+    fn exec_fb_call(&mut self, call: &CallExpression, fb: &Prototype) -> Result<(), RuntimeError> {
+        let Some(instance) = self.eval_place(call.callee())? else {
+            return Ok(());
+        };
+        let members = fb.read().unwrap().variables().to_vec();
+
+        // inputs
+        let mut inputs = members
+            .iter()
+            .filter(|x| x.flags().contains(VariableFlags::INPUT));
+        for arg in call.arguments() {
+            let (member, value) = match &arg.kind {
+                ExprKind::Assign(assign) => match assign.assign_type() {
+                    AssignType::AssignRight => continue,
+                    _ => {
+                        let name = expression_variable_name(assign.left());
+                        let member = members.iter().find(|x| Some(x.name()) == name);
+                        (member, assign.right())
+                    }
+                },
+                _ => (inputs.next(), arg),
+            };
+
+            // TODO: unknown parameter error
+            if let Some(member) = member {
+                let value = self.eval(value)?;
+                self.store(&instance.member(member.name()), value)?;
+            }
+        }
+
+        // run body on the instance, which is put back even if an error occurs. Standard
+        // function blocks have native bodies.
+        let variables = match self.load_mut(&instance) {
+            Some(Value::Struct(st)) => mem::take(st),
+            _ => return Err(RuntimeError::VariableNotFound(instance.name.clone())),
+        };
+        let name = fb.read().unwrap().name().clone();
+        let (variables, r) = if self.ctx.find_declaration(&name).is_none() {
+            let mut variables = variables;
+            let now = self.now();
+            self.executed_statements += 1;
+            let r = builtin::exec_function_block(name.string(), &mut variables, now);
+            (variables, r)
+        } else {
+            self.exec_frame(fb, variables, vec![])
+        };
+        if let Some(Value::Struct(st)) = self.load_mut(&instance) {
+            *st = variables;
+        }
+        r?;
+
+        // outputs
+        for arg in call.arguments() {
+            let ExprKind::Assign(assign) = &arg.kind else {
+                continue;
+            };
+            if !matches!(assign.assign_type(), AssignType::AssignRight) {
+                continue;
+            }
+            let Some(name) = expression_variable_name(assign.left()) else {
+                continue;
+            };
+
+            let value = self
+                .load(&instance.member(name))
+                .cloned()
+                .ok_or_else(|| RuntimeError::VariableNotFound(name.clone()))?;
+            if let Some(target) = self.eval_place(assign.right())? {
+                self.store(&target, value)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn exec_native_call(
+        &mut self,
+        call: &CallExpression,
+        name: &StString,
+    ) -> Result<Option<Value>, RuntimeError> {
+        let mut args = vec![];
+        for arg in call.arguments() {
+            let value = match &arg.kind {
+                ExprKind::Assign(assign) => assign.right(),
+                _ => arg,
+            };
+            args.push(self.eval(value)?);
+        }
+
+        match self.natives.get_mut(name) {
+            Some(f) => Ok(f(&args)),
+            None => Err(RuntimeError::FunctionNotFound(name.clone())),
+        }
+    }
+}
+
+/// Execution time of cycle is estimated by the count of executed statements
+impl CycleExecutor for Interpreter {
+    type Error = RuntimeError;
+
+    fn run_cycle(&mut self, instance: &ProgramInstance, now: u64) -> Result<u64, RuntimeError> {
+        self.set_time(now);
+
+        let executed = self.executed_statements;
+        self.run_instance(instance.name.string(), instance.program.string())?;
+        Ok((self.executed_statements - executed) * self.statement_time)
+    }
+}
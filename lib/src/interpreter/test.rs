@@ -0,0 +1,602 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use super::*;
+use crate::builtin::GENERIC_ATTRIBUTE;
+use crate::test::fixtures::{self, *};
+use crate::utils::HasAttribute;
+
+/// Load application with multiple POUs, each POU is (declaration, body)
+fn load_application(pous: &[(&str, &str)]) -> Interpreter {
+    let (mgr, ctx) = fixtures::load_application(pous);
+    Interpreter::new(mgr, ctx)
+}
+
+/// Run `main` for cycles, returns the values of variables separated by space
+fn exec_report(pous: &[(&str, &str)], cycles: usize, variables: &[&str]) -> String {
+    let mut interpreter = load_application(pous);
+    for _ in 0..cycles {
+        interpreter.run_program("main").expect("run failed");
+    }
+
+    report(&interpreter, variables)
+}
+
+fn report(interpreter: &Interpreter, variables: &[&str]) -> String {
+    variables
+        .iter()
+        .map(|x| {
+            interpreter
+                .variable(x)
+                .unwrap_or_else(|| panic!("{} not found", x))
+                .to_string()
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[test]
+fn test_integer_wrap_around() {
+    let r = exec_report(
+        &[INTEGER_WRAP_AROUND],
+        1,
+        &[
+            "main.a", "main.b", "main.c", "main.d", "main.e", "main.f", "main.g", "main.h",
+        ],
+    );
+    assert_eq!(
+        r,
+        "-128 255 -2 1 -2147483648 4294967295 -9223372036854775808 18446744073709551615"
+    );
+}
+
+#[test]
+fn test_division() {
+    let r = exec_report(
+        &[DIVISION],
+        1,
+        &[
+            "main.a", "main.b", "main.c", "main.d", "main.x", "main.u", "main.r", "main.l",
+        ],
+    );
+    assert_eq!(r, "3 -3 -1 1024 -128 1333333333 3.5 1.5");
+}
+
+#[test]
+fn test_if_statement() {
+    let r = exec_report(
+        &[IF_STATEMENT],
+        4,
+        &["main.a", "main.b", "main.x", "main.y"],
+    );
+    assert_eq!(r, "4 51 TRUE FALSE");
+}
+
+#[test]
+fn test_function_block() {
+    let r = exec_report(
+        &FUNCTION_BLOCKS,
+        2,
+        &["main.a", "main.b", "main.d", "main.o.inner.q"],
+    );
+    assert_eq!(r, "15 20 11 12");
+}
+
+#[test]
+fn test_struct_array_enum() {
+    let mut interpreter = load_application(&STRUCT_ARRAY_ENUM);
+    for _ in 0..2 {
+        interpreter.run_program("main").unwrap();
+    }
+
+    assert_eq!(
+        report(
+            &interpreter,
+            &["main.a", "main.b", "main.d", "main.l.style"]
+        ),
+        "29 29 10 11"
+    );
+    let Some(Value::Array(m)) = interpreter.variable("main.m") else {
+        panic!("main.m is not array");
+    };
+    assert_eq!(m[4], Value::DInt(14));
+    assert_eq!(
+        interpreter.variable("main.l.b").unwrap().to_string(),
+        "(x := 1, y := 28)"
+    );
+}
+
+#[test]
+fn test_function_call() {
+    let reported = Rc::new(RefCell::new(vec![]));
+    let mut interpreter = load_application(&FUNCTION_CALL);
+    let log = reported.clone();
+    interpreter.register_function("report", move |args| {
+        log.borrow_mut().push(args[0].to_string());
+        None
+    });
+
+    interpreter.run_program("main").unwrap();
+    assert_eq!(reported.borrow().join(" "), "3 15 -32768");
+    assert_eq!(
+        report(&interpreter, &["main.x", "main.y", "main.z", "main.c"]),
+        "3 15 -32768 TRUE"
+    );
+
+    // call function from host
+    let r = interpreter
+        .call_function("add", &[Value::Int(20), Value::Int(22)])
+        .unwrap();
+    assert_eq!(r, Some(Value::Int(42)));
+}
+
+#[test]
+fn test_string() {
+    let r = exec_report(&[STRING_COMPARE], 1, &["main.s", "main.x", "main.y"]);
+    assert_eq!(r, r#""a\"b?" TRUE FALSE"#);
+}
+
+#[test]
+fn test_global_variables() {
+    let globals = ("VAR_GLOBAL total: DINT := 100; END_VAR", "");
+    let main = (
+        "PROGRAM main: VAR a: DINT; END_VAR END_PROGRAM",
+        "total := total + 1; a := total;",
+    );
+
+    let r = exec_report(&[globals, main], 3, &["total", "main.a"]);
+    assert_eq!(r, "103 103");
+}
+
+#[test]
+fn test_constant_array_bounds() {
+    let globals = (
+        "VAR_GLOBAL CONSTANT MAX_AXES: INT := 4; LAST: INT := MAX_AXES - 1; END_VAR",
+        "",
+    );
+    let color = ("TYPE Color: (Red, Green, Blue) INT; END_TYPE", "");
+    let main = (
+        "PROGRAM main: VAR \
+        axes: ARRAY[0..MAX_AXES - 1] OF DINT; \
+        weights: ARRAY[Color.Red..Blue] OF INT; \
+        a, b: DINT; \
+        END_VAR END_PROGRAM",
+        "axes[LAST] := 7; weights[Blue] := 2; a := axes[MAX_AXES - 1] * weights[2]; b := LAST;",
+    );
+
+    let mut interpreter = load_application(&[globals, color, main]);
+    interpreter.run_program("main").unwrap();
+    assert_eq!(report(&interpreter, &["main.a", "main.b"]), "14 3");
+
+    let Some(Value::Array(axes)) = interpreter.variable("main.axes") else {
+        panic!("main.axes is not array");
+    };
+    assert_eq!(axes.len(), 4);
+}
+
+#[test]
+fn test_subrange_types() {
+    let percent = ("TYPE Percent: INT(0..100); END_TYPE", "");
+    let main = (
+        "PROGRAM main: VAR p: Percent; level: SINT(-5..5); a, b: INT; END_VAR END_PROGRAM",
+        "a := p; b := level; p := 150; level := level + 1;",
+    );
+
+    // values are not checked by default, subranges start from their lower bound
+    let mut interpreter = load_application(&[percent, main]);
+    interpreter.run_program("main").unwrap();
+    assert_eq!(
+        report(&interpreter, &["main.a", "main.b", "main.p", "main.level"]),
+        "0 -5 150 -4"
+    );
+
+    let mut interpreter = load_application(&[percent, main]);
+    interpreter.set_range_check(true);
+    assert_eq!(
+        interpreter.run_program("main").unwrap_err().to_string(),
+        "value out of range 0..100: p := 150"
+    );
+
+    // out of range values are not stored when continuing on errors
+    let mut interpreter = load_application(&[percent, main]);
+    interpreter.set_range_check(true);
+    interpreter.set_stop_on_error(false);
+    interpreter.run_program("main").unwrap();
+    assert_eq!(interpreter.errors().len(), 1);
+    assert_eq!(report(&interpreter, &["main.p", "main.level"]), "0 -4");
+}
+
+#[test]
+fn test_runtime_errors() {
+    let mut interpreter = load_application(&[INDEX_OUT_OF_RANGE]);
+    interpreter.run_program("main").unwrap();
+    assert_eq!(
+        interpreter.run_program("main").unwrap_err().to_string(),
+        "array index out of range at line 3, column 3: a[i]"
+    );
+
+    let division = (
+        "PROGRAM main: VAR a, b, c: INT; END_VAR END_PROGRAM",
+        "a := 1;\nb := a / b;\nc := 3;",
+    );
+    let mut interpreter = load_application(&[division]);
+    assert_eq!(
+        interpreter.run_program("main"),
+        Err(RuntimeError::DivisionByZero)
+    );
+    assert_eq!(report(&interpreter, &["main.a", "main.c"]), "1 0");
+
+    // continue on errors
+    let mut interpreter = load_application(&[division]);
+    interpreter.set_stop_on_error(false);
+    interpreter.run_program("main").unwrap();
+    assert_eq!(interpreter.errors(), &[RuntimeError::DivisionByZero]);
+    assert_eq!(report(&interpreter, &["main.b", "main.c"]), "0 3");
+}
+
+#[test]
+fn test_standard_functions() {
+    let main = (
+        "PROGRAM main: VAR \
+        i: INT := -5; b: BYTE := 129; u: UDINT; r: REAL := 2.5; \
+        a, m, l, x: INT; s: BYTE; t: BYTE; f: LREAL; k: DINT; \
+        END_VAR END_PROGRAM",
+        "a := ABS(i); f := SQRT(16.0); s := SHL(b, 1); t := ROL(b, 1); \
+        m := MAX(i, 3, 7, 2); l := LIMIT(0, i, 10); x := SEL(TRUE, 1, 2) + MUX(2, 10, 20, 30); \
+        u := SHR(IN := 2147483648, N := 31); k := REAL_TO_DINT(r) + TRUNC(r);",
+    );
+
+    let r = exec_report(
+        &[main],
+        1,
+        &[
+            "main.a", "main.f", "main.s", "main.t", "main.m", "main.l", "main.x", "main.u",
+            "main.k",
+        ],
+    );
+    assert_eq!(r, "5 4 2 3 7 0 32 1 5");
+}
+
+#[test]
+fn test_string_functions() {
+    let main = (
+        "PROGRAM main: VAR \
+        s: STRING := \"Hello World\"; a, b, c, d, e, f, g: STRING; n, p: INT; \
+        END_VAR END_PROGRAM",
+        "n := LEN(s); p := FIND(s, \"World\"); a := LEFT(s, 5); b := RIGHT(s, 5); \
+        c := MID(s, 3, 2); d := CONCAT(a, \", \", b); e := INSERT(a, \"!\", 5); \
+        f := DELETE(s, 6, 6); g := REPLACE(s, \"There\", 5, 7);",
+    );
+
+    let r = exec_report(
+        &[main],
+        1,
+        &[
+            "main.n", "main.p", "main.a", "main.b", "main.c", "main.d", "main.e", "main.f",
+            "main.g",
+        ],
+    );
+    assert_eq!(
+        r,
+        r#"11 7 "Hello" "World" "ell" "Hello, World" "Hello!" "Hello" "Hello There""#
+    );
+}
+
+#[test]
+fn test_type_conversions() {
+    let main = (
+        "PROGRAM main: VAR \
+        i: INT := 300; r: REAL := 2.5; s: STRING; t: TIME; b: SINT; n: DINT; x: BOOL; \
+        END_VAR END_PROGRAM",
+        "b := INT_TO_SINT(i); n := REAL_TO_DINT(-r); s := INT_TO_STRING(i); \
+        t := STRING_TO_TIME(\"T#1m30s\"); i := STRING_TO_INT(\" 42 \"); x := INT_TO_BOOL(i);",
+    );
+
+    let r = exec_report(
+        &[main],
+        1,
+        &["main.b", "main.n", "main.s", "main.t", "main.i", "main.x"],
+    );
+    assert_eq!(r, r#"44 -3 "300" TIME#1m30s 42 TRUE"#);
+}
+
+#[test]
+fn test_generic_function() {
+    let twice = (
+        "FUNCTION Twice: ANY_NUM VAR_INPUT x: ANY_NUM; END_VAR END_FUNCTION",
+        "Twice := x * 2;",
+    );
+    let larger = (
+        "FUNCTION Larger: ANY_NUM VAR_INPUT x, y: ANY_NUM; END_VAR END_FUNCTION",
+        "Larger := MAX(x, y) + Twice(x) - Twice(x);",
+    );
+    let main = (
+        "PROGRAM main: VAR s: SINT := 100; r: REAL := 1.25; a: SINT; b: REAL; c: LREAL; \
+        END_VAR END_PROGRAM",
+        "a := Twice(s); b := Twice(r); c := Larger(s, r);",
+    );
+
+    let mut interpreter = load_application(&[twice, larger, main]);
+    for name in ["Twice", "Larger"] {
+        let decl = interpreter
+            .ctx
+            .find_declaration(&StString::new(name))
+            .unwrap();
+        decl.write()
+            .unwrap()
+            .decl_mut()
+            .set_attribute(StString::new(GENERIC_ATTRIBUTE), None);
+    }
+    interpreter.run_program("main").unwrap();
+
+    // SINT instance wraps around
+    assert_eq!(
+        report(&interpreter, &["main.a", "main.b", "main.c"]),
+        "-56 2.5 100"
+    );
+}
+
+#[test]
+fn test_shadowed_standard_function() {
+    let abs = (
+        "FUNCTION ABS: INT VAR_INPUT x: INT; END_VAR END_FUNCTION",
+        "ABS := 100;",
+    );
+    let main = (
+        "PROGRAM main: VAR a, max: INT; END_VAR END_PROGRAM",
+        "max := 3; a := ABS(-1) + max;",
+    );
+
+    let r = exec_report(&[abs, main], 1, &["main.a"]);
+    assert_eq!(r, "103");
+}
+
+/// Run `main` once at each step of (time in milliseconds, inputs), `input(i)` in program
+/// reads the inputs of step. Returns the report of variables after each step.
+fn run_steps(pous: &[(&str, &str)], steps: &[(u64, &[i64])], variables: &[&str]) -> Vec<String> {
+    let mut interpreter = load_application(pous);
+    let inputs = Rc::new(RefCell::new(vec![]));
+    let current = inputs.clone();
+    interpreter.register_function("input", move |args| {
+        let idx = args.first()?.as_i64()? as usize;
+        current.borrow().get(idx).map(|x| Value::LInt(*x))
+    });
+
+    steps
+        .iter()
+        .map(|(ms, values)| {
+            *inputs.borrow_mut() = values.to_vec();
+            interpreter.set_time(ms * 1_000_000);
+            interpreter.run_program("main").expect("run failed");
+            report(&interpreter, variables)
+        })
+        .collect()
+}
+
+const TIMER_VARIABLES: &[&str] = &["main.t.Q", "main.t.ET"];
+
+/// Timer `t` with IN from input 0 and PT of 100ms, or 40ms if input 1 is set
+fn timer_program(timer: &str) -> String {
+    format!(
+        "PROGRAM main: VAR t: {}; pt: TIME; END_VAR END_PROGRAM",
+        timer
+    )
+}
+
+const TIMER_BODY: &str = "pt := T#100ms;\n\
+    if input(1) > 0 then\n pt := T#40ms;\nend_if\n\
+    t(IN := input(0), PT := pt);";
+
+#[test]
+fn test_on_delay_timer() {
+    let main = timer_program("TON");
+    let r = run_steps(
+        &[(&main, TIMER_BODY)],
+        &[
+            (0, &[1]),
+            (60, &[1]),
+            (100, &[1]),
+            (250, &[1]),
+            // IN dropping early resets the timer
+            (260, &[0]),
+            (300, &[1]),
+            (350, &[0]),
+            (400, &[1]),
+            // PT is changed during timing
+            (430, &[1, 1]),
+            (450, &[1, 1]),
+            (460, &[1]),
+        ],
+        TIMER_VARIABLES,
+    );
+    assert_eq!(
+        r,
+        [
+            "FALSE TIME#0s",
+            "FALSE TIME#60ms",
+            "TRUE TIME#100ms",
+            "TRUE TIME#100ms",
+            "FALSE TIME#0s",
+            "FALSE TIME#0s",
+            "FALSE TIME#0s",
+            "FALSE TIME#0s",
+            "FALSE TIME#30ms",
+            "TRUE TIME#40ms",
+            "FALSE TIME#60ms",
+        ]
+    );
+}
+
+#[test]
+fn test_off_delay_timer() {
+    let main = timer_program("TOF");
+    let r = run_steps(
+        &[(&main, TIMER_BODY)],
+        &[
+            (0, &[0]),
+            (10, &[1]),
+            (20, &[0]),
+            (80, &[0]),
+            // IN rising before PT elapsed keeps Q
+            (90, &[1]),
+            (100, &[0]),
+            (200, &[0]),
+            (300, &[0]),
+        ],
+        TIMER_VARIABLES,
+    );
+    assert_eq!(
+        r,
+        [
+            "FALSE TIME#0s",
+            "TRUE TIME#0s",
+            "TRUE TIME#0s",
+            "TRUE TIME#60ms",
+            "TRUE TIME#0s",
+            "TRUE TIME#0s",
+            "FALSE TIME#100ms",
+            "FALSE TIME#100ms",
+        ]
+    );
+}
+
+#[test]
+fn test_pulse_timer() {
+    let main = timer_program("TP");
+    let r = run_steps(
+        &[(&main, TIMER_BODY)],
+        &[
+            (0, &[1]),
+            // IN dropping early doesn't stop the pulse
+            (10, &[0]),
+            // pulse can't be retriggered
+            (20, &[1]),
+            (100, &[1]),
+            (150, &[1]),
+            (160, &[0]),
+            (170, &[1]),
+            (200, &[1]),
+        ],
+        TIMER_VARIABLES,
+    );
+    assert_eq!(
+        r,
+        [
+            "TRUE TIME#0s",
+            "TRUE TIME#10ms",
+            "TRUE TIME#20ms",
+            "FALSE TIME#100ms",
+            "FALSE TIME#100ms",
+            "FALSE TIME#0s",
+            "TRUE TIME#0s",
+            "TRUE TIME#30ms",
+        ]
+    );
+}
+
+#[test]
+fn test_timer_time_source() {
+    let main = (
+        "PROGRAM main: VAR t: TON; now: TIME; END_VAR END_PROGRAM",
+        "t(IN := TRUE, PT := T#1s); now := STC_NOW();",
+    );
+    let mut interpreter = load_application(&[main]);
+    let now = Rc::new(RefCell::new(5_000_000_000u64));
+    let source = now.clone();
+    interpreter.set_time_source(move || *source.borrow());
+
+    interpreter.run_program("main").unwrap();
+    *now.borrow_mut() += 400_000_000;
+    interpreter.run_program("main").unwrap();
+    assert_eq!(report(&interpreter, TIMER_VARIABLES), "FALSE TIME#400ms");
+    assert_eq!(report(&interpreter, &["main.now"]), "TIME#5s400ms");
+
+    // simulated time is not used by timers
+    interpreter.set_time(100_000_000_000);
+    *now.borrow_mut() += 600_000_000;
+    interpreter.run_program("main").unwrap();
+    assert_eq!(report(&interpreter, TIMER_VARIABLES), "TRUE TIME#1s");
+}
+
+#[test]
+fn test_edge_detection_and_bistables() {
+    let main = (
+        "PROGRAM main: VAR r: R_TRIG; f: F_TRIG; sr: SR; rs: RS; END_VAR END_PROGRAM",
+        "r(CLK := input(0)); f(CLK := input(0)); \
+        sr(S1 := input(1), R := input(2)); rs(S := input(1), R1 := input(2));",
+    );
+    let r = run_steps(
+        &[main],
+        &[
+            (0, &[0, 0, 0]),
+            (1, &[1, 1, 0]),
+            (2, &[1, 0, 0]),
+            (3, &[0, 1, 1]),
+            (4, &[0, 0, 1]),
+            (5, &[1, 0, 0]),
+        ],
+        &["main.r.Q", "main.f.Q", "main.sr.Q1", "main.rs.Q1"],
+    );
+    assert_eq!(
+        r,
+        [
+            "FALSE FALSE FALSE FALSE",
+            "TRUE FALSE TRUE TRUE",
+            "FALSE FALSE TRUE TRUE",
+            "FALSE TRUE TRUE FALSE",
+            "FALSE FALSE FALSE FALSE",
+            "TRUE FALSE FALSE FALSE",
+        ]
+    );
+}
+
+#[test]
+fn test_counters() {
+    let main = (
+        "PROGRAM main: VAR up: CTU; down: CTD; ud: CTUD; END_VAR END_PROGRAM",
+        "up(CU := input(0), R := input(2), PV := 2); \
+        down(CD := input(1), LD := input(3), PV := -32767); \
+        ud(CU := input(0), CD := input(1), R := input(2), LD := input(3), PV := 32767);",
+    );
+    let r = run_steps(
+        &[main],
+        &[
+            (0, &[1, 0, 0, 1]),
+            // counting is on rising edges only
+            (1, &[1, 0]),
+            (2, &[0, 0]),
+            // saturation at the bounds of INT
+            (3, &[1, 0]),
+            (4, &[0, 1]),
+            (5, &[0, 0]),
+            (6, &[0, 1]),
+            (7, &[0, 0]),
+            // counting up and down together keeps CV
+            (8, &[1, 1]),
+            (9, &[0, 0, 1]),
+        ],
+        &[
+            "main.up.CV",
+            "main.up.Q",
+            "main.down.CV",
+            "main.down.Q",
+            "main.ud.CV",
+            "main.ud.QU",
+        ],
+    );
+    assert_eq!(
+        r,
+        [
+            "1 FALSE -32767 TRUE 32767 TRUE",
+            "1 FALSE -32767 TRUE 32767 TRUE",
+            "1 FALSE -32767 TRUE 32767 TRUE",
+            "2 TRUE -32767 TRUE 32767 TRUE",
+            "2 TRUE -32768 TRUE 32766 FALSE",
+            "2 TRUE -32768 TRUE 32766 FALSE",
+            "2 TRUE -32768 TRUE 32765 FALSE",
+            "2 TRUE -32768 TRUE 32765 FALSE",
+            "3 TRUE -32768 TRUE 32765 FALSE",
+            "0 FALSE -32768 TRUE 0 FALSE",
+        ]
+    );
+}
@@ -0,0 +1,394 @@
+use crate::backend::utils::{integer_width, is_float_type};
+use crate::parser::LiteralValue;
+use crate::prelude::*;
+
+use super::RuntimeError;
+
+use std::cmp::Ordering;
+use std::fmt::{self, Display, Formatter};
+
+/// STRING holds at most 80 bytes
+pub const STRING_LENGTH: usize = 80;
+
+/// Value of variable or expression, integers are always kept in the width of their type
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Bit(bool),
+    Bool(bool),
+    SInt(i8),
+    Byte(u8),
+    Int(i16),
+    UInt(u16),
+    DInt(i32),
+    UDInt(u32),
+    LInt(i64),
+    ULInt(u64),
+    Real(f32),
+    LReal(f64),
+    /// Duration in nanoseconds
+    Time(i64),
+    String(String),
+    /// Elements of all dimensions in row-major order
+    Array(Vec<Value>),
+    /// Members of struct, function block instance or program in declaration order
+    Struct(StructValue),
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct StructValue {
+    members: Vec<(StString, Value)>,
+}
+
+impl StructValue {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, name: StString, value: Value) {
+        self.members.push((name, value))
+    }
+
+    pub fn get(&self, name: &StString) -> Option<&Value> {
+        self.members.iter().find(|(x, _)| x == name).map(|(_, v)| v)
+    }
+
+    pub fn get_mut(&mut self, name: &StString) -> Option<&mut Value> {
+        self.members
+            .iter_mut()
+            .find(|(x, _)| x == name)
+            .map(|(_, v)| v)
+    }
+
+    pub fn members(&self) -> impl Iterator<Item = (&StString, &Value)> {
+        self.members.iter().map(|(k, v)| (k, v))
+    }
+}
+
+impl Value {
+    /// Default value of elementary type class, None for aggregates
+    pub fn zero(class: TypeClass) -> Option<Value> {
+        match class {
+            TypeClass::String => Some(Value::String(String::new())),
+            _ => Value::from_i64(0, class),
+        }
+    }
+
+    pub fn class(&self) -> TypeClass {
+        match self {
+            Value::Bit(_) => TypeClass::Bit,
+            Value::Bool(_) => TypeClass::Bool,
+            Value::SInt(_) => TypeClass::SInt,
+            Value::Byte(_) => TypeClass::Byte,
+            Value::Int(_) => TypeClass::Int,
+            Value::UInt(_) => TypeClass::UInt,
+            Value::DInt(_) => TypeClass::DInt,
+            Value::UDInt(_) => TypeClass::UDInt,
+            Value::LInt(_) => TypeClass::LInt,
+            Value::ULInt(_) => TypeClass::ULInt,
+            Value::Real(_) => TypeClass::Real,
+            Value::LReal(_) => TypeClass::LReal,
+            Value::Time(_) => TypeClass::Time,
+            Value::String(_) => TypeClass::String,
+            Value::Array(_) => TypeClass::Array,
+            Value::Struct(_) => TypeClass::Struct,
+        }
+    }
+
+    /// Integer wrapped into the width of type class, None for aggregates
+    pub fn from_i64(v: i64, class: TypeClass) -> Option<Value> {
+        Some(match class {
+            TypeClass::Bit => Value::Bit(v & 1 != 0),
+            TypeClass::Bool => Value::Bool(v != 0),
+            TypeClass::SInt => Value::SInt(v as i8),
+            TypeClass::Byte => Value::Byte(v as u8),
+            TypeClass::Int => Value::Int(v as i16),
+            TypeClass::UInt => Value::UInt(v as u16),
+            TypeClass::DInt => Value::DInt(v as i32),
+            TypeClass::UDInt => Value::UDInt(v as u32),
+            TypeClass::LInt => Value::LInt(v),
+            TypeClass::ULInt => Value::ULInt(v as u64),
+            TypeClass::Real => Value::Real(v as f32),
+            TypeClass::LReal => Value::LReal(v as f64),
+            TypeClass::Time => Value::Time(v),
+            _ => return None,
+        })
+    }
+
+    /// Float converted to type class, conversion to integers truncates and saturates in 32
+    /// or 64 bits, then wraps into the width of type
+    pub fn from_f64(v: f64, class: TypeClass) -> Option<Value> {
+        match class {
+            TypeClass::Real => Some(Value::Real(v as f32)),
+            TypeClass::LReal => Some(Value::LReal(v)),
+            TypeClass::Bool => Some(Value::Bool(v != 0.0)),
+            _ => {
+                let (bits, signed) = integer_width(class)?;
+                let v = match (bits, signed) {
+                    (64, true) => v as i64,
+                    (64, false) => v as u64 as i64,
+                    (_, true) => v as i32 as i64,
+                    (_, false) => v as u32 as i64,
+                };
+                Value::from_i64(v, class)
+            }
+        }
+    }
+
+    /// Integer value, unsigned 64 bits integers are reinterpreted
+    pub fn as_i64(&self) -> Option<i64> {
+        Some(match *self {
+            Value::Bit(v) | Value::Bool(v) => v as i64,
+            Value::SInt(v) => v as i64,
+            Value::Byte(v) => v as i64,
+            Value::Int(v) => v as i64,
+            Value::UInt(v) => v as i64,
+            Value::DInt(v) => v as i64,
+            Value::UDInt(v) => v as i64,
+            Value::LInt(v) | Value::Time(v) => v,
+            Value::ULInt(v) => v as i64,
+            Value::Real(v) => v as i64,
+            Value::LReal(v) => v as i64,
+            _ => return None,
+        })
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match *self {
+            Value::Real(v) => Some(v as f64),
+            Value::LReal(v) => Some(v),
+            Value::ULInt(v) => Some(v as f64),
+            _ => self.as_i64().map(|x| x as f64),
+        }
+    }
+
+    /// Any non-zero elementary value is true
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Value::Real(_) | Value::LReal(_) => self.as_f64().map(|x| x != 0.0),
+            _ => self.as_i64().map(|x| x != 0),
+        }
+    }
+
+    /// Convert elementary value to type class, aggregates are not converted
+    pub fn convert(&self, class: TypeClass) -> Value {
+        if self.class() == class {
+            return self.clone();
+        }
+
+        let converted = match self {
+            Value::Real(_) | Value::LReal(_) => Value::from_f64(self.as_f64().unwrap(), class),
+            Value::ULInt(v) if is_float_type(class) => Value::from_f64(*v as f64, class),
+            Value::String(_) | Value::Array(_) | Value::Struct(_) => None,
+            _ if matches!(class, TypeClass::Bool) => self.as_bool().map(Value::Bool),
+            _ => Value::from_i64(self.as_i64().unwrap(), class),
+        };
+
+        converted.unwrap_or_else(|| self.clone())
+    }
+
+    /// Assign value to place holding this value, elementary values are converted to type
+    /// of place and strings are truncated
+    pub fn assign(&mut self, value: Value) {
+        *self = match (&*self, value) {
+            (Value::String(_), Value::String(mut s)) => {
+                truncate_string(&mut s);
+                Value::String(s)
+            }
+            (Value::Array(_) | Value::Struct(_) | Value::String(_), v) => v,
+            (current, v) => v.convert(current.class()),
+        }
+    }
+
+    pub fn unary(op: Operator, v: &Value) -> Result<Value, RuntimeError> {
+        let class = v.class();
+        let result = match (op, v) {
+            (Operator::Not, Value::Bool(b)) => Some(Value::Bool(!b)),
+            (Operator::Not, _) if integer_width(class).is_some() => {
+                Value::from_i64(!v.as_i64().unwrap(), class)
+            }
+            (Operator::Minus, Value::Real(v)) => Some(Value::Real(-v)),
+            (Operator::Minus, Value::LReal(v)) => Some(Value::LReal(-v)),
+            (Operator::Minus, _) if integer_width(class).is_some() => {
+                Value::from_i64(v.as_i64().unwrap().wrapping_neg(), class)
+            }
+            _ => None,
+        };
+
+        result.ok_or_else(|| RuntimeError::InvalidOperation(format!("{} {}", op, v)))
+    }
+
+    /// Binary operator on two values of the same type class
+    pub fn binary(op: Operator, a: &Value, b: &Value) -> Result<Value, RuntimeError> {
+        let class = a.class();
+        let invalid = || RuntimeError::InvalidOperation(format!("{} {} {}", a, op, b));
+
+        if let Some(ordering) = compare_operator(op) {
+            let ord = match (a, b) {
+                (Value::String(a), Value::String(b)) => Some(a.as_bytes().cmp(b.as_bytes())),
+                (Value::Real(_) | Value::LReal(_), _) => a.as_f64().partial_cmp(&b.as_f64()),
+                (Value::ULInt(a), Value::ULInt(b)) => Some(a.cmp(b)),
+                _ if a.as_i64().is_some() => Some(a.as_i64().cmp(&b.as_i64())),
+                _ => return Err(invalid()),
+            };
+
+            // NaN is not equal to anything
+            return Ok(Value::Bool(match ord {
+                Some(ord) => ordering(ord),
+                None => matches!(op, Operator::NotEqual),
+            }));
+        }
+
+        let result = match (a, b) {
+            (Value::Real(_) | Value::LReal(_), _) => {
+                let (x, y) = (a.as_f64().unwrap(), b.as_f64().unwrap());
+                match op {
+                    // REAL is calculated in single precision
+                    _ if matches!(class, TypeClass::Real) && !matches!(op, Operator::Power) => {
+                        let (x, y) = (x as f32, y as f32);
+                        let v = match op {
+                            Operator::Plus => x + y,
+                            Operator::Minus => x - y,
+                            Operator::Multiply => x * y,
+                            Operator::Division => x / y,
+                            Operator::Mod => x % y,
+                            _ => return Err(invalid()),
+                        };
+                        Some(Value::Real(v))
+                    }
+                    Operator::Plus => Some(Value::LReal(x + y)),
+                    Operator::Minus => Some(Value::LReal(x - y)),
+                    Operator::Multiply => Some(Value::LReal(x * y)),
+                    Operator::Division => Some(Value::LReal(x / y)),
+                    Operator::Mod => Some(Value::LReal(x % y)),
+                    Operator::Power => Value::from_f64(x.powf(y), class),
+                    _ => return Err(invalid()),
+                }
+            }
+            (Value::Bool(x), Value::Bool(y)) => match op {
+                Operator::BitAnd => Some(Value::Bool(*x & *y)),
+                Operator::BitOr => Some(Value::Bool(*x | *y)),
+                Operator::Xor => Some(Value::Bool(*x ^ *y)),
+                _ => return Err(invalid()),
+            },
+            _ => {
+                let (_, signed) = integer_width(class).ok_or_else(invalid)?;
+                let (x, y) = (a.as_i64().unwrap(), b.as_i64().unwrap());
+                let (ux, uy) = (x as u64, y as u64);
+
+                let v = match op {
+                    Operator::Plus => x.wrapping_add(y),
+                    Operator::Minus => x.wrapping_sub(y),
+                    Operator::Multiply => x.wrapping_mul(y),
+                    Operator::Division | Operator::Mod if y == 0 => {
+                        return Err(RuntimeError::DivisionByZero)
+                    }
+                    Operator::Division if signed => x.wrapping_div(y),
+                    Operator::Division => (ux / uy) as i64,
+                    Operator::Mod if signed => x.wrapping_rem(y),
+                    Operator::Mod => (ux % uy) as i64,
+                    Operator::Power if signed => pow_i64(x, y),
+                    Operator::Power => pow_u64(ux, uy) as i64,
+                    Operator::BitAnd => x & y,
+                    Operator::BitOr => x | y,
+                    Operator::Xor => x ^ y,
+                    _ => return Err(invalid()),
+                };
+                Value::from_i64(v, class)
+            }
+        };
+
+        result.ok_or_else(invalid)
+    }
+}
+
+fn compare_operator(op: Operator) -> Option<fn(Ordering) -> bool> {
+    Some(match op {
+        Operator::Less => Ordering::is_lt,
+        Operator::LessEqual => Ordering::is_le,
+        Operator::Greater => Ordering::is_gt,
+        Operator::GreaterEqual => Ordering::is_ge,
+        Operator::Equal => Ordering::is_eq,
+        Operator::NotEqual => Ordering::is_ne,
+        _ => return None,
+    })
+}
+
+fn pow_u64(mut base: u64, mut exp: u64) -> u64 {
+    let mut r: u64 = 1;
+    while exp > 0 {
+        if exp & 1 != 0 {
+            r = r.wrapping_mul(base);
+        }
+        base = base.wrapping_mul(base);
+        exp >>= 1;
+    }
+
+    r
+}
+
+/// Negative exponent gives 0 unless base is 1 or -1
+fn pow_i64(base: i64, exp: i64) -> i64 {
+    match (base, exp) {
+        (1, _) if exp < 0 => 1,
+        (-1, _) if exp < 0 => {
+            if exp & 1 != 0 {
+                -1
+            } else {
+                1
+            }
+        }
+        _ if exp < 0 => 0,
+        _ => pow_u64(base as u64, exp as u64) as i64,
+    }
+}
+
+/// Truncate string to the length of STRING at char boundary
+fn truncate_string(s: &mut String) {
+    if s.len() > STRING_LENGTH {
+        let mut len = STRING_LENGTH;
+        while !s.is_char_boundary(len) {
+            len -= 1;
+        }
+        s.truncate(len);
+    }
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Bit(v) | Value::Bool(v) => f.write_str(if *v { "TRUE" } else { "FALSE" }),
+            Value::SInt(v) => write!(f, "{}", v),
+            Value::Byte(v) => write!(f, "{}", v),
+            Value::Int(v) => write!(f, "{}", v),
+            Value::UInt(v) => write!(f, "{}", v),
+            Value::DInt(v) => write!(f, "{}", v),
+            Value::UDInt(v) => write!(f, "{}", v),
+            Value::LInt(v) => write!(f, "{}", v),
+            Value::ULInt(v) => write!(f, "{}", v),
+            Value::Real(v) => write!(f, "{}", v),
+            Value::LReal(v) => write!(f, "{}", v),
+            Value::Time(v) => write!(f, "{}", LiteralValue::Time(*v)),
+            Value::String(s) => write!(f, "{:?}", s),
+            Value::Array(elements) => {
+                f.write_str("[")?;
+                for (idx, v) in elements.iter().enumerate() {
+                    if idx > 0 {
+                        f.write_str(", ")?;
+                    }
+                    write!(f, "{}", v)?;
+                }
+                f.write_str("]")
+            }
+            Value::Struct(st) => {
+                f.write_str("(")?;
+                for (idx, (name, v)) in st.members().enumerate() {
+                    if idx > 0 {
+                        f.write_str(", ")?;
+                    }
+                    write!(f, "{} := {}", name, v)?;
+                }
+                f.write_str(")")
+            }
+        }
+    }
+}
@@ -4,8 +4,11 @@
 pub mod analysis;
 pub mod ast;
 pub mod backend;
+pub mod builtin;
 pub mod context;
+pub mod interpreter;
 pub mod parser;
+pub mod scheduler;
 pub mod serde;
 pub mod utils;
 
@@ -15,6 +15,10 @@ use std::sync::Arc;
 /// Parser functions result
 type ParseResult<T> = Result<Option<T>, ParseError>;
 
+/// Nested expressions are parsed recursively, deeper nesting is an error instead of
+/// overflow of stack
+const MAX_EXPRESSION_DEPTH: usize = 128;
+
 /// declaration parser wrapper
 #[derive(Default)]
 pub struct DefaultParser {}
@@ -32,12 +36,20 @@ impl ParserTrait for DefaultParser {
     }
 
     fn parse_pou(&self, lexer: &mut StLexer) -> Result<(Declaration, Statement), ParseError> {
-        todo!()
+        let mut parser = DefaultParserImpl::new(lexer.into_iter());
+        let decl = parser.parse_declaration()?;
+        let body = parser.parse_function()?;
+
+        Ok((decl, body))
     }
 
     #[inline]
     fn parse_decl(&self, lexer: &mut StLexer) -> Result<Declaration, ParseError> {
-        DefaultParserImpl::new(lexer.into_iter()).parse_declaration()
+        let mut parser = DefaultParserImpl::new(lexer.into_iter());
+        let decl = parser.parse_declaration()?;
+        parser.except_end()?;
+
+        Ok(decl)
     }
 
     #[inline]
@@ -47,21 +59,22 @@ impl ParserTrait for DefaultParser {
 
     #[inline]
     fn parse_literal(&self, lexer: &mut StLexer) -> Result<LiteralExpression, ParseError> {
-        let parse_result = DefaultParserImpl::new(lexer.into_iter()).parse_literal_expr()?;
-        if let Some(expr) = parse_result {
-            return Ok(expr);
-        }
+        let mut parser = DefaultParserImpl::new(lexer.into_iter());
+        let Some(literal) = parser.parse_literal_expr()? else {
+            return Err(parser.unexpected_token());
+        };
+        parser.except_end()?;
 
-        // TODO: Parse no result
-        Err(ParseError::InvalidToken(Location { mark: 0, offset: 0 }))
+        Ok(literal)
     }
 
     #[inline]
     fn parse_expression(&self, lexer: &mut StLexer) -> Result<Expression, ParseError> {
-        match DefaultParserImpl::new(lexer.into_iter()).parse_expression()? {
-            Some(expr) => Ok(expr),
-            None => Err(ParseError::InvalidToken(Location { mark: 0, offset: 0 })),
-        }
+        let mut parser = DefaultParserImpl::new(lexer.into_iter());
+        let expr = parser.expect_expression()?;
+        parser.except_end()?;
+
+        Ok(expr)
     }
 }
 
@@ -72,6 +85,8 @@ struct DefaultParserImpl<I: Iterator<Item = LexerResult>> {
     next: usize,
     /// save all tokens, because we need to backtracking
     tokens: SmallVec<[Token; 1024]>,
+    /// depth of expressions being parsed
+    expression_depth: usize,
 }
 
 impl<I: Iterator<Item = LexerResult>> DefaultParserImpl<I> {
@@ -80,6 +95,7 @@ impl<I: Iterator<Item = LexerResult>> DefaultParserImpl<I> {
             lexer,
             next: 0,
             tokens: smallvec![],
+            expression_depth: 0,
         }
     }
 
@@ -127,19 +143,6 @@ impl<I: Iterator<Item = LexerResult>> DefaultParserImpl<I> {
         r
     }
 
-    /// test has next token
-    fn has_next(&mut self) -> bool {
-        // fill buff
-        while self.next >= self.tokens.len() {
-            match self.lexer.next() {
-                Some(Ok(item)) => self.tokens.push(item),
-                _ => return false,
-            }
-        }
-
-        true
-    }
-
     #[inline]
     fn next_token(&mut self) -> Result<&Token, ParseError> {
         match self.next()? {
@@ -155,29 +158,98 @@ impl<I: Iterator<Item = LexerResult>> DefaultParserImpl<I> {
 
     /// parse a function
     fn parse_function(&mut self) -> Result<Statement, ParseError> {
-        let stmts = match self.parse_statement_list()? {
-            Some(stmts) => stmts,
-            // TODO: error type
-            _ => return Err(ParseError::UnexpectedEnd),
-        };
+        let stmts = self.expect_statement_list()?;
+        self.except_end()?;
+
+        Ok(stmts)
+    }
 
-        // ensure file end
+    /// Ensure all tokens are taken
+    fn except_end(&mut self) -> Result<(), ParseError> {
         match self.next()? {
-            None => {}
-            Some(tok) => return Err(ParseError::InvalidTokenAt(format!("{:?}", tok))),
+            None => Ok(()),
+            Some(tok) => Err(ParseError::UnexpectedToken(tok.location, vec![])),
         }
-
-        Ok(stmts)
     }
 
     /// parse a declaration
     fn parse_declaration(&mut self) -> Result<Declaration, ParseError> {
         let pos = self.next;
+
+        // 'NAMESPACE' name declaration 'END_NAMESPACE'
+        if matches!(self.next_kind()?, TokenKind::Namespace) {
+            let ident = self.except_identifier()?;
+            let namespace = self.parse_qualified_name(ident)?;
+            let mut decl = self.parse_declaration()?;
+            let _ = self.except_one(TokenKind::EndNamespace)?;
+
+            decl.set_namespace(namespace);
+            return Ok(decl);
+        }
+
+        // 'USING' names ';'
+        self.next = pos;
+        let mut using = vec![];
+        loop {
+            let pos = self.next;
+            if !matches!(self.next_kind()?, TokenKind::Using) {
+                self.next = pos;
+                break;
+            }
+
+            loop {
+                let ident = self.except_identifier()?;
+                using.push(self.parse_qualified_name(ident)?);
+                if matches!(
+                    self.except_one_of(&[TokenKind::Comma, TokenKind::Semicolon])?
+                        .kind,
+                    TokenKind::Semicolon
+                ) {
+                    break;
+                }
+            }
+        }
+
+        let pragmas = self.parse_pragmas()?;
+        let mut decl = self.parse_single_declaration()?;
+        for namespace in using {
+            decl.add_using(namespace);
+        }
+        apply_pragmas(&mut decl, &pragmas);
+
+        Ok(decl)
+    }
+
+    /// Pragmas before declaration, variable or statement
+    fn parse_pragmas(&mut self) -> Result<Vec<String>, ParseError> {
+        let mut pragmas = vec![];
+        loop {
+            let pos = self.next;
+            match self.next()? {
+                Some(Token {
+                    kind: TokenKind::Pragma(pragma),
+                    ..
+                }) => pragmas.push(pragma.clone()),
+                _ => {
+                    self.next = pos;
+                    return Ok(pragmas);
+                }
+            }
+        }
+    }
+
+    fn parse_single_declaration(&mut self) -> Result<Declaration, ParseError> {
+        let pos = self.next;
         let except_token = self.except_one_of(&[
             TokenKind::Type,
             TokenKind::Function,
             TokenKind::Program,
+            TokenKind::FunctionBlock,
+            TokenKind::Method,
+            TokenKind::Property,
+            TokenKind::Action,
             TokenKind::VarGlobal,
+            TokenKind::Configuration,
         ])?;
 
         match except_token.kind.clone() {
@@ -203,11 +275,39 @@ impl<I: Iterator<Item = LexerResult>> DefaultParserImpl<I> {
                 Ok(type_decl.unwrap())
             }
 
+            // function block declare
+            TokenKind::FunctionBlock => {
+                let name = self.except_identifier()?;
+
+                // optional ':'
+                let pos = self.next;
+                if !matches!(self.next_kind()?, TokenKind::Colon) {
+                    self.next = pos;
+                }
+
+                let vars = self.parse_variable_declare_factor()?;
+                let _ = self.except_one_of(&[TokenKind::EndFunctionBlock])?;
+
+                Ok(Declaration::fun(Box::new(FunctionDeclare::new(
+                    name,
+                    DeclareClass::FunctionBlock,
+                    None,
+                    vars.unwrap_or(smallvec![]),
+                ))))
+            }
+
             // functions declare
             tok @ TokenKind::Function | tok @ TokenKind::Program => {
-                // name ':'
+                // name ':', ':' is optional for programs
                 let name = self.except_identifier()?;
-                let _ = self.except_one_of(&[TokenKind::Colon])?;
+                if matches!(tok, TokenKind::Function) {
+                    let _ = self.except_one_of(&[TokenKind::Colon])?;
+                } else {
+                    let pos = self.next;
+                    if !matches!(self.next_kind()?, TokenKind::Colon) {
+                        self.next = pos;
+                    }
+                }
 
                 // match possible return type
                 let ret_type = self.parse_type()?;
@@ -231,6 +331,66 @@ impl<I: Iterator<Item = LexerResult>> DefaultParserImpl<I> {
                     vars.unwrap_or(smallvec![]),
                 ))))
             }
+
+            // method declare, return type is optional
+            TokenKind::Method => {
+                let name = self.except_identifier()?;
+
+                let pos = self.next;
+                let ret_type = if matches!(self.next_kind()?, TokenKind::Colon) {
+                    self.parse_type()?
+                } else {
+                    self.next = pos;
+                    None
+                };
+
+                let vars = self.parse_variable_declare_factor()?;
+                let _ = self.except_one(TokenKind::EndMethod)?;
+
+                Ok(Declaration::fun(Box::new(FunctionDeclare::new(
+                    name,
+                    DeclareClass::Method,
+                    ret_type,
+                    vars.unwrap_or(smallvec![]),
+                ))))
+            }
+
+            // property declare, 'name : type'
+            TokenKind::Property => {
+                let name = self.except_identifier()?;
+                let _ = self.except_one(TokenKind::Colon)?;
+                let ty = match self.parse_type()? {
+                    Some(ty) => ty,
+                    // TODO: expect type
+                    _ => return Err(ParseError::UnexpectedEnd),
+                };
+
+                let vars = self.parse_variable_declare_factor()?;
+                let _ = self.except_one(TokenKind::EndProperty)?;
+
+                Ok(Declaration::fun(Box::new(FunctionDeclare::new(
+                    name,
+                    DeclareClass::Property,
+                    Some(ty),
+                    vars.unwrap_or(smallvec![]),
+                ))))
+            }
+
+            // action declare, actions have no variables of their own
+            TokenKind::Action => {
+                let name = self.except_identifier()?;
+                let _ = self.except_one(TokenKind::EndAction)?;
+
+                Ok(Declaration::fun(Box::new(FunctionDeclare::new(
+                    name,
+                    DeclareClass::Action,
+                    None,
+                    smallvec![],
+                ))))
+            }
+
+            // configuration declare
+            TokenKind::Configuration => self.expect_configuration_declaration(),
             _ => unreachable!(),
         }
     }
@@ -239,7 +399,10 @@ impl<I: Iterator<Item = LexerResult>> DefaultParserImpl<I> {
         let tok = self.next_token()?;
         match &tok.kind {
             TokenKind::Identifier(ident) => Ok(ident.clone()),
-            _ => Err(ParseError::InvalidToken(Location { mark: 0, offset: 0 })),
+            _ => Err(ParseError::UnexpectedToken(
+                tok.location,
+                vec!["IDENTIFIER".to_owned()],
+            )),
         }
     }
 
@@ -269,10 +432,22 @@ impl<I: Iterator<Item = LexerResult>> DefaultParserImpl<I> {
             }
             TokenKind::Bit => Ok(Some(BitType::new_type())),
             TokenKind::Bool => Ok(Some(BoolType::new_type())),
-            TokenKind::Byte => Ok(Some(ByteType::new_type())),
-            TokenKind::Int => Ok(Some(IntType::new_type())),
+            TokenKind::Byte => self.parse_subrange_type(ByteType::new_type()),
+            TokenKind::Int => self.parse_subrange_type(IntType::new_type()),
             TokenKind::Real => Ok(Some(RealType::new_type())),
-            TokenKind::Identifier(ident) => Ok(Some(UnknownType::from_name(ident.clone()).into())),
+            TokenKind::SInt => self.parse_subrange_type(SIntType::new_type()),
+            TokenKind::UInt => self.parse_subrange_type(UIntType::new_type()),
+            TokenKind::DInt => self.parse_subrange_type(DIntType::new_type()),
+            TokenKind::UDInt => self.parse_subrange_type(UDIntType::new_type()),
+            TokenKind::LInt => self.parse_subrange_type(LIntType::new_type()),
+            TokenKind::ULInt => self.parse_subrange_type(ULIntType::new_type()),
+            TokenKind::LReal => Ok(Some(LRealType::new_type())),
+            TokenKind::Time => Ok(Some(TimeType::new_type())),
+            TokenKind::String => Ok(Some(StringType::new_type())),
+            TokenKind::Identifier(ident) => {
+                let ident = ident.clone();
+                self.parse_user_type(ident)
+            }
             _ => {
                 self.next = pos;
                 Ok(None)
@@ -280,6 +455,43 @@ impl<I: Iterator<Item = LexerResult>> DefaultParserImpl<I> {
         }
     }
 
+    /// Parse user type, which can be qualified by namespace of library, like: Lib.FB
+    fn parse_user_type(&mut self, ident: StString) -> ParseResult<Type> {
+        let name = self.parse_qualified_name(ident)?;
+        Ok(Some(Type::from_identifier(name)))
+    }
+
+    /// Parse the rest of qualified name like: Motion.Axis.MoveAbs
+    fn parse_qualified_name(&mut self, ident: StString) -> Result<StString, ParseError> {
+        let mut name = ident;
+        loop {
+            let pos = self.next;
+            if !matches!(self.next_kind(), Ok(TokenKind::DotAccess)) {
+                self.next = pos;
+                return Ok(name);
+            }
+
+            let ident = self.except_identifier()?;
+            name = StString::new(format!("{}.{}", name, ident));
+        }
+    }
+
+    /// Parse optional range of integer type, like: INT(0..100)
+    fn parse_subrange_type(&mut self, base: Type) -> ParseResult<Type> {
+        let pos = self.next;
+        if !matches!(*self.next_kind()?, TokenKind::LeftParentheses) {
+            self.next = pos;
+            return Ok(Some(base));
+        }
+
+        let Some(range) = self.parse_range_expression()? else {
+            return Err(ParseError::UnexpectedEnd);
+        };
+        let _ = self.except_one(TokenKind::RightParentheses)?;
+
+        Ok(Some(SubrangeType::new(base, range).into()))
+    }
+
     /// Parse range expr like: a..b
     fn parse_range_expression(&mut self) -> ParseResult<RangeExpression> {
         let lower = match self.parse_expression()? {
@@ -358,7 +570,9 @@ impl<I: Iterator<Item = LexerResult>> DefaultParserImpl<I> {
         }
 
         // enum decl
-        let tok = self.next_kind()?;
+        let tok = self.next_token()?;
+        let location = tok.location;
+        let tok = &tok.kind;
         if matches!(tok, TokenKind::LeftParentheses) {
             let mut fields = smallvec![];
 
@@ -400,10 +614,7 @@ impl<I: Iterator<Item = LexerResult>> DefaultParserImpl<I> {
             return Ok(Some(Declaration::new_struct(name, fields)));
         }
 
-        Err(ParseError::UnexpectedToken(
-            Location { mark: 0, offset: 0 },
-            vec![format!("{:?}", tok)],
-        ))
+        Err(ParseError::UnexpectedToken(location, vec![]))
     }
 
     fn parse_enum_field_decl(&mut self) -> ParseResult<Arc<Variable>> {
@@ -411,10 +622,10 @@ impl<I: Iterator<Item = LexerResult>> DefaultParserImpl<I> {
         let pos = self.next;
 
         if matches!(*self.next_kind()?, TokenKind::Assign) {
-            if let TokenKind::Literal(literal) = self.next_kind()? {
+            if let Some(value) = self.parse_expression()? {
                 return Ok(Some(Arc::new(Variable::with_initial(
                     field_name,
-                    Box::new(Expression::new_literal(literal.clone())),
+                    Box::new(value),
                 ))));
             }
         }
@@ -423,30 +634,24 @@ impl<I: Iterator<Item = LexerResult>> DefaultParserImpl<I> {
         Ok(Some(Arc::new(Variable::new(field_name))))
     }
 
+    /// GlobalVarDeclareFactor: GlobalVarDeclGroup+
     fn parse_global_variable_declare_factor(&mut self) -> ParseResult<SmallVec8<Arc<Variable>>> {
-        let mut v = smallvec![];
-        loop {
-            if !self.has_next() {
-                break;
-            }
+        let Some(mut v) = self.parse_global_variable_group()? else {
+            return Ok(None);
+        };
 
-            if let Some(mut x) = self.parse_global_variable_group()? {
-                v.append(&mut x);
-            } else {
-                panic!()
-            }
+        while let Some(mut x) = self.parse_global_variable_group()? {
+            v.append(&mut x);
         }
 
-        // TODO: should be fail if 'v' is empty
         Ok(Some(v))
     }
 
     fn parse_global_variable_group(&mut self) -> ParseResult<SmallVec8<Arc<Variable>>> {
-        let pos = self.next;
-        let tok = self.next_kind()?;
-
-        if !matches!(*tok, TokenKind::VarGlobal) {
-            self.next = pos;
+        if self
+            .next_if(|tok| matches!(tok, TokenKind::VarGlobal).then_some(()))?
+            .is_none()
+        {
             return Ok(None);
         }
 
@@ -460,6 +665,124 @@ impl<I: Iterator<Item = LexerResult>> DefaultParserImpl<I> {
         )))
     }
 
+    /// ConfigurationDecl: "CONFIGURATION" IDENTIFIER GlobalVarDeclareFactor? ResourceDecl+ "END_CONFIGURATION"
+    ///
+    /// 'CONFIGURATION' token already taken
+    fn expect_configuration_declaration(&mut self) -> Result<Declaration, ParseError> {
+        let name = self.except_identifier()?;
+        let variables = self
+            .parse_global_variable_declare_factor()?
+            .unwrap_or(smallvec![]);
+
+        let mut resources = vec![self.except_resource_declaration()?];
+        while self
+            .next_if(|tok| matches!(tok, TokenKind::EndConfiguration).then_some(()))?
+            .is_none()
+        {
+            resources.push(self.except_resource_declaration()?);
+        }
+
+        Ok(Declaration::configuration(Box::new(
+            ConfigurationDeclare::new(name, variables, resources),
+        )))
+    }
+
+    /// ResourceDecl: "RESOURCE" IDENTIFIER "ON" IDENTIFIER TaskDecl* ProgramInstanceDecl* "END_RESOURCE"
+    fn except_resource_declaration(&mut self) -> Result<ResourceDeclare, ParseError> {
+        self.except_keyword("RESOURCE")?;
+        let name = self.except_identifier()?;
+        self.except_keyword("ON")?;
+        let target = self.except_identifier()?;
+
+        let mut tasks = vec![];
+        while self.next_keyword("TASK")? {
+            tasks.push(self.expect_task_declaration()?);
+        }
+
+        let mut programs = vec![];
+        while self
+            .next_if(|tok| matches!(tok, TokenKind::Program).then_some(()))?
+            .is_some()
+        {
+            programs.push(self.expect_program_instance_declaration()?);
+        }
+
+        let _ = self.except_one(TokenKind::EndResource)?;
+        Ok(ResourceDeclare::new(name, target, tasks, programs))
+    }
+
+    /// TaskDecl: "TASK" IDENTIFIER "(" TaskParameter, .. ")" ";"
+    ///
+    /// TaskParameter: IDENTIFIER ":=" BitOrExpr
+    ///
+    /// 'TASK' token already taken
+    fn expect_task_declaration(&mut self) -> Result<TaskDeclare, ParseError> {
+        let name = self.except_identifier()?;
+        let _ = self.except_one(TokenKind::LeftParentheses)?;
+
+        let mut parameters = smallvec![];
+        loop {
+            let parameter = self.except_identifier()?;
+            let _ = self.except_one(TokenKind::Assign)?;
+            let value = match self.parse_bitor_expression()? {
+                Some(value) => value,
+                None => return Err(self.unexpected_token()),
+            };
+            parameters.push((parameter, Box::new(value)));
+
+            let tok = self.except_one_of(&[TokenKind::Comma, TokenKind::RightParentheses])?;
+            if matches!(tok.kind, TokenKind::RightParentheses) {
+                break;
+            }
+        }
+
+        let _ = self.except_one(TokenKind::Semicolon)?;
+        Ok(TaskDeclare::new(name, parameters))
+    }
+
+    /// ProgramInstanceDecl: "PROGRAM" IDENTIFIER ("WITH" IDENTIFIER)? ":" IDENTIFIER ";"
+    ///
+    /// 'PROGRAM' token already taken
+    fn expect_program_instance_declaration(
+        &mut self,
+    ) -> Result<ProgramInstanceDeclare, ParseError> {
+        let name = self.except_identifier()?;
+        let task = if self.next_keyword("WITH")? {
+            Some(self.except_identifier()?)
+        } else {
+            None
+        };
+
+        let _ = self.except_one(TokenKind::Colon)?;
+        let program = self.except_identifier()?;
+        let _ = self.except_one(TokenKind::Semicolon)?;
+
+        Ok(ProgramInstanceDeclare::new(name, task, program))
+    }
+
+    /// Take the next identifier if it's the contextual keyword, like 'TASK' in resource.
+    /// Contextual keywords are only reserved in configuration, so they are lexed as identifiers
+    fn next_keyword(&mut self, keyword: &str) -> Result<bool, ParseError> {
+        let tok = self.next_if(|tok| match tok {
+            TokenKind::Identifier(ident) if *ident == *keyword => Some(()),
+            _ => None,
+        })?;
+
+        Ok(tok.is_some())
+    }
+
+    fn except_keyword(&mut self, keyword: &str) -> Result<(), ParseError> {
+        if self.next_keyword(keyword)? {
+            return Ok(());
+        }
+
+        let tok = self.next_token()?;
+        Err(ParseError::UnexpectedToken(
+            tok.location,
+            vec![keyword.to_owned()],
+        ))
+    }
+
     fn parse_variable_declare_factor(&mut self) -> ParseResult<SmallVec8<Arc<Variable>>> {
         let mut v = SmallVec8::new();
         while let Some(mut x) = self.parse_variable_group()? {
@@ -508,6 +831,7 @@ impl<I: Iterator<Item = LexerResult>> DefaultParserImpl<I> {
         let x = match *self.next_kind()? {
             TokenKind::Retain => VariableFlags::RETAIN,
             TokenKind::Persistent => VariableFlags::PERSISTENT,
+            TokenKind::Constant => return Ok(Some(VariableFlags::CONST)),
             _ => {
                 self.next = pos;
                 return Ok(None);
@@ -539,6 +863,7 @@ impl<I: Iterator<Item = LexerResult>> DefaultParserImpl<I> {
 
     fn expect_single_line_variable_declare(&mut self) -> ParseResult<SmallVec8<Arc<Variable>>> {
         let pos = self.next;
+        let pragmas = self.parse_pragmas()?;
         let mut name_list = match self.next_kind()? {
             TokenKind::Identifier(s) => smallvec![s.to_owned()],
             _ => {
@@ -563,9 +888,34 @@ impl<I: Iterator<Item = LexerResult>> DefaultParserImpl<I> {
             // TODO: expect type
             _ => return Err(ParseError::UnexpectedEnd),
         };
+
+        // optional initial value
+        let pos = self.next;
+        let initial = if matches!(self.next_kind()?, TokenKind::Assign) {
+            match self.parse_bitor_expression()? {
+                Some(expr) => Some(expr),
+                // TODO: expect expression
+                _ => return Err(ParseError::UnexpectedEnd),
+            }
+        } else {
+            self.next = pos;
+            None
+        };
         let _ = self.except_one_of(&[TokenKind::Semicolon])?;
 
-        Ok(Some(Variable::multiple_variable_with_type(name_list, ty)))
+        let mut variables = match initial {
+            Some(initial) if name_list.len() == 1 => smallvec![Arc::new(
+                Variable::with_type_initial(name_list.pop().unwrap(), ty, Box::new(initial))
+            )],
+            // TODO: initial value for multiple variables
+            Some(_) => return Err(ParseError::UnexpectedEnd),
+            None => Variable::multiple_variable_with_type(name_list, ty),
+        };
+        for variable in variables.iter_mut() {
+            apply_pragmas(Arc::get_mut(variable).unwrap(), &pragmas);
+        }
+
+        Ok(Some(variables))
     }
 
     fn parse_statement_list(&mut self) -> ParseResult<Statement> {
@@ -604,60 +954,38 @@ impl<I: Iterator<Item = LexerResult>> DefaultParserImpl<I> {
     /// parse single statement
     fn parse_statement(&mut self) -> ParseResult<Statement> {
         let pos = self.next;
+        let pragmas = self.parse_pragmas()?;
+        let stmt_pos = self.next;
         let tok = self.next_kind()?;
 
         // IF statement
         if matches!(tok, TokenKind::If) {
-            let if_stmt = self.expect_if_statement()?;
+            let mut if_stmt = self.expect_if_statement()?;
+            apply_pragmas(&mut if_stmt, &pragmas);
             return Ok(Some(if_stmt));
         }
 
-        self.next = pos;
-        if let Some(expr) = self.parse_expr_statement()? {
+        self.next = stmt_pos;
+        if let Some(mut expr) = self.parse_expr_statement()? {
+            apply_pragmas(&mut expr, &pragmas);
             return Ok(Some(expr));
         }
 
-        // let pos = self.next;
-        // if let Ok(decl) = self.parse_declaration() {
-        //     return Ok(Some(Statement::decl(Box::new(decl))));
-        // }
-        //
-        // self.next = pos;
+        // pragmas must be followed by a statement
+        if !pragmas.is_empty() {
+            return Err(self.unexpected_token());
+        }
+
+        self.next = pos;
         Ok(None)
     }
 
-    // fn match_val<T, F: FnOnce(Option<T>, T) -> T>(
-    //     &mut self,
-    //     val: Option<T>,
-    //     fallback_pos: usize,
-    //     fallback_value: Option<T>,
-    //     success_factor: F,
-    // ) -> ParseResult<T> {
-    //     match val {
-    //         Some(v) => Ok(Some(success_factor(fallback_value, v))),
-    //         _ => {
-    //             self.next = fallback_pos;
-    //             Ok(fallback_value)
-    //         }
-    //     }
-    // }
-
     // 'IF' token already taken
     fn expect_if_statement(&mut self) -> Result<Statement, ParseError> {
         let if_position = self.start_location(self.next - 1);
-        let cond = match self.parse_expression()? {
-            Some(expr) => expr,
-            // TODO: error type
-            _ => return Err(ParseError::UnexpectedEnd),
-        };
-
+        let cond = self.expect_expression()?;
         let _ = self.except_one_of(&[TokenKind::Then])?;
-
-        let then_ctrl = match self.parse_statement_list()? {
-            Some(stmts) => stmts,
-            // TODO: error type
-            _ => return Err(ParseError::UnexpectedEnd),
-        };
+        let then_ctrl = self.expect_statement_list()?;
 
         let pos = self.next;
         match self.next_kind()? {
@@ -671,11 +999,7 @@ impl<I: Iterator<Item = LexerResult>> DefaultParserImpl<I> {
             }
             // IF .. THEN .. ELSE .. END_IF
             TokenKind::Else => {
-                let else_ctrl = match self.parse_statement_list()? {
-                    Some(stmts) => stmts,
-                    // TODO: error type
-                    _ => return Err(ParseError::UnexpectedEnd),
-                };
+                let else_ctrl = self.expect_statement_list()?;
                 let _ = self.except_one(TokenKind::EndIf)?;
 
                 return Ok(Statement::if_stmt(
@@ -687,14 +1011,58 @@ impl<I: Iterator<Item = LexerResult>> DefaultParserImpl<I> {
             _ => self.next = pos,
         }
 
-        // with ELSEIF list
+        // IF .. THEN .. ELSEIF .. THEN .. ELSE .. END_IF
         let else_if_list = self.parse_elseif_statement_list()?;
+        let _ = self.except_one(TokenKind::Else)?;
+        let else_ctrl = self.expect_statement_list()?;
+        let _ = self.except_one(TokenKind::EndIf)?;
+
+        Ok(Statement::if_stmt(
+            Box::new(IfStatement::from_then_elseif_else(
+                cond,
+                then_ctrl,
+                else_if_list,
+                else_ctrl,
+            )),
+            if_position,
+            self.current_end_location(),
+        ))
+    }
+
+    /// ElseIfStatementList: ElseIfStatement+
+    ///
+    /// ElseIfStatement: "ELSEIF" Expr "THEN" StatementList
+    fn parse_elseif_statement_list(&mut self) -> Result<Vec<ElseIfStatement>, ParseError> {
+        let mut else_if_list = vec![];
+
+        loop {
+            let pos = self.next;
+            if !matches!(self.next()?.map(|x| &x.kind), Some(TokenKind::ElseIf)) {
+                self.next = pos;
+                break;
+            }
+
+            let cond = self.expect_expression()?;
+            let _ = self.except_one(TokenKind::Then)?;
+            let then_ctrl = self.expect_statement_list()?;
+            else_if_list.push(ElseIfStatement::from_then(cond, then_ctrl));
+        }
 
-        todo!()
+        if else_if_list.is_empty() {
+            return Err(ParseError::expect_tokens(
+                self.next_token()?.location,
+                &[TokenKind::EndIf, TokenKind::Else, TokenKind::ElseIf],
+            ));
+        }
+
+        Ok(else_if_list)
     }
 
-    fn parse_elseif_statement_list(&mut self) -> ParseResult<Vec<ElseIfStatement>> {
-        todo!()
+    fn expect_statement_list(&mut self) -> Result<Statement, ParseError> {
+        match self.parse_statement_list()? {
+            Some(stmts) => Ok(stmts),
+            None => Err(self.unexpected_token()),
+        }
     }
 
     fn parse_expr_statement(&mut self) -> ParseResult<Statement> {
@@ -708,11 +1076,7 @@ impl<I: Iterator<Item = LexerResult>> DefaultParserImpl<I> {
             }
         };
 
-        if !matches!(self.next_kind()?, TokenKind::Semicolon) {
-            self.next = pos;
-            return Ok(None);
-        }
-
+        let _ = self.except_one(TokenKind::Semicolon)?;
         Ok(Some(Statement::expr(
             expr,
             expr_start,
@@ -720,440 +1084,143 @@ impl<I: Iterator<Item = LexerResult>> DefaultParserImpl<I> {
         )))
     }
 
-    fn parse_op_expression(&mut self) -> ParseResult<Box<Expression>> {
-        todo!()
-    }
-
-    /// Expr: Expr ":=" BitOrExpr
-    ///     | BitOrExpr
-    ///
-    /// Expr: BitOrExpr expr'
-    fn parse_expression(&mut self) -> ParseResult<Expression> {
+    /// Error for the next token which can't be parsed, the token is not taken
+    fn unexpected_token(&mut self) -> ParseError {
         let pos = self.next;
-        if let Some(bitor) = self.parse_bitor_expression()? {
-            let r = match self.parse_expression_fix()? {
-                Some(fix) => Expression::new_assign(bitor, fix),
-                None => bitor,
-            };
-            return Ok(Some(r));
-        }
+        let err = match self.next() {
+            Ok(Some(tok)) => ParseError::UnexpectedToken(tok.location, vec![]),
+            Ok(None) => ParseError::UnexpectedEnd,
+            Err(e) => e,
+        };
 
         self.next = pos;
-        Ok(None)
+        err
     }
 
-    /// expr': ":=" BitOrExpr expr' | ε
-    fn parse_expression_fix(&mut self) -> ParseResult<Expression> {
+    /// Take the next token if `f` accepts its kind
+    fn next_if<T>(&mut self, f: impl FnOnce(&TokenKind) -> Option<T>) -> ParseResult<T> {
         let pos = self.next;
-        if !matches!(self.next_kind()?, TokenKind::Assign) {
+        let r = self.next()?.and_then(|tok| f(&tok.kind));
+        if r.is_none() {
             self.next = pos;
-            return Ok(None);
         }
 
-        self.parse_bitor_expression()
-        // if let Some(bitor) = self.parse_bitor_expression()? {
-        //     let pos = self.next;
-        //     let fix = self.parse_expression_fix()?;
-        //     self.match_val(fix, pos, Some(bitor), |x, y| {
-        //         Box::new(AssignExpression::new(x.unwrap(), y))
-        //     })
-        // } else {
-        //     self.next = pos;
-        //     Ok(None)
-        // }
+        Ok(r)
     }
 
-    /// BitOrExpr: BitOrExpr "|" XorExpr
-    ///      | XorExpr
-    ///
-    /// BitOrExpr: XorExpr bitor'
-    /// bitor': "|" XorExpr bitor' | ε
-    fn parse_bitor_expression(&mut self) -> ParseResult<Expression> {
-        let pos = self.next;
-
-        if let Some(bitor) = self.parse_xor_expression()? {
-            if let Some(fix) = self.parse_bitor_expression_fix()? {
-                Ok(Some(Expression::new_operator2(Operator::BitOr, bitor, fix)))
-            } else {
-                Ok(Some(bitor))
-            }
-        } else {
-            self.next = pos;
-            Ok(None)
+    fn expect_expression(&mut self) -> Result<Expression, ParseError> {
+        match self.parse_expression()? {
+            Some(expr) => Ok(expr),
+            None => Err(self.unexpected_token()),
         }
     }
 
-    fn parse_bitor_expression_fix(&mut self) -> ParseResult<Expression> {
-        let pos = self.next;
-        if !matches!(self.next_kind()?, TokenKind::BitOr) {
-            self.next = pos;
-            return Ok(None);
+    /// Expression in parentheses, arguments and indexes is one level deeper
+    fn parse_expression(&mut self) -> ParseResult<Expression> {
+        if self.expression_depth >= MAX_EXPRESSION_DEPTH {
+            let location = match self.next()? {
+                Some(tok) => tok.location,
+                None => return Err(ParseError::UnexpectedEnd),
+            };
+            return Err(ParseError::InvalidTokenAt(format!(
+                "expressions nested deeper than {} levels at line {}, column {}",
+                MAX_EXPRESSION_DEPTH,
+                location.mark + 1,
+                location.offset + 1
+            )));
         }
 
-        self.parse_bitor_expression()
-    }
-
-    /// XorExpr: XorExpr "XOR" BitAndExpr
-    ///        | BitAndExpr
-    ///
-    /// XorExpr: BitAndExpr xorexpr'
-    /// xorexpr': "XOR" BitAndExpr xorexpr' | ε
-    fn parse_xor_expression(&mut self) -> ParseResult<Expression> {
-        let pos = self.next;
+        self.expression_depth += 1;
+        let result = self.parse_assign_expression();
+        self.expression_depth -= 1;
 
-        match self.parse_bitand_expression()? {
-            Some(bitand) => match self.parse_xor_expression_fix()? {
-                Some(fix) => Ok(Some(Expression::new_operator2(Operator::Xor, bitand, fix))),
-                None => Ok(Some(bitand)),
-            },
-            None => {
-                self.next = pos;
-                Ok(None)
-            }
-        }
+        result
     }
 
-    fn parse_xor_expression_fix(&mut self) -> ParseResult<Expression> {
-        let pos = self.next;
-        if !matches!(self.next_kind()?, TokenKind::Xor) {
-            self.next = pos;
+    /// Expr: Expr ":=" BitOrExpr
+    ///     | Expr "=>" BitOrExpr
+    ///     | BitOrExpr
+    fn parse_assign_expression(&mut self) -> ParseResult<Expression> {
+        let Some(mut expr) = self.parse_bitor_expression()? else {
             return Ok(None);
+        };
+
+        while let Some(assign_type) = self.next_if(|tok| match tok {
+            TokenKind::Assign => Some(AssignType::Assign),
+            TokenKind::AssignRight => Some(AssignType::AssignRight),
+            _ => None,
+        })? {
+            let rhs = match self.parse_bitor_expression()? {
+                Some(rhs) => rhs,
+                None => return Err(self.unexpected_token()),
+            };
+            expr = Expression::assign(Box::new(AssignExpression::with_type(
+                expr,
+                rhs,
+                assign_type,
+            )));
         }
 
-        self.parse_xor_expression()
+        Ok(Some(expr))
     }
 
+    /// BitOrExpr: BitOrExpr "|" XorExpr
+    ///      | XorExpr
+    /// XorExpr: XorExpr "XOR" BitAndExpr
+    ///        | BitAndExpr
     /// BitAndExpr: BitAndExpr "&" EquExpr
     ///        | EquExpr
-    ///
-    /// BitAndExpr: EquExpr bitandexpr'
-    /// bitandexpr': "&" EquExpr bitandexpr' | ε
-    fn parse_bitand_expression(&mut self) -> ParseResult<Expression> {
-        let pos = self.next;
-
-        match self.parse_equ_expr()? {
-            Some(equ) => match self.parse_bitand_expression_fix()? {
-                Some(fix) => Ok(Some(Expression::new_operator2(Operator::BitAnd, equ, fix))),
-                None => Ok(Some(equ)),
-            },
-            None => {
-                self.next = pos;
-                Ok(None)
-            }
-        }
-    }
-
-    fn parse_bitand_expression_fix(&mut self) -> ParseResult<Expression> {
-        let pos = self.next;
-        if !matches!(self.next_kind()?, TokenKind::BitAnd) {
-            self.next = pos;
-            return Ok(None);
-        }
-
-        self.parse_bitand_expression()
-    }
-
     /// EquExpr: EquExpr EquOp CmpExpr
     ///     | CmpExpr
-    ///
-    /// EquExpr: CmpExpr equexpr'
-    fn parse_equ_expr(&mut self) -> ParseResult<Expression> {
-        let pos = self.next;
-
-        match self.parse_cmp_expr()? {
-            Some(equ) => match self.parse_equ_expr_fix()? {
-                Some((tk, fix)) => Ok(Some(Expression::new_operator2(tk.into(), equ, fix))),
-                None => Ok(Some(equ)),
-            },
-            None => {
-                self.next = pos;
-                Ok(None)
-            }
-        }
-    }
-
-    /// equexpr': EquOp CmpExpr equexpr' | ε
-    fn parse_equ_expr_fix(&mut self) -> ParseResult<(TokenKind, Expression)> {
-        let pos = self.next;
-
-        let current_tok = match self.parse_equ_op()? {
-            Some(tok) => tok,
-            _ => {
-                self.next = pos;
-                return Ok(None);
-            }
-        };
-
-        let cmp_expr = match self.parse_cmp_expr()? {
-            Some(cmp) => cmp,
-            _ => {
-                self.next = pos;
-                return Ok(None);
-            }
-        };
-
-        let pos = self.next;
-        match self.parse_equ_expr_fix()? {
-            Some((tok, fix)) => {
-                let tail = Expression::new_operator2(tok.into(), cmp_expr, fix);
-                Ok(Some((current_tok, tail)))
-            }
-            _ => {
-                self.next = pos;
-                Ok(Some((current_tok, cmp_expr)))
-            }
-        }
-    }
-
-    fn parse_equ_op(&mut self) -> ParseResult<TokenKind> {
-        let pos = self.next;
-
-        match self.next_kind()? {
-            tok @ TokenKind::Equal | tok @ TokenKind::NotEqual => Ok(Some(tok.clone())),
-            _ => {
-                self.next = pos;
-                Ok(None)
-            }
-        }
-    }
-
     /// CmpExpr: CmpExpr CmpOp OpExpr
     ///     | OpExpr
-    ///
-    /// CmpExpr: OpExpr cmpexpr'
-    fn parse_cmp_expr(&mut self) -> ParseResult<Expression> {
-        let pos = self.next;
-
-        match self.parse_op_expr()? {
-            Some(cmp) => match self.parse_cmp_expr_fix()? {
-                Some((tok, fix)) => Ok(Some(Expression::new_operator2(tok.into(), cmp, fix))),
-                None => Ok(Some(cmp)),
-            },
-            None => {
-                self.next = pos;
-                Ok(None)
-            }
-        }
-    }
-
-    /// cmpexpr': CmpOp OpExpr cmpexpr' | ε
-    fn parse_cmp_expr_fix(&mut self) -> ParseResult<(TokenKind, Expression)> {
-        let pos = self.next;
-
-        let current_tok = match self.parse_cmp_op()? {
-            Some(tok) => tok,
-            _ => {
-                self.next = pos;
-                return Ok(None);
-            }
-        };
-
-        let op_expr = match self.parse_op_expr()? {
-            Some(cmp) => cmp,
-            _ => {
-                self.next = pos;
-                return Ok(None);
-            }
-        };
-
-        let pos = self.next;
-        match self.parse_cmp_expr_fix()? {
-            Some((tok, fix)) => {
-                let tail = Expression::new_operator2(tok.into(), op_expr, fix);
-                Ok(Some((current_tok, tail)))
-            }
-            _ => {
-                self.next = pos;
-                Ok(Some((current_tok, op_expr)))
-            }
-        }
-    }
-
-    fn parse_cmp_op(&mut self) -> ParseResult<TokenKind> {
-        let pos = self.next;
-
-        match self.next_kind()? {
-            tok @ TokenKind::Greater
-            | tok @ TokenKind::GreaterEqual
-            | tok @ TokenKind::Less
-            | tok @ TokenKind::LessEqual => Ok(Some(tok.clone())),
-            _ => {
-                self.next = pos;
-                Ok(None)
-            }
-        }
-    }
-
     /// OpExpr: OpExpr ExprOp Factor
     ///     | Factor
-    ///
-    /// OpExpr: Factor opexpr'
-    fn parse_op_expr(&mut self) -> ParseResult<Expression> {
-        let pos = self.next;
-
-        if let Some(factor) = self.parse_factor()? {
-            if let Some((tok, fix)) = self.parse_op_expr_fix()? {
-                Ok(Some(Expression::new_operator2(tok.into(), factor, fix)))
-            } else {
-                Ok(Some(factor))
-            }
-        } else {
-            self.next = pos;
-            Ok(None)
-        }
-    }
-
-    /// opexpr': ExprOp Factor opexpr' | ε
-    fn parse_op_expr_fix(&mut self) -> ParseResult<(TokenKind, Expression)> {
-        let pos = self.next;
-
-        let current_tok = match self.parse_expr_op()? {
-            Some(tok) => tok,
-            _ => {
-                self.next = pos;
-                return Ok(None);
-            }
-        };
-
-        let factor = match self.parse_factor()? {
-            Some(factor) => factor,
-            _ => {
-                self.next = pos;
-                return Ok(None);
-            }
-        };
-
-        let pos = self.next;
-        if let Some((tok, fix)) = self.parse_op_expr_fix()? {
-            let tail = Expression::new_operator2(tok.into(), factor, fix);
-            Ok(Some((current_tok, tail)))
-        } else {
-            self.next = pos;
-            Ok(Some((current_tok, factor)))
-        }
-    }
-
-    // + / -
-    fn parse_expr_op(&mut self) -> ParseResult<TokenKind> {
-        let pos = self.next;
-
-        match self.next_kind()? {
-            tok @ TokenKind::Plus | tok @ TokenKind::Minus => Ok(Some(tok.clone())),
-            _ => {
-                self.next = pos;
-                Ok(None)
-            }
-        }
-    }
-
     /// Factor: Factor FactorOp PowerExpr
     ///     | PowerExpr
+    /// PowerExpr: PowerExpr "**" UnaryFactor
+    ///     | UnaryFactor
     ///
-    /// Factor: PowerExpr factor'
-    fn parse_factor(&mut self) -> ParseResult<Expression> {
-        let pos = self.next;
-
-        if let Some(power) = self.parse_power_expr()? {
-            if let Some((tok, fix)) = self.parse_factor_fix()? {
-                Ok(Some(Expression::new_operator2(tok.into(), power, fix)))
-            } else {
-                Ok(Some(power))
-            }
-        } else {
-            self.next = pos;
-            Ok(None)
-        }
-    }
-
-    /// factor': FactorOp PowerExpr factor' | ε
-    fn parse_factor_fix(&mut self) -> ParseResult<(TokenKind, Expression)> {
-        let pos = self.next;
-
-        let current_tok = match self.parse_factor_op()? {
-            Some(tok) => tok,
-            _ => {
-                self.next = pos;
-                return Ok(None);
-            }
+    /// All binary operators are left associative, operators waiting for their right
+    /// operand are kept in a stack instead of a recursion for each precedence level
+    fn parse_bitor_expression(&mut self) -> ParseResult<Expression> {
+        let Some(first) = self.parse_unary_factor()? else {
+            return Ok(None);
         };
 
-        let power = match self.parse_power_expr()? {
-            Some(power) => power,
-            _ => {
-                self.next = pos;
-                return Ok(None);
+        let mut operands = vec![first];
+        let mut operators: Vec<(TokenKind, u8)> = vec![];
+        while let Some((tok, precedence)) =
+            self.next_if(|tok| binary_precedence(tok).map(|x| (tok.clone(), x)))?
+        {
+            while operators.last().is_some_and(|(_, x)| *x >= precedence) {
+                Self::reduce_binary_expression(&mut operands, &mut operators);
             }
-        };
 
-        let pos = self.next;
-        if let Some((tok, fix)) = self.parse_factor_fix()? {
-            let tail = Expression::new_operator2(tok.into(), power, fix);
-            Ok(Some((current_tok, tail)))
-        } else {
-            self.next = pos;
-            Ok(Some((current_tok, power)))
+            let rhs = match self.parse_unary_factor()? {
+                Some(rhs) => rhs,
+                None => return Err(self.unexpected_token()),
+            };
+            operators.push((tok, precedence));
+            operands.push(rhs);
         }
-    }
-
-    // '*' '/' 'MOD'
-    fn parse_factor_op(&mut self) -> ParseResult<TokenKind> {
-        let pos = self.next;
 
-        match self.next_kind()? {
-            tok @ TokenKind::Multiply | tok @ TokenKind::Division | tok @ TokenKind::Mod => {
-                Ok(Some(tok.clone()))
-            }
-            _ => {
-                self.next = pos;
-                Ok(None)
-            }
+        while !operators.is_empty() {
+            Self::reduce_binary_expression(&mut operands, &mut operators);
         }
-    }
-
-    /// PowerExpr: PowerExpr "**" UnaryFactor
-    ///     | UnaryFactor
-    ///
-    /// PowerExpr: UnaryFactor powerexpr'
-    fn parse_power_expr(&mut self) -> ParseResult<Expression> {
-        let pos = self.next;
 
-        match self.parse_unary_factor()? {
-            Some(unary) => match self.parse_power_expr_fix()? {
-                Some(fix) => Ok(Some(Expression::new_operator2(Operator::Power, unary, fix))),
-                None => Ok(Some(unary)),
-            },
-            None => {
-                self.next = pos;
-                Ok(None)
-            }
-        }
+        Ok(operands.pop())
     }
 
-    // powerexpr': "**" UnaryFactor powerexpr' | ε
-    fn parse_power_expr_fix(&mut self) -> ParseResult<Expression> {
-        let pos = self.next;
-
-        if !matches!(self.next_kind()?, TokenKind::Power) {
-            self.next = pos;
-            return Ok(None);
-        }
-
-        let unary = match self.parse_unary_factor()? {
-            Some(unary) => unary,
-            _ => {
-                self.next = pos;
-                return Ok(None);
-            }
-        };
-
-        let pos = self.next;
-        if let Some(fix) = self.parse_power_expr_fix()? {
-            let tail = Expression::new_operator2(Operator::Power, unary, fix);
-            Ok(Some(tail))
-        } else {
-            self.next = pos;
-            Ok(Some(unary))
-        }
+    /// Replace the last operator and its operands by the binary expression
+    fn reduce_binary_expression(
+        operands: &mut Vec<Expression>,
+        operators: &mut Vec<(TokenKind, u8)>,
+    ) {
+        let (tok, _) = operators.pop().unwrap();
+        let rhs = operands.pop().unwrap();
+        let lhs = operands.pop().unwrap();
+        operands.push(Expression::new_operator2(tok.into(), lhs, rhs));
     }
 
     /// UnaryFactor: UnaryOp CompoFactor
@@ -1168,7 +1235,8 @@ impl<I: Iterator<Item = LexerResult>> DefaultParserImpl<I> {
                 Ok(Some(Expression::new_operator(op.into(), smallvec![factor])))
             }
             (None, Some(factor)) => Ok(Some(factor)),
-            _ => {
+            (Some(_), None) => Err(self.unexpected_token()),
+            (None, None) => {
                 self.next = pos;
                 Ok(None)
             }
@@ -1177,60 +1245,52 @@ impl<I: Iterator<Item = LexerResult>> DefaultParserImpl<I> {
 
     // '-' 'NOT'
     fn parse_unary_op(&mut self) -> ParseResult<TokenKind> {
-        let pos = self.next;
-
-        match self.next_kind()? {
-            tok @ TokenKind::Not | tok @ TokenKind::Minus => Ok(Some(tok.clone())),
-            _ => {
-                self.next = pos;
-                Ok(None)
-            }
-        }
+        self.next_if(|tok| match tok {
+            TokenKind::Not | TokenKind::Minus => Some(tok.clone()),
+            _ => None,
+        })
     }
 
     /// CompoFactor: CompoFactor "." Term
+    ///     | CompoFactor "[" Expr, .. "]"
+    ///     | CompoFactor "(" Expr, .. ")"
     ///     | Term
-    ///
-    /// CompoFactor: Term compofactor'
     fn parse_compo_factor(&mut self) -> ParseResult<Expression> {
-        let pos = self.next;
+        let start = self.next;
+        let Some(mut factor) = self.parse_term_expr()? else {
+            return Ok(None);
+        };
 
-        match self.parse_term_expr()? {
-            Some(term) => match self.parse_compo_factor_fix()? {
-                Some(fix) => Ok(Some(Expression::new_compo(term, fix))),
-                None => Ok(Some(term)),
-            },
-            None => {
-                self.next = pos;
-                Ok(None)
+        loop {
+            let pos = self.next;
+            match self.next()?.map(|x| &x.kind) {
+                Some(TokenKind::DotAccess) => {
+                    let right = match self.parse_term_expr()? {
+                        Some(term) => term,
+                        None => return Err(self.unexpected_token()),
+                    };
+                    factor = Expression::new_compo(factor, right);
+                }
+                Some(TokenKind::LeftBracket) => {
+                    factor = self.parse_array_access_indexes(factor, start)?;
+                }
+                Some(TokenKind::LeftParentheses) => {
+                    factor = self.parse_call_expr_args(factor)?;
+                }
+                _ => {
+                    self.next = pos;
+                    return Ok(Some(factor));
+                }
             }
         }
     }
 
-    /// compofactor': "." Term compofactor' | ε
-    fn parse_compo_factor_fix(&mut self) -> ParseResult<Expression> {
-        let pos = self.next;
-
-        if !matches!(self.next_kind()?, TokenKind::DotAccess) {
-            self.next = pos;
-            return Ok(None);
-        }
-
-        self.parse_compo_factor()
-    }
-
+    /// Term: LiteralExpr
+    ///     | "(" Expr ")"
+    ///     | VarExpr
     fn parse_term_expr(&mut self) -> ParseResult<Expression> {
-        if let Some(val) = self.parse_variable_expr()? {
-            let p = self.next;
-            let tk = self.next_kind()?;
-
-            // val ^ (...
-            if matches!(tk, TokenKind::LeftParentheses) {
-                return self.parse_call_expr_args(val);
-            }
-
-            self.next = p;
-            return Ok(Some(val));
+        if let Some(var) = self.parse_variable_expr()? {
+            return Ok(Some(var));
         }
 
         if let Some(literal) = self.parse_literal_expression()? {
@@ -1238,99 +1298,70 @@ impl<I: Iterator<Item = LexerResult>> DefaultParserImpl<I> {
         }
 
         let pos = self.next;
-        if matches!(self.next_kind()?, TokenKind::LeftParentheses) {
-            if let Some(expr) = self.parse_expression()? {
-                let _ = self.except_one_of(&[TokenKind::RightParentheses])?;
-                return Ok(Some(expr));
-            }
+        if matches!(
+            self.next()?.map(|x| &x.kind),
+            Some(TokenKind::LeftParentheses)
+        ) {
+            let expr = self.expect_expression()?;
+            let _ = self.except_one(TokenKind::RightParentheses)?;
+            return Ok(Some(expr));
         }
 
         self.next = pos;
         Ok(None)
     }
 
-    // function name is already taken: fun( ^ ...)
-    fn parse_call_expr_args(&mut self, callee: Expression) -> ParseResult<Expression> {
-        let origin_pos = self.next;
-
-        // last token is comma
-        // let mut comma = false;
-        let mut args: SmallVec8<Expression> = smallvec![];
+    // array is already taken: arr[ ^ ...]
+    fn parse_array_access_indexes(
+        &mut self,
+        array: Expression,
+        start: usize,
+    ) -> Result<Expression, ParseError> {
+        let mut indexes: SmallVec3<Expression> = smallvec![];
         loop {
-            let p = self.next;
-            let nk = self.next_kind()?;
-            if matches!(nk, TokenKind::RightParentheses) {
-                return Ok(Some(Expression::call(Box::new(
-                    CallExpression::with_arguments(callee, args),
-                ))));
-            }
+            indexes.push(self.expect_expression()?);
 
-            self.next = p;
-            // argument is needed
-            if let Ok(Some(arg_assign)) = self.parse_argument_expression() {
-                // comma = false;
-                args.push(arg_assign);
-
-                // after argument been eaten, a Comma is necessary
-                let nk = self.next_kind()?;
-                match nk {
-                    // continue to eat new argument expr
-                    // TokenKind::Comma if !comma => {
-                    //     comma = true;
-                    //     continue;
-                    // }
-                    TokenKind::Comma => {
-                        // TODO: dup comma
-                        continue;
-                    }
-                    // call finished
-                    TokenKind::RightParentheses => {
-                        return Ok(Some(Expression::call(Box::new(
-                            CallExpression::with_arguments(callee, args),
-                        ))));
-                    }
-                    _ => break,
-                }
-            } else {
+            let tok = self.except_one_of(&[TokenKind::Comma, TokenKind::RightBracket])?;
+            if matches!(tok.kind, TokenKind::RightBracket) {
                 break;
             }
         }
 
-        self.next = origin_pos;
-        Ok(None)
+        let start = self.start_location(start);
+        let end = self.current_end_location();
+        Ok(Expression::array_access(
+            Box::new(ArrayAccessExpression::new(array, indexes)),
+            start,
+            end,
+        ))
     }
 
-    fn parse_argument_expression(&mut self) -> ParseResult<Expression> {
-        Ok(self
-            .parse_literal_expression()?
-            .or(self.parse_argument_assign_expr()?))
-    }
+    // function is already taken: fun( ^ ...), arguments are separated by comma and the
+    // last comma is optional
+    fn parse_call_expr_args(&mut self, callee: Expression) -> Result<Expression, ParseError> {
+        let mut args: SmallVec8<Expression> = smallvec![];
+        loop {
+            if self
+                .next_if(|tok| matches!(tok, TokenKind::RightParentheses).then_some(()))?
+                .is_some()
+            {
+                break;
+            }
 
-    // a => b
-    // a := b
-    fn parse_argument_assign_expr(&mut self) -> ParseResult<Expression> {
-        let pos = self.next;
+            args.push(self.expect_expression()?);
 
-        let lhs = self.parse_variable_expr()?;
-        if lhs.is_none() {
-            self.next = pos;
-            return Ok(None);
+            let tok = self.except_one_of(&[TokenKind::Comma, TokenKind::RightParentheses])?;
+            if matches!(tok.kind, TokenKind::RightParentheses) {
+                break;
+            }
         }
 
-        let pos = self.next;
-        let assign_type = match self.next_kind()? {
-            TokenKind::Assign => AssignType::Assign,
-            TokenKind::AssignRight => AssignType::AssignRight,
-            _ => {
-                self.next = pos;
-                return Ok(lhs);
-            }
+        let call = if args.is_empty() {
+            CallExpression::new(callee)
+        } else {
+            CallExpression::with_arguments(callee, args)
         };
-
-        let rhs = self.parse_expression()?;
-        Ok(Some(Expression::assign(Box::new(
-            AssignExpression::with_type(lhs.unwrap(), rhs.unwrap(), assign_type),
-        ))))
+        Ok(Expression::call(Box::new(call)))
     }
 
     #[inline]
@@ -1343,27 +1374,44 @@ impl<I: Iterator<Item = LexerResult>> DefaultParserImpl<I> {
     }
 
     fn parse_literal_expr(&mut self) -> ParseResult<LiteralExpression> {
-        let pos = self.next;
-
-        if let TokenKind::Literal(val) = self.next_kind()? {
-            return Ok(Some(LiteralExpression::new(val.clone())));
-        }
-
-        self.next = pos;
-        Ok(None)
+        self.next_if(|tok| match tok {
+            TokenKind::Literal(val) => Some(LiteralExpression::new(val.clone())),
+            _ => None,
+        })
     }
 
+    /// VarExpr: IDENTIFIER
     fn parse_variable_expr(&mut self) -> ParseResult<Expression> {
         let pos = self.next;
+        let Some(ident) = self.next_if(|tok| match tok {
+            TokenKind::Identifier(ident) => Some(ident.clone()),
+            _ => None,
+        })?
+        else {
+            return Ok(None);
+        };
 
-        match self.next_kind()? {
-            TokenKind::Identifier(ident) => {
-                Ok(Some(Expression::new_variable(ident.clone(), None, None)))
-            }
-            _ => {
-                self.next = pos;
-                Ok(None)
-            }
-        }
+        Ok(Some(Expression::new_variable(
+            ident,
+            self.start_location(pos),
+            self.end_location(pos),
+        )))
+    }
+}
+
+/// Precedence of binary operator, higher binds tighter
+fn binary_precedence(tok: &TokenKind) -> Option<u8> {
+    match tok {
+        TokenKind::BitOr => Some(1),
+        TokenKind::Xor => Some(2),
+        TokenKind::BitAnd => Some(3),
+        TokenKind::Equal | TokenKind::NotEqual => Some(4),
+        TokenKind::Greater | TokenKind::GreaterEqual | TokenKind::Less | TokenKind::LessEqual => {
+            Some(5)
+        }
+        TokenKind::Plus | TokenKind::Minus => Some(6),
+        TokenKind::Multiply | TokenKind::Division | TokenKind::Mod => Some(7),
+        TokenKind::Power => Some(8),
+        _ => None,
     }
 }
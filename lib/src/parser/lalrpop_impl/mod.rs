@@ -42,6 +42,23 @@ where
     }
 }
 
+/// Accept identifier `ident` as the contextual keyword `keyword`, like 'TASK' in resource
+fn contextual_keyword(
+    ident: StString,
+    keyword: &str,
+    start: Location,
+    end: Location,
+) -> Result<(), lalrpop_util::ParseError<Location, TokenKind, LexicalError>> {
+    if ident == *keyword {
+        return Ok(());
+    }
+
+    Err(lalrpop_util::ParseError::UnrecognizedToken {
+        token: (start, TokenKind::Identifier(ident), end),
+        expected: vec![format!("\"{}\"", keyword)],
+    })
+}
+
 pub struct LalrpopParser {
     decl_parser: Lazy<st::DeclarationParser>,
     body_parser: Lazy<st::StBodyParser>,
@@ -1,6 +1,7 @@
 use crate::ast::*;
+use crate::parser::preprocess::Preprocessor;
 use crate::parser::token::{Location, Token};
-use crate::parser::{Buffer, IterBuffer, StreamBuffer, TokenKind};
+use crate::parser::{Buffer, CompilerDefines, IterBuffer, SkippedRegion, StreamBuffer, TokenKind};
 use crate::prelude::StString;
 use bitflags::bitflags;
 use smallmap::Map;
@@ -47,6 +48,8 @@ pub enum LiteralValue {
     Real(String),
     LReal(String),
     String(String),
+    /// Duration in nanoseconds, like T#1s500ms
+    Time(i64),
 }
 
 impl LiteralValue {
@@ -65,6 +68,7 @@ impl LiteralValue {
             LiteralValue::Real(_) => RealType::new_type(),
             LiteralValue::LReal(_) => LRealType::new_type(),
             LiteralValue::String(_) => StringType::new_type(),
+            LiteralValue::Time(_) => TimeType::new_type(),
         }
     }
 }
@@ -86,14 +90,86 @@ impl Display for LiteralValue {
             LiteralValue::Real(x) => write!(f, "{}#{}", TokenKind::Real, x),
             LiteralValue::LReal(x) => write!(f, "{}#{}", TokenKind::LReal, x),
             LiteralValue::String(s) => write!(f, "{}#{}", TokenKind::String, s),
+            LiteralValue::Time(x) => write!(f, "{}#{}", TokenKind::Time, DurationDisplay(*x)),
         }
     }
 }
 
+const DURATION_UNITS: [(&str, i64); 7] = [
+    ("d", 86_400_000_000_000),
+    ("h", 3_600_000_000_000),
+    ("m", 60_000_000_000),
+    ("s", 1_000_000_000),
+    ("ms", 1_000_000),
+    ("us", 1_000),
+    ("ns", 1),
+];
+
+/// Duration in nanoseconds displayed with units, like 1m30s or -500ms
+struct DurationDisplay(i64);
+
+impl Display for DurationDisplay {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        if self.0 == 0 {
+            return f.write_str("0s");
+        }
+        if self.0 < 0 {
+            f.write_str("-")?;
+        }
+
+        let mut rest = self.0.unsigned_abs();
+        for (unit, ns) in DURATION_UNITS {
+            let ns = ns as u64;
+            if rest >= ns {
+                write!(f, "{}{}", rest / ns, unit)?;
+                rest %= ns;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Choose the smallest unsigned literal type which can hold the decimal digits
+fn integer_literal(s: &str) -> Option<LiteralValue> {
+    let v: u64 = s.parse().ok()?;
+
+    Some(if let Ok(v) = u16::try_from(v) {
+        LiteralValue::UInt(v)
+    } else if let Ok(v) = u32::try_from(v) {
+        LiteralValue::UDInt(v)
+    } else {
+        LiteralValue::ULInt(v)
+    })
+}
+
 #[derive(Debug, Clone, PartialOrd, PartialEq, Ord, Eq)]
 pub enum LexicalError {
     UnexpectedCharacter(usize, usize, char),
     UnexpectedEnd,
+    /// Invalid or unmatched conditional compilation directive at line and column
+    InvalidDirective(usize, usize),
+}
+
+impl Display for LexicalError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            LexicalError::UnexpectedCharacter(line, column, ch) => write!(
+                f,
+                "unexpected character '{}' at line {}, column {}",
+                ch,
+                line + 1,
+                column + 1
+            ),
+            LexicalError::UnexpectedEnd => f.write_str("unexpected end of input"),
+            LexicalError::InvalidDirective(line, column) => write!(
+                f,
+                "invalid conditional compilation directive at line {}, column {}",
+                line + 1,
+                column + 1
+            ),
+        }
+    }
 }
 
 #[allow(unused)]
@@ -102,6 +178,8 @@ pub struct StLexerOptions {
     allow_multiple_underline: bool,
     allow_suffix_underline: bool,
     keep_whitespace_token: bool,
+    /// Names defined for conditional compilation
+    defines: CompilerDefines,
 }
 
 impl Default for StLexerOptions {
@@ -111,6 +189,7 @@ impl Default for StLexerOptions {
             allow_multiple_underline: false,
             allow_suffix_underline: false,
             keep_whitespace_token: false,
+            defines: CompilerDefines::default(),
         }
     }
 }
@@ -136,6 +215,19 @@ impl StLexerOptions {
 
         self
     }
+
+    /// Define name for conditional compilation, like `{IF defined(SIMULATION)}`
+    pub fn define<S: Into<StString>>(mut self, name: S, value: Option<String>) -> Self {
+        self.defines.define(name, value);
+
+        self
+    }
+
+    pub fn defines(mut self, defines: CompilerDefines) -> Self {
+        self.defines = defines;
+
+        self
+    }
 }
 
 pub struct StLexer<'a> {
@@ -143,6 +235,7 @@ pub struct StLexer<'a> {
     keywords: Map<StString, TokenKind>,
     options: StLexerOptions,
     loc_info: Vec<LocInfo>,
+    preprocessor: Preprocessor,
 }
 
 macro_rules! keywords {
@@ -208,6 +301,14 @@ impl StLexerBuilder {
             TokenKind::EndFunction,
             TokenKind::Program,
             TokenKind::EndProgram,
+            TokenKind::FunctionBlock,
+            TokenKind::EndFunctionBlock,
+            TokenKind::Method,
+            TokenKind::EndMethod,
+            TokenKind::Property,
+            TokenKind::EndProperty,
+            TokenKind::Action,
+            TokenKind::EndAction,
             TokenKind::Struct,
             TokenKind::EndStruct,
             TokenKind::Var,
@@ -219,8 +320,15 @@ impl StLexerBuilder {
             TokenKind::EndVar,
             TokenKind::Retain,
             TokenKind::Persistent,
+            TokenKind::Constant,
             TokenKind::Type,
             TokenKind::EndType,
+            TokenKind::Configuration,
+            TokenKind::EndConfiguration,
+            TokenKind::Namespace,
+            TokenKind::EndNamespace,
+            TokenKind::Using,
+            TokenKind::EndResource,
             TokenKind::Bit,
             TokenKind::Int,
             TokenKind::Bool,
@@ -230,6 +338,12 @@ impl StLexerBuilder {
             TokenKind::SInt,
             TokenKind::UInt,
             TokenKind::USInt,
+            TokenKind::DInt,
+            TokenKind::UDInt,
+            TokenKind::LInt,
+            TokenKind::ULInt,
+            TokenKind::Time,
+            TokenKind::String,
             TokenKind::Array,
             TokenKind::Adr,
             TokenKind::SizeOf
@@ -239,10 +353,11 @@ impl StLexerBuilder {
         self
     }
 
-    pub fn build_str(self, input: &str) -> StLexer {
+    pub fn build_str(self, input: &str) -> StLexer<'_> {
         StLexer {
             buffer: Box::new(IterBuffer::new(input.chars())),
             keywords: self.keywords,
+            preprocessor: Preprocessor::new(self.options.defines.clone()),
             options: self.options,
             loc_info: vec![LocInfo::default()],
         }
@@ -252,15 +367,17 @@ impl StLexerBuilder {
         StLexer {
             buffer: Box::new(IterBuffer::new(iter)),
             keywords: self.keywords,
+            preprocessor: Preprocessor::new(self.options.defines.clone()),
             options: self.options,
             loc_info: vec![LocInfo::default()],
         }
     }
 
-    pub fn build_file(self, file: &str) -> io::Result<StLexer> {
+    pub fn build_file(self, file: &str) -> io::Result<StLexer<'_>> {
         Ok(StLexer {
             buffer: Box::new(StreamBuffer::from_file(file)?),
             keywords: self.keywords,
+            preprocessor: Preprocessor::new(self.options.defines.clone()),
             options: self.options,
             loc_info: vec![LocInfo::default()],
         })
@@ -279,6 +396,12 @@ impl<'input> StLexer<'input> {
         }
     }
 
+    /// Text skipped by conditional compilation so far, all of them after the end of text
+    #[inline]
+    pub fn skipped_regions(&self) -> &[SkippedRegion] {
+        self.preprocessor.skipped_regions()
+    }
+
     #[inline]
     pub fn buffer_offset_by_line(&self, line: usize) -> Option<usize> {
         self.loc_info
@@ -321,7 +444,8 @@ impl<'input> StLexer<'input> {
                     self.buffer.consume1();
                     s.push(c);
                 }
-                Some('.') => {
+                // avoid range like 1..3
+                Some('.') if self.buffer.peek(2) != Some('.') => {
                     flags |= NumberStringFlags::FLOAT;
                     s = self.parse_float_string(s)?;
                     break;
@@ -376,12 +500,22 @@ impl<'input> StLexer<'input> {
                     self.buffer.consume1();
                     s.push(c);
                 }
-                Some('.') => {
+                // avoid range like 1..3
+                Some('.') if self.buffer.peek(2) != Some('.') => {
                     return self.parse_floating_before_dot(tok, s);
                 }
                 _ => {
                     tok.length = s.len();
-                    tok.kind = TokenKind::Literal(LiteralValue::UInt(s.parse().unwrap()));
+                    tok.kind = match integer_literal(&s) {
+                        Some(literal) => TokenKind::Literal(literal),
+                        None => {
+                            return Some(Err(LexicalError::UnexpectedCharacter(
+                                tok.location.mark,
+                                tok.location.offset,
+                                ch,
+                            )))
+                        }
+                    };
                     return Some(Ok(tok));
                 }
             }
@@ -455,6 +589,27 @@ impl<'input> StLexer<'input> {
         }
     }
 
+    /// Pragma in braces, '{' already consumed
+    fn parse_pragma(&mut self, mut tok: Token) -> Option<LexerResult> {
+        let mut s = String::new();
+
+        loop {
+            match self.buffer.peek1() {
+                Some('}') => {
+                    self.buffer.consume1();
+                    tok.length = s.chars().count() + 2;
+                    tok.kind = TokenKind::Pragma(s.trim().to_owned());
+                    return Some(Ok(tok));
+                }
+                Some(c) => {
+                    self.buffer.consume1();
+                    s.push(c)
+                }
+                _ => return Some(Err(LexicalError::UnexpectedEnd)),
+            }
+        }
+    }
+
     /// Identifier, Keywords or literal with type annotation prefix
     fn parse_words(&mut self, mut tok: Token, ch: char) -> Option<LexerResult> {
         let mut str = String::with_capacity(32);
@@ -488,6 +643,18 @@ impl<'input> StLexer<'input> {
 
         tok.length = str.len();
         tok.kind = self.keywords_or_identifier(str);
+
+        // duration literal, like: T#1s or TIME#1h30m
+        let is_time = match &tok.kind {
+            TokenKind::Time => true,
+            TokenKind::Identifier(s) => s.string().eq_ignore_ascii_case("T"),
+            _ => false,
+        };
+        if is_time && self.buffer.peek1() == Some('#') {
+            self.buffer.consume1();
+            return Some(self.parse_duration(tok));
+        }
+
         if self.buffer.peek1() != Some('#') || !tok.kind.is_type() {
             return Some(Ok(tok));
         }
@@ -513,6 +680,71 @@ impl<'input> StLexer<'input> {
         Some(Ok(number))
     }
 
+    // parsing duration after 'T#', like: 1d2h3m4s5ms, 1.5s or -100ms
+    fn parse_duration(&mut self, mut tok: Token) -> LexerResult {
+        let unexpected = |lexer: &Self, c| {
+            LexicalError::UnexpectedCharacter(
+                lexer.buffer.current_line(),
+                lexer.buffer.line_offset(),
+                c,
+            )
+        };
+
+        tok.length += 1; // '#'
+        let negative = self.buffer.peek1() == Some('-');
+        if negative {
+            self.buffer.consume1();
+            tok.length += 1;
+        }
+
+        let mut total: f64 = 0.0;
+        let mut components = 0;
+        loop {
+            let mut number = String::new();
+            while let Some(c) = self.buffer.peek1() {
+                if !(c.is_ascii_digit() || c == '.' || c == '_') {
+                    break;
+                }
+                self.buffer.consume1();
+                if c != '_' {
+                    number.push(c);
+                }
+                tok.length += 1;
+            }
+            if number.is_empty() {
+                break;
+            }
+
+            let mut unit = String::new();
+            while let Some(c) = self.buffer.peek1().filter(|x| x.is_ascii_alphabetic()) {
+                self.buffer.consume1();
+                unit.push(c.to_ascii_lowercase());
+                tok.length += 1;
+            }
+
+            let ns = DURATION_UNITS
+                .iter()
+                .find(|(name, _)| *name == unit)
+                .map(|(_, ns)| *ns);
+            match (number.parse::<f64>(), ns) {
+                (Ok(v), Some(ns)) => total += v * ns as f64,
+                _ => return Err(unexpected(self, unit.chars().next().unwrap_or('#'))),
+            }
+            components += 1;
+        }
+
+        if components == 0 {
+            return match self.buffer.peek1() {
+                Some(c) => Err(unexpected(self, c)),
+                None => Err(LexicalError::UnexpectedEnd),
+            };
+        }
+
+        let ns = total.round() as i64;
+        tok.kind = TokenKind::Literal(LiteralValue::Time(if negative { -ns } else { ns }));
+        Ok(tok)
+    }
+
     fn parse_whitespace(&mut self, mut tok: Token) -> LexerResult {
         tok.kind = TokenKind::Whitespace;
 
@@ -577,6 +809,14 @@ impl<'input> StLexer<'input> {
     }
 
     fn keywords_or_identifier(&mut self, s: String) -> TokenKind {
+        // boolean literals
+        if s.eq_ignore_ascii_case("TRUE") {
+            return TokenKind::Literal(LiteralValue::Bool(true));
+        }
+        if s.eq_ignore_ascii_case("FALSE") {
+            return TokenKind::Literal(LiteralValue::Bool(false));
+        }
+
         let st_str = s.into();
 
         if let Some(keyword) = self.keywords.get(&st_str) {
@@ -609,6 +849,13 @@ impl<'input> StLexer<'input> {
         }
     }
 
+    fn current_location(&self) -> Location {
+        Location {
+            mark: self.buffer.current_line(),
+            offset: self.buffer.line_offset(),
+        }
+    }
+
     fn next_raw(&mut self) -> Option<<Self as Iterator>::Item> {
         let mut tok = Token {
             length: 1,
@@ -697,6 +944,10 @@ impl<'input> StLexer<'input> {
                 self.buffer.consume1();
                 self.parse_string(tok)
             }
+            Some('{') => {
+                self.buffer.consume1();
+                self.parse_pragma(tok)
+            }
             Some(c @ '<') | Some(c @ ':') | Some(c @ '>') | Some(c @ '=') | Some(c @ '*') => {
                 self.buffer.consume1();
                 self.parse_second_char(tok, c)
@@ -723,15 +974,43 @@ impl Iterator for StLexer<'_> {
     type Item = LexerResult;
 
     fn next(&mut self) -> Option<Self::Item> {
-        if self.options.keep_whitespace_token {
-            return self.next_raw();
-        }
-
         loop {
-            match self.next_raw() {
-                Some(Ok(tok)) if matches!(tok.kind, TokenKind::Whitespace) => {}
-                x => return x,
+            let tok = match self.next_raw() {
+                Some(Ok(tok)) => tok,
+                // errors in the branches not taken are ignored
+                Some(Err(_)) if !self.preprocessor.is_active() => continue,
+                Some(Err(e)) => return Some(Err(e)),
+                None => {
+                    let end = self.current_location();
+                    return match self.preprocessor.finish(end) {
+                        Ok(()) => None,
+                        Err(()) => Some(Err(LexicalError::UnexpectedEnd)),
+                    };
+                }
+            };
+
+            if let TokenKind::Pragma(text) = &tok.kind {
+                let end = self.current_location();
+                match self.preprocessor.directive(text, tok.location, end) {
+                    Ok(true) => continue,
+                    Ok(false) => {}
+                    Err(()) => {
+                        return Some(Err(LexicalError::InvalidDirective(
+                            tok.location.mark,
+                            tok.location.offset,
+                        )))
+                    }
+                }
+            }
+
+            if !self.preprocessor.is_active()
+                || (!self.options.keep_whitespace_token
+                    && matches!(tok.kind, TokenKind::Whitespace))
+            {
+                continue;
             }
+
+            return Some(Ok(tok));
         }
     }
 }
@@ -796,6 +1075,71 @@ mod test {
         assert!(lexer.next().is_none());
     }
 
+    #[test]
+    fn test_pragma() {
+        let s = "{ attribute 'hide' } a";
+        let mut lexer = StLexerBuilder::new().build_str(s);
+
+        let x = lexer.next().unwrap().unwrap();
+        assert_eq!(x.location.offset, 0);
+        assert_eq!(x.length, 20);
+        assert_eq!(x.kind, TokenKind::Pragma("attribute 'hide'".to_owned()));
+
+        let x = lexer.next().unwrap().unwrap();
+        assert_eq!(x.location.offset, 21);
+        assert!(matches!(x.kind, TokenKind::Identifier(_)));
+
+        let mut lexer = StLexerBuilder::new().build_str("{warning 'x'");
+        assert!(matches!(
+            lexer.next(),
+            Some(Err(LexicalError::UnexpectedEnd))
+        ));
+    }
+
+    #[test]
+    fn test_conditional_compilation() {
+        let s = "a {IF defined(SIM)}\nb\n{ELSIF defined(X)} c {ELSE} d\n{END_IF} e\n\
+                 {define X} {IF defined(X) AND NOT defined(SIM)} f {END_IF}";
+        let options = StLexerOptions::default().define("sim", None);
+        let mut lexer = StLexerBuilder::from_options(options).build_str(s);
+
+        let tokens: Vec<_> = lexer.by_ref().map(|x| x.unwrap()).collect();
+        let names: Vec<_> = tokens.iter().map(|x| x.kind.to_string()).collect();
+        assert_eq!(names, ["a", "b", "e"]);
+        assert_eq!(tokens[1].location.mark, 1);
+        assert_eq!(tokens[1].location.offset, 0);
+        assert_eq!(tokens[2].location.mark, 3);
+        assert_eq!(tokens[2].location.offset, 9);
+
+        let regions: Vec<_> = lexer
+            .skipped_regions()
+            .iter()
+            .map(|x| (x.start.mark, x.start.offset, x.end.mark, x.end.offset))
+            .collect();
+        assert_eq!(
+            regions,
+            [(0, 2, 0, 19), (2, 0, 3, 8), (4, 0, 4, 10), (4, 11, 4, 58)]
+        );
+
+        let mut lexer = StLexerBuilder::new().build_str("{IF defined(A)} a");
+        assert!(matches!(
+            lexer.next(),
+            Some(Err(LexicalError::UnexpectedEnd))
+        ));
+
+        for s in [
+            "{END_IF}",
+            "{IF defined(A)} {ELSE} {ELSE} {END_IF}",
+            "{IF A} {END_IF}",
+        ] {
+            let mut lexer = StLexerBuilder::new().build_str(s);
+            assert!(matches!(
+                lexer.next(),
+                Some(Err(LexicalError::InvalidDirective(0, _)))
+            ));
+        }
+    }
+
     #[test]
     fn test_identifier_semicolon() {
         let s = "1 + \na;";
@@ -873,7 +1217,7 @@ mod test {
         let x = lexer.next().unwrap().unwrap();
         assert!(matches!(x.kind, TokenKind::LeftBracket));
         let x = lexer.next().unwrap().unwrap();
-        assert!(matches!(x.kind, TokenKind::Literal(..)));
+        assert!(matches!(x.kind, TokenKind::Literal(LiteralValue::UInt(1))));
         let x = lexer.next().unwrap().unwrap();
         assert!(matches!(x.kind, TokenKind::DotRange));
         let x = lexer.next().unwrap().unwrap();
@@ -941,6 +1285,30 @@ mod test {
         // test_literal_parse!("sint#-123", TokenKind::Literal(..), 9);
         test_literal_parse!("0.5", TokenKind::Literal(LiteralValue::LReal(..)), 3);
         // test_literal_parse!("-0.5", TokenKind::Literal(LiteralValue::LReal(..)), 4);
+        test_literal_parse!(
+            "T#10ms",
+            TokenKind::Literal(LiteralValue::Time(10_000_000)),
+            6
+        );
+        test_literal_parse!(
+            "time#1m_30s",
+            TokenKind::Literal(LiteralValue::Time(90_000_000_000)),
+            11
+        );
+        test_literal_parse!(
+            "t#1.5s",
+            TokenKind::Literal(LiteralValue::Time(1_500_000_000)),
+            6
+        );
+        test_literal_parse!(
+            "T#-250us",
+            TokenKind::Literal(LiteralValue::Time(-250_000)),
+            8
+        );
+        assert_eq!(
+            LiteralValue::Time(3_723_000_000_000).to_string(),
+            "TIME#1h2m3s"
+        );
     }
 
     #[test]
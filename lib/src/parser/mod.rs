@@ -1,4 +1,6 @@
 use crate::prelude::*;
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
 
 mod buffer;
 
@@ -16,6 +18,12 @@ pub use operator::Operator;
 mod token;
 pub use token::{Location, TokenKind};
 
+mod pragma;
+pub use pragma::*;
+
+mod preprocess;
+pub use preprocess::{CompilerDefines, SkippedRegion};
+
 #[macro_export]
 macro_rules! parse_statement {
     ($code: literal) => {{
@@ -43,6 +51,51 @@ impl ParseError {
 
         Self::UnexpectedToken(loc, tokens)
     }
+
+    /// Location of error in the parsed text, None for errors at the end of text
+    pub fn location(&self) -> Option<Location> {
+        match self {
+            ParseError::LexerError(
+                LexicalError::UnexpectedCharacter(mark, offset, _)
+                | LexicalError::InvalidDirective(mark, offset),
+            ) => Some(Location {
+                mark: *mark,
+                offset: *offset,
+            }),
+            ParseError::InvalidToken(loc) | ParseError::UnexpectedToken(loc, _) => Some(*loc),
+            _ => None,
+        }
+    }
+}
+
+impl Error for ParseError {}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::LexerError(e) => Display::fmt(e, f),
+            ParseError::UnexpectedEnd => f.write_str("unexpected end of input"),
+            ParseError::InvalidToken(loc) => write!(
+                f,
+                "invalid token at line {}, column {}",
+                loc.mark + 1,
+                loc.offset + 1
+            ),
+            ParseError::InvalidTokenAt(s) => write!(f, "invalid token: {}", s),
+            ParseError::UnexpectedToken(loc, expected) => {
+                write!(
+                    f,
+                    "unexpected token at line {}, column {}",
+                    loc.mark + 1,
+                    loc.offset + 1
+                )?;
+                if !expected.is_empty() {
+                    write!(f, ", expected one of: {}", expected.join(", "))?;
+                }
+                Ok(())
+            }
+        }
+    }
 }
 
 #[cfg(feature = "lalrpop_parser")]
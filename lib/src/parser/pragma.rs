@@ -0,0 +1,144 @@
+//! Pragmas in braces, like `{attribute 'qualified_only'}` or `{warning 'not tested'}`.
+//!
+//! Pragmas before declarations, variables and statements are stored in attributes of them,
+//! pragmas unknown to compiler are ignored.
+use crate::parser::StString;
+use crate::utils::HasAttribute;
+
+/// Members of enum must be qualified by the enum name, like `Color.Red`
+pub const QUALIFIED_ONLY_ATTRIBUTE: &str = "qualified_only";
+/// Alignment of struct members in bytes, like `{attribute 'pack_mode' := '1'}`
+pub const PACK_MODE_ATTRIBUTE: &str = "pack_mode";
+/// Implicit checks, like range checks of subrange values, are not generated
+pub const NO_CHECK_ATTRIBUTE: &str = "no_check";
+/// Messages of `{warning '...'}` pragmas, one message per line
+pub const WARNING_ATTRIBUTE: &str = "warning";
+/// Messages of `{error '...'}` pragmas, one message per line
+pub const ERROR_ATTRIBUTE: &str = "error";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Pragma {
+    /// `{attribute 'name'}` or `{attribute 'name' := 'value'}`
+    Attribute(StString, Option<String>),
+    /// `{warning 'message'}`
+    Warning(String),
+    /// `{error 'message'}`
+    Error(String),
+    Unknown,
+}
+
+impl Pragma {
+    /// Parse text between braces
+    pub fn parse(text: &str) -> Self {
+        parse_pragma(text).unwrap_or(Pragma::Unknown)
+    }
+}
+
+fn parse_pragma(text: &str) -> Option<Pragma> {
+    let text = text.trim();
+    let (keyword, mut rest) = text.split_once(char::is_whitespace).unwrap_or((text, ""));
+    let first = quoted_string(&mut rest)?;
+
+    let pragma = match keyword.to_ascii_lowercase().as_str() {
+        "attribute" => {
+            let value = match rest.trim_start().strip_prefix(":=") {
+                Some(value) => {
+                    rest = value;
+                    Some(quoted_string(&mut rest)?)
+                }
+                None => None,
+            };
+
+            Pragma::Attribute(first.into(), value)
+        }
+        "warning" => Pragma::Warning(first),
+        "error" => Pragma::Error(first),
+        _ => return None,
+    };
+
+    rest.trim().is_empty().then_some(pragma)
+}
+
+/// String in single quotes, `$'` and `$$` are escaped quote and dollar like ST strings
+fn quoted_string(s: &mut &str) -> Option<String> {
+    let text: &str = s;
+    let quoted = text.trim_start().strip_prefix('\'')?;
+    let start = text.len() - quoted.len();
+    let mut chars = quoted.char_indices();
+    let mut result = String::new();
+
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\'' => {
+                *s = &text[start + i + 1..];
+                return Some(result);
+            }
+            '$' => result.push(chars.next()?.1),
+            c => result.push(c),
+        }
+    }
+
+    None
+}
+
+/// Store pragmas into attributes of target, messages of the same kind are joined by lines
+pub fn apply_pragmas<T: HasAttribute>(target: &mut T, pragmas: &[String]) {
+    for pragma in pragmas {
+        match Pragma::parse(pragma) {
+            Pragma::Attribute(name, value) => target.set_attribute(name, value),
+            Pragma::Warning(message) => append_message(target, WARNING_ATTRIBUTE, message),
+            Pragma::Error(message) => append_message(target, ERROR_ATTRIBUTE, message),
+            Pragma::Unknown => {}
+        }
+    }
+}
+
+fn append_message<T: HasAttribute>(target: &mut T, kind: &str, message: String) {
+    let kind = StString::new(kind);
+    let message = match target.get_attribute_value(&kind) {
+        Some(Some(messages)) => format!("{}\n{}", messages, message),
+        _ => message,
+    };
+
+    target.set_attribute(kind, message);
+}
+
+/// Messages of `{warning}` or `{error}` pragmas stored in attributes
+pub fn pragma_messages<'a, T: HasAttribute>(
+    target: &'a T,
+    kind: &str,
+) -> impl Iterator<Item = &'a str> {
+    target
+        .get_attribute_value(&StString::new(kind))
+        .and_then(|x| x.as_deref())
+        .into_iter()
+        .flat_map(str::lines)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_pragma() {
+        assert_eq!(
+            Pragma::parse("attribute 'qualified_only'"),
+            Pragma::Attribute("qualified_only".into(), None)
+        );
+        assert_eq!(
+            Pragma::parse("attribute 'pack_mode' := '1'"),
+            Pragma::Attribute("pack_mode".into(), Some("1".to_owned()))
+        );
+        assert_eq!(
+            Pragma::parse("WARNING 'don$'t use'"),
+            Pragma::Warning("don't use".to_owned())
+        );
+        assert_eq!(
+            Pragma::parse("error 'not implemented'"),
+            Pragma::Error("not implemented".to_owned())
+        );
+        assert_eq!(Pragma::parse("attribute hide"), Pragma::Unknown);
+        assert_eq!(Pragma::parse("attribute 'a' 'b'"), Pragma::Unknown);
+        assert_eq!(Pragma::parse("library 'x'"), Pragma::Unknown);
+    }
+}
@@ -0,0 +1,434 @@
+//! Conditional compilation, like `{IF defined(SIMULATION)} ... {ELSE} ... {END_IF}`.
+//!
+//! Directives in braces are evaluated by lexer over the compiler defines, tokens of the branches
+//! not taken are skipped and never seen by parser. `{define NAME value}` and `{undefine NAME}`
+//! change the defines of the text follows them.
+//!
+//! Conditions are `defined(NAME)`, `hasvalue(NAME, 'value')` and
+//! `hastype(variable: NAME, TYPE)`, combined by `NOT`, `AND`, `OR` and parentheses.
+use crate::parser::{Location, StString};
+use std::collections::HashMap;
+
+/// Names defined for conditional compilation, with their values and types
+#[derive(Debug, Clone, Default)]
+pub struct CompilerDefines {
+    values: HashMap<StString, Option<String>>,
+    types: HashMap<StString, StString>,
+}
+
+impl CompilerDefines {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Define name, `hasvalue()` is true only for the value given
+    pub fn define<S: Into<StString>>(&mut self, name: S, value: Option<String>) {
+        self.values.insert(name.into(), value);
+    }
+
+    pub fn undefine(&mut self, name: &StString) {
+        self.values.remove(name);
+    }
+
+    /// Type of variable checked by `hastype()`
+    pub fn define_type<S: Into<StString>, T: Into<StString>>(&mut self, variable: S, ty: T) {
+        self.types.insert(variable.into(), ty.into());
+    }
+
+    pub fn is_defined(&self, name: &StString) -> bool {
+        self.values.contains_key(name)
+    }
+
+    pub fn value(&self, name: &StString) -> Option<&str> {
+        self.values.get(name).and_then(|x| x.as_deref())
+    }
+
+    pub fn type_of(&self, variable: &StString) -> Option<&StString> {
+        self.types.get(variable)
+    }
+}
+
+/// Text skipped by conditional compilation, the directives and the branches not taken.
+/// The end is exclusive.
+#[derive(Debug, Clone, Copy)]
+pub struct SkippedRegion {
+    pub start: Location,
+    pub end: Location,
+}
+
+/// Directive of conditional compilation, conditions are evaluated only if needed
+enum Directive<'a> {
+    Define(StString, Option<String>),
+    Undefine(StString),
+    If(&'a str),
+    ElseIf(&'a str),
+    Else,
+    EndIf,
+}
+
+impl<'a> Directive<'a> {
+    /// Directive of pragma text, None for the other pragmas
+    fn parse(text: &'a str) -> Option<Self> {
+        let end = text
+            .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+            .unwrap_or(text.len());
+        let (keyword, rest) = text.split_at(end);
+        let rest = rest.trim();
+
+        Some(match keyword.to_ascii_lowercase().as_str() {
+            "define" => {
+                let (name, value) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+                let value = value.trim();
+                Directive::Define(name.into(), (!value.is_empty()).then(|| value.to_owned()))
+            }
+            "undefine" => Directive::Undefine(rest.into()),
+            "if" => Directive::If(rest),
+            "elsif" => Directive::ElseIf(rest),
+            "else" if rest.is_empty() => Directive::Else,
+            "end_if" if rest.is_empty() => Directive::EndIf,
+            _ => return None,
+        })
+    }
+}
+
+/// `{IF}` directive being read
+struct Conditional {
+    /// One of the branches is taken, or the enclosing branch is skipped
+    taken: bool,
+    /// Tokens of current branch are read
+    active: bool,
+    /// `{ELSE}` is read
+    has_else: bool,
+}
+
+/// Evaluation of directives, for the lexer reads text of them
+pub(crate) struct Preprocessor {
+    defines: CompilerDefines,
+    conditionals: Vec<Conditional>,
+    skipped: Vec<SkippedRegion>,
+    /// Start of the skipped region being read
+    skipped_start: Option<Location>,
+}
+
+impl Preprocessor {
+    pub fn new(defines: CompilerDefines) -> Self {
+        Self {
+            defines,
+            conditionals: vec![],
+            skipped: vec![],
+            skipped_start: None,
+        }
+    }
+
+    /// Tokens read are not in the branches not taken
+    pub fn is_active(&self) -> bool {
+        self.conditionals.last().is_none_or(|x| x.active)
+    }
+
+    pub fn skipped_regions(&self) -> &[SkippedRegion] {
+        &self.skipped
+    }
+
+    /// Evaluate the pragma between locations if it's a directive, false for other pragmas.
+    /// Error if directive is invalid or out of place.
+    pub fn directive(&mut self, text: &str, start: Location, end: Location) -> Result<bool, ()> {
+        let Some(directive) = Directive::parse(text) else {
+            return Ok(false);
+        };
+
+        let active = self.is_active();
+        match directive {
+            Directive::Define(name, value) if active && is_name(&name) => {
+                self.defines.define(name, value)
+            }
+            Directive::Undefine(name) if active && is_name(&name) => self.defines.undefine(&name),
+            Directive::Define(..) | Directive::Undefine(_) if active => return Err(()),
+            Directive::Define(..) | Directive::Undefine(_) => {}
+            Directive::If(condition) => {
+                let value = active && self.evaluate(condition)?;
+                self.conditionals.push(Conditional {
+                    taken: value || !active,
+                    active: value,
+                    has_else: false,
+                });
+            }
+            Directive::ElseIf(condition) => {
+                let taken = match self.conditionals.last() {
+                    Some(x) if !x.has_else => x.taken,
+                    _ => return Err(()),
+                };
+                let value = !taken && self.evaluate(condition)?;
+
+                let conditional = self.conditionals.last_mut().unwrap();
+                conditional.taken |= value;
+                conditional.active = value;
+            }
+            Directive::Else => match self.conditionals.last_mut() {
+                Some(x) if !x.has_else => {
+                    x.active = !x.taken;
+                    x.taken = true;
+                    x.has_else = true;
+                }
+                _ => return Err(()),
+            },
+            Directive::EndIf => {
+                self.conditionals.pop().ok_or(())?;
+            }
+        }
+
+        // directive and the tokens skipped after it are in one region
+        self.skipped_start.get_or_insert(start);
+        if self.is_active() {
+            self.end_skipped(end);
+        }
+
+        Ok(true)
+    }
+
+    /// End of text, error if `{END_IF}` is missing
+    pub fn finish(&mut self, end: Location) -> Result<(), ()> {
+        self.end_skipped(end);
+
+        match self.conditionals.is_empty() {
+            true => Ok(()),
+            false => {
+                self.conditionals.clear();
+                Err(())
+            }
+        }
+    }
+
+    fn end_skipped(&mut self, end: Location) {
+        if let Some(start) = self.skipped_start.take() {
+            self.skipped.push(SkippedRegion { start, end });
+        }
+    }
+
+    fn evaluate(&self, condition: &str) -> Result<bool, ()> {
+        let tokens = condition_tokens(condition).ok_or(())?;
+        let mut evaluator = ConditionEvaluator {
+            defines: &self.defines,
+            tokens: &tokens,
+            pos: 0,
+        };
+
+        match evaluator.or_expression() {
+            Some(value) if evaluator.pos == tokens.len() => Ok(value),
+            _ => Err(()),
+        }
+    }
+}
+
+fn is_name(name: &StString) -> bool {
+    !name.is_empty()
+        && name
+            .as_ref()
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '_')
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum ConditionToken {
+    Word(StString),
+    String(String),
+    LeftParentheses,
+    RightParentheses,
+    Comma,
+    Colon,
+}
+
+fn condition_tokens(text: &str) -> Option<Vec<ConditionToken>> {
+    let mut tokens = vec![];
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        let tok = match c {
+            c if c.is_whitespace() => continue,
+            '(' => ConditionToken::LeftParentheses,
+            ')' => ConditionToken::RightParentheses,
+            ',' => ConditionToken::Comma,
+            ':' => ConditionToken::Colon,
+            '\'' => {
+                let mut s = String::new();
+                loop {
+                    match chars.next()? {
+                        '\'' => break,
+                        '$' => s.push(chars.next()?),
+                        c => s.push(c),
+                    }
+                }
+                ConditionToken::String(s)
+            }
+            c if c.is_alphanumeric() || c == '_' => {
+                let mut s = String::from(c);
+                while let Some(&c) = chars.peek() {
+                    if !c.is_alphanumeric() && c != '_' && c != '.' {
+                        break;
+                    }
+                    s.push(c);
+                    chars.next();
+                }
+                ConditionToken::Word(s.into())
+            }
+            _ => return None,
+        };
+
+        tokens.push(tok);
+    }
+
+    Some(tokens)
+}
+
+struct ConditionEvaluator<'a> {
+    defines: &'a CompilerDefines,
+    tokens: &'a [ConditionToken],
+    pos: usize,
+}
+
+impl ConditionEvaluator<'_> {
+    fn next(&mut self) -> Option<&ConditionToken> {
+        let tok = self.tokens.get(self.pos)?;
+        self.pos += 1;
+        Some(tok)
+    }
+
+    fn expect(&mut self, tok: ConditionToken) -> Option<()> {
+        (self.next()? == &tok).then_some(())
+    }
+
+    /// Next token is the keyword, which is consumed
+    fn keyword(&mut self, keyword: &str) -> bool {
+        match self.tokens.get(self.pos) {
+            Some(ConditionToken::Word(word)) if word == keyword => {
+                self.pos += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn word(&mut self) -> Option<StString> {
+        match self.next()? {
+            ConditionToken::Word(word) => Some(word.clone()),
+            _ => None,
+        }
+    }
+
+    fn or_expression(&mut self) -> Option<bool> {
+        let mut value = self.and_expression()?;
+        while self.keyword("OR") {
+            value |= self.and_expression()?;
+        }
+
+        Some(value)
+    }
+
+    fn and_expression(&mut self) -> Option<bool> {
+        let mut value = self.not_expression()?;
+        while self.keyword("AND") {
+            value &= self.not_expression()?;
+        }
+
+        Some(value)
+    }
+
+    fn not_expression(&mut self) -> Option<bool> {
+        if self.keyword("NOT") {
+            return Some(!self.not_expression()?);
+        }
+
+        if self.tokens.get(self.pos) == Some(&ConditionToken::LeftParentheses) {
+            self.pos += 1;
+            let value = self.or_expression()?;
+            self.expect(ConditionToken::RightParentheses)?;
+            return Some(value);
+        }
+
+        let function = self.word()?;
+        self.expect(ConditionToken::LeftParentheses)?;
+        let value = match function.as_ref().to_ascii_lowercase().as_str() {
+            "defined" => {
+                let name = self.word()?;
+                self.defines.is_defined(&name)
+            }
+            "hasvalue" => {
+                let name = self.word()?;
+                self.expect(ConditionToken::Comma)?;
+                let ConditionToken::String(value) = self.next()?.clone() else {
+                    return None;
+                };
+                self.defines.value(&name) == Some(value.as_str())
+            }
+            "hastype" => {
+                let mut variable = self.word()?;
+                if self.tokens.get(self.pos) == Some(&ConditionToken::Colon) {
+                    if variable != *"variable" {
+                        return None;
+                    }
+                    self.pos += 1;
+                    variable = self.word()?;
+                }
+                self.expect(ConditionToken::Comma)?;
+                let ty = self.word()?;
+                self.defines.type_of(&variable) == Some(&ty)
+            }
+            _ => return None,
+        };
+        self.expect(ConditionToken::RightParentheses)?;
+
+        Some(value)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_evaluate_condition() {
+        let mut defines = CompilerDefines::new();
+        defines.define("SIMULATION", None);
+        defines.define("MACHINE", Some("CX9020".to_owned()));
+        defines.define_type("speed", "LREAL");
+        let preprocessor = Preprocessor::new(defines);
+
+        let cases = [
+            ("defined(SIMULATION)", true),
+            ("defined (simulation)", true),
+            ("defined(REAL_IO)", false),
+            ("NOT defined(REAL_IO)", true),
+            ("defined(SIMULATION) AND NOT defined(REAL_IO)", true),
+            (
+                "defined(REAL_IO) OR defined(SIMULATION) AND defined(MACHINE)",
+                true,
+            ),
+            (
+                "(defined(REAL_IO) OR defined(SIMULATION)) AND defined(X)",
+                false,
+            ),
+            ("hasvalue(MACHINE, 'CX9020')", true),
+            ("hasvalue(MACHINE, 'CX5130')", false),
+            ("hasvalue(SIMULATION, '')", false),
+            ("hastype(variable: speed, LREAL)", true),
+            ("hastype(speed, lreal)", true),
+            ("hastype(speed, INT)", false),
+        ];
+        for (condition, value) in cases {
+            assert_eq!(preprocessor.evaluate(condition), Ok(value), "{}", condition);
+        }
+
+        for condition in [
+            "",
+            "defined",
+            "defined(A",
+            "defined(A) defined(B)",
+            "defined(A) AND",
+            "hasvalue(MACHINE, CX9020)",
+            "hastype(pou: speed, LREAL)",
+            "unknown(A)",
+            "defined(A) & defined(B)",
+        ] {
+            assert_eq!(preprocessor.evaluate(condition), Err(()), "{}", condition);
+        }
+    }
+}
@@ -1,3 +1,4 @@
+use crate::ast::{DeclKind, DeclareClass, ExprKind, StmtKind};
 use crate::parser::{ParserBuilder, StLexerBuilder};
 
 #[test]
@@ -9,6 +10,82 @@ pub fn test_parse_if_statement() {
     assert!(parser.parse_stmt(&mut lexer).is_ok())
 }
 
+#[test]
+pub fn test_parse_qualified_call() {
+    let st = "Motion.Axis.Limit(x);";
+    let mut lexer = StLexerBuilder::new().build_str(st);
+    let parser = ParserBuilder::default().build();
+    let stmt = parser.parse_stmt(&mut lexer).unwrap();
+
+    // both parsers nest member access to the left: ((Motion.Axis).Limit)(x)
+    let StmtKind::Expr(expr) = &stmt.kind else {
+        panic!("expression statement expected");
+    };
+    let ExprKind::Call(call) = &expr.expr().kind else {
+        panic!("call expected");
+    };
+    let ExprKind::Compo(callee) = &call.callee().kind else {
+        panic!("member access expected");
+    };
+    assert!(matches!(callee.left().kind, ExprKind::Compo(_)));
+    assert!(matches!(callee.right().kind, ExprKind::Variable(_)));
+    assert_eq!(call.arguments().len(), 1);
+}
+
+#[test]
+pub fn test_parse_member_declarations() {
+    let sources = [
+        "METHOD Reset : BOOL VAR_INPUT force: BOOL; END_VAR END_METHOD",
+        "METHOD Stop END_METHOD",
+        "PROPERTY Speed : INT END_PROPERTY",
+        "ACTION Run END_ACTION",
+    ];
+    let parser = ParserBuilder::default().build();
+    let decls: Vec<_> = sources
+        .iter()
+        .map(|x| {
+            let mut lexer = StLexerBuilder::new().build_str(x);
+            let decl = parser.parse_decl(&mut lexer).unwrap();
+            let DeclKind::Fun(fun) = decl.kind else {
+                panic!("function declaration expected");
+            };
+            fun
+        })
+        .collect();
+
+    assert!(matches!(decls[0].class(), DeclareClass::Method));
+    assert!(decls[0].return_type().is_some());
+    assert_eq!(decls[0].parameters().len(), 1);
+    assert!(matches!(decls[1].class(), DeclareClass::Method));
+    assert!(decls[1].return_type().is_none());
+    assert!(matches!(decls[2].class(), DeclareClass::Property));
+    assert!(decls[2].return_type().is_some());
+    assert!(matches!(decls[3].class(), DeclareClass::Action));
+
+    // property must have type
+    let mut lexer = StLexerBuilder::new().build_str("PROPERTY Speed END_PROPERTY");
+    assert!(parser.parse_decl(&mut lexer).is_err());
+}
+
+#[test]
+pub fn test_parse_nested_expression() {
+    let parser = ParserBuilder::default().build();
+    let nested = |n: usize| format!("b := {}a{};", "a + (".repeat(n), ")".repeat(n));
+
+    let st = nested(120);
+    let mut lexer = StLexerBuilder::new().build_str(&st);
+    assert!(parser.parse_stmt(&mut lexer).is_ok());
+
+    // too deep nesting is a parse error of the default parser, not overflow of stack
+    #[cfg(not(feature = "lalrpop_parser"))]
+    {
+        let st = nested(200);
+        let mut lexer = StLexerBuilder::new().build_str(&st);
+        let err = parser.parse_stmt(&mut lexer).unwrap_err();
+        assert!(err.to_string().contains("nested deeper than"), "{}", err);
+    }
+}
+
 // #[test]
 // pub fn test_parser() {
 //     let st = "print(os.clock());";
@@ -141,6 +141,18 @@ pub enum TokenKind {
     FunctionBlock,
     /// 'END_FUNCTION_BLOCK'
     EndFunctionBlock,
+    /// 'METHOD'
+    Method,
+    /// 'END_METHOD'
+    EndMethod,
+    /// 'PROPERTY'
+    Property,
+    /// 'END_PROPERTY'
+    EndProperty,
+    /// 'ACTION'
+    Action,
+    /// 'END_ACTION'
+    EndAction,
     /// 'STRUCT'
     Struct,
     /// 'END_STRUCT'
@@ -165,10 +177,24 @@ pub enum TokenKind {
     Retain,
     /// 'PERSISTENT'
     Persistent,
+    /// 'CONSTANT'
+    Constant,
     /// 'TYPE'
     Type,
     /// 'END_TYPE'
     EndType,
+    /// 'CONFIGURATION'
+    Configuration,
+    /// 'END_CONFIGURATION'
+    EndConfiguration,
+    /// 'NAMESPACE'
+    Namespace,
+    /// 'END_NAMESPACE'
+    EndNamespace,
+    /// 'USING'
+    Using,
+    /// 'END_RESOURCE'
+    EndResource,
     /// 'SizeOf' Operator
     SizeOf,
     /// 'Adr' Operator
@@ -199,7 +225,7 @@ pub enum TokenKind {
     Real,
     /// 'LREAL', 64 bits unsigned
     LReal,
-    /// 'TIME' 32 bits time
+    /// 'TIME', 64 bits duration in nanoseconds
     Time,
     /// 'LTIME' 64 bits time
     LTime,
@@ -209,6 +235,8 @@ pub enum TokenKind {
     Literal(LiteralValue),
     /// Identifier
     Identifier(StString),
+    /// Pragma in braces, like '{attribute 'hide'}', text between braces is trimmed
+    Pragma(String),
 }
 
 impl TokenKind {
@@ -222,8 +250,13 @@ impl TokenKind {
                 | TokenKind::USInt
                 | TokenKind::Int
                 | TokenKind::UInt
+                | TokenKind::DInt
+                | TokenKind::UDInt
+                | TokenKind::LInt
+                | TokenKind::ULInt
                 | TokenKind::Real
                 | TokenKind::LReal
+                | TokenKind::Time
                 | TokenKind::String
         )
     }
@@ -252,42 +285,9 @@ impl TokenKind {
         )
     }
 
+    /// Same kind of token, the values of literals and identifiers are ignored
     pub fn kind_match(&self, rhs: &TokenKind) -> bool {
-        match *self {
-            TokenKind::AssignRight => matches!(rhs, TokenKind::AssignRight),
-            TokenKind::Assign => matches!(rhs, TokenKind::Assign),
-            TokenKind::Int => matches!(rhs, TokenKind::Int),
-            TokenKind::Byte => matches!(rhs, TokenKind::Byte),
-            TokenKind::Function => matches!(rhs, TokenKind::Function),
-            TokenKind::EndFunction => matches!(rhs, TokenKind::EndFunction),
-            TokenKind::Program => matches!(rhs, TokenKind::Program),
-            TokenKind::EndProgram => matches!(rhs, TokenKind::EndProgram),
-            TokenKind::Colon => matches!(rhs, TokenKind::Colon),
-            TokenKind::Comma => matches!(rhs, TokenKind::Comma),
-            TokenKind::Semicolon => matches!(rhs, TokenKind::Semicolon),
-            TokenKind::Type => matches!(rhs, TokenKind::Type),
-            TokenKind::EndType => matches!(rhs, TokenKind::EndType),
-            TokenKind::Var => matches!(rhs, TokenKind::Var),
-            TokenKind::VarGlobal => matches!(rhs, TokenKind::VarGlobal),
-            TokenKind::EndVar => matches!(rhs, TokenKind::EndVar),
-            TokenKind::Struct => matches!(rhs, TokenKind::Struct),
-            TokenKind::EndStruct => matches!(rhs, TokenKind::EndStruct),
-            TokenKind::LeftParentheses => matches!(rhs, TokenKind::LeftParentheses),
-            TokenKind::RightParentheses => matches!(rhs, TokenKind::RightParentheses),
-            TokenKind::If => matches!(rhs, TokenKind::If),
-            TokenKind::Then => matches!(rhs, TokenKind::Then),
-            TokenKind::Else => matches!(rhs, TokenKind::Else),
-            TokenKind::ElseIf => matches!(rhs, TokenKind::ElseIf),
-            TokenKind::EndIf => matches!(rhs, TokenKind::EndIf),
-            TokenKind::Array => matches!(rhs, TokenKind::Array),
-            TokenKind::LeftBracket => matches!(rhs, TokenKind::LeftBracket),
-            TokenKind::RightBracket => matches!(rhs, TokenKind::RightBracket),
-            TokenKind::Of => matches!(rhs, TokenKind::Of),
-            TokenKind::DotRange => matches!(rhs, TokenKind::DotRange),
-            TokenKind::DotAccess => matches!(rhs, TokenKind::DotAccess),
-            TokenKind::Identifier(..) => matches!(rhs, TokenKind::Identifier(..)),
-            _ => panic!("Token {self} not handle"),
-        }
+        std::mem::discriminant(self) == std::mem::discriminant(rhs)
     }
 
     pub fn is_operator(&self) -> bool {
@@ -369,6 +369,12 @@ impl From<&TokenKind> for String {
             TokenKind::EndProgram => "END_PROGRAM",
             TokenKind::FunctionBlock => "FUNCTION_BLOCK",
             TokenKind::EndFunctionBlock => "END_FUNCTION_BLOCK",
+            TokenKind::Method => "METHOD",
+            TokenKind::EndMethod => "END_METHOD",
+            TokenKind::Property => "PROPERTY",
+            TokenKind::EndProperty => "END_PROPERTY",
+            TokenKind::Action => "ACTION",
+            TokenKind::EndAction => "END_ACTION",
             TokenKind::Struct => "STRUCT",
             TokenKind::EndStruct => "END_STRUCT",
             TokenKind::VarGlobal => "VAR_GLOBAL",
@@ -381,8 +387,15 @@ impl From<&TokenKind> for String {
             TokenKind::EndVar => "END_VAR",
             TokenKind::Retain => "RETAIN",
             TokenKind::Persistent => "PERSISTENT",
+            TokenKind::Constant => "CONSTANT",
             TokenKind::Type => "TYPE",
             TokenKind::EndType => "END_TYPE",
+            TokenKind::Configuration => "CONFIGURATION",
+            TokenKind::EndConfiguration => "END_CONFIGURATION",
+            TokenKind::Namespace => "NAMESPACE",
+            TokenKind::EndNamespace => "END_NAMESPACE",
+            TokenKind::Using => "USING",
+            TokenKind::EndResource => "END_RESOURCE",
             TokenKind::SizeOf => "SIZEOF",
             TokenKind::Adr => "ADR",
             TokenKind::Int => "INT",
@@ -412,6 +425,10 @@ impl From<&TokenKind> for String {
                 tmp_string.as_str()
             }
             TokenKind::Identifier(s) => s.string(),
+            TokenKind::Pragma(s) => {
+                tmp_string = format!("{{{}}}", s);
+                tmp_string.as_str()
+            }
         };
 
         s.to_owned()
@@ -0,0 +1,226 @@
+//! Cyclic task scheduler running in simulated time. Tasks are released at multiples of
+//! their intervals and run without preemption on a single processing unit: whenever the
+//! processor is free, the released task with the highest priority runs all its program
+//! instances. Execution times are reported by the executor, so a schedule is fully
+//! deterministic and timing dependent logic can be tested without hardware.
+
+#[cfg(test)]
+mod test;
+
+use crate::context::{ProgramInstance, TaskConfiguration};
+use crate::parser::StString;
+
+/// Executes program instances for scheduler, like the interpreter or a backend runtime
+pub trait CycleExecutor {
+    type Error;
+
+    /// Run one cycle of program instance at simulated time `now` in nanoseconds, returns
+    /// the execution time of cycle in nanoseconds
+    fn run_cycle(&mut self, instance: &ProgramInstance, now: u64) -> Result<u64, Self::Error>;
+}
+
+/// Timing statistics of task, times are in nanoseconds
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TaskStatistics {
+    pub cycles: u64,
+    /// Cycles not finished before the next release of task
+    pub overruns: u64,
+    /// Releases dropped because the previous release was still waiting or running
+    pub skipped: u64,
+    /// Delay from release to the start of cycle
+    pub last_jitter: u64,
+    pub max_jitter: u64,
+    pub last_execution: u64,
+    pub max_execution: u64,
+}
+
+/// Cycle executed by scheduler, times are in nanoseconds
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CycleRecord {
+    /// Index of task in configuration
+    pub task: usize,
+    pub release: u64,
+    pub start: u64,
+    pub end: u64,
+}
+
+#[derive(Debug, Default)]
+struct TaskState {
+    next_release: u64,
+    /// Release waiting for processor
+    pending: Option<u64>,
+    statistics: TaskStatistics,
+}
+
+pub struct Scheduler {
+    configuration: TaskConfiguration,
+    states: Vec<TaskState>,
+    now: u64,
+    record_cycles: bool,
+    records: Vec<CycleRecord>,
+}
+
+impl Scheduler {
+    pub fn new(configuration: TaskConfiguration) -> Self {
+        let states = configuration
+            .tasks()
+            .iter()
+            .map(|_| TaskState::default())
+            .collect();
+
+        Self {
+            configuration,
+            states,
+            now: 0,
+            record_cycles: false,
+            records: vec![],
+        }
+    }
+
+    #[inline]
+    pub fn configuration(&self) -> &TaskConfiguration {
+        &self.configuration
+    }
+
+    /// Simulated time in nanoseconds
+    #[inline]
+    pub fn now(&self) -> u64 {
+        self.now
+    }
+
+    /// Keep every executed cycle in `records()`
+    pub fn set_record_cycles(&mut self, record: bool) {
+        self.record_cycles = record;
+    }
+
+    #[inline]
+    pub fn records(&self) -> &[CycleRecord] {
+        &self.records
+    }
+
+    #[inline]
+    pub fn statistics(&self, task: usize) -> Option<&TaskStatistics> {
+        self.states.get(task).map(|x| &x.statistics)
+    }
+
+    pub fn task_statistics(&self, name: &str) -> Option<&TaskStatistics> {
+        self.statistics(self.configuration.find_task(&StString::new(name))?)
+    }
+
+    /// Run tasks for duration in nanoseconds
+    pub fn run_for<E: CycleExecutor>(
+        &mut self,
+        executor: &mut E,
+        duration: u64,
+    ) -> Result<(), E::Error> {
+        self.run_until(executor, self.now.saturating_add(duration))
+    }
+
+    /// Run tasks until time in nanoseconds, cycles are only started before `end`. An error
+    /// of executor stops the scheduler at the failed cycle.
+    pub fn run_until<E: CycleExecutor>(
+        &mut self,
+        executor: &mut E,
+        end: u64,
+    ) -> Result<(), E::Error> {
+        loop {
+            self.release_tasks();
+
+            if self.now >= end {
+                return Ok(());
+            }
+
+            match self.next_task() {
+                Some(task) => self.run_task(executor, task)?,
+                // idle until the next release
+                None => match self.next_release() {
+                    Some(t) if t < end => self.now = t,
+                    _ => {
+                        self.now = end;
+                        return Ok(());
+                    }
+                },
+            }
+        }
+    }
+
+    /// Release all tasks which interval elapsed
+    fn release_tasks(&mut self) {
+        for (info, state) in self
+            .configuration
+            .tasks()
+            .iter()
+            .zip(self.states.iter_mut())
+        {
+            let Some(interval) = info.interval else {
+                continue;
+            };
+
+            while state.next_release <= self.now {
+                if state.pending.is_some() {
+                    state.statistics.skipped += 1;
+                } else {
+                    state.pending = Some(state.next_release);
+                }
+                state.next_release += interval;
+            }
+        }
+    }
+
+    /// Released task of the highest priority, tasks of the same priority are in
+    /// declaration order
+    fn next_task(&self) -> Option<usize> {
+        self.states
+            .iter()
+            .enumerate()
+            .filter(|(_, state)| state.pending.is_some())
+            .min_by_key(|(idx, _)| (self.configuration.tasks()[*idx].priority, *idx))
+            .map(|(idx, _)| idx)
+    }
+
+    fn next_release(&self) -> Option<u64> {
+        self.configuration
+            .tasks()
+            .iter()
+            .zip(self.states.iter())
+            .filter(|(info, _)| info.interval.is_some())
+            .map(|(_, state)| state.next_release)
+            .min()
+    }
+
+    fn run_task<E: CycleExecutor>(
+        &mut self,
+        executor: &mut E,
+        task: usize,
+    ) -> Result<(), E::Error> {
+        let release = self.states[task].pending.take().unwrap();
+        let start = self.now;
+
+        for instance in self.configuration.task_instances(task) {
+            self.now += executor.run_cycle(instance, self.now)?;
+        }
+
+        let end = self.now;
+        let interval = self.configuration.tasks()[task].interval.unwrap_or(0);
+        let statistics = &mut self.states[task].statistics;
+        statistics.cycles += 1;
+        statistics.last_jitter = start - release;
+        statistics.max_jitter = statistics.max_jitter.max(start - release);
+        statistics.last_execution = end - start;
+        statistics.max_execution = statistics.max_execution.max(end - start);
+        if end > release + interval {
+            statistics.overruns += 1;
+        }
+
+        if self.record_cycles {
+            self.records.push(CycleRecord {
+                task,
+                release,
+                start,
+                end,
+            });
+        }
+
+        Ok(())
+    }
+}
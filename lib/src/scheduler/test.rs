@@ -0,0 +1,284 @@
+use super::*;
+use crate::context::{ConfigurationError, ModuleContext, ModuleKind, UnitsManager};
+use crate::interpreter::Interpreter;
+use crate::parser::*;
+use crate::prelude::*;
+
+/// Load application with POUs and configuration, each POU is (declaration, body)
+fn load_application(pous: &[(&str, &str)]) -> (UnitsManager, ModuleContext) {
+    let mgr = UnitsManager::new();
+    let ctx = ModuleContext::new(ModuleKind::Application);
+
+    for (decl, body) in pous {
+        let mut lexer = StLexerBuilder::new().build_str(decl);
+        let decl = ParserBuilder::default()
+            .build()
+            .parse_decl(&mut lexer)
+            .unwrap();
+        let fun_id = ctx.write().add_declaration(decl, Uuid::new_v4());
+
+        if body.is_empty() {
+            continue;
+        }
+
+        let mut lexer = StLexerBuilder::new().build_str(body);
+        let body = ParserBuilder::default()
+            .build()
+            .parse_stmt(&mut lexer)
+            .unwrap();
+        ctx.write().add_function(fun_id, body);
+    }
+
+    mgr.write().add_context(ctx.clone());
+    mgr.write().set_active_application(Some(ctx.read().id()));
+
+    (mgr, ctx)
+}
+
+/// Executor with fixed execution time of each program
+struct FixedTimeExecutor {
+    times: Vec<(&'static str, u64)>,
+    cycles: Vec<(StString, u64)>,
+}
+
+impl CycleExecutor for FixedTimeExecutor {
+    type Error = ();
+
+    fn run_cycle(&mut self, instance: &ProgramInstance, now: u64) -> Result<u64, ()> {
+        self.cycles.push((instance.name.clone(), now));
+        self.times
+            .iter()
+            .find(|(name, _)| instance.program == **name)
+            .map(|(_, time)| *time)
+            .ok_or(())
+    }
+}
+
+const MS: u64 = 1_000_000;
+
+const COUNTERS: &[(&str, &str)] = &[
+    (
+        "PROGRAM Fast: VAR count: INT; END_VAR END_PROGRAM",
+        "count := count + 1; total := total + 1;",
+    ),
+    (
+        "PROGRAM Slow: VAR count: INT; END_VAR END_PROGRAM",
+        "count := count + 1; total := total + 1;",
+    ),
+    (
+        "CONFIGURATION Cfg \
+        VAR_GLOBAL total: INT; END_VAR \
+        RESOURCE Res ON PLC \
+            TASK FastTask(INTERVAL := T#10ms, PRIORITY := 1); \
+            TASK SlowTask(INTERVAL := T#50ms, PRIORITY := 2); \
+            PROGRAM Fast1 WITH FastTask : Fast; \
+            PROGRAM Fast2 WITH FastTask : Fast; \
+            PROGRAM Slow1 WITH SlowTask : Slow; \
+            PROGRAM Idle : Slow; \
+        END_RESOURCE \
+        END_CONFIGURATION",
+        "",
+    ),
+];
+
+fn configuration(pous: &[(&str, &str)]) -> Result<TaskConfiguration, ConfigurationError> {
+    let (_, app) = load_application(pous);
+    TaskConfiguration::from_application(&app, None)
+}
+
+#[test]
+fn test_configuration() {
+    let cfg = configuration(COUNTERS).unwrap();
+
+    assert_eq!(cfg.name(), "Cfg");
+    assert_eq!(cfg.tasks().len(), 2);
+    assert_eq!(&cfg.tasks()[0].resource, "Res");
+    assert_eq!(cfg.tasks()[0].interval, Some(10 * MS));
+    assert_eq!(cfg.tasks()[1].interval, Some(50 * MS));
+    assert_eq!(cfg.tasks()[1].priority, 2);
+
+    let fast: Vec<_> = cfg.task_instances(0).map(|x| x.name.string()).collect();
+    assert_eq!(fast, ["Fast1", "Fast2"]);
+    assert_eq!(
+        cfg.find_instance(&StString::new("idle")).unwrap().task,
+        None
+    );
+}
+
+#[test]
+fn test_configuration_errors() {
+    let program = ("PROGRAM Main: END_PROGRAM", "");
+    let cases = [
+        (
+            "CONFIGURATION Cfg RESOURCE Res ON PLC \
+            PROGRAM Main1 WITH Missing : Main; \
+            END_RESOURCE END_CONFIGURATION",
+            "task 'Missing' of program 'Main1' not found",
+        ),
+        (
+            "CONFIGURATION Cfg RESOURCE Res ON PLC \
+            TASK T1(INTERVAL := x); \
+            END_RESOURCE END_CONFIGURATION",
+            "invalid parameter 'INTERVAL' of task 'T1'",
+        ),
+        (
+            "CONFIGURATION Cfg RESOURCE Res ON PLC \
+            TASK T1(INTERVAL := T#0s); \
+            END_RESOURCE END_CONFIGURATION",
+            "invalid parameter 'INTERVAL' of task 'T1'",
+        ),
+        (
+            "CONFIGURATION Cfg RESOURCE Res ON PLC \
+            PROGRAM Main1 : Other; \
+            END_RESOURCE END_CONFIGURATION",
+            "program 'Other' of instance 'Main1' not found",
+        ),
+        (
+            "CONFIGURATION Cfg RESOURCE Res ON PLC \
+            TASK T1(INTERVAL := T#1ms); TASK T1(INTERVAL := T#2ms); \
+            END_RESOURCE END_CONFIGURATION",
+            "'T1' declared twice",
+        ),
+    ];
+
+    for (cfg, expected) in cases {
+        let err = configuration(&[program, (cfg, "")]).unwrap_err();
+        assert_eq!(err.to_string(), expected);
+    }
+
+    let err = configuration(&[program]).unwrap_err();
+    assert_eq!(err, ConfigurationError::NotFound(None));
+}
+
+#[test]
+fn test_priority_and_jitter() {
+    let mut scheduler = Scheduler::new(configuration(COUNTERS).unwrap());
+    scheduler.set_record_cycles(true);
+
+    let mut executor = FixedTimeExecutor {
+        times: vec![("Fast", MS), ("Slow", 3 * MS)],
+        cycles: vec![],
+    };
+    scheduler.run_for(&mut executor, 100 * MS).unwrap();
+    assert_eq!(scheduler.now(), 100 * MS);
+
+    // both tasks released at 0, the fast task runs first and delays the slow task
+    assert_eq!(
+        &scheduler.records()[..2],
+        &[
+            CycleRecord {
+                task: 0,
+                release: 0,
+                start: 0,
+                end: 2 * MS
+            },
+            CycleRecord {
+                task: 1,
+                release: 0,
+                start: 2 * MS,
+                end: 5 * MS
+            },
+        ]
+    );
+    assert_eq!(
+        &executor.cycles[..3],
+        &[
+            (StString::new("Fast1"), 0),
+            (StString::new("Fast2"), MS),
+            (StString::new("Slow1"), 2 * MS),
+        ]
+    );
+
+    let fast = scheduler.task_statistics("FastTask").unwrap();
+    assert_eq!(fast.cycles, 10);
+    assert_eq!(fast.max_jitter, 0);
+    assert_eq!(fast.max_execution, 2 * MS);
+    assert_eq!(fast.overruns, 0);
+
+    let slow = scheduler.task_statistics("SlowTask").unwrap();
+    assert_eq!(slow.cycles, 2);
+    assert_eq!(slow.max_jitter, 2 * MS);
+    assert_eq!(slow.last_execution, 3 * MS);
+    assert_eq!(slow.overruns, 0);
+}
+
+#[test]
+fn test_overrun() {
+    let mut scheduler = Scheduler::new(configuration(COUNTERS).unwrap());
+    let mut executor = FixedTimeExecutor {
+        times: vec![("Fast", MS), ("Slow", 25 * MS)],
+        cycles: vec![],
+    };
+    scheduler.run_for(&mut executor, 100 * MS).unwrap();
+
+    // the slow task blocks the processor for 25ms, the fast task misses releases
+    let fast = scheduler.task_statistics("FastTask").unwrap();
+    assert_eq!(fast.max_jitter, 17 * MS);
+    assert_eq!(fast.overruns, 2);
+    assert_eq!(fast.skipped, 2);
+    assert_eq!(fast.cycles, 8);
+
+    let slow = scheduler.task_statistics("SlowTask").unwrap();
+    assert_eq!(slow.cycles, 2);
+    assert_eq!(slow.overruns, 0);
+}
+
+#[test]
+fn test_executor_error() {
+    let mut scheduler = Scheduler::new(configuration(COUNTERS).unwrap());
+    let mut executor = FixedTimeExecutor {
+        times: vec![("Fast", MS)],
+        cycles: vec![],
+    };
+
+    assert_eq!(scheduler.run_for(&mut executor, 100 * MS), Err(()));
+    assert_eq!(scheduler.now(), 2 * MS);
+}
+
+#[test]
+fn test_interpreter() {
+    let (mgr, app) = load_application(COUNTERS);
+    let mut scheduler = Scheduler::new(TaskConfiguration::from_application(&app, None).unwrap());
+    let mut interpreter = Interpreter::new(mgr, app);
+    interpreter.init().unwrap();
+
+    scheduler.run_for(&mut interpreter, 100 * MS).unwrap();
+    scheduler.run_for(&mut interpreter, 100 * MS).unwrap();
+
+    let value = |name: &str| interpreter.variable(name).unwrap().to_string();
+    assert_eq!(value("Fast1.count"), "20");
+    assert_eq!(value("Fast2.count"), "20");
+    assert_eq!(value("Slow1.count"), "4");
+    assert_eq!(value("total"), "44");
+    assert!(interpreter.variable("Idle.count").is_none());
+
+    // two statements of 1us each
+    let fast = scheduler.task_statistics("FastTask").unwrap();
+    assert_eq!(fast.max_execution, 4_000);
+    assert_eq!(interpreter.time(), 190 * MS + 2_000);
+}
+
+#[cfg(feature = "lua_backend")]
+#[test]
+fn test_lua_executor() {
+    use crate::backend::LuaExecutor;
+
+    let (mgr, app) = load_application(COUNTERS);
+    let mut scheduler = Scheduler::new(TaskConfiguration::from_application(&app, None).unwrap());
+    let mut executor = LuaExecutor::new(mgr, app).unwrap();
+
+    scheduler.run_for(&mut executor, 100 * MS).unwrap();
+    scheduler.run_for(&mut executor, 100 * MS).unwrap();
+
+    let value = |name: &str| executor.variable(name).unwrap().as_i64();
+    assert_eq!(value("Fast1.count"), Some(20));
+    assert_eq!(value("Fast2.count"), Some(20));
+    assert_eq!(value("Slow1.count"), Some(4));
+    assert_eq!(value("total"), Some(44));
+    assert_eq!(value("Idle.count"), None);
+
+    // instructions of 100ns each, the last cycle of Fast2 starts after Fast1
+    let fast = scheduler.task_statistics("FastTask").unwrap();
+    assert_eq!(fast.last_execution, 2 * 3_500);
+    assert_eq!(executor.time(), 190 * MS + 3_500);
+}
@@ -0,0 +1,380 @@
+//! Cache of incremental builds. Declarations of application are units of the cache, with
+//! their hashes by `AstHasher`, the names they use, the errors of type analysis and the code
+//! saved by backend. A unit is reused if neither its hashes nor the interfaces of the names
+//! it uses are changed since the last build.
+//!
+//! All numbers are little endian, strings and byte arrays are prefixed by u32 length:
+//!
+//! ```text
+//! magic "STCC", u32 format version, u32 CRC32 of the rest of file
+//! u64 key, u64 layout
+//! u32 count, units: name, u64 interface hash, u8 body flag, u64 body hash if flag is 1,
+//!                   u32 count, interface dependencies, u32 count, body dependencies,
+//!                   u8 diagnostics flag, u32 count, diagnostics if flag is 1,
+//!                   u8 code flag, code bytes if flag is 1
+//! ```
+use super::library::{invalid_data, read_bytes, read_string, write_bytes, write_string};
+use crate::analysis::{declaration_dependencies, variable_dependencies, Dependencies};
+use crate::ast::*;
+use crate::context::{ModuleContext, Prototype};
+use crate::parser::StString;
+use crate::utils::{AstHasher, Crc32Hasher};
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use crc::{Crc, CRC_32_ISO_HDLC};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::io::{self, Read, Write};
+use std::path::Path;
+
+/// Magic number of cache file
+const CACHE_MAGIC: &[u8; 4] = b"STCC";
+/// Version of cache file format, files of other versions are discarded
+pub const CACHE_FORMAT_VERSION: u32 = 1;
+
+const CRC32: Crc<u32> = Crc::<u32>::new(&CRC_32_ISO_HDLC);
+
+/// Declaration or global variable of application
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CacheUnit {
+    pub name: StString,
+    /// Hash of declaration or global variable by `AstHasher`
+    pub interface_hash: u64,
+    /// Hash of body by `AstHasher`, None for data types and global variables
+    pub body_hash: Option<u64>,
+    pub dependencies: Dependencies,
+    /// Errors of type analysis of body, None if body isn't analysed
+    pub diagnostics: Option<Vec<String>>,
+    /// Code of body saved by backend, None if it isn't generated
+    pub code: Option<Vec<u8>>,
+}
+
+impl CacheUnit {
+    /// Units of declaration, global variable lists have a unit for each variable
+    fn from_declaration(ctx: &ModuleContext, proto: &Prototype) -> Vec<Self> {
+        let proto = proto.read().unwrap();
+        let decl = proto.decl();
+
+        if let DeclKind::GlobalVar(global) = &decl.kind {
+            return global
+                .variables()
+                .iter()
+                .map(|variable| Self {
+                    name: qualified_name(decl.namespace(), variable.name()),
+                    interface_hash: AstHasher::new(Crc32Hasher::new()).calc_variable(variable),
+                    body_hash: None,
+                    dependencies: Dependencies {
+                        interface: variable_dependencies(variable),
+                        body: BTreeSet::new(),
+                    },
+                    diagnostics: None,
+                    code: None,
+                })
+                .collect();
+        }
+
+        let f = ctx.read().get_function(proto.id()).cloned();
+        let f = f.as_ref().map(|x| x.read());
+        let body = f.as_ref().map(|x| x.parse_tree());
+
+        vec![Self {
+            name: decl.qualified_name(),
+            interface_hash: AstHasher::new(Crc32Hasher::new()).calc_declaration(decl),
+            body_hash: body.map(|x| AstHasher::new(Crc32Hasher::new()).calc_statement(x)),
+            dependencies: declaration_dependencies(decl, body),
+            diagnostics: None,
+            code: None,
+        }]
+    }
+
+    fn write(&self, w: &mut dyn Write) -> io::Result<()> {
+        write_string(w, self.name.as_ref())?;
+        w.write_u64::<LittleEndian>(self.interface_hash)?;
+        match self.body_hash {
+            Some(hash) => {
+                w.write_u8(1)?;
+                w.write_u64::<LittleEndian>(hash)?;
+            }
+            None => w.write_u8(0)?,
+        }
+
+        for names in [&self.dependencies.interface, &self.dependencies.body] {
+            w.write_u32::<LittleEndian>(names.len() as u32)?;
+            for name in names {
+                write_string(w, name.as_ref())?;
+            }
+        }
+
+        match &self.diagnostics {
+            Some(diagnostics) => {
+                w.write_u8(1)?;
+                w.write_u32::<LittleEndian>(diagnostics.len() as u32)?;
+                for diagnostic in diagnostics {
+                    write_string(w, diagnostic)?;
+                }
+            }
+            None => w.write_u8(0)?,
+        }
+
+        match &self.code {
+            Some(code) => {
+                w.write_u8(1)?;
+                write_bytes(w, code)
+            }
+            None => w.write_u8(0),
+        }
+    }
+
+    fn read(r: &mut dyn Read) -> io::Result<Self> {
+        let name = read_string(r)?.into();
+        let interface_hash = r.read_u64::<LittleEndian>()?;
+        let body_hash = match r.read_u8()? {
+            0 => None,
+            _ => Some(r.read_u64::<LittleEndian>()?),
+        };
+
+        let mut dependencies = Dependencies::default();
+        for names in [&mut dependencies.interface, &mut dependencies.body] {
+            for _ in 0..r.read_u32::<LittleEndian>()? {
+                names.insert(read_string(r)?.into());
+            }
+        }
+
+        let diagnostics = match r.read_u8()? {
+            0 => None,
+            _ => {
+                let count = r.read_u32::<LittleEndian>()?;
+                Some(
+                    (0..count)
+                        .map(|_| read_string(r))
+                        .collect::<io::Result<_>>()?,
+                )
+            }
+        };
+
+        let code = match r.read_u8()? {
+            0 => None,
+            _ => Some(read_bytes(r)?),
+        };
+
+        Ok(Self {
+            name,
+            interface_hash,
+            body_hash,
+            dependencies,
+            diagnostics,
+            code,
+        })
+    }
+}
+
+/// Name of global variable in namespace
+fn qualified_name(namespace: &StString, name: &StString) -> StString {
+    if namespace.is_empty() {
+        name.clone()
+    } else {
+        StString::new(format!("{}.{}", namespace, name))
+    }
+}
+
+/// Whether name used by unit may refer to one of the units, names are resolved by namespaces
+/// of unit, so 'MoveAbs' or 'Axis.MoveAbs' may refer to 'Motion.Axis.MoveAbs'
+fn uses(units: &BTreeSet<StString>, name: &StString) -> bool {
+    let suffix = format!(".{}", name).to_ascii_uppercase();
+    units.contains(name)
+        || units
+            .iter()
+            .any(|x| x.string().to_ascii_uppercase().ends_with(&suffix))
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BuildCache {
+    /// Hash of compiler version and options, cache of other key is discarded
+    key: u64,
+    /// Hash of names of POUs ordered by declaration id, codes are discarded if it's changed
+    layout: u64,
+    units: BTreeMap<StString, CacheUnit>,
+}
+
+impl BuildCache {
+    pub fn new(key: u64) -> Self {
+        Self {
+            key,
+            layout: 0,
+            units: BTreeMap::new(),
+        }
+    }
+
+    /// Read cache file, an empty cache is returned if the file can't be read or it's written
+    /// with other key
+    pub fn load<P: AsRef<Path>>(path: P, key: u64) -> Self {
+        let cache = fs::File::open(path).and_then(|mut file| Self::read(&mut file));
+        match cache {
+            Ok(cache) if cache.key == key => cache,
+            _ => Self::new(key),
+        }
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut buf = vec![];
+        self.write(&mut buf)?;
+        fs::write(path, buf)
+    }
+
+    #[inline]
+    pub fn key(&self) -> u64 {
+        self.key
+    }
+
+    #[inline]
+    pub fn units(&self) -> impl Iterator<Item = &CacheUnit> {
+        self.units.values()
+    }
+
+    #[inline]
+    pub fn unit(&self, name: &StString) -> Option<&CacheUnit> {
+        self.units.get(name)
+    }
+
+    /// Replace units by the declarations of application. Results of units are kept if they're
+    /// reused, returns the names of reused units with body.
+    /// Types of expressions are hashed, so it's called before type analysis.
+    pub fn update(&mut self, app: &ModuleContext) -> Vec<StString> {
+        let mut decls: Vec<_> = app.read().declarations().cloned().collect();
+        decls.sort_by_key(|x| x.read().unwrap().id());
+
+        let mut units = BTreeMap::new();
+        let mut layout = Crc32Hasher::new();
+        for proto in &decls {
+            for unit in CacheUnit::from_declaration(app, proto) {
+                if unit.body_hash.is_some() {
+                    unit.name.hash(&mut layout);
+                }
+                units.insert(unit.name.clone(), unit);
+            }
+        }
+
+        // code refers to other POUs by their orders in module
+        let layout = layout.finish();
+        if layout != self.layout {
+            self.layout = layout;
+            for unit in self.units.values_mut() {
+                unit.code = None;
+            }
+        }
+
+        // names whose interfaces are changed, and the units using them in their interfaces
+        let mut changed: BTreeSet<StString> = self
+            .units
+            .keys()
+            .filter(|x| !units.contains_key(*x))
+            .cloned()
+            .collect();
+        for unit in units.values() {
+            match self.units.get(&unit.name) {
+                Some(old) if old.interface_hash == unit.interface_hash => {}
+                _ => {
+                    changed.insert(unit.name.clone());
+                }
+            }
+        }
+        loop {
+            let affected: Vec<_> = units
+                .values()
+                .filter(|x| !changed.contains(&x.name))
+                .filter(|x| x.dependencies.interface.iter().any(|d| uses(&changed, d)))
+                .map(|x| x.name.clone())
+                .collect();
+            if affected.is_empty() {
+                break;
+            }
+            changed.extend(affected);
+        }
+
+        let mut reused = vec![];
+        for unit in units.values_mut() {
+            let Some(old) = self.units.remove(&unit.name) else {
+                continue;
+            };
+            if changed.contains(&unit.name)
+                || old.body_hash != unit.body_hash
+                || unit.dependencies.body.iter().any(|x| uses(&changed, x))
+            {
+                continue;
+            }
+
+            if unit.body_hash.is_some() {
+                reused.push(unit.name.clone());
+            }
+            unit.diagnostics = old.diagnostics;
+            unit.code = old.code;
+        }
+
+        self.units = units;
+        reused
+    }
+
+    /// Errors of type analysis of unit, they're kept until the unit is changed
+    pub fn set_diagnostics(&mut self, name: &StString, diagnostics: Vec<String>) {
+        if let Some(unit) = self.units.get_mut(name) {
+            unit.diagnostics = Some(diagnostics);
+        }
+    }
+
+    /// Code of unit saved by backend
+    pub fn set_code(&mut self, name: &StString, code: Vec<u8>) {
+        if let Some(unit) = self.units.get_mut(name) {
+            unit.code = Some(code);
+        }
+    }
+
+    pub fn write(&self, w: &mut dyn Write) -> io::Result<()> {
+        let mut buf = vec![];
+        buf.write_u64::<LittleEndian>(self.key)?;
+        buf.write_u64::<LittleEndian>(self.layout)?;
+        buf.write_u32::<LittleEndian>(self.units.len() as u32)?;
+        for unit in self.units.values() {
+            unit.write(&mut buf)?;
+        }
+
+        w.write_all(CACHE_MAGIC)?;
+        w.write_u32::<LittleEndian>(CACHE_FORMAT_VERSION)?;
+        w.write_u32::<LittleEndian>(CRC32.checksum(&buf))?;
+        w.write_all(&buf)
+    }
+
+    pub fn read(r: &mut dyn Read) -> io::Result<Self> {
+        let mut magic = [0; 4];
+        r.read_exact(&mut magic)?;
+        if &magic != CACHE_MAGIC {
+            return Err(invalid_data("not a cache file"));
+        }
+
+        let format_version = r.read_u32::<LittleEndian>()?;
+        if format_version != CACHE_FORMAT_VERSION {
+            return Err(invalid_data(format!(
+                "cache format version {} is not supported",
+                format_version
+            )));
+        }
+
+        let checksum = r.read_u32::<LittleEndian>()?;
+        let mut buf = vec![];
+        r.read_to_end(&mut buf)?;
+        if CRC32.checksum(&buf) != checksum {
+            return Err(invalid_data("corrupted cache file"));
+        }
+
+        let r = &mut buf.as_slice();
+        let key = r.read_u64::<LittleEndian>()?;
+        let layout = r.read_u64::<LittleEndian>()?;
+        let mut units = BTreeMap::new();
+        for _ in 0..r.read_u32::<LittleEndian>()? {
+            let unit = CacheUnit::read(r)?;
+            units.insert(unit.name.clone(), unit);
+        }
+
+        Ok(Self { key, layout, units })
+    }
+}
@@ -0,0 +1,345 @@
+//! Precompiled library file. The file contains the public declarations of library as ST text,
+//! so that the library can be used for type checking without its sources, and the code of
+//! library compiled by backends, which is linked when the application is compiled.
+//!
+//! All numbers are little endian, strings and byte arrays are prefixed by u32 length:
+//!
+//! ```text
+//! magic "STCL", u32 format version, u32 CRC32 of the rest of file
+//! name, version, namespace (empty if not set)
+//! u32 count, declarations: name, uuid (16 bytes), text, u64 interface hash,
+//!                          u8 body flag, u64 body hash if flag is 1
+//! u32 count, payloads: u8 backend, bytes
+//! ```
+use super::loader::loader;
+use super::plcopen::{declaration_text, global_lists_text};
+use super::xml::POUError;
+use crate::ast::*;
+use crate::backend::utils::sorted_declarations;
+use crate::context::{ModuleContext, ModuleKind};
+use crate::utils::{AstHasher, Crc32Hasher};
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use crc::{Crc, CRC_32_ISO_HDLC};
+use semver::Version;
+use std::io::{self, Read, Write};
+use std::str::FromStr;
+use uuid::Uuid;
+
+/// Magic number of library file
+const LIBRARY_MAGIC: &[u8; 4] = b"STCL";
+/// Version of library file format, files of other versions can't be loaded
+pub const LIBRARY_FORMAT_VERSION: u32 = 1;
+
+const CRC32: Crc<u32> = Crc::<u32>::new(&CRC_32_ISO_HDLC);
+
+/// Backend which compiled the payload
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PayloadKind {
+    /// Lua 5.4 binary chunk
+    Lua,
+    /// C source
+    C,
+    /// WebAssembly module
+    Wasm,
+    /// LLVM bitcode
+    Llvm,
+}
+
+impl PayloadKind {
+    fn tag(&self) -> u8 {
+        match self {
+            PayloadKind::Lua => 1,
+            PayloadKind::C => 2,
+            PayloadKind::Wasm => 3,
+            PayloadKind::Llvm => 4,
+        }
+    }
+
+    fn from_tag(tag: u8) -> Option<Self> {
+        Some(match tag {
+            1 => PayloadKind::Lua,
+            2 => PayloadKind::C,
+            3 => PayloadKind::Wasm,
+            4 => PayloadKind::Llvm,
+            _ => return None,
+        })
+    }
+}
+
+/// Public declaration of library
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LibraryDeclaration {
+    pub name: String,
+    pub uuid: Uuid,
+    /// ST text of declaration without body
+    pub text: String,
+    /// Hash of declaration by `AstHasher`
+    pub interface_hash: u64,
+    /// Hash of body by `AstHasher`, None for data types and global variables
+    pub body_hash: Option<u64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LibraryPayload {
+    pub kind: PayloadKind,
+    pub data: Vec<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LibraryFile {
+    pub name: String,
+    pub version: Version,
+    pub namespace: Option<String>,
+    pub declarations: Vec<LibraryDeclaration>,
+    pub payloads: Vec<LibraryPayload>,
+}
+
+impl LibraryFile {
+    /// Public declarations of library module: data types, POU interfaces and global constants.
+    /// None if module isn't a library.
+    pub fn from_module(ctx: &ModuleContext, name: &str, version: Version) -> Option<Self> {
+        if !matches!(ctx.read().scope(), ModuleKind::Library) {
+            return None;
+        }
+
+        let mut declarations = vec![];
+        for proto in sorted_declarations(ctx) {
+            let proto = proto.read().unwrap();
+            let decl = proto.decl();
+            let interface_hash = AstHasher::new(Crc32Hasher::new()).calc_declaration(decl);
+            let body_hash = ctx
+                .read()
+                .get_function(proto.id())
+                .map(|f| AstHasher::new(Crc32Hasher::new()).calc_statement(f.read().parse_tree()));
+
+            let texts = match &decl.kind {
+                DeclKind::GlobalVar(global) => {
+                    let constants: Vec<_> = global
+                        .variables()
+                        .iter()
+                        .filter(|x| x.flags().contains(VariableFlags::CONST))
+                        .cloned()
+                        .collect();
+                    global_lists_text(&constants)
+                }
+                _ => declaration_text(decl).into_iter().collect(),
+            };
+
+            // global lists of different flags are separate declarations with new ids
+            let single = texts.len() == 1;
+            for text in texts {
+                declarations.push(LibraryDeclaration {
+                    name: decl.qualified_name().to_string(),
+                    uuid: if single {
+                        proto.object_id()
+                    } else {
+                        Uuid::new_v4()
+                    },
+                    text: namespace_text(decl, text),
+                    interface_hash,
+                    body_hash,
+                });
+            }
+        }
+
+        Some(Self {
+            name: name.to_owned(),
+            version,
+            namespace: None,
+            declarations,
+            payloads: vec![],
+        })
+    }
+
+    /// Add code compiled by backend, the payload of the same backend is replaced
+    pub fn set_payload(&mut self, kind: PayloadKind, data: Vec<u8>) {
+        self.payloads.retain(|x| x.kind != kind);
+        self.payloads.push(LibraryPayload { kind, data });
+    }
+
+    /// Code compiled by backend, linked when the application is compiled
+    pub fn payload(&self, kind: PayloadKind) -> Option<&[u8]> {
+        self.payloads
+            .iter()
+            .find(|x| x.kind == kind)
+            .map(|x| x.data.as_slice())
+    }
+
+    /// Load declarations into a library module without bodies, the object ids are kept
+    pub fn load_interface(&self) -> (ModuleContext, Vec<POUError>) {
+        let mut loader = loader(ModuleContext::new(ModuleKind::Library));
+        for decl in &self.declarations {
+            loader.add(&decl.name, Some(decl.uuid), &decl.text, None);
+        }
+
+        loader.finish()
+    }
+
+    /// Whether the public declarations and bodies of module are the same as the library is
+    /// built from
+    pub fn is_up_to_date(&self, ctx: &ModuleContext) -> bool {
+        let Some(current) = Self::from_module(ctx, &self.name, self.version.clone()) else {
+            return false;
+        };
+
+        let hashes = |lib: &Self| {
+            let mut hashes: Vec<_> = lib
+                .declarations
+                .iter()
+                .map(|x| (x.name.clone(), x.interface_hash, x.body_hash))
+                .collect();
+            hashes.sort();
+            hashes
+        };
+        hashes(self) == hashes(&current)
+    }
+
+    pub fn write(&self, w: &mut dyn Write) -> io::Result<()> {
+        let mut buf = vec![];
+        write_string(&mut buf, &self.name)?;
+        write_string(&mut buf, &self.version.to_string())?;
+        write_string(&mut buf, self.namespace.as_deref().unwrap_or_default())?;
+
+        buf.write_u32::<LittleEndian>(self.declarations.len() as u32)?;
+        for decl in &self.declarations {
+            write_string(&mut buf, &decl.name)?;
+            buf.write_all(decl.uuid.as_bytes())?;
+            write_string(&mut buf, &decl.text)?;
+            buf.write_u64::<LittleEndian>(decl.interface_hash)?;
+            match decl.body_hash {
+                Some(hash) => {
+                    buf.write_u8(1)?;
+                    buf.write_u64::<LittleEndian>(hash)?;
+                }
+                None => buf.write_u8(0)?,
+            }
+        }
+
+        buf.write_u32::<LittleEndian>(self.payloads.len() as u32)?;
+        for payload in &self.payloads {
+            buf.write_u8(payload.kind.tag())?;
+            write_bytes(&mut buf, &payload.data)?;
+        }
+
+        w.write_all(LIBRARY_MAGIC)?;
+        w.write_u32::<LittleEndian>(LIBRARY_FORMAT_VERSION)?;
+        w.write_u32::<LittleEndian>(CRC32.checksum(&buf))?;
+        w.write_all(&buf)
+    }
+
+    pub fn read(r: &mut dyn Read) -> io::Result<Self> {
+        let mut magic = [0; 4];
+        r.read_exact(&mut magic)?;
+        if &magic != LIBRARY_MAGIC {
+            return Err(invalid_data("not a library file"));
+        }
+
+        let format_version = r.read_u32::<LittleEndian>()?;
+        if format_version != LIBRARY_FORMAT_VERSION {
+            return Err(invalid_data(format!(
+                "library format version {} is not supported",
+                format_version
+            )));
+        }
+
+        let checksum = r.read_u32::<LittleEndian>()?;
+        let mut buf = vec![];
+        r.read_to_end(&mut buf)?;
+        if CRC32.checksum(&buf) != checksum {
+            return Err(invalid_data("corrupted library file"));
+        }
+
+        let r = &mut buf.as_slice();
+        let name = read_string(r)?;
+        let version = Version::from_str(&read_string(r)?).map_err(invalid_data)?;
+        let namespace = Some(read_string(r)?).filter(|x| !x.is_empty());
+
+        let count = r.read_u32::<LittleEndian>()?;
+        let mut declarations = vec![];
+        for _ in 0..count {
+            let name = read_string(r)?;
+            let mut uuid = [0; 16];
+            r.read_exact(&mut uuid)?;
+            let text = read_string(r)?;
+            let interface_hash = r.read_u64::<LittleEndian>()?;
+            let body_hash = match r.read_u8()? {
+                0 => None,
+                _ => Some(r.read_u64::<LittleEndian>()?),
+            };
+
+            declarations.push(LibraryDeclaration {
+                name,
+                uuid: Uuid::from_bytes(uuid),
+                text,
+                interface_hash,
+                body_hash,
+            });
+        }
+
+        let count = r.read_u32::<LittleEndian>()?;
+        let mut payloads = vec![];
+        for _ in 0..count {
+            let tag = r.read_u8()?;
+            let kind = PayloadKind::from_tag(tag)
+                .ok_or_else(|| invalid_data(format!("unknown payload {}", tag)))?;
+            let data = read_bytes(r)?;
+
+            payloads.push(LibraryPayload { kind, data });
+        }
+
+        Ok(Self {
+            name,
+            version,
+            namespace,
+            declarations,
+            payloads,
+        })
+    }
+}
+
+/// Text of declaration with its 'USING' directives, in the 'NAMESPACE' block of declaration
+fn namespace_text(decl: &Declaration, text: String) -> String {
+    let mut text = text;
+    if !decl.using().is_empty() {
+        let using: Vec<_> = decl.using().iter().map(|x| x.to_string()).collect();
+        text = format!("USING {};\n{}", using.join(", "), text);
+    }
+    if !decl.namespace().is_empty() {
+        text = format!("NAMESPACE {}\n{}\nEND_NAMESPACE\n", decl.namespace(), text);
+    }
+
+    text
+}
+
+pub(super) fn write_bytes(w: &mut dyn Write, bytes: &[u8]) -> io::Result<()> {
+    w.write_u32::<LittleEndian>(bytes.len() as u32)?;
+    w.write_all(bytes)
+}
+
+#[inline]
+pub(super) fn write_string(w: &mut dyn Write, s: &str) -> io::Result<()> {
+    write_bytes(w, s.as_bytes())
+}
+
+pub(super) fn read_bytes(r: &mut dyn Read) -> io::Result<Vec<u8>> {
+    let len = r.read_u32::<LittleEndian>()? as usize;
+    let mut bytes = vec![];
+    r.take(len as u64).read_to_end(&mut bytes)?;
+    if bytes.len() != len {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+
+    Ok(bytes)
+}
+
+pub(super) fn read_string(r: &mut dyn Read) -> io::Result<String> {
+    String::from_utf8(read_bytes(r)?).map_err(invalid_data)
+}
+
+pub(super) fn invalid_data<E>(e: E) -> io::Error
+where
+    E: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    io::Error::new(io::ErrorKind::InvalidData, e)
+}
@@ -0,0 +1,121 @@
+use super::xml::{POUError, POUErrorKind, POUPart};
+use crate::ast::Statement;
+use crate::context::ModuleContext;
+use crate::parser::{Parser, ParserBuilder, ParserTrait, StLexerBuilder, StString};
+use uuid::Uuid;
+
+/// Loader of POUs whose declaration and body are separate ST texts, errors of POUs are
+/// collected and the other POUs are still loaded
+pub(super) struct Loader<P: ParserTrait> {
+    ctx: ModuleContext,
+    parser: Parser<P>,
+    errors: Vec<POUError>,
+    /// Namespace and 'USING' directives of the declarations being added
+    namespace: StString,
+    using: Vec<StString>,
+}
+
+pub(super) fn loader(ctx: ModuleContext) -> Loader<impl ParserTrait> {
+    Loader {
+        ctx,
+        parser: ParserBuilder::default().build(),
+        errors: vec![],
+        namespace: StString::empty(),
+        using: vec![],
+    }
+}
+
+impl<P: ParserTrait> Loader<P> {
+    /// Parse declaration and body, the body of POU is None if it can't be loaded and empty
+    /// body does nothing. New object id is created if uuid is None.
+    pub fn add(&mut self, name: &str, uuid: Option<Uuid>, decl: &str, body: Option<&str>) {
+        self.add_part(name, uuid, POUPart::Interface, POUPart::Body, decl, body)
+    }
+
+    /// Parse declaration and body of method, property or action of POU `name`, errors of
+    /// both are reported as errors of the member
+    pub fn add_member(
+        &mut self,
+        name: &str,
+        uuid: Option<Uuid>,
+        part: POUPart,
+        decl: &str,
+        body: Option<&str>,
+    ) {
+        self.add_part(name, uuid, part.clone(), part, decl, body)
+    }
+
+    fn add_part(
+        &mut self,
+        name: &str,
+        uuid: Option<Uuid>,
+        decl_part: POUPart,
+        body_part: POUPart,
+        decl: &str,
+        body: Option<&str>,
+    ) {
+        let mut lexer = StLexerBuilder::new().build_str(decl);
+        let mut decl = match self.parser.parse_decl(&mut lexer) {
+            Ok(decl) => decl,
+            Err(e) => return self.error(name, uuid, decl_part, e.into()),
+        };
+        if !self.namespace.is_empty() {
+            decl.set_namespace(self.namespace.clone());
+        }
+        for namespace in &self.using {
+            decl.add_using(namespace.clone());
+        }
+
+        let id = self
+            .ctx
+            .write()
+            .add_declaration(decl, uuid.unwrap_or_else(Uuid::new_v4));
+        let Some(body) = body else {
+            return;
+        };
+
+        let body = if StLexerBuilder::new().build_str(body).next().is_none() {
+            Ok(Statement::statement_list(Box::default()))
+        } else {
+            self.parser
+                .parse_stmt(&mut StLexerBuilder::new().build_str(body))
+        };
+        match body {
+            Ok(body) => {
+                self.ctx.write().add_function(id, body);
+            }
+            Err(e) => self.error(name, uuid, body_part, e.into()),
+        }
+    }
+
+    /// Namespace and 'USING' directives of the declarations added after, like the namespace
+    /// blocks of '.st' files
+    pub fn set_namespace(&mut self, namespace: StString, using: Vec<StString>) {
+        self.namespace = namespace;
+        self.using = using;
+    }
+
+    /// Report part of POU which can't be loaded, like the body in LD
+    pub fn unsupported<S: Into<String>>(
+        &mut self,
+        name: &str,
+        uuid: Option<Uuid>,
+        part: POUPart,
+        what: S,
+    ) {
+        self.error(name, uuid, part, POUErrorKind::Unsupported(what.into()))
+    }
+
+    pub fn error(&mut self, name: &str, uuid: Option<Uuid>, part: POUPart, error: POUErrorKind) {
+        self.errors.push(POUError {
+            name: Some(name.into()),
+            uuid,
+            part,
+            error,
+        })
+    }
+
+    pub fn finish(self) -> (ModuleContext, Vec<POUError>) {
+        (self.ctx, self.errors)
+    }
+}
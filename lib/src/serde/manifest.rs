@@ -0,0 +1,378 @@
+//! Project manifest 'stc.toml', lists the source files of project and the libraries it uses.
+//!
+//! ```toml
+//! [package]
+//! name = "Conveyor"
+//! version = "0.1.0"
+//! sources = ["src/**/*.st"]
+//!
+//! [dependencies]
+//! Motion = { path = "../motion", version = "^1.2", namespace = "MC" }
+//! ```
+//!
+//! Libraries are folders with manifest too, or precompiled library files whose interfaces
+//! are loaded without sources. Declarations of library are used by namespace,
+//! which is the namespace of dependency, the namespace of library package or the name of
+//! library, in this order.
+use super::library::LibraryFile;
+use super::loader::loader;
+use super::source::split_source;
+use super::xml::{POUError, POUPart};
+use crate::context::{ModuleContext, ModuleKind, UnitsManager};
+use crate::parser::{CompilerDefines, StString};
+
+use semver::{Version, VersionReq};
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// File name of manifest in the folder of project or library
+pub const MANIFEST_FILE: &str = "stc.toml";
+
+#[derive(Deserialize, Debug)]
+pub struct Manifest {
+    pub package: Package,
+    /// Libraries by their names
+    #[serde(default)]
+    pub dependencies: BTreeMap<String, Dependency>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Package {
+    pub name: String,
+    pub version: Version,
+    /// Namespace of library, the name of library is used if it's not set
+    pub namespace: Option<String>,
+    /// Glob patterns of '.st' files, relative to the folder of manifest
+    #[serde(default = "default_sources")]
+    pub sources: Vec<String>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Dependency {
+    /// Folder or precompiled file of library, relative to the folder of manifest
+    pub path: PathBuf,
+    /// Any version is accepted if it's not set
+    #[serde(default)]
+    pub version: VersionReq,
+    /// Namespace the library is used by, overrides the namespace of library
+    pub namespace: Option<String>,
+}
+
+fn default_sources() -> Vec<String> {
+    vec!["src/**/*.st".to_owned()]
+}
+
+#[derive(Debug)]
+pub enum ManifestError {
+    Io(PathBuf, io::Error),
+    /// Manifest can't be parsed
+    Invalid(PathBuf, String),
+    /// Invalid glob pattern of sources
+    Pattern(String, String),
+    /// Name of library is not the name it's depended by
+    NameMismatch(String, String),
+    /// Two libraries with the same name in different folders
+    DuplicateLibrary(String),
+    /// Version of library doesn't match the version required by dependency
+    VersionMismatch(String, VersionReq, Version),
+    /// Two dependencies of package are used by the same namespace
+    DuplicateNamespace(String, StString),
+    /// Library depends on itself
+    Cycle(String),
+}
+
+impl Error for ManifestError {}
+
+impl Display for ManifestError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ManifestError::Io(path, e) => write!(f, "{}: {}", path.display(), e),
+            ManifestError::Invalid(path, e) => write!(f, "{}: {}", path.display(), e),
+            ManifestError::Pattern(pattern, e) => write!(f, "{}: {}", pattern, e),
+            ManifestError::NameMismatch(expected, found) => {
+                write!(f, "library {} is depended by name {}", found, expected)
+            }
+            ManifestError::DuplicateLibrary(name) => {
+                write!(f, "library {} is found in different folders", name)
+            }
+            ManifestError::VersionMismatch(name, required, found) => write!(
+                f,
+                "library {} {} doesn't match required version {}",
+                name, found, required
+            ),
+            ManifestError::DuplicateNamespace(package, namespace) => write!(
+                f,
+                "{}: namespace {} is used by more than one library",
+                package, namespace
+            ),
+            ManifestError::Cycle(name) => write!(f, "library {} depends on itself", name),
+        }
+    }
+}
+
+impl Manifest {
+    /// Read manifest file, or the manifest in folder
+    pub fn read<P: AsRef<Path>>(path: P) -> Result<Self, ManifestError> {
+        let mut path = path.as_ref().to_path_buf();
+        if path.is_dir() {
+            path.push(MANIFEST_FILE);
+        }
+
+        let text = fs::read_to_string(&path).map_err(|e| ManifestError::Io(path.clone(), e))?;
+        toml::from_str(&text).map_err(|e| ManifestError::Invalid(path, e.message().to_owned()))
+    }
+
+    /// Source files matched by patterns, sorted by path
+    pub fn source_files(&self, root: &Path) -> Result<Vec<PathBuf>, ManifestError> {
+        let root = glob::Pattern::escape(&root.to_string_lossy());
+
+        let mut files = vec![];
+        for source in &self.package.sources {
+            let pattern = format!("{}/{}", root, source);
+            let paths = glob::glob(&pattern)
+                .map_err(|e| ManifestError::Pattern(source.clone(), e.to_string()))?;
+
+            for path in paths {
+                let path = path.map_err(|e| {
+                    let path = e.path().to_path_buf();
+                    ManifestError::Io(path, e.into())
+                })?;
+                if path.is_file() {
+                    files.push(path);
+                }
+            }
+        }
+
+        files.sort();
+        files.dedup();
+        Ok(files)
+    }
+}
+
+/// Application or library of workspace
+#[derive(Debug)]
+pub struct WorkspacePackage {
+    pub name: String,
+    pub version: Version,
+    pub namespace: Option<StString>,
+    /// Folder of manifest
+    pub root: PathBuf,
+    pub sources: Vec<PathBuf>,
+    /// Libraries used by package, with the namespaces and indexes of libraries
+    pub dependencies: Vec<(StString, usize)>,
+    /// Precompiled library, whose interface is loaded instead of sources
+    pub library: Option<LibraryFile>,
+}
+
+/// Application and all the libraries it uses directly or indirectly
+#[derive(Debug)]
+pub struct Workspace {
+    /// Libraries are before the packages using them, the application is the last one
+    pub packages: Vec<WorkspacePackage>,
+}
+
+impl Workspace {
+    /// Read manifest of application in folder, or the manifest file in the folder of
+    /// application, and the manifests of libraries
+    pub fn resolve<P: AsRef<Path>>(path: P) -> Result<Self, ManifestError> {
+        let mut root = canonicalize(path.as_ref())?;
+        if root.is_file() {
+            root.pop();
+        }
+
+        let mut resolver = Resolver::default();
+        resolver.resolve(root)?;
+
+        Ok(Self {
+            packages: resolver.packages,
+        })
+    }
+
+    #[inline]
+    pub fn application(&self) -> &WorkspacePackage {
+        self.packages.last().unwrap()
+    }
+
+    /// Load application and libraries into contexts of mgr, the application is set active.
+    /// POUs can't be parsed are reported with the source file as name.
+    pub fn load(
+        &self,
+        mgr: &UnitsManager,
+    ) -> Result<(ModuleContext, Vec<POUError>), ManifestError> {
+        let mut contexts: Vec<ModuleContext> = vec![];
+        let mut errors = vec![];
+
+        for (index, package) in self.packages.iter().enumerate() {
+            let kind = if index + 1 == self.packages.len() {
+                ModuleKind::Application
+            } else {
+                ModuleKind::Library
+            };
+            let (ctx, pou_errors) = match &package.library {
+                Some(library) => library.load_interface(),
+                None => load_sources(&package.sources, kind)?,
+            };
+            errors.extend(pou_errors);
+
+            let mut mgr = mgr.write();
+            let ctx_id = ctx.read().id();
+            mgr.add_context(ctx.clone());
+            for (namespace, lib) in &package.dependencies {
+                mgr.add_dependency(ctx_id, namespace.clone(), contexts[*lib].read().id());
+            }
+            contexts.push(ctx);
+        }
+
+        let app = contexts.pop().unwrap();
+        mgr.write().set_active_application(Some(app.read().id()));
+        Ok((app, errors))
+    }
+}
+
+fn load_sources(
+    files: &[PathBuf],
+    kind: ModuleKind,
+) -> Result<(ModuleContext, Vec<POUError>), ManifestError> {
+    let mut loader = loader(ModuleContext::new(kind));
+
+    for file in files {
+        let text = fs::read_to_string(file).map_err(|e| ManifestError::Io(file.clone(), e))?;
+        let name = file.display().to_string();
+
+        let segments = match split_source(&text, &CompilerDefines::default()) {
+            Ok(segments) => segments,
+            Err(e) => {
+                loader.error(&name, None, POUPart::Interface, e.into());
+                continue;
+            }
+        };
+        for segment in segments {
+            let body = match segment.body.as_deref() {
+                None if segment.is_pou() => Some(""),
+                body => body,
+            };
+            loader.set_namespace(segment.namespace, segment.using);
+            loader.add(&name, None, &segment.decl, body);
+        }
+    }
+
+    Ok(loader.finish())
+}
+
+#[derive(Default)]
+struct Resolver {
+    packages: Vec<WorkspacePackage>,
+    indexes: HashMap<PathBuf, usize>,
+    /// Folders of packages being resolved, to find cyclic dependencies
+    resolving: Vec<PathBuf>,
+}
+
+impl Resolver {
+    /// Resolve package in folder and its dependencies, returns index of package
+    fn resolve(&mut self, root: PathBuf) -> Result<usize, ManifestError> {
+        if let Some(index) = self.indexes.get(&root) {
+            return Ok(*index);
+        }
+        if root.is_file() {
+            return self.add_library_file(root);
+        }
+
+        let manifest = Manifest::read(&root)?;
+        if self.resolving.contains(&root) {
+            return Err(ManifestError::Cycle(manifest.package.name));
+        }
+        if self
+            .packages
+            .iter()
+            .any(|x| x.name == manifest.package.name)
+        {
+            return Err(ManifestError::DuplicateLibrary(manifest.package.name));
+        }
+
+        self.resolving.push(root.clone());
+        let mut dependencies: Vec<(StString, usize)> = vec![];
+        for (name, dependency) in &manifest.dependencies {
+            let index = self.resolve(canonicalize(&root.join(&dependency.path))?)?;
+            let library = &self.packages[index];
+
+            if library.name != *name {
+                return Err(ManifestError::NameMismatch(
+                    name.clone(),
+                    library.name.clone(),
+                ));
+            }
+            if !dependency.version.matches(&library.version) {
+                return Err(ManifestError::VersionMismatch(
+                    name.clone(),
+                    dependency.version.clone(),
+                    library.version.clone(),
+                ));
+            }
+
+            let namespace = dependency
+                .namespace
+                .as_deref()
+                .map(StString::from)
+                .or_else(|| library.namespace.clone())
+                .unwrap_or_else(|| name.as_str().into());
+            if dependencies.iter().any(|(ns, _)| *ns == namespace) {
+                return Err(ManifestError::DuplicateNamespace(
+                    manifest.package.name,
+                    namespace,
+                ));
+            }
+            dependencies.push((namespace, index));
+        }
+        self.resolving.pop();
+
+        let sources = manifest.source_files(&root)?;
+        let package = manifest.package;
+        self.packages.push(WorkspacePackage {
+            name: package.name,
+            version: package.version,
+            namespace: package.namespace.map(StString::from),
+            root: root.clone(),
+            sources,
+            dependencies,
+            library: None,
+        });
+
+        let index = self.packages.len() - 1;
+        self.indexes.insert(root, index);
+        Ok(index)
+    }
+
+    /// Precompiled library has no dependencies, the declarations of other libraries are
+    /// included in its interface
+    fn add_library_file(&mut self, path: PathBuf) -> Result<usize, ManifestError> {
+        let library = fs::File::open(&path)
+            .and_then(|mut file| LibraryFile::read(&mut file))
+            .map_err(|e| ManifestError::Io(path.clone(), e))?;
+        if self.packages.iter().any(|x| x.name == library.name) {
+            return Err(ManifestError::DuplicateLibrary(library.name));
+        }
+
+        self.packages.push(WorkspacePackage {
+            name: library.name.clone(),
+            version: library.version.clone(),
+            namespace: library.namespace.as_deref().map(StString::from),
+            root: path.clone(),
+            sources: vec![],
+            dependencies: vec![],
+            library: Some(library),
+        });
+
+        let index = self.packages.len() - 1;
+        self.indexes.insert(path, index);
+        Ok(index)
+    }
+}
+
+fn canonicalize(path: &Path) -> Result<PathBuf, ManifestError> {
+    fs::canonicalize(path).map_err(|e| ManifestError::Io(path.to_path_buf(), e))
+}
@@ -1,2 +1,58 @@
 mod xml;
-pub use xml::Project;
+pub use xml::{POUError, POUErrorKind, POUPart, Project};
+
+mod loader;
+
+mod plcopen;
+pub use plcopen::PLCopenProject;
+
+mod twincat;
+pub use twincat::TwinCATProject;
+
+mod source;
+pub use source::{split_source, SourceSegment};
+
+mod library;
+pub use library::{
+    LibraryDeclaration, LibraryFile, LibraryPayload, PayloadKind, LIBRARY_FORMAT_VERSION,
+};
+
+mod cache;
+pub use cache::{BuildCache, CacheUnit, CACHE_FORMAT_VERSION};
+
+mod manifest;
+pub use manifest::{
+    Dependency, Manifest, ManifestError, Package, Workspace, WorkspacePackage, MANIFEST_FILE,
+};
+
+use crate::context::ModuleContext;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Load TwinCAT PLC project folder, TwinCAT object file or PLCopen XML export of CODESYS
+pub fn load_vendor_project<P: AsRef<Path>>(path: P) -> io::Result<(ModuleContext, Vec<POUError>)> {
+    let path = path.as_ref();
+    if path.is_dir() {
+        return Ok(TwinCATProject::read_dir(path)?.load());
+    }
+
+    let is_xml = path
+        .extension()
+        .and_then(|x| x.to_str())
+        .is_some_and(|x| x.eq_ignore_ascii_case("xml"));
+    if !is_xml {
+        let mut project = TwinCATProject::default();
+        project.read_file(path)?;
+        return Ok(project.load());
+    }
+
+    let xml = fs::read_to_string(path)?;
+    let project: PLCopenProject = quick_xml::de::from_str(&xml).map_err(|e| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{}: {}", path.display(), e),
+        )
+    })?;
+    Ok(project.load())
+}
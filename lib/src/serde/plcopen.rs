@@ -0,0 +1,1122 @@
+//! PLCopen TC6 XML (tc6_0200/tc6_0201) documents. Declarations of the document are
+//! translated to Structured Text and parsed by the ST parser, so that unsupported constructs
+//! are reported as parse errors of the POU.
+use super::loader::loader;
+use super::xml::{POUError, POUPart};
+use crate::ast::*;
+use crate::backend::utils::sorted_declarations;
+use crate::context::{ModuleContext, ModuleKind};
+use crate::parser::TokenKind;
+
+use serde::de::IgnoredAny;
+use serde::{Deserialize, Serialize};
+use std::fmt::Write;
+use std::str::FromStr;
+use std::sync::Arc;
+use uuid::Uuid;
+
+const TC6_NAMESPACE: &str = "http://www.plcopen.org/xml/tc6_0201";
+const XHTML_NAMESPACE: &str = "http://www.w3.org/1999/xhtml";
+
+/// Global variable lists are not part of TC6, they are stored in 'addData' like CODESYS does
+const GLOBAL_VARS_DATA: &str = "http://www.3s-software.com/plcopenxml/globalvars";
+
+/// Object id of POUs, data types and global variable lists exported by CODESYS
+const OBJECT_ID_DATA: &str = "http://www.3s-software.com/plcopenxml/objectid";
+
+/// Resources of PLCopen have no processing unit, resources are loaded as 'RESOURCE x ON PLC'
+const RESOURCE_TARGET: &str = "PLC";
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename = "project")]
+pub struct PLCopenProject {
+    #[serde(rename = "@xmlns", default)]
+    pub xmlns: String,
+    #[serde(rename = "fileHeader")]
+    pub file_header: FileHeader,
+    #[serde(rename = "contentHeader")]
+    pub content_header: ContentHeader,
+    #[serde(default)]
+    pub types: Types,
+    #[serde(default)]
+    pub instances: Instances,
+    #[serde(rename = "addData", skip_serializing_if = "Option::is_none")]
+    pub add_data: Option<AddData>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FileHeader {
+    #[serde(rename = "@companyName", default)]
+    pub company_name: String,
+    #[serde(rename = "@productName", default)]
+    pub product_name: String,
+    #[serde(rename = "@productVersion", default)]
+    pub product_version: String,
+    #[serde(rename = "@creationDateTime", default)]
+    pub creation_date_time: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ContentHeader {
+    #[serde(rename = "@name")]
+    pub name: String,
+    #[serde(rename = "coordinateInfo", default)]
+    pub coordinate_info: CoordinateInfo,
+}
+
+/// Scaling of graphical languages, required by the schema
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct CoordinateInfo {
+    #[serde(default)]
+    pub fbd: Scaling,
+    #[serde(default)]
+    pub ld: Scaling,
+    #[serde(default)]
+    pub sfc: Scaling,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Scaling {
+    pub scaling: Position,
+}
+
+impl Default for Scaling {
+    fn default() -> Self {
+        Self {
+            scaling: Position {
+                x: "1".to_owned(),
+                y: "1".to_owned(),
+            },
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Position {
+    #[serde(rename = "@x")]
+    pub x: String,
+    #[serde(rename = "@y")]
+    pub y: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct Types {
+    #[serde(rename = "dataTypes", default)]
+    pub data_types: DataTypes,
+    #[serde(default)]
+    pub pous: POUs,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct DataTypes {
+    #[serde(rename = "dataType", default)]
+    pub data_type: Vec<DataTypeDecl>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DataTypeDecl {
+    #[serde(rename = "@name")]
+    pub name: String,
+    #[serde(rename = "baseType")]
+    pub base_type: TypeElement,
+    #[serde(rename = "addData", skip_serializing_if = "Option::is_none")]
+    pub add_data: Option<AddData>,
+}
+
+/// Element containing a single data type element, like '<type><INT/></type>'
+#[derive(Serialize, Deserialize, Debug)]
+pub struct TypeElement {
+    #[serde(rename = "$value")]
+    pub ty: DataType,
+}
+
+impl TypeElement {
+    fn new(ty: DataType) -> Self {
+        Self { ty }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub enum DataType {
+    #[serde(rename = "BOOL")]
+    Bool,
+    #[serde(rename = "BYTE")]
+    Byte,
+    #[serde(rename = "WORD")]
+    Word,
+    #[serde(rename = "DWORD")]
+    DWord,
+    #[serde(rename = "LWORD")]
+    LWord,
+    #[serde(rename = "SINT")]
+    SInt,
+    #[serde(rename = "INT")]
+    Int,
+    #[serde(rename = "DINT")]
+    DInt,
+    #[serde(rename = "LINT")]
+    LInt,
+    #[serde(rename = "USINT")]
+    USInt,
+    #[serde(rename = "UINT")]
+    UInt,
+    #[serde(rename = "UDINT")]
+    UDInt,
+    #[serde(rename = "ULINT")]
+    ULInt,
+    #[serde(rename = "REAL")]
+    Real,
+    #[serde(rename = "LREAL")]
+    LReal,
+    #[serde(rename = "TIME")]
+    Time,
+    #[serde(rename = "DATE")]
+    Date,
+    #[serde(rename = "DT")]
+    DateAndTime,
+    #[serde(rename = "TOD")]
+    TimeOfDay,
+    #[serde(rename = "string")]
+    String {
+        #[serde(rename = "@length", skip_serializing_if = "Option::is_none")]
+        length: Option<String>,
+    },
+    #[serde(rename = "wstring")]
+    WString {
+        #[serde(rename = "@length", skip_serializing_if = "Option::is_none")]
+        length: Option<String>,
+    },
+    #[serde(rename = "derived")]
+    Derived {
+        #[serde(rename = "@name")]
+        name: String,
+    },
+    #[serde(rename = "array")]
+    Array {
+        dimension: Vec<Range>,
+        #[serde(rename = "baseType")]
+        base_type: Box<TypeElement>,
+    },
+    #[serde(rename = "enum")]
+    Enum {
+        values: EnumValues,
+        #[serde(rename = "baseType", skip_serializing_if = "Option::is_none")]
+        base_type: Option<Box<TypeElement>>,
+    },
+    #[serde(rename = "struct")]
+    Struct {
+        #[serde(default)]
+        variable: Vec<VariableDecl>,
+    },
+    #[serde(rename = "subrangeSigned")]
+    SubrangeSigned {
+        range: Range,
+        #[serde(rename = "baseType")]
+        base_type: Box<TypeElement>,
+    },
+    #[serde(rename = "subrangeUnsigned")]
+    SubrangeUnsigned {
+        range: Range,
+        #[serde(rename = "baseType")]
+        base_type: Box<TypeElement>,
+    },
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Range {
+    #[serde(rename = "@lower")]
+    pub lower: String,
+    #[serde(rename = "@upper")]
+    pub upper: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct EnumValues {
+    #[serde(default)]
+    pub value: Vec<EnumValue>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct EnumValue {
+    #[serde(rename = "@name")]
+    pub name: String,
+    #[serde(rename = "@value", skip_serializing_if = "Option::is_none")]
+    pub value: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct POUs {
+    #[serde(default)]
+    pub pou: Vec<POU>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum POUType {
+    #[serde(rename = "function")]
+    Function,
+    #[serde(rename = "functionBlock")]
+    FunctionBlock,
+    #[serde(rename = "program")]
+    Program,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[allow(clippy::upper_case_acronyms)] // Allow upper case POU naming
+pub struct POU {
+    #[serde(rename = "@name")]
+    pub name: String,
+    #[serde(rename = "@pouType")]
+    pub pou_type: POUType,
+    #[serde(default)]
+    pub interface: Interface,
+    pub body: Option<Body>,
+    #[serde(rename = "addData", skip_serializing_if = "Option::is_none")]
+    pub add_data: Option<AddData>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct Interface {
+    #[serde(rename = "$value", default)]
+    pub items: Vec<InterfaceItem>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub enum InterfaceItem {
+    #[serde(rename = "returnType")]
+    ReturnType(TypeElement),
+    #[serde(rename = "localVars")]
+    LocalVars(VarList),
+    #[serde(rename = "tempVars")]
+    TempVars(VarList),
+    #[serde(rename = "inputVars")]
+    InputVars(VarList),
+    #[serde(rename = "outputVars")]
+    OutputVars(VarList),
+    #[serde(rename = "inOutVars")]
+    InOutVars(VarList),
+    #[serde(rename = "externalVars")]
+    ExternalVars(VarList),
+    #[serde(rename = "globalVars")]
+    GlobalVars(VarList),
+    #[serde(rename = "accessVars")]
+    AccessVars(VarList),
+}
+
+/// Variable section, like '<inputVars constant="true">'
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct VarList {
+    #[serde(rename = "@name", skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(rename = "@constant", skip_serializing_if = "Option::is_none")]
+    pub constant: Option<bool>,
+    #[serde(rename = "@retain", skip_serializing_if = "Option::is_none")]
+    pub retain: Option<bool>,
+    #[serde(rename = "@persistent", skip_serializing_if = "Option::is_none")]
+    pub persistent: Option<bool>,
+    #[serde(default)]
+    pub variable: Vec<VariableDecl>,
+    #[serde(rename = "addData", skip_serializing_if = "Option::is_none")]
+    pub add_data: Option<AddData>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct VariableDecl {
+    #[serde(rename = "@name")]
+    pub name: String,
+    #[serde(rename = "type")]
+    pub ty: TypeElement,
+    #[serde(rename = "initialValue", skip_serializing_if = "Option::is_none")]
+    pub initial_value: Option<Value>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Value {
+    #[serde(rename = "$value")]
+    pub value: ValueKind,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub enum ValueKind {
+    #[serde(rename = "simpleValue")]
+    Simple {
+        #[serde(rename = "@value")]
+        value: String,
+    },
+    #[serde(rename = "arrayValue")]
+    Array {
+        #[serde(default)]
+        value: Vec<ArrayValueItem>,
+    },
+    #[serde(rename = "structValue")]
+    Struct {
+        #[serde(default)]
+        value: Vec<StructValueItem>,
+    },
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ArrayValueItem {
+    #[serde(rename = "@repetitionValue", skip_serializing_if = "Option::is_none")]
+    pub repetition: Option<String>,
+    #[serde(rename = "$value")]
+    pub value: ValueKind,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct StructValueItem {
+    #[serde(rename = "@member")]
+    pub member: String,
+    #[serde(rename = "$value")]
+    pub value: ValueKind,
+}
+
+/// Body of POU, only Structured Text bodies are supported, the other languages are only
+/// recognized to be reported
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct Body {
+    #[serde(rename = "ST", skip_serializing_if = "Option::is_none")]
+    pub st: Option<FormattedText>,
+    #[serde(rename = "IL", skip_serializing)]
+    pub il: Option<IgnoredAny>,
+    #[serde(rename = "FBD", skip_serializing)]
+    pub fbd: Option<IgnoredAny>,
+    #[serde(rename = "LD", skip_serializing)]
+    pub ld: Option<IgnoredAny>,
+    #[serde(rename = "SFC", skip_serializing)]
+    pub sfc: Option<IgnoredAny>,
+}
+
+impl Body {
+    /// Name of the language if body is not in ST
+    pub fn unsupported_language(&self) -> Option<&'static str> {
+        [
+            ("IL", self.il.is_some()),
+            ("FBD", self.fbd.is_some()),
+            ("LD", self.ld.is_some()),
+            ("SFC", self.sfc.is_some()),
+        ]
+        .into_iter()
+        .find_map(|(name, present)| present.then_some(name))
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct FormattedText {
+    pub xhtml: XHtml,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct XHtml {
+    #[serde(rename = "@xmlns", default)]
+    pub xmlns: String,
+    #[serde(rename = "$text", default)]
+    pub content: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct Instances {
+    #[serde(default)]
+    pub configurations: Configurations,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct Configurations {
+    #[serde(default)]
+    pub configuration: Vec<Configuration>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Configuration {
+    #[serde(rename = "@name")]
+    pub name: String,
+    #[serde(default)]
+    pub resource: Vec<Resource>,
+    #[serde(rename = "globalVars", default)]
+    pub global_vars: Vec<VarList>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Resource {
+    #[serde(rename = "@name")]
+    pub name: String,
+    #[serde(default)]
+    pub task: Vec<Task>,
+    #[serde(rename = "globalVars", default)]
+    pub global_vars: Vec<VarList>,
+    #[serde(rename = "pouInstance", default)]
+    pub pou_instance: Vec<POUInstance>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Task {
+    #[serde(rename = "@name")]
+    pub name: String,
+    #[serde(rename = "@single", skip_serializing_if = "Option::is_none")]
+    pub single: Option<String>,
+    #[serde(rename = "@interval", skip_serializing_if = "Option::is_none")]
+    pub interval: Option<String>,
+    #[serde(rename = "@priority")]
+    pub priority: String,
+    #[serde(rename = "pouInstance", default)]
+    pub pou_instance: Vec<POUInstance>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct POUInstance {
+    #[serde(rename = "@name")]
+    pub name: String,
+    #[serde(rename = "@typeName")]
+    pub type_name: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct AddData {
+    #[serde(default)]
+    pub data: Vec<Data>,
+}
+
+impl AddData {
+    fn with_object_id(uuid: Uuid) -> Option<Self> {
+        (!uuid.is_nil()).then(|| Self {
+            data: vec![Data {
+                name: OBJECT_ID_DATA.to_owned(),
+                handle_unknown: "discard".to_owned(),
+                global_vars: vec![],
+                object_id: Some(uuid.to_string()),
+            }],
+        })
+    }
+
+    fn object_id(data: &Option<AddData>) -> Option<Uuid> {
+        data.iter()
+            .flat_map(|x| &x.data)
+            .filter(|x| x.name == OBJECT_ID_DATA)
+            .find_map(|x| Uuid::from_str(x.object_id.as_ref()?).ok())
+    }
+}
+
+/// Vendor data, other contents than global variable lists and object ids are ignored
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Data {
+    #[serde(rename = "@name")]
+    pub name: String,
+    #[serde(rename = "@handleUnknown", default)]
+    pub handle_unknown: String,
+    #[serde(rename = "globalVars", default)]
+    pub global_vars: Vec<VarList>,
+    #[serde(rename = "ObjectId", skip_serializing_if = "Option::is_none")]
+    pub object_id: Option<String>,
+}
+
+impl PLCopenProject {
+    /// Load data types, POUs, global variable lists and configurations into module. The errors
+    /// are reported like `Project::load`, locations are relative to the generated ST text.
+    pub fn load(self) -> (ModuleContext, Vec<POUError>) {
+        let mut loader = loader(ModuleContext::new(ModuleKind::Application));
+
+        for data_type in &self.types.data_types.data_type {
+            let uuid = AddData::object_id(&data_type.add_data);
+            loader.add(&data_type.name, uuid, &data_type_text(data_type), None);
+        }
+
+        for pou in &self.types.pous.pou {
+            let uuid = AddData::object_id(&pou.add_data);
+            let body = match &pou.body {
+                Some(Body { st: Some(st), .. }) => Some(st.xhtml.content.as_str()),
+                Some(body) => {
+                    let language = body.unsupported_language().unwrap_or("empty");
+                    let what = format!("{} body", language);
+                    loader.unsupported(&pou.name, uuid, POUPart::Body, what);
+                    None
+                }
+                None => Some(""),
+            };
+            loader.add(&pou.name, uuid, &pou_text(pou), body);
+        }
+
+        let global_lists = self.add_data.iter().flat_map(|x| &x.data);
+        for list in global_lists
+            .filter(|x| x.name == GLOBAL_VARS_DATA)
+            .flat_map(|x| &x.global_vars)
+        {
+            let name = list.name.clone().unwrap_or_default();
+            let uuid = AddData::object_id(&list.add_data);
+            // names of lists are dropped, so that the variables are visible to the whole application
+            let text = var_list_text(VariableFlags::GLOBAL, list);
+            loader.add(&name, uuid, &text, None);
+        }
+
+        for configuration in &self.instances.configurations.configuration {
+            let text = configuration_text(configuration);
+            loader.add(&configuration.name, None, &text, None);
+        }
+
+        loader.finish()
+    }
+
+    /// Export declarations and bodies of module, global variables outside configurations are
+    /// written to the global variable lists of 'addData'
+    pub fn from_module(ctx: &ModuleContext, name: &str) -> Self {
+        let mut types = Types::default();
+        let mut configurations = vec![];
+        let mut global_vars = vec![];
+
+        let ctx_read = ctx.read();
+        for proto in sorted_declarations(ctx) {
+            let proto = proto.read().unwrap();
+            let add_data = AddData::with_object_id(proto.object_id());
+            match &proto.decl().kind {
+                DeclKind::Fun(fun) => {
+                    let body = ctx_read
+                        .get_function(proto.id())
+                        .map(|f| f.read().parse_tree().to_string());
+                    types.pous.pou.push(POU {
+                        add_data,
+                        ..export_pou(fun, body)
+                    });
+                }
+                DeclKind::Alias(alias) => types.data_types.data_type.push(DataTypeDecl {
+                    add_data,
+                    ..export_alias(alias)
+                }),
+                DeclKind::Enum(decl) => types.data_types.data_type.push(DataTypeDecl {
+                    add_data,
+                    ..export_enum(decl)
+                }),
+                DeclKind::Struct(decl) => types.data_types.data_type.push(DataTypeDecl {
+                    add_data,
+                    ..export_struct(decl)
+                }),
+                DeclKind::GlobalVar(decl) => {
+                    // lists of different flags are loaded as separate declarations
+                    let mut add_data = add_data;
+                    for (_, mut list) in export_var_lists(decl.variables()) {
+                        list.name = (!decl.name().is_empty()).then(|| decl.name().to_string());
+                        list.add_data = add_data.take();
+                        global_vars.push(list);
+                    }
+                }
+                DeclKind::Configuration(decl) => configurations.push(export_configuration(decl)),
+                _ => {}
+            }
+        }
+
+        let add_data = (!global_vars.is_empty()).then(|| AddData {
+            data: vec![Data {
+                name: GLOBAL_VARS_DATA.to_owned(),
+                handle_unknown: "implementation".to_owned(),
+                global_vars,
+                object_id: None,
+            }],
+        });
+
+        Self {
+            xmlns: TC6_NAMESPACE.to_owned(),
+            file_header: FileHeader {
+                company_name: String::new(),
+                product_name: "stc".to_owned(),
+                product_version: env!("CARGO_PKG_VERSION").to_owned(),
+                creation_date_time: chrono::Local::now().format("%Y-%m-%dT%H:%M:%S").to_string(),
+            },
+            content_header: ContentHeader {
+                name: name.to_owned(),
+                coordinate_info: CoordinateInfo::default(),
+            },
+            types,
+            instances: Instances {
+                configurations: Configurations {
+                    configuration: configurations,
+                },
+            },
+            add_data,
+        }
+    }
+}
+
+/// ST text of POU interface or data type, which is loaded like the declarations of PLCopen
+/// XML. Global variables and configurations are not supported.
+pub(super) fn declaration_text(decl: &Declaration) -> Option<String> {
+    Some(match &decl.kind {
+        DeclKind::Fun(fun) => pou_text(&export_pou(fun, None)),
+        DeclKind::Alias(alias) => data_type_text(&export_alias(alias)),
+        DeclKind::Enum(decl) => data_type_text(&export_enum(decl)),
+        DeclKind::Struct(decl) => data_type_text(&export_struct(decl)),
+        _ => return None,
+    })
+}
+
+/// ST text of global variables without the name of list, one 'VAR_GLOBAL' declaration for
+/// each section of consecutive variables with the same flags
+pub(super) fn global_lists_text(variables: &[Arc<Variable>]) -> Vec<String> {
+    export_var_lists(variables)
+        .iter()
+        .map(|(_, list)| var_list_text(VariableFlags::GLOBAL, list))
+        .collect()
+}
+
+fn data_type_text(decl: &DataTypeDecl) -> String {
+    let ty = match &decl.base_type.ty {
+        DataType::Enum { values, base_type } => {
+            let fields: Vec<_> = values
+                .value
+                .iter()
+                .map(|x| match &x.value {
+                    Some(value) => format!("{} := {}", x.name, value),
+                    None => x.name.clone(),
+                })
+                .collect();
+            match base_type {
+                Some(base) => format!("({}) {}", fields.join(", "), type_text(&base.ty)),
+                None => format!("({})", fields.join(", ")),
+            }
+        }
+        DataType::Struct { variable } => {
+            let mut s = "STRUCT\n".to_owned();
+            for v in variable {
+                s.push_str(&variable_text(v));
+            }
+            return format!("TYPE {} : {}END_STRUCT\nEND_TYPE\n", decl.name, s);
+        }
+        ty => type_text(ty),
+    };
+
+    format!("TYPE {} : {};\nEND_TYPE\n", decl.name, ty)
+}
+
+fn pou_text(pou: &POU) -> String {
+    let return_type = pou.interface.items.iter().find_map(|x| match x {
+        InterfaceItem::ReturnType(ty) => Some(type_text(&ty.ty)),
+        _ => None,
+    });
+
+    let (start, end) = match pou.pou_type {
+        POUType::Function => (TokenKind::Function, TokenKind::EndFunction),
+        POUType::FunctionBlock => (TokenKind::FunctionBlock, TokenKind::EndFunctionBlock),
+        POUType::Program => (TokenKind::Program, TokenKind::EndProgram),
+    };
+    let mut s = match return_type {
+        Some(ty) => format!("{} {} : {}\n", start, pou.name, ty),
+        None => format!("{} {} :\n", start, pou.name),
+    };
+
+    for item in &pou.interface.items {
+        let (flags, list) = match item {
+            InterfaceItem::LocalVars(x) => (VariableFlags::NONE, x),
+            InterfaceItem::TempVars(x) => (VariableFlags::TEMP, x),
+            InterfaceItem::InputVars(x) => (VariableFlags::INPUT, x),
+            InterfaceItem::OutputVars(x) => (VariableFlags::OUTPUT, x),
+            InterfaceItem::InOutVars(x) => (VariableFlags::INOUT, x),
+            // global variables are visible without external declarations
+            InterfaceItem::ExternalVars(_) | InterfaceItem::GlobalVars(_) => continue,
+            InterfaceItem::AccessVars(_) | InterfaceItem::ReturnType(_) => continue,
+        };
+        s.push_str(&var_list_text(flags, list));
+    }
+
+    let _ = writeln!(s, "{}", end);
+    s
+}
+
+fn configuration_text(configuration: &Configuration) -> String {
+    let mut s = format!("CONFIGURATION {}\n", configuration.name);
+
+    // variables of resources are visible to the whole configuration
+    let global_vars = configuration
+        .global_vars
+        .iter()
+        .chain(configuration.resource.iter().flat_map(|x| &x.global_vars));
+    for list in global_vars {
+        s.push_str(&var_list_text(VariableFlags::GLOBAL, list));
+    }
+
+    for resource in &configuration.resource {
+        let _ = writeln!(s, "RESOURCE {} ON {}", resource.name, RESOURCE_TARGET);
+        for task in &resource.task {
+            let parameters: Vec<_> = [
+                ("SINGLE", &task.single),
+                ("INTERVAL", &task.interval),
+                ("PRIORITY", &Some(task.priority.clone())),
+            ]
+            .into_iter()
+            .filter_map(|(name, value)| Some(format!("{} := {}", name, value.as_ref()?)))
+            .collect();
+            let _ = writeln!(s, "TASK {}({});", task.name, parameters.join(", "));
+        }
+
+        let instances = resource
+            .task
+            .iter()
+            .flat_map(|task| task.pou_instance.iter().map(move |x| (Some(task), x)))
+            .chain(resource.pou_instance.iter().map(|x| (None, x)));
+        for (task, instance) in instances {
+            match task {
+                Some(task) => {
+                    let _ = writeln!(
+                        s,
+                        "PROGRAM {} WITH {} : {};",
+                        instance.name, task.name, instance.type_name
+                    );
+                }
+                None => {
+                    let _ = writeln!(s, "PROGRAM {} : {};", instance.name, instance.type_name);
+                }
+            }
+        }
+        s.push_str("END_RESOURCE\n");
+    }
+
+    s.push_str("END_CONFIGURATION\n");
+    s
+}
+
+fn var_list_text(mut flags: VariableFlags, list: &VarList) -> String {
+    if list.constant == Some(true) {
+        flags |= VariableFlags::CONST;
+    }
+    if list.retain == Some(true) {
+        flags |= VariableFlags::RETAIN;
+    }
+    if list.persistent == Some(true) {
+        flags |= VariableFlags::PERSISTENT;
+    }
+
+    let mut s = format!("{}\n", flags);
+    for v in &list.variable {
+        s.push_str(&variable_text(v));
+    }
+    s.push_str("END_VAR\n");
+
+    s
+}
+
+fn variable_text(v: &VariableDecl) -> String {
+    match &v.initial_value {
+        Some(initial) => format!(
+            "    {} : {} := {};\n",
+            v.name,
+            type_text(&v.ty.ty),
+            value_text(&initial.value)
+        ),
+        None => format!("    {} : {};\n", v.name, type_text(&v.ty.ty)),
+    }
+}
+
+/// Type in ST, anonymous enums and structs are written as is and rejected by the parser
+fn type_text(ty: &DataType) -> String {
+    match ty {
+        DataType::Bool => "BOOL".to_owned(),
+        DataType::Byte => "BYTE".to_owned(),
+        DataType::Word => "WORD".to_owned(),
+        DataType::DWord => "DWORD".to_owned(),
+        DataType::LWord => "LWORD".to_owned(),
+        DataType::SInt => "SINT".to_owned(),
+        DataType::Int => "INT".to_owned(),
+        DataType::DInt => "DINT".to_owned(),
+        DataType::LInt => "LINT".to_owned(),
+        DataType::USInt => "USINT".to_owned(),
+        DataType::UInt => "UINT".to_owned(),
+        DataType::UDInt => "UDINT".to_owned(),
+        DataType::ULInt => "ULINT".to_owned(),
+        DataType::Real => "REAL".to_owned(),
+        DataType::LReal => "LREAL".to_owned(),
+        DataType::Time => "TIME".to_owned(),
+        DataType::Date => "DATE".to_owned(),
+        DataType::DateAndTime => "DT".to_owned(),
+        DataType::TimeOfDay => "TOD".to_owned(),
+        DataType::String { .. } => "STRING".to_owned(),
+        DataType::WString { .. } => "WSTRING".to_owned(),
+        DataType::Derived { name } => name.clone(),
+        DataType::Array {
+            dimension,
+            base_type,
+        } => {
+            let dimensions: Vec<_> = dimension
+                .iter()
+                .map(|x| format!("{}..{}", x.lower, x.upper))
+                .collect();
+            format!(
+                "ARRAY[{}] OF {}",
+                dimensions.join(", "),
+                type_text(&base_type.ty)
+            )
+        }
+        DataType::Enum { values, .. } => {
+            let names: Vec<_> = values.value.iter().map(|x| x.name.as_str()).collect();
+            format!("({})", names.join(", "))
+        }
+        DataType::Struct { variable } => {
+            let variables: String = variable.iter().map(variable_text).collect();
+            format!("STRUCT\n{}END_STRUCT", variables)
+        }
+        DataType::SubrangeSigned { range, base_type }
+        | DataType::SubrangeUnsigned { range, base_type } => format!(
+            "{}({}..{})",
+            type_text(&base_type.ty),
+            range.lower,
+            range.upper
+        ),
+    }
+}
+
+/// Initial value in ST, array and struct initializers are not supported by the parser
+fn value_text(value: &ValueKind) -> String {
+    match value {
+        ValueKind::Simple { value } => value.clone(),
+        ValueKind::Array { value } => {
+            let items: Vec<_> = value
+                .iter()
+                .map(|x| match &x.repetition {
+                    Some(n) => format!("{}({})", n, value_text(&x.value)),
+                    None => value_text(&x.value),
+                })
+                .collect();
+            format!("[{}]", items.join(", "))
+        }
+        ValueKind::Struct { value } => {
+            let items: Vec<_> = value
+                .iter()
+                .map(|x| format!("{} := {}", x.member, value_text(&x.value)))
+                .collect();
+            format!("({})", items.join(", "))
+        }
+    }
+}
+
+fn export_pou(fun: &FunctionDeclare, body: Option<String>) -> POU {
+    let pou_type = match fun.class() {
+        DeclareClass::Program => POUType::Program,
+        DeclareClass::FunctionBlock => POUType::FunctionBlock,
+        _ => POUType::Function,
+    };
+
+    let mut items = vec![];
+    if let Some(ty) = fun.return_type() {
+        items.push(InterfaceItem::ReturnType(TypeElement::new(export_type(ty))));
+    }
+    for (flags, list) in export_var_lists(fun.parameters()) {
+        items.push(if flags.contains(VariableFlags::INPUT) {
+            InterfaceItem::InputVars(list)
+        } else if flags.contains(VariableFlags::OUTPUT) {
+            InterfaceItem::OutputVars(list)
+        } else if flags.contains(VariableFlags::INOUT) {
+            InterfaceItem::InOutVars(list)
+        } else if flags.contains(VariableFlags::TEMP) {
+            InterfaceItem::TempVars(list)
+        } else {
+            InterfaceItem::LocalVars(list)
+        });
+    }
+
+    POU {
+        name: fun.name().to_string(),
+        pou_type,
+        interface: Interface { items },
+        body: body.map(|content| Body {
+            st: Some(FormattedText {
+                xhtml: XHtml {
+                    xmlns: XHTML_NAMESPACE.to_owned(),
+                    content,
+                },
+            }),
+            ..Body::default()
+        }),
+        add_data: None,
+    }
+}
+
+/// Sections of consecutive variables with the same flags, static variables are exported as
+/// local variables
+fn export_var_lists(variables: &[Arc<Variable>]) -> Vec<(VariableFlags, VarList)> {
+    let mut lists: Vec<(VariableFlags, VarList)> = vec![];
+
+    for v in variables {
+        match lists.last_mut() {
+            Some((flags, list)) if *flags == v.flags() => list.variable.push(export_variable(v)),
+            _ => {
+                let flags = v.flags();
+                let list = VarList {
+                    name: None,
+                    constant: flags.contains(VariableFlags::CONST).then_some(true),
+                    retain: flags.contains(VariableFlags::RETAIN).then_some(true),
+                    persistent: flags.contains(VariableFlags::PERSISTENT).then_some(true),
+                    variable: vec![export_variable(v)],
+                    add_data: None,
+                };
+                lists.push((flags, list));
+            }
+        }
+    }
+
+    lists
+}
+
+fn export_variable(v: &Variable) -> VariableDecl {
+    VariableDecl {
+        name: v.name().to_string(),
+        ty: TypeElement::new(v.ty().map_or(DataType::Int, export_type)),
+        initial_value: v.initial().as_ref().map(|x| Value {
+            value: ValueKind::Simple {
+                value: x.to_string(),
+            },
+        }),
+    }
+}
+
+fn export_alias(alias: &AliasDeclare) -> DataTypeDecl {
+    DataTypeDecl {
+        name: alias.name().to_string(),
+        base_type: TypeElement::new(export_type(alias.alias())),
+        add_data: None,
+    }
+}
+
+fn export_struct(decl: &StructDeclare) -> DataTypeDecl {
+    DataTypeDecl {
+        name: decl.name().to_string(),
+        base_type: TypeElement::new(DataType::Struct {
+            variable: decl
+                .variables()
+                .iter()
+                .map(|x| export_variable(x))
+                .collect(),
+        }),
+        add_data: None,
+    }
+}
+
+fn export_enum(decl: &EnumDeclare) -> DataTypeDecl {
+    let values = decl
+        .fields()
+        .iter()
+        .map(|x| EnumValue {
+            name: x.name().to_string(),
+            value: x.initial().as_ref().map(|x| x.to_string()),
+        })
+        .collect();
+
+    DataTypeDecl {
+        name: decl.name().to_string(),
+        base_type: TypeElement::new(DataType::Enum {
+            values: EnumValues { value: values },
+            base_type: decl
+                .ty()
+                .as_ref()
+                .map(|x| Box::new(TypeElement::new(export_type(x)))),
+        }),
+        add_data: None,
+    }
+}
+
+fn export_configuration(decl: &ConfigurationDeclare) -> Configuration {
+    let resource = decl
+        .resources()
+        .iter()
+        .map(|res| {
+            let parameter = |task: &TaskDeclare, name| task.parameter(name).map(|x| x.to_string());
+            let task = res
+                .tasks()
+                .iter()
+                .map(|task| Task {
+                    name: task.name().to_string(),
+                    single: parameter(task, "SINGLE"),
+                    interval: parameter(task, "INTERVAL"),
+                    priority: parameter(task, "PRIORITY").unwrap_or("0".to_owned()),
+                    pou_instance: res
+                        .programs()
+                        .iter()
+                        .filter(|x| x.task() == Some(task.name()))
+                        .map(export_instance)
+                        .collect(),
+                })
+                .collect();
+
+            Resource {
+                name: res.name().to_string(),
+                task,
+                global_vars: vec![],
+                pou_instance: res
+                    .programs()
+                    .iter()
+                    .filter(|x| x.task().is_none())
+                    .map(export_instance)
+                    .collect(),
+            }
+        })
+        .collect();
+
+    Configuration {
+        name: decl.name().to_string(),
+        resource,
+        global_vars: export_var_lists(decl.variables())
+            .into_iter()
+            .map(|(_, x)| x)
+            .collect(),
+    }
+}
+
+fn export_instance(program: &ProgramInstanceDeclare) -> POUInstance {
+    POUInstance {
+        name: program.name().to_string(),
+        type_name: program.program().to_string(),
+    }
+}
+
+/// Data type element of type, 'BIT' and generic type families are exported as derived types
+fn export_type(ty: &Type) -> DataType {
+    if let Some(sub) = ty.as_subrange() {
+        let range = Range {
+            lower: sub.range().lower().to_string(),
+            upper: sub.range().upper().to_string(),
+        };
+        let base_type = Box::new(TypeElement::new(export_type(sub.base_type())));
+
+        return match sub.base_type().type_class() {
+            TypeClass::Byte | TypeClass::UInt | TypeClass::UDInt | TypeClass::ULInt => {
+                DataType::SubrangeUnsigned { range, base_type }
+            }
+            _ => DataType::SubrangeSigned { range, base_type },
+        };
+    }
+
+    if let Some(arr) = ty.as_array() {
+        return DataType::Array {
+            dimension: arr
+                .dimensions()
+                .iter()
+                .map(|x| Range {
+                    lower: x.lower().to_string(),
+                    upper: x.upper().to_string(),
+                })
+                .collect(),
+            base_type: Box::new(TypeElement::new(export_type(arr.base_type()))),
+        };
+    }
+
+    if let Some(name) = ty.user_type_name() {
+        return DataType::Derived {
+            name: name.to_string(),
+        };
+    }
+
+    match ty.type_class() {
+        TypeClass::Bool => DataType::Bool,
+        TypeClass::SInt => DataType::SInt,
+        TypeClass::Byte => DataType::Byte,
+        TypeClass::Int => DataType::Int,
+        TypeClass::UInt => DataType::UInt,
+        TypeClass::DInt => DataType::DInt,
+        TypeClass::UDInt => DataType::UDInt,
+        TypeClass::LInt => DataType::LInt,
+        TypeClass::ULInt => DataType::ULInt,
+        TypeClass::Real => DataType::Real,
+        TypeClass::LReal => DataType::LReal,
+        TypeClass::Time => DataType::Time,
+        TypeClass::String => DataType::String { length: None },
+        class => DataType::Derived {
+            name: match class {
+                TypeClass::Generic(family) => family.name().to_owned(),
+                _ => "BIT".to_owned(),
+            },
+        },
+    }
+}
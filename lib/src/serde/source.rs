@@ -0,0 +1,276 @@
+//! Plain '.st' files, declarations of the file are followed by their bodies, like
+//! `PROGRAM main: VAR x: INT; END_VAR END_PROGRAM x := x + 1;`
+//!
+//! Declarations can be put into namespaces by 'NAMESPACE Motion.Axis ... END_NAMESPACE'
+//! blocks, and 'USING Motion;' directives of file or block let the names of namespace be
+//! used without qualifier.
+//!
+//! Pragmas right before a declaration, like `{attribute 'qualified_only'}`, belong to it.
+//!
+//! Conditional compilation directives are evaluated on the whole file, so that they can enclose
+//! declarations. Directives and the branches not taken are replaced by spaces in segments.
+use crate::parser::{
+    CompilerDefines, Location, ParseError, SkippedRegion, StLexerBuilder, StLexerOptions, StString,
+    TokenKind,
+};
+
+/// Declaration of '.st' file with the body following it. Text out of the segment is replaced
+/// by spaces, so that locations reported by parser are locations of the whole file.
+pub struct SourceSegment {
+    /// Keyword starts the declaration, like 'PROGRAM' or 'TYPE'
+    pub kind: TokenKind,
+    pub decl: String,
+    /// None if there are no tokens between the declaration and the next one
+    pub body: Option<String>,
+    /// Qualified name of the 'NAMESPACE' blocks declaration is in, empty if it's in none
+    pub namespace: StString,
+    /// Namespaces of 'USING' directives of the file and the enclosing blocks
+    pub using: Vec<StString>,
+}
+
+impl SourceSegment {
+    /// Programs, function blocks and functions without body do nothing
+    pub fn is_pou(&self) -> bool {
+        matches!(
+            self.kind,
+            TokenKind::Program | TokenKind::FunctionBlock | TokenKind::Function
+        )
+    }
+}
+
+/// 'NAMESPACE' block or the file itself, with its 'USING' directives
+struct NamespaceBlock {
+    namespace: StString,
+    using: Vec<StString>,
+}
+
+struct Segment {
+    kind: TokenKind,
+    start: Location,
+    end: Location,
+    /// End of body, the start of next declaration or namespace directive
+    body_end: Location,
+    namespace: StString,
+    using: Vec<StString>,
+}
+
+/// Directive being read, with the part of name read
+enum Directive {
+    Namespace(String),
+    Using(String),
+}
+
+/// Split text of '.st' file into declarations and bodies.
+/// 'NAMESPACE' blocks and 'USING' directives out of declarations are applied to segments.
+pub fn split_source(
+    text: &str,
+    defines: &CompilerDefines,
+) -> Result<Vec<SourceSegment>, ParseError> {
+    let end_of_text = end_location(text);
+
+    let mut blocks = vec![NamespaceBlock {
+        namespace: StString::empty(),
+        using: vec![],
+    }];
+    let mut segments: Vec<Segment> = vec![];
+    let mut end_kind = None;
+    let mut directive = None;
+    // body tokens follow the declaration, until the next directive
+    let mut in_body = false;
+    // start of pragmas read, which belong to the declaration follows them
+    let mut pragma_start = None;
+    let options = StLexerOptions::default().defines(defines.clone());
+    let mut lexer = StLexerBuilder::from_options(options).build_str(text);
+    for tok in lexer.by_ref() {
+        let tok = tok.map_err(ParseError::LexerError)?;
+
+        if let Some(kind) = &end_kind {
+            if tok.kind == *kind {
+                let end = Location {
+                    mark: tok.location.mark,
+                    offset: tok.location.offset + tok.length,
+                };
+                segments.last_mut().unwrap().end = end;
+                end_kind = None;
+                in_body = true;
+            }
+            continue;
+        }
+
+        match (&mut directive, &tok.kind) {
+            (Some(Directive::Namespace(name)), TokenKind::Identifier(ident)) => {
+                name.push_str(ident.as_ref());
+                continue;
+            }
+            (Some(Directive::Using(name)), TokenKind::Identifier(ident)) => {
+                name.push_str(ident.as_ref());
+                continue;
+            }
+            (Some(Directive::Namespace(name) | Directive::Using(name)), TokenKind::DotAccess) => {
+                name.push('.');
+                continue;
+            }
+            (Some(Directive::Using(name)), TokenKind::Comma) => {
+                blocks.last_mut().unwrap().using.push(name.as_str().into());
+                name.clear();
+                continue;
+            }
+            (Some(Directive::Using(name)), TokenKind::Semicolon) => {
+                blocks.last_mut().unwrap().using.push(name.as_str().into());
+                directive = None;
+                continue;
+            }
+            (Some(Directive::Using(_)), _) => {
+                return Err(ParseError::expect_tokens(
+                    tok.location,
+                    &[TokenKind::Semicolon],
+                ))
+            }
+            // name of namespace is followed by its declarations
+            (Some(Directive::Namespace(name)), _) => {
+                let namespace = match blocks.last().unwrap().namespace.as_ref() {
+                    "" => name.as_str().into(),
+                    parent => StString::new(format!("{}.{}", parent, name)),
+                };
+                blocks.push(NamespaceBlock {
+                    namespace,
+                    using: vec![],
+                });
+                directive = None;
+            }
+            (None, _) => {}
+        }
+
+        if matches!(tok.kind, TokenKind::Pragma(_)) {
+            pragma_start.get_or_insert(tok.location);
+            continue;
+        }
+
+        let pragma = pragma_start.take();
+        if let Some(kind) = declaration_end(&tok.kind) {
+            let start = pragma.unwrap_or(tok.location);
+            if in_body {
+                segments.last_mut().unwrap().body_end = start;
+            }
+
+            segments.push(Segment {
+                kind: tok.kind,
+                start,
+                end: end_of_text,
+                body_end: end_of_text,
+                namespace: blocks.last().unwrap().namespace.clone(),
+                using: blocks.iter().flat_map(|x| x.using.clone()).collect(),
+            });
+            end_kind = Some(kind);
+            continue;
+        }
+
+        match tok.kind {
+            TokenKind::Namespace | TokenKind::Using | TokenKind::EndNamespace => {
+                if let Some(location) = pragma {
+                    return Err(ParseError::expect_tokens(location, &DECLARATION_KEYWORDS));
+                }
+                if in_body {
+                    segments.last_mut().unwrap().body_end = tok.location;
+                    in_body = false;
+                }
+
+                match tok.kind {
+                    TokenKind::Namespace => directive = Some(Directive::Namespace(String::new())),
+                    TokenKind::Using => directive = Some(Directive::Using(String::new())),
+                    _ if blocks.len() > 1 => {
+                        blocks.pop();
+                    }
+                    _ => return Err(ParseError::InvalidToken(tok.location)),
+                }
+            }
+            _ if !in_body => {
+                let mut expected = DECLARATION_KEYWORDS.to_vec();
+                if pragma.is_none() {
+                    expected.extend([TokenKind::Namespace, TokenKind::Using]);
+                }
+
+                return Err(ParseError::expect_tokens(tok.location, &expected));
+            }
+            _ => {}
+        }
+    }
+
+    if blocks.len() > 1 || (pragma_start.is_some() && !in_body) {
+        return Err(ParseError::UnexpectedEnd);
+    }
+
+    let skipped = lexer.skipped_regions();
+    let mut result = Vec::with_capacity(segments.len());
+    for segment in segments {
+        let body = source_range(text, segment.end, segment.body_end, skipped);
+        let has_body = StLexerBuilder::new().build_str(&body).next().is_some();
+
+        result.push(SourceSegment {
+            kind: segment.kind,
+            decl: source_range(text, segment.start, segment.end, skipped),
+            body: has_body.then_some(body),
+            namespace: segment.namespace,
+            using: segment.using,
+        });
+    }
+
+    Ok(result)
+}
+
+/// Keywords start declarations
+const DECLARATION_KEYWORDS: [TokenKind; 6] = [
+    TokenKind::Program,
+    TokenKind::FunctionBlock,
+    TokenKind::Function,
+    TokenKind::Type,
+    TokenKind::VarGlobal,
+    TokenKind::Configuration,
+];
+
+/// Keyword ends the declaration started by token
+fn declaration_end(kind: &TokenKind) -> Option<TokenKind> {
+    Some(match kind {
+        TokenKind::Function => TokenKind::EndFunction,
+        TokenKind::FunctionBlock => TokenKind::EndFunctionBlock,
+        TokenKind::Program => TokenKind::EndProgram,
+        TokenKind::Type => TokenKind::EndType,
+        TokenKind::VarGlobal => TokenKind::EndVar,
+        TokenKind::Configuration => TokenKind::EndConfiguration,
+        _ => return None,
+    })
+}
+
+/// Location of the end of text, Location line starts from 0
+fn end_location(text: &str) -> Location {
+    let mark = text.matches('\n').count();
+    let offset = text.rsplit('\n').next().map_or(0, |x| x.chars().count());
+
+    Location { mark, offset }
+}
+
+/// Text between locations, text out of range or skipped is replaced by spaces
+fn source_range(text: &str, start: Location, end: Location, skipped: &[SkippedRegion]) -> String {
+    let position = |x: Location| (x.mark, x.offset);
+    let start = position(start);
+    let end = position(end);
+
+    let mut s = String::with_capacity(text.len());
+    for (mark, line) in text.split('\n').enumerate() {
+        if mark > 0 {
+            s.push('\n');
+        }
+
+        for (offset, c) in line.chars().enumerate() {
+            let pos = (mark, offset);
+            let in_range = pos >= start
+                && pos < end
+                && !skipped
+                    .iter()
+                    .any(|x| pos >= position(x.start) && pos < position(x.end));
+            s.push(if in_range || c == '\r' { c } else { ' ' });
+        }
+    }
+
+    s
+}
@@ -0,0 +1,292 @@
+//! TwinCAT 3 PLC project files. Every '.TcPOU', '.TcDUT' and '.TcGVL' file contains one object,
+//! the ST declaration is in 'Declaration' and the body of POU is in 'Implementation'. Methods,
+//! properties and actions of POU are objects in the POU with the same layout, the 'Get' and
+//! 'Set' accessors of property are objects in the property.
+use super::loader::{loader, Loader};
+use super::xml::{POUError, POUPart};
+use crate::context::{ModuleContext, ModuleKind};
+use crate::parser::{ParserTrait, StLexerBuilder, StString, TokenKind};
+
+use serde::de::IgnoredAny;
+use serde::Deserialize;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use uuid::Uuid;
+
+/// Extensions of object files
+const EXTENSIONS: [&str; 3] = ["TcDUT", "TcGVL", "TcPOU"];
+
+#[derive(Deserialize, Debug)]
+pub struct TcPlcObject {
+    #[serde(rename = "POU")]
+    pub pou: Option<TcPOU>,
+    #[serde(rename = "DUT")]
+    pub dut: Option<TcObject>,
+    #[serde(rename = "GVL")]
+    pub gvl: Option<TcObject>,
+}
+
+/// Data type or global variable list
+#[derive(Deserialize, Debug)]
+pub struct TcObject {
+    #[serde(rename = "@Name")]
+    pub name: String,
+    #[serde(rename = "@Id")]
+    pub id: Option<String>,
+    #[serde(rename = "Declaration", default)]
+    pub declaration: String,
+}
+
+#[derive(Deserialize, Debug)]
+#[allow(clippy::upper_case_acronyms)] // Allow upper case POU naming
+pub struct TcPOU {
+    #[serde(rename = "@Name")]
+    pub name: String,
+    #[serde(rename = "@Id")]
+    pub id: Option<String>,
+    #[serde(rename = "Declaration", default)]
+    pub declaration: String,
+    #[serde(rename = "Implementation")]
+    pub implementation: Option<Implementation>,
+    #[serde(rename = "Method", default)]
+    pub methods: Vec<TcMember>,
+    #[serde(rename = "Property", default)]
+    pub properties: Vec<TcMember>,
+    #[serde(rename = "Action", default)]
+    pub actions: Vec<TcMember>,
+}
+
+/// Method, property or action of POU, or accessor of property
+#[derive(Deserialize, Debug)]
+pub struct TcMember {
+    #[serde(rename = "@Name")]
+    pub name: String,
+    #[serde(rename = "@Id")]
+    pub id: Option<String>,
+    /// Empty for actions, variables only for accessors
+    #[serde(rename = "Declaration", default)]
+    pub declaration: String,
+    #[serde(rename = "Implementation")]
+    pub implementation: Option<Implementation>,
+    #[serde(rename = "Get")]
+    pub get: Option<Box<TcMember>>,
+    #[serde(rename = "Set")]
+    pub set: Option<Box<TcMember>>,
+}
+
+/// Body of POU, graphical languages are only recognized to be reported
+#[derive(Deserialize, Debug)]
+pub struct Implementation {
+    #[serde(rename = "ST")]
+    pub st: Option<String>,
+    /// Networks of LD and FBD
+    #[serde(rename = "NWL")]
+    pub nwl: Option<IgnoredAny>,
+    #[serde(rename = "SFC")]
+    pub sfc: Option<IgnoredAny>,
+    #[serde(rename = "CFC")]
+    pub cfc: Option<IgnoredAny>,
+    #[serde(rename = "IL")]
+    pub il: Option<IgnoredAny>,
+}
+
+impl Implementation {
+    /// Name of the language if body is not in ST
+    pub fn unsupported_language(&self) -> Option<&'static str> {
+        [
+            ("LD/FBD", self.nwl.is_some()),
+            ("SFC", self.sfc.is_some()),
+            ("CFC", self.cfc.is_some()),
+            ("IL", self.il.is_some()),
+        ]
+        .into_iter()
+        .find_map(|(name, present)| present.then_some(name))
+    }
+}
+
+/// Objects of TwinCAT PLC project
+#[derive(Debug, Default)]
+pub struct TwinCATProject {
+    pub objects: Vec<TcPlcObject>,
+}
+
+impl TwinCATProject {
+    /// Read object files in folder and its subfolders
+    pub fn read_dir<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let mut files = vec![];
+        find_files(path.as_ref(), &mut files)?;
+        files.sort();
+
+        let mut project = Self::default();
+        for file in files {
+            project.read_file(&file)?;
+        }
+
+        Ok(project)
+    }
+
+    /// Read one object file
+    pub fn read_file<P: AsRef<Path>>(&mut self, path: P) -> io::Result<()> {
+        let path = path.as_ref();
+        let xml = fs::read_to_string(path)?;
+        let object = quick_xml::de::from_str(&xml).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: {}", path.display(), e),
+            )
+        })?;
+
+        self.objects.push(object);
+        Ok(())
+    }
+
+    /// Load data types, global variable lists and POUs into module, object GUIDs are kept as
+    /// the object ids of declarations. Methods, properties and actions are declared in the
+    /// namespace named by their POU, like 'FB_Motor.Reset', and the accessors of property are
+    /// methods in the namespace of property, like 'FB_Motor.Speed.Get'. The value type of
+    /// accessors is the type of their property.
+    pub fn load(self) -> (ModuleContext, Vec<POUError>) {
+        let mut loader = loader(ModuleContext::new(ModuleKind::Application));
+
+        for object in self.objects.iter().filter_map(|x| x.dut.as_ref()) {
+            loader.add(
+                &object.name,
+                object_id(&object.id),
+                &object.declaration,
+                None,
+            );
+        }
+
+        for object in self.objects.iter().filter_map(|x| x.gvl.as_ref()) {
+            loader.add(
+                &object.name,
+                object_id(&object.id),
+                &object.declaration,
+                None,
+            );
+        }
+
+        for pou in self.objects.iter().filter_map(|x| x.pou.as_ref()) {
+            let uuid = object_id(&pou.id);
+            let implementation = &pou.implementation;
+            let body = st_body(&mut loader, &pou.name, uuid, POUPart::Body, implementation);
+            loader.add(&pou.name, uuid, &pou_declaration(&pou.declaration), body);
+
+            let namespace = StString::from(pou.name.as_str());
+            loader.set_namespace(namespace.clone(), vec![]);
+            for method in &pou.methods {
+                let part = POUPart::Method(method.name.as_str().into());
+                let decl = pou_declaration(&method.declaration);
+                add_member(&mut loader, &pou.name, part, method, &decl);
+            }
+            for property in &pou.properties {
+                let part = POUPart::Property(property.name.as_str().into());
+                let uuid = object_id(&property.id);
+                let decl = pou_declaration(&property.declaration);
+                loader.add_member(&pou.name, uuid, part.clone(), &decl, None);
+
+                let accessors = format!("{}.{}", pou.name, property.name);
+                loader.set_namespace(accessors.as_str().into(), vec![]);
+                for accessor in [&property.get, &property.set].into_iter().flatten() {
+                    let vars = accessor.declaration.trim_end();
+                    let decl = format!("METHOD {}\n{}\nEND_METHOD\n", accessor.name, vars);
+                    add_member(&mut loader, &pou.name, part.clone(), accessor, &decl);
+                }
+                loader.set_namespace(namespace.clone(), vec![]);
+            }
+            for action in &pou.actions {
+                let part = POUPart::Action(action.name.as_str().into());
+                let decl = format!("ACTION {}\nEND_ACTION\n", action.name);
+                add_member(&mut loader, &pou.name, part, action, &decl);
+            }
+            loader.set_namespace(StString::empty(), vec![]);
+        }
+
+        loader.finish()
+    }
+}
+
+/// Body in ST, empty if there is no implementation. Bodies in other languages are reported as
+/// unsupported part of POU `name`.
+fn st_body<'a, P: ParserTrait>(
+    loader: &mut Loader<P>,
+    name: &str,
+    uuid: Option<Uuid>,
+    part: POUPart,
+    implementation: &'a Option<Implementation>,
+) -> Option<&'a str> {
+    match implementation {
+        Some(Implementation { st: Some(st), .. }) => Some(st.as_str()),
+        Some(implementation) => {
+            let language = implementation.unsupported_language().unwrap_or("empty");
+            loader.unsupported(name, uuid, part, format!("{} body", language));
+            None
+        }
+        None => Some(""),
+    }
+}
+
+/// Add method, property accessor or action of POU `name`
+fn add_member<P: ParserTrait>(
+    loader: &mut Loader<P>,
+    name: &str,
+    part: POUPart,
+    member: &TcMember,
+    decl: &str,
+) {
+    let uuid = object_id(&member.id);
+    let body = st_body(loader, name, uuid, part.clone(), &member.implementation);
+    loader.add_member(name, uuid, part, decl, body);
+}
+
+/// Declarations of POU end without 'END_PROGRAM' or 'END_FUNCTION_BLOCK' in TwinCAT, and the
+/// declarations of methods and properties without 'END_METHOD' or 'END_PROPERTY'. Pragmas like
+/// `{attribute 'no_check'}` may be before the declaration.
+fn pou_declaration(declaration: &str) -> String {
+    let kinds: Vec<_> = StLexerBuilder::new()
+        .build_str(declaration)
+        .map_while(|x| x.ok().map(|tok| tok.kind))
+        .skip_while(|x| matches!(x, TokenKind::Pragma(_)))
+        .collect();
+
+    let end = match kinds.first() {
+        Some(TokenKind::Program) => TokenKind::EndProgram,
+        Some(TokenKind::FunctionBlock) => TokenKind::EndFunctionBlock,
+        Some(TokenKind::Function) => TokenKind::EndFunction,
+        Some(TokenKind::Method) => TokenKind::EndMethod,
+        Some(TokenKind::Property) => TokenKind::EndProperty,
+        _ => return declaration.to_owned(),
+    };
+    if kinds.last() == Some(&end) {
+        return declaration.to_owned();
+    }
+
+    format!("{}\n{}\n", declaration.trim_end(), end)
+}
+
+/// Object GUID, like '{2c2a7a3e-1f0b-4d56-9a3e-5b0c1d2e3f40}'
+fn object_id(id: &Option<String>) -> Option<Uuid> {
+    id.as_ref().and_then(|x| Uuid::from_str(x).ok())
+}
+
+fn find_files(dir: &Path, files: &mut Vec<PathBuf>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            find_files(&path, files)?;
+            continue;
+        }
+
+        let is_object = path
+            .extension()
+            .and_then(|x| x.to_str())
+            .is_some_and(|x| EXTENSIONS.iter().any(|ext| ext.eq_ignore_ascii_case(x)));
+        if is_object {
+            files.push(path);
+        }
+    }
+
+    Ok(())
+}
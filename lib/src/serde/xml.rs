@@ -1,22 +1,19 @@
 use crate::context::{ModuleContext, ModuleKind};
-use crate::parser::{ParserBuilder, StLexerBuilder};
+use crate::parser::{Location, ParseError, ParserBuilder, StLexerBuilder, StString};
 
 use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
 use std::str::FromStr;
 use uuid::Uuid;
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Default)]
 pub enum ProjectType {
+    #[default]
     App,
     Library,
 }
 
-impl Default for ProjectType {
-    fn default() -> Self {
-        Self::App
-    }
-}
-
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Project {
     #[serde(default, rename = "pou-list")]
@@ -29,39 +26,139 @@ pub struct Project {
     pub namespace: Option<String>,
 }
 
-impl From<Project> for ModuleContext {
-    fn from(proj: Project) -> Self {
-        let ctx = match proj.project_type {
+/// Part of POU in project
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum POUPart {
+    Interface,
+    Body,
+    Method(StString),
+    Property(StString),
+    Action(StString),
+}
+
+impl Display for POUPart {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            POUPart::Interface => f.write_str("interface"),
+            POUPart::Body => f.write_str("body"),
+            POUPart::Method(name) => write!(f, "method {}", name),
+            POUPart::Property(name) => write!(f, "property {}", name),
+            POUPart::Action(name) => write!(f, "action {}", name),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum POUErrorKind {
+    /// Location of parse error is relative to the text of the part
+    Parse(ParseError),
+    /// Part of POU can't be compiled, like bodies in LD or FBD
+    Unsupported(String),
+}
+
+impl POUErrorKind {
+    pub fn location(&self) -> Option<Location> {
+        match self {
+            POUErrorKind::Parse(e) => e.location(),
+            POUErrorKind::Unsupported(_) => None,
+        }
+    }
+}
+
+impl Display for POUErrorKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            POUErrorKind::Parse(e) => write!(f, "{}", e),
+            POUErrorKind::Unsupported(what) => write!(f, "{} is not supported", what),
+        }
+    }
+}
+
+impl From<ParseError> for POUErrorKind {
+    fn from(e: ParseError) -> Self {
+        POUErrorKind::Parse(e)
+    }
+}
+
+/// Error of POU in project, the POU is loaded without the part
+#[derive(Debug, Clone)]
+pub struct POUError {
+    /// Name of POU, None if interface can't be parsed
+    pub name: Option<StString>,
+    pub uuid: Option<Uuid>,
+    pub part: POUPart,
+    pub error: POUErrorKind,
+}
+
+impl Error for POUError {}
+
+impl Display for POUError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match &self.name {
+            Some(name) => write!(f, "{}", name)?,
+            None => f.write_str("<unknown>")?,
+        }
+        if let Some(uuid) = &self.uuid {
+            write!(f, " ({})", uuid)?;
+        }
+
+        write!(f, " {}: {}", self.part, self.error)
+    }
+}
+
+impl Project {
+    /// Parse POUs into module. POUs with errors in interface are skipped, and the
+    /// declaration is kept without function if the body has errors.
+    /// POUs are put into the namespace of project if it's set.
+    pub fn load(self) -> (ModuleContext, Vec<POUError>) {
+        let ctx = match self.project_type {
             ProjectType::App => ModuleContext::new(ModuleKind::Application),
             ProjectType::Library => ModuleContext::new(ModuleKind::Library),
         };
+        let parser = ParserBuilder::default().build();
+        let mut errors = vec![];
+
         let mut ctx_write = ctx.write();
+        for pou in self.pou_list.pou {
+            let uuid = pou.uuid_text.and_then(|s| Uuid::from_str(&s).ok());
 
-        for pou in proj.pou_list.pou {
             let mut lexer = StLexerBuilder::new().build_str(&pou.interface.content);
-            let decl = ParserBuilder::default()
-                .build()
-                .parse_decl(&mut lexer)
-                .unwrap();
-            let func = ctx_write.add_declaration(
-                decl,
-                pou.uuid_text
-                    .and_then(|s| Uuid::from_str(&s).ok())
-                    .unwrap_or(Uuid::nil()),
-            );
+            let mut decl = match parser.parse_decl(&mut lexer) {
+                Ok(decl) => decl,
+                Err(error) => {
+                    errors.push(POUError {
+                        name: None,
+                        uuid,
+                        part: POUPart::Interface,
+                        error: error.into(),
+                    });
+                    continue;
+                }
+            };
+            if let Some(namespace) = self.namespace.as_deref().filter(|x| !x.is_empty()) {
+                decl.set_namespace(namespace.into());
+            }
+            let name = decl.qualified_name();
+            let func = ctx_write.add_declaration(decl, uuid.unwrap_or(Uuid::nil()));
 
             if let Some(body) = pou.body {
                 let mut lexer = StLexerBuilder::new().build_str(&body.content);
-                let body = ParserBuilder::default()
-                    .build()
-                    .parse_stmt(&mut lexer)
-                    .unwrap();
-                ctx_write.add_function(func, body);
+                match parser.parse_stmt(&mut lexer) {
+                    Ok(body) => {
+                        ctx_write.add_function(func, body);
+                    }
+                    Err(error) => errors.push(POUError {
+                        name: Some(name),
+                        uuid,
+                        part: POUPart::Body,
+                        error: error.into(),
+                    }),
+                }
             }
         }
 
         drop(ctx_write);
-        ctx
+        (ctx, errors)
     }
 }
 
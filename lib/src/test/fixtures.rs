@@ -0,0 +1,243 @@
+//! Applications shared by the tests of backends and interpreter. Each POU is (declaration,
+//! body), every backend runs the same sources and checks the values in its own way.
+
+use crate::backend::{CodeGenBackend, CodeGenDriver, CodeGenError};
+use crate::parser::{ParserBuilder, StLexerBuilder};
+use crate::prelude::*;
+
+pub type Pou = (&'static str, &'static str);
+
+/// Load application with multiple POUs as the active application of a new `UnitsManager`
+pub fn load_application(pous: &[(&str, &str)]) -> (UnitsManager, ModuleContext) {
+    let mgr = UnitsManager::new();
+    let ctx = ModuleContext::new(ModuleKind::Application);
+
+    for (decl, body) in pous {
+        let mut lexer = StLexerBuilder::new().build_str(decl);
+        let decl = ParserBuilder::default()
+            .build()
+            .parse_decl(&mut lexer)
+            .unwrap();
+        let fun_id = ctx.write().add_declaration(decl, Uuid::new_v4());
+
+        // type declarations have no body
+        if body.is_empty() {
+            continue;
+        }
+
+        let mut lexer = StLexerBuilder::new().build_str(body);
+        let body = ParserBuilder::default()
+            .build()
+            .parse_stmt(&mut lexer)
+            .unwrap();
+        ctx.write().add_function(fun_id, body);
+    }
+
+    mgr.write().add_context(ctx.clone());
+    mgr.write().set_active_application(Some(ctx.read().id()));
+
+    (mgr, ctx)
+}
+
+/// Build application by backend `B`, options of code generation are set by `configure`
+pub fn build_application<B: CodeGenBackend>(
+    pous: &[(&str, &str)],
+    configure: impl FnOnce(&mut CodeGenDriver<B>),
+) -> Result<CodeGenDriver<B>, CodeGenError> {
+    let (mgr, ctx) = load_application(pous);
+
+    let ctx_id = ctx.read().id();
+    let mut code_gen: CodeGenDriver<B> = CodeGenDriver::new(mgr, ctx_id).unwrap();
+    configure(&mut code_gen);
+    code_gen.build_application()?;
+
+    Ok(code_gen)
+}
+
+pub const COUNTER_FB: Pou = (
+    "FUNCTION_BLOCK Counter \
+    VAR_INPUT step: INT; END_VAR \
+    VAR_OUTPUT count: INT; END_VAR \
+    END_FUNCTION_BLOCK",
+    "count := count + step;",
+);
+
+pub const INNER_FB: Pou = (
+    "FUNCTION_BLOCK Inner VAR_OUTPUT q: INT := 10; END_VAR END_FUNCTION_BLOCK",
+    "q := q + 1;",
+);
+
+/// Function block with instance of `INNER_FB`
+pub const OUTER_FB: Pou = (
+    "FUNCTION_BLOCK Outer \
+    VAR_INPUT x: SINT := -1; END_VAR \
+    VAR_OUTPUT q: INT; END_VAR \
+    VAR inner: Inner; END_VAR \
+    END_FUNCTION_BLOCK",
+    "inner(); q := inner.q + x;",
+);
+
+/// Structured types and `main` without body, like `(a.b).c`
+pub const NESTED_STRUCTS: [Pou; 3] = [
+    ("TYPE Inner: STRUCT c: INT; END_STRUCT END_TYPE", ""),
+    ("TYPE Outer: STRUCT b: Inner; END_STRUCT END_TYPE", ""),
+    ("PROGRAM main VAR a: Outer; x: INT; END_VAR END_PROGRAM", ""),
+];
+
+/// a b r l d: 3 -32768 4 1026 -2
+pub const STANDARD_NUMERICAL: Pou = (
+    "PROGRAM main: VAR a: INT; b: INT := -32768; r: REAL; l: LREAL; d: DINT; END_VAR END_PROGRAM",
+    "a := ABS(-3); b := ABS(b); r := SQRT(REAL#16.0); l := LOG(100.0) + EXPT(2.0, 10); \
+    d := TRUNC(-2.7);",
+);
+
+/// a b c d e: 2 64 3 192 0
+pub const STANDARD_BIT_SHIFT: Pou = (
+    "PROGRAM main: VAR a, b, c, d, e: BYTE; x: BYTE := 129; END_VAR END_PROGRAM",
+    "a := SHL(x, 1); b := SHR(x, 1); c := ROL(x, 1); d := ROR(x, 9); e := SHL(x, 8);",
+);
+
+/// a b c d: 2 6 100 20
+pub const STANDARD_SELECTION: Pou = (
+    "PROGRAM main: VAR a, b, c, d: INT; k: INT := 1; END_VAR END_PROGRAM",
+    "a := SEL(TRUE, 1, 2); b := MAX(3, 7, 5) + MIN(4, -1); c := LIMIT(0, 120, 100); \
+    d := MUX(k, 10, 20, 30);",
+);
+
+/// Error "MUX K out of range"
+pub const MUX_OUT_OF_RANGE: Pou = (
+    "PROGRAM main: VAR d: INT; k: INT := 2; END_VAR END_PROGRAM",
+    "d := MUX(k, 10, 20);",
+);
+
+/// a b c r: -3 -56 TRUE 3
+pub const STANDARD_CONVERSION: Pou = (
+    "PROGRAM main: VAR a: INT; b: SINT; c: BOOL; r: REAL; END_VAR END_PROGRAM",
+    "a := REAL_TO_INT(-2.5); b := INT_TO_SINT(200); c := INT_TO_BOOL(2); \
+    r := DINT_TO_REAL(3);",
+);
+
+/// q et cv cq: FALSE 20ms 1 FALSE after 3 cycles, TRUE 25ms 2 TRUE after 4 cycles
+pub const STANDARD_FUNCTION_BLOCKS: Pou = (
+    "PROGRAM main: VAR t: TON; c: CTU; q, cq: BOOL; et: TIME; n, cv: INT; END_VAR END_PROGRAM",
+    "t(IN := TRUE, PT := T#25ms, Q => q, ET => et); n := n + 1; \
+    c(CU := n MOD 2 = 0, PV := 2); cv := c.CV; cq := c.Q;",
+);
+
+/// a b c d e f g h: -128 255 -2 1 -2147483648 4294967295 -9223372036854775808
+/// 18446744073709551615
+pub const INTEGER_WRAP_AROUND: Pou = (
+    "PROGRAM main: VAR \
+    a: SINT; b: BYTE; c: INT; d: UINT; e: DINT; f: UDINT; g: LINT; h: ULINT; \
+    END_VAR END_PROGRAM",
+    "a := 127; a := a + 1; b := 0; b := b - 1; c := 32767; c := c * 2; \
+    d := 65535; d := d + 2; e := -2147483647 - 1; e := -e; f := 0; f := f - 1; \
+    g := 9223372036854775807; g := g + 1; h := 0; h := h - 1;",
+);
+
+/// a b c d x u r l: 3 -3 -1 1024 -128 1333333333 3.5 1.5
+pub const DIVISION: Pou = (
+    "PROGRAM main: VAR a, b, c, d: INT; x, y: SINT; u: UDINT; r: REAL; l: LREAL; END_VAR END_PROGRAM",
+    "a := 7 / 2; b := -7 / 2; c := -7 MOD 3; d := 2 ** 10; \
+    x := -128; y := -1; x := x / y; u := 4000000000; u := u / 3; \
+    r := 7.0; r := r / 2.0; l := 7.5; l := l MOD 2.0;",
+);
+
+/// a: 7
+pub const CONSTANT_EXPRESSION: [Pou; 2] = [
+    (
+        "VAR_GLOBAL CONSTANT MAX_AXES: INT := 4; LAST: INT := MAX_AXES - 1; END_VAR",
+        "",
+    ),
+    (
+        "PROGRAM main: VAR axes: ARRAY[0..MAX_AXES - 1] OF DINT; a: DINT; END_VAR END_PROGRAM",
+        "axes[LAST] := LAST * 2 + 1; a := axes[MAX_AXES - 1];",
+    ),
+];
+
+/// a b x y: 4 51 TRUE FALSE after 4 cycles
+pub const IF_STATEMENT: Pou = (
+    "PROGRAM main: VAR a, b: DINT; x, y: BOOL; END_VAR END_PROGRAM",
+    "a := a + 1; x := a < 5 AND NOT (a = 2); y := x XOR TRUE;\n\
+    if a = 1 then\n b := 10;\nelseif a > 2 then\n b := b + 20;\nelse\n b := b + 1;\nend_if\n",
+);
+
+/// a b d o.inner.q: 15 20 11 12 after 2 cycles
+pub const FUNCTION_BLOCKS: [Pou; 4] = [
+    (
+        "PROGRAM main: VAR c: Counter; o: Outer; a, b, d: INT; END_VAR END_PROGRAM",
+        "c(step := 2); c(step := 3); a := c.count; c(5, count => b); o(); d := o.q;",
+    ),
+    COUNTER_FB,
+    OUTER_FB,
+    INNER_FB,
+];
+
+/// m[2, 1] a b d l.style: 14 29 29 10 11 after 2 cycles
+pub const STRUCT_ARRAY_ENUM: [Pou; 4] = [
+    (
+        "TYPE Line: STRUCT a, b: Point; style: Color := Color.Blue; END_STRUCT END_TYPE",
+        "",
+    ),
+    (
+        "TYPE Point: STRUCT x: DINT := 1; y: DINT; END_STRUCT END_TYPE",
+        "",
+    ),
+    ("TYPE Color: (Red, Green := 10, Blue) INT; END_TYPE", ""),
+    (
+        "PROGRAM main: VAR \
+        m: ARRAY[1..2, 0..2] OF DINT; \
+        p: ARRAY[0..1] OF Point; \
+        l: Line; \
+        c: Color := Color.Green; \
+        a, b, d: DINT; \
+        END_VAR END_PROGRAM",
+        "m[2, 1] := m[2, 1] + 7; p[1].y := m[2, 1] * 2; a := p[1].y + p[0].x; \
+        l.b := p[1]; b := l.b.y + l.a.x; \
+        if l.style = Color.Blue then d := c + Red; end_if\n",
+    ),
+];
+
+/// Function `add` reports the sum by external function `report(sum)`, reports 3 15 -32768,
+/// x y z c: 3 15 -32768 TRUE
+pub const FUNCTION_CALL: [Pou; 2] = [
+    (
+        "FUNCTION add : INT \
+        VAR_INPUT a: INT; b: INT := 10; END_VAR \
+        VAR_OUTPUT sum: INT; carry: BOOL; END_VAR \
+        VAR tmp: DINT; END_VAR \
+        END_FUNCTION",
+        "tmp := a; tmp := tmp + b; sum := a + b; carry := tmp > 32767; add := sum; report(sum);",
+    ),
+    (
+        "PROGRAM main: VAR x, y, z: INT; c: BOOL; END_VAR END_PROGRAM",
+        "add(1, 2, sum => x); add(a := 5, sum => y); add(a := 32767, b := 1, sum => z, carry => c);",
+    ),
+];
+
+/// s x y: a"b? TRUE FALSE
+pub const STRING_COMPARE: Pou = (
+    r#"PROGRAM main: VAR s: STRING; t: STRING := "a\"b?"; x, y: BOOL; END_VAR END_PROGRAM"#,
+    r#"s := t; x := s = t; y := s = "abc";"#,
+);
+
+/// Error "array index out of range at line 3, column 3: a[i]" in the 2nd cycle
+pub const INDEX_OUT_OF_RANGE: Pou = (
+    "PROGRAM main: VAR a: ARRAY[1..3] OF INT; i: INT; END_VAR END_PROGRAM",
+    "i := i + 2;\n\
+    if i > 3 then\n  \
+    a[i] := 2;\n\
+    end_if\n",
+);
+
+/// Error "integer division by zero"
+pub const DIVISION_BY_ZERO: Pou = (
+    "PROGRAM main: VAR a, b: INT; END_VAR END_PROGRAM",
+    "a := 1;\nb := a / b;",
+);
+
+/// Instance of `COUNTER_FB` called every cycle, a: number of cycles
+pub const COUNTER_MAIN: Pou = (
+    "PROGRAM main: VAR c: Counter; a: INT; END_VAR END_PROGRAM",
+    "c(step := 1); a := c.count;",
+);
@@ -2,6 +2,7 @@ use crate::parser::{self, ParserBuilder, StLexerBuilder, StString};
 use crate::prelude::*;
 use std::fs;
 
+pub mod fixtures;
 mod test_type_analyze;
 
 #[test]
@@ -79,3 +80,960 @@ fn test_scope_lookup() {
 
     assert!(variable.is_some());
 }
+
+#[test]
+fn test_type_family() {
+    assert!(TypeClass::Int.belongs_to(TypeFamily::AnyNum));
+    assert!(TypeClass::UDInt.belongs_to(TypeFamily::AnyBit));
+    assert!(!TypeClass::DInt.belongs_to(TypeFamily::AnyBit));
+    assert!(TypeClass::Time.belongs_to(TypeFamily::AnyMagnitude));
+    assert!(!TypeClass::Time.belongs_to(TypeFamily::AnyNum));
+    assert!(TypeClass::String.belongs_to(TypeFamily::AnyElementary));
+    assert!(!TypeClass::Struct.belongs_to(TypeFamily::AnyElementary));
+    assert!(TypeClass::Struct.belongs_to(TypeFamily::Any));
+
+    // generic classes are members of enclosing families
+    let any_int = TypeClass::Generic(TypeFamily::AnyInt);
+    assert!(any_int.belongs_to(TypeFamily::AnyMagnitude));
+    assert!(!any_int.belongs_to(TypeFamily::AnyReal));
+    assert!(TypeFamily::AnyReal.is_within(TypeFamily::Any));
+    assert!(!TypeFamily::AnyNum.is_within(TypeFamily::AnyReal));
+
+    assert_eq!(
+        Type::from_identifier(StString::new("any_num")).type_class(),
+        TypeClass::Generic(TypeFamily::AnyNum)
+    );
+}
+
+#[test]
+fn test_project_load_errors() {
+    use crate::serde::{POUPart, Project};
+
+    let xml = r#"<application name="broken">
+    <pou-list>
+        <pou uuid-text="2a2e4c1e-7a4b-4b53-9c43-5b7e2f1d7c10">
+            <interface><![CDATA[
+program main:
+VAR x INT; END_VAR
+end_program
+]]></interface>
+        </pou>
+        <pou>
+            <interface><![CDATA[VAR_GLOBAL g: INT; END_VAR]]></interface>
+        </pou>
+        <pou uuid-text="6f0c9a57-0d0e-4a2d-8f7b-0d5b8e3c2a41">
+            <interface><![CDATA[program counter: VAR n: INT; END_VAR end_program]]></interface>
+            <body><![CDATA[n := n + 1;
+n := n + ;]]></body>
+        </pou>
+    </pou-list>
+</application>"#;
+    let project: Project = quick_xml::de::from_str(xml).unwrap();
+    let (ctx, errors) = project.load();
+
+    // broken POUs don't stop loading of the others
+    assert_eq!(errors.len(), 2);
+    assert!(ctx
+        .read()
+        .find_toplevel_global_variable(&"g".into())
+        .is_some());
+    let counter = ctx
+        .read()
+        .find_declaration_by_name(&"counter".into())
+        .cloned();
+    let counter_id = counter.unwrap().read().unwrap().id();
+    assert!(ctx.read().get_function(counter_id).is_none());
+
+    assert_eq!(errors[0].name, None);
+    assert_eq!(errors[0].part, POUPart::Interface);
+    assert_eq!(
+        errors[0].uuid.unwrap().to_string(),
+        "2a2e4c1e-7a4b-4b53-9c43-5b7e2f1d7c10"
+    );
+    let loc = errors[0].error.location().unwrap();
+    assert_eq!((loc.mark, loc.offset), (2, 6));
+
+    assert_eq!(errors[1].name, Some("counter".into()));
+    assert_eq!(errors[1].part, POUPart::Body);
+    let loc = errors[1].error.location().unwrap();
+    assert_eq!((loc.mark, loc.offset), (1, 9));
+    assert!(errors[1]
+        .to_string()
+        .starts_with("counter (6f0c9a57-0d0e-4a2d-8f7b-0d5b8e3c2a41) body: unexpected token at line 2, column 10"));
+}
+
+#[test]
+fn test_plcopen_import() {
+    use crate::serde::PLCopenProject;
+
+    let xml = fs::read_to_string("src/test/test_plcopen/tc6_project.xml").unwrap();
+    let project: PLCopenProject = quick_xml::de::from_str(&xml).unwrap();
+    let (ctx, errors) = project.load();
+    assert!(errors.is_empty(), "{:?}", errors);
+
+    let ctx = ctx.read();
+    for name in [
+        "MotorState",
+        "Percent",
+        "Speed",
+        "Axis",
+        "Samples",
+        "Scale",
+        "Motor",
+    ] {
+        let decl = ctx.find_declaration_by_name(&name.into());
+        assert!(decl.is_some(), "{}", name);
+    }
+
+    // external variables are resolved to the global variable list
+    let main = ctx.find_declaration_by_name(&"Main".into()).unwrap();
+    let main = main.read().unwrap();
+    let names: Vec<_> = main
+        .variables()
+        .iter()
+        .map(|x| x.name().to_string())
+        .collect();
+    assert_eq!(names, ["motor", "axis", "samples", "level", "output"]);
+    assert!(ctx.get_function(main.id()).is_some());
+
+    let motor = ctx.find_declaration_by_name(&"Motor".into()).unwrap();
+    let max_speed = motor.read().unwrap().variables()[3].clone();
+    assert!(max_speed.flags().contains(VariableFlags::CONST));
+
+    for name in ["cycles", "limit", "alarm", "operatingHours"] {
+        let variable = ctx.find_toplevel_global_variable(&name.into());
+        assert!(variable.is_some(), "{}", name);
+    }
+}
+
+#[test]
+fn test_plcopen_round_trip() {
+    use crate::serde::PLCopenProject;
+
+    fn export(ctx: &ModuleContext) -> String {
+        let mut project = PLCopenProject::from_module(ctx, "conveyor");
+        project.file_header.creation_date_time = "2024-03-18T10:21:07".to_owned();
+        quick_xml::se::to_string(&project).unwrap()
+    }
+
+    let xml = fs::read_to_string("src/test/test_plcopen/tc6_project.xml").unwrap();
+    let project: PLCopenProject = quick_xml::de::from_str(&xml).unwrap();
+    let (ctx, errors) = project.load();
+    assert!(errors.is_empty());
+
+    let exported = export(&ctx);
+    let project: PLCopenProject = quick_xml::de::from_str(&exported).unwrap();
+    assert_eq!(project.types.pous.pou.len(), 3);
+    assert_eq!(project.types.data_types.data_type.len(), 5);
+    let (ctx, errors) = project.load();
+    assert!(errors.is_empty(), "{:?}", errors);
+
+    assert_eq!(export(&ctx), exported);
+}
+
+#[test]
+fn test_plcopen_import_errors() {
+    use crate::serde::{PLCopenProject, POUPart};
+
+    let xml = r#"<project xmlns="http://www.plcopen.org/xml/tc6_0201">
+  <fileHeader companyName="" productName="" productVersion="" creationDateTime=""/>
+  <contentHeader name="broken"/>
+  <types>
+    <dataTypes/>
+    <pous>
+      <pou name="Anonymous" pouType="program">
+        <interface>
+          <localVars>
+            <variable name="mode">
+              <type><enum><values><value name="A"/><value name="B"/></values></enum></type>
+            </variable>
+          </localVars>
+        </interface>
+        <body><ST><xhtml xmlns="http://www.w3.org/1999/xhtml"/></ST></body>
+      </pou>
+      <pou name="Counter" pouType="program">
+        <interface>
+          <localVars>
+            <variable name="n"><type><INT/></type></variable>
+          </localVars>
+        </interface>
+        <body><ST><xhtml xmlns="http://www.w3.org/1999/xhtml">n := n + ;</xhtml></ST></body>
+      </pou>
+      <pou name="Empty" pouType="program">
+        <body><ST><xhtml xmlns="http://www.w3.org/1999/xhtml"/></ST></body>
+      </pou>
+    </pous>
+  </types>
+  <instances><configurations/></instances>
+</project>"#;
+    let project: PLCopenProject = quick_xml::de::from_str(xml).unwrap();
+    let (ctx, errors) = project.load();
+
+    assert_eq!(errors.len(), 2);
+    assert_eq!(errors[0].name, Some("Anonymous".into()));
+    assert_eq!(errors[0].part, POUPart::Interface);
+    assert_eq!(errors[1].name, Some("Counter".into()));
+    assert_eq!(errors[1].part, POUPart::Body);
+
+    // POUs with empty bodies do nothing
+    let ctx = ctx.read();
+    let empty = ctx.find_declaration_by_name(&"Empty".into()).unwrap();
+    assert!(ctx.get_function(empty.read().unwrap().id()).is_some());
+}
+
+#[test]
+fn test_twincat_project_load() {
+    use crate::serde::{POUErrorKind, POUPart, TwinCATProject};
+
+    let project = TwinCATProject::read_dir("src/test/test_twincat").unwrap();
+    let (ctx, errors) = project.load();
+    let ctx = ctx.read();
+
+    // object GUIDs are kept
+    let main = ctx.find_declaration_by_name(&"MAIN".into()).unwrap();
+    let main = main.read().unwrap();
+    assert_eq!(
+        main.object_id().to_string(),
+        "2c2a7a3e-1f0b-4d56-9a3e-5b0c1d2e3f40"
+    );
+    assert!(ctx.get_function(main.id()).is_some());
+    assert!(ctx
+        .find_toplevel_global_variable(&"speedSetpoint".into())
+        .is_some());
+    assert!(ctx.find_declaration_by_name(&"ST_Axis".into()).is_some());
+
+    // methods, properties and actions are declared in the namespace of POU
+    let reset = ctx
+        .find_declaration_by_name(&"FB_Motor.Reset".into())
+        .unwrap();
+    let reset = reset.read().unwrap();
+    assert_eq!(
+        reset.object_id().to_string(),
+        "d4e5f6a7-b8c9-4d0e-9f10-223344556677"
+    );
+    assert!(ctx.get_function(reset.id()).is_some());
+    let speed = ctx
+        .find_declaration_by_name(&"FB_Motor.Speed".into())
+        .unwrap();
+    assert!(ctx.get_function(speed.read().unwrap().id()).is_none());
+    for name in ["FB_Motor.Speed.Get", "FB_Motor.Speed.Set", "FB_Motor.Stop"] {
+        let member = ctx.find_declaration_by_name(&name.into()).unwrap();
+        assert!(ctx.get_function(member.read().unwrap().id()).is_some());
+    }
+
+    // graphical bodies are reported
+    let parts: Vec<_> = errors
+        .iter()
+        .map(|x| (x.name.clone().unwrap(), x.part.clone()))
+        .collect();
+    assert_eq!(
+        parts,
+        [
+            ("FB_Motor".into(), POUPart::Method("Trace".into())),
+            ("P_Interlock".into(), POUPart::Body),
+        ]
+    );
+    assert!(errors
+        .iter()
+        .all(|x| matches!(x.error, POUErrorKind::Unsupported(_))));
+    assert_eq!(
+        errors[0].to_string(),
+        "FB_Motor (b8c9d0e1-f2a3-4b42-9354-66778899aabb) method Trace: LD/FBD body is not supported"
+    );
+    let trace = ctx
+        .find_declaration_by_name(&"FB_Motor.Trace".into())
+        .unwrap();
+    assert!(ctx.get_function(trace.read().unwrap().id()).is_none());
+    assert_eq!(
+        errors[1].to_string(),
+        "P_Interlock (7e6d5c4b-3a29-4817-8605-f4e3d2c1b0a9) body: LD/FBD body is not supported"
+    );
+
+    // the declaration of POU without supported body is loaded
+    let interlock = ctx.find_declaration_by_name(&"P_Interlock".into()).unwrap();
+    assert!(ctx.get_function(interlock.read().unwrap().id()).is_none());
+}
+
+#[test]
+fn test_codesys_export_load() {
+    use crate::serde::{PLCopenProject, POUPart};
+
+    let xml = r#"<?xml version="1.0" encoding="utf-8"?>
+<project xmlns="http://www.plcopen.org/xml/tc6_0200">
+  <fileHeader companyName="" productName="CODESYS" productVersion="CODESYS V3.5 SP19" creationDateTime="2024-05-02T08:15:00"/>
+  <contentHeader name="Pump.project">
+    <coordinateInfo>
+      <fbd><scaling x="1" y="1"/></fbd>
+      <ld><scaling x="1" y="1"/></ld>
+      <sfc><scaling x="1" y="1"/></sfc>
+    </coordinateInfo>
+  </contentHeader>
+  <types>
+    <dataTypes/>
+    <pous>
+      <pou name="PLC_PRG" pouType="program">
+        <interface>
+          <localVars>
+            <variable name="running"><type><BOOL/></type></variable>
+          </localVars>
+        </interface>
+        <body>
+          <ST><xhtml xmlns="http://www.w3.org/1999/xhtml">running := NOT running;</xhtml></ST>
+        </body>
+        <addData>
+          <data name="http://www.3s-software.com/plcopenxml/objectid" handleUnknown="discard">
+            <ObjectId>f1e2d3c4-b5a6-4978-8a9b-0c1d2e3f4a5b</ObjectId>
+          </data>
+        </addData>
+      </pou>
+      <pou name="Valves" pouType="program">
+        <interface/>
+        <body>
+          <FBD>
+            <block localId="1" typeName="AND" height="40" width="60"/>
+          </FBD>
+        </body>
+      </pou>
+    </pous>
+  </types>
+  <instances>
+    <configurations/>
+  </instances>
+</project>"#;
+    let project: PLCopenProject = quick_xml::de::from_str(xml).unwrap();
+    let (ctx, errors) = project.load();
+
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].name, Some("Valves".into()));
+    assert_eq!(errors[0].part, POUPart::Body);
+    assert_eq!(errors[0].error.to_string(), "FBD body is not supported");
+
+    let ctx = ctx.read();
+    let prg = ctx.find_declaration_by_name(&"PLC_PRG".into()).unwrap();
+    assert_eq!(
+        prg.read().unwrap().object_id().to_string(),
+        "f1e2d3c4-b5a6-4978-8a9b-0c1d2e3f4a5b"
+    );
+    assert!(ctx.find_declaration_by_name(&"Valves".into()).is_some());
+}
+
+#[test]
+fn test_workspace_load() {
+    use crate::analysis::{check_declarations, DeclarationError, TypeAnalyzer};
+    use crate::context::ResolveError;
+    use crate::parse_statement;
+    use crate::serde::Workspace;
+
+    let workspace = Workspace::resolve("src/test/test_manifest/conveyor").unwrap();
+    let names: Vec<_> = workspace.packages.iter().map(|x| x.name.as_str()).collect();
+    assert_eq!(names, ["Util", "Motion", "Conveyor"]);
+    // namespace of dependency overrides the namespace of library
+    assert_eq!(
+        workspace.application().dependencies,
+        [("MC".into(), 1), ("U".into(), 0)]
+    );
+
+    let mgr = UnitsManager::new();
+    let (app, errors) = workspace.load(&mgr).unwrap();
+    assert!(errors.is_empty());
+    // builtin module is not a context of workspace
+    assert_eq!(mgr.read().contexts().count(), 3);
+
+    let app_id = app.read().id();
+    let scope = mgr.module_scope(app_id);
+    let resolve = |scope: &Scope, name: &str| {
+        scope
+            .resolve_declaration(&name.into())
+            .map(|(_, scope)| scope.local_context().unwrap().read().id())
+    };
+    let [motion_id, util_id] = [0, 1].map(|i| mgr.read().dependencies(app_id)[i].1);
+
+    // declarations of application shadow the declarations of libraries
+    assert_eq!(resolve(&scope, "Clamp"), Ok(app_id));
+    assert_eq!(resolve(&scope, "U.Clamp"), Ok(util_id));
+    assert_eq!(resolve(&scope, "moveabs"), Ok(motion_id));
+    assert_eq!(resolve(&scope, "mc.MoveAbs"), Ok(motion_id));
+    assert_eq!(
+        resolve(&scope, "Ramp"),
+        Err(ResolveError::Ambiguous(
+            "Ramp".into(),
+            vec!["MC".into(), "U".into()]
+        ))
+    );
+    assert_eq!(
+        resolve(&scope, "Motion.MoveAbs"),
+        Err(ResolveError::UnknownNamespace("Motion".into()))
+    );
+    assert_eq!(
+        resolve(&scope, "MC.Clamp"),
+        Err(ResolveError::NotFound("MC.Clamp".into()))
+    );
+
+    // names in library are resolved by the dependencies of library
+    let (_, motion) = scope.resolve_declaration(&"MC.MoveAbs".into()).unwrap();
+    assert_eq!(resolve(&motion, "Ramp"), Ok(motion_id));
+    assert_eq!(resolve(&motion, "U.Ramp"), Ok(util_id));
+    assert_eq!(resolve(&motion, "Clamp"), Ok(util_id));
+
+    assert_eq!(
+        check_declarations(&mgr, &app),
+        [DeclarationError::UnresolvedType(
+            "ramp".into(),
+            ResolveError::Ambiguous("Ramp".into(), vec!["MC".into(), "U".into()])
+        )]
+    );
+
+    let main = app.read().find_declaration_by_name(&"main".into()).cloned();
+    let main_id = main.unwrap().read().unwrap().id();
+    let main_scope = Scope::new(Some(mgr.clone()), Some(app_id), Some(main_id));
+    let mut type_analyzer = TypeAnalyzer::new();
+
+    let mut stmt = parse_statement!("speed := MC.Scale(speed);").unwrap();
+    type_analyzer.analyze_statement(&mut stmt, main_scope.clone());
+    let StmtKind::Expr(expr) = &stmt.kind else {
+        panic!()
+    };
+    let ExprKind::Assign(assign) = &expr.expr().kind else {
+        panic!()
+    };
+    let ty = assign.right().ty().map(|x| x.type_class());
+    assert_eq!(ty, Some(TypeClass::Int));
+    assert!(type_analyzer.errors().is_empty());
+
+    let mut stmt = parse_statement!("speed := Ramp(speed);").unwrap();
+    type_analyzer.analyze_statement(&mut stmt, main_scope);
+    assert_eq!(
+        type_analyzer.errors()[0].to_string(),
+        "Ramp: Ramp is ambiguous: MC.Ramp, U.Ramp"
+    );
+}
+
+#[test]
+fn test_workspace_errors() {
+    use crate::serde::{ManifestError, Workspace};
+
+    let libraries = fs::canonicalize("src/test/test_manifest").unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    let write_manifest = |name: &str, dependencies: &str| {
+        let root = dir.path().join(name);
+        fs::create_dir_all(&root).unwrap();
+        let manifest = format!(
+            "[package]\nname = \"{}\"\nversion = \"1.0.0\"\n\n[dependencies]\n{}\n",
+            name, dependencies
+        );
+        fs::write(root.join("stc.toml"), manifest).unwrap();
+        root
+    };
+
+    let motion = libraries.join("motion");
+    let root = write_manifest(
+        "Mismatch",
+        &format!("Motion = {{ path = {:?}, version = \"^2\" }}", motion),
+    );
+    let e = Workspace::resolve(root).unwrap_err();
+    assert!(matches!(e, ManifestError::VersionMismatch(..)));
+    assert_eq!(
+        e.to_string(),
+        "library Motion 1.2.3 doesn't match required version ^2"
+    );
+
+    let root = write_manifest("Renamed", &format!("Mover = {{ path = {:?} }}", motion));
+    let e = Workspace::resolve(root).unwrap_err();
+    assert!(matches!(e, ManifestError::NameMismatch(..)));
+
+    let root = write_manifest(
+        "Aliased",
+        &format!(
+            "Motion = {{ path = {:?} }}\nUtil = {{ path = {:?}, namespace = \"motion\" }}",
+            motion,
+            libraries.join("util")
+        ),
+    );
+    let e = Workspace::resolve(root).unwrap_err();
+    assert!(matches!(e, ManifestError::DuplicateNamespace(..)));
+
+    write_manifest("Left", "Right = { path = \"../Right\" }");
+    let root = write_manifest("Right", "Left = { path = \"../Left\" }");
+    let e = Workspace::resolve(root).unwrap_err();
+    assert!(matches!(e, ManifestError::Cycle(..)));
+}
+
+#[test]
+fn test_library_file() {
+    use crate::serde::{LibraryFile, PayloadKind, Workspace};
+    use std::io;
+
+    let workspace = Workspace::resolve("src/test/test_manifest/conveyor").unwrap();
+    let mgr = UnitsManager::new();
+    let (app, _) = workspace.load(&mgr).unwrap();
+    assert!(LibraryFile::from_module(&app, "Conveyor", "0.1.0".parse().unwrap()).is_none());
+
+    let util_id = mgr.read().dependencies(app.read().id())[1].1;
+    let util = mgr.read().get_context(util_id).unwrap();
+    let mut library = LibraryFile::from_module(&util, "Util", "0.3.1".parse().unwrap()).unwrap();
+    library.namespace = Some("U".to_owned());
+    library.set_payload(PayloadKind::Lua, vec![1, 2, 3]);
+    library.set_payload(PayloadKind::Lua, vec![4, 5]);
+    assert_eq!(library.payload(PayloadKind::Lua), Some([4, 5].as_slice()));
+    assert_eq!(library.payload(PayloadKind::Llvm), None);
+
+    // only constants of global variables are public
+    let globals: Vec<_> = library
+        .declarations
+        .iter()
+        .filter(|x| x.text.contains("VAR_GLOBAL"))
+        .collect();
+    assert_eq!(globals.len(), 1);
+    assert!(globals[0].text.contains("MaxSpeed"));
+    assert!(!globals[0].text.contains("counter"));
+
+    let mut buf = vec![];
+    library.write(&mut buf).unwrap();
+    assert_eq!(LibraryFile::read(&mut buf.as_slice()).unwrap(), library);
+
+    let mut corrupted = buf.clone();
+    *corrupted.last_mut().unwrap() ^= 0xff;
+    let e = LibraryFile::read(&mut corrupted.as_slice()).unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+    let mut newer = buf.clone();
+    newer[4] += 1;
+    let e = LibraryFile::read(&mut newer.as_slice()).unwrap_err();
+    assert_eq!(e.to_string(), "library format version 2 is not supported");
+
+    // interface is loaded without bodies, object ids are kept
+    let (interface, errors) = library.load_interface();
+    assert!(errors.is_empty());
+    assert_eq!(interface.read().functions().count(), 0);
+    let clamp = util
+        .read()
+        .find_declaration_by_name(&"Clamp".into())
+        .cloned();
+    let clamp_uuid = clamp.unwrap().read().unwrap().object_id();
+    let loaded = interface
+        .read()
+        .find_declaration_by_name(&"Clamp".into())
+        .cloned();
+    assert_eq!(loaded.unwrap().read().unwrap().object_id(), clamp_uuid);
+    assert!(library.is_up_to_date(&util));
+    assert!(!library.is_up_to_date(&interface));
+
+    // precompiled library is used by dependency like library folder
+    let dir = tempfile::tempdir().unwrap();
+    let file = dir.path().join("util.stlib");
+    fs::write(&file, &buf).unwrap();
+    let root = dir.path().join("app");
+    fs::create_dir_all(root.join("src")).unwrap();
+    fs::write(
+        root.join("stc.toml"),
+        format!(
+            "[package]\nname = \"App\"\nversion = \"1.0.0\"\n\n[dependencies]\nUtil = {{ path = {:?} }}\n",
+            file
+        ),
+    )
+    .unwrap();
+
+    let workspace = Workspace::resolve(&root).unwrap();
+    assert_eq!(workspace.packages[0].name, "Util");
+    assert_eq!(workspace.application().dependencies, [("U".into(), 0)]);
+    let mgr = UnitsManager::new();
+    let (app, errors) = workspace.load(&mgr).unwrap();
+    assert!(errors.is_empty());
+    let scope = mgr.module_scope(app.read().id());
+    assert!(scope.resolve_declaration(&"U.Clamp".into()).is_ok());
+    let max_speed = scope.find_global_variable(&"U.MaxSpeed".into()).unwrap();
+    assert!(max_speed.flags().contains(VariableFlags::CONST));
+}
+
+#[test]
+fn test_build_cache() {
+    use crate::serde::BuildCache;
+
+    let application = |pous: &[(&str, &str)]| {
+        let ctx = ModuleContext::new(ModuleKind::Application);
+        for (decl, body) in pous {
+            let mut lexer = StLexerBuilder::new().build_str(decl);
+            let decl = ParserBuilder::default()
+                .build()
+                .parse_decl(&mut lexer)
+                .unwrap();
+            let id = ctx.write().add_declaration(decl, Uuid::new_v4());
+            if !body.is_empty() {
+                let mut lexer = StLexerBuilder::new().build_str(body);
+                let body = ParserBuilder::default()
+                    .build()
+                    .parse_stmt(&mut lexer)
+                    .unwrap();
+                ctx.write().add_function(id, body);
+            }
+        }
+        ctx
+    };
+    let pous = [
+        ("TYPE Speed : INT; END_TYPE", ""),
+        ("VAR_GLOBAL limit : Speed; END_VAR", ""),
+        (
+            "FUNCTION Scale : INT VAR_INPUT x : INT; END_VAR END_FUNCTION",
+            "Scale := x * 2;",
+        ),
+        (
+            "FUNCTION_BLOCK Motor VAR s : Speed; END_VAR END_FUNCTION_BLOCK",
+            "s := limit;",
+        ),
+        (
+            "PROGRAM main VAR m : Motor; y : INT; END_VAR END_PROGRAM",
+            "y := Scale(x := 1); m();",
+        ),
+    ];
+    let names = |names: Vec<StString>| names.iter().map(|x| x.to_string()).collect::<Vec<_>>();
+
+    let mut cache = BuildCache::new(1);
+    assert!(cache.update(&application(&pous)).is_empty());
+    let motor = cache.unit(&"motor".into()).unwrap();
+    assert!(motor.dependencies.interface.contains(&"Speed".into()));
+    assert!(motor.dependencies.body.contains(&"limit".into()));
+    let main = cache.unit(&"main".into()).unwrap();
+    assert!(main.dependencies.body.contains(&"Scale".into()));
+    assert!(!main.dependencies.body.contains(&"y".into()));
+    for name in ["Scale", "Motor", "main"] {
+        cache.set_diagnostics(&name.into(), vec![]);
+        cache.set_code(&name.into(), name.as_bytes().to_vec());
+    }
+
+    let mut buf = vec![];
+    cache.write(&mut buf).unwrap();
+    let mut cache = BuildCache::read(&mut buf.as_slice()).unwrap();
+    assert_eq!(
+        names(cache.update(&application(&pous))),
+        ["main", "Motor", "Scale"]
+    );
+    assert_eq!(
+        cache.unit(&"Motor".into()).unwrap().code.as_deref(),
+        Some("Motor".as_bytes())
+    );
+
+    // callers only depend on the interface of function
+    let mut changed = pous;
+    changed[2].1 = "Scale := x * 3;";
+    assert_eq!(
+        names(cache.update(&application(&changed))),
+        ["main", "Motor"]
+    );
+    assert!(cache.unit(&"Scale".into()).unwrap().code.is_none());
+    cache.set_code(&"Scale".into(), vec![]);
+
+    // Motor uses Speed, main uses the interface of Motor
+    changed[0].0 = "TYPE Speed : DINT; END_TYPE";
+    assert_eq!(names(cache.update(&application(&changed))), ["Scale"]);
+
+    // codes are discarded if POUs are added, the errors are kept
+    changed[0].0 = "TYPE Speed : INT; END_TYPE";
+    cache.update(&application(&changed));
+    cache.set_diagnostics(&"Motor".into(), vec!["error".to_owned()]);
+    let mut added = changed.to_vec();
+    added.insert(2, ("FUNCTION Twice : INT END_FUNCTION", "Twice := 2;"));
+    assert_eq!(
+        names(cache.update(&application(&added))),
+        ["main", "Motor", "Scale"]
+    );
+    let motor = cache.unit(&"Motor".into()).unwrap();
+    assert_eq!(motor.diagnostics, Some(vec!["error".to_owned()]));
+    assert!(cache.units().all(|x| x.code.is_none()));
+}
+
+#[test]
+fn test_namespace() {
+    use crate::analysis::{check_declarations, TypeAnalyzer};
+    use crate::context::ResolveError;
+    use crate::serde::Workspace;
+
+    let workspace = Workspace::resolve("src/test/test_namespace").unwrap();
+    let mgr = UnitsManager::new();
+    let (app, errors) = workspace.load(&mgr).unwrap();
+    assert!(errors.is_empty());
+
+    let app_id = app.read().id();
+    let decl_scope = |name: &str| {
+        let decl = app.read().find_declaration_by_name(&name.into()).cloned();
+        let id = decl.unwrap().read().unwrap().id();
+        Scope::new(Some(mgr.clone()), Some(app_id), Some(id))
+    };
+    let resolve = |scope: &Scope, name: &str| {
+        scope
+            .resolve_declaration(&name.into())
+            .map(|(decl, _)| decl.read().unwrap().to_string())
+    };
+
+    // declarations are found by qualified names
+    let scope = mgr.module_scope(app_id);
+    assert_eq!(
+        resolve(&scope, "motion.axis.moveabs"),
+        Ok("Motion.Axis.MoveAbs (FUN)".to_owned())
+    );
+    assert_eq!(
+        resolve(&scope, "MoveAbs"),
+        Err(ResolveError::NotFound("MoveAbs".into()))
+    );
+    assert_eq!(
+        resolve(&scope, "Motion.Drive.MoveAbs"),
+        Err(ResolveError::UnknownNamespace("Motion.Drive".into()))
+    );
+    assert!(scope.is_namespace(&"Motion.Axis".into()));
+    assert!(!scope.is_namespace(&"Axis".into()));
+
+    // names of enclosing namespaces shadow the names of 'USING' namespaces
+    let move_abs = decl_scope("Motion.Axis.MoveAbs");
+    assert_eq!(
+        resolve(&move_abs, "Mode"),
+        Ok("Motion.Mode (ENUM)".to_owned())
+    );
+    assert_eq!(
+        resolve(&move_abs, "Limit"),
+        Ok("Motion.Axis.Limit (FUN)".to_owned())
+    );
+    let limit = decl_scope("Motion.Axis.Limit");
+    assert!(limit.find_variable(&"MaxSpeed".into()).is_some());
+
+    let main = decl_scope("main");
+    assert_eq!(
+        resolve(&main, "MoveAbs"),
+        Ok("Motion.Axis.MoveAbs (FUN)".to_owned())
+    );
+    assert_eq!(
+        resolve(&main, "Limit"),
+        Err(ResolveError::Ambiguous(
+            "Limit".into(),
+            vec!["Motion.Axis".into(), "Conveyor".into()]
+        ))
+    );
+
+    let errors: Vec<_> = check_declarations(&mgr, &app)
+        .iter()
+        .map(|x| x.to_string())
+        .collect();
+    assert_eq!(
+        errors,
+        [
+            "bad: unknown namespace Packing",
+            "Motion.Mode.Fault: duplicate enum value 1",
+        ]
+    );
+
+    let cases = [
+        (
+            "speed := Motion.Axis.Limit(speed);",
+            Some(TypeClass::Int),
+            None,
+        ),
+        ("speed := Motion.MaxSpeed;", Some(TypeClass::Int), None),
+        (
+            "speed := Limit(speed);",
+            None,
+            Some("Limit: Limit is ambiguous: Motion.Axis.Limit, Conveyor.Limit"),
+        ),
+    ];
+    for (code, expected, error) in cases {
+        let mut lexer = StLexerBuilder::new().build_str(code);
+        let mut stmt = ParserBuilder::default()
+            .build()
+            .parse_stmt(&mut lexer)
+            .unwrap();
+        let mut type_analyzer = TypeAnalyzer::new();
+        type_analyzer.analyze_statement(&mut stmt, main.clone());
+
+        let StmtKind::Expr(expr) = &stmt.kind else {
+            panic!("{}", code)
+        };
+        let ExprKind::Assign(assign) = &expr.expr().kind else {
+            panic!("{}", code)
+        };
+        let ty = match &assign.right().kind {
+            ExprKind::Compo(compo) => compo.ty(),
+            _ => assign.right().ty().cloned(),
+        };
+        let ty = ty.map(|x| x.type_class());
+        assert_eq!(ty, expected, "{}", code);
+
+        let errors: Vec<_> = type_analyzer
+            .errors()
+            .iter()
+            .map(|x| x.to_string())
+            .collect();
+        assert_eq!(errors, error.into_iter().collect::<Vec<_>>(), "{}", code);
+    }
+}
+
+#[test]
+fn test_pragma() {
+    use crate::analysis::{
+        check_declarations, collect_pragma_messages, eval_constant, ConstValue, TypeAnalyzer,
+    };
+    use crate::serde::Workspace;
+    use crate::utils::HasAttribute;
+
+    let workspace = Workspace::resolve("src/test/test_pragma").unwrap();
+    let mgr = UnitsManager::new();
+    let (app, errors) = workspace.load(&mgr).unwrap();
+    assert!(errors.is_empty());
+
+    // pragmas are stored in attributes of declarations, variables and statements
+    let main = app.read().find_declaration_by_name(&"main".into()).cloned();
+    let main = main.unwrap();
+    let main_id = main.read().unwrap().id();
+    let variable = |name: &str| {
+        let main = main.read().unwrap();
+        let variable = main
+            .variables()
+            .iter()
+            .find(|x| x.name() == &StString::new(name));
+        variable.cloned().unwrap()
+    };
+    assert_eq!(
+        variable("mode").get_attribute_value(&"hide".into()),
+        Some(&None)
+    );
+    assert!(variable("color")
+        .get_attribute_value(&"hide".into())
+        .is_none());
+
+    let f = app.read().get_function(main_id).cloned().unwrap();
+    let f = f.read();
+    let StmtKind::Stmts(stmts) = &f.parse_tree().kind else {
+        panic!()
+    };
+    assert!(stmts[0].get_attribute_value(&"no_check".into()).is_none());
+    assert!(stmts[1].get_attribute_value(&"no_check".into()).is_some());
+
+    let messages: Vec<_> = collect_pragma_messages(&app)
+        .iter()
+        .map(|x| (x.is_error, x.to_string(), x.location.map(|loc| loc.mark)))
+        .collect();
+    assert_eq!(
+        messages,
+        [
+            (false, "main: not tested on target".to_owned(), None),
+            (false, "main: use Mode instead".to_owned(), None),
+            (false, "main: use Mode instead".to_owned(), None),
+            (true, "main: unreachable".to_owned(), Some(29)),
+            (true, "main: remove it".to_owned(), Some(29)),
+        ]
+    );
+
+    let errors: Vec<_> = check_declarations(&mgr, &app)
+        .iter()
+        .map(|x| x.to_string())
+        .collect();
+    assert_eq!(
+        errors,
+        ["Frame: invalid pack_mode '3', expected 1, 2, 4 or 8"]
+    );
+
+    // members of 'qualified_only' enum must be qualified
+    let scope = Scope::new(Some(mgr.clone()), Some(app.read().id()), Some(main_id));
+    let cases = [
+        (
+            "mode := Idle;",
+            Some("Idle: member of enum 'Mode' must be qualified, like 'Mode.Idle'"),
+        ),
+        ("mode := Mode.Idle;", None),
+        ("color := Green;", None),
+    ];
+    for (code, error) in cases {
+        let mut lexer = StLexerBuilder::new().build_str(code);
+        let mut stmt = ParserBuilder::default()
+            .build()
+            .parse_stmt(&mut lexer)
+            .unwrap();
+        let mut type_analyzer = TypeAnalyzer::new();
+        type_analyzer.analyze_statement(&mut stmt, scope.clone());
+
+        let errors: Vec<_> = type_analyzer
+            .errors()
+            .iter()
+            .map(|x| x.to_string())
+            .collect();
+        assert_eq!(errors, error.into_iter().collect::<Vec<_>>(), "{}", code);
+    }
+
+    let eval = |value: &str| {
+        let code = format!("x := {};", value);
+        let mut lexer = StLexerBuilder::new().build_str(&code);
+        let stmt = ParserBuilder::default()
+            .build()
+            .parse_stmt(&mut lexer)
+            .unwrap();
+        let StmtKind::Expr(expr) = &stmt.kind else {
+            panic!("{}", code)
+        };
+        let ExprKind::Assign(assign) = &expr.expr().kind else {
+            panic!("{}", code)
+        };
+        eval_constant(assign.right(), &app).ok()
+    };
+    assert_eq!(eval("Mode.Fault"), Some(ConstValue::Integer(2, None)));
+    assert_eq!(eval("Fault"), None);
+    assert_eq!(eval("Green"), Some(ConstValue::Integer(1, None)));
+}
+
+#[test]
+fn test_conditional_compilation() {
+    use crate::parser::{CompilerDefines, ParseError, StLexerOptions};
+    use crate::serde::split_source;
+
+    let code = "\
+{IF defined(SIMULATION)}
+FUNCTION read_input: INT
+END_FUNCTION
+read_input := 42;
+{ELSE}
+FUNCTION read_input: INT
+VAR_INPUT channel: INT; END_VAR
+END_FUNCTION
+read_input := channel;
+{END_IF}
+
+PROGRAM main:
+VAR x: INT; END_VAR
+END_PROGRAM
+{IF hasvalue(MACHINE, 'CX9020')}
+x := read_input(1);
+{ELSE}
+x := read_input(2) + ;
+{END_IF}
+";
+    let parse = |defines: &CompilerDefines| {
+        let segments = split_source(code, defines).unwrap();
+        let parser = ParserBuilder::default().build();
+        let lexer = |text| {
+            let options = StLexerOptions::default().defines(defines.clone());
+            StLexerBuilder::from_options(options).build_str(text)
+        };
+
+        let mut bodies = vec![];
+        for segment in &segments {
+            parser.parse_decl(&mut lexer(&segment.decl))?;
+            let body = segment.body.as_deref().unwrap_or_default();
+            parser.parse_stmt(&mut lexer(body))?;
+            bodies.push(body.trim().to_owned());
+        }
+
+        Ok::<_, ParseError>(bodies)
+    };
+
+    // declarations and statements of the branches not taken are skipped
+    let mut defines = CompilerDefines::new();
+    defines.define("SIMULATION", None);
+    defines.define("MACHINE", Some("CX9020".to_owned()));
+    assert_eq!(
+        parse(&defines).unwrap(),
+        ["read_input := 42;", "x := read_input(1);"]
+    );
+
+    // locations of the branches taken are the locations in file
+    let mut defines = CompilerDefines::new();
+    defines.define("MACHINE", Some("CX5130".to_owned()));
+    let location = parse(&defines).unwrap_err().location().unwrap();
+    assert_eq!((location.mark, location.offset), (17, 21));
+
+    let code = code.replace("{END_IF}\n\nPROGRAM", "\nPROGRAM");
+    assert!(split_source(&code, &defines).is_err());
+}
@@ -8,7 +8,8 @@ use quick_xml::de::from_str;
 #[test]
 fn test_type_analyze() {
     let app: Project = from_str(include_str!("test_projects/test_proj1.xml")).unwrap();
-    let ctx: ModuleContext = app.into();
+    let (ctx, errors) = app.load();
+    assert!(errors.is_empty());
 
     let mgr = UnitsManager::new();
     let ctx_id = ctx.read().id();
@@ -44,3 +45,292 @@ fn test_type_analyze() {
 
     // TODO: test right side type, it's an operator expression
 }
+
+// the hand-written parser does not support calls in expressions
+#[test]
+fn test_generic_function_instance() {
+    let app: Project = from_str(include_str!("test_projects/test_proj1.xml")).unwrap();
+    let (ctx, errors) = app.load();
+    assert!(errors.is_empty());
+
+    let mgr = UnitsManager::new();
+    let ctx_id = ctx.read().id();
+    mgr.write().add_context(ctx.clone());
+    mgr.write().set_active_application(Some(ctx.read().id()));
+
+    let cases = [
+        ("c := MAX(a, b);", Some(TypeClass::UDInt)),
+        ("c := MAX(a, b, c);", Some(TypeClass::Real)),
+        ("c := ABS(c);", Some(TypeClass::Real)),
+        ("a := SHL(b, 2);", Some(TypeClass::UDInt)),
+        ("a := LIMIT(0, a, 100);", Some(TypeClass::Int)),
+        ("c := SQRT(4);", Some(TypeClass::LReal)),
+        ("c := INT_TO_REAL(IN := a);", Some(TypeClass::Real)),
+        ("a := LEN(\"abc\");", Some(TypeClass::Int)),
+        ("a := main();", None),
+    ];
+
+    for (code, expected) in cases {
+        let mut lexer = StLexerBuilder::new().build_str(code);
+        let mut stmt = ParserBuilder::default()
+            .build()
+            .parse_stmt(&mut lexer)
+            .unwrap();
+        let mut type_analyzer = TypeAnalyzer::new();
+        type_analyzer.analyze_statement(&mut stmt, mgr.module_scope(ctx_id));
+
+        let StmtKind::Expr(expr) = &stmt.kind else {
+            panic!("{}", code)
+        };
+        let ExprKind::Assign(assign) = &expr.expr().kind else {
+            panic!("{}", code)
+        };
+        let ty = assign.right().ty().map(|x| x.type_class());
+        assert_eq!(ty, expected, "{}", code);
+    }
+}
+
+#[test]
+fn test_generic_user_function() {
+    use crate::analysis::{GenericError, TypeAnalyzeError};
+    use crate::builtin::GENERIC_ATTRIBUTE;
+    use crate::utils::HasAttribute;
+
+    let app: Project = from_str(include_str!("test_projects/test_proj1.xml")).unwrap();
+    let (ctx, errors) = app.load();
+    assert!(errors.is_empty());
+
+    let functions = [
+        (
+            "FUNCTION Twice: ANY_NUM VAR_INPUT x: ANY_NUM; END_VAR END_FUNCTION",
+            true,
+        ),
+        (
+            "FUNCTION Zero: ANY_NUM VAR_INPUT n: INT; END_VAR END_FUNCTION",
+            true,
+        ),
+        (
+            "FUNCTION Plain: INT VAR_INPUT x: ANY_INT; END_VAR END_FUNCTION",
+            false,
+        ),
+    ];
+    for (code, generic) in functions {
+        let mut lexer = StLexerBuilder::new().build_str(code);
+        let mut decl = ParserBuilder::default()
+            .build()
+            .parse_decl(&mut lexer)
+            .unwrap();
+        if generic {
+            decl.set_attribute(StString::new(GENERIC_ATTRIBUTE), None);
+        }
+        ctx.write().add_declaration(decl, Uuid::new_v4());
+    }
+
+    let mgr = UnitsManager::new();
+    let ctx_id = ctx.read().id();
+    mgr.write().add_context(ctx.clone());
+    mgr.write().set_active_application(Some(ctx.read().id()));
+
+    let ambiguous =
+        GenericError::Ambiguous(TypeFamily::AnyElementary, TypeClass::Real, TypeClass::Time);
+    let cases = [
+        ("c := Twice(a);", Some(TypeClass::Int), None),
+        ("c := Twice(c);", Some(TypeClass::Real), None),
+        ("c := Twice(Twice(b));", Some(TypeClass::UDInt), None),
+        ("c := MAX(c, T#1s);", None, Some(ambiguous)),
+        (
+            "c := Zero(1);",
+            None,
+            Some(GenericError::Unbound(TypeFamily::AnyNum)),
+        ),
+        ("a := Plain(a);", None, Some(GenericError::NotGeneric)),
+    ];
+
+    for (code, expected, error) in cases {
+        let mut lexer = StLexerBuilder::new().build_str(code);
+        let mut stmt = ParserBuilder::default()
+            .build()
+            .parse_stmt(&mut lexer)
+            .unwrap();
+        let mut type_analyzer = TypeAnalyzer::new();
+        type_analyzer.analyze_statement(&mut stmt, mgr.module_scope(ctx_id));
+
+        let StmtKind::Expr(expr) = &stmt.kind else {
+            panic!("{}", code)
+        };
+        let ExprKind::Assign(assign) = &expr.expr().kind else {
+            panic!("{}", code)
+        };
+        let ty = assign.right().ty().map(|x| x.type_class());
+        assert_eq!(ty, expected, "{}", code);
+
+        let errors: Vec<_> = type_analyzer
+            .errors()
+            .iter()
+            .filter_map(|x| match x {
+                TypeAnalyzeError::InvalidCall(_, e) => Some(e.clone()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(errors, error.into_iter().collect::<Vec<_>>(), "{}", code);
+    }
+}
+
+#[test]
+fn test_constant_expression() {
+    use crate::analysis::{eval_constant, fold_constants, ConstError, TypeAnalyzeError};
+
+    let ctx = ModuleContext::new(ModuleKind::Application);
+    let declarations = [
+        "VAR_GLOBAL CONSTANT MAX_AXES: INT := 4; LAST: INT := MAX_AXES - 1; \
+        BIG: SINT := 100 + 28; SCALE: REAL := 1.5; END_VAR",
+        "VAR_GLOBAL count: INT := 4; END_VAR",
+        "TYPE Color: (Red, Green := 10, Blue) INT; END_TYPE",
+    ];
+    for code in declarations {
+        let mut lexer = StLexerBuilder::new().build_str(code);
+        let decl = ParserBuilder::default()
+            .build()
+            .parse_decl(&mut lexer)
+            .unwrap();
+        ctx.write().add_declaration(decl, Uuid::new_v4());
+    }
+
+    let mgr = UnitsManager::new();
+    let ctx_id = ctx.read().id();
+    mgr.write().add_context(ctx.clone());
+    mgr.write().set_active_application(Some(ctx_id));
+    let scope = mgr.module_scope(ctx_id);
+
+    let cases = [
+        ("x := MAX_AXES * 2 - 1;", Ok("INT#7")),
+        ("x := LAST;", Ok("INT#3")),
+        ("x := Color.Blue + Red;", Ok("LINT#11")),
+        ("x := SCALE * 2;", Ok("REAL#3.0")),
+        ("x := -7 / 2 + -7 MOD 2;", Ok("LINT#-4")),
+        ("x := MAX_AXES > 3;", Ok("BOOL#true")),
+        (
+            "x := MAX_AXES * 10000;",
+            Err(ConstError::Overflow(TypeClass::Int)),
+        ),
+        ("x := BIG;", Err(ConstError::Overflow(TypeClass::SInt))),
+        ("x := 1 / (LAST - 3);", Err(ConstError::DivisionByZero)),
+        ("x := count + 1;", Err(ConstError::NotConstant)),
+    ];
+    for (code, expected) in cases {
+        let mut lexer = StLexerBuilder::new().build_str(code);
+        let stmt = ParserBuilder::default()
+            .build()
+            .parse_stmt(&mut lexer)
+            .unwrap();
+        let StmtKind::Expr(expr) = &stmt.kind else {
+            panic!("{}", code)
+        };
+        let ExprKind::Assign(assign) = &expr.expr().kind else {
+            panic!("{}", code)
+        };
+
+        let value = eval_constant(assign.right(), &scope).map(|x| x.to_string());
+        assert_eq!(value, expected.map(|x| x.to_owned()), "{}", code);
+    }
+
+    // division by constant zero is reported by type analysis
+    let mut stmt = parse_statement!("count := count / (MAX_AXES - 4);").unwrap();
+    let mut type_analyzer = TypeAnalyzer::new();
+    type_analyzer.analyze_statement(&mut stmt, scope.clone());
+    assert!(matches!(
+        type_analyzer.errors(),
+        [TypeAnalyzeError::InvalidConstant(
+            _,
+            ConstError::DivisionByZero
+        )]
+    ));
+
+    // only constant sub-expressions are folded, assignment targets are kept
+    let mut stmt = parse_statement!("count := count + LAST * 2 + Color.Green;").unwrap();
+    assert!(fold_constants(&mut stmt, &scope).is_empty());
+    assert_eq!(stmt.to_string().trim(), "count := (count + 6) + 10;");
+}
+
+#[test]
+fn test_enum_and_subrange_declarations() {
+    use crate::analysis::{check_declarations, DeclarationError, TypeAnalyzeError};
+    use crate::backend::utils::enum_values;
+
+    let declarations = [
+        "TYPE State: (Idle := 0, Run := 10, Stop, Error := -1) INT; END_TYPE",
+        "TYPE Percent: INT(0..100); END_TYPE",
+        "VAR_GLOBAL CONSTANT FULL: INT := 100; END_VAR",
+        "VAR_GLOBAL level: Percent := FULL; speed: SINT(-10..10) := 5; END_VAR",
+    ];
+    let mgr = UnitsManager::new();
+    let ctx = ModuleContext::new(ModuleKind::Application);
+    for code in declarations {
+        let mut lexer = StLexerBuilder::new().build_str(code);
+        let decl = ParserBuilder::default()
+            .build()
+            .parse_decl(&mut lexer)
+            .unwrap();
+        ctx.write().add_declaration(decl, Uuid::new_v4());
+    }
+    let ctx_id = ctx.read().id();
+    mgr.write().add_context(ctx.clone());
+    mgr.write().set_active_application(Some(ctx_id));
+    assert!(check_declarations(&mgr, &ctx).is_empty());
+
+    // implicit values follow the previous value
+    let state = ctx
+        .read()
+        .find_declaration_by_name(&"State".into())
+        .cloned()
+        .unwrap();
+    let state = state.read().unwrap();
+    let DeclKind::Enum(e) = &state.decl().kind else {
+        panic!("State is not enum")
+    };
+    let values: Vec<_> = enum_values(e).into_iter().map(|(_, v)| v).collect();
+    assert_eq!(values, [0, 10, 11, -1]);
+
+    // constant assignments are checked against the subrange
+    let scope = mgr.module_scope(ctx_id);
+    let mut type_analyzer = TypeAnalyzer::new();
+    for code in ["level := 50;", "level := FULL + 1;", "speed := -11;"] {
+        let mut lexer = StLexerBuilder::new().build_str(code);
+        let mut stmt = ParserBuilder::default()
+            .build()
+            .parse_stmt(&mut lexer)
+            .unwrap();
+        type_analyzer.analyze_statement(&mut stmt, scope.clone());
+    }
+    assert_eq!(
+        type_analyzer.errors(),
+        &[
+            TypeAnalyzeError::OutOfRange("level := FULL + 1".to_owned(), 101, (0, 100)),
+            TypeAnalyzeError::OutOfRange("speed := -11".to_owned(), -11, (-10, 10)),
+        ]
+    );
+
+    let invalid = [
+        "TYPE Mode: (Manual := 1, Auto, Remote := 2) INT; END_TYPE",
+        "TYPE Small: (Low := 0, High := 300) SINT; END_TYPE",
+        "TYPE Reversed: INT(10..0); END_TYPE",
+        "VAR_GLOBAL ratio: Percent := 101; END_VAR",
+    ];
+    for code in invalid {
+        let mut lexer = StLexerBuilder::new().build_str(code);
+        let decl = ParserBuilder::default()
+            .build()
+            .parse_decl(&mut lexer)
+            .unwrap();
+        ctx.write().add_declaration(decl, Uuid::new_v4());
+    }
+    assert_eq!(
+        check_declarations(&mgr, &ctx),
+        [
+            DeclarationError::DuplicateEnumValue("Mode".into(), "Remote".into(), 2),
+            DeclarationError::InvalidEnumValue("Small".into(), "High".into()),
+            DeclarationError::InvalidSubrange("Reversed".into()),
+            DeclarationError::OutOfRange("ratio".into(), 101, (0, 100)),
+        ]
+    );
+}
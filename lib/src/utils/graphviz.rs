@@ -541,4 +541,35 @@ impl<W: Write> AstVisitor<'_> for GraphvizExporter<W> {
             top.node_name = name;
         }
     }
+
+    fn visit_array_access_expression(&mut self, access: &ArrayAccessExpression) {
+        let name = self.unique_node("array_access_expression");
+
+        let mut labels = vec![];
+
+        self.push_empty();
+        self.visit_expression(access.array());
+        let attr = self.pop();
+
+        let (pos, label) = self.sub_label_to_new_node("Array");
+        self.connect_from_pos(&name, pos, attr.node_name);
+        labels.push(label);
+
+        for (idx, index) in access.indexes().iter().enumerate() {
+            self.push_empty();
+            self.visit_expression(index);
+            let attr = self.pop();
+
+            let (pos, label) = self.sub_label_to_new_node(format!("Index {}", idx));
+            self.connect_from_pos(&name, pos, attr.node_name);
+            labels.push(label);
+        }
+
+        let info_group = LabelGroups::new("ArrayAccess").append_group(Labels::from_iter(labels));
+        self.write_node(&name, info_group);
+
+        if let Some(top) = self.top_mut() {
+            top.node_name = name;
+        }
+    }
 }
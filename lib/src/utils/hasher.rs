@@ -3,6 +3,7 @@ use crate::parser::LiteralValue;
 use crc::{Crc, Digest, CRC_32_ISO_HDLC};
 use std::cell::RefCell;
 use std::hash::{Hash, Hasher};
+use std::mem;
 
 const CRC32: Crc<u32> = Crc::<u32>::new(&CRC_32_ISO_HDLC);
 
@@ -22,6 +23,11 @@ enum VisitType {
     Operand,
     AssignExpression,
     CompoAccessExpression,
+    ArrayAccessExpression,
+    VariableDeclaration,
+    Resource,
+    Task,
+    ProgramInstance,
 }
 
 trait MyHash {
@@ -46,8 +52,14 @@ impl MyHash for Variable {
 
 impl MyHash for Type {
     fn hash<H: Hasher>(&self, state: &mut H) {
-        // TODO: incomplete implements
+        // bounds of arrays and subranges are expressions, hashed by AstHasher
         self.type_class().hash(state);
+        if let Some(name) = self.user_type_name() {
+            name.hash(state);
+        }
+        if let Some(array) = self.as_array() {
+            MyHash::hash(array.base_type(), state);
+        }
     }
 }
 
@@ -75,6 +87,36 @@ impl<H: Hasher> AstHasher<H> {
         self.visit_statement(stmt);
         self.hasher.finish()
     }
+
+    pub fn calc_declaration(&mut self, decl: &Declaration) -> u64 {
+        // names are resolved by namespaces of declaration
+        decl.namespace().hash(&mut self.hasher);
+        decl.using().hash(&mut self.hasher);
+        self.visit_declaration(decl);
+        self.hasher.finish()
+    }
+
+    pub fn calc_variable(&mut self, variable: &Variable) -> u64 {
+        self.visit_variable_declaration(variable);
+        self.hasher.finish()
+    }
+
+    fn hash_type(&mut self, ty: &Type) {
+        MyHash::hash(ty, &mut self.hasher);
+
+        if let Some(array) = ty.as_array() {
+            array.dimensions().len().hash(&mut self.hasher);
+            for dim in array.dimensions() {
+                self.visit_expression(dim.lower());
+                self.visit_expression(dim.upper());
+            }
+            self.hash_type(array.base_type());
+        }
+        if let Some(sub) = ty.as_subrange() {
+            self.visit_expression(sub.range().lower());
+            self.visit_expression(sub.range().upper());
+        }
+    }
 }
 
 pub struct Crc32Hasher<'a> {
@@ -107,9 +149,65 @@ impl Hasher for Crc32Hasher<'_> {
 }
 
 impl<H: Hasher> DeclVisitor<'_> for AstHasher<H> {
-    fn visit_declaration(&mut self, _: &Declaration) {
+    fn visit_declaration(&mut self, decl: &Declaration) {
         VisitType::DeclarationStatement.hash(&mut self.hasher);
-        unimplemented!()
+        mem::discriminant(&decl.kind).hash(&mut self.hasher);
+        decl.identifier().hash(&mut self.hasher);
+
+        match &decl.kind {
+            DeclKind::Fun(fun) | DeclKind::FB(fun) | DeclKind::Prg(fun) => {
+                mem::discriminant(fun.class()).hash(&mut self.hasher);
+                if let Some(ty) = fun.return_type() {
+                    self.hash_type(ty);
+                }
+            }
+            DeclKind::Alias(alias) => self.hash_type(alias.alias()),
+            DeclKind::Enum(decl) => {
+                if let Some(ty) = decl.ty() {
+                    self.hash_type(ty);
+                }
+            }
+            DeclKind::Configuration(decl) => {
+                for resource in decl.resources() {
+                    VisitType::Resource.hash(&mut self.hasher);
+                    resource.name().hash(&mut self.hasher);
+                    resource.target().hash(&mut self.hasher);
+
+                    for task in resource.tasks() {
+                        VisitType::Task.hash(&mut self.hasher);
+                        task.name().hash(&mut self.hasher);
+                        for (name, value) in task.parameters() {
+                            name.hash(&mut self.hasher);
+                            self.visit_expression(value);
+                        }
+                    }
+                    for program in resource.programs() {
+                        VisitType::ProgramInstance.hash(&mut self.hasher);
+                        program.name().hash(&mut self.hasher);
+                        program.task().hash(&mut self.hasher);
+                        program.program().hash(&mut self.hasher);
+                    }
+                }
+            }
+            DeclKind::Struct(_) | DeclKind::GlobalVar(_) => {}
+        }
+
+        decl.variables().len().hash(&mut self.hasher);
+        for variable in decl.variables() {
+            self.visit_variable_declaration(variable);
+        }
+    }
+
+    fn visit_variable_declaration(&mut self, variable: &Variable) {
+        VisitType::VariableDeclaration.hash(&mut self.hasher);
+        variable.name().hash(&mut self.hasher);
+        variable.flags().hash(&mut self.hasher);
+        if let Some(ty) = variable.ty() {
+            self.hash_type(ty);
+        }
+        if let Some(initial) = variable.initial() {
+            self.visit_expression(initial);
+        }
     }
 }
 
@@ -182,4 +280,14 @@ impl<H: Hasher> AstVisitor<'_> for AstHasher<H> {
         self.visit_expression(compo_expr.left());
         self.visit_expression(compo_expr.right());
     }
+
+    fn visit_array_access_expression(&mut self, access: &ArrayAccessExpression) {
+        VisitType::ArrayAccessExpression.hash(&mut self.hasher);
+        self.visit_expression(access.array());
+
+        access.indexes().len().hash(&mut self.hasher);
+        for index in access.indexes() {
+            self.visit_expression(index);
+        }
+    }
 }
@@ -169,6 +169,7 @@ impl<W: Write> AstVisitor<'_> for StringifyVisitor<W> {
             LiteralValue::Real(x) => self.write(format_args!("{}", x)),
             LiteralValue::LReal(x) => self.write(format_args!("{}", x)),
             LiteralValue::String(x) => self.write(format_args!("{:?}", x)),
+            LiteralValue::Time(_) => self.write(format_args!("{}", literal.literal())),
         }
     }
 
@@ -288,6 +289,22 @@ impl<W: Write> AstVisitor<'_> for StringifyVisitor<W> {
         self.write(format_args!("{}", TokenKind::DotAccess));
         self.visit_expression(compo.right());
     }
+
+    fn visit_array_access_expression(&mut self, access: &ArrayAccessExpression) {
+        self.visit_expression(access.array());
+
+        self.write(format_args!("{}", TokenKind::LeftBracket));
+        let mut first = true;
+        for index in access.indexes() {
+            if !first {
+                self.write(format_args!("{} ", TokenKind::Comma));
+            }
+
+            self.visit_expression(index);
+            first = false;
+        }
+        self.write(format_args!("{}", TokenKind::RightBracket));
+    }
 }
 
 #[inline]
@@ -2,7 +2,7 @@ use crate::lsp_types::{TokenModifiers, TokenTypes};
 use dashmap::DashMap;
 use ropey::Rope;
 use serde_json::Value;
-use stc::parser::{ParserBuilder, StLexerBuilder, TokenKind};
+use stc::parser::{Location, ParserBuilder, SkippedRegion, StLexerBuilder, TokenKind};
 use stc::prelude::{ModuleContext, ModuleKind, UnitsManager, Uuid};
 use strum::IntoEnumIterator;
 use tower_lsp::jsonrpc::Result;
@@ -17,6 +17,7 @@ fn semantic_token_type_id(tok: &TokenKind) -> (u32, u32) {
         TokenKind::Identifier(_) => (TokenTypes::Variable as u32, TokenModifiers::None as u32),
         TokenKind::Literal(_) => (TokenTypes::Number as u32, TokenModifiers::None as u32),
         TokenKind::String => (TokenTypes::String as u32, TokenModifiers::None as u32),
+        TokenKind::Pragma(_) => (TokenTypes::Macro as u32, TokenModifiers::None as u32),
         // operators
         op if op.is_operator() => (TokenTypes::Operator as u32, TokenModifiers::None as u32),
         // builtin-operators
@@ -32,6 +33,34 @@ fn semantic_token_type_id(tok: &TokenKind) -> (u32, u32) {
     }
 }
 
+/// Lines of text skipped by conditional compilation, greyed out as comments.
+/// Returns location and length of the skipped part of each line.
+fn skipped_lines(src: &Rope, region: &SkippedRegion) -> Vec<(Location, usize)> {
+    let mut lines = vec![];
+
+    for mark in region.start.mark..=region.end.mark {
+        let offset = if mark == region.start.mark {
+            region.start.offset
+        } else {
+            0
+        };
+        let end = if mark == region.end.mark {
+            region.end.offset
+        } else {
+            src.line(mark)
+                .chars()
+                .take_while(|c| !matches!(c, '\r' | '\n'))
+                .count()
+        };
+
+        if end > offset {
+            lines.push((Location { mark, offset }, end - offset));
+        }
+    }
+
+    lines
+}
+
 pub struct StcLsp {
     client: Client,
     src_mgr: DashMap<Url, Rope>,
@@ -223,28 +252,43 @@ impl LanguageServer for StcLsp {
         trace!("tokens_full: {}", params.text_document.uri);
 
         let s = self.src_mgr.get(&params.text_document.uri).unwrap();
-        let lexer = StLexerBuilder::new().build_iter(s.chars());
+        let mut lexer = StLexerBuilder::new().build_iter(s.chars());
+
+        let mut items: Vec<_> = lexer
+            .by_ref()
+            .flatten()
+            .map(|tok| (tok.location, tok.length, semantic_token_type_id(&tok.kind)))
+            .collect();
+        // text skipped by conditional compilation is between the tokens
+        let comment = (TokenTypes::Comment as u32, TokenModifiers::None as u32);
+        for region in lexer.skipped_regions() {
+            items.extend(
+                skipped_lines(&s, region)
+                    .into_iter()
+                    .map(|(location, length)| (location, length, comment)),
+            );
+        }
+        items.sort_by_key(|(location, _, _)| (location.mark, location.offset));
 
         let mut last_line = 0;
         let mut last_offset = 0;
         let mut tokens: Vec<SemanticToken> = vec![];
-        for tok in lexer.flatten() {
-            if tok.location.mark != last_line {
+        for (location, length, (tt, tm)) in items {
+            if location.mark != last_line {
                 last_offset = 0;
             }
 
-            let (tt, tm) = semantic_token_type_id(&tok.kind);
             let token = SemanticToken {
-                delta_line: (tok.location.mark - last_line) as u32,
-                delta_start: (tok.location.offset - last_offset) as u32,
-                length: tok.length as u32,
+                delta_line: (location.mark - last_line) as u32,
+                delta_start: (location.offset - last_offset) as u32,
+                length: length as u32,
                 token_type: tt,
                 token_modifiers_bitset: tm,
             };
             tokens.push(token);
 
-            last_line = tok.location.mark;
-            last_offset = tok.location.offset;
+            last_line = location.mark;
+            last_offset = location.offset;
         }
 
         Ok(Some(SemanticTokensResult::Tokens(SemanticTokens {
@@ -12,6 +12,8 @@ pub enum TokenTypes {
     Number,
     String,
     Type,
+    Macro,
+    Comment,
 }
 
 impl From<TokenTypes> for SemanticTokenType {
@@ -25,6 +27,8 @@ impl From<TokenTypes> for SemanticTokenType {
             TokenTypes::Number => SemanticTokenType::NUMBER,
             TokenTypes::String => SemanticTokenType::STRING,
             TokenTypes::Type => SemanticTokenType::TYPE,
+            TokenTypes::Macro => SemanticTokenType::MACRO,
+            TokenTypes::Comment => SemanticTokenType::COMMENT,
         }
     }
 }
@@ -5,7 +5,7 @@ use stc::serde::Project;
 use stc::utils::write_ast_to_file;
 
 use crate::egui::CompileBackend;
-use log::info;
+use log::{info, warn};
 use quick_xml::de::from_str;
 use std::fs;
 use std::io::Write;
@@ -24,7 +24,10 @@ impl StcViewerApp {
     #[inline]
     pub fn from_app(app: Project) -> Self {
         let mgr = UnitsManager::new();
-        let ctx: ModuleContext = app.into();
+        let (ctx, errors) = app.load();
+        for e in errors {
+            warn!("{}", e);
+        }
         mgr.write().add_context(ctx.clone());
         mgr.write().set_active_application(Some(ctx.read().id()));
 